@@ -0,0 +1,592 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use anyhow::Result;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    agents::SystemPrompts,
+    approval::{Approver, CostApprover, Steering},
+    config::AppConfig,
+    cost_tracker::CostTracker,
+    error::AgentError,
+    events::{EventRecord, EventSink},
+    llm::{LLMClient, LLMProvider, RoleParams},
+    memory::Memory,
+    metrics::RunMetrics,
+    orchestrator::Orchestrator,
+    reporter::{NullReporter, Reporter},
+    state::AppState,
+    tools::{ToolContext, ToolRegistry},
+};
+
+/// The agent as a library: plans and works towards a goal, reporting progress through events and
+/// an optional `Reporter` instead of printing.
+///
+/// ```no_run
+/// # async fn example(client: std::sync::Arc<dyn cli_coding_agent::LLMClient>) -> anyhow::Result<()> {
+/// use cli_coding_agent::Agent;
+///
+/// let mut agent = Agent::builder()
+///     .goal("Add a --verbose flag")
+///     .llm(client)
+///     .on_event(|record| println!("{:?}", record.event))
+///     .build()?;
+/// agent.run().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Agent {
+    orchestrator: Orchestrator,
+}
+
+impl Agent {
+    pub fn builder() -> AgentBuilder {
+        AgentBuilder::default()
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        self.orchestrator.run().await
+    }
+
+    /// Creates the plan for the goal without executing it.
+    pub async fn plan(&mut self) -> Result<Vec<String>, AgentError> {
+        self.orchestrator.plan().await
+    }
+
+    pub fn state(&self) -> &AppState {
+        self.orchestrator.state()
+    }
+
+    /// How long each step of the last run took.
+    pub fn metrics(&self) -> &RunMetrics {
+        self.orchestrator.metrics()
+    }
+}
+
+/// Builds an `Agent`. Only a goal (or state) and an LLM client are required; everything else has
+/// the same defaults as the CLI, except that nothing is printed unless a reporter is set.
+#[derive(Default)]
+pub struct AgentBuilder {
+    state: Option<AppState>,
+    pinned_files: Vec<String>,
+    llm: Option<Arc<dyn LLMClient>>,
+    reasoner: Option<Arc<dyn LLMClient>>,
+    planner: Option<Arc<dyn LLMClient>>,
+    summarizer: Option<Arc<dyn LLMClient>>,
+    decision_ensemble: Vec<Arc<dyn LLMClient>>,
+    cost_tracker: Option<Arc<CostTracker>>,
+    reporter: Option<Arc<dyn Reporter>>,
+    approver: Option<Arc<dyn Approver>>,
+    steering: Option<Arc<dyn Steering>>,
+    cost_approver: Option<Arc<dyn CostApprover>>,
+    sinks: Vec<Arc<dyn EventSink>>,
+    checkpoints: Vec<PathBuf>,
+    journal: Option<PathBuf>,
+    memory: Option<Memory>,
+    tools: Option<ToolRegistry>,
+    app_config: Option<Arc<AppConfig>>,
+    workdir: Option<PathBuf>,
+    cancel: Option<CancellationToken>,
+    params: Option<RoleParams>,
+    system_prompts: Option<SystemPrompts>,
+    max_fix_attempts: Option<u32>,
+    max_repair_attempts: Option<u32>,
+    max_review_iterations: Option<u32>,
+    context_budget: Option<usize>,
+    tokenizer: Option<LLMProvider>,
+    max_history_entries: Option<usize>,
+    summarize_output_bytes: Option<usize>,
+    native_tool_calls: Option<bool>,
+    replan: Option<bool>,
+    verify: Option<bool>,
+    step_changes: Option<bool>,
+    max_verification_rounds: Option<u32>,
+    max_steps: Option<usize>,
+    max_cost: Option<f64>,
+    loop_threshold: Option<u32>,
+    max_workers: Option<usize>,
+    max_relevant_files: Option<usize>,
+    cost_threshold: Option<f64>,
+}
+
+impl AgentBuilder {
+    pub fn goal(mut self, goal: impl Into<String>) -> Self {
+        self.state = Some(AppState::new(goal.into()));
+        self
+    }
+
+    /// Starts from an existing (e.g. checkpointed) state instead of a fresh goal.
+    pub fn state(mut self, state: AppState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Includes the current content of `path` in every planner, decision and coder context.
+    pub fn pin(mut self, path: impl Into<String>) -> Self {
+        self.pinned_files.push(path.into());
+        self
+    }
+
+    /// The client used for code generation, and for every other role not set explicitly.
+    pub fn llm(mut self, client: Arc<dyn LLMClient>) -> Self {
+        self.llm = Some(client);
+        self
+    }
+
+    /// The client for tool decisions, reviews, summaries and verification.
+    pub fn reasoner(mut self, client: Arc<dyn LLMClient>) -> Self {
+        self.reasoner = Some(client);
+        self
+    }
+
+    /// The client for planning; defaults to the reasoner.
+    pub fn planner(mut self, client: Arc<dyn LLMClient>) -> Self {
+        self.planner = Some(client);
+        self
+    }
+
+    /// The client that summarizes history and large tool output; defaults to the reasoner.
+    pub fn summarizer(mut self, client: Arc<dyn LLMClient>) -> Self {
+        self.summarizer = Some(client);
+        self
+    }
+
+    /// Two or three clients that each answer every decision prompt and vote on the action; a split
+    /// vote is settled by the reasoner.
+    pub fn decision_ensemble(mut self, clients: Vec<Arc<dyn LLMClient>>) -> Self {
+        self.decision_ensemble = clients;
+        self
+    }
+
+    pub fn cost_tracker(mut self, cost_tracker: Arc<CostTracker>) -> Self {
+        self.cost_tracker = Some(cost_tracker);
+        self
+    }
+
+    pub fn reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    /// Asks `approver` before every file change (confirm mode).
+    pub fn approver(mut self, approver: Arc<dyn Approver>) -> Self {
+        self.approver = Some(approver);
+        self
+    }
+
+    /// Asks `steering` for feedback after every step that changed files (feedback mode).
+    pub fn steering(mut self, steering: Arc<dyn Steering>) -> Self {
+        self.steering = Some(steering);
+        self
+    }
+
+    /// Asks `approver` before running a plan estimated to cost more than the cost threshold.
+    pub fn cost_approver(mut self, approver: Arc<dyn CostApprover>) -> Self {
+        self.cost_approver = Some(approver);
+        self
+    }
+
+    /// Calls `callback` with every event of the run.
+    pub fn on_event(self, callback: impl Fn(&EventRecord) + Send + Sync + 'static) -> Self {
+        self.event_sink(Arc::new(callback))
+    }
+
+    pub fn event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Saves the state to `path` after planning and every step; repeat to save it in several places.
+    pub fn checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoints.push(path.into());
+        self
+    }
+
+    /// Journals the original content of files the run changes, for `undo`.
+    pub fn journal(mut self, path: impl Into<PathBuf>) -> Self {
+        self.journal = Some(path.into());
+        self
+    }
+
+    /// Long-term memory recalled into the context of each tool decision.
+    pub fn memory(mut self, memory: Memory) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Custom tools offered alongside the built-in ones.
+    pub fn tools(mut self, registry: ToolRegistry) -> Self {
+        self.tools = Some(registry);
+        self
+    }
+
+    /// Directory the agent's tools work in instead of the process working directory. Relative
+    /// paths chosen by the model and every command resolve against it.
+    pub fn workdir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.workdir = Some(path.into());
+        self
+    }
+
+    /// Stops the run with `AgentError::Cancelled` once `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Applies the behaviour settings (personas, fix attempts, decision repairs, review passes, context budget, history
+    /// compaction, output summaries, tool calling, re-planning, verification, step limit, loop detection, workers, relevant files) from
+    /// `config`. Tools also run with `config` rather than re-reading settings from the environment.
+    pub fn config(mut self, config: &AppConfig) -> Self {
+        self.app_config = Some(Arc::new(config.clone()));
+        self.params = Some(config.role_params());
+        self.system_prompts = Some(config.system_prompts());
+        self.max_fix_attempts = Some(config.max_fix_attempts);
+        self.max_repair_attempts = Some(config.max_repair_attempts);
+        self.max_review_iterations = Some(config.max_review_iterations);
+        self.context_budget = Some(config.context_token_budget);
+        self.max_history_entries = Some(config.max_history_entries);
+        self.summarize_output_bytes = Some(config.summarize_output_bytes);
+        self.native_tool_calls = Some(config.native_tool_calls);
+        self.replan = Some(config.replan);
+        self.verify = Some(config.verify);
+        self.max_verification_rounds = Some(config.max_verification_rounds);
+        self.max_steps = Some(config.max_steps);
+        self.max_cost = config.max_cost;
+        self.loop_threshold = Some(config.loop_threshold);
+        self.max_workers = Some(config.max_workers);
+        self.max_relevant_files = Some(config.max_relevant_files);
+        self.cost_threshold = Some(config.cost_confirm_threshold);
+        self
+    }
+
+    /// Temperature, token limit and top-p for the planner, coder and reasoner.
+    pub fn generation_params(mut self, params: RoleParams) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    /// Personas the planner, coder and reviewer send as system messages.
+    pub fn system_prompts(mut self, system_prompts: SystemPrompts) -> Self {
+        self.system_prompts = Some(system_prompts);
+        self
+    }
+
+    pub fn max_fix_attempts(mut self, attempts: u32) -> Self {
+        self.max_fix_attempts = Some(attempts);
+        self
+    }
+
+    /// How many times an invalid decision is sent back to the model for correction.
+    pub fn max_repair_attempts(mut self, attempts: u32) -> Self {
+        self.max_repair_attempts = Some(attempts);
+        self
+    }
+
+    pub fn max_review_iterations(mut self, iterations: u32) -> Self {
+        self.max_review_iterations = Some(iterations);
+        self
+    }
+
+    pub fn context_budget(mut self, tokens: usize) -> Self {
+        self.context_budget = Some(tokens);
+        self
+    }
+
+    /// Counts context tokens the way `provider`'s models do.
+    pub fn tokenizer(mut self, provider: LLMProvider) -> Self {
+        self.tokenizer = Some(provider);
+        self
+    }
+
+    /// History entries after which older ones are summarized; zero leaves it to the context budget.
+    pub fn max_history_entries(mut self, entries: usize) -> Self {
+        self.max_history_entries = Some(entries);
+        self
+    }
+
+    /// Bytes of tool output above which it is summarized and saved in full; zero disables.
+    pub fn summarize_output_bytes(mut self, bytes: usize) -> Self {
+        self.summarize_output_bytes = Some(bytes);
+        self
+    }
+
+    pub fn native_tool_calls(mut self, enabled: bool) -> Self {
+        self.native_tool_calls = Some(enabled);
+        self
+    }
+
+    pub fn replanning(mut self, enabled: bool) -> Self {
+        self.replan = Some(enabled);
+        self
+    }
+
+    pub fn verification(mut self, enabled: bool) -> Self {
+        self.verify = Some(enabled);
+        self
+    }
+
+    /// Whether to emit each step's file changes; see `Orchestrator::with_step_changes`.
+    pub fn step_changes(mut self, enabled: bool) -> Self {
+        self.step_changes = Some(enabled);
+        self
+    }
+
+    pub fn max_verification_rounds(mut self, rounds: u32) -> Self {
+        self.max_verification_rounds = Some(rounds);
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Dollars the run may spend before it stops; see `Orchestrator::with_max_cost`.
+    pub fn max_cost(mut self, dollars: f64) -> Self {
+        self.max_cost = Some(dollars);
+        self
+    }
+
+    pub fn loop_threshold(mut self, threshold: u32) -> Self {
+        self.loop_threshold = Some(threshold);
+        self
+    }
+
+    /// How many independent subtasks a step may hand to parallel workers; fewer than two disables it.
+    pub fn max_workers(mut self, workers: usize) -> Self {
+        self.max_workers = Some(workers);
+        self
+    }
+
+    /// How many existing files the coder is shown before it writes code; zero disables.
+    pub fn max_relevant_files(mut self, files: usize) -> Self {
+        self.max_relevant_files = Some(files);
+        self
+    }
+
+    /// Shows each new plan's estimated cost, and asks the cost approver when it is over `dollars`.
+    pub fn cost_threshold(mut self, dollars: f64) -> Self {
+        self.cost_threshold = Some(dollars);
+        self
+    }
+
+    pub fn build(self) -> Result<Agent, AgentError> {
+        let mut state = self.state.ok_or_else(|| AgentError::ConfigError("Agent needs a goal or a state".to_string()))?;
+        for path in &self.pinned_files {
+            state.pin(path);
+        }
+        let llm = self.llm.ok_or_else(|| AgentError::ConfigError("Agent needs an LLM client".to_string()))?;
+        let reasoner = self.reasoner.unwrap_or_else(|| llm.clone());
+        let planner = self.planner.unwrap_or_else(|| reasoner.clone());
+        let summarizer = self.summarizer.unwrap_or_else(|| reasoner.clone());
+        let cost_tracker = self.cost_tracker.unwrap_or_default();
+
+        let mut orchestrator = Orchestrator::from_state(state, llm, reasoner, cost_tracker)
+            .with_planner_client(planner)
+            .with_summarizer_client(summarizer)
+            .with_decision_ensemble(self.decision_ensemble)
+            .with_reporter(self.reporter.unwrap_or_else(|| Arc::new(NullReporter)));
+        for sink in self.sinks {
+            orchestrator = orchestrator.with_event_sink(sink);
+        }
+        if let Some(approver) = self.approver {
+            orchestrator = orchestrator.with_approver(approver);
+        }
+        if let Some(steering) = self.steering {
+            orchestrator = orchestrator.with_steering(steering);
+        }
+        if let Some(approver) = self.cost_approver {
+            orchestrator = orchestrator.with_cost_approver(approver);
+        }
+        // Relative session files belong to the workdir, so sessions in different directories
+        // never share a checkpoint or journal.
+        let in_workdir = |path: PathBuf| match &self.workdir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path,
+        };
+        for path in self.checkpoints {
+            orchestrator = orchestrator.with_checkpoint(in_workdir(path));
+        }
+        if let Some(path) = self.journal {
+            orchestrator = orchestrator.with_journal(in_workdir(path));
+        }
+        if let Some(memory) = self.memory {
+            orchestrator = orchestrator.with_memory(memory);
+        }
+        if let Some(tools) = self.tools {
+            orchestrator = orchestrator.with_tool_registry(tools);
+        }
+        if self.app_config.is_some() || self.workdir.is_some() {
+            let config = match self.app_config {
+                Some(config) => config,
+                None => Arc::new(AppConfig::load()?),
+            };
+            let mut context = ToolContext::new(config);
+            if let Some(workdir) = self.workdir {
+                context = context.with_workdir(workdir);
+            }
+            orchestrator = orchestrator.with_tool_context(context);
+        }
+        if let Some(token) = self.cancel {
+            orchestrator = orchestrator.with_cancellation(token);
+        }
+        if let Some(params) = self.params {
+            orchestrator = orchestrator.with_generation_params(params);
+        }
+        if let Some(system_prompts) = self.system_prompts {
+            orchestrator = orchestrator.with_system_prompts(system_prompts);
+        }
+        if let Some(attempts) = self.max_fix_attempts {
+            orchestrator = orchestrator.with_max_fix_attempts(attempts);
+        }
+        if let Some(attempts) = self.max_repair_attempts {
+            orchestrator = orchestrator.with_max_repair_attempts(attempts);
+        }
+        if let Some(iterations) = self.max_review_iterations {
+            orchestrator = orchestrator.with_max_review_iterations(iterations);
+        }
+        if let Some(tokens) = self.context_budget {
+            orchestrator = orchestrator.with_context_budget(tokens);
+        }
+        if let Some(provider) = self.tokenizer {
+            orchestrator = orchestrator.with_tokenizer(provider);
+        }
+        if let Some(entries) = self.max_history_entries {
+            orchestrator = orchestrator.with_max_history_entries(entries);
+        }
+        if let Some(bytes) = self.summarize_output_bytes {
+            orchestrator = orchestrator.with_output_summary_threshold(bytes);
+        }
+        if let Some(enabled) = self.native_tool_calls {
+            orchestrator = orchestrator.with_native_tool_calls(enabled);
+        }
+        if let Some(enabled) = self.replan {
+            orchestrator = orchestrator.with_replanning(enabled);
+        }
+        if let Some(enabled) = self.verify {
+            orchestrator = orchestrator.with_verification(enabled);
+        }
+        if let Some(enabled) = self.step_changes {
+            orchestrator = orchestrator.with_step_changes(enabled);
+        }
+        if let Some(rounds) = self.max_verification_rounds {
+            orchestrator = orchestrator.with_max_verification_rounds(rounds);
+        }
+        if let Some(max_steps) = self.max_steps {
+            orchestrator = orchestrator.with_max_steps(max_steps);
+        }
+        if let Some(dollars) = self.max_cost {
+            orchestrator = orchestrator.with_max_cost(dollars);
+        }
+        if let Some(threshold) = self.loop_threshold {
+            orchestrator = orchestrator.with_loop_threshold(threshold);
+        }
+        if let Some(workers) = self.max_workers {
+            orchestrator = orchestrator.with_max_workers(workers);
+        }
+        if let Some(files) = self.max_relevant_files {
+            orchestrator = orchestrator.with_max_relevant_files(files);
+        }
+        if let Some(dollars) = self.cost_threshold {
+            orchestrator = orchestrator.with_cost_threshold(dollars);
+        }
+        Ok(Agent { orchestrator })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::AgentEvent;
+    use crate::llm::{AIResponse, GenerationParams, ModelInfo};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    // Mock LLM client for testing
+    struct MockLLMClient {
+        responses: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, _prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            let mut responses = self.responses.lock().unwrap();
+            if responses.is_empty() {
+                return Err(AgentError::LLMError("No more mock responses".to_string()));
+            }
+            Ok(AIResponse {
+                content: responses.remove(0),
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 0,
+                cost: 0.001,
+                model: "mock-model".to_string(),
+                provider: "mock-provider".to_string(),
+            })
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo {
+                name: "mock-model".to_string(),
+                input_cost_per_token: 0.0,
+                output_cost_per_token: 0.0,
+            }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    struct CollectingReporter(Mutex<Vec<String>>);
+
+    impl Reporter for CollectingReporter {
+        fn report(&self, line: &str) {
+            self.0.lock().unwrap().push(line.to_string());
+        }
+    }
+
+    #[test]
+    fn test_build_requires_goal_and_llm() {
+        assert!(matches!(Agent::builder().build(), Err(AgentError::ConfigError(_))));
+        let result = Agent::builder().goal("Goal").build();
+        assert!(matches!(result, Err(AgentError::ConfigError(msg)) if msg.contains("LLM client")));
+    }
+
+    #[tokio::test]
+    async fn test_agent_reports_through_callbacks() {
+        let client = Arc::new(MockLLMClient {
+            responses: Mutex::new(vec![
+                "1. Say hello".to_string(),
+                r#"{"thought": "Greet", "tool_name": "RunCommand", "parameters": {"command": "echo hello"}}"#.to_string(),
+                r#"{"status": "complete"}"#.to_string(),
+            ]),
+        });
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let reporter = Arc::new(CollectingReporter(Mutex::new(Vec::new())));
+        let collected = events.clone();
+        let mut agent = Agent::builder()
+            .goal("Greet")
+            .llm(client)
+            .reporter(reporter.clone())
+            .on_event(move |record| collected.lock().unwrap().push(record.event.clone()))
+            .build()
+            .unwrap();
+
+        agent.run().await.unwrap();
+        assert!(agent.state().is_complete());
+        let events = events.lock().unwrap();
+        assert!(matches!(events.first(), Some(AgentEvent::PlanCreated { .. })));
+        assert!(matches!(events.last(), Some(AgentEvent::RunFinished { success: true, .. })));
+        assert!(reporter.0.lock().unwrap().iter().any(|line| line.contains("Executing Step 1: Say hello")));
+    }
+
+    #[tokio::test]
+    async fn test_plan_does_not_execute() {
+        let client = Arc::new(MockLLMClient { responses: Mutex::new(vec!["1. Write the file\n2. Run the tests".to_string()]) });
+        let mut agent = Agent::builder().goal("Add tests").llm(client.clone()).build().unwrap();
+
+        let plan = agent.plan().await.unwrap();
+        assert_eq!(plan, vec!["Write the file".to_string(), "Run the tests".to_string()]);
+        assert_eq!(agent.state().current_step, 0);
+        assert!(client.responses.lock().unwrap().is_empty());
+    }
+}
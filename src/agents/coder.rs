@@ -1,29 +1,155 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
 use anyhow::Result;
 use log::info;
+use regex::Regex;
 
-use crate::{error::AgentError, llm::{LLMClient, AIResponse, ModelInfo}, cost_tracker::CostTracker};
+use crate::{error::AgentError, llm::{GenerationParams, LLMClient}, cost_tracker::CostTracker, project::ProjectLanguage};
+
+/// Opens a file in multi-file coder output: `=== FILE: path/to/file.ext ===`.
+const FILE_MARKER_START: &str = "=== FILE:";
+/// Closes a file in multi-file coder output.
+const FILE_MARKER_END: &str = "=== END FILE ===";
+
+/// One file of a multi-file code generation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedFile {
+    pub path: String,
+    pub content: String,
+}
+
+/// Splits coder output into the files it marks with `=== FILE: path ===` ... `=== END FILE ===`.
+/// Returns an empty list for plain single-file output. A missing end marker closes the file at the
+/// next start marker or the end of the output; text outside the markers is ignored.
+pub fn split_files(code: &str) -> Vec<GeneratedFile> {
+    let mut files = Vec::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+    for line in code.lines() {
+        let trimmed = line.trim();
+        if let Some(path) = trimmed.strip_prefix(FILE_MARKER_START).and_then(|rest| rest.strip_suffix("===")) {
+            files.extend(current.take().map(to_file));
+            current = Some((path.trim().to_string(), Vec::new()));
+        } else if trimmed == FILE_MARKER_END {
+            files.extend(current.take().map(to_file));
+        } else if let Some((_, lines)) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+    files.extend(current.map(to_file));
+    files.retain(|file| !file.path.is_empty());
+    files
+}
+
+fn to_file((path, lines): (String, Vec<&str>)) -> GeneratedFile {
+    let mut content = strip_code_fence(&lines.join("\n"));
+    content.push('\n');
+    GeneratedFile { path, content }
+}
+
+/// Lines models put in front of code despite being told not to, e.g. "Here is the code:".
+fn is_preamble(line: &str) -> bool {
+    static PREAMBLE: OnceLock<Regex> = OnceLock::new();
+    let preamble = PREAMBLE.get_or_init(|| {
+        Regex::new(r"(?i)^(sure|certainly|of course|okay|ok|here(?:'s| is| are)|below is|the following)\b").expect("valid preamble regex")
+    });
+    preamble.is_match(line.trim())
+}
+
+fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with("```")
+}
+
+/// Removes a markdown fence wrapped around `text`: the opening ```` ```lang ```` line, the last
+/// closing ```` ``` ```` line and any commentary after it. Fences inside the code are kept, so a
+/// generated README with its own code blocks survives. Text that does not start with a fence is
+/// returned unchanged.
+fn strip_code_fence(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let Some(open) = lines.iter().position(|line| !line.trim().is_empty()) else {
+        return text.to_string();
+    };
+    if !is_fence(lines[open]) {
+        return text.to_string();
+    }
+    let body = &lines[open + 1..];
+    let close = body.iter().rposition(|line| line.trim() == "```").unwrap_or(body.len());
+    body[..close].join("\n")
+}
 
 pub struct CoderAgent {
     llm_client: Arc<dyn LLMClient>,
     cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+    language: Option<ProjectLanguage>,
+    system_prompt: Option<String>,
 }
 
 impl CoderAgent {
     pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
-        Self { llm_client, cost_tracker }
+        Self { llm_client, cost_tracker, params: GenerationParams::default(), language: None, system_prompt: None }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Makes `language` the default for generated code instead of Python.
+    pub fn with_language(mut self, language: Option<ProjectLanguage>) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// The coder's persona, sent as a system message before each prompt.
+    pub fn with_system_prompt(mut self, system_prompt: Option<String>) -> Self {
+        self.system_prompt = system_prompt;
+        self
+    }
+
+    /// Syntax errors in generated Rust code. Marked files are checked when their path ends in
+    /// `.rs`; unmarked code when `file_path` does, or when it has no path in a Rust project.
+    /// Other languages are not checked.
+    pub fn syntax_errors(&self, code: &str, file_path: Option<&str>) -> Vec<String> {
+        let files = split_files(code);
+        let rust_sources: Vec<(String, &str)> = if files.is_empty() {
+            let is_rust = match file_path {
+                Some(path) => path.ends_with(".rs"),
+                None => self.language == Some(ProjectLanguage::Rust),
+            };
+            if is_rust { vec![(file_path.unwrap_or("generated code").to_string(), code)] } else { Vec::new() }
+        } else {
+            files.iter().filter(|file| file.path.ends_with(".rs")).map(|file| (file.path.clone(), file.content.as_str())).collect()
+        };
+        rust_sources
+            .into_iter()
+            .filter_map(|(path, source)| syn::parse_file(source).err().map(|e| format!("{} does not parse as Rust: {}", path, e)))
+            .collect()
     }
 
     pub async fn generate_code(&self, task_description: &str, context: &str) -> Result<String, AgentError> {
         let prompt = self.build_prompt(task_description, context);
         info!("Coder prompt:\n{}", prompt);
-        let response = self.llm_client.generate(&prompt).await?;
-        self.cost_tracker.add_cost(response.cost);
+        let started = Instant::now();
+        let response = super::send(self.llm_client.as_ref(), self.system_prompt.as_deref(), &prompt, &self.params, false).await?;
+        self.cost_tracker.record_call("coder", &response, started.elapsed());
         info!("Coder response:\n{}", response.content);
         Ok(self.parse_code(&response.content))
     }
 
+    /// Rewrites previously generated code to address reviewer feedback.
+    pub async fn revise_code(&self, task_description: &str, previous_code: &str, feedback: &[String], context: &str) -> Result<String, AgentError> {
+        let prompt = self.build_revision_prompt(task_description, previous_code, feedback, context);
+        info!("Coder revision prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = super::send(self.llm_client.as_ref(), self.system_prompt.as_deref(), &prompt, &self.params, false).await?;
+        self.cost_tracker.record_call("coder", &response, started.elapsed());
+        info!("Coder revision response:\n{}", response.content);
+        Ok(self.parse_code(&response.content))
+    }
+
     fn build_prompt(&self, task_description: &str, context: &str) -> String {
+        let default_language = self.language.map_or_else(|| "python".to_string(), |language| language.to_string());
         format!(r#"
 You are an expert programmer. Your sole responsibility is to write clean, efficient, and correct code.
 You will be given the overall context of the project and a specific task to complete.
@@ -34,19 +160,56 @@ You will be given the overall context of the project and a specific task to comp
 
 Your current task is: "{task_description}"
 
-Based on the context and the task, write the necessary code. By default, you should write python code, but if the task requires a different language, use that language instead.
+Based on the context and the task, write the necessary code. By default, you should write {default_language} code, but if the task requires a different language, use that language instead.
+If the task needs more than one file (for example, scaffolding a project), output every file in this form, one after another:
+{FILE_MARKER_START} path/to/file.ext ===
+<the file's raw content>
+{FILE_MARKER_END}
 IMPORTANT: Output ONLY the raw code. Do not include any explanations, comments about the code, or markdown code fences like ```rust.
 "#)
     }
 
+    fn build_revision_prompt(&self, task_description: &str, previous_code: &str, feedback: &[String], context: &str) -> String {
+        let feedback = feedback.iter().map(|f| format!("- {}", f)).collect::<Vec<_>>().join("\n");
+        format!(r#"
+You are an expert programmer. A reviewer rejected your previous attempt at a task. Fix every problem they raised.
+
+--- Context ---
+{context}
+--- End Context ---
+
+Your task is: "{task_description}"
+
+--- Previous Code ---
+{previous_code}
+--- End Previous Code ---
+
+--- Reviewer Feedback ---
+{feedback}
+--- End Reviewer Feedback ---
+
+Write the complete corrected code, not just the changed parts. If the previous code was split into files with {FILE_MARKER_START} markers, keep using them and output every file.
+IMPORTANT: Output ONLY the raw code. Do not include any explanations, comments about the code, or markdown code fences like ```rust.
+"#)
+    }
+
+    /// Extracts the code from a response: drops a short preamble such as "Here is the code:",
+    /// unwraps a surrounding markdown fence and drops commentary after it.
     fn parse_code(&self, response: &str) -> String {
-        response.trim().to_string()
+        let lines: Vec<&str> = response.trim().lines().collect();
+        let start = match lines.iter().position(|line| is_fence(line)) {
+            // Everything before the opening fence is prose if it is only a few introductory lines.
+            Some(fence) if fence <= 3 && lines[..fence].iter().all(|line| line.trim().is_empty() || is_preamble(line) || line.trim_end().ends_with(':')) => fence,
+            _ => lines.iter().take_while(|line| is_preamble(line) && line.trim_end().ends_with(':')).count(),
+        };
+        strip_code_fence(&lines[start..].join("\n")).trim().to_string()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::llm::{AIResponse, ModelInfo};
     use async_trait::async_trait;
     use std::sync::Arc;
 
@@ -58,18 +221,19 @@ mod tests {
 
     #[async_trait]
     impl LLMClient for MockLLMClient {
-        async fn generate(&self, _prompt: &str) -> Result<AIResponse, AgentError> {
+        async fn generate(&self, _prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
             Ok(AIResponse {
                 content: self.response.clone(),
                 input_tokens: 10,
                 output_tokens: 20,
+                reasoning_tokens: 0,
                 cost: self.cost,
                 model: "mock-model".to_string(),
                 provider: "mock-provider".to_string(),
             })
         }
-        async fn generate_json(&self, _prompt: &str) -> Result<AIResponse, AgentError> {
-            self.generate(_prompt).await
+        async fn generate_json(&self, _prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            self.generate(_prompt, params).await
         }
         async fn get_model_info(&self) -> ModelInfo {
             ModelInfo {
@@ -211,6 +375,125 @@ mod tests {
         assert_eq!(cost_tracker.get_total_cost(), 0.002);
     }
 
+    #[tokio::test]
+    async fn test_revise_code() {
+        let mock_client = Arc::new(MockLLMClient {
+            response: "def add(a, b):\n    return a + b".to_string(),
+            cost: 0.003,
+        });
+        let cost_tracker = Arc::new(CostTracker::new());
+        let coder = CoderAgent::new(mock_client, cost_tracker.clone());
+
+        let feedback = vec!["Subtracts instead of adding".to_string()];
+        let code = coder.revise_code("Add numbers", "def add(a, b):\n    return a - b", &feedback, "ctx").await.unwrap();
+
+        assert_eq!(code, "def add(a, b):\n    return a + b");
+        assert_eq!(cost_tracker.get_total_cost(), 0.003);
+    }
+
+    #[test]
+    fn test_build_revision_prompt() {
+        let mock_client = Arc::new(MockLLMClient {
+            response: "".to_string(),
+            cost: 0.0,
+        });
+        let cost_tracker = Arc::new(CostTracker::new());
+        let coder = CoderAgent::new(mock_client, cost_tracker);
+
+        let feedback = vec!["Missing error handling".to_string(), "Off-by-one in loop".to_string()];
+        let prompt = coder.build_revision_prompt("Parse the file", "fn parse() {}", &feedback, "Rust project");
+
+        assert!(prompt.contains("Parse the file"));
+        assert!(prompt.contains("fn parse() {}"));
+        assert!(prompt.contains("- Missing error handling\n- Off-by-one in loop"));
+        assert!(prompt.contains("Rust project"));
+        assert!(prompt.contains("ONLY the raw code"));
+    }
+
+    #[test]
+    fn test_split_files() {
+        let output = "=== FILE: app/main.py ===\nfrom app import util\n\nutil.run()\n=== END FILE ===\n\n=== FILE: app/util.py ===\ndef run():\n    pass\n=== FILE: README.md ===\n# App";
+        assert_eq!(split_files(output), vec![
+            GeneratedFile { path: "app/main.py".to_string(), content: "from app import util\n\nutil.run()\n".to_string() },
+            GeneratedFile { path: "app/util.py".to_string(), content: "def run():\n    pass\n".to_string() },
+            GeneratedFile { path: "README.md".to_string(), content: "# App\n".to_string() },
+        ]);
+        assert!(split_files("def run():\n    pass").is_empty());
+    }
+
+    #[test]
+    fn test_build_prompt_uses_project_language() {
+        let mock_client = Arc::new(MockLLMClient {
+            response: "".to_string(),
+            cost: 0.0,
+        });
+        let coder = CoderAgent::new(mock_client, Arc::new(CostTracker::new())).with_language(Some(ProjectLanguage::Rust));
+
+        let prompt = coder.build_prompt("Add a parser", "Rust project");
+        assert!(prompt.contains("you should write Rust code"));
+        assert!(!prompt.contains("python"));
+    }
+
+    #[test]
+    fn test_parse_code_strips_markdown_fence() {
+        let mock_client = Arc::new(MockLLMClient {
+            response: "".to_string(),
+            cost: 0.0,
+        });
+        let coder = CoderAgent::new(mock_client, Arc::new(CostTracker::new()));
+
+        assert_eq!(coder.parse_code("```rust\nfn main() {}\n```"), "fn main() {}");
+    }
+
+    #[test]
+    fn test_parse_code_strips_preamble_and_commentary() {
+        let mock_client = Arc::new(MockLLMClient {
+            response: "".to_string(),
+            cost: 0.0,
+        });
+        let coder = CoderAgent::new(mock_client, Arc::new(CostTracker::new()));
+
+        let cases = [
+            ("Here is the code:\n```python\nprint('hi')\n```", "print('hi')"),
+            ("Sure! Below is the implementation:\n\n```\nprint('hi')\n```\n\nThis prints a greeting.", "print('hi')"),
+            ("Here's the updated function:\nprint('hi')", "print('hi')"),
+            ("```py\nprint('hi')", "print('hi')"),
+            ("```\n```", ""),
+            ("```markdown\n# Usage\n```bash\nrun\n```\nDone.\n```", "# Usage\n```bash\nrun\n```\nDone."),
+            // Code that merely starts with a word like "Okay" is left alone without a fence.
+            ("ok = True\nprint(ok)", "ok = True\nprint(ok)"),
+        ];
+        for (response, expected) in cases {
+            assert_eq!(coder.parse_code(response), expected, "response: {:?}", response);
+        }
+    }
+
+    #[test]
+    fn test_split_files_strips_fences_per_file() {
+        let output = "=== FILE: main.py ===\n```python\nprint('hi')\n```\n=== END FILE ===";
+        assert_eq!(split_files(output), vec![GeneratedFile { path: "main.py".to_string(), content: "print('hi')\n".to_string() }]);
+    }
+
+    #[test]
+    fn test_syntax_errors_checks_rust_only() {
+        let mock_client = Arc::new(MockLLMClient {
+            response: "".to_string(),
+            cost: 0.0,
+        });
+        let coder = CoderAgent::new(mock_client, Arc::new(CostTracker::new())).with_language(Some(ProjectLanguage::Rust));
+
+        assert!(coder.syntax_errors("fn main() {}", None).is_empty());
+        let errors = coder.syntax_errors("fn main() {", Some("src/main.rs"));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("src/main.rs does not parse as Rust"));
+        assert!(coder.syntax_errors("def main(:", Some("main.py")).is_empty());
+
+        let files = "=== FILE: src/lib.rs ===\npub fn ok() {}\n=== END FILE ===\n=== FILE: src/bad.rs ===\nfn (\n=== END FILE ===";
+        let errors = coder.syntax_errors(files, None);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("src/bad.rs"));
+    }
+
     #[test]
     fn test_build_prompt_contains_required_elements() {
         let mock_client = Arc::new(MockLLMClient {
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::Result;
+use log::info;
+use serde::Deserialize;
+
+use crate::{
+    cost_tracker::CostTracker,
+    doc_gen::{ItemDoc, UndocumentedItem},
+    error::AgentError,
+    json_repair,
+    llm::{GenerationParams, LLMClient},
+};
+
+/// Characters of the file shown to the documenter; the rest is cut off.
+const MAX_SOURCE_CHARS: usize = 60_000;
+
+#[derive(Debug, Deserialize)]
+struct Docs {
+    #[serde(default)]
+    docs: Vec<ItemDoc>,
+}
+
+/// Writes the doc comments `doc-gen` adds to the undocumented public items of a file, in the style
+/// of the comments already there.
+pub struct DocumenterAgent {
+    llm_client: Arc<dyn LLMClient>,
+    cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+}
+
+impl DocumenterAgent {
+    pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
+        Self { llm_client, cost_tracker, params: GenerationParams::default() }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Doc comments for `items`, the undocumented public items of `source`, the content of `path`.
+    /// Items the model leaves out get none.
+    pub async fn document(&self, path: &str, source: &str, items: &[UndocumentedItem]) -> Result<Vec<ItemDoc>, AgentError> {
+        let prompt = build_prompt(path, source, items);
+        info!("Documenter prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = self.llm_client.generate_json(&prompt, &self.params).await?;
+        self.cost_tracker.record_call("documenter", &response, started.elapsed());
+        info!("Documenter response:\n{}", response.content);
+        let docs: Docs = json_repair::parse_lenient(&response.content)
+            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse doc comments: {}. Response: {}", e, response.content)))?;
+        Ok(docs.docs)
+    }
+}
+
+fn build_prompt(path: &str, source: &str, items: &[UndocumentedItem]) -> String {
+    let numbered: String = source.lines().enumerate().map(|(i, line)| format!("{:>5} {}\n", i + 1, line)).collect();
+    let numbered = match numbered.char_indices().nth(MAX_SOURCE_CHARS) {
+        Some((cut, _)) => format!("{}[... file truncated ...]", &numbered[..cut]),
+        None => numbered,
+    };
+    let items: Vec<String> = items.iter().map(|item| format!("- line {}: {} `{}`", item.line, item.kind, item.name)).collect();
+    let items = items.join("\n");
+    format!(r#"
+You are documenting the public API of {path}, shown here with line numbers:
+
+--- {path} ---
+{numbered}--- End {path} ---
+
+These public items have no doc comment yet:
+{items}
+
+Write a rustdoc comment for each of them. Match the comments already in the file: their length, tone,
+and how they refer to parameters and other items. Say what the item is or does and anything a caller must
+know (errors, panics, defaults) that the signature does not show; do not restate the signature or invent
+behaviour the code does not have. Write the text only, without `///` markers.
+
+You MUST respond with a single JSON object matching this structure, with the line numbers listed above:
+{{
+  "docs": [{{ "line": 12, "doc": "Parses `text` as TOML; fails on unknown keys." }}]
+}}
+"#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{AIResponse, ModelInfo};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    struct MockLLMClient {
+        response: String,
+        prompt: Mutex<String>,
+    }
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            *self.prompt.lock().unwrap() = prompt.to_string();
+            Ok(AIResponse {
+                content: self.response.clone(),
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 0,
+                cost: 0.001,
+                model: "mock-model".to_string(),
+                provider: "mock-provider".to_string(),
+            })
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo { name: "mock-model".to_string(), input_cost_per_token: 0.0, output_cost_per_token: 0.0 }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_document_lists_items_by_line() {
+        let client = Arc::new(MockLLMClient { response: r#"{"docs": [{"line": 2, "doc": "Parses `text`."}]}"#.to_string(), prompt: Mutex::new(String::new()) });
+        let cost_tracker = Arc::new(CostTracker::new());
+        let documenter = DocumenterAgent::new(client.clone(), cost_tracker.clone());
+        let items = [UndocumentedItem { kind: "fn".to_string(), name: "parse".to_string(), line: 2 }];
+
+        let docs = documenter.document("src/lib.rs", "/// Settings.\npub fn parse(text: &str) {}\n", &items).await.unwrap();
+
+        assert_eq!(docs, vec![ItemDoc { line: 2, doc: "Parses `text`.".to_string() }]);
+        let prompt = client.prompt.lock().unwrap();
+        assert!(prompt.contains("    2 pub fn parse(text: &str) {}") && prompt.contains("- line 2: fn `parse`"));
+        assert_eq!(cost_tracker.get_total_cost(), 0.001);
+    }
+}
@@ -0,0 +1,228 @@
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::Result;
+use colored::*;
+use log::{info, warn};
+use serde_json::{json, Value};
+
+use crate::{
+    cost_tracker::CostTracker,
+    error::AgentError,
+    json_repair,
+    llm::{ChatMessage, GenerationParams, LLMClient, OnText},
+    reporter::{NullReporter, Reporter},
+    tools::{Decision, Tool, ToolContext, ToolRegistry},
+};
+
+/// The tool the explainer chooses once it has looked up enough to answer.
+const ANSWER_TOOL: &str = "Answer";
+/// Characters of each lookup's output kept for the answer; longer output is cut.
+const MAX_FINDING_CHARS: usize = 8_000;
+
+/// Answers questions about the codebase without changing it: it looks things up with the
+/// read-only tools (`tools::READ_ONLY_TOOLS`), then streams an answer based on what it found.
+pub struct ExplainerAgent {
+    llm_client: Arc<dyn LLMClient>,
+    cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+    tools: ToolRegistry,
+    context: ToolContext,
+    max_lookups: usize,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl ExplainerAgent {
+    /// An explainer whose lookups run with `context`; network tools are left out when its
+    /// configuration is offline.
+    pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>, context: ToolContext) -> Self {
+        let mut tools = ToolRegistry::default().with_read_only(true).with_offline(context.config.offline);
+        tools
+            .register(ANSWER_TOOL, "Stop looking things up and answer the question from what you found so far.", json!({ "type": "object", "properties": {} }), |_: Value| async { Ok(String::new()) })
+            .expect("the answer tool has a valid, unique name");
+        Self { llm_client, cost_tracker, params: GenerationParams::default(), tools, context, max_lookups: 8, reporter: Arc::new(NullReporter) }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// The most tools it runs before answering with what it has.
+    pub fn with_max_lookups(mut self, lookups: usize) -> Self {
+        self.max_lookups = lookups;
+        self
+    }
+
+    /// Where the lookups are reported as they run; nowhere by default.
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Looks up what `question` needs, then answers it, passing the answer to `on_text` as it is
+    /// written. Returns the whole answer.
+    pub async fn explain(&self, question: &str, on_text: &OnText<'_>) -> Result<String, AgentError> {
+        let listing = Tool::ListFiles { path: ".".to_string(), max_depth: Some(2), glob: None };
+        let mut findings = vec![("Directory Listing".to_string(), self.look_up(listing).await)];
+        for _ in 0..self.max_lookups {
+            let prompt = self.tools.decision_prompt(&lookup_step(question), &render(&findings));
+            info!("Explainer prompt:\n{}", prompt);
+            let started = Instant::now();
+            let response = self.llm_client.generate_json(&prompt, &self.params).await?;
+            self.cost_tracker.record_call("explainer", &response, started.elapsed());
+            info!("Explainer response:\n{}", response.content);
+            let decision = match self.parse_decision(&response.content) {
+                Ok(decision) => decision,
+                Err(e) => {
+                    warn!("Invalid explainer decision: {}", e);
+                    findings.push(("Invalid Decision".to_string(), e));
+                    continue;
+                }
+            };
+            if matches!(&decision.tool, Tool::Custom { name, .. } if name == ANSWER_TOOL) {
+                break;
+            }
+            self.reporter.report(&format!("   {} {:?}", "🔎 Looking Up:".magenta(), decision.tool));
+            let label = format!("{} {}", decision.tool.name(), serde_json::to_value(&decision.tool).map(|value| value["parameters"].to_string()).unwrap_or_default());
+            findings.push((label, self.look_up(decision.tool).await));
+        }
+
+        let messages = [ChatMessage::system(SYSTEM_PROMPT), ChatMessage::user(answer_prompt(question, &findings))];
+        let started = Instant::now();
+        let response = self.llm_client.generate_stream(&messages, &self.params, on_text).await?;
+        self.cost_tracker.record_call("explainer", &response, started.elapsed());
+        Ok(response.content)
+    }
+
+    /// The output of `tool`, or why it failed, cut to `MAX_FINDING_CHARS`.
+    async fn look_up(&self, tool: Tool) -> String {
+        let output = match self.tools.run(tool, &self.context).await {
+            Ok(result) => result.text(),
+            Err(e) => format!("Error: {}", e),
+        };
+        match output.char_indices().nth(MAX_FINDING_CHARS) {
+            Some((end, _)) => format!("{}\n... (cut; read a narrower line range for the rest)", &output[..end]),
+            None => output,
+        }
+    }
+
+    /// A decision from its JSON text, leniently parsed and checked against the read-only tools.
+    fn parse_decision(&self, content: &str) -> Result<Decision, String> {
+        let value: Value = json_repair::parse_lenient(content).map_err(|e| e.to_string())?;
+        self.tools.validate_decision(&value).map_err(|e| e.to_string())?;
+        let decision = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        self.tools.check(&decision).map_err(|e| e.to_string())?;
+        Ok(decision)
+    }
+}
+
+const SYSTEM_PROMPT: &str = "You explain codebases to developers. Answer from the code you were shown: cite the files (and lines, when you know them) your answer rests on, and say plainly when the code you saw does not settle a point instead of guessing. Be concise.";
+
+/// The "step" each lookup decision is asked for.
+fn lookup_step(question: &str) -> String {
+    format!(
+        "Find the code needed to answer this question about the codebase: \"{question}\"\nLook up one thing at a time, starting from the files most likely to matter. Choose {ANSWER_TOOL} as soon as what you found is enough to answer; you cannot change files or run commands."
+    )
+}
+
+fn render(findings: &[(String, String)]) -> String {
+    findings.iter().map(|(source, output)| format!("[{}]\n{}", source, output)).collect::<Vec<_>>().join("\n---\n")
+}
+
+fn answer_prompt(question: &str, findings: &[(String, String)]) -> String {
+    let findings = render(findings);
+    format!(r#"
+--- What You Looked Up ---
+{findings}
+--- End What You Looked Up ---
+
+Question: {question}
+
+Answer the question in Markdown.
+"#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::llm::{AIResponse, ModelInfo};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    // Answers decisions from `decisions` in order, then streams `answer` in two pieces.
+    struct MockLLMClient {
+        decisions: Mutex<Vec<String>>,
+        answer: String,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    fn response(content: &str) -> AIResponse {
+        AIResponse { content: content.to_string(), input_tokens: 10, output_tokens: 20, reasoning_tokens: 0, cost: 0.001, model: "mock-model".to_string(), provider: "mock-provider".to_string() }
+    }
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            let mut decisions = self.decisions.lock().unwrap();
+            Ok(response(&if decisions.is_empty() { json!({ "thought": "t", "tool_name": ANSWER_TOOL, "parameters": {} }).to_string() } else { decisions.remove(0) }))
+        }
+        async fn generate_stream(&self, messages: &[ChatMessage], _params: &GenerationParams, on_text: &OnText<'_>) -> Result<AIResponse, AgentError> {
+            self.prompts.lock().unwrap().push(messages[1].content.clone());
+            let (first, rest) = self.answer.split_at(self.answer.len() / 2);
+            on_text(first);
+            on_text(rest);
+            Ok(response(&self.answer))
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo { name: "mock-model".to_string(), input_cost_per_token: 0.0, output_cost_per_token: 0.0 }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explain_reads_files_then_streams_an_answer() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("auth.rs"), "fn check_token() {}").unwrap();
+        let decisions = [
+            json!({ "thought": "Change it", "tool_name": "WriteFile", "parameters": { "path": "auth.rs", "content": "" } }),
+            json!({ "thought": "Read it", "tool_name": "ReadFile", "parameters": { "path": "auth.rs" } }),
+        ];
+        let client = Arc::new(MockLLMClient {
+            decisions: Mutex::new(decisions.iter().map(Value::to_string).collect()),
+            answer: "Tokens are checked by `check_token` in auth.rs.".to_string(),
+            prompts: Mutex::new(Vec::new()),
+        });
+        let cost_tracker = Arc::new(CostTracker::new());
+        let context = ToolContext::new(Arc::new(AppConfig::default())).with_workdir(dir.path());
+        let explainer = ExplainerAgent::new(client.clone(), cost_tracker.clone(), context);
+
+        let streamed = Mutex::new(String::new());
+        let answer = explainer.explain("How does auth work?", &|text: &str| streamed.lock().unwrap().push_str(text)).await.unwrap();
+
+        assert_eq!(answer, "Tokens are checked by `check_token` in auth.rs.");
+        assert_eq!(*streamed.lock().unwrap(), answer);
+        assert_eq!(std::fs::read_to_string(dir.path().join("auth.rs")).unwrap(), "fn check_token() {}");
+        let prompts = client.prompts.lock().unwrap();
+        assert!(!prompts[0].contains("`WriteFile {") && !prompts[0].contains("`RunCommand {"));
+        assert!(prompts[1].contains("not available in read-only mode"));
+        assert!(prompts.last().unwrap().contains("fn check_token() {}"));
+        assert!((cost_tracker.get_total_cost() - 0.004).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_explain_answers_after_max_lookups() {
+        let read = json!({ "thought": "Read it", "tool_name": "ListFiles", "parameters": { "path": "." } }).to_string();
+        let client = Arc::new(MockLLMClient { decisions: Mutex::new(vec![read; 5]), answer: "An empty project.".to_string(), prompts: Mutex::new(Vec::new()) });
+        let dir = tempfile::tempdir().unwrap();
+        let context = ToolContext::new(Arc::new(AppConfig::default())).with_workdir(dir.path());
+        let explainer = ExplainerAgent::new(client.clone(), Arc::new(CostTracker::new()), context).with_max_lookups(2);
+
+        assert_eq!(explainer.explain("What is this?", &|_: &str| {}).await.unwrap(), "An empty project.");
+        assert_eq!(client.prompts.lock().unwrap().len(), 3);
+    }
+}
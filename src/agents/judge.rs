@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::Result;
+use log::info;
+use serde::Deserialize;
+
+use crate::{error::AgentError, json_repair, llm::{GenerationParams, LLMClient}, cost_tracker::CostTracker, tools::Decision};
+
+/// The index of the decision a strict majority of `decisions` agrees on by tool, or `None` on a
+/// split vote. Among the majority, the decision whose parameters most others share wins, the
+/// earliest on a tie.
+pub fn majority(decisions: &[Decision]) -> Option<usize> {
+    let mut votes: HashMap<String, usize> = HashMap::new();
+    for decision in decisions {
+        *votes.entry(decision.tool.name()).or_default() += 1;
+    }
+    let (winner, count) = votes.into_iter().max_by_key(|(_, count)| *count)?;
+    if count * 2 <= decisions.len() {
+        return None;
+    }
+    let actions: Vec<String> = decisions.iter().map(|decision| serde_json::to_string(&decision.tool).unwrap_or_default()).collect();
+    (0..decisions.len())
+        .filter(|&i| decisions[i].tool.name() == winner)
+        .max_by_key(|&i| (actions.iter().filter(|action| **action == actions[i]).count(), std::cmp::Reverse(i)))
+}
+
+#[derive(Debug, Deserialize)]
+struct Judgement {
+    #[serde(default)]
+    critique: String,
+    choice: usize,
+}
+
+/// Settles split votes of a decision ensemble: critiques the candidate decisions and picks one.
+pub struct JudgeAgent {
+    llm_client: Arc<dyn LLMClient>,
+    cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+}
+
+impl JudgeAgent {
+    pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
+        Self { llm_client, cost_tracker, params: GenerationParams::default() }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// The index of the best of `candidates`, the answers of several models to `decision_prompt`.
+    pub async fn pick(&self, decision_prompt: &str, candidates: &[Decision]) -> Result<usize, AgentError> {
+        let prompt = self.build_prompt(decision_prompt, candidates);
+        info!("Judge prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = self.llm_client.generate_json(&prompt, &self.params).await?;
+        self.cost_tracker.record_call("judge", &response, started.elapsed());
+        info!("Judge response:\n{}", response.content);
+        self.parse_choice(&response.content, candidates.len())
+    }
+
+    fn build_prompt(&self, decision_prompt: &str, candidates: &[Decision]) -> String {
+        let candidates = candidates
+            .iter()
+            .enumerate()
+            .map(|(i, decision)| format!("{}. {}", i + 1, serde_json::to_string(decision).unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!(r#"
+You are settling a disagreement. Several AI models were given the decision prompt below and chose different actions for a CLI coding agent.
+
+--- DECISION PROMPT ---
+{decision_prompt}
+--- END DECISION PROMPT ---
+
+--- CANDIDATE DECISIONS ---
+{candidates}
+--- END CANDIDATE DECISIONS ---
+
+Critique each candidate against the current step and the context: does it make real progress on the step, are its parameters correct, could it do damage?
+Then choose the candidate most likely to complete the step correctly.
+
+You MUST respond with a single JSON object matching this structure:
+{{
+  "critique": "One or two sentences per candidate.",
+  "choice": 1
+}}
+"#)
+    }
+
+    fn parse_choice(&self, response: &str, candidates: usize) -> Result<usize, AgentError> {
+        let judgement: Judgement = json_repair::parse_lenient(response)
+            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse judgement: {}. Response: {}", e, response)))?;
+        info!("Judge critique: {}", judgement.critique);
+        if judgement.choice == 0 || judgement.choice > candidates {
+            return Err(AgentError::ResponseParseError(format!("The judge chose candidate {}, but there are only {}", judgement.choice, candidates)));
+        }
+        Ok(judgement.choice - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(tool: &str, parameters: serde_json::Value) -> Decision {
+        serde_json::from_value(serde_json::json!({ "thought": "t", "tool_name": tool, "parameters": parameters })).unwrap()
+    }
+
+    #[test]
+    fn test_majority_agrees_on_the_tool_and_prefers_shared_parameters() {
+        let read = |path: &str| decision("ReadFile", serde_json::json!({ "path": path }));
+        let search = decision("SearchInFiles", serde_json::json!({ "pattern": "main", "path": "." }));
+        assert_eq!(majority(&[read("a.rs"), read("b.rs"), read("b.rs")]), Some(1));
+        assert_eq!(majority(&[search.clone(), read("a.rs"), read("b.rs")]), Some(1));
+        assert_eq!(majority(&[read("a.rs"), search.clone()]), None);
+        assert_eq!(majority(&[search]), Some(0));
+        assert_eq!(majority(&[]), None);
+    }
+}
@@ -1,2 +1,38 @@
+use crate::error::AgentError;
+use crate::llm::{AIResponse, ChatMessage, GenerationParams, LLMClient};
+
 pub mod coder;
+pub mod documenter;
+pub mod explainer;
+pub mod judge;
 pub mod planner;
+pub mod retriever;
+pub mod reviewer;
+pub mod scribe;
+pub mod summarizer;
+pub mod verifier;
+
+/// The persona of each agent that has one, sent as a system message before its prompts. Unset
+/// agents send their prompts alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemPrompts {
+    pub planner: Option<String>,
+    pub coder: Option<String>,
+    pub reviewer: Option<String>,
+}
+
+/// Sends `prompt`, after `system` as a system message when there is one; as JSON when `json` is set.
+async fn send(client: &dyn LLMClient, system: Option<&str>, prompt: &str, params: &GenerationParams, json: bool) -> Result<AIResponse, AgentError> {
+    match system {
+        Some(system) => {
+            let messages = [ChatMessage::system(system), ChatMessage::user(prompt)];
+            if json {
+                client.generate_json_chat(&messages, params).await
+            } else {
+                client.generate_chat(&messages, params).await
+            }
+        }
+        None if json => client.generate_json(prompt, params).await,
+        None => client.generate(prompt, params).await,
+    }
+}
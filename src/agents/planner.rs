@@ -1,29 +1,103 @@
 use std::sync::Arc;
+use std::time::Instant;
 use anyhow::Result;
 use log::info;
+use serde::Deserialize;
 
-use crate::{error::AgentError, llm::{LLMClient, AIResponse, ModelInfo}, cost_tracker::CostTracker};
+use crate::{error::AgentError, llm::{GenerationParams, LLMClient}, cost_tracker::CostTracker};
 
+/// Whether the remaining steps of a plan still make sense after the latest step.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlanCheck {
+    pub valid: bool,
+    #[serde(default)]
+    pub reason: String,
+}
 
 pub struct PlannerAgent {
     llm_client: Arc<dyn LLMClient>,
     cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+    system_prompt: Option<String>,
 }
 
 impl PlannerAgent {
     pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
-        Self { llm_client, cost_tracker }
+        Self { llm_client, cost_tracker, params: GenerationParams::default(), system_prompt: None }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// The planner's persona, sent as a system message before each prompt.
+    pub fn with_system_prompt(mut self, system_prompt: Option<String>) -> Self {
+        self.system_prompt = system_prompt;
+        self
     }
 
     pub async fn create_plan(&self, goal: &str, context: &str) -> Result<Vec<String>, AgentError> {
         let prompt = self.build_prompt(goal, context);
         info!("Planner prompt:\n{}", prompt);
-        let response = self.llm_client.generate(&prompt).await?;
-        self.cost_tracker.add_cost(response.cost);
+        let started = Instant::now();
+        let response = super::send(self.llm_client.as_ref(), self.system_prompt.as_deref(), &prompt, &self.params, false).await?;
+        self.cost_tracker.record_call("planner", &response, started.elapsed());
         info!("Planner response:\n{}", response.content);
         Ok(self.parse_plan(&response.content))
     }
 
+    /// Asks whether `remaining` is still the right way to finish the goal given what has happened.
+    pub async fn check_plan(&self, goal: &str, completed: &[String], remaining: &[String], context: &str) -> Result<PlanCheck, AgentError> {
+        let prompt = self.build_check_prompt(goal, completed, remaining, context);
+        info!("Plan check prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = super::send(self.llm_client.as_ref(), self.system_prompt.as_deref(), &prompt, &self.params, true).await?;
+        self.cost_tracker.record_call("planner", &response, started.elapsed());
+        info!("Plan check response:\n{}", response.content);
+        serde_json::from_str(response.content.trim())
+            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse plan check: {}. Response: {}", e, response.content)))
+    }
+
+    /// Plans the rest of the goal from scratch, given the completed steps and why the old plan was dropped.
+    pub async fn replan(&self, goal: &str, completed: &[String], reason: &str, context: &str) -> Result<Vec<String>, AgentError> {
+        let objective = format!(
+            "{goal}\n\nThese steps are already done:\n{}\n\nThe previous plan for the rest was abandoned because: {reason}\nPlan ONLY the remaining work.",
+            numbered(completed)
+        );
+        self.create_plan(&objective, context).await
+    }
+
+    fn build_check_prompt(&self, goal: &str, completed: &[String], remaining: &[String], context: &str) -> String {
+        let completed = numbered(completed);
+        let remaining = numbered(remaining);
+        format!(r#"
+You are supervising an AI agent that is executing a plan for the goal: "{goal}"
+
+--- CONTEXT ---
+{context}
+--- END CONTEXT ---
+
+--- COMPLETED STEPS ---
+{completed}
+--- END COMPLETED STEPS ---
+
+--- REMAINING STEPS ---
+{remaining}
+--- END REMAINING STEPS ---
+
+Based on what the completed steps revealed, is the remaining plan still a correct and sensible way to reach the goal?
+Mark it invalid only when a remaining step is wrong, impossible, redundant, or something essential is missing.
+
+You MUST respond with a single JSON object matching this structure:
+{{
+  "valid": true or false,
+  "reason": "Why the remaining plan is no longer valid. Empty when valid."
+}}
+"#)
+    }
+
     fn build_prompt(&self, goal: &str, context: &str) -> String {
         format!(r#"
 You are a master planner AI. Your job is to create a detailed, step-by-step plan to accomplish a given programming goal.
@@ -46,20 +120,28 @@ Output ONLY the numbered list of steps, with each step on a new line. Do not inc
             .lines()
             .map(|line| line.trim())
             .filter(|line| !line.is_empty())
-            .filter_map(|line| {
+            .map(|line| {
                 if let Some(pos) = line.find(". ") {
-                    Some(line[pos + 2..].to_string())
+                    line[pos + 2..].to_string()
                 } else {
-                    Some(line.to_string())
+                    line.to_string()
                 }
             })
             .collect()
     }
 }
 
+fn numbered(steps: &[String]) -> String {
+    if steps.is_empty() {
+        return "(none)".to_string();
+    }
+    steps.iter().enumerate().map(|(i, step)| format!("{}. {}", i + 1, step)).collect::<Vec<_>>().join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::llm::{AIResponse, ModelInfo};
     use async_trait::async_trait;
     use std::sync::Arc;
 
@@ -71,18 +153,19 @@ mod tests {
 
     #[async_trait]
     impl LLMClient for MockLLMClient {
-        async fn generate(&self, _prompt: &str) -> Result<AIResponse, AgentError> {
+        async fn generate(&self, _prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
             Ok(AIResponse {
                 content: self.response.clone(),
                 input_tokens: 10,
                 output_tokens: 20,
+                reasoning_tokens: 0,
                 cost: self.cost,
                 model: "mock-model".to_string(),
                 provider: "mock-provider".to_string(),
             })
         }
-        async fn generate_json(&self, _prompt: &str) -> Result<AIResponse, AgentError> {
-            self.generate(_prompt).await
+        async fn generate_json(&self, _prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            self.generate(_prompt, params).await
         }
         async fn get_model_info(&self) -> ModelInfo {
             ModelInfo {
@@ -117,6 +200,56 @@ mod tests {
         assert_eq!(cost_tracker.get_total_cost(), 0.001);
     }
 
+    #[tokio::test]
+    async fn test_create_plan_sends_persona_as_system_message() {
+        let mock_client = Arc::new(crate::llm::mock::MockClient::replaying(["1. Read files", "1. Read files"]));
+        let planner = PlannerAgent::new(mock_client.clone(), Arc::new(CostTracker::new()))
+            .with_system_prompt(Some("You plan small, testable steps.".to_string()));
+        planner.create_plan("Create a function", "No context").await.unwrap();
+        let planner = PlannerAgent::new(mock_client.clone(), Arc::new(CostTracker::new()));
+        planner.create_plan("Create a function", "No context").await.unwrap();
+
+        let prompts = mock_client.prompts();
+        assert!(prompts[0].starts_with("[system]\nYou plan small, testable steps.\n\n[user]\n"));
+        assert!(!prompts[1].contains("[system]"));
+    }
+
+    #[tokio::test]
+    async fn test_check_plan() {
+        let mock_client = Arc::new(MockLLMClient {
+            response: r#"{"valid": false, "reason": "The project uses pnpm, not npm"}"#.to_string(),
+            cost: 0.001,
+        });
+        let planner = PlannerAgent::new(mock_client, Arc::new(CostTracker::new()));
+        let check = planner
+            .check_plan("Add a dependency", &["Read package.json".to_string()], &["Run npm install".to_string()], "ctx")
+            .await
+            .unwrap();
+
+        assert!(!check.valid);
+        assert_eq!(check.reason, "The project uses pnpm, not npm");
+    }
+
+    #[tokio::test]
+    async fn test_check_plan_invalid_response() {
+        let mock_client = Arc::new(MockLLMClient { response: "Looks fine".to_string(), cost: 0.0 });
+        let planner = PlannerAgent::new(mock_client, Arc::new(CostTracker::new()));
+        let result = planner.check_plan("Goal", &[], &["Step".to_string()], "ctx").await;
+        assert!(matches!(result, Err(AgentError::ResponseParseError(_))));
+    }
+
+    #[test]
+    fn test_build_check_prompt() {
+        let mock_client = Arc::new(MockLLMClient { response: "".to_string(), cost: 0.0 });
+        let planner = PlannerAgent::new(mock_client, Arc::new(CostTracker::new()));
+        let prompt = planner.build_check_prompt("Ship it", &["Build".to_string()], &["Test".to_string(), "Deploy".to_string()], "ctx");
+
+        assert!(prompt.contains("Ship it"));
+        assert!(prompt.contains("1. Build"));
+        assert!(prompt.contains("1. Test\n2. Deploy"));
+        assert!(prompt.contains("\"valid\""));
+    }
+
     #[test]
     fn test_build_prompt() {
         let mock_client = Arc::new(MockLLMClient {
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::Result;
+use log::info;
+use serde::Deserialize;
+
+use crate::{error::AgentError, json_repair, llm::{GenerationParams, LLMClient}, cost_tracker::CostTracker};
+
+#[derive(Debug, Deserialize)]
+struct Selection {
+    #[serde(default)]
+    files: Vec<String>,
+}
+
+/// Picks the existing files a coding task needs to see, so the coder edits them instead of
+/// writing code blind.
+pub struct RetrieverAgent {
+    llm_client: Arc<dyn LLMClient>,
+    cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+}
+
+impl RetrieverAgent {
+    pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
+        Self { llm_client, cost_tracker, params: GenerationParams::default() }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Asks which of `candidates` (project-relative paths) `task`, part of `goal`, has to modify or
+    /// build on. Returns at most `max_files` of them, most relevant first; paths that are not
+    /// candidates are dropped.
+    pub async fn select_files(&self, goal: &str, task: &str, candidates: &[String], max_files: usize) -> Result<Vec<String>, AgentError> {
+        let prompt = self.build_prompt(goal, task, candidates, max_files);
+        info!("Retriever prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = self.llm_client.generate_json(&prompt, &self.params).await?;
+        self.cost_tracker.record_call("retriever", &response, started.elapsed());
+        info!("Retriever response:\n{}", response.content);
+        self.parse_selection(&response.content, candidates, max_files)
+    }
+
+    fn build_prompt(&self, goal: &str, task: &str, candidates: &[String], max_files: usize) -> String {
+        let candidates = candidates.join("\n");
+        format!(r#"
+You are helping an AI coding agent that is about to write code.
+The overall goal is: "{goal}"
+The coding task is: "{task}"
+
+--- Project Files ---
+{candidates}
+--- End Project Files ---
+
+Which of these files must the coder read before writing the code: files the task modifies, and files
+whose types, functions or conventions the new code uses? Pick at most {max_files}, most relevant first.
+Pick none if the task does not depend on existing files.
+
+You MUST respond with a single JSON object matching this structure:
+{{
+  "files": ["src/lib.rs"]
+}}
+"#)
+    }
+
+    fn parse_selection(&self, response: &str, candidates: &[String], max_files: usize) -> Result<Vec<String>, AgentError> {
+        let selection: Selection = json_repair::parse_lenient(response)
+            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse file selection: {}. Response: {}", e, response)))?;
+        let mut files: Vec<String> = Vec::new();
+        for file in selection.files {
+            let file = file.trim().trim_start_matches("./").to_string();
+            if candidates.contains(&file) && !files.contains(&file) {
+                files.push(file);
+            }
+        }
+        files.truncate(max_files);
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{AIResponse, ModelInfo};
+    use async_trait::async_trait;
+
+    struct MockLLMClient {
+        response: String,
+    }
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, _prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            Ok(AIResponse {
+                content: self.response.clone(),
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 0,
+                cost: 0.001,
+                model: "mock-model".to_string(),
+                provider: "mock-provider".to_string(),
+            })
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo { name: "mock-model".to_string(), input_cost_per_token: 0.0, output_cost_per_token: 0.0 }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_select_files_keeps_known_files_up_to_the_limit() {
+        let cost_tracker = Arc::new(CostTracker::new());
+        let client = MockLLMClient { response: r#"{"files": ["./src/users.rs", "src/missing.rs", "src/users.rs", "src/lib.rs", "Cargo.toml"]}"#.to_string() };
+        let retriever = RetrieverAgent::new(Arc::new(client), cost_tracker.clone());
+        let candidates = ["Cargo.toml", "src/lib.rs", "src/users.rs"].map(String::from);
+
+        let files = retriever.select_files("Add users", "Add a find_by_email method", &candidates, 2).await.unwrap();
+        assert_eq!(files, vec!["src/users.rs".to_string(), "src/lib.rs".to_string()]);
+        assert_eq!(cost_tracker.get_total_cost(), 0.001);
+    }
+
+    #[test]
+    fn test_build_prompt_lists_the_candidates() {
+        let retriever = RetrieverAgent::new(Arc::new(MockLLMClient { response: String::new() }), Arc::new(CostTracker::new()));
+        let prompt = retriever.build_prompt("Add users", "Write the repository", &["src/lib.rs".to_string(), "src/db.rs".to_string()], 3);
+        assert!(prompt.contains("--- Project Files ---\nsrc/lib.rs\nsrc/db.rs\n"));
+        assert!(prompt.contains("at most 3"));
+        assert!(retriever.parse_selection("not json", &[], 3).is_err());
+        let fenced = "Here you go:\n```json\n{\"files\": [\"src/lib.rs\",]}\n```";
+        assert_eq!(retriever.parse_selection(fenced, &["src/lib.rs".to_string()], 3).unwrap(), vec!["src/lib.rs".to_string()]);
+    }
+}
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::Result;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AgentError, json_repair, llm::{GenerationParams, LLMClient}, cost_tracker::CostTracker, review::DiffReview};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewVerdict {
+    Approve,
+    RequestChanges,
+}
+
+/// Structured feedback from the reviewer on a piece of generated code.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Review {
+    pub verdict: ReviewVerdict,
+    #[serde(default)]
+    pub reasons: Vec<String>,
+}
+
+impl Review {
+    pub fn is_approved(&self) -> bool {
+        self.verdict == ReviewVerdict::Approve
+    }
+}
+
+pub struct ReviewerAgent {
+    llm_client: Arc<dyn LLMClient>,
+    cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+    system_prompt: Option<String>,
+}
+
+impl ReviewerAgent {
+    pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
+        Self { llm_client, cost_tracker, params: GenerationParams::default(), system_prompt: None }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// The reviewer's persona, sent as a system message before each prompt.
+    pub fn with_system_prompt(mut self, system_prompt: Option<String>) -> Self {
+        self.system_prompt = system_prompt;
+        self
+    }
+
+    pub async fn review(&self, task_description: &str, code: &str, context: &str) -> Result<Review, AgentError> {
+        let prompt = self.build_prompt(task_description, code, context);
+        info!("Reviewer prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = super::send(self.llm_client.as_ref(), self.system_prompt.as_deref(), &prompt, &self.params, true).await?;
+        self.cost_tracker.record_call("reviewer", &response, started.elapsed());
+        info!("Reviewer response:\n{}", response.content);
+        self.parse_review(&response.content)
+    }
+
+    /// Reviews a unified `diff`, with `context` the lines around its hunks, and lists the problems
+    /// it finds, most severe first.
+    pub async fn review_diff(&self, diff: &str, context: &str) -> Result<DiffReview, AgentError> {
+        let prompt = build_diff_prompt(diff, context);
+        info!("Reviewer prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = super::send(self.llm_client.as_ref(), self.system_prompt.as_deref(), &prompt, &self.params, true).await?;
+        self.cost_tracker.record_call("reviewer", &response, started.elapsed());
+        info!("Reviewer response:\n{}", response.content);
+        let mut review: DiffReview = json_repair::parse_lenient(&response.content)
+            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse diff review: {}. Response: {}", e, response.content)))?;
+        review.findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+        Ok(review)
+    }
+
+    fn build_prompt(&self, task_description: &str, code: &str, context: &str) -> String {
+        format!(r#"
+You are a meticulous senior code reviewer. Another AI wrote the code below for a specific task.
+Decide whether it is correct, complete, and safe to save as-is.
+
+--- Context ---
+{context}
+--- End Context ---
+
+The task was: "{task_description}"
+
+--- Code Under Review ---
+{code}
+--- End Code ---
+
+Approve the code if it accomplishes the task without bugs. Request changes only for concrete problems
+(bugs, missing requirements, syntax errors, security issues), not for style preferences.
+
+You MUST respond with a single JSON object matching this structure:
+{{
+  "verdict": "approve" or "request_changes",
+  "reasons": ["One specific, actionable problem per entry. Empty when approving."]
+}}
+"#)
+    }
+
+    fn parse_review(&self, response: &str) -> Result<Review, AgentError> {
+        serde_json::from_str(response.trim())
+            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse code review: {}. Response: {}", e, response)))
+    }
+}
+
+fn build_diff_prompt(diff: &str, context: &str) -> String {
+    format!(r#"
+You are a meticulous senior code reviewer reviewing a change before it is merged.
+
+--- Surrounding Code (numbered lines of the changed files, after the change) ---
+{context}
+--- End Surrounding Code ---
+
+--- Diff ---
+{diff}
+--- End Diff ---
+
+Look for concrete problems the change introduces: bugs, missing error handling, security issues,
+broken callers, missing tests for new behavior. Skip style preferences and code the diff does not touch.
+Rate each finding "high" (must be fixed before merging), "medium" (should be fixed) or "low" (worth a look).
+Give the line in the changed version of the file, from the numbered surrounding code, when the problem has one.
+
+You MUST respond with a single JSON object matching this structure:
+{{
+  "summary": "One or two sentences on the change and its overall quality.",
+  "findings": [
+    {{ "severity": "high", "file": "path/to/file.ext", "line": 42, "message": "The problem.", "suggestion": "How to fix it, or null." }}
+  ]
+}}
+"#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{AIResponse, ModelInfo};
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    // Mock LLM client for testing
+    struct MockLLMClient {
+        response: String,
+        cost: f64,
+    }
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, _prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            Ok(AIResponse {
+                content: self.response.clone(),
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 0,
+                cost: self.cost,
+                model: "mock-model".to_string(),
+                provider: "mock-provider".to_string(),
+            })
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo {
+                name: "mock-model".to_string(),
+                input_cost_per_token: 0.0,
+                output_cost_per_token: 0.0,
+            }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    fn reviewer_with_response(response: &str) -> (ReviewerAgent, Arc<CostTracker>) {
+        let mock_client = Arc::new(MockLLMClient {
+            response: response.to_string(),
+            cost: 0.001,
+        });
+        let cost_tracker = Arc::new(CostTracker::new());
+        (ReviewerAgent::new(mock_client, cost_tracker.clone()), cost_tracker)
+    }
+
+    #[tokio::test]
+    async fn test_review_approve() {
+        let (reviewer, cost_tracker) = reviewer_with_response(r#"{"verdict": "approve", "reasons": []}"#);
+        let review = reviewer.review("Add numbers", "def add(a, b): return a + b", "ctx").await.unwrap();
+
+        assert!(review.is_approved());
+        assert!(review.reasons.is_empty());
+        assert_eq!(cost_tracker.get_total_cost(), 0.001);
+    }
+
+    #[tokio::test]
+    async fn test_review_request_changes() {
+        let (reviewer, _) = reviewer_with_response(r#"{"verdict": "request_changes", "reasons": ["Subtracts instead of adding"]}"#);
+        let review = reviewer.review("Add numbers", "def add(a, b): return a - b", "ctx").await.unwrap();
+
+        assert_eq!(review.verdict, ReviewVerdict::RequestChanges);
+        assert_eq!(review.reasons, vec!["Subtracts instead of adding".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_review_missing_reasons_defaults_empty() {
+        let (reviewer, _) = reviewer_with_response(r#"{"verdict": "approve"}"#);
+        let review = reviewer.review("Task", "code", "ctx").await.unwrap();
+        assert!(review.reasons.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_review_invalid_response() {
+        let (reviewer, _) = reviewer_with_response("Looks good to me!");
+        let result = reviewer.review("Task", "code", "ctx").await;
+        assert!(matches!(result, Err(AgentError::ResponseParseError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_review_diff_sorts_findings_by_severity() {
+        let (reviewer, cost_tracker) = reviewer_with_response(r#"```json
+{"summary": "Adds token checks.", "findings": [
+  {"severity": "low", "file": "src/auth.rs", "line": 3, "message": "Unclear name."},
+  {"severity": "high", "file": "src/auth.rs", "line": 12, "message": "Expired tokens pass.", "suggestion": "Compare `exp` with now."}
+]}
+```"#);
+        let review = reviewer.review_diff("+fn check() {}", "    1 fn check() {}").await.unwrap();
+
+        assert_eq!(review.summary, "Adds token checks.");
+        assert_eq!(review.findings[0].severity, crate::review::Severity::High);
+        assert_eq!(review.findings[0].suggestion.as_deref(), Some("Compare `exp` with now."));
+        assert_eq!(review.findings[1].line, Some(3));
+        assert!(review.has_blocking());
+        assert_eq!(cost_tracker.get_total_cost(), 0.001);
+
+        let prompt = build_diff_prompt("+fn check() {}", "    1 fn check() {}");
+        assert!(prompt.contains("+fn check() {}") && prompt.contains("    1 fn check() {}"));
+    }
+
+    #[test]
+    fn test_build_prompt() {
+        let (reviewer, _) = reviewer_with_response("");
+        let prompt = reviewer.build_prompt("Write a parser", "fn parse() {}", "Rust project");
+
+        assert!(prompt.contains("Write a parser"));
+        assert!(prompt.contains("fn parse() {}"));
+        assert!(prompt.contains("Rust project"));
+        assert!(prompt.contains("request_changes"));
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::Result;
+use log::info;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{error::AgentError, json_repair, llm::{GenerationParams, LLMClient}, cost_tracker::CostTracker};
+
+/// The changelog the scribe adds entries to, in the working directory.
+pub const CHANGELOG_FILE: &str = "CHANGELOG.md";
+/// Characters of the diff shown to the scribe; the rest is cut off.
+const MAX_DIFF_CHARS: usize = 20_000;
+const CONVENTIONAL_SUBJECT: &str = r"^(feat|fix|docs|style|refactor|perf|test|build|ci|chore|revert)(\([\w./-]+\))?!?: \S";
+
+/// What the scribe writes for a set of changes.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CommitNote {
+    /// A conventional-commit message: `type(scope): subject`, optionally followed by a body.
+    pub commit_message: String,
+    /// Markdown bullet(s) for the changelog; `None` for changes users would not notice.
+    #[serde(default)]
+    pub changelog: Option<String>,
+}
+
+/// Writes the commit message, and a changelog entry, for the changes a run leaves behind or the
+/// changes staged for `commit`.
+pub struct ScribeAgent {
+    llm_client: Arc<dyn LLMClient>,
+    cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+}
+
+impl ScribeAgent {
+    pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
+        Self { llm_client, cost_tracker, params: GenerationParams::default() }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Describes `diff`, the changes made for `goal` when a run made them. A changelog entry is only
+    /// asked for when `changelog` is set, i.e. when the project keeps one.
+    pub async fn describe(&self, goal: Option<&str>, diff: &str, changelog: bool) -> Result<CommitNote, AgentError> {
+        let prompt = self.build_prompt(goal, diff, changelog);
+        info!("Scribe prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = self.llm_client.generate_json(&prompt, &self.params).await?;
+        self.cost_tracker.record_call("scribe", &response, started.elapsed());
+        info!("Scribe response:\n{}", response.content);
+        let mut note = self.parse_note(&response.content)?;
+        if !changelog {
+            note.changelog = None;
+        }
+        Ok(note)
+    }
+
+    fn build_prompt(&self, goal: Option<&str>, diff: &str, changelog: bool) -> String {
+        let diff = match diff.char_indices().nth(MAX_DIFF_CHARS) {
+            Some((cut, _)) => format!("{}\n[... diff truncated ...]", &diff[..cut]),
+            None => diff.to_string(),
+        };
+        let changelog = if changelog {
+            "a Markdown bullet list (\"- ...\") describing the change for the project's users, or null if users would not notice it"
+        } else {
+            "null"
+        };
+        let intro = match goal {
+            Some(goal) => format!("You are writing the commit for changes an AI coding agent made.\nThe agent's goal was: \"{}\"", goal),
+            None => "You are writing the commit for the changes a developer staged.".to_string(),
+        };
+        format!(r#"
+{intro}
+
+--- Diff ---
+{diff}
+--- End Diff ---
+
+Write a Conventional Commits message: a subject line "type(scope): summary" where type is one of feat, fix,
+docs, style, refactor, perf, test, build, ci, chore or revert, the scope is optional, and the summary is in
+the imperative mood, lowercase and at most 72 characters in all. Add a body after a blank line only when the
+reason for the change is not obvious from the subject.
+
+You MUST respond with a single JSON object matching this structure:
+{{
+  "commit_message": "feat(users): add a repository for the users table",
+  "changelog": {changelog}
+}}
+"#)
+    }
+
+    fn parse_note(&self, response: &str) -> Result<CommitNote, AgentError> {
+        let mut note: CommitNote = json_repair::parse_lenient(response)
+            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse commit note: {}. Response: {}", e, response)))?;
+        note.commit_message = note.commit_message.trim().to_string();
+        let subject = note.commit_message.lines().next().unwrap_or_default();
+        if !Regex::new(CONVENTIONAL_SUBJECT).expect("valid regex").is_match(subject) {
+            return Err(AgentError::ResponseParseError(format!("'{}' is not a conventional commit subject", subject)));
+        }
+        note.changelog = note.changelog.map(|entry| entry.trim().to_string()).filter(|entry| !entry.is_empty());
+        Ok(note)
+    }
+}
+
+/// `changelog` with `entry` added at the end of its `## [Unreleased]` section, which is created
+/// above the first release when missing.
+pub fn add_changelog_entry(changelog: &str, entry: &str) -> String {
+    let entry = entry.trim();
+    let updated = match changelog.find("## [Unreleased]") {
+        Some(start) => {
+            let heading_end = changelog[start..].find('\n').map_or(changelog.len(), |end| start + end + 1);
+            let section_end = changelog[heading_end - 1..].find("\n## ").map_or(changelog.len(), |end| heading_end + end);
+            let section = changelog[heading_end..section_end].trim();
+            let section = if section.is_empty() { entry.to_string() } else { format!("{}\n{}", section, entry) };
+            let heading = changelog[..heading_end].trim_end();
+            format!("{}\n\n{}\n\n{}", heading, section, &changelog[section_end..])
+        }
+        None => {
+            let first_release = if changelog.starts_with("## ") { Some(0) } else { changelog.find("\n## ").map(|i| i + 1) };
+            let unreleased = format!("## [Unreleased]\n\n{}\n\n", entry);
+            match first_release {
+                Some(i) => format!("{}{}{}", &changelog[..i], unreleased, &changelog[i..]),
+                None if changelog.trim().is_empty() => format!("# Changelog\n\n{}", unreleased),
+                None => format!("{}\n\n{}", changelog.trim_end(), unreleased),
+            }
+        }
+    };
+    format!("{}\n", updated.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{AIResponse, ModelInfo};
+    use async_trait::async_trait;
+
+    struct MockLLMClient {
+        response: String,
+    }
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, _prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            Ok(AIResponse {
+                content: self.response.clone(),
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 0,
+                cost: 0.001,
+                model: "mock-model".to_string(),
+                provider: "mock-provider".to_string(),
+            })
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo { name: "mock-model".to_string(), input_cost_per_token: 0.0, output_cost_per_token: 0.0 }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    fn scribe_with_response(response: &str) -> (ScribeAgent, Arc<CostTracker>) {
+        let cost_tracker = Arc::new(CostTracker::new());
+        (ScribeAgent::new(Arc::new(MockLLMClient { response: response.to_string() }), cost_tracker.clone()), cost_tracker)
+    }
+
+    #[tokio::test]
+    async fn test_describe_returns_a_conventional_commit_and_changelog_entry() {
+        let (scribe, cost_tracker) = scribe_with_response(r#"{"commit_message": "feat(users): add a users repository\n\nReads users with sqlx.", "changelog": "- Users can be looked up by email."}"#);
+        let note = scribe.describe(Some("Add a users repository"), "+pub struct UserRepository;", true).await.unwrap();
+        assert_eq!(note.commit_message, "feat(users): add a users repository\n\nReads users with sqlx.");
+        assert_eq!(note.changelog.as_deref(), Some("- Users can be looked up by email."));
+        assert_eq!(cost_tracker.get_total_cost(), 0.001);
+
+        // Without a changelog in the project, an entry is dropped even if the model writes one.
+        assert_eq!(scribe.describe(Some("Add a users repository"), "", false).await.unwrap().changelog, None);
+    }
+
+    #[tokio::test]
+    async fn test_describe_accepts_a_fenced_note() {
+        let (scribe, _) = scribe_with_response("```json\n{\"commit_message\": \"fix: handle empty input\", \"changelog\": null,}\n```");
+        assert_eq!(scribe.describe(None, "", false).await.unwrap().commit_message, "fix: handle empty input");
+    }
+
+    #[tokio::test]
+    async fn test_describe_rejects_unconventional_messages() {
+        let (scribe, _) = scribe_with_response(r#"{"commit_message": "Added the users repository", "changelog": null}"#);
+        assert!(matches!(scribe.describe(None, "", false).await, Err(AgentError::ResponseParseError(_))));
+    }
+
+    #[test]
+    fn test_build_prompt_without_a_goal() {
+        let (scribe, _) = scribe_with_response("");
+        let prompt = scribe.build_prompt(None, "+fn main() {}", false);
+        assert!(prompt.contains("changes a developer staged") && !prompt.contains("goal was"));
+        assert!(prompt.contains("+fn main() {}") && prompt.contains(r#""changelog": null"#));
+    }
+
+    #[test]
+    fn test_add_changelog_entry_appends_to_the_unreleased_section() {
+        let changelog = "# Changelog\n\n## [Unreleased]\n\n- Existing change.\n\n## [1.0.0] - 2024-05-01\n\n- First release.\n";
+        assert_eq!(
+            add_changelog_entry(changelog, "- New change."),
+            "# Changelog\n\n## [Unreleased]\n\n- Existing change.\n- New change.\n\n## [1.0.0] - 2024-05-01\n\n- First release.\n"
+        );
+        assert_eq!(
+            add_changelog_entry("# Changelog\n\n## [1.0.0]\n\n- First release.\n", "- New change."),
+            "# Changelog\n\n## [Unreleased]\n\n- New change.\n\n## [1.0.0]\n\n- First release.\n"
+        );
+        assert_eq!(add_changelog_entry("", "- New change."), "# Changelog\n\n## [Unreleased]\n\n- New change.\n");
+    }
+}
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::Result;
+use log::info;
+
+use crate::{error::AgentError, llm::{GenerationParams, LLMClient}, cost_tracker::CostTracker};
+
+/// Condenses older history entries, and tool output too large for the history, so the context
+/// stays within its token budget.
+pub struct SummarizerAgent {
+    llm_client: Arc<dyn LLMClient>,
+    cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+}
+
+impl SummarizerAgent {
+    pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
+        Self { llm_client, cost_tracker, params: GenerationParams::default() }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub async fn summarize(&self, goal: &str, previous_summary: Option<&str>, entries: &[(String, String)]) -> Result<String, AgentError> {
+        let prompt = self.build_prompt(goal, previous_summary, entries);
+        info!("Summarizer prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = self.llm_client.generate(&prompt, &self.params).await?;
+        self.cost_tracker.record_call("summarizer", &response, started.elapsed());
+        info!("Summarizer response:\n{}", response.content);
+        Ok(response.content.trim().to_string())
+    }
+
+    /// Condenses one large tool output (a build log, a test run) for the history, keeping errors,
+    /// warnings and the lines the agent is likely to act on. `source` names what produced it.
+    pub async fn summarize_output(&self, source: &str, output: &str) -> Result<String, AgentError> {
+        let prompt = build_output_prompt(source, output);
+        info!("Summarizer prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = self.llm_client.generate(&prompt, &self.params).await?;
+        self.cost_tracker.record_call("summarizer", &response, started.elapsed());
+        info!("Summarizer response:\n{}", response.content);
+        Ok(response.content.trim().to_string())
+    }
+
+    fn build_prompt(&self, goal: &str, previous_summary: Option<&str>, entries: &[(String, String)]) -> String {
+        let previous_summary = previous_summary.unwrap_or("(none)");
+        let history = entries
+            .iter()
+            .map(|(entry_type, content)| format!("[{}]\n{}", entry_type, content))
+            .collect::<Vec<_>>()
+            .join("\n---\n");
+        format!(r#"
+You are summarizing the working memory of an AI coding agent so it fits in a limited context window.
+The agent's goal is: "{goal}"
+
+--- Existing Summary ---
+{previous_summary}
+--- End Existing Summary ---
+
+--- History To Summarize ---
+{history}
+--- End History ---
+
+Write one concise summary that merges the existing summary with the new history.
+Keep every fact the agent may need later: file paths created or changed, commands run and whether they
+succeeded, errors still unresolved, and decisions made. Drop verbose tool output and full code listings.
+
+Output ONLY the summary text.
+"#)
+    }
+}
+
+fn build_output_prompt(source: &str, output: &str) -> String {
+    format!(r#"
+You are condensing the output of a tool run by an AI coding agent: {source}
+The output is too long for the agent's context window, so it will only see your summary.
+
+--- Output ---
+{output}
+--- End Output ---
+
+Summarize the output in at most 40 lines. Copy every error and warning verbatim with its file, line and
+message, along with failing test names, the final status or result line, and any other line the agent
+would need to act on. Collapse repetitive progress lines (e.g. "Compiling ...") into one line with a count.
+
+Output ONLY the summary text.
+"#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{AIResponse, ModelInfo};
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    // Mock LLM client for testing
+    struct MockLLMClient {
+        response: String,
+        cost: f64,
+    }
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, _prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            Ok(AIResponse {
+                content: self.response.clone(),
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 0,
+                cost: self.cost,
+                model: "mock-model".to_string(),
+                provider: "mock-provider".to_string(),
+            })
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo {
+                name: "mock-model".to_string(),
+                input_cost_per_token: 0.0,
+                output_cost_per_token: 0.0,
+            }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarize() {
+        let mock_client = Arc::new(MockLLMClient {
+            response: "  Listed files and created main.rs.\n".to_string(),
+            cost: 0.002,
+        });
+        let cost_tracker = Arc::new(CostTracker::new());
+        let summarizer = SummarizerAgent::new(mock_client, cost_tracker.clone());
+
+        let entries = vec![("Tool Output".to_string(), "src/\nmain.rs".to_string())];
+        let summary = summarizer.summarize("Build a CLI", None, &entries).await.unwrap();
+
+        assert_eq!(summary, "Listed files and created main.rs.");
+        assert_eq!(cost_tracker.get_total_cost(), 0.002);
+    }
+
+    #[test]
+    fn test_build_prompt() {
+        let summarizer = SummarizerAgent::new(
+            Arc::new(MockLLMClient { response: String::new(), cost: 0.0 }),
+            Arc::new(CostTracker::new()),
+        );
+        let entries = vec![
+            ("Tool Output".to_string(), "cargo build ok".to_string()),
+            ("Tool Error".to_string(), "missing file".to_string()),
+        ];
+        let prompt = summarizer.build_prompt("Build a CLI", Some("Created Cargo.toml"), &entries);
+
+        assert!(prompt.contains("Build a CLI"));
+        assert!(prompt.contains("Created Cargo.toml"));
+        assert!(prompt.contains("[Tool Output]\ncargo build ok"));
+        assert!(prompt.contains("[Tool Error]\nmissing file"));
+    }
+
+    #[tokio::test]
+    async fn test_summarize_output() {
+        let cost_tracker = Arc::new(CostTracker::new());
+        let summarizer = SummarizerAgent::new(Arc::new(MockLLMClient { response: "error[E0425] in src/main.rs:3\n".to_string(), cost: 0.001 }), cost_tracker.clone());
+        let summary = summarizer.summarize_output("RunCommand cargo build", "Compiling a\nerror[E0425]").await.unwrap();
+        assert_eq!(summary, "error[E0425] in src/main.rs:3");
+
+        let prompt = build_output_prompt("RunCommand cargo build", "Compiling a\nerror[E0425]");
+        assert!(prompt.contains("RunCommand cargo build") && prompt.contains("Compiling a\nerror[E0425]"));
+    }
+}
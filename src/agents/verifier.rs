@@ -0,0 +1,172 @@
+use std::sync::Arc;
+use std::time::Instant;
+use anyhow::Result;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AgentError, llm::{GenerationParams, LLMClient}, cost_tracker::CostTracker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationStatus {
+    Complete,
+    Incomplete,
+}
+
+/// The verifier's verdict on whether the goal has been achieved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verification {
+    pub status: VerificationStatus,
+    /// What is still missing when the goal is incomplete.
+    #[serde(default)]
+    pub missing: Vec<String>,
+}
+
+impl Verification {
+    pub fn is_complete(&self) -> bool {
+        self.status == VerificationStatus::Complete
+    }
+}
+
+pub struct VerifierAgent {
+    llm_client: Arc<dyn LLMClient>,
+    cost_tracker: Arc<CostTracker>,
+    params: GenerationParams,
+}
+
+impl VerifierAgent {
+    pub fn new(llm_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
+        Self { llm_client, cost_tracker, params: GenerationParams::default() }
+    }
+
+    /// Sampling settings for this agent's requests; the provider's defaults when unset.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Judges whether `goal` is done from the run's context and the workspace changes.
+    pub async fn verify(&self, goal: &str, context: &str, changes: &str) -> Result<Verification, AgentError> {
+        let prompt = self.build_prompt(goal, context, changes);
+        info!("Verifier prompt:\n{}", prompt);
+        let started = Instant::now();
+        let response = self.llm_client.generate_json(&prompt, &self.params).await?;
+        self.cost_tracker.record_call("verifier", &response, started.elapsed());
+        info!("Verifier response:\n{}", response.content);
+        self.parse_verification(&response.content)
+    }
+
+    fn build_prompt(&self, goal: &str, context: &str, changes: &str) -> String {
+        format!(r#"
+You are a strict QA engineer verifying whether an AI agent actually achieved its goal.
+The goal was: "{goal}"
+
+--- Run History ---
+{context}
+--- End Run History ---
+
+--- Workspace Changes ---
+{changes}
+--- End Workspace Changes ---
+
+Check the evidence: were the required files created or changed, and did the relevant builds or tests pass?
+Do not assume work was done unless the history or the changes show it.
+
+You MUST respond with a single JSON object matching this structure:
+{{
+  "status": "complete" or "incomplete",
+  "missing": ["One specific piece of remaining work per entry. Empty when complete."]
+}}
+"#)
+    }
+
+    fn parse_verification(&self, response: &str) -> Result<Verification, AgentError> {
+        serde_json::from_str(response.trim())
+            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse verification: {}. Response: {}", e, response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{AIResponse, ModelInfo};
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    // Mock LLM client for testing
+    struct MockLLMClient {
+        response: String,
+        cost: f64,
+    }
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, _prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            Ok(AIResponse {
+                content: self.response.clone(),
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 0,
+                cost: self.cost,
+                model: "mock-model".to_string(),
+                provider: "mock-provider".to_string(),
+            })
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo {
+                name: "mock-model".to_string(),
+                input_cost_per_token: 0.0,
+                output_cost_per_token: 0.0,
+            }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    fn verifier_with_response(response: &str) -> (VerifierAgent, Arc<CostTracker>) {
+        let mock_client = Arc::new(MockLLMClient {
+            response: response.to_string(),
+            cost: 0.001,
+        });
+        let cost_tracker = Arc::new(CostTracker::new());
+        (VerifierAgent::new(mock_client, cost_tracker.clone()), cost_tracker)
+    }
+
+    #[tokio::test]
+    async fn test_verify_complete() {
+        let (verifier, cost_tracker) = verifier_with_response(r#"{"status": "complete"}"#);
+        let verification = verifier.verify("Add a README", "ctx", "A README.md").await.unwrap();
+
+        assert!(verification.is_complete());
+        assert!(verification.missing.is_empty());
+        assert_eq!(cost_tracker.get_total_cost(), 0.001);
+    }
+
+    #[tokio::test]
+    async fn test_verify_incomplete() {
+        let (verifier, _) = verifier_with_response(r#"{"status": "incomplete", "missing": ["Tests were never run"]}"#);
+        let verification = verifier.verify("Add tests", "ctx", "").await.unwrap();
+
+        assert_eq!(verification.status, VerificationStatus::Incomplete);
+        assert_eq!(verification.missing, vec!["Tests were never run".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_verify_invalid_response() {
+        let (verifier, _) = verifier_with_response("All done!");
+        let result = verifier.verify("Goal", "ctx", "").await;
+        assert!(matches!(result, Err(AgentError::ResponseParseError(_))));
+    }
+
+    #[test]
+    fn test_build_prompt() {
+        let (verifier, _) = verifier_with_response("");
+        let prompt = verifier.build_prompt("Fix the bug", "[Tool Output]\ntests passed", " M src/lib.rs");
+
+        assert!(prompt.contains("Fix the bug"));
+        assert!(prompt.contains("tests passed"));
+        assert!(prompt.contains(" M src/lib.rs"));
+        assert!(prompt.contains("\"incomplete\""));
+    }
+}
@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+use colored::*;
+use std::io::Write;
+
+use crate::cost_tracker::CostEstimate;
+
+/// Decides whether a file change may be made. The orchestrator asks it before every `WriteFile`
+/// and `EditFile` once the change's diff has been reported, so the user approves the diff itself.
+#[async_trait]
+pub trait Approver: Send + Sync {
+    /// Returns whether `diff`, the pending change to `path`, may be applied.
+    async fn approve(&self, path: &str, diff: &str) -> bool;
+}
+
+#[async_trait]
+impl<F> Approver for F
+where
+    F: Fn(&str, &str) -> bool + Send + Sync,
+{
+    async fn approve(&self, path: &str, diff: &str) -> bool {
+        self(path, diff)
+    }
+}
+
+/// Asks on the terminal; anything but `y` or `yes` rejects the change. Relies on the diff having
+/// been printed already, as `ConsoleReporter` does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleApprover;
+
+#[async_trait]
+impl Approver for ConsoleApprover {
+    async fn approve(&self, path: &str, _diff: &str) -> bool {
+        print!("   {} ", format!("Apply this change to {}? [y/N]", path).bold().yellow());
+        let _ = std::io::stdout().flush();
+        let answer = tokio::task::spawn_blocking(|| {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).map(|_| line)
+        })
+        .await;
+        matches!(answer, Ok(Ok(line)) if matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+}
+
+/// Lets the user steer a run between steps. The orchestrator asks it after every step that changed
+/// files; what it returns is added to the history as "User Feedback" before the next decision.
+#[async_trait]
+pub trait Steering: Send + Sync {
+    /// Returns feedback on `step`, which changed `files`, or `None` to carry on unchanged.
+    async fn feedback(&self, step: &str, files: &[String]) -> Option<String>;
+}
+
+#[async_trait]
+impl<F> Steering for F
+where
+    F: Fn(&str, &[String]) -> Option<String> + Send + Sync,
+{
+    async fn feedback(&self, step: &str, files: &[String]) -> Option<String> {
+        self(step, files)
+    }
+}
+
+/// Reads one line of feedback from the terminal; an empty line carries on.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleSteering;
+
+#[async_trait]
+impl Steering for ConsoleSteering {
+    async fn feedback(&self, _step: &str, files: &[String]) -> Option<String> {
+        println!("   {} {}", "✏️  Changed:".bold().yellow(), files.join(", "));
+        print!("   {} ", "Feedback for the next step (Enter to continue):".bold().yellow());
+        let _ = std::io::stdout().flush();
+        let answer = tokio::task::spawn_blocking(|| {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).map(|_| line)
+        })
+        .await;
+        match answer {
+            Ok(Ok(line)) if !line.trim().is_empty() => Some(line.trim().to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Decides whether a plan expected to cost more than the threshold may run. The orchestrator asks
+/// it once the plan is made, before the first step; a rejection fails the run with
+/// `AgentError::CostNotApproved`.
+#[async_trait]
+pub trait CostApprover: Send + Sync {
+    /// Returns whether a run estimated at `estimate`, over `threshold` dollars, may go ahead.
+    async fn approve_cost(&self, estimate: &CostEstimate, threshold: f64) -> bool;
+}
+
+#[async_trait]
+impl<F> CostApprover for F
+where
+    F: Fn(&CostEstimate, f64) -> bool + Send + Sync,
+{
+    async fn approve_cost(&self, estimate: &CostEstimate, threshold: f64) -> bool {
+        self(estimate, threshold)
+    }
+}
+
+/// Asks on the terminal; anything but `y` or `yes` stops the run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleCostApprover;
+
+#[async_trait]
+impl CostApprover for ConsoleCostApprover {
+    async fn approve_cost(&self, estimate: &CostEstimate, threshold: f64) -> bool {
+        print!("   {} ", format!("The run may cost ${:.2}, more than ${:.2}. Run the plan? [y/N]", estimate.cost, threshold).bold().yellow());
+        let _ = std::io::stdout().flush();
+        let answer = tokio::task::spawn_blocking(|| {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).map(|_| line)
+        })
+        .await;
+        matches!(answer, Ok(Ok(line)) if matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+}
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use walkdir::WalkDir;
+
+use crate::error::AgentError;
+
+/// The file describing a benchmark task, inside the task's directory.
+pub const TASK_FILE: &str = "task.toml";
+
+/// The directory copied into the workspace when `task.toml` does not name one.
+pub const DEFAULT_FIXTURE_DIR: &str = "fixture";
+
+/// How long the agent may work on a task unless `task.toml` sets `timeout_secs`.
+pub const DEFAULT_TASK_TIMEOUT_SECS: u64 = 900;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaskFile {
+    goal: String,
+    check: String,
+    fixture: Option<String>,
+    timeout_secs: Option<u64>,
+}
+
+/// One benchmark task: a goal, the files it starts from, and the command that decides whether the
+/// agent achieved it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchTask {
+    /// The name of the task's directory.
+    pub name: String,
+    pub goal: String,
+    /// Run in the workspace after the agent finishes; the task passes if it exits successfully.
+    pub check: String,
+    /// Copied into a fresh workspace before every run; the workspace starts empty without one.
+    pub fixture: Option<PathBuf>,
+    pub timeout: Duration,
+}
+
+impl BenchTask {
+    /// Fills `workspace` with a copy of the task's fixture.
+    pub fn prepare(&self, workspace: &Path) -> Result<(), AgentError> {
+        std::fs::create_dir_all(workspace)?;
+        if let Some(fixture) = &self.fixture {
+            copy_dir(fixture, workspace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads every task in `dir`: each subdirectory with a `task.toml`, in name order.
+///
+/// ```toml
+/// goal = "Make the failing test in tests/parse.rs pass"
+/// check = "cargo test"
+/// # Optional: the directory to start from (default "fixture") and the agent's time limit
+/// fixture = "repo"
+/// timeout_secs = 600
+/// ```
+pub fn load_tasks(dir: &Path) -> Result<Vec<BenchTask>, AgentError> {
+    let mut tasks = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let task_dir = entry?.path();
+        let file = task_dir.join(TASK_FILE);
+        if task_dir.is_dir() && file.is_file() {
+            tasks.push(load_task(&task_dir, &file)?);
+        }
+    }
+    if tasks.is_empty() {
+        return Err(AgentError::ConfigError(format!("No benchmark tasks in {}: expected subdirectories with a {}", dir.display(), TASK_FILE)));
+    }
+    tasks.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(tasks)
+}
+
+fn load_task(task_dir: &Path, file: &Path) -> Result<BenchTask, AgentError> {
+    let definition: TaskFile = toml::from_str(&std::fs::read_to_string(file)?)
+        .map_err(|e| AgentError::ConfigError(format!("Invalid benchmark task {}: {}", file.display(), e)))?;
+    let fixture = match &definition.fixture {
+        Some(fixture) => {
+            let path = task_dir.join(fixture);
+            if !path.is_dir() {
+                return Err(AgentError::ConfigError(format!("The fixture {} of {} is not a directory", path.display(), file.display())));
+            }
+            Some(path)
+        }
+        None => Some(task_dir.join(DEFAULT_FIXTURE_DIR)).filter(|path| path.is_dir()),
+    };
+    Ok(BenchTask {
+        name: task_dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+        goal: definition.goal,
+        check: definition.check,
+        fixture,
+        timeout: Duration::from_secs(definition.timeout_secs.unwrap_or(DEFAULT_TASK_TIMEOUT_SECS)),
+    })
+}
+
+/// Copies the files and directories under `from` into `to`, including hidden ones such as `.git`.
+pub fn copy_dir(from: &Path, to: &Path) -> Result<(), AgentError> {
+    for entry in WalkDir::new(from).min_depth(1) {
+        let entry = entry.map_err(|e| AgentError::IoError(e.into()))?;
+        let target = to.join(entry.path().strip_prefix(from).unwrap_or(entry.path()));
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// The outcome of one task run with one model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchResult {
+    pub task: String,
+    pub model: String,
+    /// Whether the check command passed; this is what the success rate counts.
+    pub passed: bool,
+    /// Whether the agent itself reported success.
+    pub agent_succeeded: bool,
+    pub steps: usize,
+    pub cost: f64,
+    pub duration_ms: u64,
+    /// Why the run could not finish normally, e.g. a timeout or a provider error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How one model did across the suite.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelSummary {
+    pub model: String,
+    pub tasks: usize,
+    pub passed: usize,
+    pub success_rate: f64,
+    pub avg_steps: f64,
+    pub total_cost: f64,
+    pub avg_duration_ms: u64,
+}
+
+/// Every result of a benchmark run, summarized per model for comparison.
+#[derive(Debug, Clone, Default)]
+pub struct BenchReport {
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchReport {
+    /// One summary per model, in the order the models were run.
+    pub fn summaries(&self) -> Vec<ModelSummary> {
+        let mut models: Vec<&str> = Vec::new();
+        for result in &self.results {
+            if !models.contains(&result.model.as_str()) {
+                models.push(&result.model);
+            }
+        }
+        models
+            .into_iter()
+            .map(|model| {
+                let results: Vec<&BenchResult> = self.results.iter().filter(|result| result.model == model).collect();
+                let tasks = results.len();
+                let passed = results.iter().filter(|result| result.passed).count();
+                ModelSummary {
+                    model: model.to_string(),
+                    tasks,
+                    passed,
+                    success_rate: passed as f64 / tasks as f64,
+                    avg_steps: results.iter().map(|result| result.steps).sum::<usize>() as f64 / tasks as f64,
+                    total_cost: results.iter().map(|result| result.cost).sum(),
+                    avg_duration_ms: results.iter().map(|result| result.duration_ms).sum::<u64>() / tasks as u64,
+                }
+            })
+            .collect()
+    }
+
+    /// A table comparing the models, followed by each task's outcome per model.
+    pub fn render(&self) -> String {
+        let summaries = self.summaries();
+        let width = summaries.iter().map(|summary| summary.model.len()).max().unwrap_or(0).max("MODEL".len());
+        let mut text = format!("{:<width$}  {:>7}  {:>8}  {:>9}  {:>9}  {:>9}\n", "MODEL", "PASSED", "SUCCESS", "AVG STEPS", "COST", "AVG TIME");
+        for summary in &summaries {
+            text.push_str(&format!(
+                "{:<width$}  {:>7}  {:>7.0}%  {:>9.1}  {:>9}  {:>8.1}s\n",
+                summary.model,
+                format!("{}/{}", summary.passed, summary.tasks),
+                summary.success_rate * 100.0,
+                summary.avg_steps,
+                format!("${:.4}", summary.total_cost),
+                summary.avg_duration_ms as f64 / 1000.0,
+            ));
+        }
+        text.push('\n');
+        let task_width = self.results.iter().map(|result| result.task.len()).max().unwrap_or(0);
+        for result in &self.results {
+            let outcome = if result.passed { "pass" } else { "FAIL" };
+            text.push_str(&format!("{:<4}  {:<task_width$}  [{}]", outcome, result.task, result.model));
+            if let Some(error) = &result.error {
+                text.push_str(&format!(": {}", error));
+            }
+            text.push('\n');
+        }
+        text.trim_end().to_string()
+    }
+
+    /// The results and summaries as JSON, for comparing runs over time.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({ "summaries": self.summaries(), "results": self.results })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(task: &str, model: &str, passed: bool, steps: usize, cost: f64) -> BenchResult {
+        BenchResult { task: task.to_string(), model: model.to_string(), passed, agent_succeeded: passed, steps, cost, duration_ms: 2000, error: None }
+    }
+
+    #[test]
+    fn test_load_tasks_reads_definitions_and_fixtures() {
+        let dir = tempfile::tempdir().unwrap();
+        let fix = dir.path().join("b-fix-test");
+        std::fs::create_dir_all(fix.join("fixture/src")).unwrap();
+        std::fs::write(fix.join("fixture/src/lib.rs"), "fn broken(").unwrap();
+        std::fs::write(fix.join(TASK_FILE), "goal = \"Fix the build\"\ncheck = \"cargo build\"\ntimeout_secs = 60\n").unwrap();
+        let new = dir.path().join("a-new-project");
+        std::fs::create_dir_all(&new).unwrap();
+        std::fs::write(new.join(TASK_FILE), "goal = \"Create hello.py\"\ncheck = \"python hello.py\"\n").unwrap();
+        std::fs::create_dir_all(dir.path().join("notes")).unwrap();
+
+        let tasks = load_tasks(dir.path()).unwrap();
+        assert_eq!(tasks.iter().map(|task| task.name.as_str()).collect::<Vec<_>>(), ["a-new-project", "b-fix-test"]);
+        assert_eq!(tasks[0].fixture, None);
+        assert_eq!(tasks[0].timeout, Duration::from_secs(DEFAULT_TASK_TIMEOUT_SECS));
+        assert_eq!(tasks[1].timeout, Duration::from_secs(60));
+
+        let workspace = dir.path().join("workspace");
+        tasks[1].prepare(&workspace).unwrap();
+        assert_eq!(std::fs::read_to_string(workspace.join("src/lib.rs")).unwrap(), "fn broken(");
+
+        std::fs::write(new.join(TASK_FILE), "goal = \"x\"\n").unwrap();
+        assert!(matches!(load_tasks(dir.path()), Err(AgentError::ConfigError(_))));
+        assert!(load_tasks(&dir.path().join("notes")).is_err());
+    }
+
+    #[test]
+    fn test_report_summarizes_per_model() {
+        let report = BenchReport {
+            results: vec![
+                result("fix-test", "openai", true, 4, 0.02),
+                result("new-project", "openai", false, 8, 0.04),
+                result("fix-test", "ollama:llama3", true, 6, 0.0),
+                result("new-project", "ollama:llama3", true, 2, 0.0),
+            ],
+        };
+        let summaries = report.summaries();
+        assert_eq!(summaries.iter().map(|summary| summary.model.as_str()).collect::<Vec<_>>(), ["openai", "ollama:llama3"]);
+        assert_eq!((summaries[0].passed, summaries[0].success_rate, summaries[0].avg_steps), (1, 0.5, 6.0));
+        assert!((summaries[0].total_cost - 0.06).abs() < 1e-9);
+        assert_eq!(summaries[1].success_rate, 1.0);
+
+        let table = report.render();
+        assert!(table.contains("openai             1/2       50%"), "{}", table);
+        assert!(table.contains("FAIL  new-project  [openai]"), "{}", table);
+        assert_eq!(report.to_json()["results"].as_array().unwrap().len(), 4);
+    }
+}
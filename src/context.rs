@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+
+use crate::llm::LLMProvider;
+use crate::state::AppState;
+use crate::tokens::{self, approximate_tokens};
+
+/// Default token budget for the context handed to agents.
+pub const DEFAULT_CONTEXT_TOKENS: usize = 8000;
+/// The most recent history entries are always kept (truncated if needed) and never summarized.
+pub const KEEP_RECENT_ENTRIES: usize = 3;
+
+/// Cuts `text` down to roughly `max_tokens`, on a char boundary, with a trailing ellipsis.
+pub fn truncate_to_tokens(text: &str, max_tokens: usize) -> String {
+    truncate_with(text, max_tokens, approximate_tokens)
+}
+
+fn truncate_with(text: &str, max_tokens: usize, count: impl Fn(&str) -> usize) -> String {
+    if count(text) <= max_tokens {
+        return text.to_string();
+    }
+    // Binary search for the longest char prefix that fits.
+    let chars: Vec<char> = text.chars().collect();
+    let (mut low, mut high) = (0, chars.len());
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if count(&chars[..mid].iter().collect::<String>()) <= max_tokens {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    format!("{}...", chars[..low].iter().collect::<String>())
+}
+
+/// Builds the agent context from `AppState` within a token budget.
+///
+/// The goal, any project instructions, pinned files, earlier goals of the session and any summary of compacted history always come first. The most recent entries are
+/// kept, then the remaining budget goes to older entries that share keywords with the current
+/// plan step, newest first. Selected entries are rendered in chronological order.
+#[derive(Debug, Clone, Copy)]
+pub struct ContextBuilder {
+    budget: usize,
+    provider: Option<LLMProvider>,
+}
+
+impl Default for ContextBuilder {
+    fn default() -> Self {
+        Self::new(DEFAULT_CONTEXT_TOKENS)
+    }
+}
+
+impl ContextBuilder {
+    pub fn new(budget: usize) -> Self {
+        Self { budget, provider: None }
+    }
+
+    /// Counts tokens the way `provider`'s models do (see `tokens::estimate_tokens`) instead of
+    /// with the provider-neutral approximation.
+    pub fn with_provider(mut self, provider: LLMProvider) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// The same builder with a different token budget.
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Whether the untruncated history no longer fits and older entries should be summarized.
+    pub fn needs_compaction(&self, state: &AppState) -> bool {
+        state.history.len() > KEEP_RECENT_ENTRIES && self.count(&render_full(state)) > self.budget
+    }
+
+    /// Tokens in `text`, counted like the context is.
+    pub fn count(&self, text: &str) -> usize {
+        match self.provider {
+            Some(provider) => tokens::estimate_tokens(provider, text),
+            None => approximate_tokens(text),
+        }
+    }
+
+    fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        truncate_with(text, max_tokens, |text| self.count(text))
+    }
+
+    pub fn build(&self, state: &AppState) -> String {
+        self.render(state, None)
+    }
+
+    /// Like `build`, but older history is represented by `recalled` memory chunks (most relevant
+    /// first) instead of being picked by keyword; only the most recent entries are kept verbatim.
+    pub fn build_with_recall(&self, state: &AppState, recalled: &[String]) -> String {
+        self.render(state, Some(recalled))
+    }
+
+    fn render(&self, state: &AppState, recalled: Option<&[String]>) -> String {
+        let mut context = format!("The overall goal is: {}\n", state.goal);
+        if let Some(instructions) = &state.instructions {
+            // Instructions are always included, but may not crowd out the history entirely.
+            context.push_str(&format!("\n--- Project Instructions ({}) ---\n", instructions.source));
+            context.push_str(&self.truncate(&instructions.content, (self.budget / 4).max(1)));
+            context.push_str("\nFollow these project instructions in every step.\n--- End Project Instructions ---\n");
+        }
+        if !state.pinned_files.is_empty() {
+            // Pinned files share half the budget, so the recent history still fits.
+            let mut remaining = (self.budget / 2).max(1);
+            context.push_str("\n--- Pinned Files ---\n");
+            for file in &state.pinned_files {
+                let content = match &file.content {
+                    Some(content) => self.truncate(content, remaining),
+                    None => "(could not be read)".to_string(),
+                };
+                remaining = remaining.saturating_sub(self.count(&content));
+                context.push_str(&format!("[{}]\n{}\n---\n", file.path, content));
+            }
+            context.push_str("These are the current contents of files the user pinned as important to the goal.\n--- End Pinned Files ---\n");
+        }
+        if let Some(conversation) = &state.conversation {
+            context.push_str("\n--- Earlier Goals in This Session ---\n");
+            context.push_str(&self.truncate(conversation, (self.budget / 4).max(1)));
+            context.push_str("\nThe current goal may refer to these, e.g. \"that\" or \"the new module\".\n--- End Earlier Goals ---\n");
+        }
+        if let Some(summary) = &state.summary {
+            context.push_str("\n--- Summary of Earlier History ---\n");
+            context.push_str(summary);
+            context.push('\n');
+        }
+        if let Some(recalled) = recalled.filter(|chunks| !chunks.is_empty()) {
+            // Recalled memory gets at most a quarter of the budget, leaving room for recent history.
+            let mut remaining = (self.budget / 4).max(1);
+            context.push_str("\n--- Relevant Memory ---\n");
+            for chunk in recalled {
+                let chunk = self.truncate(chunk, remaining);
+                remaining = remaining.saturating_sub(self.count(&chunk));
+                context.push_str(&chunk);
+                context.push_str("\n---\n");
+                if remaining == 0 {
+                    break;
+                }
+            }
+        }
+        context.push_str("\n--- History & Context ---\n");
+        if state.history.is_empty() {
+            context.push_str("No actions have been taken yet.\n");
+            return context;
+        }
+
+        // Reserve room for the "entries omitted" note up front.
+        let note_tokens = self.count(&omitted_note(state.history.len()));
+        let mut remaining = self.budget.saturating_sub(self.count(&context) + note_tokens);
+        // No single entry may take more than a quarter of the budget.
+        let entry_cap = (self.budget / 4).max(1);
+        let mut selected: Vec<(usize, String)> = Vec::new();
+        let mut take = |index: usize, remaining: &mut usize| {
+            let (entry_type, content) = &state.history[index];
+            // Framing plus the ellipsis a truncated entry gains.
+            let overhead = self.count(&format!("[{}]\n\n---\n", entry_type)) + 3;
+            let cap = entry_cap.min(remaining.saturating_sub(overhead));
+            if cap == 0 {
+                return false;
+            }
+            let content = self.truncate(content, cap);
+            let rendered = format!("[{}]\n{}\n---\n", entry_type, content);
+            *remaining = remaining.saturating_sub(self.count(&rendered));
+            selected.push((index, rendered));
+            true
+        };
+
+        let recent_start = state.history.len().saturating_sub(KEEP_RECENT_ENTRIES);
+        for index in (recent_start..state.history.len()).rev() {
+            take(index, &mut remaining);
+        }
+
+        let keywords = state.plan.get(state.current_step).map(|step| keywords(step)).unwrap_or_default();
+        // With recalled memory, older entries are already represented by the relevant chunks; an
+        // empty recall represents nothing, so they are kept then.
+        let older_end = if recalled.is_some_and(|chunks| !chunks.is_empty()) { 0 } else { recent_start };
+        let mut older: Vec<usize> = (0..older_end).collect();
+        // Stable sort keeps newest-first order among entries with equal relevance.
+        older.reverse();
+        older.sort_by_key(|&index| std::cmp::Reverse(relevance(&state.history[index].1, &keywords)));
+        for index in older {
+            if !take(index, &mut remaining) {
+                break;
+            }
+        }
+
+        let omitted = state.history.len() - selected.len();
+        if omitted > 0 {
+            context.push_str(&omitted_note(omitted));
+        }
+        selected.sort_by_key(|(index, _)| *index);
+        for (_, rendered) in selected {
+            context.push_str(&rendered);
+        }
+        context
+    }
+}
+
+fn omitted_note(count: usize) -> String {
+    format!("({} earlier entries omitted to fit the context budget)\n---\n", count)
+}
+
+fn render_full(state: &AppState) -> String {
+    let mut text = state.summary.clone().unwrap_or_default();
+    for (entry_type, content) in &state.history {
+        text.push_str(&format!("[{}]\n{}\n---\n", entry_type, content));
+    }
+    text
+}
+
+fn keywords(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| word.len() >= 3)
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn relevance(content: &str, keywords: &HashSet<String>) -> usize {
+    if keywords.is_empty() {
+        return 0;
+    }
+    let content = content.to_lowercase();
+    keywords.iter().filter(|k| content.contains(k.as_str())).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::ProjectInstructions;
+
+    #[test]
+    fn test_truncate_to_tokens_is_char_safe() {
+        let text = "é".repeat(100);
+        let truncated = truncate_to_tokens(&text, 5);
+        assert!(truncated.ends_with("..."));
+        assert!(approximate_tokens(truncated.trim_end_matches("...")) <= 5);
+        assert_eq!(truncate_to_tokens("short", 5), "short");
+    }
+
+    #[test]
+    fn test_build_keeps_recent_entries_within_budget() {
+        let mut state = AppState::new("Goal".to_string());
+        for i in 0..50 {
+            state.add_history(&format!("Entry{}", i), &"word ".repeat(40));
+        }
+        let builder = ContextBuilder::new(500);
+        let context = builder.build(&state);
+
+        assert!(approximate_tokens(&context) <= 500);
+        assert!(context.contains("[Entry49]"));
+        assert!(context.contains("[Entry47]"));
+        assert!(!context.contains("[Entry0]"));
+        assert!(context.contains("earlier entries omitted"));
+    }
+
+    #[test]
+    fn test_build_counts_with_the_providers_tokenizer() {
+        let mut state = AppState::new("Goal".to_string());
+        for i in 0..50 {
+            state.add_history(&format!("Entry{}", i), &"word ".repeat(40));
+        }
+        let context = ContextBuilder::new(500).with_provider(LLMProvider::OpenAI).build(&state);
+        assert!(tokens::estimate_tokens(LLMProvider::OpenAI, &context) <= 500);
+        // "word " is one tiktoken token but two approximate ones, so more entries fit.
+        assert!(context.len() > ContextBuilder::new(500).build(&state).len());
+    }
+
+    #[test]
+    fn test_build_prefers_relevant_older_entries() {
+        let mut state = AppState::new("Goal".to_string());
+        state.add_history("Tool Output", "Cargo.toml lists the serde dependency");
+        for i in 0..10 {
+            state.add_history(&format!("Noise{}", i), &"unrelated ".repeat(20));
+        }
+        state.plan = vec!["Add a serde derive".to_string()];
+        state.current_step = 0;
+
+        let context = ContextBuilder::new(300).build(&state);
+        assert!(context.contains("serde dependency"));
+        assert!(!context.contains("[Noise0]"));
+    }
+
+    #[test]
+    fn test_build_includes_summary() {
+        let mut state = AppState::new("Goal".to_string());
+        state.summary = Some("Created src/lib.rs".to_string());
+        let context = ContextBuilder::default().build(&state);
+        assert!(context.contains("--- Summary of Earlier History ---"));
+        assert!(context.contains("Created src/lib.rs"));
+    }
+
+    #[test]
+    fn test_build_includes_project_instructions() {
+        let mut state = AppState::new("Goal".to_string());
+        state.instructions = Some(ProjectInstructions { source: "AGENTS.md".to_string(), content: "Use pnpm, not npm.".to_string() });
+        let context = ContextBuilder::default().build(&state);
+        assert!(context.contains("--- Project Instructions (AGENTS.md) ---"));
+        assert!(context.contains("Use pnpm, not npm."));
+
+        state.instructions.as_mut().unwrap().content = "rule ".repeat(1000);
+        let context = ContextBuilder::new(200).build(&state);
+        assert!(approximate_tokens(&context) <= 200);
+    }
+
+    #[test]
+    fn test_build_includes_pinned_files() {
+        let mut state = AppState::new("Goal".to_string());
+        state.pin("src/lib.rs");
+        state.pin("docs/ARCHITECTURE.md");
+        state.pin("src/lib.rs");
+        state.pinned_files[0].content = Some("pub mod users;".to_string());
+        let context = ContextBuilder::default().build(&state);
+        assert!(context.contains("--- Pinned Files ---\n[src/lib.rs]\npub mod users;\n---\n[docs/ARCHITECTURE.md]\n(could not be read)\n"), "{}", context);
+
+        state.pinned_files[0].content = Some("pub mod users;\n".repeat(1000));
+        let context = ContextBuilder::new(400).build(&state);
+        assert!(approximate_tokens(&context) <= 400);
+        assert!(context.contains("[docs/ARCHITECTURE.md]"));
+    }
+
+    #[test]
+    fn test_build_includes_earlier_goals() {
+        let mut state = AppState::new("Now add tests for that".to_string());
+        state.conversation = Some("1. Add a slugify helper (completed)".to_string());
+        let context = ContextBuilder::default().build(&state);
+        assert!(context.contains("--- Earlier Goals in This Session ---"));
+        assert!(context.contains("Add a slugify helper"));
+    }
+
+    #[test]
+    fn test_build_with_recall_replaces_older_history() {
+        let mut state = AppState::new("Goal".to_string());
+        state.add_history("Old", "the config parser lives in src/config.rs");
+        for i in 0..KEEP_RECENT_ENTRIES {
+            state.add_history(&format!("Recent{}", i), "recent output");
+        }
+        let context = ContextBuilder::default().build_with_recall(&state, &["fn parse_config() {}".to_string()]);
+        assert!(context.contains("--- Relevant Memory ---\nfn parse_config() {}"));
+        assert!(context.contains("[Recent0]"));
+        assert!(!context.contains("[Old]"));
+        assert!(context.contains("(1 earlier entries omitted"));
+
+        // Recall that finds nothing keeps the older history.
+        let context = ContextBuilder::default().build_with_recall(&state, &[]);
+        assert!(!context.contains("--- Relevant Memory ---"));
+        assert!(context.contains("[Old]\nthe config parser lives in src/config.rs"));
+        assert!(!context.contains("earlier entries omitted"));
+    }
+
+    #[test]
+    fn test_needs_compaction() {
+        let mut state = AppState::new("Goal".to_string());
+        for _ in 0..KEEP_RECENT_ENTRIES {
+            state.add_history("Big", &"x ".repeat(1000));
+        }
+        let builder = ContextBuilder::new(100);
+        // Only recent entries: nothing to summarize yet.
+        assert!(!builder.needs_compaction(&state));
+        state.add_history("Big", &"x ".repeat(1000));
+        assert!(builder.needs_compaction(&state));
+        assert!(!ContextBuilder::new(100_000).needs_compaction(&state));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::state::{goal_headline, AppState};
+
+/// How many earlier goals of an interactive session are remembered; older ones are dropped.
+pub const MAX_REMEMBERED_GOALS: usize = 5;
+
+/// What one finished goal did, as remembered for the goals after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoalSummary {
+    pub goal: String,
+    pub succeeded: bool,
+    pub plan: Vec<String>,
+    pub files_modified: Vec<String>,
+}
+
+/// Memory shared by the goals of one interactive session, so a follow-up goal such as "now add
+/// tests for that" knows what "that" is.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationMemory {
+    goals: Vec<GoalSummary>,
+}
+
+impl ConversationMemory {
+    /// Remembers the goal `state` worked on, forgetting the oldest goal past `MAX_REMEMBERED_GOALS`.
+    pub fn record(&mut self, state: &AppState, succeeded: bool) {
+        self.goals.push(GoalSummary {
+            goal: state.goal.clone(),
+            succeeded,
+            plan: state.plan[..state.current_step.min(state.plan.len())].to_vec(),
+            files_modified: state.files_modified.clone(),
+        });
+        if self.goals.len() > MAX_REMEMBERED_GOALS {
+            self.goals.remove(0);
+        }
+    }
+
+    pub fn goals(&self) -> &[GoalSummary] {
+        &self.goals
+    }
+
+    /// The remembered goals as context for the next one, oldest first; `None` before the first goal.
+    pub fn render(&self) -> Option<String> {
+        if self.goals.is_empty() {
+            return None;
+        }
+        let mut text = String::new();
+        for (n, summary) in self.goals.iter().enumerate() {
+            let outcome = if summary.succeeded { "completed" } else { "failed" };
+            text.push_str(&format!("{}. {} ({})\n", n + 1, goal_headline(&summary.goal), outcome));
+            if !summary.plan.is_empty() {
+                text.push_str(&format!("   Steps done: {}\n", summary.plan.join("; ")));
+            }
+            if !summary.files_modified.is_empty() {
+                text.push_str(&format!("   Files changed: {}\n", summary.files_modified.join(", ")));
+            }
+        }
+        Some(text.trim_end().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(goal: &str, files: &[&str]) -> AppState {
+        let mut state = AppState::new(goal.to_string());
+        state.plan = vec!["Write the code".to_string(), "Run it".to_string()];
+        state.current_step = 1;
+        state.files_modified = files.iter().map(|f| f.to_string()).collect();
+        state
+    }
+
+    #[test]
+    fn test_render_lists_goals_with_steps_and_files() {
+        let mut memory = ConversationMemory::default();
+        assert_eq!(memory.render(), None);
+
+        memory.record(&finished("Add a slugify helper", &["src/slug.rs"]), true);
+        memory.record(&finished("Use it in the router", &[]), false);
+        assert_eq!(
+            memory.render().unwrap(),
+            "1. Add a slugify helper (completed)\n   Steps done: Write the code\n   Files changed: src/slug.rs\n2. Use it in the router (failed)\n   Steps done: Write the code"
+        );
+    }
+
+    #[test]
+    fn test_record_forgets_oldest_goals() {
+        let mut memory = ConversationMemory::default();
+        for i in 0..MAX_REMEMBERED_GOALS + 2 {
+            memory.record(&finished(&format!("Goal {}", i), &[]), true);
+        }
+        assert_eq!(memory.goals().len(), MAX_REMEMBERED_GOALS);
+        assert_eq!(memory.goals()[0].goal, "Goal 2");
+    }
+}
@@ -1,23 +1,173 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::llm::AIResponse;
+
+/// Completion tokens a cost estimate assumes for a tool decision (or verification) and for a
+/// code generation.
+pub const ESTIMATED_DECISION_TOKENS: u32 = 300;
+pub const ESTIMATED_CODE_TOKENS: u32 = 1500;
+/// Estimated cost in dollars above which a plan only runs once it is confirmed.
+pub const DEFAULT_COST_CONFIRM_THRESHOLD: f64 = 1.0;
+
+/// What running a plan is expected to cost at most: every step is priced as a tool decision (by
+/// each model of a decision ensemble), a plan check and relevant-file pick, a code generation with
+/// a syntax fix and the review passes with their revisions, each sent a prompt of `prompt_tokens`,
+/// and the goal verification as one more decision.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostEstimate {
+    pub steps: usize,
+    pub prompt_tokens: u32,
+    pub cost: f64,
+}
+
+impl fmt::Display for CostEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "~${:.2} for {} step(s), at ~{} prompt tokens per call", self.cost, self.steps, self.prompt_tokens)
+    }
+}
+
+/// Aggregated LLM usage of one agent (planner, coder, reviewer, ...).
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentUsage {
+    pub calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// The part of `output_tokens` that reasoning models spent thinking.
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+    pub cost: f64,
+    pub total_latency_ms: u64,
+}
+
+impl AgentUsage {
+    pub fn average_latency_ms(&self) -> u64 {
+        self.total_latency_ms.checked_div(self.calls).unwrap_or(0)
+    }
+
+    pub fn merge(&mut self, other: &AgentUsage) {
+        self.calls += other.calls;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+        self.cost += other.cost;
+        self.total_latency_ms += other.total_latency_ms;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    cost: f64,
+    agents: BTreeMap<String, AgentUsage>,
+    routes: BTreeMap<String, AgentUsage>,
+}
 
 #[derive(Debug, Default, Clone)]
 pub struct CostTracker {
-    total_cost: Arc<Mutex<f64>>,
+    totals: Arc<Mutex<Totals>>,
 }
 
 impl CostTracker {
     pub fn new() -> Self {
-        Self {
-            total_cost: Arc::new(Mutex::new(0.0)),
-        }
+        Self::default()
     }
 
     pub fn add_cost(&self, cost: f64) {
-        let mut total_cost = self.total_cost.lock().unwrap();
-        *total_cost += cost;
+        self.totals.lock().unwrap().cost += cost;
+    }
+
+    /// Records one LLM call made by `agent`: its cost, token counts and how long it took.
+    pub fn record_call(&self, agent: &str, response: &AIResponse, latency: Duration) {
+        debug!(
+            "{} call to {}: {} prompt + {} completion tokens in {} ms",
+            agent,
+            response.model,
+            response.input_tokens,
+            response.output_tokens,
+            latency.as_millis()
+        );
+        let mut totals = self.totals.lock().unwrap();
+        totals.cost += response.cost;
+        let usage = totals.agents.entry(agent.to_string()).or_default();
+        usage.calls += 1;
+        usage.input_tokens += u64::from(response.input_tokens);
+        usage.output_tokens += u64::from(response.output_tokens);
+        usage.reasoning_tokens += u64::from(response.reasoning_tokens);
+        usage.cost += response.cost;
+        usage.total_latency_ms += latency.as_millis() as u64;
+    }
+
+    /// Records which model a `RoutingClient` sent a call to, keyed like "cheap (gpt-4o-mini)". The
+    /// call's cost is already counted by `record_call`; this only breaks it down by route.
+    pub fn record_route(&self, route: &str, response: &AIResponse) {
+        let mut totals = self.totals.lock().unwrap();
+        let usage = totals.routes.entry(format!("{} ({})", route, response.model)).or_default();
+        usage.calls += 1;
+        usage.input_tokens += u64::from(response.input_tokens);
+        usage.output_tokens += u64::from(response.output_tokens);
+        usage.reasoning_tokens += u64::from(response.reasoning_tokens);
+        usage.cost += response.cost;
     }
 
     pub fn get_total_cost(&self) -> f64 {
-        *self.total_cost.lock().unwrap()
+        self.totals.lock().unwrap().cost
+    }
+
+    /// Usage recorded so far across every agent.
+    pub fn total_usage(&self) -> AgentUsage {
+        let mut total = AgentUsage::default();
+        for usage in self.totals.lock().unwrap().agents.values() {
+            total.merge(usage);
+        }
+        total
+    }
+
+    /// Usage recorded so far, keyed by agent name.
+    pub fn usage_by_agent(&self) -> BTreeMap<String, AgentUsage> {
+        self.totals.lock().unwrap().agents.clone()
+    }
+
+    /// Usage of routed calls so far, keyed by route and model; empty without model routing.
+    pub fn usage_by_route(&self) -> BTreeMap<String, AgentUsage> {
+        self.totals.lock().unwrap().routes.clone()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(input_tokens: u32, output_tokens: u32, cost: f64) -> AIResponse {
+        AIResponse {
+            content: String::new(),
+            input_tokens,
+            output_tokens,
+            reasoning_tokens: 0,
+            cost,
+            model: "mock-model".to_string(),
+            provider: "mock-provider".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_record_call_aggregates_per_agent() {
+        let tracker = CostTracker::new();
+        tracker.record_call("coder", &response(100, 50, 0.01), Duration::from_millis(300));
+        tracker.record_call("coder", &response(200, 70, 0.02), Duration::from_millis(500));
+        tracker.record_call("planner", &response(10, 5, 0.001), Duration::from_millis(100));
+        tracker.record_call("reasoner", &AIResponse { reasoning_tokens: 64, ..response(10, 80, 0.0) }, Duration::from_millis(100));
+        tracker.add_cost(0.5);
+
+        let usage = tracker.usage_by_agent();
+        let coder = &usage["coder"];
+        assert_eq!((coder.calls, coder.input_tokens, coder.output_tokens), (2, 300, 120));
+        assert_eq!(coder.average_latency_ms(), 400);
+        assert_eq!(usage["planner"].calls, 1);
+        assert_eq!((tracker.total_usage().output_tokens, tracker.total_usage().reasoning_tokens), (205, 64));
+        assert!((tracker.get_total_cost() - 0.531).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use syn::spanned::Spanned;
+use syn::{Attribute, ImplItem, Item, TraitItem, Visibility};
+
+use crate::error::AgentError;
+use crate::tools::{FileEdit, Tool};
+
+/// A public item of a Rust file without a doc comment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UndocumentedItem {
+    /// The keyword that declares it, e.g. `fn` or `struct`.
+    pub kind: String,
+    /// Its name, qualified by its type or trait for methods and by its module in inline modules,
+    /// e.g. `Config::parse`.
+    pub name: String,
+    /// The 1-based line it starts on, its attributes included.
+    pub line: usize,
+}
+
+/// The doc comment the documenter wrote for the item starting on `line`, without `///` markers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemDoc {
+    pub line: usize,
+    pub doc: String,
+}
+
+/// The public items of `source` that have no doc comment, in file order: functions, types, traits,
+/// constants, statics and modules, the public methods and constants of inherent impls, and the
+/// items of public traits. Public inline modules are searched too.
+pub fn undocumented_items(source: &str) -> Result<Vec<UndocumentedItem>, AgentError> {
+    let file = syn::parse_file(source).map_err(|e| AgentError::ToolError(format!("Does not parse as Rust: {}", e)))?;
+    let mut items = Vec::new();
+    collect(&file.items, "", &mut items);
+    items.sort_by_key(|item| item.line);
+    Ok(items)
+}
+
+fn collect(items: &[Item], prefix: &str, out: &mut Vec<UndocumentedItem>) {
+    let mut check = |attrs: &[Attribute], vis: &Visibility, kind: &str, name: String, span: proc_macro2::Span| {
+        if matches!(vis, Visibility::Public(_)) && !is_documented(attrs) {
+            out.push(UndocumentedItem { kind: kind.to_string(), name: format!("{}{}", prefix, name), line: span.start().line });
+        }
+    };
+    let mut nested = Vec::new();
+    for item in items {
+        match item {
+            Item::Fn(f) => check(&f.attrs, &f.vis, "fn", f.sig.ident.to_string(), item.span()),
+            Item::Struct(s) => check(&s.attrs, &s.vis, "struct", s.ident.to_string(), item.span()),
+            Item::Enum(e) => check(&e.attrs, &e.vis, "enum", e.ident.to_string(), item.span()),
+            Item::Union(u) => check(&u.attrs, &u.vis, "union", u.ident.to_string(), item.span()),
+            Item::Type(t) => check(&t.attrs, &t.vis, "type", t.ident.to_string(), item.span()),
+            Item::Const(c) => check(&c.attrs, &c.vis, "const", c.ident.to_string(), item.span()),
+            Item::Static(s) => check(&s.attrs, &s.vis, "static", s.ident.to_string(), item.span()),
+            Item::Mod(m) => {
+                check(&m.attrs, &m.vis, "mod", m.ident.to_string(), item.span());
+                if let (Visibility::Public(_), Some((_, content))) = (&m.vis, &m.content) {
+                    nested.push((format!("{}{}::", prefix, m.ident), content));
+                }
+            }
+            Item::Trait(t) => {
+                check(&t.attrs, &t.vis, "trait", t.ident.to_string(), item.span());
+                if !matches!(t.vis, Visibility::Public(_)) {
+                    continue;
+                }
+                // Trait items are as public as the trait, without a `pub` of their own.
+                let public = Visibility::Public(Default::default());
+                for trait_item in &t.items {
+                    let name = |ident: &syn::Ident| format!("{}::{}", t.ident, ident);
+                    match trait_item {
+                        TraitItem::Fn(f) => check(&f.attrs, &public, "fn", name(&f.sig.ident), trait_item.span()),
+                        TraitItem::Const(c) => check(&c.attrs, &public, "const", name(&c.ident), trait_item.span()),
+                        TraitItem::Type(ty) => check(&ty.attrs, &public, "type", name(&ty.ident), trait_item.span()),
+                        _ => {}
+                    }
+                }
+            }
+            // Methods of trait impls are documented on the trait.
+            Item::Impl(i) if i.trait_.is_none() => {
+                let self_type = match i.self_ty.as_ref() {
+                    syn::Type::Path(path) => path.path.segments.last().map(|segment| segment.ident.to_string()),
+                    _ => None,
+                };
+                let Some(self_type) = self_type else { continue };
+                for impl_item in &i.items {
+                    match impl_item {
+                        ImplItem::Fn(f) => check(&f.attrs, &f.vis, "fn", format!("{}::{}", self_type, f.sig.ident), impl_item.span()),
+                        ImplItem::Const(c) => check(&c.attrs, &c.vis, "const", format!("{}::{}", self_type, c.ident), impl_item.span()),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for (prefix, items) in nested {
+        collect(items, &prefix, out);
+    }
+}
+
+fn is_documented(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| attr.path().is_ident("doc"))
+}
+
+/// The Rust files at `path`: the file itself, or every `.rs` file below a directory that git
+/// does not ignore, sorted.
+pub fn rust_files(path: &Path) -> Vec<PathBuf> {
+    if path.is_file() {
+        return vec![path.to_path_buf()];
+    }
+    let mut files: Vec<PathBuf> = ignore::WalkBuilder::new(path)
+        .build()
+        .filter_map(Result::ok)
+        .map(|entry| entry.into_path())
+        .filter(|file| file.is_file() && file.extension().is_some_and(|extension| extension == "rs"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// The `EditFile` that puts each of `docs` above the item of `items` it belongs to, as `///` lines
+/// indented like the item. Docs for lines that start no undocumented item are left out; `None`
+/// when none are left. The comments end their lines the way `source` does, `\r\n` or `\n`.
+pub fn doc_edit(path: &str, source: &str, items: &[UndocumentedItem], docs: &[ItemDoc]) -> Option<Tool> {
+    let lines: Vec<&str> = source.lines().collect();
+    let newline = if source.contains("\r\n") { "\r\n" } else { "\n" };
+    let edits: Vec<FileEdit> = docs
+        .iter()
+        .filter(|doc| items.iter().any(|item| item.line == doc.line) && !doc.doc.trim().is_empty())
+        .filter_map(|doc| {
+            let start = doc.line.checked_sub(1).filter(|start| *start < lines.len())?;
+            // The item's first lines, as many as it takes to match only there.
+            let search = (start + 1..=lines.len()).map(|end| lines[start..end].join(newline)).find(|search| source.matches(search.as_str()).count() == 1)?;
+            let indent: String = lines[start].chars().take_while(|c| c.is_whitespace()).collect();
+            let comment: Vec<String> = doc
+                .doc
+                .trim()
+                .lines()
+                .map(|line| {
+                    // Markers the model wrote itself are dropped; indentation in code examples is kept.
+                    let line = line.trim_end();
+                    let text = line.trim_start().strip_prefix("///").map(|text| text.strip_prefix(' ').unwrap_or(text)).unwrap_or(line);
+                    if text.is_empty() { format!("{}///", indent) } else { format!("{}/// {}", indent, text) }
+                })
+                .collect();
+            Some(FileEdit { search: search.clone(), replace: format!("{}{}{}", comment.join(newline), newline, search) })
+        })
+        .collect();
+    (!edits.is_empty()).then(|| Tool::EditFile { path: path.to_string(), edits })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::apply_edits;
+
+    const SOURCE: &str = r#"//! Settings.
+
+/// Documented.
+pub struct Config {
+    pub name: String,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Self {
+        todo!()
+    }
+
+    fn private(&self) {}
+}
+
+#[derive(Debug)]
+pub enum Mode {
+    Fast,
+}
+
+pub trait Loader {
+    /// Documented.
+    fn load(&self);
+    fn reload(&self);
+}
+
+pub mod nested {
+    pub const LIMIT: usize = 3;
+}
+
+pub(crate) fn internal() {}
+"#;
+
+    #[test]
+    fn test_undocumented_items() {
+        let items: Vec<(String, usize)> = undocumented_items(SOURCE).unwrap().into_iter().map(|item| (item.name, item.line)).collect();
+        let expected = [("Config::parse", 9), ("Mode", 16), ("Loader", 21), ("Loader::reload", 24), ("nested", 27), ("nested::LIMIT", 28)];
+        assert_eq!(items, expected.map(|(name, line)| (name.to_string(), line)));
+        assert!(undocumented_items("pub fn broken(").is_err());
+    }
+
+    #[test]
+    fn test_doc_edit_inserts_indented_comments() {
+        let items = undocumented_items(SOURCE).unwrap();
+        let docs = [
+            ItemDoc { line: 9, doc: "Parses `text`.\n\nPanics for now.".to_string() },
+            ItemDoc { line: 16, doc: "/// How fast to go.".to_string() },
+            ItemDoc { line: 3, doc: "Not an undocumented item.".to_string() },
+        ];
+        let Some(Tool::EditFile { path, edits }) = doc_edit("src/config.rs", SOURCE, &items, &docs) else { panic!("expected an edit") };
+        assert_eq!(path, "src/config.rs");
+        assert_eq!(edits.len(), 2);
+
+        let updated = apply_edits(SOURCE, &edits).unwrap();
+        assert!(updated.contains("impl Config {\n    /// Parses `text`.\n    ///\n    /// Panics for now.\n    pub fn parse(text: &str) -> Self {"));
+        assert!(updated.contains("}\n\n/// How fast to go.\n#[derive(Debug)]\npub enum Mode {"));
+        assert!(undocumented_items(&updated).unwrap().iter().all(|item| item.line != 9 && item.name != "Mode"));
+        assert!(doc_edit("src/config.rs", SOURCE, &items, &docs[2..]).is_none());
+    }
+
+    #[test]
+    fn test_doc_edit_keeps_crlf_line_endings() {
+        let source = SOURCE.replace('\n', "\r\n");
+        let items = undocumented_items(&source).unwrap();
+        let docs = [ItemDoc { line: 9, doc: "Parses `text`.\n\nPanics for now.".to_string() }];
+        let Some(Tool::EditFile { edits, .. }) = doc_edit("src/config.rs", &source, &items, &docs) else { panic!("expected an edit") };
+
+        let updated = apply_edits(&source, &edits).unwrap();
+        assert!(updated.contains("impl Config {\r\n    /// Parses `text`.\r\n    ///\r\n    /// Panics for now.\r\n    pub fn parse(text: &str) -> Self {"));
+    }
+}
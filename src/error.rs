@@ -20,6 +20,22 @@ pub enum AgentError {
     JsonError(#[from] serde_json::Error),
     #[error("Failed to parse LLM response: {0}")]
     ResponseParseError(String),
+    #[error("Sandbox violation: {0}")]
+    SandboxViolation(String),
+    #[error("Command timed out after {seconds}s and was killed: {command}")]
+    ToolTimeout { command: String, seconds: u64 },
+    #[error("Loop detected: {0}")]
+    LoopDetected(String),
+    #[error("Stopped after reaching the limit of {0} steps")]
+    MaxStepsExceeded(usize),
+    #[error("Run cancelled")]
+    Cancelled,
+    #[error("The plan's estimated cost of ${0:.2} was not approved")]
+    CostNotApproved(f64),
+    #[error("Stopped after spending ${spent:.2} of the ${limit:.2} budget")]
+    BudgetExceeded { spent: f64, limit: f64 },
+    #[error("Session error: {0}")]
+    SessionError(String),
 }
 
 #[cfg(test)]
@@ -43,6 +59,21 @@ mod tests {
 
         let error = AgentError::ResponseParseError("invalid json".to_string());
         assert_eq!(error.to_string(), "Failed to parse LLM response: invalid json");
+
+        let error = AgentError::SandboxViolation("outside workspace".to_string());
+        assert_eq!(error.to_string(), "Sandbox violation: outside workspace");
+
+        let error = AgentError::ToolTimeout { command: "cargo build".to_string(), seconds: 5 };
+        assert_eq!(error.to_string(), "Command timed out after 5s and was killed: cargo build");
+
+        let error = AgentError::MaxStepsExceeded(50);
+        assert_eq!(error.to_string(), "Stopped after reaching the limit of 50 steps");
+
+        let error = AgentError::CostNotApproved(4.987);
+        assert_eq!(error.to_string(), "The plan's estimated cost of $4.99 was not approved");
+
+        let error = AgentError::BudgetExceeded { spent: 0.5123, limit: 0.5 };
+        assert_eq!(error.to_string(), "Stopped after spending $0.51 of the $0.50 budget");
     }
 
     #[test]
@@ -89,6 +120,12 @@ mod tests {
             AgentError::ApiKeyMissing("provider".to_string()),
             AgentError::ToolError("tool".to_string()),
             AgentError::ResponseParseError("parse".to_string()),
+            AgentError::SandboxViolation("sandbox".to_string()),
+            AgentError::ToolTimeout { command: "command".to_string(), seconds: 1 },
+            AgentError::LoopDetected("loop".to_string()),
+            AgentError::MaxStepsExceeded(1),
+            AgentError::Cancelled,
+            AgentError::SessionError("session".to_string()),
         ];
 
         for error in errors {
@@ -0,0 +1,221 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::error::AgentError;
+use crate::snapshot::FileChange;
+use crate::tools::{Decision, Tool};
+
+/// Something that happened during a run, in the order the orchestrator did it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum AgentEvent {
+    PlanCreated { steps: Vec<String> },
+    /// The steps after `step_index` were replaced because the old ones were no longer valid.
+    PlanRevised { step_index: usize, reason: String, steps: Vec<String> },
+    StepStarted { step_index: usize, step: String },
+    /// The files a step created, modified or deleted, by whatever means (tools, commands, workers),
+    /// with their hashes and diffs. Only emitted for steps that changed something.
+    StepChanges { step_index: usize, changes: Vec<FileChange> },
+    /// A step completed: how long it took and how much of that was spent in LLM calls.
+    StepFinished { step_index: usize, duration_ms: u64, llm_calls: u64, llm_ms: u64 },
+    DecisionMade { step_index: usize, decision: Decision },
+    ToolExecuted { step_index: usize, tool: Tool, success: bool, output: String },
+    CodeGenerated { step_index: usize, task: String, file_path: Option<String>, code: String },
+    /// The verifier's verdict once the plan finished; `missing` is empty when complete.
+    GoalVerified { complete: bool, missing: Vec<String> },
+    Error { step_index: Option<usize>, message: String },
+    /// The last event of every run, with its outcome and what it cost in total.
+    RunFinished { success: bool, total_cost: f64 },
+}
+
+/// An event stamped with the time it was emitted; one line of a transcript.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: AgentEvent,
+}
+
+/// Receives every event emitted on an `EventBus`.
+pub trait EventSink: Send + Sync {
+    fn handle(&self, record: &EventRecord);
+}
+
+/// Any `Fn(&EventRecord)` closure is a sink, e.g. for `AgentBuilder::on_event`.
+impl<F: Fn(&EventRecord) + Send + Sync> EventSink for F {
+    fn handle(&self, record: &EventRecord) {
+        self(record)
+    }
+}
+
+/// Fans orchestrator events out to the subscribed sinks. With no sinks, emitting is a no-op.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&mut self, sink: Arc<dyn EventSink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Whether no sink is subscribed, so emitted events go nowhere.
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub fn emit(&self, event: AgentEvent) {
+        if self.sinks.is_empty() {
+            return;
+        }
+        let record = EventRecord { timestamp: Utc::now(), event };
+        for sink in &self.sinks {
+            sink.handle(&record);
+        }
+    }
+}
+
+/// Writes each event as one JSON line, flushing after every event so a crashed run still
+/// leaves a usable transcript.
+pub struct JsonlTranscript {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonlTranscript {
+    /// Creates (or truncates) the transcript at `path`, creating parent directories as needed.
+    pub fn create(path: &Path) -> Result<Self, AgentError> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(Self::to_writer(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Streams events to stdout, for `--output json`.
+    pub fn stdout() -> Self {
+        Self::to_writer(std::io::stdout())
+    }
+
+    pub fn to_writer(writer: impl Write + Send + 'static) -> Self {
+        Self { writer: Mutex::new(Box::new(writer)) }
+    }
+
+    /// Reads a transcript back, e.g. to replay or analyze a run.
+    pub fn read(path: &Path) -> Result<Vec<EventRecord>, AgentError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut records = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                records.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(records)
+    }
+}
+
+impl EventSink for JsonlTranscript {
+    fn handle(&self, record: &EventRecord) {
+        let mut writer = self.writer.lock().unwrap();
+        let result = serde_json::to_string(record)
+            .map_err(std::io::Error::from)
+            .and_then(|line| writeln!(writer, "{}", line))
+            .and_then(|_| writer.flush());
+        if let Err(e) = result {
+            warn!("Failed to write transcript event: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CollectingSink(Mutex<Vec<EventRecord>>);
+
+    impl EventSink for CollectingSink {
+        fn handle(&self, record: &EventRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    #[test]
+    fn test_event_bus_fans_out() {
+        let first = Arc::new(CollectingSink(Mutex::new(Vec::new())));
+        let second = Arc::new(CollectingSink(Mutex::new(Vec::new())));
+        let mut bus = EventBus::new();
+        bus.subscribe(first.clone());
+        bus.subscribe(second.clone());
+
+        bus.emit(AgentEvent::StepStarted { step_index: 0, step: "List files".to_string() });
+
+        assert_eq!(first.0.lock().unwrap().len(), 1);
+        assert_eq!(second.0.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let record = EventRecord {
+            timestamp: Utc::now(),
+            event: AgentEvent::ToolExecuted {
+                step_index: 2,
+                tool: Tool::ListFiles { path: ".".to_string(), max_depth: None, glob: None },
+                success: true,
+                output: "src/".to_string(),
+            },
+        };
+        let json: serde_json::Value = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["event"], "ToolExecuted");
+        assert_eq!(json["step_index"], 2);
+        assert_eq!(json["tool"]["tool_name"], "ListFiles");
+        assert!(json["timestamp"].is_string());
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_jsonl_transcript_to_writer() {
+        let buffer = SharedBuffer::default();
+        let transcript = JsonlTranscript::to_writer(buffer.clone());
+        transcript.handle(&EventRecord { timestamp: Utc::now(), event: AgentEvent::RunFinished { success: true, total_cost: 0.25 } });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(output.trim_end()).unwrap();
+        assert!(output.ends_with('\n'));
+        assert_eq!(json["event"], "RunFinished");
+        assert_eq!(json["total_cost"], 0.25);
+    }
+
+    #[test]
+    fn test_jsonl_transcript_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("runs").join("transcript.jsonl");
+        let transcript = JsonlTranscript::create(&path).unwrap();
+
+        transcript.handle(&EventRecord { timestamp: Utc::now(), event: AgentEvent::PlanCreated { steps: vec!["a".to_string()] } });
+        transcript.handle(&EventRecord { timestamp: Utc::now(), event: AgentEvent::Error { step_index: None, message: "boom".to_string() } });
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+        let records = JsonlTranscript::read(&path).unwrap();
+        assert!(matches!(&records[0].event, AgentEvent::PlanCreated { steps } if steps == &["a".to_string()]));
+        assert!(matches!(&records[1].event, AgentEvent::Error { message, .. } if message == "boom"));
+    }
+}
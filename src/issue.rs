@@ -0,0 +1,205 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::error::AgentError;
+use crate::pull_request::{Forge, Remote, API_TIMEOUT};
+
+/// A GitHub issue, written `owner/repo#123` or as its URL, e.g.
+/// `https://github.com/owner/repo/issues/123` (also on GitHub Enterprise hosts).
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssueRef {
+    pub repository: Remote,
+    pub number: u64,
+}
+
+impl FromStr for IssueRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("'{}' is not an issue; expected owner/repo#123 or an issue URL", s);
+        let (repository, number) = match s.split_once("/issues/") {
+            Some((url, number)) => (Remote::parse(url).ok_or_else(invalid)?, number),
+            None => {
+                let (path, number) = s.split_once('#').ok_or_else(invalid)?;
+                let repository = Remote::parse(&format!("https://github.com/{}", path)).filter(|remote| remote.path.split('/').count() == 2).ok_or_else(invalid)?;
+                (repository, number)
+            }
+        };
+        if repository.forge != Forge::GitHub {
+            return Err(format!("{} is not a GitHub repository", repository.host));
+        }
+        let number = number.trim_end_matches('/').parse().map_err(|_| invalid())?;
+        Ok(Self { repository, number })
+    }
+}
+
+impl fmt::Display for IssueRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.repository.path, self.number)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IssueComment {
+    #[serde(rename = "user", deserialize_with = "login")]
+    pub author: String,
+    #[serde(default, deserialize_with = "text")]
+    pub body: String,
+}
+
+/// An issue with its discussion, as fetched by `IssueClient::fetch`.
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub reference: IssueRef,
+    pub title: String,
+    pub body: String,
+    pub comments: Vec<IssueComment>,
+}
+
+impl Issue {
+    /// The goal of a run working on the issue: its title and description.
+    pub fn goal(&self) -> String {
+        let goal = format!("Resolve GitHub issue {}: {}", self.reference, self.title.trim());
+        match self.body.trim() {
+            "" => goal,
+            body => format!("{}\n\n{}", goal, body),
+        }
+    }
+
+    /// The discussion below the issue, for the run's initial context; `None` without comments.
+    pub fn discussion(&self) -> Option<String> {
+        let comments: Vec<String> = self
+            .comments
+            .iter()
+            .filter(|comment| !comment.body.trim().is_empty())
+            .map(|comment| format!("@{}:\n{}", comment.author, comment.body.trim()))
+            .collect();
+        (!comments.is_empty()).then(|| format!("Comments on {}:\n\n{}", self.reference, comments.join("\n\n")))
+    }
+}
+
+/// Reads issues through the GitHub REST API.
+pub struct IssueClient {
+    client: reqwest::Client,
+    token: Option<String>,
+    api_url: Option<String>,
+}
+
+impl IssueClient {
+    /// A client authenticating with `token`; public repositories can be read without one.
+    pub fn new(token: Option<&str>) -> Self {
+        Self { client: reqwest::Client::new(), token: token.map(str::to_string), api_url: None }
+    }
+
+    /// Sends API requests to `api_url` instead of the issue host's API, e.g. a test server.
+    pub fn with_api_url(mut self, api_url: &str) -> Self {
+        self.api_url = Some(api_url.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Sends API requests through `client`, e.g. the shared client with the configured proxy.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Fetches the issue `reference` with its title, description and first 100 comments.
+    pub async fn fetch(&self, reference: &IssueRef) -> Result<Issue, AgentError> {
+        #[derive(Deserialize)]
+        struct IssueFields {
+            title: String,
+            #[serde(default, deserialize_with = "text")]
+            body: String,
+        }
+
+        let api_url = self.api_url.clone().unwrap_or_else(|| reference.repository.api_url());
+        let url = format!("{}/repos/{}/issues/{}", api_url, reference.repository.path, reference.number);
+        let fields: IssueFields = self.get(&url, reference).await?;
+        let comments = self.get(&format!("{}/comments?per_page=100", url), reference).await?;
+        Ok(Issue { reference: reference.clone(), title: fields.title, body: fields.body, comments })
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, url: &str, reference: &IssueRef) -> Result<T, AgentError> {
+        let mut request = self
+            .client
+            .get(url)
+            .timeout(API_TIMEOUT)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", concat!("rust-cli-agent/", env!("CARGO_PKG_VERSION")));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let hint = if self.token.is_none() { " (set GITHUB_TOKEN for private repositories)" } else { "" };
+            return Err(AgentError::ToolError(format!("Could not fetch issue {} ({}){}", reference, status, hint)));
+        }
+        Ok(response.json().await?)
+    }
+}
+
+/// A comment's author from its `user` object; deleted users have none.
+fn login<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    struct User {
+        login: String,
+    }
+    Ok(Option::<User>::deserialize(deserializer)?.map_or_else(|| "ghost".to_string(), |user| user.login))
+}
+
+/// A text field GitHub sends as `null` when it is empty.
+fn text<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_issue_refs_parse_short_forms_and_urls() {
+        let issue: IssueRef = "ohboyftw/rust-cli-agent#123".parse().unwrap();
+        assert_eq!((issue.repository.host.as_str(), issue.repository.path.as_str(), issue.number), ("github.com", "ohboyftw/rust-cli-agent", 123));
+        assert_eq!(issue.to_string(), "ohboyftw/rust-cli-agent#123");
+        assert_eq!("https://github.com/ohboyftw/rust-cli-agent/issues/123".parse::<IssueRef>().unwrap(), issue);
+        assert_eq!("https://github.corp.example/team/app/issues/9".parse::<IssueRef>().unwrap().repository.api_url(), "https://github.corp.example/api/v3");
+
+        for invalid in ["rust-cli-agent#123", "owner/repo#abc", "owner/repo", "https://gitlab.com/group/app/issues/4"] {
+            assert!(invalid.parse::<IssueRef>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_reads_the_issue_and_its_comments() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/owner/app/issues/7"))
+            .and(header("authorization", "Bearer gh-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "title": "Add a users repository", "body": "We need `UserRepository`." })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/owner/app/issues/7/comments"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                { "user": { "login": "ada" }, "body": "Use sqlx, please." },
+                { "user": null, "body": null },
+            ])))
+            .mount(&server)
+            .await;
+
+        let reference: IssueRef = "owner/app#7".parse().unwrap();
+        let issue = IssueClient::new(Some("gh-token")).with_api_url(&server.uri()).fetch(&reference).await.unwrap();
+        assert_eq!(issue.goal(), "Resolve GitHub issue owner/app#7: Add a users repository\n\nWe need `UserRepository`.");
+        assert_eq!(issue.discussion().unwrap(), "Comments on owner/app#7:\n\n@ada:\nUse sqlx, please.");
+
+        let missing: IssueRef = "owner/app#8".parse().unwrap();
+        assert!(IssueClient::new(None).with_api_url(&server.uri()).fetch(&missing).await.unwrap_err().to_string().contains("GITHUB_TOKEN"));
+    }
+}
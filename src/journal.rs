@@ -0,0 +1,168 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::error::AgentError;
+
+/// Where the file changes of the latest run are journaled for `undo`.
+pub const JOURNAL_FILE: &str = ".agent/journal.json";
+
+/// A file as it was before the run first changed it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    pub path: PathBuf,
+    /// Content before the run; `None` if the run created the file.
+    pub original: Option<String>,
+    /// When the snapshot was taken; `None` in journals written before this was recorded.
+    #[serde(default)]
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+/// What rolling back did to one file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RestoredFile {
+    Restored(PathBuf),
+    Removed(PathBuf),
+}
+
+impl std::fmt::Display for RestoredFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestoredFile::Restored(path) => write!(f, "restored {}", path.display()),
+            RestoredFile::Removed(path) => write!(f, "removed {}", path.display()),
+        }
+    }
+}
+
+/// The pre-run contents of every file a run wrote or edited, so the run can be undone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Journal {
+    pub goal: String,
+    pub started_at: DateTime<Utc>,
+    pub changes: Vec<FileSnapshot>,
+}
+
+impl Journal {
+    pub fn new(goal: &str) -> Self {
+        Self { goal: goal.to_string(), started_at: Utc::now(), changes: Vec::new() }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, AgentError> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AgentError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Snapshots `file` before the run changes it. Only the first change counts: later ones must
+    /// not overwrite the pre-run content. Relative paths are stored as absolute ones so `undo`
+    /// works from any directory.
+    pub fn record(&mut self, file: &Path) -> Result<(), AgentError> {
+        let path = std::path::absolute(file)?;
+        if self.changes.iter().any(|change| change.path == path) {
+            return Ok(());
+        }
+        let original = match std::fs::read(&path) {
+            Ok(bytes) => Some(String::from_utf8(bytes).map_err(|_| {
+                AgentError::ToolError(format!("{} is not a text file and cannot be journaled for undo", path.display()))
+            })?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        self.changes.push(FileSnapshot { path, original, recorded_at: Some(Utc::now()) });
+        Ok(())
+    }
+
+    /// Takes over the snapshots of `other`, e.g. a worker's journal. Where both recorded a file, the
+    /// earlier snapshot is kept, since a later one may hold content the run itself wrote.
+    pub fn merge(&mut self, other: Journal) {
+        for change in other.changes {
+            match self.changes.iter_mut().find(|existing| existing.path == change.path) {
+                Some(existing) if change.recorded_at < existing.recorded_at => *existing = change,
+                Some(_) => {}
+                None => self.changes.push(change),
+            }
+        }
+    }
+
+    /// Puts every journaled file back the way it was before the run, newest change first, and
+    /// deletes files the run created.
+    pub fn rollback(&self) -> Result<Vec<RestoredFile>, AgentError> {
+        let mut restored = Vec::new();
+        for change in self.changes.iter().rev() {
+            match &change.original {
+                Some(content) => {
+                    if let Some(parent) = change.path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&change.path, content)?;
+                    restored.push(RestoredFile::Restored(change.path.clone()));
+                }
+                None => match std::fs::remove_file(&change.path) {
+                    Ok(()) => restored.push(RestoredFile::Removed(change.path.clone())),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                },
+            }
+        }
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_restores_pre_run_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let edited = dir.path().join("lib.rs");
+        let created = dir.path().join("src").join("new.rs");
+        std::fs::write(&edited, "original\n").unwrap();
+
+        let mut journal = Journal::new("Refactor");
+        journal.record(&edited).unwrap();
+        std::fs::write(&edited, "first change\n").unwrap();
+        journal.record(&edited).unwrap();
+        std::fs::write(&edited, "second change\n").unwrap();
+        journal.record(&created).unwrap();
+        std::fs::create_dir_all(created.parent().unwrap()).unwrap();
+        std::fs::write(&created, "new file\n").unwrap();
+        assert_eq!(journal.changes.len(), 2);
+
+        let path = dir.path().join(".agent").join("journal.json");
+        journal.save(&path).unwrap();
+        let restored = Journal::load(&path).unwrap().rollback().unwrap();
+
+        assert_eq!(restored, vec![RestoredFile::Removed(created.clone()), RestoredFile::Restored(edited.clone())]);
+        assert_eq!(std::fs::read_to_string(&edited).unwrap(), "original\n");
+        assert!(!created.exists());
+    }
+
+    #[test]
+    fn test_merge_keeps_the_earliest_snapshot_of_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("lib.rs");
+        std::fs::write(&file, "original\n").unwrap();
+        let mut first = Journal::new("Refactor");
+        first.record(&file).unwrap();
+        std::fs::write(&file, "first worker's change\n").unwrap();
+        let mut second = Journal::new("Refactor");
+        second.record(&file).unwrap();
+
+        // The later snapshot does not replace the earlier one, in either order.
+        let mut merged = Journal::new("Refactor");
+        merged.merge(second.clone());
+        merged.merge(first.clone());
+        assert_eq!(merged.changes.len(), 1);
+        assert_eq!(merged.changes[0].original.as_deref(), Some("original\n"));
+        first.merge(second);
+        assert_eq!(first.changes[0].original.as_deref(), Some("original\n"));
+    }
+}
@@ -0,0 +1,170 @@
+use serde::de::DeserializeOwned;
+
+/// Parses `text` as JSON, falling back to `repair` when it is not valid as is. A repaired value
+/// that is valid JSON but not a valid `T` reports why it is not; otherwise the original error is kept.
+pub fn parse_lenient<T: DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+    let error = match serde_json::from_str(text.trim()) {
+        Ok(value) => return Ok(value),
+        Err(error) => error,
+    };
+    let Some(repaired) = repair(text) else {
+        return Err(error);
+    };
+    match serde_json::from_str::<serde_json::Value>(&repaired) {
+        Ok(value) => serde_json::from_value(value),
+        Err(_) => Err(error),
+    }
+}
+
+/// Rewrites the JSON object or array in a model's answer into strict JSON, undoing the usual ways
+/// models get it wrong: prose or markdown fences around it, `//` and `/* */` comments, single-quoted
+/// strings, unquoted keys, Python's `True`/`False`/`None`, raw newlines in strings and trailing
+/// commas. `None` if the text contains no object or array, or if a string, object or array in it
+/// is never closed: that answer was cut off (e.g. at the token limit), and closing it would pass
+/// truncated content off as complete, so the model is asked again instead.
+pub fn repair(text: &str) -> Option<String> {
+    let start = text.find(['{', '['])?;
+    let chars: Vec<char> = text[start..].chars().collect();
+    let mut out = String::new();
+    let mut closers: Vec<char> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' => {
+                i = copy_string(&chars, i, &mut out)?;
+                continue;
+            }
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                drop_trailing_comma(&mut out);
+                closers.pop();
+                out.push(c);
+                if closers.is_empty() {
+                    return Some(out);
+                }
+                i += 1;
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' || c == '$' => {
+                let end = (i..chars.len()).find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '_' || chars[j] == '$')).unwrap_or(chars.len());
+                let word: String = chars[i..end].iter().collect();
+                let is_key = chars[end..].iter().find(|c| !c.is_whitespace()) == Some(&':');
+                match word.as_str() {
+                    _ if is_key => out.push_str(&format!("\"{}\"", word)),
+                    "True" => out.push_str("true"),
+                    "False" => out.push_str("false"),
+                    "None" => out.push_str("null"),
+                    _ => out.push_str(&word),
+                }
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+        i += 1;
+    }
+    // The answer was cut off before its object or array was closed.
+    None
+}
+
+/// Copies the string starting at `chars[start]` as a double-quoted JSON string and returns the
+/// index after its closing quote, or `None` if the string is never closed.
+fn copy_string(chars: &[char], start: usize, out: &mut String) -> Option<usize> {
+    let quote = chars[start];
+    out.push('"');
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if chars.get(i + 1) == Some(&'\'') => out.push('\''),
+            '\\' if i + 1 < chars.len() => {
+                out.push('\\');
+                out.push(chars[i + 1]);
+            }
+            c if c == quote => {
+                out.push('"');
+                return Some(i + 1);
+            }
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+        i += if chars[i] == '\\' { 2 } else { 1 };
+    }
+    None
+}
+
+fn drop_trailing_comma(out: &mut String) {
+    let trimmed = out.trim_end();
+    if trimmed.ends_with(',') {
+        out.truncate(trimmed.len() - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_repair_fixes_common_model_mistakes() {
+        let fenced = "Here is my decision:\n```json\n{\"tool_name\": \"ReadFile\", \"parameters\": {\"path\": \"a.rs\"}}\n```\nLet me know!";
+        assert_eq!(parse_lenient::<Value>(fenced).unwrap(), json!({ "tool_name": "ReadFile", "parameters": { "path": "a.rs" } }));
+
+        let sloppy = r#"{
+  thought: 'It\'s a "quick" read', // why
+  'tool_name': "ReadFile",
+  /* the file */ "parameters": {"path": "a.rs", "verbose": True, "limit": None,},
+  "file_path": "x
+y",
+}"#;
+        assert_eq!(
+            parse_lenient::<Value>(sloppy).unwrap(),
+            json!({ "thought": "It's a \"quick\" read", "tool_name": "ReadFile", "parameters": { "path": "a.rs", "verbose": true, "limit": null }, "file_path": "x\ny" })
+        );
+
+        assert_eq!(repair(r#"["a", "b",]"#).unwrap(), r#"["a", "b"]"#);
+        assert_eq!(repair(r#"{"url": "https://example.com/a"}"#).unwrap(), r#"{"url": "https://example.com/a"}"#);
+    }
+
+    #[test]
+    fn test_repair_refuses_cut_off_answers() {
+        // A decision cut off at the token limit must not become a WriteFile of the partial content.
+        let cut_off = r#"{"thought": "Write it", "parameters": {"path": "a.txt", "content": "hel"#;
+        assert_eq!(repair(cut_off), None);
+        assert!(parse_lenient::<Value>(cut_off).unwrap_err().is_eof());
+        assert_eq!(repair(r#"{"thought": "Write it", "parameters": {"path": "a.txt"}"#), None);
+        assert_eq!(repair("```json\n[1, 2,"), None);
+    }
+
+    #[test]
+    fn test_parse_lenient_reports_why_a_repaired_value_is_invalid() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Decision {
+            #[allow(dead_code)]
+            tool_name: String,
+        }
+        let error = parse_lenient::<Decision>("Sure! {\"tool\": \"ReadFile\"}").unwrap_err();
+        assert!(error.to_string().contains("missing field `tool_name`"), "{}", error);
+        let error = parse_lenient::<Decision>("I cannot decide.").unwrap_err();
+        assert!(error.to_string().contains("expected value"), "{}", error);
+    }
+}
@@ -3,20 +3,56 @@
 //! This crate provides a multi-provider AI coding assistant that can understand goals,
 //! create plans, and execute them using various tools and LLM providers.
 
+pub mod agent;
 pub mod agents;
+pub mod approval;
+pub mod bench;
 pub mod config;
+pub mod context;
+pub mod doc_gen;
+pub mod conversation;
 pub mod error;
+pub mod events;
+pub mod issue;
+pub mod journal;
+pub mod json_repair;
 pub mod llm;
+pub mod memory;
+pub mod metrics;
 pub mod orchestrator;
+pub mod plan_file;
+pub mod project;
+pub mod pull_request;
+pub mod queue;
+pub mod repl;
+pub mod reporter;
+pub mod review;
+pub mod run_report;
+pub mod session;
+pub mod session_store;
+pub mod snapshot;
 pub mod state;
+pub mod stats;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod test_gen;
+pub mod tokens;
 pub mod tools;
+pub mod tui;
+pub mod watch;
 pub mod cost_tracker;
 
 // Re-export commonly used types for easier access in tests and external usage
+pub use agent::{Agent, AgentBuilder};
+pub use approval::{Approver, ConsoleApprover, ConsoleCostApprover, ConsoleSteering, CostApprover, Steering};
 pub use config::AppConfig;
+pub use context::ContextBuilder;
 pub use error::AgentError;
-pub use llm::{create_llm_client, LLMClient, LLMProvider, AIResponse, ModelInfo};
+pub use llm::embeddings::{create_embeddings_client, EmbeddingProvider, EmbeddingsClient};
+pub use llm::{create_llm_client, create_llm_client_for, GenerationParams, LLMClient, ModelSpec, LLMProvider, AIResponse, ChatMessage, ChatRole, ModelInfo, OnText, ToolCall, ToolDefinition, RoleParams};
 pub use orchestrator::Orchestrator;
+pub use reporter::{ConsoleReporter, NullReporter, PrefixedReporter, Reporter};
+pub use session::{SessionId, SessionInfo, SessionManager, SessionStatus};
 pub use state::AppState;
-pub use tools::{run_tool, Tool, ToolResult, Decision, get_decision_prompt};
-pub use cost_tracker::CostTracker;
\ No newline at end of file
+pub use tools::{run_tool, run_tool_in, Tool, ToolContext, ToolHandler, ToolRegistry, ToolResult, Decision, FileEdit, get_decision_prompt};
+pub use cost_tracker::{CostEstimate, CostTracker};
\ No newline at end of file
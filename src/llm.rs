@@ -1,49 +1,356 @@
 use async_trait::async_trait;
+use base64::Engine;
 use clap::ValueEnum;
-use std::{fmt, sync::Arc};
+use std::{fmt, str::FromStr, sync::Arc, time::Duration};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
-use crate::{config::AppConfig, error::AgentError};
+use log::warn;
+
+use crate::{config::AppConfig, error::AgentError, tokens};
 
 mod claude;
 mod deepseek;
+pub mod embeddings;
 mod gemini;
+mod groq;
+pub mod http;
+mod mistral;
+pub mod mock;
 mod openai;
 mod ollama;
+pub mod pricing;
+pub mod prompt_log;
+pub mod rate_limit;
+pub mod routing;
+
+use pricing::PricingTable;
 
 #[derive(Debug, Clone)]
 pub struct AIResponse {
     pub content: String,
     pub input_tokens: u32,
     pub output_tokens: u32,
+    /// Output tokens that were hidden reasoning, for reasoning models; already counted in
+    /// `output_tokens` and `cost`.
+    pub reasoning_tokens: u32,
     pub cost: f64,
     pub model: String,
     pub provider: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+}
+
+impl ChatRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRole::System => "system",
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+        }
+    }
+}
+
+/// An image sent along with a message to models that accept images (OpenAI's GPT-4o, Claude,
+/// Gemini). Providers without image input drop it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageAttachment {
+    /// MIME type, e.g. "image/png".
+    pub media_type: String,
+    /// The image, base64-encoded.
+    pub data: String,
+}
+
+impl ImageAttachment {
+    pub fn new(media_type: impl Into<String>, bytes: &[u8]) -> Self {
+        Self { media_type: media_type.into(), data: base64::engine::general_purpose::STANDARD.encode(bytes) }
+    }
+
+    /// The image as a `data:` URL, the form OpenAI-style APIs take.
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+/// A single turn in a conversation sent to `LLMClient::generate_chat`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String,
+    /// Images shown after `content`; only user messages carry them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageAttachment>,
+}
+
+impl ChatMessage {
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self { role, content: content.into(), images: Vec::new() }
+    }
+
+    pub fn with_images(mut self, images: Vec<ImageAttachment>) -> Self {
+        self.images = images;
+        self
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::User, content)
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(ChatRole::Assistant, content)
+    }
+}
+
+/// Flattens a conversation into a single prompt for clients without native chat support. Images
+/// are dropped.
+pub fn flatten_messages(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .map(|m| format!("[{}]\n{}", m.role.as_str(), m.content))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// A tool the model may call, described by a JSON schema for its arguments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// A native tool call returned by the model: the tool's name and its JSON arguments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// Sampling settings sent with a request. Unset fields keep the provider's defaults (e.g. OpenAI's
+/// low temperature, Claude's 4096-token limit).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+}
+
+impl GenerationParams {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Parses `temperature=0.7,max_tokens=2048,top_p=0.9`; any subset, in any order. An empty string
+/// leaves everything at the provider's defaults.
+impl FromStr for GenerationParams {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = Self::default();
+        for setting in s.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value in '{}'", setting))?;
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = |e: &dyn fmt::Display| format!("invalid {} '{}': {}", key, value, e);
+            match key {
+                "temperature" => {
+                    let temperature: f32 = value.parse().map_err(|e| invalid(&e))?;
+                    if !(0.0..=2.0).contains(&temperature) {
+                        return Err(invalid(&"must be between 0 and 2"));
+                    }
+                    params.temperature = Some(temperature);
+                }
+                "max_tokens" => {
+                    let max_tokens: u32 = value.parse().map_err(|e| invalid(&e))?;
+                    if max_tokens == 0 {
+                        return Err(invalid(&"must be positive"));
+                    }
+                    params.max_tokens = Some(max_tokens);
+                }
+                "top_p" => {
+                    let top_p: f32 = value.parse().map_err(|e| invalid(&e))?;
+                    if !(0.0..=1.0).contains(&top_p) {
+                        return Err(invalid(&"must be between 0 and 1"));
+                    }
+                    params.top_p = Some(top_p);
+                }
+                other => return Err(format!("unknown generation parameter '{}' (expected temperature, max_tokens or top_p)", other)),
+            }
+        }
+        Ok(params)
+    }
+}
+
+impl fmt::Display for GenerationParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut settings = Vec::new();
+        if let Some(temperature) = self.temperature {
+            settings.push(format!("temperature={}", temperature));
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            settings.push(format!("max_tokens={}", max_tokens));
+        }
+        if let Some(top_p) = self.top_p {
+            settings.push(format!("top_p={}", top_p));
+        }
+        write!(f, "{}", settings.join(","))
+    }
+}
+
+/// Generation parameters for each agent role: the planner, the coder, the reasoner that makes
+/// tool decisions, reviews and checks, and the summarizer of history, tool output and commits.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RoleParams {
+    pub planner: GenerationParams,
+    pub coder: GenerationParams,
+    pub reasoner: GenerationParams,
+    pub summarizer: GenerationParams,
+}
+
 pub struct ModelInfo {
     pub name: String,
     pub input_cost_per_token: f64,
     pub output_cost_per_token: f64,
 }
 
+/// Receives the pieces of a streamed answer as they arrive (see `LLMClient::generate_stream`).
+pub type OnText<'a> = dyn Fn(&str) + Send + Sync + 'a;
+
 #[async_trait]
 pub trait LLMClient: Send + Sync {
-    async fn generate(&self, prompt: &str) -> Result<AIResponse, AgentError>;
-    async fn generate_json(&self, prompt: &str) -> Result<AIResponse, AgentError> {
-        self.generate(prompt).await
+    /// Every request carries the `GenerationParams` of the role making it.
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError>;
+    /// Sends a multi-turn conversation. Providers override this to map roles to their native
+    /// format; the default flattens the conversation into a single prompt.
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate(&flatten_messages(messages), params).await
+    }
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate(prompt, params).await
+    }
+    /// Like `generate_json`, for a conversation, typically a system prompt and the request.
+    /// Providers with system messages override this; the default flattens the conversation.
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_json(&flatten_messages(messages), params).await
+    }
+    /// Like `generate_chat`, passing the answer's text to `on_text` piece by piece as it arrives.
+    /// Providers that stream override this; the default passes the whole answer once it is done.
+    async fn generate_stream(&self, messages: &[ChatMessage], params: &GenerationParams, on_text: &OnText<'_>) -> Result<AIResponse, AgentError> {
+        let response = self.generate_chat(messages, params).await?;
+        on_text(&response.content);
+        Ok(response)
+    }
+    /// Asks the model to answer by calling one of `tools`. Providers with native tool calling
+    /// override this; the default sends a JSON-mode request and returns no tool call, leaving the
+    /// caller to parse the JSON text.
+    async fn generate_with_tools(&self, prompt: &str, _tools: &[ToolDefinition], params: &GenerationParams) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
+        Ok((self.generate_json(prompt, params).await?, None))
+    }
+    /// Models the provider offers to this client's credentials. Providers without a model-list
+    /// endpoint keep the default, which reports that listing is unsupported.
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        Err(AgentError::LLMError("this provider cannot list its models".to_string()))
     }
     async fn get_model_info(&self) -> ModelInfo;
     fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64;
 }
 
-#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ListedModel>,
+}
+
+#[derive(Deserialize)]
+struct ListedModel {
+    id: String,
+}
+
+/// Sends a prepared model-list request and reads an OpenAI-style `{"data": [{"id": ...}]}` reply,
+/// the format shared by most providers.
+async fn fetch_model_ids(request: reqwest::RequestBuilder, provider: &str) -> Result<Vec<String>, AgentError> {
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        let error_body = response.text().await?;
+        return Err(AgentError::LLMError(format!("{} API Error: {}", provider, error_body)));
+    }
+    let list: ModelList = response.json().await?;
+    let mut ids: Vec<String> = list.data.into_iter().map(|model| model.id).collect();
+    ids.sort();
+    Ok(ids)
+}
+
+/// Reads a streamed response body line by line as it arrives, passing each non-empty line to
+/// `on_line`. Streaming APIs send one JSON event per line (Ollama) or per `data:` line (SSE).
+async fn for_each_line(mut response: reqwest::Response, mut on_line: impl FnMut(&str) -> Result<(), AgentError>) -> Result<(), AgentError> {
+    let mut pending: Vec<u8> = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        pending.extend_from_slice(&chunk);
+        while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                on_line(line.trim())?;
+            }
+        }
+    }
+    let rest = String::from_utf8_lossy(&pending);
+    if !rest.trim().is_empty() {
+        on_line(rest.trim())?;
+    }
+    Ok(())
+}
+
+/// How long `model_available` waits for a provider to list its models.
+pub const MODEL_LIST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Whether `client`'s model is among those its provider lists. A model without a tag also matches
+/// its `:latest` tag, as Ollama names them. Errors when the models cannot be listed within
+/// `MODEL_LIST_TIMEOUT`.
+pub async fn model_available(client: &dyn LLMClient) -> Result<bool, AgentError> {
+    model_available_within(client, MODEL_LIST_TIMEOUT).await
+}
+
+/// `model_available`, waiting at most `timeout` for the list.
+pub async fn model_available_within(client: &dyn LLMClient, timeout: Duration) -> Result<bool, AgentError> {
+    let name = client.get_model_info().await.name;
+    let latest = format!("{}:latest", name);
+    let models = tokio::time::timeout(timeout, client.list_models())
+        .await
+        .map_err(|_| AgentError::LLMError(format!("the models were not listed within {}s", timeout.as_secs_f64())))??;
+    Ok(models.iter().any(|model| *model == name || *model == latest))
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq, Hash)]
 pub enum LLMProvider {
     OpenAI,
     Gemini,
     Claude,
     DeepSeek,
+    Mistral,
+    Groq,
     Ollama,
+    /// Replays the responses in `AGENT_MOCK_SCRIPT`, for running offline.
+    Mock,
+}
+
+impl LLMProvider {
+    /// Whether the provider works without the internet: Ollama runs models on this machine (or the
+    /// local network) and the mock provider replays a script.
+    pub fn is_local(&self) -> bool {
+        matches!(self, LLMProvider::Ollama | LLMProvider::Mock)
+    }
 }
 
 impl fmt::Display for LLMProvider {
@@ -53,7 +360,67 @@ impl fmt::Display for LLMProvider {
             LLMProvider::Gemini => write!(f, "Gemini"),
             LLMProvider::Claude => write!(f, "Claude"),
             LLMProvider::DeepSeek => write!(f, "DeepSeek"),
+            LLMProvider::Mistral => write!(f, "Mistral"),
+            LLMProvider::Groq => write!(f, "Groq"),
             LLMProvider::Ollama => write!(f, "Ollama"),
+            LLMProvider::Mock => write!(f, "Mock"),
+        }
+    }
+}
+
+/// A provider plus an optional model, written `provider` or `provider:model`
+/// (e.g. `openai:gpt-4o-mini`). Without a model the provider's configured default is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelSpec {
+    pub provider: LLMProvider,
+    pub model: Option<String>,
+}
+
+impl ModelSpec {
+    pub fn new(provider: LLMProvider, model: Option<String>) -> Self {
+        Self { provider, model }
+    }
+
+    /// This spec if its provider is local, else Ollama with its configured model; what offline
+    /// mode runs a role on.
+    pub fn local(self) -> Self {
+        if self.provider.is_local() { self } else { LLMProvider::Ollama.into() }
+    }
+}
+
+impl From<LLMProvider> for ModelSpec {
+    fn from(provider: LLMProvider) -> Self {
+        Self::new(provider, None)
+    }
+}
+
+impl FromStr for ModelSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (provider, model) = match s.trim().split_once(':') {
+            Some((provider, model)) => (provider, Some(model.trim())),
+            None => (s.trim(), None),
+        };
+        // Accept both the CLI spelling ("open-ai") and the plain one ("openai").
+        let wanted = provider.trim().replace('-', "").to_lowercase();
+        let provider = LLMProvider::value_variants()
+            .iter()
+            .copied()
+            .find(|p| p.to_string().to_lowercase() == wanted)
+            .ok_or_else(|| format!("unknown provider '{}' in '{}' (expected provider or provider:model)", provider, s))?;
+        match model {
+            Some("") => Err(format!("missing model name after ':' in '{}'", s)),
+            model => Ok(Self::new(provider, model.map(str::to_string))),
+        }
+    }
+}
+
+impl fmt::Display for ModelSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.model {
+            Some(model) => write!(f, "{}:{}", self.provider, model),
+            None => write!(f, "{}", self.provider),
         }
     }
 }
@@ -62,25 +429,197 @@ pub fn create_llm_client(
     provider: LLMProvider,
     config: Arc<AppConfig>,
 ) -> Result<Arc<dyn LLMClient>, AgentError> {
+    create_llm_client_for(&ModelSpec::from(provider), config)
+}
+
+/// Like `create_llm_client`, but the spec's model (if any) overrides the provider's configured model.
+/// In offline mode, specs for providers that are not local are replaced by Ollama.
+pub fn create_llm_client_for(
+    spec: &ModelSpec,
+    config: Arc<AppConfig>,
+) -> Result<Arc<dyn LLMClient>, AgentError> {
+    let spec = &if config.offline { spec.clone().local() } else { spec.clone() };
+    let mut inner = create_provider_client(spec, config.clone())?;
+    if let Some(limit) = rate_limit::RateLimit::find(&config.rate_limits, spec.provider) {
+        inner = rate_limit::rate_limited(inner, spec.provider, rate_limit::shared_limiter(spec.provider, limit));
+    }
+    Ok(Arc::new(ContextWindowCheck { inner, provider: spec.provider }))
+}
+
+fn create_provider_client(
+    spec: &ModelSpec,
+    config: Arc<AppConfig>,
+) -> Result<Arc<dyn LLMClient>, AgentError> {
+    let provider = spec.provider;
+    let model = spec.model.clone();
+    let pricing = PricingTable::load(config.pricing_file.as_deref().map(std::path::Path::new))?;
+    let http_client = || http::shared_client(&config);
     match provider {
         LLMProvider::OpenAI => {
             let api_key = config.openai_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("OpenAI".to_string()))?;
-            Ok(Arc::new(openai::OpenAIClient::new(api_key, config.openai_model.clone())))
+            Ok(Arc::new(openai::OpenAIClient::new(api_key, model.or_else(|| config.openai_model.clone()), &pricing).with_http_client(http_client()?)))
         }
         LLMProvider::Gemini => {
             let api_key = config.google_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("Google Gemini".to_string()))?;
-            Ok(Arc::new(gemini::GeminiClient::new(api_key, config.google_model.clone())))
+            Ok(Arc::new(gemini::GeminiClient::new(api_key, model.or_else(|| config.google_model.clone()), &pricing).with_http_client(http_client()?)))
         }
         LLMProvider::Claude => {
             let api_key = config.anthropic_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("Anthropic Claude".to_string()))?;
-            Ok(Arc::new(claude::ClaudeClient::new(api_key, config.anthropic_model.clone())))
+            Ok(Arc::new(claude::ClaudeClient::new(api_key, model.or_else(|| config.anthropic_model.clone()), &pricing).with_http_client(http_client()?)))
         }
         LLMProvider::DeepSeek => {
             let api_key = config.deepseek_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("DeepSeek".to_string()))?;
-            Ok(Arc::new(deepseek::DeepSeekClient::new(api_key, config.deepseek_model.clone())))
+            Ok(Arc::new(deepseek::DeepSeekClient::new(api_key, model.or_else(|| config.deepseek_model.clone()), &pricing).with_http_client(http_client()?)))
+        }
+        LLMProvider::Mistral => {
+            let api_key = config.mistral_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("Mistral".to_string()))?;
+            Ok(Arc::new(mistral::MistralClient::new(api_key, model.or_else(|| config.mistral_model.clone()), &pricing).with_http_client(http_client()?)))
+        }
+        LLMProvider::Groq => {
+            let api_key = config.groq_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("Groq".to_string()))?;
+            Ok(Arc::new(groq::GroqClient::new(api_key, model.or_else(|| config.groq_model.clone()), &pricing).with_http_client(http_client()?)))
         }
         LLMProvider::Ollama => {
-            Ok(Arc::new(ollama::OllamaClient::new(&config.ollama_base_url, &config.ollama_model)))
+            let model = model.unwrap_or_else(|| config.ollama_model.clone());
+            Ok(Arc::new(ollama::OllamaClient::new(&config.ollama_base_url, &model, &pricing).with_http_client(http_client()?)))
+        }
+        LLMProvider::Mock => {
+            let path = config.mock_script.as_deref().ok_or_else(|| AgentError::ConfigError("The mock provider needs a script: set AGENT_MOCK_SCRIPT or pass --mock-script".to_string()))?;
+            let responses = mock::load_script(std::path::Path::new(path))?;
+            Ok(Arc::new(mock::MockClient::new(model.unwrap_or_else(|| mock::DEFAULT_MOCK_MODEL.to_string()), responses)))
         }
     }
 }
+
+/// Logs a warning before a prompt that will not fit the model's context window is sent, so the
+/// cause is clear when the provider then rejects it with an opaque error.
+struct ContextWindowCheck {
+    inner: Arc<dyn LLMClient>,
+    provider: LLMProvider,
+}
+
+impl ContextWindowCheck {
+    async fn check(&self, prompt: &str) {
+        let model = self.inner.get_model_info().await.name;
+        if let Some(overflow) = tokens::prompt_overflow(self.provider, &model, prompt) {
+            warn!("{}", overflow);
+        }
+    }
+}
+
+#[async_trait]
+impl LLMClient for ContextWindowCheck {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.check(prompt).await;
+        self.inner.generate(prompt, params).await
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.check(&flatten_messages(messages)).await;
+        self.inner.generate_chat(messages, params).await
+    }
+
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.check(prompt).await;
+        self.inner.generate_json(prompt, params).await
+    }
+
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.check(&flatten_messages(messages)).await;
+        self.inner.generate_json_chat(messages, params).await
+    }
+
+    async fn generate_stream(&self, messages: &[ChatMessage], params: &GenerationParams, on_text: &OnText<'_>) -> Result<AIResponse, AgentError> {
+        self.check(&flatten_messages(messages)).await;
+        self.inner.generate_stream(messages, params, on_text).await
+    }
+
+    async fn generate_with_tools(&self, prompt: &str, tools: &[ToolDefinition], params: &GenerationParams) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
+        self.check(prompt).await;
+        self.inner.generate_with_tools(prompt, tools, params).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        self.inner.list_models().await
+    }
+
+    async fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info().await
+    }
+
+    fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        self.inner.calculate_cost(input_tokens, output_tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_message_constructors() {
+        assert_eq!(ChatMessage::system("s").role, ChatRole::System);
+        assert_eq!(ChatMessage::user("u").role, ChatRole::User);
+        assert_eq!(ChatMessage::assistant("a").role, ChatRole::Assistant);
+        assert_eq!(ChatMessage::user("hello").content, "hello");
+    }
+
+    #[test]
+    fn test_chat_role_serialization() {
+        let json = serde_json::to_string(&ChatMessage::system("be brief")).unwrap();
+        assert_eq!(json, r#"{"role":"system","content":"be brief"}"#);
+    }
+
+    #[test]
+    fn test_model_spec_parsing() {
+        assert_eq!("openai".parse::<ModelSpec>().unwrap(), ModelSpec::new(LLMProvider::OpenAI, None));
+        assert_eq!("deep-seek".parse::<ModelSpec>().unwrap(), ModelSpec::new(LLMProvider::DeepSeek, None));
+        assert_eq!(
+            "Claude:claude-3-haiku-20240307".parse::<ModelSpec>().unwrap(),
+            ModelSpec::new(LLMProvider::Claude, Some("claude-3-haiku-20240307".to_string()))
+        );
+        // Only the first ':' separates provider and model (Ollama tags contain one).
+        assert_eq!("ollama:llama3:8b".parse::<ModelSpec>().unwrap().model.as_deref(), Some("llama3:8b"));
+        assert!("mystery:model".parse::<ModelSpec>().is_err());
+        assert!("openai:".parse::<ModelSpec>().is_err());
+    }
+
+    #[test]
+    fn test_model_spec_display() {
+        assert_eq!(ModelSpec::new(LLMProvider::DeepSeek, None).to_string(), "DeepSeek");
+        assert_eq!(ModelSpec::new(LLMProvider::OpenAI, Some("gpt-4o-mini".to_string())).to_string(), "OpenAI:gpt-4o-mini");
+    }
+
+    #[tokio::test]
+    async fn test_offline_mode_runs_every_spec_on_a_local_provider() {
+        assert_eq!(ModelSpec::new(LLMProvider::Claude, Some("claude-3-haiku-20240307".to_string())).local(), ModelSpec::from(LLMProvider::Ollama));
+        let mock = ModelSpec::new(LLMProvider::Mock, Some("scripted".to_string()));
+        assert_eq!(mock.clone().local(), mock);
+
+        // No OpenAI key is needed: the Ollama client is created instead.
+        let config = Arc::new(AppConfig { offline: true, ..AppConfig::default() });
+        let client = create_llm_client_for(&"openai:gpt-4o".parse().unwrap(), config.clone()).unwrap();
+        assert_eq!(client.get_model_info().await.name, config.ollama_model);
+    }
+
+    #[test]
+    fn test_generation_params_parsing() {
+        let params: GenerationParams = "temperature=0.7, max_tokens=2048,top_p=0.9".parse().unwrap();
+        assert_eq!(params, GenerationParams { temperature: Some(0.7), max_tokens: Some(2048), top_p: Some(0.9) });
+        assert_eq!(params.to_string(), "temperature=0.7,max_tokens=2048,top_p=0.9");
+        assert!("".parse::<GenerationParams>().unwrap().is_default());
+        assert_eq!("max_tokens=100".parse::<GenerationParams>().unwrap().temperature, None);
+        assert!("temperature=3".parse::<GenerationParams>().is_err());
+        assert!("top_p=-0.1".parse::<GenerationParams>().is_err());
+        assert!("max_tokens=0".parse::<GenerationParams>().is_err());
+        assert!("seed=1".parse::<GenerationParams>().is_err());
+        assert!("temperature".parse::<GenerationParams>().is_err());
+    }
+
+    #[test]
+    fn test_flatten_messages() {
+        let messages = vec![ChatMessage::system("rules"), ChatMessage::user("question")];
+        let flattened = flatten_messages(&messages);
+        assert_eq!(flattened, "[system]\nrules\n\n[user]\nquestion");
+    }
+}
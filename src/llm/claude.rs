@@ -2,26 +2,81 @@ use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use super::{LLMClient, AIResponse, ModelInfo};
+use super::{pricing::{ModelPrice, PricingTable}, GenerationParams, LLMClient, AIResponse, ChatMessage, ChatRole, ModelInfo, ToolCall, ToolDefinition};
 use crate::error::AgentError;
 
+/// The Messages API requires a token limit; this one is used unless the role sets `max_tokens`.
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
 pub struct ClaudeClient {
     api_key: String,
     http_client: Client,
     model: String,
+    pricing: ModelPrice,
 }
 
 #[derive(Serialize)]
 struct ClaudeRequest<'a> {
     model: &'a str,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
     messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ClaudeTool<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ClaudeTool<'a> {
+    name: &'a str,
+    description: &'a str,
+    input_schema: &'a serde_json::Value,
 }
 
 #[derive(Serialize)]
 struct Message<'a> {
     role: &'a str,
-    content: &'a str,
+    content: MessageContent<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum MessageContent<'a> {
+    Text(&'a str),
+    /// The text followed by the message's images.
+    Blocks(Vec<ContentBlock<'a>>),
+}
+
+impl<'a> MessageContent<'a> {
+    fn of(message: &'a ChatMessage) -> Self {
+        if message.images.is_empty() {
+            return MessageContent::Text(&message.content);
+        }
+        let images = message.images.iter().map(|image| ContentBlock::Image {
+            source: ImageSource { r#type: "base64", media_type: &image.media_type, data: &image.data },
+        });
+        MessageContent::Blocks(std::iter::once(ContentBlock::Text { text: &message.content }).chain(images).collect())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock<'a> {
+    Text { text: &'a str },
+    Image { source: ImageSource<'a> },
+}
+
+#[derive(Serialize)]
+struct ImageSource<'a> {
+    r#type: &'a str,
+    media_type: &'a str,
+    data: &'a str,
 }
 
 #[derive(Deserialize)]
@@ -31,8 +86,12 @@ struct ClaudeResponse {
 }
 
 #[derive(Deserialize)]
-struct ResponseContent {
-    text: String,
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponseContent {
+    Text { text: String },
+    ToolUse { name: String, input: serde_json::Value },
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Deserialize)]
@@ -42,50 +101,108 @@ struct Usage {
 }
 
 impl ClaudeClient {
-    pub fn new(api_key: String, model: Option<String>) -> Self {
+    pub fn new(api_key: String, model: Option<String>, pricing: &PricingTable) -> Self {
+        let model = model.unwrap_or_else(|| "claude-3-opus-20240229".to_string());
         Self {
             api_key,
             http_client: Client::new(),
-            model: model.unwrap_or_else(|| "claude-3-opus-20240229".to_string()),
+            pricing: pricing.price_for("claude", &model),
+            model,
         }
     }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
 }
 
 #[async_trait]
 impl LLMClient for ClaudeClient {
-    async fn generate(&self, prompt: &str) -> Result<AIResponse, AgentError> {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_chat(&[ChatMessage::user(prompt)], params).await
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        // Claude takes the system prompt as a top-level field rather than a message.
+        let system_parts: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == ChatRole::System)
+            .map(|m| m.content.as_str())
+            .collect();
+        let request_payload = ClaudeRequest {
+            model: &self.model,
+            max_tokens: params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            temperature: params.temperature,
+            top_p: params.top_p,
+            system: if system_parts.is_empty() { None } else { Some(system_parts.join("\n\n")) },
+            messages: messages
+                .iter()
+                .filter(|m| m.role != ChatRole::System)
+                .map(|m| Message { role: m.role.as_str(), content: MessageContent::of(m) })
+                .collect(),
+            tools: Vec::new(),
+            tool_choice: None,
+        };
+        let (response, _) = self.send_request(request_payload).await?;
+        if response.content.is_empty() {
+            return Err(AgentError::ResponseParseError("No content in Claude response".to_string()));
+        }
+        Ok(response)
+    }
+
+    async fn generate_with_tools(&self, prompt: &str, tools: &[ToolDefinition], params: &GenerationParams) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
         let request_payload = ClaudeRequest {
             model: &self.model,
-            max_tokens: 4096,
-            messages: vec![Message { role: "user", content: prompt }],
+            max_tokens: params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            temperature: params.temperature,
+            top_p: params.top_p,
+            system: None,
+            messages: vec![Message { role: "user", content: MessageContent::Text(prompt) }],
+            tools: tools
+                .iter()
+                .map(|t| ClaudeTool { name: &t.name, description: &t.description, input_schema: &t.parameters })
+                .collect(),
+            // "any" makes Claude answer with a tool call instead of prose.
+            tool_choice: Some(serde_json::json!({ "type": "any" })),
         };
         self.send_request(request_payload).await
     }
 
-    async fn generate_json(&self, prompt: &str) -> Result<AIResponse, AgentError> {
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
         // Claude API does not have a direct JSON mode like OpenAI.
         // We'll just call the regular generate and hope for JSON in the response.
-        self.generate(prompt).await
+        self.generate(prompt, params).await
+    }
+
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_chat(messages, params).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        let request = self
+            .http_client
+            .get("https://api.anthropic.com/v1/models?limit=1000")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01");
+        super::fetch_model_ids(request, "Claude").await
     }
 
     async fn get_model_info(&self) -> ModelInfo {
-        // These are example costs for claude-3-opus. Real costs should be fetched or configured.
         ModelInfo {
             name: self.model.clone(),
-            input_cost_per_token: 0.000015, // Example: $15 per 1M tokens
-            output_cost_per_token: 0.000075, // Example: $75 per 1M tokens
+            input_cost_per_token: self.pricing.input_cost_per_token(),
+            output_cost_per_token: self.pricing.output_cost_per_token(),
         }
     }
 
     fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
-        let model_info = futures::executor::block_on(self.get_model_info());
-        (input_tokens as f64 * model_info.input_cost_per_token) +
-        (output_tokens as f64 * model_info.output_cost_per_token)
+        self.pricing.cost(input_tokens, output_tokens)
     }
 }
 
 impl ClaudeClient {
-    async fn send_request(&self, payload: ClaudeRequest<'_>) -> Result<AIResponse, AgentError> {
+    async fn send_request(&self, payload: ClaudeRequest<'_>) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
         let response = self
             .http_client
             .post("https://api.anthropic.com/v1/messages")
@@ -101,26 +218,114 @@ impl ClaudeClient {
         }
 
         let response_data: ClaudeResponse = response.json().await?;
+        Ok(self.parse_response(response_data))
+    }
 
-        let content = response_data
-            .content
-            .into_iter()
-            .next()
-            .map(|c| c.text)
-            .ok_or_else(|| AgentError::ResponseParseError("No content in Claude response".to_string()))?;
+    /// Joins the text blocks into `content` and returns the first `tool_use` block, if any.
+    fn parse_response(&self, response_data: ClaudeResponse) -> (AIResponse, Option<ToolCall>) {
+        let mut texts = Vec::new();
+        let mut tool_call = None;
+        for block in response_data.content {
+            match block {
+                ResponseContent::Text { text } => texts.push(text),
+                ResponseContent::ToolUse { name, input } if tool_call.is_none() => {
+                    tool_call = Some(ToolCall { name, arguments: input });
+                }
+                _ => {}
+            }
+        }
 
         // Parse actual token usage from Claude API response
         let input_tokens = response_data.usage.input_tokens;
         let output_tokens = response_data.usage.output_tokens;
         let cost = self.calculate_cost(input_tokens, output_tokens);
 
-        Ok(AIResponse {
-            content,
+        let response = AIResponse {
+            content: texts.join("\n"),
             input_tokens,
             output_tokens,
+            reasoning_tokens: 0,
             cost,
             model: self.model.clone(),
             provider: "Claude".to_string(),
-        })
+        };
+        (response, tool_call)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> ClaudeClient {
+        ClaudeClient::new("test-key".to_string(), None, &PricingTable::bundled())
+    }
+
+    #[test]
+    fn test_parse_response_with_tool_use() {
+        let data: ClaudeResponse = serde_json::from_value(serde_json::json!({
+            "content": [
+                { "type": "text", "text": "I'll list the files first." },
+                { "type": "tool_use", "id": "toolu_01", "name": "ListFiles", "input": { "thought": "Look around", "path": "." } }
+            ],
+            "usage": { "input_tokens": 100, "output_tokens": 20 }
+        }))
+        .unwrap();
+
+        let (response, tool_call) = client().parse_response(data);
+        assert_eq!(response.content, "I'll list the files first.");
+        assert_eq!(response.input_tokens, 100);
+        let call = tool_call.unwrap();
+        assert_eq!(call.name, "ListFiles");
+        assert_eq!(call.arguments["path"], ".");
+    }
+
+    #[test]
+    fn test_parse_response_text_only() {
+        let data: ClaudeResponse = serde_json::from_value(serde_json::json!({
+            "content": [{ "type": "text", "text": "Hello" }],
+            "usage": { "input_tokens": 1, "output_tokens": 1 }
+        }))
+        .unwrap();
+
+        let (response, tool_call) = client().parse_response(data);
+        assert_eq!(response.content, "Hello");
+        assert!(tool_call.is_none());
+    }
+
+    #[test]
+    fn test_tool_request_serialization() {
+        let tools = crate::tools::tool_definitions();
+        let payload = ClaudeRequest {
+            model: "claude-3-opus-20240229",
+            max_tokens: 10,
+            temperature: Some(0.3),
+            top_p: None,
+            system: None,
+            messages: vec![Message { role: "user", content: MessageContent::Text("hi") }],
+            tools: tools.iter().map(|t| ClaudeTool { name: &t.name, description: &t.description, input_schema: &t.parameters }).collect(),
+            tool_choice: Some(serde_json::json!({ "type": "any" })),
+        };
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["tool_choice"]["type"], "any");
+        assert!((json["temperature"].as_f64().unwrap() - 0.3).abs() < 1e-6);
+        assert!(json.get("top_p").is_none());
+        assert_eq!(json["tools"][0]["name"], "ReadFile");
+        assert!(json["tools"][0]["input_schema"]["required"].as_array().unwrap().contains(&serde_json::json!("path")));
+
+        let plain = ClaudeRequest { model: "m", max_tokens: 1, temperature: None, top_p: None, system: None, messages: vec![], tools: vec![], tool_choice: None };
+        let json = serde_json::to_value(&plain).unwrap();
+        assert!(json.get("tools").is_none());
+        assert!(json.get("tool_choice").is_none());
+    }
+
+    #[test]
+    fn test_message_with_images_serializes_as_content_blocks() {
+        let message = ChatMessage::user("What is wrong?").with_images(vec![crate::llm::ImageAttachment::new("image/png", b"png")]);
+        let json = serde_json::to_value(Message { role: "user", content: MessageContent::of(&message) }).unwrap();
+        assert_eq!(json["content"][0], serde_json::json!({ "type": "text", "text": "What is wrong?" }));
+        assert_eq!(json["content"][1], serde_json::json!({ "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "cG5n" } }));
+        let plain = ChatMessage::user("hi");
+        assert_eq!(serde_json::to_value(Message { role: "user", content: MessageContent::of(&plain) }).unwrap()["content"], "hi");
     }
 }
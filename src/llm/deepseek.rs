@@ -2,19 +2,26 @@ use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use super::{LLMClient, AIResponse, ModelInfo};
+use super::{pricing::{ModelPrice, PricingTable}, GenerationParams, LLMClient, AIResponse, ChatMessage, ModelInfo};
 use crate::error::AgentError;
 
 pub struct DeepSeekClient {
     api_key: String,
     http_client: Client,
     model: String,
+    pricing: ModelPrice,
 }
 
 #[derive(Serialize)]
 struct DeepSeekRequest<'a> {
     model: &'a str,
     messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
 }
 
 #[derive(Serialize)]
@@ -43,51 +50,69 @@ struct ResponseMessage {
 struct Usage {
     prompt_tokens: u32,
     completion_tokens: u32,
-    total_tokens: u32,
 }
 
 impl DeepSeekClient {
-    pub fn new(api_key: String, model: Option<String>) -> Self {
+    pub fn new(api_key: String, model: Option<String>, pricing: &PricingTable) -> Self {
+        let model = model.unwrap_or_else(|| "deepseek-coder".to_string());
         Self {
             api_key,
             http_client: Client::new(),
-            model: model.unwrap_or_else(|| "deepseek-coder".to_string()),
+            pricing: pricing.price_for("deepseek", &model),
+            model,
         }
     }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
 }
 
 #[async_trait]
 impl LLMClient for DeepSeekClient {
-    async fn generate(&self, prompt: &str) -> Result<AIResponse, AgentError> {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_chat(&[ChatMessage::user(prompt)], params).await
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        // DeepSeek uses the OpenAI message format, so roles map one-to-one.
         let request_payload = DeepSeekRequest {
             model: &self.model,
-            messages: vec![Message { role: "user", content: prompt }],
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
+            top_p: params.top_p,
+            messages: messages
+                .iter()
+                .map(|m| Message { role: m.role.as_str(), content: &m.content })
+                .collect(),
         };
         self.send_request(request_payload).await
     }
 
-    async fn generate_json(&self, prompt: &str) -> Result<AIResponse, AgentError> {
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
         // DeepSeek API is compatible with OpenAI's JSON mode
-        let request_payload = DeepSeekRequest {
-            model: &self.model,
-            messages: vec![Message { role: "user", content: prompt }],
-        };
-        self.send_request(request_payload).await
+        self.generate_json_chat(&[ChatMessage::user(prompt)], params).await
+    }
+
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_chat(messages, params).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        super::fetch_model_ids(self.http_client.get("https://api.deepseek.com/models").bearer_auth(&self.api_key), "DeepSeek").await
     }
 
     async fn get_model_info(&self) -> ModelInfo {
-        // These are example costs for deepseek-coder. Real costs should be fetched or configured.
         ModelInfo {
             name: self.model.clone(),
-            input_cost_per_token: 0.0000001, // Example: $0.1 per 1M tokens
-            output_cost_per_token: 0.0000001, // Example: $0.1 per 1M tokens
+            input_cost_per_token: self.pricing.input_cost_per_token(),
+            output_cost_per_token: self.pricing.output_cost_per_token(),
         }
     }
 
     fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
-        let model_info = futures::executor::block_on(self.get_model_info());
-        (input_tokens as f64 * model_info.input_cost_per_token) +
-        (output_tokens as f64 * model_info.output_cost_per_token)
+        self.pricing.cost(input_tokens, output_tokens)
     }
 }
 
@@ -123,6 +148,7 @@ impl DeepSeekClient {
             content,
             input_tokens,
             output_tokens,
+            reasoning_tokens: 0,
             cost,
             model: self.model.clone(),
             provider: "DeepSeek".to_string(),
@@ -0,0 +1,253 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::error::AgentError;
+
+pub const OPENAI_EMBEDDINGS_URL: &str = "https://api.openai.com/v1/embeddings";
+pub const GEMINI_API_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+pub const DEFAULT_OPENAI_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+pub const DEFAULT_GEMINI_EMBEDDING_MODEL: &str = "text-embedding-004";
+pub const DEFAULT_OLLAMA_EMBEDDING_MODEL: &str = "nomic-embed-text";
+
+/// Turns text into vectors whose cosine similarity reflects how related the texts are.
+#[async_trait]
+pub trait EmbeddingsClient: Send + Sync {
+    /// Identifies the vector space; vectors from different models cannot be compared.
+    fn model(&self) -> &str;
+    /// One vector per text, in the order of `texts`.
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, AgentError>;
+}
+
+/// The providers with an embeddings API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingProvider {
+    OpenAI,
+    Gemini,
+    Ollama,
+}
+
+impl EmbeddingProvider {
+    pub fn default_model(&self) -> &'static str {
+        match self {
+            EmbeddingProvider::OpenAI => DEFAULT_OPENAI_EMBEDDING_MODEL,
+            EmbeddingProvider::Gemini => DEFAULT_GEMINI_EMBEDDING_MODEL,
+            EmbeddingProvider::Ollama => DEFAULT_OLLAMA_EMBEDDING_MODEL,
+        }
+    }
+}
+
+impl fmt::Display for EmbeddingProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddingProvider::OpenAI => write!(f, "openai"),
+            EmbeddingProvider::Gemini => write!(f, "gemini"),
+            EmbeddingProvider::Ollama => write!(f, "ollama"),
+        }
+    }
+}
+
+impl FromStr for EmbeddingProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "openai" => Ok(EmbeddingProvider::OpenAI),
+            "gemini" => Ok(EmbeddingProvider::Gemini),
+            "ollama" => Ok(EmbeddingProvider::Ollama),
+            other => Err(format!("unknown embedding provider '{}' (expected openai, gemini or ollama)", other)),
+        }
+    }
+}
+
+/// Creates an embeddings client for `provider` with the API keys, Ollama URL and HTTP settings in `config`,
+/// or an Ollama client in offline mode. `model` defaults to the provider's `default_model`.
+pub fn create_embeddings_client(provider: EmbeddingProvider, model: Option<String>, config: &AppConfig) -> Result<Arc<dyn EmbeddingsClient>, AgentError> {
+    // Offline, embeddings come from Ollama with its default model.
+    let (provider, model) = match provider {
+        EmbeddingProvider::OpenAI | EmbeddingProvider::Gemini if config.offline => (EmbeddingProvider::Ollama, None),
+        _ => (provider, model),
+    };
+    let model = model.unwrap_or_else(|| provider.default_model().to_string());
+    let http_client = super::http::shared_client(config)?;
+    Ok(match provider {
+        EmbeddingProvider::OpenAI => {
+            let api_key = config.openai_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("OpenAI".to_string()))?;
+            Arc::new(OpenAIEmbeddingsClient::new(api_key, model).with_http_client(http_client))
+        }
+        EmbeddingProvider::Gemini => {
+            let api_key = config.google_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("Google Gemini".to_string()))?;
+            Arc::new(GeminiEmbeddingsClient::new(api_key, model).with_http_client(http_client))
+        }
+        EmbeddingProvider::Ollama => Arc::new(OllamaEmbeddingsClient::new(&config.ollama_base_url, model).with_http_client(http_client)),
+    })
+}
+
+async fn check_status(name: &str, response: reqwest::Response) -> Result<reqwest::Response, AgentError> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(AgentError::LLMError(format!("{} embeddings API Error: {}", name, response.text().await?)))
+    }
+}
+
+/// Fails unless the provider returned one vector per text.
+fn check_count(embeddings: Vec<Vec<f32>>, texts: &[String]) -> Result<Vec<Vec<f32>>, AgentError> {
+    if embeddings.len() != texts.len() {
+        return Err(AgentError::LLMError(format!("expected {} embeddings, got {}", texts.len(), embeddings.len())));
+    }
+    Ok(embeddings)
+}
+
+pub struct OpenAIEmbeddingsClient {
+    api_key: String,
+    model: String,
+    base_url: String,
+    http_client: Client,
+}
+
+impl OpenAIEmbeddingsClient {
+    pub fn new(api_key: String, model: String) -> Self {
+        Self::with_base_url(api_key, model, OPENAI_EMBEDDINGS_URL.to_string())
+    }
+
+    pub fn with_base_url(api_key: String, model: String, base_url: String) -> Self {
+        Self { api_key, model, base_url, http_client: Client::new() }
+    }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAIEmbeddingResponse {
+    data: Vec<OpenAIEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[async_trait]
+impl EmbeddingsClient for OpenAIEmbeddingsClient {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, AgentError> {
+        let response = self
+            .http_client
+            .post(&self.base_url)
+            .bearer_auth(&self.api_key)
+            .json(&json!({ "model": self.model, "input": texts }))
+            .send()
+            .await?;
+        let mut body: OpenAIEmbeddingResponse = check_status("OpenAI", response).await?.json().await?;
+        body.data.sort_by_key(|embedding| embedding.index);
+        check_count(body.data.into_iter().map(|embedding| embedding.embedding).collect(), texts)
+    }
+}
+
+pub struct GeminiEmbeddingsClient {
+    api_key: String,
+    model: String,
+    base_url: String,
+    http_client: Client,
+}
+
+impl GeminiEmbeddingsClient {
+    pub fn new(api_key: String, model: String) -> Self {
+        Self::with_base_url(api_key, model, GEMINI_API_URL.to_string())
+    }
+
+    /// `base_url` is the API root the `models/...` paths hang off, e.g. `GEMINI_API_URL`.
+    pub fn with_base_url(api_key: String, model: String, base_url: String) -> Self {
+        Self { api_key, model, base_url: base_url.trim_end_matches('/').to_string(), http_client: Client::new() }
+    }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct GeminiEmbeddingResponse {
+    #[serde(default)]
+    embeddings: Vec<GeminiEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct GeminiEmbedding {
+    values: Vec<f32>,
+}
+
+#[async_trait]
+impl EmbeddingsClient for GeminiEmbeddingsClient {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, AgentError> {
+        let model = format!("models/{}", self.model);
+        let requests: Vec<_> = texts.iter().map(|text| json!({ "model": model, "content": { "parts": [{ "text": text }] } })).collect();
+        let response = self
+            .http_client
+            .post(format!("{}/{}:batchEmbedContents", self.base_url, model))
+            .query(&[("key", &self.api_key)])
+            .json(&json!({ "requests": requests }))
+            .send()
+            .await?;
+        let body: GeminiEmbeddingResponse = check_status("Gemini", response).await?.json().await?;
+        check_count(body.embeddings.into_iter().map(|embedding| embedding.values).collect(), texts)
+    }
+}
+
+pub struct OllamaEmbeddingsClient {
+    base_url: String,
+    model: String,
+    http_client: Client,
+}
+
+impl OllamaEmbeddingsClient {
+    pub fn new(base_url: &str, model: String) -> Self {
+        Self { base_url: base_url.trim_end_matches('/').to_string(), model, http_client: Client::new() }
+    }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbeddingResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[async_trait]
+impl EmbeddingsClient for OllamaEmbeddingsClient {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, AgentError> {
+        let response = self
+            .http_client
+            .post(format!("{}/api/embed", self.base_url))
+            .json(&json!({ "model": self.model, "input": texts }))
+            .send()
+            .await?;
+        let body: OllamaEmbeddingResponse = check_status("Ollama", response).await?.json().await?;
+        check_count(body.embeddings, texts)
+    }
+}
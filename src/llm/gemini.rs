@@ -2,28 +2,58 @@ use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use super::{LLMClient, AIResponse, ModelInfo};
+use super::{pricing::{ModelPrice, PricingTable}, GenerationParams, LLMClient, AIResponse, ChatMessage, ChatRole, ModelInfo};
 use crate::error::AgentError;
 
 pub struct GeminiClient {
     api_key: String,
     http_client: Client,
     model: String,
+    pricing: ModelPrice,
 }
 
 #[derive(Serialize)]
 struct GeminiRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<SystemInstruction<'a>>,
     contents: Vec<Content<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GenerationConfig>,
+}
+
+#[derive(Serialize)]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct SystemInstruction<'a> {
+    parts: Vec<Part<'a>>,
 }
 
 #[derive(Serialize)]
 struct Content<'a> {
+    role: &'a str,
     parts: Vec<Part<'a>>,
 }
 
 #[derive(Serialize)]
-struct Part<'a> {
-    text: &'a str,
+#[serde(untagged)]
+enum Part<'a> {
+    Text { text: &'a str },
+    InlineData { inline_data: InlineData<'a> },
+}
+
+#[derive(Serialize)]
+struct InlineData<'a> {
+    mime_type: &'a str,
+    /// Base64-encoded.
+    data: &'a str,
 }
 
 #[derive(Deserialize)]
@@ -48,6 +78,18 @@ struct ResponsePart {
     text: String,
 }
 
+#[derive(Deserialize)]
+struct ModelList {
+    #[serde(default)]
+    models: Vec<ListedModel>,
+}
+
+#[derive(Deserialize)]
+struct ListedModel {
+    /// Prefixed with `models/`, e.g. `models/gemini-1.5-flash`.
+    name: String,
+}
+
 #[derive(Deserialize)]
 struct PromptFeedback {
     block_reason: Option<String>,
@@ -57,28 +99,57 @@ struct PromptFeedback {
 struct UsageMetadata {
     prompt_token_count: u32,
     candidates_token_count: u32,
-    total_token_count: u32,
 }
 
 impl GeminiClient {
-    pub fn new(api_key: String, model: Option<String>) -> Self {
+    pub fn new(api_key: String, model: Option<String>, pricing: &PricingTable) -> Self {
+        let model = model.unwrap_or_else(|| "gemini-1.5-flash-2.5-pro".to_string());
         Self {
             api_key,
             http_client: Client::new(),
-            model: model.unwrap_or_else(|| "gemini-1.5-flash-2.5-pro".to_string()),
+            pricing: pricing.price_for("gemini", &model),
+            model,
         }
     }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
 }
 
 #[async_trait]
 impl LLMClient for GeminiClient {
-    async fn generate(&self, prompt: &str) -> Result<AIResponse, AgentError> {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_chat(&[ChatMessage::user(prompt)], params).await
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
         let url = format!("https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}", self.model, self.api_key);
-        
+
+        // Gemini calls the assistant role "model" and takes system prompts separately.
+        let system_parts: Vec<Part> = messages
+            .iter()
+            .filter(|m| m.role == ChatRole::System)
+            .map(|m| Part::Text { text: &m.content })
+            .collect();
         let request_payload = GeminiRequest {
-            contents: vec![Content {
-                parts: vec![Part { text: prompt }],
-            }],
+            system_instruction: if system_parts.is_empty() { None } else { Some(SystemInstruction { parts: system_parts }) },
+            contents: messages
+                .iter()
+                .filter(|m| m.role != ChatRole::System)
+                .map(|m| Content {
+                    role: if m.role == ChatRole::Assistant { "model" } else { "user" },
+                    parts: std::iter::once(Part::Text { text: &m.content })
+                        .chain(m.images.iter().map(|image| Part::InlineData { inline_data: InlineData { mime_type: &image.media_type, data: &image.data } }))
+                        .collect(),
+                })
+                .collect(),
+            generation_config: (!params.is_default()).then_some(GenerationConfig {
+                temperature: params.temperature,
+                max_output_tokens: params.max_tokens,
+                top_p: params.top_p,
+            }),
         };
 
         let response = self
@@ -121,30 +192,49 @@ impl LLMClient for GeminiClient {
             content,
             input_tokens,
             output_tokens,
+            reasoning_tokens: 0,
             cost,
             model: self.model.clone(),
             provider: "Gemini".to_string(),
         })
     }
 
-    async fn generate_json(&self, prompt: &str) -> Result<AIResponse, AgentError> {
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
         // Gemini API does not have a direct JSON mode like OpenAI.
         // We'll just call the regular generate and hope for JSON in the response.
-        self.generate(prompt).await
+        self.generate(prompt, params).await
+    }
+
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_chat(messages, params).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        let url = format!("https://generativelanguage.googleapis.com/v1beta/models?pageSize=1000&key={}", self.api_key);
+        let response = self.http_client.get(&url).send().await?;
+        if !response.status().is_success() {
+            let error_body = response.text().await?;
+            return Err(AgentError::LLMError(format!("Gemini API Error: {}", error_body)));
+        }
+        let list: ModelList = response.json().await?;
+        let mut names: Vec<String> = list
+            .models
+            .into_iter()
+            .map(|model| model.name.strip_prefix("models/").map(str::to_string).unwrap_or(model.name))
+            .collect();
+        names.sort();
+        Ok(names)
     }
 
     async fn get_model_info(&self) -> ModelInfo {
-        // These are example costs for gemini-1.5-flash-2.5-pro. Real costs should be fetched or configured.
         ModelInfo {
             name: self.model.clone(),
-            input_cost_per_token: 0.00000035, // Example: $0.35 per 1M tokens
-            output_cost_per_token: 0.00000105, // Example: $1.05 per 1M tokens
+            input_cost_per_token: self.pricing.input_cost_per_token(),
+            output_cost_per_token: self.pricing.output_cost_per_token(),
         }
     }
 
     fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
-        let model_info = futures::executor::block_on(self.get_model_info());
-        (input_tokens as f64 * model_info.input_cost_per_token) +
-        (output_tokens as f64 * model_info.output_cost_per_token)
+        self.pricing.cost(input_tokens, output_tokens)
     }
 }
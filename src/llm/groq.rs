@@ -0,0 +1,196 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{pricing::{ModelPrice, PricingTable}, GenerationParams, LLMClient, AIResponse, ChatMessage, ModelInfo};
+use crate::error::AgentError;
+
+/// Groq refuses JSON-mode requests whose messages never mention JSON, so one is added when missing.
+const JSON_INSTRUCTION: &str = "Respond with a single valid JSON object.";
+
+pub struct GroqClient {
+    api_key: String,
+    http_client: Client,
+    model: String,
+    pricing: ModelPrice,
+}
+
+#[derive(Serialize)]
+struct GroqRequest<'a> {
+    model: &'a str,
+    messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat<'a>>,
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct ResponseFormat<'a> {
+    r#type: &'a str,
+}
+
+#[derive(Deserialize)]
+struct GroqResponse {
+    choices: Vec<Choice>,
+    usage: Usage,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    // Groq returns `null` content instead of an empty string for some finish reasons.
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+impl GroqClient {
+    pub fn new(api_key: String, model: Option<String>, pricing: &PricingTable) -> Self {
+        let model = model.unwrap_or_else(|| "llama-3.3-70b-versatile".to_string());
+        Self {
+            api_key,
+            http_client: Client::new(),
+            pricing: pricing.price_for("groq", &model),
+            model,
+        }
+    }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    fn json_messages(prompt: &str) -> Vec<Message<'_>> {
+        let mut messages = Vec::with_capacity(2);
+        if !prompt.to_lowercase().contains("json") {
+            messages.push(Message { role: "system", content: JSON_INSTRUCTION });
+        }
+        messages.push(Message { role: "user", content: prompt });
+        messages
+    }
+}
+
+#[async_trait]
+impl LLMClient for GroqClient {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_chat(&[ChatMessage::user(prompt)], params).await
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let request_payload = GroqRequest {
+            model: &self.model,
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
+            top_p: params.top_p,
+            messages: messages
+                .iter()
+                .map(|m| Message { role: m.role.as_str(), content: &m.content })
+                .collect(),
+            response_format: None,
+        };
+        self.send_request(request_payload).await
+    }
+
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let request_payload = GroqRequest {
+            model: &self.model,
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
+            top_p: params.top_p,
+            messages: Self::json_messages(prompt),
+            response_format: Some(ResponseFormat { r#type: "json_object" }),
+        };
+        self.send_request(request_payload).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        super::fetch_model_ids(self.http_client.get("https://api.groq.com/openai/v1/models").bearer_auth(&self.api_key), "Groq").await
+    }
+
+    async fn get_model_info(&self) -> ModelInfo {
+        ModelInfo {
+            name: self.model.clone(),
+            input_cost_per_token: self.pricing.input_cost_per_token(),
+            output_cost_per_token: self.pricing.output_cost_per_token(),
+        }
+    }
+
+    fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        self.pricing.cost(input_tokens, output_tokens)
+    }
+}
+
+impl GroqClient {
+    async fn send_request(&self, payload: GroqRequest<'_>) -> Result<AIResponse, AgentError> {
+        let response = self
+            .http_client
+            .post("https://api.groq.com/openai/v1/chat/completions")
+            .bearer_auth(&self.api_key)
+            .json(&payload)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_body = response.text().await?;
+            return Err(AgentError::LLMError(format!("Groq API Error: {}", error_body)));
+        }
+
+        let response_data: GroqResponse = response.json().await?;
+
+        let content = response_data
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .ok_or_else(|| AgentError::ResponseParseError("No content in Groq response".to_string()))?;
+
+        let input_tokens = response_data.usage.prompt_tokens;
+        let output_tokens = response_data.usage.completion_tokens;
+        let cost = self.calculate_cost(input_tokens, output_tokens);
+
+        Ok(AIResponse {
+            content,
+            input_tokens,
+            output_tokens,
+            reasoning_tokens: 0,
+            cost,
+            model: self.model.clone(),
+            provider: "Groq".to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_messages_mention_json() {
+        let messages = GroqClient::json_messages("List three colors as {\"colors\": [...]}");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, "system");
+
+        let messages = GroqClient::json_messages("Answer in JSON");
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].role, "user");
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, Proxy};
+
+use crate::{config::AppConfig, error::AgentError};
+
+/// How the HTTP client model providers send their requests through is set up: `AGENT_HTTP_TIMEOUT`,
+/// `AGENT_HTTP_CONNECT_TIMEOUT`, `AGENT_HTTP_KEEPALIVE`, `AGENT_HTTP_PROXY`, `AGENT_HTTPS_PROXY`
+/// and `AGENT_CA_CERTS`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct HttpSettings {
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub keepalive: Option<Duration>,
+    pub http_proxy: Option<String>,
+    pub https_proxy: Option<String>,
+    pub ca_certs: Vec<String>,
+}
+
+impl HttpSettings {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            timeout: config.http_timeout_secs.map(Duration::from_secs),
+            connect_timeout: config.http_connect_timeout_secs.map(Duration::from_secs),
+            keepalive: config.http_keepalive_secs.map(Duration::from_secs),
+            http_proxy: config.http_proxy.clone(),
+            https_proxy: config.https_proxy.clone(),
+            ca_certs: config.ca_certs.clone(),
+        }
+    }
+
+    /// Builds a client with these settings. Fails on a proxy URL that does not parse or a
+    /// certificate file that cannot be read as PEM.
+    pub fn build(&self) -> Result<Client, AgentError> {
+        let mut builder = Client::builder();
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(keepalive) = self.keepalive {
+            builder = builder.pool_idle_timeout(keepalive).tcp_keepalive(keepalive);
+        }
+        let proxy_error = |variable: &str, e: reqwest::Error| AgentError::ConfigError(format!("{} is not a valid proxy URL: {}", variable, e));
+        if let Some(url) = &self.http_proxy {
+            builder = builder.proxy(Proxy::http(url).map_err(|e| proxy_error("AGENT_HTTP_PROXY", e))?);
+        }
+        if let Some(url) = &self.https_proxy {
+            builder = builder.proxy(Proxy::https(url).map_err(|e| proxy_error("AGENT_HTTPS_PROXY", e))?);
+        }
+        for path in &self.ca_certs {
+            let pem = std::fs::read(path).map_err(|e| AgentError::ConfigError(format!("Could not read the certificates in {}: {}", path, e)))?;
+            let certificates = Certificate::from_pem_bundle(&pem).map_err(|e| AgentError::ConfigError(format!("{} does not hold PEM certificates: {}", path, e)))?;
+            if certificates.is_empty() {
+                return Err(AgentError::ConfigError(format!("{} does not hold PEM certificates", path)));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        builder.build().map_err(|e| AgentError::ConfigError(format!("Could not set up the HTTP client: {}", e)))
+    }
+}
+
+/// The client for `config`'s HTTP settings, shared by every provider client created with them so
+/// they reuse its connection pool.
+pub fn shared_client(config: &AppConfig) -> Result<Client, AgentError> {
+    static CLIENTS: OnceLock<Mutex<HashMap<HttpSettings, Client>>> = OnceLock::new();
+    let settings = HttpSettings::from_config(config);
+    let mut clients = CLIENTS.get_or_init(Default::default).lock().unwrap();
+    if let Some(client) = clients.get(&settings) {
+        return Ok(client.clone());
+    }
+    let client = settings.build()?;
+    clients.insert(settings, client.clone());
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_come_from_the_config() {
+        let config = AppConfig { http_timeout_secs: Some(600), http_keepalive_secs: Some(30), https_proxy: Some("http://proxy:3128".to_string()), ..AppConfig::default() };
+        let settings = HttpSettings::from_config(&config);
+        assert_eq!(settings.timeout, Some(Duration::from_secs(600)));
+        assert_eq!(settings.connect_timeout, None);
+        assert_eq!(settings.keepalive, Some(Duration::from_secs(30)));
+        assert_eq!(settings.https_proxy.as_deref(), Some("http://proxy:3128"));
+        assert!(settings.build().is_ok());
+    }
+
+    #[test]
+    fn test_build_rejects_bad_proxies_and_certificates() {
+        let proxy = HttpSettings { http_proxy: Some("not a url".to_string()), ..HttpSettings::default() };
+        assert!(proxy.build().unwrap_err().to_string().contains("AGENT_HTTP_PROXY"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let not_pem = dir.path().join("root.pem");
+        std::fs::write(&not_pem, "not a certificate").unwrap();
+        for path in [not_pem, dir.path().join("missing.pem")] {
+            let certs = HttpSettings { ca_certs: vec![path.to_string_lossy().to_string()], ..HttpSettings::default() };
+            assert!(matches!(certs.build(), Err(AgentError::ConfigError(_))));
+        }
+    }
+}
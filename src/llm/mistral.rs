@@ -0,0 +1,200 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{pricing::{ModelPrice, PricingTable}, GenerationParams, LLMClient, AIResponse, ChatMessage, ChatRole, ModelInfo};
+use crate::error::AgentError;
+
+pub struct MistralClient {
+    api_key: String,
+    http_client: Client,
+    model: String,
+    pricing: ModelPrice,
+}
+
+#[derive(Serialize)]
+struct MistralRequest<'a> {
+    model: &'a str,
+    messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    // Mistral validates the request strictly, so optional fields are left out rather than sent as null.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat<'a>>,
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: &'a str,
+    content: &'a str,
+    /// Mistral rejects a conversation ending in an assistant message unless it is marked as a
+    /// prefix for the model to continue.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    prefix: bool,
+}
+
+#[derive(Serialize)]
+struct ResponseFormat<'a> {
+    r#type: &'a str,
+}
+
+#[derive(Deserialize)]
+struct MistralResponse {
+    choices: Vec<Choice>,
+    usage: Usage,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+impl MistralClient {
+    pub fn new(api_key: String, model: Option<String>, pricing: &PricingTable) -> Self {
+        let model = model.unwrap_or_else(|| "mistral-large-latest".to_string());
+        Self {
+            api_key,
+            http_client: Client::new(),
+            pricing: pricing.price_for("mistral", &model),
+            model,
+        }
+    }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    fn chat_messages<'a>(messages: &'a [ChatMessage]) -> Vec<Message<'a>> {
+        let last = messages.len().saturating_sub(1);
+        messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| Message {
+                role: m.role.as_str(),
+                content: &m.content,
+                prefix: i == last && m.role == ChatRole::Assistant,
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl LLMClient for MistralClient {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_chat(&[ChatMessage::user(prompt)], params).await
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let request_payload = MistralRequest {
+            model: &self.model,
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
+            top_p: params.top_p,
+            messages: Self::chat_messages(messages),
+            response_format: None,
+        };
+        self.send_request(request_payload).await
+    }
+
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let request_payload = MistralRequest {
+            model: &self.model,
+            temperature: params.temperature,
+            max_tokens: params.max_tokens,
+            top_p: params.top_p,
+            messages: vec![Message { role: "user", content: prompt, prefix: false }],
+            response_format: Some(ResponseFormat { r#type: "json_object" }),
+        };
+        self.send_request(request_payload).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        super::fetch_model_ids(self.http_client.get("https://api.mistral.ai/v1/models").bearer_auth(&self.api_key), "Mistral").await
+    }
+
+    async fn get_model_info(&self) -> ModelInfo {
+        ModelInfo {
+            name: self.model.clone(),
+            input_cost_per_token: self.pricing.input_cost_per_token(),
+            output_cost_per_token: self.pricing.output_cost_per_token(),
+        }
+    }
+
+    fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        self.pricing.cost(input_tokens, output_tokens)
+    }
+}
+
+impl MistralClient {
+    async fn send_request(&self, payload: MistralRequest<'_>) -> Result<AIResponse, AgentError> {
+        let response = self
+            .http_client
+            .post("https://api.mistral.ai/v1/chat/completions")
+            .bearer_auth(&self.api_key)
+            .json(&payload)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_body = response.text().await?;
+            return Err(AgentError::LLMError(format!("Mistral API Error: {}", error_body)));
+        }
+
+        let response_data: MistralResponse = response.json().await?;
+
+        let content = response_data
+            .choices
+            .into_iter()
+            .next()
+            .map(|c| c.message.content)
+            .ok_or_else(|| AgentError::ResponseParseError("No content in Mistral response".to_string()))?;
+
+        let input_tokens = response_data.usage.prompt_tokens;
+        let output_tokens = response_data.usage.completion_tokens;
+        let cost = self.calculate_cost(input_tokens, output_tokens);
+
+        Ok(AIResponse {
+            content,
+            input_tokens,
+            output_tokens,
+            reasoning_tokens: 0,
+            cost,
+            model: self.model.clone(),
+            provider: "Mistral".to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trailing_assistant_message_is_prefix() {
+        let messages = [ChatMessage::user("Write a haiku"), ChatMessage::assistant("Autumn")];
+        let request = MistralRequest { model: "mistral-small-latest", messages: MistralClient::chat_messages(&messages), temperature: None, max_tokens: Some(64), top_p: None, response_format: None };
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["messages"][0], serde_json::json!({"role": "user", "content": "Write a haiku"}));
+        assert_eq!(json["messages"][1]["prefix"], true);
+        assert!(json.get("response_format").is_none());
+        assert_eq!(json["max_tokens"], 64);
+        assert!(json.get("temperature").is_none());
+    }
+}
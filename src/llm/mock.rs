@@ -0,0 +1,219 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::{AIResponse, GenerationParams, LLMClient, ModelInfo, ToolCall, ToolDefinition};
+use crate::{error::AgentError, tokens};
+
+/// The model name of a mock client created without one (`--provider mock`).
+pub const DEFAULT_MOCK_MODEL: &str = "mock";
+
+/// One scripted reply of the mock provider.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockResponse {
+    /// Only answers prompts containing this text; without it, answers any prompt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    /// Only answers clients of this model (e.g. `planner` for `mock:planner`), so each role can
+    /// follow its own script.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub content: String,
+    /// Returned as a native tool call by `generate_with_tools`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call: Option<ToolCall>,
+    /// Fails the call with this message instead of answering, to script provider errors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Answers every matching prompt instead of only the first.
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+impl MockResponse {
+    pub fn new(content: impl Into<String>) -> Self {
+        Self { content: content.into(), ..Self::default() }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScriptFile {
+    #[serde(default)]
+    response: Vec<MockResponse>,
+}
+
+/// Reads a mock script: `[[response]]` tables in TOML, or a list of responses in JSON when the
+/// file ends in `.json` and in YAML when it ends in `.yaml` or `.yml`.
+///
+/// ```toml
+/// [[response]]
+/// when = "Create a step-by-step plan"
+/// content = "1. Write hello.py"
+///
+/// [[response]]
+/// content = '{"thought": "Write it", "tool_name": "WriteFile", "parameters": {"path": "hello.py", "content": "print(1)"}}'
+/// ```
+pub fn load_script(path: &Path) -> Result<Vec<MockResponse>, AgentError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| AgentError::ConfigError(format!("Failed to read the mock script {}: {}", path.display(), e)))?;
+    let invalid = |e: &dyn std::fmt::Display| AgentError::ConfigError(format!("Invalid mock script {}: {}", path.display(), e));
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str(&text).map_err(|e| invalid(&e)),
+        Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| invalid(&e)),
+        _ => toml::from_str::<ScriptFile>(&text).map(|script| script.response).map_err(|e| invalid(&e)),
+    }
+}
+
+/// A provider that replays scripted responses instead of calling a model, so tests, demos and
+/// benchmarks run offline and deterministically. Each call takes the first remaining response
+/// that matches the prompt; replies are free and token counts are estimated.
+pub struct MockClient {
+    model: String,
+    /// This client's model followed by every other model the script names.
+    models: Vec<String>,
+    responses: Mutex<Vec<MockResponse>>,
+    prompts: Mutex<Vec<String>>,
+}
+
+impl MockClient {
+    /// A client for `model` that answers with the responses scripted for it or for any model.
+    pub fn new(model: impl Into<String>, responses: Vec<MockResponse>) -> Self {
+        let model = model.into();
+        let mut models = vec![model.clone()];
+        for name in responses.iter().filter_map(|response| response.model.as_ref()) {
+            if !models.contains(name) {
+                models.push(name.clone());
+            }
+        }
+        let responses = responses.into_iter().filter(|response| response.model.as_ref().is_none_or(|name| *name == model)).collect();
+        Self { model, models, responses: Mutex::new(responses), prompts: Mutex::new(Vec::new()) }
+    }
+
+    /// A client that answers with `contents`, in order, whatever the prompts.
+    pub fn replaying<S: Into<String>>(contents: impl IntoIterator<Item = S>) -> Self {
+        Self::new(DEFAULT_MOCK_MODEL, contents.into_iter().map(MockResponse::new).collect())
+    }
+
+    /// Every prompt received so far, in order.
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
+
+    fn next(&self, prompt: &str) -> Result<MockResponse, AgentError> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
+        let mut responses = self.responses.lock().unwrap();
+        let index = responses
+            .iter()
+            .position(|response| response.when.as_ref().is_none_or(|text| prompt.contains(text.as_str())))
+            .ok_or_else(|| {
+                let start: String = prompt.chars().take(80).collect();
+                AgentError::LLMError(format!("The mock script has no response left for model '{}' and the prompt starting '{}'", self.model, start))
+            })?;
+        let response = if responses[index].repeat { responses[index].clone() } else { responses.remove(index) };
+        match &response.error {
+            Some(message) => Err(AgentError::LLMError(message.clone())),
+            None => Ok(response),
+        }
+    }
+
+    fn reply(&self, prompt: &str, content: String) -> AIResponse {
+        AIResponse {
+            input_tokens: tokens::approximate_tokens(prompt) as u32,
+            output_tokens: tokens::approximate_tokens(&content) as u32,
+            reasoning_tokens: 0,
+            content,
+            cost: 0.0,
+            model: self.model.clone(),
+            provider: "Mock".to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl LLMClient for MockClient {
+    async fn generate(&self, prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let response = self.next(prompt)?;
+        Ok(self.reply(prompt, response.content))
+    }
+
+    async fn generate_with_tools(&self, prompt: &str, _tools: &[ToolDefinition], _params: &GenerationParams) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
+        let response = self.next(prompt)?;
+        Ok((self.reply(prompt, response.content), response.tool_call))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        Ok(self.models.clone())
+    }
+
+    async fn get_model_info(&self) -> ModelInfo {
+        ModelInfo { name: self.model.clone(), input_cost_per_token: 0.0, output_cost_per_token: 0.0 }
+    }
+
+    fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_client_replays_matching_responses() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.toml");
+        std::fs::write(
+            &path,
+            r#"
+[[response]]
+model = "planner"
+content = "1. Write hello.py"
+
+[[response]]
+when = "review"
+content = "Looks good."
+repeat = true
+
+[[response]]
+content = "first"
+
+[[response]]
+content = "second"
+tool_call = { name = "ReadFile", arguments = { path = "hello.py" } }
+
+[[response]]
+error = "rate limited"
+"#,
+        )
+        .unwrap();
+        let script = load_script(&path).unwrap();
+        let params = GenerationParams::default();
+
+        let planner = MockClient::new("planner", script.clone());
+        assert_eq!(planner.generate("plan it", &params).await.unwrap().content, "1. Write hello.py");
+
+        let coder = MockClient::new(DEFAULT_MOCK_MODEL, script);
+        assert_eq!(coder.generate("please review", &params).await.unwrap().content, "Looks good.");
+        assert_eq!(coder.generate("write it", &params).await.unwrap().content, "first");
+        assert_eq!(coder.generate("review again", &params).await.unwrap().content, "Looks good.");
+        let (response, call) = coder.generate_with_tools("read it", &[], &params).await.unwrap();
+        assert_eq!((response.content.as_str(), response.cost, response.model.as_str()), ("second", 0.0, "mock"));
+        assert_eq!(call, Some(ToolCall { name: "ReadFile".to_string(), arguments: serde_json::json!({ "path": "hello.py" }) }));
+        assert!(matches!(coder.generate("go", &params).await, Err(AgentError::LLMError(message)) if message == "rate limited"));
+        assert!(matches!(coder.generate("go", &params).await, Err(AgentError::LLMError(message)) if message.contains("no response left")));
+        assert_eq!(coder.prompts().len(), 6);
+        assert_eq!(coder.list_models().await.unwrap(), vec!["mock".to_string(), "planner".to_string()]);
+
+        std::fs::write(dir.path().join("script.json"), r#"[{"content": "hi"}]"#).unwrap();
+        assert_eq!(load_script(&dir.path().join("script.json")).unwrap(), vec![MockResponse::new("hi")]);
+        std::fs::write(dir.path().join("script.yaml"), "- when: plan\n  content: \"1. Write hello.py\"\n- content: hi\n").unwrap();
+        let yaml = load_script(&dir.path().join("script.yaml")).unwrap();
+        assert_eq!((yaml[0].when.as_deref(), yaml[1].content.as_str()), (Some("plan"), "hi"));
+        std::fs::write(&path, "[[response]]\ntext = 'typo'\n").unwrap();
+        assert!(matches!(load_script(&path), Err(AgentError::ConfigError(_))));
+    }
+}
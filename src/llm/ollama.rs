@@ -2,13 +2,14 @@ use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use super::{LLMClient, AIResponse, ModelInfo};
+use super::{pricing::{ModelPrice, PricingTable}, GenerationParams, LLMClient, AIResponse, ChatMessage, ModelInfo, OnText};
 use crate::error::AgentError;
 
 pub struct OllamaClient {
     base_url: String,
     model: String,
     http_client: Client,
+    pricing: ModelPrice,
 }
 
 #[derive(Serialize)]
@@ -16,6 +17,25 @@ struct OllamaRequest<'a> {
     model: &'a str,
     prompt: &'a str,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+}
+
+/// Ollama's name for the sampling settings; `num_predict` is its output token limit.
+#[derive(Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+}
+
+impl OllamaOptions {
+    fn from_params(params: &GenerationParams) -> Option<Self> {
+        (!params.is_default()).then_some(Self { temperature: params.temperature, num_predict: params.max_tokens, top_p: params.top_p })
+    }
 }
 
 #[derive(Deserialize)]
@@ -25,25 +45,84 @@ struct OllamaResponse {
     eval_count: Option<u32>,
 }
 
+#[derive(Serialize)]
+struct OllamaChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<Message<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Deserialize)]
+struct OllamaChatResponse {
+    message: ResponseMessage,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+}
+
+/// One line of a streamed chat answer; the last, with `done` set, carries the token counts.
+#[derive(Deserialize)]
+struct OllamaChatChunk {
+    #[serde(default)]
+    message: Option<ResponseMessage>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    /// Set instead of a message when the model fails mid-answer.
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TagList {
+    #[serde(default)]
+    models: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: String,
+}
+
 impl OllamaClient {
-    pub fn new(base_url: &str, model: &str) -> Self {
+    pub fn new(base_url: &str, model: &str, pricing: &PricingTable) -> Self {
         Self {
             base_url: base_url.to_string(),
             model: model.to_string(),
             http_client: Client::new(),
+            pricing: pricing.price_for("ollama", model),
         }
     }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
 }
 
 #[async_trait]
 impl LLMClient for OllamaClient {
-    async fn generate(&self, prompt: &str) -> Result<AIResponse, AgentError> {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
         let url = format!("{}/api/generate", self.base_url);
         
         let request_payload = OllamaRequest {
             model: &self.model,
             prompt,
             stream: false,
+            options: OllamaOptions::from_params(params),
         };
 
         let response = self
@@ -68,29 +147,140 @@ impl LLMClient for OllamaClient {
             content: response_data.response,
             input_tokens,
             output_tokens,
+            reasoning_tokens: 0,
+            cost,
+            model: self.model.clone(),
+            provider: "Ollama".to_string(),
+        })
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let url = format!("{}/api/chat", self.base_url);
+
+        let request_payload = OllamaChatRequest {
+            model: &self.model,
+            messages: messages
+                .iter()
+                .map(|m| Message { role: m.role.as_str(), content: &m.content })
+                .collect(),
+            stream: false,
+            options: OllamaOptions::from_params(params),
+        };
+
+        let response = self
+            .http_client
+            .post(&url)
+            .json(&request_payload)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_body = response.text().await?;
+            return Err(AgentError::LLMError(format!("Ollama API Error: {}", error_body)));
+        }
+
+        let response_data: OllamaChatResponse = response.json().await?;
+
+        let input_tokens = response_data.prompt_eval_count.unwrap_or(0);
+        let output_tokens = response_data.eval_count.unwrap_or(0);
+        let cost = self.calculate_cost(input_tokens, output_tokens);
+
+        Ok(AIResponse {
+            content: response_data.message.content,
+            input_tokens,
+            output_tokens,
+            reasoning_tokens: 0,
+            cost,
+            model: self.model.clone(),
+            provider: "Ollama".to_string(),
+        })
+    }
+
+    async fn generate_stream(&self, messages: &[ChatMessage], params: &GenerationParams, on_text: &OnText<'_>) -> Result<AIResponse, AgentError> {
+        let url = format!("{}/api/chat", self.base_url);
+
+        let request_payload = OllamaChatRequest {
+            model: &self.model,
+            messages: messages
+                .iter()
+                .map(|m| Message { role: m.role.as_str(), content: &m.content })
+                .collect(),
+            stream: true,
+            options: OllamaOptions::from_params(params),
+        };
+
+        let response = self
+            .http_client
+            .post(&url)
+            .json(&request_payload)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_body = response.text().await?;
+            return Err(AgentError::LLMError(format!("Ollama API Error: {}", error_body)));
+        }
+
+        let (mut content, mut input_tokens, mut output_tokens) = (String::new(), 0, 0);
+        super::for_each_line(response, |line| {
+            let chunk: OllamaChatChunk = serde_json::from_str(line)
+                .map_err(|e| AgentError::ResponseParseError(format!("Invalid Ollama stream line: {}. Line: {}", e, line)))?;
+            if let Some(error) = chunk.error {
+                return Err(AgentError::LLMError(format!("Ollama API Error: {}", error)));
+            }
+            if let Some(message) = chunk.message.filter(|message| !message.content.is_empty()) {
+                on_text(&message.content);
+                content.push_str(&message.content);
+            }
+            if chunk.done {
+                input_tokens = chunk.prompt_eval_count.unwrap_or(0);
+                output_tokens = chunk.eval_count.unwrap_or(0);
+            }
+            Ok(())
+        })
+        .await?;
+        let cost = self.calculate_cost(input_tokens, output_tokens);
+
+        Ok(AIResponse {
+            content,
+            input_tokens,
+            output_tokens,
+            reasoning_tokens: 0,
             cost,
             model: self.model.clone(),
             provider: "Ollama".to_string(),
         })
     }
 
-    async fn generate_json(&self, prompt: &str) -> Result<AIResponse, AgentError> {
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
         // Ollama does not have a direct JSON mode. We'll just call generate.
-        self.generate(prompt).await
+        self.generate(prompt, params).await
+    }
+
+    /// The models pulled into the local Ollama, from `/api/tags`.
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        let url = format!("{}/api/tags", self.base_url);
+        let response = self.http_client.get(&url).send().await?;
+        if !response.status().is_success() {
+            let error_body = response.text().await?;
+            return Err(AgentError::LLMError(format!("Ollama API Error: {}", error_body)));
+        }
+        let tags: TagList = response.json().await?;
+        let mut names: Vec<String> = tags.models.into_iter().map(|tag| tag.name).collect();
+        names.sort();
+        Ok(names)
     }
 
     async fn get_model_info(&self) -> ModelInfo {
-        // Ollama models are typically free or self-hosted, so cost is 0.
+        // Self-hosted models default to a zero price in the bundled table.
         ModelInfo {
             name: self.model.clone(),
-            input_cost_per_token: 0.0,
-            output_cost_per_token: 0.0,
+            input_cost_per_token: self.pricing.input_cost_per_token(),
+            output_cost_per_token: self.pricing.output_cost_per_token(),
         }
     }
 
     fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
-        let model_info = futures::executor::block_on(self.get_model_info());
-        (input_tokens as f64 * model_info.input_cost_per_token) +
-        (output_tokens as f64 * model_info.output_cost_per_token)
+        self.pricing.cost(input_tokens, output_tokens)
     }
 }
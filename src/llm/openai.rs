@@ -2,27 +2,95 @@ use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
-use super::{LLMClient, AIResponse, ModelInfo};
+use super::{pricing::{ModelPrice, PricingTable}, GenerationParams, LLMClient, AIResponse, ChatMessage, ChatRole, ModelInfo, OnText, ToolCall, ToolDefinition};
 use crate::error::AgentError;
 
 pub struct OpenAIClient {
     api_key: String,
     http_client: Client,
     model: String,
+    pricing: ModelPrice,
 }
 
 #[derive(Serialize)]
 struct OpenAIRequest<'a> {
     model: &'a str,
     messages: Vec<Message<'a>>,
-    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    /// Replaces `max_tokens` on reasoning models, where it also bounds the hidden reasoning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     response_format: Option<ResponseFormat<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<FunctionTool<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<&'a str>,
+    /// Set by `generate_stream`, which reads the answer as server-sent events.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    /// Asks for a last event carrying the token usage, which streamed answers otherwise lack.
+    include_usage: bool,
+}
+
+#[derive(Serialize)]
+struct FunctionTool<'a> {
+    r#type: &'a str,
+    function: FunctionDefinition<'a>,
+}
+
+#[derive(Serialize)]
+struct FunctionDefinition<'a> {
+    name: &'a str,
+    description: &'a str,
+    parameters: &'a serde_json::Value,
 }
 
 #[derive(Serialize)]
 struct Message<'a> {
     role: &'a str,
-    content: &'a str,
+    content: MessageContent<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum MessageContent<'a> {
+    Text(&'a str),
+    /// The text followed by the message's images.
+    Parts(Vec<ContentPart<'a>>),
+}
+
+impl<'a> MessageContent<'a> {
+    fn of(message: &'a ChatMessage) -> Self {
+        if message.images.is_empty() {
+            return MessageContent::Text(&message.content);
+        }
+        let images = message.images.iter().map(|image| ContentPart::ImageUrl { image_url: ImageUrl { url: image.data_url() } });
+        MessageContent::Parts(std::iter::once(ContentPart::Text { text: &message.content }).chain(images).collect())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart<'a> {
+    Text { text: &'a str },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Serialize)]
+struct ImageUrl {
+    url: String,
 }
 
 #[derive(Serialize)]
@@ -43,66 +111,198 @@ struct Choice {
 
 #[derive(Deserialize)]
 struct ResponseMessage {
-    content: String,
+    // Null when the model answers with tool calls only.
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ResponseToolCall>,
+}
+
+#[derive(Deserialize)]
+struct ResponseToolCall {
+    function: FunctionCall,
+}
+
+#[derive(Deserialize)]
+struct FunctionCall {
+    name: String,
+    /// JSON-encoded arguments, as a string.
+    arguments: String,
+}
+
+/// One server-sent event of a streamed answer.
+#[derive(Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct StreamChoice {
+    delta: Delta,
 }
 
 #[derive(Deserialize)]
+struct Delta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
 struct Usage {
     prompt_tokens: u32,
+    /// Includes the reasoning tokens, which are billed as output.
     completion_tokens: u32,
-    total_tokens: u32,
+    #[serde(default)]
+    completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Deserialize)]
+struct CompletionTokensDetails {
+    #[serde(default)]
+    reasoning_tokens: u32,
+}
+
+/// Whether `model` is an o-series reasoning model (o1, o3, o4-mini, ...). These reject
+/// `temperature`, `top_p` and `max_tokens`, and older ones `response_format` and system messages.
+pub fn is_reasoning_model(model: &str) -> bool {
+    let mut chars = model.chars();
+    chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit())
 }
 
 impl OpenAIClient {
-    pub fn new(api_key: String, model: Option<String>) -> Self {
+    pub fn new(api_key: String, model: Option<String>, pricing: &PricingTable) -> Self {
+        let model = model.unwrap_or_else(|| "gpt-4o".to_string());
         Self {
             api_key,
             http_client: Client::new(),
-            model: model.unwrap_or_else(|| "gpt-4o".to_string()),
+            pricing: pricing.price_for("openai", &model),
+            model,
+        }
+    }
+
+    pub fn with_http_client(mut self, http_client: Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    /// A request for `messages` without tools, adapted to reasoning models: their sampling
+    /// parameters are dropped, the token limit is sent as `max_completion_tokens`, system messages
+    /// become developer messages and JSON is only asked for by the prompt.
+    fn request<'a>(&'a self, messages: &'a [ChatMessage], params: &GenerationParams, default_temperature: f32, json: bool) -> OpenAIRequest<'a> {
+        let reasoning = is_reasoning_model(&self.model);
+        let role = |role: ChatRole| match role {
+            ChatRole::System if reasoning => "developer",
+            role => role.as_str(),
+        };
+        OpenAIRequest {
+            model: &self.model,
+            messages: messages.iter().map(|m| Message { role: role(m.role), content: MessageContent::of(m) }).collect(),
+            temperature: (!reasoning).then(|| params.temperature.unwrap_or(default_temperature)),
+            max_tokens: params.max_tokens.filter(|_| !reasoning),
+            max_completion_tokens: params.max_tokens.filter(|_| reasoning),
+            top_p: params.top_p.filter(|_| !reasoning),
+            response_format: (json && !reasoning).then_some(ResponseFormat { r#type: "json_object" }),
+            tools: Vec::new(),
+            tool_choice: None,
+            stream: false,
+            stream_options: None,
         }
     }
 }
 
 #[async_trait]
 impl LLMClient for OpenAIClient {
-    async fn generate(&self, prompt: &str) -> Result<AIResponse, AgentError> {
-        let request_payload = OpenAIRequest {
-            model: &self.model,
-            messages: vec![Message { role: "user", content: prompt }],
-            temperature: 0.2,
-            response_format: None,
-        };
-        self.send_request(request_payload).await
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_chat(&[ChatMessage::user(prompt)], params).await
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let (response, _) = self.send_request(self.request(messages, params, 0.2, false)).await?;
+        Ok(response)
     }
     
-    async fn generate_json(&self, prompt: &str) -> Result<AIResponse, AgentError> {
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.generate_json_chat(&[ChatMessage::user(prompt)], params).await
+    }
+
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let (response, _) = self.send_request(self.request(messages, params, 0.0, true)).await?;
+        Ok(response)
+    }
+
+    async fn generate_stream(&self, messages: &[ChatMessage], params: &GenerationParams, on_text: &OnText<'_>) -> Result<AIResponse, AgentError> {
+        let payload = OpenAIRequest { stream: true, stream_options: Some(StreamOptions { include_usage: true }), ..self.request(messages, params, 0.2, false) };
+        let response = self
+            .http_client
+            .post("https://api.openai.com/v1/chat/completions")
+            .bearer_auth(&self.api_key)
+            .json(&payload)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let error_body = response.text().await?;
+            return Err(AgentError::LLMError(format!("OpenAI API Error: {}", error_body)));
+        }
+
+        let (mut content, mut usage) = (String::new(), Usage::default());
+        super::for_each_line(response, |line| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim).filter(|data| *data != "[DONE]") else {
+                return Ok(());
+            };
+            let chunk: StreamChunk = serde_json::from_str(data)
+                .map_err(|e| AgentError::ResponseParseError(format!("Invalid OpenAI stream event: {}. Event: {}", e, data)))?;
+            if let Some(text) = chunk.choices.into_iter().next().and_then(|choice| choice.delta.content) {
+                on_text(&text);
+                content.push_str(&text);
+            }
+            if let Some(last) = chunk.usage {
+                usage = last;
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(self.response(content, usage))
+    }
+
+    async fn generate_with_tools(&self, prompt: &str, tools: &[ToolDefinition], params: &GenerationParams) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
+        let messages = [ChatMessage::user(prompt)];
         let request_payload = OpenAIRequest {
-            model: &self.model,
-            messages: vec![Message { role: "user", content: prompt }],
-            temperature: 0.0,
-            response_format: Some(ResponseFormat { r#type: "json_object" }),
+            tools: tools
+                .iter()
+                .map(|t| FunctionTool {
+                    r#type: "function",
+                    function: FunctionDefinition { name: &t.name, description: &t.description, parameters: &t.parameters },
+                })
+                .collect(),
+            // "required" forces a function call instead of a plain-text answer.
+            tool_choice: Some("required"),
+            ..self.request(&messages, params, 0.0, false)
         };
         self.send_request(request_payload).await
     }
 
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        super::fetch_model_ids(self.http_client.get("https://api.openai.com/v1/models").bearer_auth(&self.api_key), "OpenAI").await
+    }
+
     async fn get_model_info(&self) -> ModelInfo {
-        // These are example costs for gpt-4o. Real costs should be fetched or configured.
         ModelInfo {
             name: self.model.clone(),
-            input_cost_per_token: 0.000005, // Example: $5 per 1M tokens
-            output_cost_per_token: 0.000015, // Example: $15 per 1M tokens
+            input_cost_per_token: self.pricing.input_cost_per_token(),
+            output_cost_per_token: self.pricing.output_cost_per_token(),
         }
     }
 
     fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
-        let model_info = futures::executor::block_on(self.get_model_info());
-        (input_tokens as f64 * model_info.input_cost_per_token) + 
-        (output_tokens as f64 * model_info.output_cost_per_token)
+        self.pricing.cost(input_tokens, output_tokens)
     }
 }
 
 impl OpenAIClient {
-    async fn send_request(&self, payload: OpenAIRequest<'_>) -> Result<AIResponse, AgentError> {
+    async fn send_request(&self, payload: OpenAIRequest<'_>) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
         let response = self
             .http_client
             .post("https://api.openai.com/v1/chat/completions")
@@ -117,20 +317,178 @@ impl OpenAIClient {
         }
 
         let response_data: OpenAIResponse = response.json().await?;
-        let content = response_data.choices.into_iter().next().map(|c| c.message.content)
+        self.parse_response(response_data)
+    }
+
+    /// Extracts the message text and the first tool call, decoding its JSON-string arguments.
+    fn parse_response(&self, response_data: OpenAIResponse) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
+        let message = response_data.choices.into_iter().next().map(|c| c.message)
             .ok_or_else(|| AgentError::ResponseParseError("No content in OpenAI response".to_string()))?;
+        let tool_call = match message.tool_calls.into_iter().next() {
+            Some(call) => {
+                let arguments = serde_json::from_str(&call.function.arguments).map_err(|e| {
+                    AgentError::ResponseParseError(format!("Invalid arguments for {}: {}. Arguments: {}", call.function.name, e, call.function.arguments))
+                })?;
+                Some(ToolCall { name: call.function.name, arguments })
+            }
+            None => None,
+        };
+        let content = match (message.content, &tool_call) {
+            (Some(content), _) => content,
+            (None, Some(_)) => String::new(),
+            (None, None) => return Err(AgentError::ResponseParseError("No content in OpenAI response".to_string())),
+        };
 
-        let input_tokens = response_data.usage.prompt_tokens;
-        let output_tokens = response_data.usage.completion_tokens;
+        Ok((self.response(content, response_data.usage), tool_call))
+    }
+
+    /// The answer `content` with the token counts and cost of `usage`.
+    fn response(&self, content: String, usage: Usage) -> AIResponse {
+        let input_tokens = usage.prompt_tokens;
+        let output_tokens = usage.completion_tokens;
+        let reasoning_tokens = usage.completion_tokens_details.map_or(0, |details| details.reasoning_tokens);
         let cost = self.calculate_cost(input_tokens, output_tokens);
 
-        Ok(AIResponse {
+        AIResponse {
             content,
             input_tokens,
             output_tokens,
+            reasoning_tokens,
             cost,
             model: self.model.clone(),
             provider: "OpenAI".to_string(),
-        })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> OpenAIClient {
+        OpenAIClient::new("test-key".to_string(), None, &PricingTable::bundled())
+    }
+
+    fn response(message: serde_json::Value) -> OpenAIResponse {
+        serde_json::from_value(serde_json::json!({
+            "choices": [{ "message": message }],
+            "usage": { "prompt_tokens": 50, "completion_tokens": 10 }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_response_with_tool_call() {
+        let data = response(serde_json::json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": { "name": "RunCommand", "arguments": "{\"thought\":\"Run tests\",\"command\":\"cargo test\"}" }
+            }]
+        }));
+
+        let (response, tool_call) = client().parse_response(data).unwrap();
+        assert_eq!(response.content, "");
+        assert_eq!(response.output_tokens, 10);
+        let call = tool_call.unwrap();
+        assert_eq!(call.name, "RunCommand");
+        assert_eq!(call.arguments["command"], "cargo test");
+    }
+
+    #[test]
+    fn test_parse_response_text_only() {
+        let (response, tool_call) = client().parse_response(response(serde_json::json!({ "content": "Hi" }))).unwrap();
+        assert_eq!(response.content, "Hi");
+        assert!(tool_call.is_none());
+    }
+
+    #[test]
+    fn test_parse_response_invalid_arguments() {
+        let data = response(serde_json::json!({
+            "content": null,
+            "tool_calls": [{ "function": { "name": "ReadFile", "arguments": "{not json" } }]
+        }));
+        assert!(matches!(client().parse_response(data), Err(AgentError::ResponseParseError(_))));
+        assert!(matches!(client().parse_response(response(serde_json::json!({ "content": null }))), Err(AgentError::ResponseParseError(_))));
+    }
+
+    #[test]
+    fn test_tool_request_serialization() {
+        let tools = crate::tools::tool_definitions();
+        let messages = [ChatMessage::user("hi")];
+        let client = client();
+        let payload = OpenAIRequest {
+            tools: tools
+                .iter()
+                .map(|t| FunctionTool {
+                    r#type: "function",
+                    function: FunctionDefinition { name: &t.name, description: &t.description, parameters: &t.parameters },
+                })
+                .collect(),
+            tool_choice: Some("required"),
+            ..client.request(&messages, &GenerationParams { top_p: Some(0.5), ..Default::default() }, 0.0, false)
+        };
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["tool_choice"], "required");
+        assert_eq!(json["top_p"], 0.5);
+        assert!(json.get("max_tokens").is_none());
+        assert_eq!(json["tools"][0]["type"], "function");
+        assert_eq!(json["tools"][0]["function"]["name"], "ReadFile");
+        assert_eq!(json["tools"][0]["function"]["parameters"]["type"], "object");
+    }
+
+    #[test]
+    fn test_chat_request_keeps_system_message() {
+        let messages = [ChatMessage::system("You are a strict reviewer."), ChatMessage::user("Review this")];
+        let client = client();
+        let json = serde_json::to_value(client.request(&messages, &GenerationParams::default(), 0.0, true)).unwrap();
+        assert_eq!(json["messages"][0]["role"], "system");
+        assert_eq!(json["messages"][0]["content"], "You are a strict reviewer.");
+        assert_eq!(json["messages"][1]["role"], "user");
+        assert_eq!(json["response_format"]["type"], "json_object");
+        assert_eq!(json["temperature"], 0.0);
+    }
+
+    #[test]
+    fn test_chat_request_sends_images_as_content_parts() {
+        let messages = [ChatMessage::user("Match this mock").with_images(vec![crate::llm::ImageAttachment::new("image/jpeg", b"jpg")])];
+        let client = client();
+        let json = serde_json::to_value(client.request(&messages, &GenerationParams::default(), 0.0, true)).unwrap();
+        assert_eq!(json["messages"][0]["content"][0], serde_json::json!({ "type": "text", "text": "Match this mock" }));
+        assert_eq!(json["messages"][0]["content"][1], serde_json::json!({ "type": "image_url", "image_url": { "url": "data:image/jpeg;base64,anBn" } }));
+    }
+
+    #[test]
+    fn test_reasoning_model_request_drops_unsupported_parameters() {
+        assert!(is_reasoning_model("o4-mini") && is_reasoning_model("o1") && is_reasoning_model("o3-mini-2025-01-31"));
+        assert!(!is_reasoning_model("gpt-4o") && !is_reasoning_model("omni"));
+
+        let client = OpenAIClient::new("test-key".to_string(), Some("o4-mini".to_string()), &PricingTable::bundled());
+        let messages = [ChatMessage::system("Be brief."), ChatMessage::user("Pick a tool")];
+        let params = GenerationParams { temperature: Some(0.3), max_tokens: Some(2048), top_p: Some(0.9) };
+        let json = serde_json::to_value(client.request(&messages, &params, 0.0, true)).unwrap();
+        assert_eq!(json["messages"][0]["role"], "developer");
+        assert_eq!(json["max_completion_tokens"], 2048);
+        for unsupported in ["temperature", "top_p", "max_tokens", "response_format"] {
+            assert!(json.get(unsupported).is_none(), "{} was sent", unsupported);
+        }
+    }
+
+    #[test]
+    fn test_parse_response_counts_reasoning_tokens() {
+        let data: OpenAIResponse = serde_json::from_value(serde_json::json!({
+            "choices": [{ "message": { "content": "{}" } }],
+            "usage": { "prompt_tokens": 50, "completion_tokens": 400, "completion_tokens_details": { "reasoning_tokens": 384 } }
+        }))
+        .unwrap();
+        let client = OpenAIClient::new("test-key".to_string(), Some("o4-mini".to_string()), &PricingTable::bundled());
+        let (parsed, _) = client.parse_response(data).unwrap();
+        assert_eq!(parsed.reasoning_tokens, 384);
+        // Reasoning is billed as output.
+        assert_eq!(parsed.output_tokens, 400);
+        assert_eq!(parsed.cost, client.calculate_cost(50, 400));
+        assert_eq!(client.parse_response(response(serde_json::json!({ "content": "Hi" }))).unwrap().0.reasoning_tokens, 0);
     }
 }
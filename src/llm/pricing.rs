@@ -0,0 +1,154 @@
+use log::warn;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::AgentError;
+
+const BUNDLED_PRICING: &str = include_str!("pricing.toml");
+
+/// Price of a model in USD per one million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    pub fn input_cost_per_token(&self) -> f64 {
+        self.input / 1_000_000.0
+    }
+
+    pub fn output_cost_per_token(&self) -> f64 {
+        self.output / 1_000_000.0
+    }
+
+    pub fn cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        (input_tokens as f64 * self.input_cost_per_token()) + (output_tokens as f64 * self.output_cost_per_token())
+    }
+}
+
+/// Prices keyed by provider and model, loaded from the bundled table plus an optional override file.
+#[derive(Debug, Clone, Default)]
+pub struct PricingTable {
+    providers: HashMap<String, HashMap<String, ModelPrice>>,
+}
+
+impl PricingTable {
+    pub fn bundled() -> Self {
+        Self::from_toml(BUNDLED_PRICING).expect("bundled pricing.toml is valid")
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, AgentError> {
+        let providers: HashMap<String, HashMap<String, ModelPrice>> = toml::from_str(content)
+            .map_err(|e| AgentError::ConfigError(format!("Invalid pricing table: {}", e)))?;
+        let providers = providers
+            .into_iter()
+            .map(|(provider, models)| (provider.to_lowercase(), models))
+            .collect();
+        Ok(Self { providers })
+    }
+
+    /// Loads the bundled prices, then applies entries from `override_path` on top.
+    pub fn load(override_path: Option<&Path>) -> Result<Self, AgentError> {
+        let mut table = Self::bundled();
+        if let Some(path) = override_path {
+            let content = std::fs::read_to_string(path).map_err(|e| {
+                AgentError::ConfigError(format!("Failed to read pricing file {}: {}", path.display(), e))
+            })?;
+            table.merge(Self::from_toml(&content)?);
+        }
+        Ok(table)
+    }
+
+    pub fn merge(&mut self, other: PricingTable) {
+        for (provider, models) in other.providers {
+            self.providers.entry(provider).or_default().extend(models);
+        }
+    }
+
+    /// Finds the price for `model`, preferring an exact match, then the longest matching prefix,
+    /// then the provider's `*` entry.
+    pub fn lookup(&self, provider: &str, model: &str) -> Option<ModelPrice> {
+        let models = self.providers.get(&provider.to_lowercase())?;
+        if let Some(price) = models.get(model) {
+            return Some(*price);
+        }
+        models
+            .iter()
+            .filter(|(key, _)| key.as_str() != "*" && model.starts_with(key.as_str()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, price)| *price)
+            .or_else(|| models.get("*").copied())
+    }
+
+    /// Like `lookup`, but falls back to a zero price (with a warning) for unknown models.
+    pub fn price_for(&self, provider: &str, model: &str) -> ModelPrice {
+        self.lookup(provider, model).unwrap_or_else(|| {
+            warn!("No pricing found for {} model '{}'; costs will be reported as $0.", provider, model);
+            ModelPrice::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_table_parses() {
+        let table = PricingTable::bundled();
+        assert_eq!(table.lookup("openai", "gpt-4o"), Some(ModelPrice { input: 2.50, output: 10.00 }));
+        assert_eq!(table.lookup("ollama", "llama3"), Some(ModelPrice { input: 0.0, output: 0.0 }));
+        assert_eq!(table.lookup("mistral", "mistral-large-latest"), Some(ModelPrice { input: 2.00, output: 6.00 }));
+        assert_eq!(table.lookup("groq", "llama-3.3-70b-versatile"), Some(ModelPrice { input: 0.59, output: 0.79 }));
+    }
+
+    #[test]
+    fn test_lookup_prefers_longest_prefix() {
+        let table = PricingTable::bundled();
+        assert_eq!(table.lookup("openai", "gpt-4o-mini-2024-07-18"), table.lookup("openai", "gpt-4o-mini"));
+        assert_eq!(table.lookup("openai", "gpt-4o-2024-08-06"), table.lookup("openai", "gpt-4o"));
+        assert_eq!(table.lookup("OpenAI", "gpt-4o"), table.lookup("openai", "gpt-4o"));
+    }
+
+    #[test]
+    fn test_lookup_unknown_model() {
+        let table = PricingTable::bundled();
+        assert_eq!(table.lookup("openai", "unknown-model"), None);
+        assert_eq!(table.lookup("unknown-provider", "gpt-4o"), None);
+        assert_eq!(table.price_for("openai", "unknown-model"), ModelPrice::default());
+    }
+
+    #[test]
+    fn test_merge_overrides_and_extends() {
+        let mut table = PricingTable::bundled();
+        let overrides = PricingTable::from_toml(
+            r#"
+            [openai]
+            "gpt-4o" = { input = 1.0, output = 2.0 }
+            [together]
+            "qwen-2.5-coder" = { input = 0.8, output = 0.8 }
+            "#,
+        )
+        .unwrap();
+        table.merge(overrides);
+
+        assert_eq!(table.lookup("openai", "gpt-4o"), Some(ModelPrice { input: 1.0, output: 2.0 }));
+        assert!(table.lookup("openai", "gpt-4o-mini").is_some());
+        assert_eq!(table.lookup("together", "qwen-2.5-coder"), Some(ModelPrice { input: 0.8, output: 0.8 }));
+    }
+
+    #[test]
+    fn test_invalid_toml() {
+        let result = PricingTable::from_toml("[openai]\n\"gpt-4o\" = 3");
+        assert!(matches!(result, Err(AgentError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_model_price_cost() {
+        let price = ModelPrice { input: 2.0, output: 8.0 };
+        let cost = price.cost(1_000_000, 500_000);
+        assert!((cost - 6.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,435 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::hash_map::RandomState;
+use std::fs::{File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::AppConfig;
+use crate::error::AgentError;
+use crate::llm::{AIResponse, ChatMessage, GenerationParams, LLMClient, ModelInfo, OnText, ToolCall, ToolDefinition};
+
+/// Where LangSmith runs are posted unless `LANGSMITH_ENDPOINT` is set.
+pub const DEFAULT_LANGSMITH_ENDPOINT: &str = "https://api.smith.langchain.com";
+
+/// The LangSmith project runs are filed under unless `LANGSMITH_PROJECT` is set.
+pub const DEFAULT_LANGSMITH_PROJECT: &str = "rust-cli-agent";
+
+/// The `AGENT_PROMPT_LOG` value that sends prompts to LangSmith instead of a file.
+pub const LANGSMITH: &str = "langsmith";
+
+/// How long posting one run to LangSmith may hold up the call it records.
+const LANGSMITH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Secrets recognized in prompts and responses without any configuration: provider API keys,
+/// GitHub and AWS tokens, bearer tokens and private keys.
+const BUILTIN_SECRET_PATTERNS: &[&str] = &[
+    r"sk-[A-Za-z0-9_-]{16,}",
+    r"AIza[0-9A-Za-z_-]{35}",
+    r"gsk_[A-Za-z0-9]{20,}",
+    r"gh[pousr]_[A-Za-z0-9]{36,}",
+    r"AKIA[0-9A-Z]{16}",
+    r"(?i)bearer\s+[A-Za-z0-9._~+/=-]{16,}",
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+];
+
+const REDACTED: &str = "[REDACTED]";
+
+/// One LLM call: what was sent, what came back, and what it cost.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptRecord {
+    pub id: String,
+    /// The agent role that made the call, e.g. "planner" or "coder".
+    pub role: String,
+    pub model: String,
+    pub started_at: DateTime<Utc>,
+    pub latency_ms: u64,
+    /// The request; a plain prompt is recorded as one user message.
+    pub messages: Vec<ChatMessage>,
+    pub response: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call: Option<ToolCall>,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cost: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Receives every LLM call made through a client wrapped with `logged`.
+#[async_trait]
+pub trait PromptSink: Send + Sync {
+    async fn record(&self, record: &PromptRecord) -> Result<(), AgentError>;
+}
+
+/// Appends each call as one JSON line, so the prompts of many runs can be compared over time.
+pub struct PromptLogFile {
+    writer: Mutex<File>,
+}
+
+impl PromptLogFile {
+    /// Opens `path` for appending, creating it and its directory if needed.
+    pub fn open(path: &Path) -> Result<Self, AgentError> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { writer: Mutex::new(file) })
+    }
+
+    /// Reads back every record of a prompt log.
+    pub fn read(path: &Path) -> Result<Vec<PromptRecord>, AgentError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut records = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            records.push(serde_json::from_str(&line)?);
+        }
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl PromptSink for PromptLogFile {
+    async fn record(&self, record: &PromptRecord) -> Result<(), AgentError> {
+        let line = serde_json::to_string(record)?;
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{}", line)?;
+        Ok(())
+    }
+}
+
+/// Posts each call as an `llm` run to LangSmith, or any server implementing its `/runs` API. A post
+/// that takes longer than `LANGSMITH_TIMEOUT` is given up on.
+pub struct LangSmith {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+    project: String,
+}
+
+impl LangSmith {
+    pub fn new(endpoint: &str, api_key: &str, project: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            project: project.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl PromptSink for LangSmith {
+    async fn record(&self, record: &PromptRecord) -> Result<(), AgentError> {
+        let end = record.started_at + chrono::Duration::milliseconds(record.latency_ms as i64);
+        let mut outputs = json!({
+            "usage_metadata": {
+                "input_tokens": record.input_tokens,
+                "output_tokens": record.output_tokens,
+                "total_tokens": record.input_tokens + record.output_tokens,
+            },
+        });
+        if let Some(response) = &record.response {
+            outputs["generations"] = json!([{ "text": response }]);
+        }
+        if let Some(call) = &record.tool_call {
+            outputs["tool_call"] = json!(call);
+        }
+        let run = json!({
+            "id": record.id,
+            "name": record.role,
+            "run_type": "llm",
+            "start_time": record.started_at.to_rfc3339(),
+            "end_time": end.to_rfc3339(),
+            "inputs": { "messages": record.messages },
+            "outputs": outputs,
+            "error": record.error,
+            "session_name": self.project,
+            "tags": [record.role],
+            "extra": { "metadata": { "ls_model_name": record.model, "agent_role": record.role, "cost_usd": record.cost } },
+        });
+        let response = self.client.post(format!("{}/runs", self.endpoint)).timeout(LANGSMITH_TIMEOUT).header("x-api-key", &self.api_key).json(&run).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(AgentError::LLMError(format!("LangSmith rejected the run ({}): {}", status, body)));
+        }
+        Ok(())
+    }
+}
+
+/// Masks secrets in prompts and responses before they leave the process: the configured API keys,
+/// well-known token formats, and any `AGENT_REDACT_PATTERNS`.
+pub struct Redactor {
+    secrets: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    /// A redactor for the literal `secrets` plus the built-in and `extra_patterns` regexes.
+    pub fn new(secrets: Vec<String>, extra_patterns: &[String]) -> Result<Self, AgentError> {
+        let patterns = BUILTIN_SECRET_PATTERNS
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .chain(extra_patterns.iter().cloned())
+            .map(|pattern| Regex::new(&pattern).map_err(|e| AgentError::ConfigError(format!("Invalid redaction pattern '{}': {}", pattern, e))))
+            .collect::<Result<_, _>>()?;
+        // Very short values would mask ordinary words.
+        let secrets = secrets.into_iter().filter(|secret| secret.len() >= 8).collect();
+        Ok(Self { secrets, patterns })
+    }
+
+    /// A redactor for every API key in `config` and its `AGENT_REDACT_PATTERNS`.
+    pub fn from_config(config: &AppConfig) -> Result<Self, AgentError> {
+        let keys = [
+            &config.openai_api_key,
+            &config.anthropic_api_key,
+            &config.google_api_key,
+            &config.deepseek_api_key,
+            &config.mistral_api_key,
+            &config.groq_api_key,
+            &config.brave_search_api_key,
+            &config.tavily_api_key,
+            &config.serpapi_api_key,
+            &config.langsmith_api_key,
+            &config.github_token,
+            &config.gitlab_token,
+        ];
+        Self::new(keys.into_iter().flatten().cloned().collect(), &config.redact_patterns)
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in &self.secrets {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+        for pattern in &self.patterns {
+            text = pattern.replace_all(&text, REDACTED).into_owned();
+        }
+        text
+    }
+}
+
+/// The prompt sink `AGENT_PROMPT_LOG` asks for: LangSmith for `langsmith`, otherwise a JSONL file
+/// at that path. `None` when it is not set.
+pub fn prompt_sink(config: &AppConfig) -> Result<Option<Arc<dyn PromptSink>>, AgentError> {
+    let Some(target) = config.prompt_log.as_deref().map(str::trim).filter(|target| !target.is_empty()) else {
+        return Ok(None);
+    };
+    if target.eq_ignore_ascii_case(LANGSMITH) {
+        let api_key = config
+            .langsmith_api_key
+            .as_deref()
+            .ok_or_else(|| AgentError::ConfigError("AGENT_PROMPT_LOG=langsmith needs LANGSMITH_API_KEY".to_string()))?;
+        return Ok(Some(Arc::new(LangSmith::new(&config.langsmith_endpoint, api_key, &config.langsmith_project))));
+    }
+    Ok(Some(Arc::new(PromptLogFile::open(Path::new(target))?)))
+}
+
+/// Wraps `client` so every call `role` makes through it is recorded in `sink`, with secrets
+/// masked by `redactor`. A sink that fails only logs a warning; the call itself is unaffected.
+pub fn logged(client: Arc<dyn LLMClient>, role: &str, sink: Arc<dyn PromptSink>, redactor: Arc<Redactor>) -> Arc<dyn LLMClient> {
+    Arc::new(PromptLogger { inner: client, role: role.to_string(), sink, redactor })
+}
+
+struct PromptLogger {
+    inner: Arc<dyn LLMClient>,
+    role: String,
+    sink: Arc<dyn PromptSink>,
+    redactor: Arc<Redactor>,
+}
+
+impl PromptLogger {
+    async fn record(&self, messages: &[ChatMessage], started_at: DateTime<Utc>, started: Instant, result: Result<(&AIResponse, Option<&ToolCall>), &AgentError>) {
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let messages = messages.iter().map(|message| ChatMessage::new(message.role, self.redactor.redact(&message.content))).collect();
+        let mut record = PromptRecord {
+            id: new_run_id(),
+            role: self.role.clone(),
+            model: self.inner.get_model_info().await.name,
+            started_at,
+            latency_ms,
+            messages,
+            response: None,
+            tool_call: None,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost: 0.0,
+            error: None,
+        };
+        match result {
+            Ok((response, tool_call)) => {
+                record.model = response.model.clone();
+                record.response = Some(self.redactor.redact(&response.content));
+                record.tool_call = tool_call.map(|call| ToolCall { name: call.name.clone(), arguments: self.redact_json(&call.arguments) });
+                record.input_tokens = response.input_tokens;
+                record.output_tokens = response.output_tokens;
+                record.cost = response.cost;
+            }
+            Err(e) => record.error = Some(self.redactor.redact(&e.to_string())),
+        }
+        if let Err(e) = self.sink.record(&record).await {
+            warn!("Failed to record the {} prompt: {}", self.role, e);
+        }
+    }
+
+    fn redact_json(&self, value: &serde_json::Value) -> serde_json::Value {
+        let redacted = self.redactor.redact(&value.to_string());
+        serde_json::from_str(&redacted).unwrap_or(serde_json::Value::String(redacted))
+    }
+}
+
+#[async_trait]
+impl LLMClient for PromptLogger {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let (started_at, started) = (Utc::now(), Instant::now());
+        let result = self.inner.generate(prompt, params).await;
+        self.record(&[ChatMessage::user(prompt)], started_at, started, result.as_ref().map(|response| (response, None))).await;
+        result
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let (started_at, started) = (Utc::now(), Instant::now());
+        let result = self.inner.generate_chat(messages, params).await;
+        self.record(messages, started_at, started, result.as_ref().map(|response| (response, None))).await;
+        result
+    }
+
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let (started_at, started) = (Utc::now(), Instant::now());
+        let result = self.inner.generate_json(prompt, params).await;
+        self.record(&[ChatMessage::user(prompt)], started_at, started, result.as_ref().map(|response| (response, None))).await;
+        result
+    }
+
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let (started_at, started) = (Utc::now(), Instant::now());
+        let result = self.inner.generate_json_chat(messages, params).await;
+        self.record(messages, started_at, started, result.as_ref().map(|response| (response, None))).await;
+        result
+    }
+
+    async fn generate_stream(&self, messages: &[ChatMessage], params: &GenerationParams, on_text: &OnText<'_>) -> Result<AIResponse, AgentError> {
+        let (started_at, started) = (Utc::now(), Instant::now());
+        let result = self.inner.generate_stream(messages, params, on_text).await;
+        self.record(messages, started_at, started, result.as_ref().map(|response| (response, None))).await;
+        result
+    }
+
+    async fn generate_with_tools(&self, prompt: &str, tools: &[ToolDefinition], params: &GenerationParams) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
+        let (started_at, started) = (Utc::now(), Instant::now());
+        let result = self.inner.generate_with_tools(prompt, tools, params).await;
+        self.record(&[ChatMessage::user(prompt)], started_at, started, result.as_ref().map(|(response, call)| (response, call.as_ref()))).await;
+        result
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        self.inner.list_models().await
+    }
+
+    async fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info().await
+    }
+
+    fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        self.inner.calculate_cost(input_tokens, output_tokens)
+    }
+}
+
+/// A random version 4 UUID, the id format LangSmith expects.
+fn new_run_id() -> String {
+    let random = |salt: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(salt);
+        hasher.finish()
+    };
+    let (high, low) = (random(1), random(2));
+    let high = (high & !0xf000) | 0x4000;
+    let low = (low & !(0xc000 << 48)) | (0x8000 << 48);
+    format!("{:08x}-{:04x}-{:04x}-{:04x}-{:012x}", high >> 32, (high >> 16) & 0xffff, high & 0xffff, low >> 48, low & 0xffff_ffff_ffff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    struct MockLLMClient;
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            Ok(AIResponse {
+                content: format!("echo: {}", prompt),
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 0,
+                cost: 0.01,
+                model: "mock-model".to_string(),
+                provider: "mock-provider".to_string(),
+            })
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo { name: "mock-model".to_string(), input_cost_per_token: 0.0, output_cost_per_token: 0.0 }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    #[test]
+    fn test_redact_masks_keys_and_known_token_formats() {
+        let redactor = Redactor::new(vec!["hunter2-secret".to_string(), "short".to_string()], &[r"internal-\d+".to_string()]).unwrap();
+        let text = "key=hunter2-secret token=sk-abcdefghijklmnopqrstu auth: Bearer abc.def.ghijklmnopqrst host internal-42 short";
+        assert_eq!(redactor.redact(text), "key=[REDACTED] token=[REDACTED] auth: [REDACTED] host [REDACTED] short");
+        assert!(matches!(Redactor::new(Vec::new(), &["(".to_string()]), Err(AgentError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_logged_calls_are_appended_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs/prompts.jsonl");
+        let sink = Arc::new(PromptLogFile::open(&path).unwrap());
+        let redactor = Arc::new(Redactor::new(vec!["top-secret-value".to_string()], &[]).unwrap());
+        let client = logged(Arc::new(MockLLMClient), "planner", sink, redactor);
+
+        client.generate("Use top-secret-value to deploy", &GenerationParams::default()).await.unwrap();
+
+        let records = PromptLogFile::read(&path).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].role, "planner");
+        assert_eq!(records[0].messages, vec![ChatMessage::user("Use [REDACTED] to deploy")]);
+        assert_eq!(records[0].response.as_deref(), Some("echo: Use [REDACTED] to deploy"));
+        assert_eq!((records[0].input_tokens, records[0].output_tokens), (10, 20));
+    }
+
+    #[tokio::test]
+    async fn test_langsmith_posts_llm_runs() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/runs")).and(header("x-api-key", "ls-key")).respond_with(ResponseTemplate::new(202)).expect(1).mount(&server).await;
+        let sink = Arc::new(LangSmith::new(&server.uri(), "ls-key", "agent-tests"));
+        let client = logged(Arc::new(MockLLMClient), "coder", sink, Arc::new(Redactor::new(Vec::new(), &[]).unwrap()));
+
+        client.generate("Write hello world", &GenerationParams::default()).await.unwrap();
+
+        let request = &server.received_requests().await.unwrap()[0];
+        let run: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(run["run_type"], "llm");
+        assert_eq!(run["session_name"], "agent-tests");
+        assert_eq!(run["inputs"]["messages"][0]["content"], "Write hello world");
+        assert_eq!(run["outputs"]["generations"][0]["text"], "echo: Write hello world");
+        assert_eq!(run["id"].as_str().unwrap().len(), 36);
+    }
+}
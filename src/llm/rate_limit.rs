@@ -0,0 +1,306 @@
+use async_trait::async_trait;
+use log::info;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::{flatten_messages, AIResponse, ChatMessage, GenerationParams, LLMClient, LLMProvider, ModelInfo, ModelSpec, OnText, ToolCall, ToolDefinition};
+use crate::{error::AgentError, tokens};
+
+/// The provider name in `AGENT_RATE_LIMITS` whose limit applies to providers without their own.
+pub const ANY_PROVIDER: &str = "default";
+
+/// A provider's request and token budgets per minute, written `openai=500rpm/200000tpm`; either
+/// budget may be left out. Without a provider name (`60rpm`, or `default=60rpm`) it applies to
+/// every provider that has no limit of its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub provider: Option<LLMProvider>,
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+}
+
+impl RateLimit {
+    /// The limit for `provider` among `limits`: its own, else the one for every provider.
+    pub fn find(limits: &[RateLimit], provider: LLMProvider) -> Option<RateLimit> {
+        limits.iter().find(|limit| limit.provider == Some(provider)).or_else(|| limits.iter().find(|limit| limit.provider.is_none())).copied()
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (provider, budgets) = match s.trim().split_once('=') {
+            Some((provider, budgets)) if provider.trim().eq_ignore_ascii_case(ANY_PROVIDER) => (None, budgets),
+            Some((provider, budgets)) => (Some(provider.parse::<ModelSpec>()?.provider), budgets),
+            None => (None, s.trim()),
+        };
+        let mut limit = Self { provider, requests_per_minute: None, tokens_per_minute: None };
+        for budget in budgets.split('/').map(str::trim) {
+            let lower = budget.to_lowercase();
+            let (number, slot) = if let Some(number) = lower.strip_suffix("rpm") {
+                (number, &mut limit.requests_per_minute)
+            } else if let Some(number) = lower.strip_suffix("tpm") {
+                (number, &mut limit.tokens_per_minute)
+            } else {
+                return Err(format!("expected a number followed by rpm or tpm in '{}', e.g. openai=500rpm/200000tpm", s));
+            };
+            match number.trim().replace('_', "").parse::<u32>() {
+                Ok(per_minute) if per_minute > 0 => *slot = Some(per_minute),
+                _ => return Err(format!("invalid rate limit '{}' in '{}': must be a positive number", budget, s)),
+            }
+        }
+        Ok(limit)
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut budgets = Vec::new();
+        if let Some(requests) = self.requests_per_minute {
+            budgets.push(format!("{}rpm", requests));
+        }
+        if let Some(tokens) = self.tokens_per_minute {
+            budgets.push(format!("{}tpm", tokens));
+        }
+        match self.provider {
+            Some(provider) => write!(f, "{}={}", provider.to_string().to_lowercase(), budgets.join("/")),
+            None => write!(f, "{}={}", ANY_PROVIDER, budgets.join("/")),
+        }
+    }
+}
+
+struct Buckets {
+    requests: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Token buckets for one provider's requests and tokens. Both start full and refill continuously,
+/// so a burst of up to a minute's budget goes through at once and the rest is spread out.
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        let buckets = Buckets {
+            requests: limit.requests_per_minute.unwrap_or(0) as f64,
+            tokens: limit.tokens_per_minute.unwrap_or(0) as f64,
+            refilled: Instant::now(),
+        };
+        Self { limit, buckets: Mutex::new(buckets) }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Waits until a request estimated at `tokens` prompt tokens fits both budgets, then takes it
+    /// out of them. A prompt larger than the whole token budget waits for a full bucket.
+    pub async fn acquire(&self, tokens: u32) {
+        let mut waited = false;
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                self.refill(&mut buckets);
+                let mut wait = Duration::ZERO;
+                if let Some(per_minute) = self.limit.requests_per_minute {
+                    wait = wait.max(time_to(1.0 - buckets.requests, per_minute));
+                }
+                if let Some(per_minute) = self.limit.tokens_per_minute {
+                    let needed = (tokens as f64).min(per_minute as f64);
+                    wait = wait.max(time_to(needed - buckets.tokens, per_minute));
+                }
+                if wait.is_zero() {
+                    buckets.requests -= 1.0;
+                    buckets.tokens -= tokens as f64;
+                    return;
+                }
+                wait
+            };
+            if !waited {
+                info!("Waiting {:.1}s for the {} rate limit", wait.as_secs_f64(), self.limit);
+                waited = true;
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes tokens only known once the response arrives (its output) out of the token budget;
+    /// the budget may go negative, delaying the requests that follow.
+    pub fn charge(&self, tokens: u32) {
+        let mut buckets = self.buckets.lock().unwrap();
+        self.refill(&mut buckets);
+        buckets.tokens -= tokens as f64;
+    }
+
+    fn refill(&self, buckets: &mut Buckets) {
+        let now = Instant::now();
+        let minutes = now.duration_since(buckets.refilled).as_secs_f64() / 60.0;
+        buckets.refilled = now;
+        if let Some(per_minute) = self.limit.requests_per_minute {
+            buckets.requests = (buckets.requests + minutes * per_minute as f64).min(per_minute as f64);
+        }
+        if let Some(per_minute) = self.limit.tokens_per_minute {
+            buckets.tokens = (buckets.tokens + minutes * per_minute as f64).min(per_minute as f64);
+        }
+    }
+}
+
+/// How long a bucket refilling at `per_minute` takes to gain `missing`.
+fn time_to(missing: f64, per_minute: u32) -> Duration {
+    if missing <= 0.0 {
+        Duration::ZERO
+    } else {
+        Duration::from_secs_f64(missing * 60.0 / per_minute as f64)
+    }
+}
+
+/// The limiter every client of `provider` in this process shares, so concurrent steps and sessions
+/// draw on one budget. It is replaced when the limit changes.
+pub fn shared_limiter(provider: LLMProvider, limit: RateLimit) -> Arc<RateLimiter> {
+    static LIMITERS: OnceLock<Mutex<HashMap<LLMProvider, Arc<RateLimiter>>>> = OnceLock::new();
+    let mut limiters = LIMITERS.get_or_init(Default::default).lock().unwrap();
+    match limiters.get(&provider) {
+        Some(limiter) if limiter.limit() == limit => limiter.clone(),
+        _ => {
+            let limiter = Arc::new(RateLimiter::new(limit));
+            limiters.insert(provider, limiter.clone());
+            limiter
+        }
+    }
+}
+
+/// Holds each request back until `limiter` allows it.
+pub fn rate_limited(client: Arc<dyn LLMClient>, provider: LLMProvider, limiter: Arc<RateLimiter>) -> Arc<dyn LLMClient> {
+    Arc::new(RateLimited { inner: client, provider, limiter })
+}
+
+struct RateLimited {
+    inner: Arc<dyn LLMClient>,
+    provider: LLMProvider,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimited {
+    async fn acquire(&self, prompt: &str) {
+        self.limiter.acquire(tokens::estimate_tokens(self.provider, prompt) as u32).await;
+    }
+
+    fn charge(&self, response: &AIResponse) {
+        self.limiter.charge(response.output_tokens);
+    }
+}
+
+#[async_trait]
+impl LLMClient for RateLimited {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.acquire(prompt).await;
+        let response = self.inner.generate(prompt, params).await?;
+        self.charge(&response);
+        Ok(response)
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.acquire(&flatten_messages(messages)).await;
+        let response = self.inner.generate_chat(messages, params).await?;
+        self.charge(&response);
+        Ok(response)
+    }
+
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.acquire(prompt).await;
+        let response = self.inner.generate_json(prompt, params).await?;
+        self.charge(&response);
+        Ok(response)
+    }
+
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        self.acquire(&flatten_messages(messages)).await;
+        let response = self.inner.generate_json_chat(messages, params).await?;
+        self.charge(&response);
+        Ok(response)
+    }
+
+    async fn generate_stream(&self, messages: &[ChatMessage], params: &GenerationParams, on_text: &OnText<'_>) -> Result<AIResponse, AgentError> {
+        self.acquire(&flatten_messages(messages)).await;
+        let response = self.inner.generate_stream(messages, params, on_text).await?;
+        self.charge(&response);
+        Ok(response)
+    }
+
+    async fn generate_with_tools(&self, prompt: &str, tools: &[ToolDefinition], params: &GenerationParams) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
+        self.acquire(prompt).await;
+        let (response, call) = self.inner.generate_with_tools(prompt, tools, params).await?;
+        self.charge(&response);
+        Ok((response, call))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        self.inner.list_models().await
+    }
+
+    async fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info().await
+    }
+
+    fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        self.inner.calculate_cost(input_tokens, output_tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_parsing() {
+        let limit: RateLimit = "openai=500rpm/200_000tpm".parse().unwrap();
+        assert_eq!(limit, RateLimit { provider: Some(LLMProvider::OpenAI), requests_per_minute: Some(500), tokens_per_minute: Some(200_000) });
+        assert_eq!(limit.to_string(), "openai=500rpm/200000tpm");
+        let any: RateLimit = "60RPM".parse().unwrap();
+        assert_eq!((any.provider, any.requests_per_minute, any.tokens_per_minute), (None, Some(60), None));
+        assert_eq!("default=10tpm".parse::<RateLimit>().unwrap().to_string(), "default=10tpm");
+        assert!("openai=500".parse::<RateLimit>().is_err());
+        assert!("openai=0rpm".parse::<RateLimit>().is_err());
+        assert!("mystery=5rpm".parse::<RateLimit>().is_err());
+
+        let claude: RateLimit = "claude=50rpm".parse().unwrap();
+        let limits = [any, claude];
+        assert_eq!(RateLimit::find(&limits, LLMProvider::Claude), Some(claude));
+        assert_eq!(RateLimit::find(&limits, LLMProvider::Groq), Some(any));
+        assert_eq!(RateLimit::find(&[claude], LLMProvider::Groq), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limiter_spreads_requests_and_tokens_over_the_minute() {
+        let limiter = RateLimiter::new(RateLimit { provider: None, requests_per_minute: Some(2), tokens_per_minute: Some(1000) });
+        let started = Instant::now();
+        limiter.acquire(100).await;
+        limiter.acquire(100).await;
+        assert_eq!(started.elapsed(), Duration::ZERO);
+        // The third request waits for one request to refill: half a minute at 2 per minute.
+        limiter.acquire(100).await;
+        assert_eq!(started.elapsed().as_secs(), 30);
+
+        // Output tokens overdraw the budget, so the next request waits until 500 tokens are back.
+        limiter.charge(1200);
+        let before = Instant::now();
+        limiter.acquire(500).await;
+        assert_eq!(before.elapsed().as_secs(), 48);
+    }
+
+    #[test]
+    fn test_shared_limiter_is_shared_per_provider_and_limit() {
+        let limit: RateLimit = "mistral=5rpm".parse().unwrap();
+        let first = shared_limiter(LLMProvider::Mistral, limit);
+        assert!(Arc::ptr_eq(&first, &shared_limiter(LLMProvider::Mistral, limit)));
+        assert!(!Arc::ptr_eq(&first, &shared_limiter(LLMProvider::Mistral, "mistral=6rpm".parse().unwrap())));
+    }
+}
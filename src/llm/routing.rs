@@ -0,0 +1,225 @@
+use async_trait::async_trait;
+use log::debug;
+use std::fmt;
+use std::sync::Arc;
+
+use super::{create_llm_client_for, flatten_messages, AIResponse, ChatMessage, GenerationParams, LLMClient, ModelInfo, OnText, ToolCall, ToolDefinition};
+use crate::{config::AppConfig, cost_tracker::CostTracker, error::AgentError, tokens};
+
+/// Decision prompts up to this many tokens go to the cheap model by default.
+pub const DEFAULT_ROUTE_CHEAP_MAX_TOKENS: usize = 2_000;
+/// Generations whose prompt and requested output reach this many tokens go to the strong model by default.
+pub const DEFAULT_ROUTE_STRONG_MIN_TOKENS: usize = 8_000;
+
+/// Where `RoutingClient` sends calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoutingThresholds {
+    /// JSON and tool-call requests (tool decisions, checks) with prompts up to this size go to the
+    /// cheap model.
+    pub cheap_max_tokens: usize,
+    /// Text generations (code, plans) whose prompt plus `max_tokens` reaches this size go to the
+    /// strong model.
+    pub strong_min_tokens: usize,
+}
+
+impl Default for RoutingThresholds {
+    fn default() -> Self {
+        Self { cheap_max_tokens: DEFAULT_ROUTE_CHEAP_MAX_TOKENS, strong_min_tokens: DEFAULT_ROUTE_STRONG_MIN_TOKENS }
+    }
+}
+
+/// The model a call was routed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    Cheap,
+    /// The role's own model, for calls neither heuristic applies to.
+    Default,
+    Strong,
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Route::Cheap => write!(f, "cheap"),
+            Route::Default => write!(f, "default"),
+            Route::Strong => write!(f, "strong"),
+        }
+    }
+}
+
+/// Picks the model for each call: short decision prompts go to a cheap model, large generations
+/// to a strong one, and the rest to the role's own model. Each routed call is recorded in the
+/// cost ledger under its route and model.
+pub struct RoutingClient {
+    default: Arc<dyn LLMClient>,
+    cheap: Option<Arc<dyn LLMClient>>,
+    strong: Option<Arc<dyn LLMClient>>,
+    thresholds: RoutingThresholds,
+    ledger: Option<Arc<CostTracker>>,
+}
+
+impl RoutingClient {
+    /// Routes nothing until a cheap or strong model is set: every call goes to `default`.
+    pub fn new(default: Arc<dyn LLMClient>) -> Self {
+        Self { default, cheap: None, strong: None, thresholds: RoutingThresholds::default(), ledger: None }
+    }
+
+    pub fn with_cheap(mut self, client: Arc<dyn LLMClient>) -> Self {
+        self.cheap = Some(client);
+        self
+    }
+
+    pub fn with_strong(mut self, client: Arc<dyn LLMClient>) -> Self {
+        self.strong = Some(client);
+        self
+    }
+
+    pub fn with_thresholds(mut self, thresholds: RoutingThresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// Records every call's route in `cost_tracker` (see `CostTracker::usage_by_route`).
+    pub fn with_ledger(mut self, cost_tracker: Arc<CostTracker>) -> Self {
+        self.ledger = Some(cost_tracker);
+        self
+    }
+
+    /// The route for a call with `prompt`: `decision` for JSON and tool-call requests, otherwise a
+    /// text generation. Routes without a client fall back to the default.
+    pub fn route(&self, prompt: &str, params: &GenerationParams, decision: bool) -> Route {
+        let prompt_tokens = tokens::approximate_tokens(prompt);
+        if decision && self.cheap.is_some() && prompt_tokens <= self.thresholds.cheap_max_tokens {
+            return Route::Cheap;
+        }
+        let expected = prompt_tokens + params.max_tokens.unwrap_or(0) as usize;
+        if !decision && self.strong.is_some() && expected >= self.thresholds.strong_min_tokens {
+            return Route::Strong;
+        }
+        Route::Default
+    }
+
+    fn client(&self, route: Route) -> &Arc<dyn LLMClient> {
+        match route {
+            Route::Cheap => self.cheap.as_ref(),
+            Route::Strong => self.strong.as_ref(),
+            Route::Default => None,
+        }
+        .unwrap_or(&self.default)
+    }
+
+    fn record(&self, route: Route, response: &AIResponse) {
+        debug!("Routed a {}-token prompt to the {} model ({})", response.input_tokens, route, response.model);
+        if let Some(ledger) = &self.ledger {
+            ledger.record_route(&route.to_string(), response);
+        }
+    }
+}
+
+/// `client` routed as configured by `AGENT_ROUTE_CHEAP_MODEL`, `AGENT_ROUTE_STRONG_MODEL` and their
+/// thresholds, recording routes in `ledger`; `client` itself when no routing model is set.
+pub fn routed(client: Arc<dyn LLMClient>, config: &Arc<AppConfig>, ledger: &Arc<CostTracker>) -> Result<Arc<dyn LLMClient>, AgentError> {
+    if config.route_cheap_model.is_none() && config.route_strong_model.is_none() {
+        return Ok(client);
+    }
+    let mut router = RoutingClient::new(client).with_thresholds(config.routing_thresholds()).with_ledger(ledger.clone());
+    if let Some(spec) = &config.route_cheap_model {
+        router = router.with_cheap(create_llm_client_for(spec, config.clone())?);
+    }
+    if let Some(spec) = &config.route_strong_model {
+        router = router.with_strong(create_llm_client_for(spec, config.clone())?);
+    }
+    Ok(Arc::new(router))
+}
+
+#[async_trait]
+impl LLMClient for RoutingClient {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let route = self.route(prompt, params, false);
+        let response = self.client(route).generate(prompt, params).await?;
+        self.record(route, &response);
+        Ok(response)
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let route = self.route(&flatten_messages(messages), params, false);
+        let response = self.client(route).generate_chat(messages, params).await?;
+        self.record(route, &response);
+        Ok(response)
+    }
+
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let route = self.route(prompt, params, true);
+        let response = self.client(route).generate_json(prompt, params).await?;
+        self.record(route, &response);
+        Ok(response)
+    }
+
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let route = self.route(&flatten_messages(messages), params, true);
+        let response = self.client(route).generate_json_chat(messages, params).await?;
+        self.record(route, &response);
+        Ok(response)
+    }
+
+    async fn generate_stream(&self, messages: &[ChatMessage], params: &GenerationParams, on_text: &OnText<'_>) -> Result<AIResponse, AgentError> {
+        let route = self.route(&flatten_messages(messages), params, false);
+        let response = self.client(route).generate_stream(messages, params, on_text).await?;
+        self.record(route, &response);
+        Ok(response)
+    }
+
+    async fn generate_with_tools(&self, prompt: &str, tools: &[ToolDefinition], params: &GenerationParams) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
+        let route = self.route(prompt, params, true);
+        let (response, call) = self.client(route).generate_with_tools(prompt, tools, params).await?;
+        self.record(route, &response);
+        Ok((response, call))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        self.default.list_models().await
+    }
+
+    async fn get_model_info(&self) -> ModelInfo {
+        self.default.get_model_info().await
+    }
+
+    fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        self.default.calculate_cost(input_tokens, output_tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::MockClient;
+
+    #[tokio::test]
+    async fn test_routing_client_sends_calls_by_size_and_kind() {
+        let client = RoutingClient::new(Arc::new(MockClient::new("default", Vec::new())))
+            .with_cheap(Arc::new(MockClient::replaying(["cheap"])))
+            .with_strong(Arc::new(MockClient::replaying(["strong"])))
+            .with_thresholds(RoutingThresholds { cheap_max_tokens: 100, strong_min_tokens: 1000 });
+        let params = GenerationParams::default();
+        let short = "Pick a tool.";
+        let long = "word ".repeat(2000);
+        assert_eq!(client.route(short, &params, true), Route::Cheap);
+        assert_eq!(client.route(&long, &params, true), Route::Default);
+        assert_eq!(client.route(short, &params, false), Route::Default);
+        assert_eq!(client.route(&long, &params, false), Route::Strong);
+        // A large requested output counts towards the generation's size.
+        assert_eq!(client.route(short, &GenerationParams { max_tokens: Some(4000), ..params }, false), Route::Strong);
+
+        let ledger = Arc::new(CostTracker::new());
+        let client = client.with_ledger(ledger.clone());
+        assert_eq!(client.generate_json(short, &params).await.unwrap().content, "cheap");
+        assert_eq!(client.generate(&long, &params).await.unwrap().content, "strong");
+        let routes = ledger.usage_by_route();
+        assert_eq!(routes.keys().cloned().collect::<Vec<_>>(), vec!["cheap (mock)".to_string(), "strong (mock)".to_string()]);
+        assert_eq!(routes["cheap (mock)"].calls, 1);
+        assert_eq!(client.get_model_info().await.name, "default");
+
+        let unrouted = RoutingClient::new(Arc::new(MockClient::new("default", Vec::new())));
+        assert_eq!(unrouted.route(short, &params, true), Route::Default);
+    }
+}
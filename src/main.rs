@@ -1,46 +1,1711 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::*;
 
-use log::{info, error};
+use log::{info, error, warn};
 use std::io::{self, Write};
-use std::sync::Arc;
-
-mod agents;
-mod config;
-mod cost_tracker;
-mod error;
-mod llm;
-mod orchestrator;
-mod state;
-mod tools;
-
-use config::AppConfig;
-use llm::{create_llm_client, LLMProvider};
-use orchestrator::Orchestrator;
-use crate::cost_tracker::CostTracker;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use cli_coding_agent::{
+    agent::{Agent, AgentBuilder},
+    agents::{documenter::DocumenterAgent, explainer::ExplainerAgent, reviewer::ReviewerAgent, scribe::{self, ScribeAgent, CHANGELOG_FILE}},
+    bench::{self, BenchReport, BenchResult},
+    approval::{Approver, ConsoleApprover, ConsoleCostApprover, ConsoleSteering},
+    config::AppConfig,
+    conversation::ConversationMemory,
+    cost_tracker::CostTracker,
+    doc_gen,
+    error::AgentError,
+    events::{EventRecord, EventSink, JsonlTranscript},
+    issue::{IssueClient, IssueRef},
+    journal::{Journal, JOURNAL_FILE},
+    llm::{self, create_llm_client, create_llm_client_for, pricing::PricingTable, prompt_log::{self, Redactor}, routing, GenerationParams, LLMClient, LLMProvider, ModelSpec},
+    memory::{create_embedder, Memory, MEMORY_FILE},
+    orchestrator::colorize_diff,
+    plan_file::{self, StepSelection},
+    project::{ProjectInstructions, ProjectLanguage},
+    pull_request::{self, Forge, PullRequest, PullRequestClient, Remote},
+    queue::{self, TaskQueue, QUEUE_FILE, TRANSCRIPTS_DIR},
+    repl::{self, GoalReader, ModelRole, SessionLog, SlashCommand},
+    reporter::ConsoleReporter,
+    review::{self, DiffSource},
+    run_report::{self, RunOutcome, RunReport},
+    session_store::{self, SessionStore},
+    state::{goal_headline, AppState, SESSION_FILE},
+    stats::{self, SessionStats},
+    test_gen::{TestGeneration, TestTarget},
+    tools::{self, create_search_provider, CommandLimits, Sandbox, Shell, Tool, ToolContext, ToolRegistry},
+    tui::Tui,
+    watch::{self, WorkspaceWatcher},
+};
+#[cfg(feature = "otel")]
+use cli_coding_agent::telemetry::{self, Tracer};
 
 /// A CLI Coding Agent powered by Large Language Models
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The LLM provider to use for generation
-    #[arg(long, value_enum, default_value_t = LLMProvider::OpenAI)]
+    #[arg(long, global = true, value_enum, default_value_t = LLMProvider::OpenAI)]
     provider: LLMProvider,
+
+    /// Model for planning as provider[:model], e.g. claude:claude-3-opus-20240229 (default: the reasoner)
+    #[arg(long, global = true, value_name = "PROVIDER[:MODEL]")]
+    planner_model: Option<ModelSpec>,
+
+    /// Model for code generation as provider[:model] (default: --provider with its configured model)
+    #[arg(long, global = true, value_name = "PROVIDER[:MODEL]")]
+    coder_model: Option<ModelSpec>,
+
+    /// Model for tool decisions, reviews and summaries as provider[:model], e.g. openai:gpt-4o-mini (default: openai)
+    #[arg(long, global = true, value_name = "PROVIDER[:MODEL]")]
+    reasoner_model: Option<ModelSpec>,
+
+    /// Generation parameters for planning, e.g. temperature=0.7,max_tokens=8192,top_p=0.9 (default: PLANNER_PARAMS)
+    #[arg(long, global = true, value_name = "KEY=VALUE,...")]
+    planner_params: Option<GenerationParams>,
+
+    /// Generation parameters for code generation (default: CODER_PARAMS)
+    #[arg(long, global = true, value_name = "KEY=VALUE,...")]
+    coder_params: Option<GenerationParams>,
+
+    /// Generation parameters for tool decisions, reviews and summaries (default: REASONER_PARAMS)
+    #[arg(long, global = true, value_name = "KEY=VALUE,...")]
+    reasoner_params: Option<GenerationParams>,
+
+    /// Resume the interrupted session saved in .agent/session.json (same as `chat --resume`)
+    #[arg(long)]
+    resume: bool,
+
+    /// Goal to run once, after which the agent exits (same as `run GOAL`)
+    #[arg(value_name = "GOAL", conflicts_with = "goal")]
+    goal_arg: Option<String>,
+
+    /// Goal to run once, after which the agent exits (same as `run GOAL`)
+    #[arg(long)]
+    goal: Option<String>,
+
+    /// Never prompt on stdin; exit non-zero if the task fails
+    #[arg(long, global = true)]
+    non_interactive: bool,
+
+    /// Write a JSONL transcript of structured run events (plan, steps, decisions, tool results) to this file
+    #[arg(long, global = true, value_name = "PATH")]
+    transcript: Option<PathBuf>,
+
+    /// Directory the agent works in: file tools, commands, context gathering and the session file are
+    /// all relative to it (default: the current directory)
+    #[arg(long, global = true, value_name = "PATH")]
+    workdir: Option<PathBuf>,
+
+    /// Show the run in a full-screen terminal UI with the plan, live output, history and cost
+    #[arg(long, global = true, conflicts_with_all = ["non_interactive", "output"])]
+    tui: bool,
+
+    /// Output format: decorated text for people, or newline-delimited JSON events on stdout for programs
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Preview the run: file writes and edits are shown as diffs and commands are printed, but nothing is changed or executed
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Work without the internet: Search, FetchUrl and HttpRequest are disabled and every role runs on Ollama
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Show the diff of every file write or edit and ask before applying it
+    #[arg(long, global = true, conflicts_with_all = ["non_interactive", "tui", "output", "dry_run"])]
+    confirm: bool,
+
+    /// After each step that changed files, ask for feedback to steer the next step
+    #[arg(long, global = true, conflicts_with_all = ["non_interactive", "tui", "output", "dry_run"])]
+    feedback: bool,
+
+    /// File whose current content is always part of the planner and coder context; repeat to pin several
+    #[arg(long = "context", global = true, value_name = "PATH")]
+    context: Vec<String>,
+
+    /// Responses for the mock provider to replay (TOML, JSON or YAML), e.g. with --provider mock (default: AGENT_MOCK_SCRIPT)
+    #[arg(long, visible_alias = "script", global = true, value_name = "PATH")]
+    mock_script: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Run one goal to completion and exit; the exit code reports whether it succeeded
+    Run {
+        /// The goal to achieve, or - to read it from stdin (may be omitted with --resume, --from-issue or --goal-file)
+        #[arg(required_unless_present_any = ["resume", "from_issue", "goal_file"])]
+        goal: Option<String>,
+
+        /// Read the goal from this file (- for stdin), e.g. a long goal with code blocks or logs
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["goal", "from_issue"])]
+        goal_file: Option<PathBuf>,
+
+        /// Work on this GitHub issue, e.g. owner/repo#123: its title and description become the goal and its comments the initial context
+        #[arg(long, value_name = "OWNER/REPO#N", conflicts_with = "goal")]
+        from_issue: Option<IssueRef>,
+
+        /// Finish the interrupted session in .agent/session.json first
+        #[arg(long)]
+        resume: bool,
+
+        /// Execute the steps in this file (one per line, as written by `plan --save`) instead of planning
+        #[arg(long, value_name = "PATH", requires = "goal", conflicts_with = "edit_plan")]
+        plan_file: Option<PathBuf>,
+
+        /// Start the plan file at this step, e.g. to rerun the steps after the one that failed
+        #[arg(long, value_name = "N", requires = "plan_file")]
+        from_step: Option<usize>,
+
+        /// Run only this step of the plan file, without re-planning or verifying the goal
+        #[arg(long, value_name = "N", requires = "plan_file", conflicts_with_all = ["from_step", "skip_step"])]
+        only_step: Option<usize>,
+
+        /// Leave these steps of the plan file out (repeatable or comma-separated)
+        #[arg(long, value_name = "N", requires = "plan_file", value_delimiter = ',')]
+        skip_step: Vec<usize>,
+
+        /// Open the generated plan in $EDITOR and execute the steps as saved
+        #[arg(long, requires = "goal", conflicts_with = "non_interactive")]
+        edit_plan: bool,
+
+        /// If the run fails, restore every file it wrote or edited (same as running `undo`)
+        #[arg(long)]
+        rollback_on_failure: bool,
+
+        /// If the run succeeds, commit and push its branch and open a pull request (GITHUB_TOKEN or GITLAB_TOKEN)
+        #[arg(long)]
+        create_pr: bool,
+
+        /// Name the session, so `sessions show` and `sessions resume` can refer to it by name
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+    },
+    /// Prompt for goals interactively until 'quit' (the default without a subcommand)
+    Chat {
+        /// Finish the interrupted session in .agent/session.json before prompting
+        #[arg(long)]
+        resume: bool,
+    },
+    /// Answer a question about the codebase, only reading files and never changing them
+    Explain {
+        /// The question, or - to read it from stdin
+        question: String,
+    },
+    /// Review a diff and list the problems found, by severity, file and line
+    Review {
+        /// Review the staged changes instead of all uncommitted ones
+        #[arg(long, conflicts_with = "range")]
+        staged: bool,
+
+        /// Review the changes of a range of commits, e.g. main..HEAD
+        #[arg(long, value_name = "A..B")]
+        range: Option<String>,
+
+        /// Print the findings as the JSON body of a GitHub pull request review
+        #[arg(long)]
+        github: bool,
+    },
+    /// Write doc comments for the undocumented public items of Rust files, showing each file's diff for approval
+    DocGen {
+        /// Files or directories to document (default: src)
+        #[arg(value_name = "PATH", default_value = "src")]
+        paths: Vec<PathBuf>,
+
+        /// Apply the doc comments without asking for approval
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Write a commit message for the staged changes and commit them once approved
+    Commit {
+        /// Commit without asking for approval
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Write tests for a file or one function in it, run them, and fix them until they pass
+    TestGen {
+        /// The file, or a function in it, e.g. src/foo.rs::parse_config
+        #[arg(value_name = "PATH[::FUNCTION]")]
+        target: TestTarget,
+    },
+    /// Create and print the plan for a goal without executing any of it
+    Plan {
+        /// The goal to plan, or - to read it from stdin
+        goal: String,
+
+        /// Also write the plan to this file, ready to edit and pass to `run --plan-file`
+        #[arg(long, value_name = "PATH")]
+        save: Option<PathBuf>,
+    },
+    /// Run a standing goal, e.g. "keep the tests passing", and again whenever files in the working directory change
+    Watch {
+        /// The goal to keep achieving, or - to read it from stdin
+        goal: String,
+
+        /// Milliseconds the files must stay unchanged before the goal runs again
+        #[arg(long, value_name = "MS", default_value_t = watch::DEFAULT_DEBOUNCE.as_millis() as u64)]
+        debounce: u64,
+
+        /// Dollars each run of the goal may spend before it is stopped (default: AGENT_MAX_COST)
+        #[arg(long, value_name = "DOLLARS")]
+        budget: Option<f64>,
+    },
+    /// Add a goal to the task queue in .agent/queue.json for `worker` to run later
+    Enqueue {
+        /// The goal to queue, or - to read it from stdin
+        goal: String,
+
+        /// Dollars the task may spend before it is stopped (default: the worker's --budget)
+        #[arg(long, value_name = "DOLLARS")]
+        budget: Option<f64>,
+    },
+    /// Show the queued tasks and how the ones already run ended
+    Queue,
+    /// Run the queued tasks one after another until the queue is empty
+    Worker {
+        /// Dollars each task without its own budget may spend (default: AGENT_MAX_COST)
+        #[arg(long, value_name = "DOLLARS")]
+        budget: Option<f64>,
+
+        /// Directory for the tasks' transcripts, one task-<ID>.jsonl per task
+        #[arg(long, value_name = "PATH", default_value = TRANSCRIPTS_DIR)]
+        transcripts: PathBuf,
+    },
+    /// Inspect the tools the agent can use
+    #[command(subcommand)]
+    Tools(ToolsCommand),
+    /// Show or check the configuration loaded from the environment and .env
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Query the providers for the models you can use
+    #[command(subcommand)]
+    Models(ModelsCommand),
+    /// List, inspect, resume or delete the sessions saved across every directory
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+    /// Show token usage, latency and cost recorded across past sessions
+    Stats,
+    /// Restore the files written or edited by the last run to their contents before it
+    Undo,
+    /// Probe every API key, Ollama, web search, git and the shell, and print what will and won't work
+    Doctor,
+    /// Run a suite of benchmark tasks and compare success rate, steps, cost and time per model
+    Bench {
+        /// Directory with one subdirectory per task, each holding a task.toml and optionally a fixture/
+        dir: PathBuf,
+
+        /// Run the suite with every role on this model; repeat to compare (default: the configured models)
+        #[arg(long = "model", value_name = "PROVIDER[:MODEL]")]
+        models: Vec<ModelSpec>,
+
+        /// Also write the results and per-model summaries to this file as JSON
+        #[arg(long, value_name = "PATH")]
+        report: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ToolsCommand {
+    /// List every tool with its description
+    List,
+    /// Print the JSON Schema decisions are validated against
+    Schema,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ModelsCommand {
+    /// List the models of every provider with an API key (and a reachable Ollama), with their prices
+    List,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum SessionsCommand {
+    /// List the saved sessions, most recent first, with their progress and directory
+    List,
+    /// Show a session's goal, plan, changed files and latest history
+    Show {
+        /// The session's id, name or the start of its id
+        id: String,
+    },
+    /// Continue a session in the directory it was started in
+    Resume {
+        /// The session's id, name or the start of its id
+        id: String,
+    },
+    /// Delete a saved session
+    Delete {
+        /// The session's id, name or the start of its id
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigCommand {
+    /// Print the effective settings (API keys are only reported as set or not set)
+    Show,
+    /// Check that the settings are usable: numbers parse, API keys exist for the chosen models,
+    /// and the pricing file and sandbox patterns load
+    Validate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Prints human-oriented output, which `--output json` suppresses so stdout carries only events.
+macro_rules! say {
+    ($cli:expr, $($arg:tt)*) => {
+        if $cli.output == OutputFormat::Text {
+            println!($($arg)*);
+        }
+    };
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let current_exe = std::env::current_exe()?;                                                                                             
-    let project_root = current_exe.parent().and_then(|p| p.parent()).and_then(|p| p.parent()).unwrap_or_else(|| std::path::Path::new("."));  
+    let project_root = current_exe.parent().and_then(|p| p.parent()).and_then(|p| p.parent()).unwrap_or_else(|| Path::new("."));  
     let dotenv_path = project_root.join(".env");                                                                                             
     dotenvy::from_path(dotenv_path).ok();   
 
     env_logger::builder().filter_level(log::LevelFilter::Info).init();
 
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    // Absolute, so sessions record where their transcript is even after --workdir changes directory.
+    cli.transcript = cli.transcript.map(|path| std::path::absolute(&path).unwrap_or(path));
     info!("CLI arguments parsed successfully.");
 
+    let command = match cli.command.clone().map_or_else(|| default_command(&cli), Ok) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("{} {}", "❌".bold().red(), message.bold().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    let command = match read_goal_input(command) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{} {}", "❌".bold().red(), e.to_string().bold().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    match command {
+        Command::Tools(ToolsCommand::List) => return list_tools(&cli),
+        Command::Tools(ToolsCommand::Schema) => {
+            let registry = tool_registry(&load_config(&cli)?, Path::new("."))?;
+            println!("{}", serde_json::to_string_pretty(&registry.decision_schema())?);
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Config(ConfigCommand::Show) => return show_config(&cli),
+        Command::Config(ConfigCommand::Validate) => return Ok(validate_config(&cli).await),
+        Command::Models(ModelsCommand::List) => return list_models(&cli).await,
+        Command::Sessions(SessionsCommand::List) => return list_sessions(&cli),
+        Command::Sessions(SessionsCommand::Show { id }) => return show_session(&cli, &id),
+        Command::Sessions(SessionsCommand::Delete { id }) => return delete_session(&cli, &id),
+        Command::Commit { yes } => return commit(&cli, yes).await,
+        Command::Enqueue { goal, budget } => return enqueue(&cli, goal, budget),
+        Command::Queue => return show_queue(&cli),
+        Command::Stats => return show_stats(&cli),
+        Command::Undo => return undo(&cli),
+        Command::Doctor => return Ok(doctor(&cli).await),
+        Command::Chat { .. } if cli.non_interactive => {
+            eprintln!("{}", "❌ chat reads goals from stdin; use `run GOAL` with --non-interactive.".bold().red());
+            return Ok(ExitCode::FAILURE);
+        }
+        Command::Run { .. } | Command::Chat { .. } | Command::Explain { .. } | Command::Review { .. } | Command::TestGen { .. } | Command::DocGen { .. } | Command::Plan { .. } | Command::Watch { .. } | Command::Worker { .. } | Command::Bench { .. } | Command::Sessions(SessionsCommand::Resume { .. }) => {}
+    }
+
+    if cli.output == OutputFormat::Text {
+        print_banner(&cli);
+    }
+    handle_ctrl_c();
+
+    let mut config = load_config(&cli)?;
+    // Tools run with this config (see `AgentBuilder::config`), so the flag reaches every tool call.
+    config.dry_run |= cli.dry_run;
+    config.confirm_writes |= cli.confirm;
+    config.step_feedback |= cli.feedback;
+    config.planner_params = cli.planner_params.unwrap_or(config.planner_params);
+    config.coder_params = cli.coder_params.unwrap_or(config.coder_params);
+    config.reasoner_params = cli.reasoner_params.unwrap_or(config.reasoner_params);
+    let config = Arc::new(config);
+    info!("Configuration loaded.");
+    if config.dry_run {
+        say!(cli, "{}", "//: DRY RUN: no files will be written and no commands will be run.".yellow().bold());
+    }
+    if config.offline {
+        say!(cli, "{}", "//: OFFLINE: network tools are disabled and every role runs on a local model.".yellow().bold());
+    }
+
+    let models = RoleModels::resolve(&cli, &config);
+    say!(cli, "{} {}", "//: PLANNER:".yellow().bold(), models.planner.to_string().white());
+    say!(cli, "{} {}", "//: CODER:".yellow().bold(), models.coder.to_string().white());
+    say!(cli, "{} {}", "//: REASONER:".yellow().bold(), models.reasoner.to_string().white());
+    say!(cli, "{} {}", "//: SUMMARIZER:".yellow().bold(), models.summarizer.to_string().white());
+    say!(cli, "");
+    if config.validate_models {
+        if let Err(message) = check_models(&models, &config).await {
+            eprintln!("{} {}", "❌".bold().red(), message.bold().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    start_tracing(&cli, &config);
+
+    // One transcript covers every goal run in this session.
+    let transcript: Option<Arc<dyn EventSink>> = match &cli.transcript {
+        Some(path) => {
+            say!(cli, "{} {}", "//: TRANSCRIPT:".yellow().bold(), path.display().to_string().white());
+            Some(Arc::new(JsonlTranscript::create(path)?))
+        }
+        None => None,
+    };
+
+    // Entered after the transcript is created so a relative --transcript path is taken from where
+    // the agent was launched.
+    if let Some(workdir) = &cli.workdir {
+        match enter_workdir(workdir) {
+            Ok(root) => say!(cli, "{} {}", "//: WORKDIR:".yellow().bold(), root.display().to_string().white()),
+            Err(e) => {
+                eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+
+    match command {
+        Command::Run { goal, goal_file: _, from_issue, resume, plan_file, from_step, only_step, skip_step, edit_plan, rollback_on_failure, create_pr, name } => {
+            let mut succeeded = true;
+            let mut finished = None;
+            // The pull request commits only the run's files, which must not mix in the user's staged work.
+            let staged_before = if create_pr && !config.dry_run { pull_request::staged_files(&[]).await.unwrap_or_default() } else { Vec::new() };
+            if resume {
+                if let Some(outcome) = resume_session(&cli, &config, &models, transcript.as_ref()).await? {
+                    succeeded = outcome.succeeded;
+                    finished = Some(outcome);
+                }
+            }
+            let issue = match &from_issue {
+                Some(reference) => {
+                    say!(cli, "{} {}", "📥 Fetching issue".yellow(), reference);
+                    Some(IssueClient::new(config.github_token.as_deref()).with_client(llm::http::shared_client(&config)?).fetch(reference).await?)
+                }
+                None => None,
+            };
+            // Run the given goal once and report the result through the exit code.
+            if let Some(goal) = goal.or_else(|| issue.as_ref().map(|issue| issue.goal())) {
+                if cli.output == OutputFormat::Text {
+                    print_objective(&goal);
+                }
+                let mut state = AppState::new(goal);
+                state.name = name;
+                if let Some(discussion) = issue.as_ref().and_then(|issue| issue.discussion()) {
+                    state.add_history("GitHub Issue Comments", &discussion);
+                }
+                let mut config = config.clone();
+                if let Some(path) = &plan_file {
+                    let steps = plan_file::read(path)?;
+                    let selection = StepSelection { from: from_step, only: only_step, skip: skip_step };
+                    state.plan = selection.apply(&steps)?;
+                    if let Some(number) = only_step {
+                        say!(cli, "{} {}/{}: {}", "🎯 Running only step".yellow(), number, steps.len(), state.plan[0]);
+                        // A single step is not expected to achieve the goal on its own.
+                        config = Arc::new(AppConfig { replan: false, verify: false, ..(*config).clone() });
+                    } else if !selection.is_empty() {
+                        say!(cli, "{} {} of {} steps from {}", "🎯 Running".yellow(), state.plan.len(), steps.len(), path.display());
+                    }
+                } else if edit_plan {
+                    let Some(steps) = edit_plan_for(&state.goal, &cli, &config, &models, transcript.as_ref()).await? else {
+                        return Ok(ExitCode::FAILURE);
+                    };
+                    state.plan = steps;
+                }
+                let outcome = run_goal(state, &cli, &config, &models, transcript.as_ref()).await?;
+                succeeded = outcome.succeeded;
+                finished = Some(outcome);
+            }
+            if succeeded && create_pr {
+                if config.dry_run {
+                    say!(cli, "{}", "Dry run: not opening a pull request.".yellow());
+                } else {
+                    // Only a resumed session that had already completed, so its checkpoint holds what it did.
+                    let (state, cost_tracker) = match finished {
+                        Some(outcome) => (outcome.state, outcome.cost_tracker),
+                        None => (AppState::load(Path::new(SESSION_FILE))?, Arc::new(CostTracker::new())),
+                    };
+                    if let Err(e) = open_pull_request(&cli, &config, &models, &state, &staged_before, &cost_tracker).await {
+                        eprintln!("{} {} {}", "❌".bold().red(), "Could not open a pull request:".bold().red(), e.to_string().red());
+                        succeeded = false;
+                    }
+                }
+            }
+            if !succeeded && rollback_on_failure && !config.dry_run {
+                say!(cli, "{}", "↩️  Rolling back the files changed by the failed run...".yellow());
+                rollback_last_run(&cli)?;
+            }
+            Ok(exit_code(succeeded))
+        }
+        Command::Chat { resume } => {
+            if resume {
+                resume_session(&cli, &config, &models, transcript.as_ref()).await?;
+            }
+            chat(&cli, &config, models, transcript.as_ref()).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Explain { question } => explain(&question, &cli, &config, &models).await,
+        Command::Review { staged, range, github } => {
+            let source = match range {
+                Some(range) => DiffSource::Range(range),
+                None if staged => DiffSource::Staged,
+                None => DiffSource::Uncommitted,
+            };
+            review_diff(&source, github, &cli, &config, &models).await
+        }
+        Command::TestGen { target } => {
+            let generation = match TestGeneration::prepare(Path::new("."), target) {
+                Ok(generation) => generation,
+                Err(e) => {
+                    eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+                    return Ok(ExitCode::FAILURE);
+                }
+            };
+            say!(cli, "{} {} {} {}", "🧪 Writing".yellow(), generation.framework, "tests in".yellow(), generation.test_file.display());
+            let mut state = AppState::new(generation.goal());
+            if cli.output == OutputFormat::Text {
+                print_objective(&state.goal);
+            }
+            state.plan = generation.steps();
+            state.add_history("Target Code", &generation.context());
+            Ok(exit_code(run_goal(state, &cli, &config, &models, transcript.as_ref()).await?.succeeded))
+        }
+        Command::DocGen { paths, yes } => document(&paths, yes, &cli, &config, &models).await,
+        Command::Plan { goal, save } => {
+            if cli.output == OutputFormat::Text {
+                print_objective(&goal);
+            }
+            let Some(steps) = plan_goal(goal.clone(), &cli, &config, &models, transcript.as_ref()).await? else {
+                return Ok(ExitCode::FAILURE);
+            };
+            if let Some(path) = save {
+                plan_file::write(&path, &goal, &steps)?;
+                say!(cli, "{} {}", "💾 Plan saved to".green(), path.display());
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Watch { goal, debounce, budget } => {
+            if cli.output == OutputFormat::Text {
+                print_objective(&goal);
+            }
+            let config = match budget {
+                Some(dollars) => Arc::new(AppConfig { max_cost: Some(dollars), ..(*config).clone() }),
+                None => config,
+            };
+            watch(goal, Duration::from_millis(debounce), &cli, &config, &models, transcript.as_ref()).await
+        }
+        Command::Worker { budget, transcripts } => work_through_queue(budget, &transcripts, &cli, &config, &models).await,
+        Command::Bench { dir, models: specs, report } => bench(&cli, &config, &models, specs, &dir, report.as_deref(), transcript.as_ref()).await,
+        Command::Sessions(SessionsCommand::Resume { id }) => {
+            let session = match session_store(&config).map(|store| store.find(&id)) {
+                Some(Ok(session)) => session,
+                Some(Err(e)) => {
+                    eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+                    return Ok(ExitCode::FAILURE);
+                }
+                None => {
+                    eprintln!("{}", "❌ Cannot locate the sessions directory: set AGENT_SESSIONS_DIR or HOME.".bold().red());
+                    return Ok(ExitCode::FAILURE);
+                }
+            };
+            // --workdir wins, e.g. for a repository that has moved since the session started.
+            if let Some(workdir) = session.state.workdir.as_ref().filter(|_| cli.workdir.is_none()) {
+                match enter_workdir(workdir) {
+                    Ok(root) => say!(cli, "{} {}", "//: WORKDIR:".yellow().bold(), root.display().to_string().white()),
+                    Err(e) => {
+                        eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+                        return Ok(ExitCode::FAILURE);
+                    }
+                }
+            }
+            let outcome = continue_session(session.state, &cli, &config, &models, transcript.as_ref()).await?;
+            Ok(exit_code(outcome.is_none_or(|outcome| outcome.succeeded)))
+        }
+        Command::Tools(_) | Command::Config(_) | Command::Models(_) | Command::Sessions(_) | Command::Commit { .. } | Command::Enqueue { .. } | Command::Queue | Command::Stats | Command::Undo | Command::Doctor => unreachable!("handled before the session starts"),
+    }
+}
+
+/// The run Ctrl-C stops. With no run in progress Ctrl-C exits, except at the chat prompt of a
+/// terminal, where the line editor reads it as a key that clears the line.
+static CURRENT_RUN: Mutex<Option<CancellationToken>> = Mutex::new(None);
+
+/// The first Ctrl-C cancels the current run, which aborts the in-flight step and saves the session
+/// checkpoint; a second one exits immediately.
+fn handle_ctrl_c() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            let run = CURRENT_RUN.lock().unwrap().clone();
+            match run {
+                Some(token) if !token.is_cancelled() => {
+                    eprintln!("\n{}", "⏹️  Stopping the current step... press Ctrl-C again to quit immediately.".yellow().bold());
+                    token.cancel();
+                }
+                _ => std::process::exit(130),
+            }
+        }
+    });
+}
+
+/// Exports a span per run, step, tool execution and LLM call when `OTEL_EXPORTER_OTLP_ENDPOINT`
+/// is set; created once by `start_tracing`.
+#[cfg(feature = "otel")]
+static TRACER: std::sync::OnceLock<Arc<Tracer>> = std::sync::OnceLock::new();
+
+#[cfg(feature = "otel")]
+fn start_tracing(cli: &Cli, config: &AppConfig) {
+    if let Some(tracer) = Tracer::from_config(config) {
+        say!(cli, "{} {}", "//: TRACES:".yellow().bold(), config.otel_endpoint.as_deref().unwrap_or_default().white());
+        let _ = TRACER.set(tracer);
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn start_tracing(_cli: &Cli, config: &AppConfig) {
+    if config.otel_endpoint.is_some() {
+        warn!("OTEL_EXPORTER_OTLP_ENDPOINT is set, but this build cannot export traces; rebuild with `--features otel`.");
+    }
+}
+
+/// `client`, recording a span for every call `role` makes when traces are exported.
+#[cfg(feature = "otel")]
+fn traced(client: Arc<dyn LLMClient>, role: &str) -> Arc<dyn LLMClient> {
+    match TRACER.get() {
+        Some(tracer) => telemetry::traced(client, role, tracer.clone()),
+        None => client,
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+fn traced(client: Arc<dyn LLMClient>, _role: &str) -> Arc<dyn LLMClient> {
+    client
+}
+
+#[cfg(feature = "otel")]
+fn trace_sink() -> Option<Arc<dyn EventSink>> {
+    TRACER.get().map(|tracer| tracer.clone() as Arc<dyn EventSink>)
+}
+
+#[cfg(not(feature = "otel"))]
+fn trace_sink() -> Option<Arc<dyn EventSink>> {
+    None
+}
+
+/// Sends the spans of the run that just ended; an unreachable collector only costs a warning.
+async fn flush_traces() {
+    #[cfg(feature = "otel")]
+    if let Some(tracer) = TRACER.get() {
+        if let Err(e) = tracer.flush().await {
+            warn!("Failed to export traces: {}", e);
+        }
+    }
+}
+
+/// A fresh token for the next run, cancelled by Ctrl-C until `end_run` is called.
+fn start_run() -> CancellationToken {
+    let token = CancellationToken::new();
+    *CURRENT_RUN.lock().unwrap() = Some(token.clone());
+    token
+}
+
+fn end_run() {
+    *CURRENT_RUN.lock().unwrap() = None;
+}
+
+fn is_cancelled(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<AgentError>(), Some(AgentError::Cancelled))
+}
+
+/// Maps an invocation without a subcommand onto one: a goal means `run`, otherwise `chat`.
+fn default_command(cli: &Cli) -> Result<Command, &'static str> {
+    let goal = cli.goal.clone().or_else(|| cli.goal_arg.clone());
+    match goal {
+        Some(goal) => Ok(Command::Run { goal: Some(goal), goal_file: None, from_issue: None, resume: cli.resume, plan_file: None, from_step: None, only_step: None, skip_step: Vec::new(), edit_plan: false, rollback_on_failure: false, create_pr: false, name: None }),
+        None if cli.non_interactive && cli.resume => {
+            Ok(Command::Run { goal: None, goal_file: None, from_issue: None, resume: true, plan_file: None, from_step: None, only_step: None, skip_step: Vec::new(), edit_plan: false, rollback_on_failure: false, create_pr: false, name: None })
+        }
+        None if cli.non_interactive => Err("--non-interactive requires a goal (positional or --goal) or --resume."),
+        None => Ok(Command::Chat { resume: cli.resume }),
+    }
+}
+
+/// Prompts for goals until 'quit' or the end of input. Input starting with `/` is a slash command
+/// (see `repl::SLASH_COMMANDS`) handled here rather than run as a goal.
+async fn chat(cli: &Cli, config: &Arc<AppConfig>, mut models: RoleModels, transcript: Option<&Arc<dyn EventSink>>) -> Result<()> {
+    // Later goals see what earlier ones did, so follow-ups like "now add tests for that" work.
+    let mut memory = ConversationMemory::default();
+    let mut log = SessionLog::default();
+    // Files pinned with `/pin`, on top of `--context`, for every goal of the session.
+    let mut pinned: Vec<String> = Vec::new();
+    let mut reader = GoalReader::new(repl::default_history_path())?;
+    let prompt = if cli.output == OutputFormat::Text { "> " } else { "" };
+    loop {
+        say!(cli, "{}", "//: PRIMARY DIRECTIVE:".yellow().bold());
+
+        io::stdout().flush()?;
+
+        // End of input (e.g. a closed pipe from an embedding program) or Ctrl-D ends the session.
+        let Some(goal) = tokio::task::block_in_place(|| reader.read_goal(prompt))? else {
+            break;
+        };
+        let goal = goal.as_str();
+
+        if goal.eq_ignore_ascii_case("quit") || goal.eq_ignore_ascii_case("exit") {
+            say!(cli, "{}", "Exiting agent. Goodbye!".bold().cyan());
+            break;
+        }
+
+        if goal.is_empty() {
+            say!(cli, "{}", "Goal cannot be empty. Please enter a valid goal.".red());
+            continue;
+        }
+
+        match SlashCommand::parse(goal) {
+            Some(Ok(command)) => {
+                // A failed command, e.g. `/save` to a missing directory, is reported; the session goes on.
+                if let Err(e) = run_slash_command(command, cli, config, &mut models, &mut memory, &mut log, &mut pinned).await {
+                    say!(cli, "{} {}", "❌".bold().red(), e.to_string().red());
+                }
+                continue;
+            }
+            Some(Err(message)) => {
+                say!(cli, "{}", message.red());
+                continue;
+            }
+            None => {}
+        }
+
+        if cli.output == OutputFormat::Text {
+            print_objective(goal);
+        }
+        let mut state = AppState::new(goal.to_string());
+        state.conversation = memory.render();
+        for path in &pinned {
+            state.pin(path);
+        }
+        let outcome = run_goal(state, cli, config, &models, transcript).await?;
+        memory.record(&outcome.state, outcome.succeeded);
+        log.record(&outcome.state, outcome.succeeded, outcome.cost_tracker.get_total_cost());
+    }
+    Ok(())
+}
+
+/// Runs a slash command entered at the chat prompt. Its output is for people, so `--output json`
+/// suppresses it like the rest of the text output.
+async fn run_slash_command(
+    command: SlashCommand,
+    cli: &Cli,
+    config: &Arc<AppConfig>,
+    models: &mut RoleModels,
+    memory: &mut ConversationMemory,
+    log: &mut SessionLog,
+    pinned: &mut Vec<String>,
+) -> Result<()> {
+    match command {
+        SlashCommand::Help => {
+            for (usage, description) in repl::SLASH_COMMANDS {
+                say!(cli, "  {:<32} {}", usage.bold().cyan(), description);
+            }
+        }
+        SlashCommand::Cost => {
+            say!(cli, "{} {}{:.4} over {} goal(s)", "💰 Session Cost:".bold().green(), "$".bold().green(), log.total_cost(), log.goals().len());
+            if let Some(last) = log.last() {
+                say!(cli, "   Last goal: ${:.4}", last.cost);
+            }
+        }
+        SlashCommand::Plan => match log.last() {
+            Some(last) if !last.plan.is_empty() => {
+                say!(cli, "{} {}", "📋 Plan of:".bold().cyan(), last.goal);
+                say!(cli, "{}", repl::render_plan(last));
+            }
+            Some(_) => say!(cli, "{}", "The last goal stopped before it had a plan.".yellow()),
+            None => say!(cli, "{}", "No goal has run yet in this session.".yellow()),
+        },
+        SlashCommand::History => {
+            if log.goals().is_empty() {
+                say!(cli, "{}", "No goal has run yet in this session.".yellow());
+            } else {
+                say!(cli, "{}", log.render_history());
+            }
+        }
+        SlashCommand::Model(None) => {
+            say!(cli, "{} {}", "//: PLANNER:".yellow().bold(), models.planner.to_string().white());
+            say!(cli, "{} {}", "//: CODER:".yellow().bold(), models.coder.to_string().white());
+            say!(cli, "{} {}", "//: REASONER:".yellow().bold(), models.reasoner.to_string().white());
+            say!(cli, "{} {}", "//: SUMMARIZER:".yellow().bold(), models.summarizer.to_string().white());
+        }
+        SlashCommand::Model(Some((role, spec))) => switch_models(cli, config, models, &[role], spec).await,
+        SlashCommand::Provider(spec) => switch_models(cli, config, models, &ModelRole::ALL, spec).await,
+        SlashCommand::Undo => rollback_last_run(cli)?,
+        SlashCommand::Clear => {
+            *memory = ConversationMemory::default();
+            log.clear();
+            say!(cli, "{}", "🧹 Cleared the conversation: the next goal starts without the earlier ones as context.".green());
+        }
+        SlashCommand::Save(path) => {
+            std::fs::write(&path, log.to_markdown()).map_err(|e| AgentError::ToolError(format!("Could not save the session to {}: {}", path.display(), e)))?;
+            say!(cli, "{} {}", "💾 Session saved to".green(), path.display());
+        }
+        SlashCommand::Pin(None) => {
+            let all: Vec<&String> = cli.context.iter().chain(pinned.iter()).collect();
+            if all.is_empty() {
+                say!(cli, "{}", "No files are pinned; /pin PATH pins one.".yellow());
+            }
+            for path in all {
+                say!(cli, "  📌 {}", path);
+            }
+        }
+        SlashCommand::Pin(Some(path)) => {
+            // Checked the way the goals will read it: relative to their workdir and within the sandbox.
+            let context = ToolContext::new(config.clone()).with_workdir(std::env::current_dir()?);
+            if !Path::new(&context.resolve(&path)).is_file() {
+                return Err(AgentError::ToolError(format!("Cannot pin {}: no such file", path)).into());
+            }
+            let read = Tool::ReadFile { path: path.clone(), start_line: None, end_line: None };
+            if let Err(e) = tools::run_tool_in(read, &context).await {
+                return Err(AgentError::ToolError(format!("Cannot pin {}: {}", path, e)).into());
+            }
+            if !pinned.contains(&path) && !cli.context.contains(&path) {
+                pinned.push(path.clone());
+            }
+            say!(cli, "{} {} {}", "📌 Pinned".green(), path, "for the next goals.".green());
+        }
+        SlashCommand::Unpin(path) => {
+            if cli.context.contains(&path) {
+                return Err(AgentError::ToolError(format!("{} was pinned with --context for the whole session", path)).into());
+            }
+            let before = pinned.len();
+            pinned.retain(|pinned| *pinned != path);
+            if pinned.len() == before {
+                return Err(AgentError::ToolError(format!("{} is not pinned", path)).into());
+            }
+            say!(cli, "{} {}", "Unpinned".green(), path);
+        }
+    }
+    Ok(())
+}
+
+/// Switches `roles` to `spec` for the goals that follow. Clients are created per goal from
+/// `models`, so nothing else needs rebuilding; a model that fails the model check is not switched to.
+async fn switch_models(cli: &Cli, config: &Arc<AppConfig>, models: &mut RoleModels, roles: &[ModelRole], spec: ModelSpec) {
+    let mut switched = models.clone();
+    for role in roles {
+        switched.set(*role, spec.clone());
+    }
+    if config.validate_models {
+        if let Err(message) = check_models(&switched, config).await {
+            say!(cli, "{} {}", "❌".bold().red(), message.red());
+            return;
+        }
+    }
+    *models = switched;
+    let roles: Vec<String> = roles.iter().map(ModelRole::to_string).collect();
+    say!(cli, "{} {} {}", "🔀 The next goals use".green(), spec.to_string().white(), format!("as the {}.", roles.join(", ")).green());
+}
+
+/// Runs every task in `dir` once per model, each in a fresh copy of its fixture, and prints how
+/// the models compare. Without `specs` the suite runs once with the configured role models.
+async fn bench(
+    cli: &Cli,
+    config: &Arc<AppConfig>,
+    models: &RoleModels,
+    specs: Vec<ModelSpec>,
+    dir: &Path,
+    report_path: Option<&Path>,
+    transcript: Option<&Arc<dyn EventSink>>,
+) -> Result<ExitCode> {
+    let tasks = bench::load_tasks(dir)?;
+    let mut variants = vec![(models.coder.to_string(), models.clone())];
+    if !specs.is_empty() {
+        variants = specs
+            .into_iter()
+            .map(|spec| {
+                let mut variant = models.clone();
+                for role in ModelRole::ALL {
+                    variant.set(role, spec.clone());
+                }
+                (spec.to_string(), variant)
+            })
+            .collect();
+        if config.validate_models {
+            for (_, variant) in &variants {
+                if let Err(message) = check_models(variant, config).await {
+                    eprintln!("{} {}", "❌".bold().red(), message.bold().red());
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        }
+    }
+    // Benchmark runs are not the user's sessions, so they stay out of `stats` and `sessions`.
+    let config = Arc::new(AppConfig { stats_enabled: false, sessions_enabled: false, ..(**config).clone() });
+    let shell = config.shell.as_deref().map(Shell::from_name).unwrap_or_else(Shell::detect);
+    let limits = CommandLimits { timeout: Duration::from_secs(config.command_timeout_secs), max_output_bytes: config.max_command_output_bytes };
+    let mut report = BenchReport::default();
+    for (label, variant) in &variants {
+        for task in &tasks {
+            say!(cli, "{} {} [{}]", "🏁 BENCH TASK:".bold().cyan(), task.name.bold(), label);
+            let workspace = std::env::temp_dir().join(format!("agent-bench-{}-{}-{}", std::process::id(), report.results.len(), task.name));
+            task.prepare(&workspace)?;
+            let started = std::time::Instant::now();
+            let timeout = task.timeout;
+            let timer = tokio::spawn(async move {
+                tokio::time::sleep(timeout).await;
+                if let Some(run) = CURRENT_RUN.lock().unwrap().clone() {
+                    run.cancel();
+                }
+            });
+            // The run sees the workspace exactly as if the agent had been started in it.
+            let outcome = run_goal_in(AppState::new(task.goal.clone()), cli, &config, variant, transcript, &workspace).await;
+            let timed_out = timer.is_finished();
+            timer.abort();
+            let duration_ms = started.elapsed().as_millis() as u64;
+            let check = shell.run_in(&task.check, Some(&workspace), limits, None).await;
+
+            let mut result = BenchResult {
+                task: task.name.clone(),
+                model: label.clone(),
+                passed: matches!(&check, Ok(output) if output.success),
+                agent_succeeded: false,
+                steps: 0,
+                cost: 0.0,
+                duration_ms,
+                error: None,
+            };
+            match outcome {
+                Ok(outcome) => {
+                    result.agent_succeeded = outcome.succeeded;
+                    result.steps = outcome.state.current_step;
+                    result.cost = outcome.cost_tracker.get_total_cost();
+                }
+                Err(e) => result.error = Some(e.to_string()),
+            }
+            if timed_out {
+                result.error = Some(format!("timed out after {}s", timeout.as_secs()));
+            } else if let Err(e) = &check {
+                result.error = Some(format!("check failed to run: {}", e));
+            }
+            if result.passed {
+                say!(cli, "{} {}", "✅ Check passed:".green(), task.check);
+                std::fs::remove_dir_all(&workspace).ok();
+            } else {
+                say!(cli, "{} {} {}", "❌ Check failed:".red(), task.check, format!("(workspace kept in {})", workspace.display()).dimmed());
+            }
+            report.results.push(result);
+        }
+    }
+
+    println!("{}", report.render());
+    if let Some(path) = report_path {
+        std::fs::write(path, serde_json::to_string_pretty(&report.to_json())?)
+            .map_err(|e| AgentError::ToolError(format!("Failed to write the benchmark report to {}: {}", path.display(), e)))?;
+        say!(cli, "{} {}", "💾 Benchmark report saved to".green(), path.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs `goal` now and again after every change to the working directory, until Ctrl-C. Each run
+/// is told which files changed and what the runs before it did; the changes a run makes itself
+/// do not trigger the next one.
+async fn watch(goal: String, debounce: Duration, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels, transcript: Option<&Arc<dyn EventSink>>) -> Result<ExitCode> {
+    let mut watcher = WorkspaceWatcher::new(&std::env::current_dir()?)?;
+    // Every run would otherwise be its own entry in `sessions list`.
+    let config = Arc::new(AppConfig { sessions_enabled: false, ..(**config).clone() });
+    let mut memory = ConversationMemory::default();
+    let mut changed: Vec<String> = Vec::new();
+    loop {
+        let mut state = AppState::new(goal.clone());
+        state.conversation = memory.render();
+        if !changed.is_empty() {
+            state.add_history("Changed Files", &format!("These files changed since the goal last ran:\n{}", changed.join("\n")));
+        }
+        let outcome = run_goal(state, cli, &config, models, transcript).await?;
+        if outcome.cancelled {
+            return Ok(ExitCode::SUCCESS);
+        }
+        memory.record(&outcome.state, outcome.succeeded);
+        // Edits made by hand while the goal ran start the next run straight away.
+        changed = watcher.discard(debounce, &outcome.state.files_modified).await;
+        if changed.is_empty() {
+            say!(cli, "{}", "👀 Watching for changes... press Ctrl-C to stop.".cyan());
+            changed = watcher.changes(debounce).await?;
+        }
+        say!(cli, "{} {}", "🔁 Changed:".yellow().bold(), changed.join(", "));
+    }
+}
+
+/// Runs the pending tasks of `QUEUE_FILE` one at a time, oldest first, each within its budget and
+/// with its own transcript, until none are left. The queue is re-read between tasks, so tasks
+/// enqueued meanwhile are run too. Ctrl-C stops the worker and puts the interrupted task back.
+async fn work_through_queue(budget: Option<f64>, transcripts: &Path, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels) -> Result<ExitCode> {
+    let path = Path::new(QUEUE_FILE);
+    let worker = std::process::id();
+    let requeued = TaskQueue::update(path, |tasks| tasks.requeue_abandoned(queue::process_alive))?;
+    if requeued > 0 {
+        say!(cli, "{} {} task(s) left running by a worker that stopped; they start over.", "♻️ ".yellow(), requeued);
+    }
+    if cli.transcript.is_some() {
+        warn!("--transcript is ignored by `worker`: each task's transcript is written to {}.", transcripts.display());
+    }
+    let transcripts = std::env::current_dir()?.join(transcripts);
+    std::fs::create_dir_all(&transcripts)?;
+    let (mut succeeded, mut failed) = (0, 0);
+    loop {
+        let Some((task, pending)) = TaskQueue::update(path, |queue| queue.start_next(worker).map(|task| (task, queue.pending())))? else { break };
+        say!(cli, "{} #{} ({} more pending)", "📋 TASK".bold().cyan(), task.id, pending);
+        if cli.output == OutputFormat::Text {
+            print_objective(&task.goal);
+        }
+
+        let transcript_path = transcripts.join(format!("task-{}.jsonl", task.id));
+        let transcript: Arc<dyn EventSink> = Arc::new(JsonlTranscript::create(&transcript_path)?);
+        let config = match task.budget.or(budget) {
+            Some(dollars) => Arc::new(AppConfig { max_cost: Some(dollars), ..(**config).clone() }),
+            None => config.clone(),
+        };
+        // The session records the task's transcript as if it had been given with --transcript.
+        let task_cli = Cli { transcript: Some(transcript_path.clone()), ..cli.clone() };
+        let outcome = run_goal(AppState::new(task.goal.clone()), &task_cli, &config, models, Some(&transcript)).await?;
+
+        if outcome.cancelled {
+            TaskQueue::update(path, |queue| queue.requeue(task.id))?;
+            say!(cli, "{} #{} {}", "⏹️  Worker stopped; task".bold().yellow(), task.id, "is back in the queue.".bold().yellow());
+            return Ok(ExitCode::FAILURE);
+        }
+        TaskQueue::update(path, |queue| queue.finish(task.id, outcome.succeeded, outcome.cost_tracker.get_total_cost(), &outcome.state.id, &transcript_path))?;
+        if outcome.succeeded {
+            succeeded += 1;
+        } else {
+            failed += 1;
+        }
+    }
+    say!(cli, "{} {} succeeded, {} failed.", "🏁 Queue done:".bold().green(), succeeded, failed);
+    Ok(exit_code(failed == 0))
+}
+
+/// Continues the session checkpointed in `SESSION_FILE` and reports how it ended, or None when it
+/// had already completed.
+async fn resume_session(cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels, transcript: Option<&Arc<dyn EventSink>>) -> Result<Option<GoalOutcome>> {
+    continue_session(AppState::load(Path::new(SESSION_FILE))?, cli, config, models, transcript).await
+}
+
+/// Runs the rest of a checkpointed session and reports how it ended, or None when it had already
+/// completed.
+async fn continue_session(state: AppState, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels, transcript: Option<&Arc<dyn EventSink>>) -> Result<Option<GoalOutcome>> {
+    say!(cli, "{} {} (session {})", "⏯️  RESUMING DIRECTIVE:".yellow().bold(), state.goal, state.id);
+    if state.is_complete() {
+        say!(cli, "{}", "Saved session already completed; nothing to resume.".green());
+        return Ok(None);
+    }
+    Ok(Some(run_goal(state, cli, config, models, transcript).await?))
+}
+
+/// Commits the files the run changed, and nothing else, with a message, and changelog entry, by the
+/// scribe, pushes the current branch to `origin` and opens a pull request, or a merge request on
+/// GitLab, with a title and description written by the summarizer. Refuses when a file the run
+/// changed was already among `staged_before`, the files staged before the run. The summarizer's and
+/// scribe's calls are added to the run's `cost_tracker`.
+async fn open_pull_request(cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels, state: &AppState, staged_before: &[String], cost_tracker: &Arc<CostTracker>) -> Result<()> {
+    let origin = pull_request::git(&["remote", "get-url", "origin"]).await?;
+    let remote = Remote::parse(&origin).ok_or_else(|| AgentError::ConfigError(format!("origin ({}) is not a GitHub or GitLab repository", origin)))?;
+    let token = match remote.forge {
+        Forge::GitHub => &config.github_token,
+        Forge::GitLab => &config.gitlab_token,
+    };
+    let token = token.clone().ok_or_else(|| AgentError::ApiKeyMissing(format!("{} ({})", remote.forge, remote.forge.token_variable())))?;
+    let branch = pull_request::git(&["rev-parse", "--abbrev-ref", "HEAD"]).await?;
+    let base = pull_request::base_branch(config.pr_base.as_deref()).await;
+    if branch == "HEAD" || branch == base {
+        return Err(AgentError::ConfigError(format!("The run's changes are on {}; check out a new branch to open a pull request from it", branch)).into());
+    }
+    let mixed: Vec<String> = pull_request::staged_files(&state.files_modified).await?.into_iter().filter(|file| staged_before.contains(file)).collect();
+    if !mixed.is_empty() {
+        return Err(AgentError::ConfigError(format!("{} had staged changes before the run; commit or unstage them to open a pull request", mixed.join(", "))).into());
+    }
+
+    say!(cli, "{}", "🔀 Opening a pull request...".yellow());
+    // Only the files the run changed are committed; the user's other changes stay where they are.
+    let mut files = pull_request::changed_files(&state.files_modified).await?;
+    if !files.is_empty() {
+        pull_request::git_on(&["add", "--all"], &files).await?;
+    }
+    let since = pull_request::git(&["merge-base", &format!("origin/{}", base), "HEAD"]).await.unwrap_or_else(|_| "HEAD".to_string());
+    let diff_stat = pull_request::git_on(&["diff", "--cached", "--stat", &since], &state.files_modified).await?;
+    let client = create_llm_client_for(&models.summarizer, config.clone())?;
+    let started = std::time::Instant::now();
+    let response = client.generate(&pull_request::description_prompt(state, &diff_stat), &config.role_params().summarizer).await?;
+    cost_tracker.record_call("summarizer", &response, started.elapsed());
+    let description = PullRequest::parse(&response.content)?;
+    if !files.is_empty() {
+        let message = commit_message(config, client, cost_tracker, state, &description, &mut files).await?;
+        pull_request::git_on(&["commit", "--quiet", "--message", &message], &files).await?;
+    }
+    pull_request::git(&["push", "--quiet", "--set-upstream", "origin", &branch]).await?;
+    let url = PullRequestClient::new(remote, &token).with_client(llm::http::shared_client(config)?).open(&branch, &base, &description).await?;
+    say!(cli, "{} {}", "✅ Opened pull request:".bold().green(), url);
+    Ok(())
+}
+
+/// The scribe's conventional-commit message for the staged changes to `files`, after adding its
+/// changelog entry to `CHANGELOG_FILE` when the project keeps one, which then joins `files`. Falls
+/// back to the pull request title.
+async fn commit_message(config: &AppConfig, client: Arc<dyn LLMClient>, cost_tracker: &Arc<CostTracker>, state: &AppState, description: &PullRequest, files: &mut Vec<String>) -> Result<String> {
+    let diff = pull_request::git_on(&["diff", "--cached"], files).await?;
+    let changelog = Path::new(CHANGELOG_FILE);
+    let scribe = ScribeAgent::new(client, cost_tracker.clone()).with_params(config.role_params().summarizer);
+    let note = match scribe.describe(Some(&state.goal), &diff, changelog.is_file()).await {
+        Ok(note) => note,
+        Err(e) => {
+            warn!("Using the pull request title as the commit message: {}", e);
+            return Ok(description.title.clone());
+        }
+    };
+    if let Some(entry) = &note.changelog {
+        std::fs::write(changelog, scribe::add_changelog_entry(&std::fs::read_to_string(changelog)?, entry))?;
+        pull_request::git(&["add", CHANGELOG_FILE]).await?;
+        if !files.iter().any(|file| file == CHANGELOG_FILE) {
+            files.push(CHANGELOG_FILE.to_string());
+        }
+    }
+    Ok(note.commit_message)
+}
+
+/// Has the scribe write a conventional commit message for the staged changes, shows it, and commits
+/// with it once the user approves it, after editing it in git's editor if they ask to.
+async fn commit(cli: &Cli, yes: bool) -> Result<ExitCode> {
+    if let Some(workdir) = &cli.workdir {
+        if let Err(e) = enter_workdir(workdir) {
+            eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    let config = Arc::new(load_config(cli)?);
+    let models = RoleModels::resolve(cli, &config);
+    let diff = match pull_request::git(&["diff", "--cached"]).await {
+        Ok(diff) if diff.is_empty() => {
+            eprintln!("{}", "❌ Nothing is staged; stage the changes to commit with `git add` first.".bold().red());
+            return Ok(ExitCode::FAILURE);
+        }
+        Ok(diff) => diff,
+        Err(e) => {
+            eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    say!(cli, "{} {}", "✍️  Writing a commit message with".yellow(), models.summarizer);
+    let cost_tracker = Arc::new(CostTracker::new());
+    let client = traced(create_llm_client_for(&models.summarizer, config.clone())?, "scribe");
+    let scribe = ScribeAgent::new(client, cost_tracker.clone()).with_params(config.role_params().summarizer);
+    let message = match scribe.describe(None, &diff, false).await {
+        Ok(note) => note.commit_message,
+        Err(e) => {
+            eprintln!("{} {}", "❌ Could not write a commit message:".bold().red(), e);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    say!(cli, "\n{}\n", message);
+    let json = |message: &str, committed: bool| println!("{}", serde_json::json!({ "message": message, "committed": committed, "cost": cost_tracker.get_total_cost() }));
+    if config.dry_run || cli.dry_run {
+        say!(cli, "{}", "Dry run: not committing.".yellow());
+        if cli.output == OutputFormat::Json {
+            json(&message, false);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let edit = if yes || cli.non_interactive {
+        false
+    } else {
+        // With JSON output the message and question go to stderr, keeping stdout to the result.
+        let question = format!("{} ", "Commit with this message? [Y/n/e(dit)]".bold().yellow());
+        if cli.output == OutputFormat::Json {
+            eprint!("\n{}\n\n{}", message, question);
+            io::stderr().flush()?;
+        } else {
+            print!("{}", question);
+            io::stdout().flush()?;
+        }
+        let mut answer = String::new();
+        // End of input is no answer, so nothing is committed.
+        let read = io::stdin().read_line(&mut answer)?;
+        match answer.trim().to_lowercase().as_str() {
+            "" | "y" | "yes" if read > 0 => false,
+            "e" | "edit" => true,
+            _ => {
+                say!(cli, "{}", "Not committed.".yellow());
+                if cli.output == OutputFormat::Json {
+                    json(&message, false);
+                }
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    };
+    // Run with the terminal, so `--edit` can open git's editor on the message.
+    let mut git = std::process::Command::new("git");
+    git.args(["commit", "--message", &message]);
+    if edit {
+        git.arg("--edit");
+    }
+    if cli.output == OutputFormat::Json {
+        git.arg("--quiet");
+    }
+    let committed = git.status()?.success();
+    if committed {
+        say!(cli, "{}", "✅ Committed.".bold().green());
+    } else {
+        eprintln!("{}", "❌ git commit failed; nothing was committed.".bold().red());
+    }
+    say!(cli, "{} {}{:.4}", "💰 Commit Message Cost:".bold().green(), "$".bold().green(), cost_tracker.get_total_cost());
+    if cli.output == OutputFormat::Json {
+        // An edited message is reported as committed.
+        let committed_message = if committed && edit { pull_request::git(&["log", "-1", "--format=%B"]).await.unwrap_or_else(|_| message.clone()) } else { message.clone() };
+        json(&committed_message, committed);
+    }
+    Ok(exit_code(committed))
+}
+
+fn list_tools(cli: &Cli) -> Result<ExitCode> {
+    let config = load_config(cli)?;
+    for tool in tool_registry(&config, Path::new("."))?.definitions() {
+        println!("{}", tool.name.bold().cyan());
+        println!("    {}", tool.description);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The built-in tools (without the network ones offline), `RunScript` when script runtimes are
+/// configured, `DbQuery` when databases are, plus the WASM plugins in the configured plugin directory
+/// (relative to `workdir`).
+fn tool_registry(config: &AppConfig, workdir: &Path) -> Result<ToolRegistry> {
+    let dir = config.plugin_dir.as_deref().unwrap_or(tools::DEFAULT_PLUGIN_DIR);
+    let mut languages: Vec<String> = config.script_runtimes.iter().map(|runtime| runtime.language.clone()).collect();
+    if !languages.is_empty() && !cfg!(feature = "scripts") {
+        warn!("AGENT_SCRIPT_RUNTIMES is set, but this build cannot run scripts; rebuild with `--features scripts`.");
+        languages.clear();
+    }
+    let mut connections: Vec<String> = config.db_connections.iter().map(|connection| connection.name.clone()).collect();
+    if !connections.is_empty() && !cfg!(feature = "db") {
+        warn!("AGENT_DB_CONNECTIONS is set, but this build cannot query databases; rebuild with `--features db`.");
+        connections.clear();
+    }
+    let mut registry = ToolRegistry::default().with_script_languages(languages).with_db_connections(connections).with_offline(config.offline);
+    let names = tools::load_plugins(&workdir.join(dir), &mut registry)?;
+    if !names.is_empty() {
+        info!("Loaded tool plugins from {}: {}", dir, names.join(", "));
+    }
+    Ok(registry)
+}
+
+/// Prints each provider's models with their prices. Providers without an API key are skipped.
+async fn list_models(cli: &Cli) -> Result<ExitCode> {
+    let config = Arc::new(load_config(cli)?);
+    let pricing = PricingTable::load(config.pricing_file.as_deref().map(Path::new))?;
+    let mut listed = false;
+    // The mock provider only offers the models its script names.
+    for provider in LLMProvider::value_variants().iter().filter(|provider| **provider != LLMProvider::Mock) {
+        let client = match create_llm_client(*provider, config.clone()) {
+            Ok(client) => client,
+            Err(e) => {
+                println!("{} {}", format!("{}:", provider).bold().dimmed(), e.to_string().dimmed());
+                continue;
+            }
+        };
+        match client.list_models().await {
+            Ok(models) => {
+                listed = true;
+                println!("{} {} models", format!("{}:", provider).bold().cyan(), models.len());
+                for model in models {
+                    let price = match pricing.lookup(&provider.to_string(), &model) {
+                        Some(price) => format!("${:.2} / ${:.2} per 1M tokens", price.input, price.output),
+                        None => "price unknown".to_string(),
+                    };
+                    println!("    {:<48} {}", model, price.dimmed());
+                }
+            }
+            Err(e) => println!("{} {}", format!("{}:", provider).bold().yellow(), e),
+        }
+    }
+    Ok(exit_code(listed))
+}
+
+/// Fails when a role's provider does not offer its model, so a typo is caught before the run
+/// instead of surfacing as a 404 mid-run. Providers that cannot be asked are not held against it.
+async fn check_models(models: &RoleModels, config: &Arc<AppConfig>) -> Result<(), String> {
+    let mut checked: Vec<&ModelSpec> = Vec::new();
+    let routes = route_models(config).into_iter().map(|(_, spec)| spec);
+    for spec in [&models.planner, &models.coder, &models.reasoner, &models.summarizer].into_iter().chain(routes) {
+        if checked.contains(&spec) {
+            continue;
+        }
+        checked.push(spec);
+        // A missing API key is reported when the clients are created for the run.
+        let Ok(client) = create_llm_client_for(spec, config.clone()) else {
+            continue;
+        };
+        match llm::model_available(client.as_ref()).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(format!(
+                    "{} has no model named '{}'. Run `cli_coding_agent models list` to see the models you can use, or set AGENT_VALIDATE_MODELS=false to skip this check.",
+                    spec.provider,
+                    client.get_model_info().await.name
+                ))
+            }
+            Err(e) => warn!("Could not check that {} offers the model: {}", spec.provider, e),
+        }
+    }
+    Ok(())
+}
+
+fn show_config(cli: &Cli) -> Result<ExitCode> {
+    let config = load_config(cli)?;
+    for path in &config.config_files {
+        println!("{} {}", "Read settings from".dimmed(), path.display());
+    }
+    for (name, value) in config.summary() {
+        println!("{:<28} {}", name.bold(), value);
+    }
+    let models = RoleModels::resolve(cli, &config);
+    println!();
+    println!("{:<28} {}", "planner".bold().yellow(), models.planner);
+    println!("{:<28} {}", "coder".bold().yellow(), models.coder);
+    println!("{:<28} {}", "reasoner".bold().yellow(), models.reasoner);
+    println!("{:<28} {}", "summarizer".bold().yellow(), models.summarizer);
+    for (route, spec) in route_models(&config) {
+        println!("{:<28} {}", route.bold().yellow(), spec);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The models calls may be routed to (`AGENT_ROUTE_CHEAP_MODEL`, `AGENT_ROUTE_STRONG_MODEL`), by route.
+fn route_models(config: &AppConfig) -> Vec<(&'static str, &ModelSpec)> {
+    [("cheap route", &config.route_cheap_model), ("strong route", &config.route_strong_model)]
+        .into_iter()
+        .filter_map(|(route, spec)| spec.as_ref().map(|spec| (route, spec)))
+        .collect()
+}
+
+/// Reports every problem that would stop a run before it starts, rather than the first one.
+async fn validate_config(cli: &Cli) -> ExitCode {
+    let config = match load_config(cli) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            println!("{} {}", "❌".red(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut problems = 0;
+    let models = RoleModels::resolve(cli, &config);
+    let roles = [("planner", &models.planner), ("coder", &models.coder), ("reasoner", &models.reasoner), ("summarizer", &models.summarizer)];
+    for (role, spec) in roles.into_iter().chain(route_models(&config)) {
+        match create_llm_client_for(spec, config.clone()) {
+            Ok(client) => {
+                let info = client.get_model_info().await;
+                match llm::model_available(client.as_ref()).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        problems += 1;
+                        println!("{} {}: {} does not offer a model named '{}'", "❌".red(), role, spec.provider, info.name);
+                        continue;
+                    }
+                    Err(e) => println!("{} {}: could not list {} models: {}", "⚠️ ".yellow(), role, spec.provider, e),
+                }
+                let unpriced = info.input_cost_per_token == 0.0 && info.output_cost_per_token == 0.0 && !matches!(spec.provider, LLMProvider::Ollama | LLMProvider::Mock);
+                if unpriced {
+                    println!("{} {}: {} has no known price; its cost will be reported as $0", "⚠️ ".yellow(), role, info.name);
+                } else {
+                    println!("{} {}: {} ({})", "✅".green(), role, spec.provider, info.name);
+                }
+            }
+            Err(e) => {
+                problems += 1;
+                println!("{} {}: {}", "❌".red(), role, e);
+            }
+        }
+    }
+    match Sandbox::from_config(&config) {
+        Ok(_) => println!("{} sandbox settings", "✅".green()),
+        Err(e) => {
+            problems += 1;
+            println!("{} sandbox: {}", "❌".red(), e);
+        }
+    }
+    match create_search_provider(&config) {
+        Ok(provider) => println!("{} search: {}", "✅".green(), provider.name()),
+        Err(e) => println!("{} search: {}; the Search tool will fail", "⚠️ ".yellow(), e),
+    }
+    if let Some(target) = &config.prompt_log {
+        match Redactor::from_config(&config).and_then(|_| prompt_log::prompt_sink(&config)) {
+            Ok(_) => println!("{} prompt log: {}", "✅".green(), target),
+            Err(e) => {
+                problems += 1;
+                println!("{} prompt log: {}", "❌".red(), e);
+            }
+        }
+    }
+    exit_code(problems == 0)
+}
+
+/// How long `doctor` waits for any one probe before reporting it as unreachable.
+const DOCTOR_PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The outcome of one `doctor` check: a problem is `Broken` when the configured run needs the
+/// thing that failed, `Unavailable` when only an optional feature is lost.
+enum Health {
+    Ok,
+    Unavailable,
+    Broken,
+}
+
+/// Makes one cheap request with every configured credential and tool, instead of discovering a
+/// missing key halfway through a run, and prints a table of what will and won't work.
+async fn doctor(cli: &Cli) -> ExitCode {
+    let config = match load_config(cli) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            println!("{} {}", "❌".red(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let models = RoleModels::resolve(cli, &config);
+    let roles = [("planner", &models.planner), ("coder", &models.coder), ("reasoner", &models.reasoner), ("summarizer", &models.summarizer)];
+    let mut rows: Vec<(String, Health, String)> = Vec::new();
+
+    let mut listed: Vec<(LLMProvider, Vec<String>)> = Vec::new();
+    for provider in LLMProvider::value_variants() {
+        let used_by: Vec<&str> = roles.iter().filter(|(_, spec)| spec.provider == *provider).map(|(role, _)| *role).collect();
+        if *provider == LLMProvider::Mock && used_by.is_empty() {
+            continue;
+        }
+        let needed = if used_by.is_empty() { Health::Unavailable } else { Health::Broken };
+        let client = match create_llm_client(*provider, config.clone()) {
+            Ok(client) => client,
+            Err(e) => {
+                let detail = if used_by.is_empty() { e.to_string() } else { format!("{}; needed by the {}", e, used_by.join(", ")) };
+                rows.push((provider.to_string(), needed, detail));
+                continue;
+            }
+        };
+        let row = match tokio::time::timeout(DOCTOR_PROBE_TIMEOUT, client.list_models()).await {
+            Ok(Ok(names)) => {
+                let detail = if *provider == LLMProvider::Ollama {
+                    format!("reachable, {} models pulled", names.len())
+                } else if *provider == LLMProvider::Mock {
+                    format!("script loaded, {} models", names.len())
+                } else {
+                    format!("key accepted, {} models", names.len())
+                };
+                listed.push((*provider, names));
+                (Health::Ok, detail)
+            }
+            Ok(Err(e)) => (needed, e.to_string()),
+            Err(_) => (needed, format!("no answer within {}s", DOCTOR_PROBE_TIMEOUT.as_secs())),
+        };
+        rows.push((provider.to_string(), row.0, row.1));
+    }
+
+    for (role, spec) in roles {
+        let Ok(client) = create_llm_client_for(spec, config.clone()) else {
+            rows.push((format!("{} model", role), Health::Broken, format!("{} is not configured", spec.provider)));
+            continue;
+        };
+        let name = client.get_model_info().await.name;
+        let (health, detail) = match listed.iter().find(|(provider, _)| *provider == spec.provider) {
+            Some((_, names)) if names.iter().any(|model| *model == name || *model == format!("{}:latest", name)) => {
+                (Health::Ok, format!("{} offers {}", spec.provider, name))
+            }
+            Some(_) => (Health::Broken, format!("{} has no model named '{}'", spec.provider, name)),
+            None => (Health::Broken, format!("{} could not be checked: {} did not answer", name, spec.provider)),
+        };
+        rows.push((format!("{} model", role), health, detail));
+    }
+
+    let (health, detail) = match create_search_provider(&config) {
+        Ok(provider) => match tokio::time::timeout(DOCTOR_PROBE_TIMEOUT, provider.search("rust programming language")).await {
+            Ok(Ok(results)) => (Health::Ok, format!("{} answered with {} results", provider.name(), results.len())),
+            Ok(Err(e)) => (Health::Unavailable, format!("{}; the Search tool will fail", e)),
+            Err(_) => (Health::Unavailable, format!("{} did not answer within {}s", provider.name(), DOCTOR_PROBE_TIMEOUT.as_secs())),
+        },
+        Err(e) => (Health::Unavailable, format!("{}; the Search tool will fail", e)),
+    };
+    rows.push(("web search".to_string(), health, detail));
+
+    let (health, detail) = match tokio::process::Command::new("git").arg("--version").output().await {
+        Ok(output) if output.status.success() => (Health::Ok, String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        Ok(output) => (Health::Unavailable, String::from_utf8_lossy(&output.stderr).trim().to_string()),
+        Err(e) => (Health::Unavailable, format!("not found ({}); git commands run by the agent will fail", e)),
+    };
+    rows.push(("git".to_string(), health, detail));
+
+    let shell = config.shell.as_deref().map(Shell::from_name).unwrap_or_else(Shell::detect);
+    let shell_name = config.shell.clone().unwrap_or_else(|| format!("{:?}", shell).to_lowercase());
+    let limits = CommandLimits { timeout: DOCTOR_PROBE_TIMEOUT, ..CommandLimits::default() };
+    let (health, detail) = match shell.run("echo ok", limits).await {
+        Ok(output) if output.success && output.stdout.trim() == "ok" => (Health::Ok, format!("{} runs commands", shell_name)),
+        Ok(output) => (Health::Broken, format!("{} failed to run `echo ok`: {}", shell_name, output.stderr.trim())),
+        Err(e) => (Health::Broken, format!("{} cannot run commands: {}; RunCommand will fail", shell_name, e)),
+    };
+    rows.push(("shell".to_string(), health, detail));
+
+    let width = rows.iter().map(|(check, _, _)| check.len()).max().unwrap_or(0);
+    let mut broken = 0;
+    for (check, health, detail) in &rows {
+        let mark = match health {
+            Health::Ok => "✅".green(),
+            Health::Unavailable => "⚠️ ".yellow(),
+            Health::Broken => {
+                broken += 1;
+                "❌".red()
+            }
+        };
+        println!("{} {:<width$}  {}", mark, check.bold(), detail, width = width);
+    }
+    println!();
+    if broken == 0 {
+        println!("{}", "Everything the configured models need is working.".green());
+    } else {
+        println!("{}", format!("{} problem(s) will stop a run; fix the ❌ rows first.", broken).red());
+    }
+    exit_code(broken == 0)
+}
+
+fn show_stats(cli: &Cli) -> Result<ExitCode> {
+    let config = load_config(cli)?;
+    let Some(path) = stats_path(&config) else {
+        eprintln!("{}", "❌ Cannot locate the stats file: set AGENT_STATS_FILE or HOME.".bold().red());
+        return Ok(ExitCode::FAILURE);
+    };
+    println!("{} {}", "📊 Usage from".bold().cyan(), path.display());
+    println!("{}", stats::summarize(&stats::load(&path)?));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn enqueue(cli: &Cli, goal: String, budget: Option<f64>) -> Result<ExitCode> {
+    if let Some(workdir) = &cli.workdir {
+        if let Err(e) = enter_workdir(workdir) {
+            eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    let (task, pending) = TaskQueue::update(Path::new(QUEUE_FILE), |queue| (queue.enqueue(goal, budget).clone(), queue.pending()))?;
+    say!(cli, "{} #{}: {}", "📥 Queued task".bold().cyan(), task.id, goal_headline(&task.goal));
+    say!(cli, "   {} task(s) pending; run them with `cli_coding_agent worker`.", pending);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn show_queue(cli: &Cli) -> Result<ExitCode> {
+    if let Some(workdir) = &cli.workdir {
+        if let Err(e) = enter_workdir(workdir) {
+            eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    println!("{}", queue::table(&TaskQueue::load(Path::new(QUEUE_FILE))?.tasks));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn list_sessions(cli: &Cli) -> Result<ExitCode> {
+    let Some(store) = session_store(&load_config(cli)?) else {
+        eprintln!("{}", "❌ Cannot locate the sessions directory: set AGENT_SESSIONS_DIR or HOME.".bold().red());
+        return Ok(ExitCode::FAILURE);
+    };
+    println!("{} {}", "🗂️  Sessions in".bold().cyan(), store.dir().display());
+    println!("{}", session_store::table(&store.list()?));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn show_session(cli: &Cli, id: &str) -> Result<ExitCode> {
+    let Some(store) = session_store(&load_config(cli)?) else {
+        eprintln!("{}", "❌ Cannot locate the sessions directory: set AGENT_SESSIONS_DIR or HOME.".bold().red());
+        return Ok(ExitCode::FAILURE);
+    };
+    match store.find(id) {
+        Ok(session) => {
+            println!("{}", session_store::describe(&session));
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(e) => {
+            eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// Deletes a stored session, and its directory's `SESSION_FILE` when that holds the same session,
+/// so `--resume` does not bring it back either.
+fn delete_session(cli: &Cli, id: &str) -> Result<ExitCode> {
+    let Some(store) = session_store(&load_config(cli)?) else {
+        eprintln!("{}", "❌ Cannot locate the sessions directory: set AGENT_SESSIONS_DIR or HOME.".bold().red());
+        return Ok(ExitCode::FAILURE);
+    };
+    let session = match store.delete(id) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    if let Some(workdir) = &session.state.workdir {
+        let checkpoint = workdir.join(SESSION_FILE);
+        if AppState::load(&checkpoint).is_ok_and(|state| state.id == session.state.id) {
+            std::fs::remove_file(&checkpoint)?;
+        }
+    }
+    println!("{} {} ({})", "🗑️  Deleted session".bold().cyan(), session.state.id, goal_headline(&session.state.goal));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn undo(cli: &Cli) -> Result<ExitCode> {
+    if let Some(workdir) = &cli.workdir {
+        if let Err(e) = enter_workdir(workdir) {
+            eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    rollback_last_run(cli)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Restores the files journaled by the last run and discards the journal, so the same run is not
+/// undone twice.
+fn rollback_last_run(cli: &Cli) -> Result<()> {
+    let path = Path::new(JOURNAL_FILE);
+    if !path.exists() {
+        say!(cli, "{}", "Nothing to undo: no run has been journaled here.".yellow());
+        return Ok(());
+    }
+    let journal = Journal::load(path)?;
+    let restored = journal.rollback()?;
+    std::fs::remove_file(path)?;
+    say!(cli, "{} {}", "↩️  Undid the file changes of:".bold().cyan(), journal.goal);
+    if restored.is_empty() {
+        say!(cli, "   {}", "The run did not change any files.".dimmed());
+    }
+    for file in restored {
+        say!(cli, "   {} {}", "✅".green(), file);
+    }
+    Ok(())
+}
+
+fn print_banner(cli: &Cli) {
     println!("{}", "===================================".cyan());
     println!("{}", "🤖 Rust CLI Coding Agent Initialized 🤖".bold().cyan());
     println!("{}", "===================================".cyan());
@@ -59,63 +1724,542 @@ async fn main() -> Result<()> {
     // Display the provider as a string using Debug formatting
     println!("{} {}", "//: NEURAL LINK VIA:".yellow().bold(), format!("{:?}", cli.provider).white());
     println!();
+}
 
+fn print_objective(goal: &str) {
+    // Deus Ex Inspired: "Objective" and gold/blue color scheme
+    println!(
+        "{} {}",
+        "🗝️ OBJECTIVE:".bold().truecolor(212, 175, 55), // gold
+        goal_headline(goal).truecolor(51, 153, 255) // blue
+    );
+}
 
+/// Replaces a goal given as `-` (`run -`, `plan -`) with the text piped to stdin, and reads the
+/// goal of `run --goal-file`. Done before `--workdir` is entered, so a relative goal file is found
+/// where the agent was launched.
+fn read_goal_input(mut command: Command) -> Result<Command, AgentError> {
+    let stdin = || repl::read_goal_text(io::stdin().lock(), "stdin");
+    match &mut command {
+        Command::Run { goal, goal_file, .. } => match goal_file.take() {
+            Some(path) if path.as_os_str() == "-" => *goal = Some(stdin()?),
+            Some(path) => {
+                let file = std::fs::File::open(&path).map_err(|e| AgentError::ConfigError(format!("Cannot read the goal file {}: {}", path.display(), e)))?;
+                *goal = Some(repl::read_goal_text(file, &path.display().to_string())?);
+            }
+            None if goal.as_deref() == Some("-") => *goal = Some(stdin()?),
+            None => {}
+        },
+        Command::Plan { goal, .. } | Command::Watch { goal, .. } | Command::Enqueue { goal, .. } if goal == "-" => *goal = stdin()?,
+        Command::Explain { question } if question == "-" => *question = stdin()?,
+        _ => {}
+    }
+    Ok(command)
+}
 
-    let config = Arc::new(AppConfig::load()?);
-    info!("Configuration loaded.");
+/// Makes `path` the process working directory, so every tool, the sandbox root and the session
+/// file resolve relative to it exactly as if the agent had been started there.
+fn enter_workdir(path: &Path) -> Result<PathBuf, AgentError> {
+    if !path.is_dir() {
+        return Err(AgentError::ConfigError(format!("--workdir {} is not an existing directory", path.display())));
+    }
+    let root = path.canonicalize()?;
+    std::env::set_current_dir(&root)?;
+    Ok(root)
+}
 
-    loop {
-        println!("{}", "//: PRIMARY DIRECTIVE:".yellow().bold());
+fn session_store(config: &AppConfig) -> Option<SessionStore> {
+    config.sessions_dir.as_ref().map(PathBuf::from).or_else(session_store::default_sessions_dir).map(SessionStore::new)
+}
 
-        io::stdout().flush()?;
+fn stats_path(config: &AppConfig) -> Option<PathBuf> {
+    config.stats_file.as_ref().map(PathBuf::from).or_else(stats::default_stats_path)
+}
 
-        let mut goal = String::new();
-        io::stdin().read_line(&mut goal)?;
-        let goal = goal.trim();
+/// Appends this run's usage to the stats file; a failure here never fails the run itself.
+fn record_stats(config: &AppConfig, state: &AppState, success: bool, cost_tracker: &CostTracker) {
+    let Some(path) = stats_path(config).filter(|_| config.stats_enabled) else {
+        return;
+    };
+    let session = SessionStats {
+        finished_at: chrono::Utc::now(),
+        goal: state.goal.clone(),
+        success,
+        steps: state.current_step,
+        total_cost: cost_tracker.get_total_cost(),
+        agents: cost_tracker.usage_by_agent(),
+    };
+    if let Err(e) = stats::append(&path, &session) {
+        warn!("Failed to record session stats in {}: {}", path.display(), e);
+    }
+}
 
-        if goal.eq_ignore_ascii_case("quit") || goal.eq_ignore_ascii_case("exit") {
-            println!("{}", "Exiting agent. Goodbye!".bold().cyan());
-            break;
+/// The configuration, with `.agent.toml` read from `--workdir` when it is given and `--offline` applied.
+fn load_config(cli: &Cli) -> Result<AppConfig, AgentError> {
+    let mut config = AppConfig::load_in(cli.workdir.as_deref().unwrap_or(Path::new(".")))?;
+    config.offline |= cli.offline;
+    if let Some(path) = &cli.mock_script {
+        config.mock_script = Some(path.display().to_string());
+    }
+    // Taken from where the agent was launched, before --workdir changes directory.
+    if let Some(path) = &config.mock_script {
+        config.mock_script = Some(std::path::absolute(path)?.display().to_string());
+    }
+    Ok(config)
+}
+
+fn exit_code(succeeded: bool) -> ExitCode {
+    if succeeded { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+/// Models chosen for each agent role: CLI flags win over config, which wins over the defaults.
+#[derive(Clone)]
+struct RoleModels {
+    planner: ModelSpec,
+    coder: ModelSpec,
+    reasoner: ModelSpec,
+    summarizer: ModelSpec,
+}
+
+impl RoleModels {
+    fn resolve(cli: &Cli, config: &AppConfig) -> Self {
+        let coder = cli.coder_model.clone().or_else(|| config.coder_model.clone()).unwrap_or_else(|| cli.provider.into());
+        let reasoner = cli.reasoner_model.clone().or_else(|| config.reasoner_model.clone()).unwrap_or_else(|| LLMProvider::OpenAI.into());
+        let planner = cli.planner_model.clone().or_else(|| config.planner_model.clone()).unwrap_or_else(|| reasoner.clone());
+        let summarizer = config.summarizer_model.clone().unwrap_or_else(|| reasoner.clone());
+        let models = Self { planner, coder, reasoner, summarizer };
+        if config.offline { models.local() } else { models }
+    }
+
+    /// Every role on a local provider, for offline mode.
+    fn local(self) -> Self {
+        Self { planner: self.planner.local(), coder: self.coder.local(), reasoner: self.reasoner.local(), summarizer: self.summarizer.local() }
+    }
+
+    fn set(&mut self, role: ModelRole, spec: ModelSpec) {
+        let model = match role {
+            ModelRole::Planner => &mut self.planner,
+            ModelRole::Coder => &mut self.coder,
+            ModelRole::Reasoner => &mut self.reasoner,
+            ModelRole::Summarizer => &mut self.summarizer,
+        };
+        *model = spec;
+    }
+}
+
+/// An agent builder for `state` in `workdir` with the role clients, configuration and project
+/// instructions set.
+fn agent_builder(mut state: AppState, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels, cost_tracker: &Arc<CostTracker>, workdir: &Path) -> Result<AgentBuilder> {
+    // Re-read on every goal (and on resume) so edits to the instructions file take effect.
+    state.instructions = ProjectInstructions::discover(workdir)?;
+    if let Some(configured) = &config.instructions {
+        state.instructions = Some(ProjectInstructions::with_configured(configured, state.instructions.take()));
+    }
+    if let Some(instructions) = &state.instructions {
+        say!(cli, "{} {}", "📘 Following project instructions from".yellow(), instructions.source);
+    }
+    state.language = ProjectLanguage::detect(workdir);
+    if let Some(language) = state.language {
+        info!("Detected a {} project", language);
+    }
+    // Every call is recorded in the prompt log (if any) and exported as a span (if tracing).
+    let prompts = prompt_log::prompt_sink(config)?.map(|sink| Redactor::from_config(config).map(|redactor| (sink, Arc::new(redactor)))).transpose()?;
+    let instrument = |client: Arc<dyn LLMClient>, role: &str| {
+        let client = match &prompts {
+            Some((sink, redactor)) => prompt_log::logged(client, role, sink.clone(), redactor.clone()),
+            None => client,
+        };
+        traced(client, role)
+    };
+    // Planning, coding and decisions may be routed to cheaper or stronger models per call.
+    let routed = |spec: &ModelSpec| routing::routed(create_llm_client_for(spec, config.clone())?, config, cost_tracker);
+    let llm_client = instrument(routed(&models.coder)?, "coder");
+    info!("Coder client created for {}", models.coder);
+
+    let reasoning_client = instrument(routed(&models.reasoner)?, "reasoner");
+    info!("Reasoning client created for {} (tool decisions, reviews, summaries).", models.reasoner);
+
+    let planner_client = instrument(routed(&models.planner)?, "planner");
+    info!("Planner client created for {}", models.planner);
+
+    let summarizer_client = instrument(create_llm_client_for(&models.summarizer, config.clone())?, "summarizer");
+    info!("Summarizer client created for {} (history and large tool output).", models.summarizer);
+
+    let ensemble = config
+        .decision_ensemble
+        .iter()
+        .map(|spec| Ok(instrument(create_llm_client_for(spec, config.clone())?, "decision")))
+        .collect::<Result<Vec<_>>>()?;
+    if !ensemble.is_empty() {
+        info!("Decisions are voted on by {}.", config.decision_ensemble.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "));
+    }
+
+    let mut builder = Agent::builder()
+        .state(state)
+        .llm(llm_client)
+        .reasoner(reasoning_client)
+        .planner(planner_client)
+        .summarizer(summarizer_client)
+        .decision_ensemble(ensemble)
+        .tokenizer(models.reasoner.provider)
+        .cost_tracker(cost_tracker.clone())
+        .tools(tool_registry(config, workdir)?)
+        .config(config)
+        .workdir(workdir);
+    for path in &cli.context {
+        builder = builder.pin(path);
+    }
+    Ok(builder)
+}
+
+/// Creates the plan for `goal` and lets the user edit it; `None` if planning failed or the user
+/// cancelled by saving an empty plan.
+async fn edit_plan_for(goal: &str, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels, transcript: Option<&Arc<dyn EventSink>>) -> Result<Option<Vec<String>>> {
+    let Some(steps) = plan_goal(goal.to_string(), cli, config, models, transcript).await? else {
+        return Ok(None);
+    };
+    say!(cli, "{}", "✏️  Opening the plan in your editor...".yellow());
+    match plan_file::edit(goal, &steps) {
+        Ok(steps) => Ok(Some(steps)),
+        Err(e) => {
+            eprintln!("{} {}", "❌ Plan not executed:".bold().red(), e);
+            Ok(None)
         }
+    }
+}
 
-        if goal.is_empty() {
-            println!("{}", "Goal cannot be empty. Please enter a valid goal.".red());
+/// Answers `question` with the explainer, which only reads the workspace, streaming the answer to
+/// stdout as it is written. With `--output json`, prints the answer as one JSON object instead.
+async fn explain(question: &str, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels) -> Result<ExitCode> {
+    say!(cli, "{} {}", "❓ QUESTION:".bold().cyan(), goal_headline(question));
+    let cost_tracker = Arc::new(CostTracker::new());
+    let client = traced(create_llm_client_for(&models.reasoner, config.clone())?, "explainer");
+    let cancel = start_run();
+    let context = ToolContext::new(config.clone()).with_cancellation(cancel.clone());
+    let mut explainer = ExplainerAgent::new(client, cost_tracker.clone(), context).with_params(config.reasoner_params);
+    if cli.output == OutputFormat::Text {
+        explainer = explainer.with_reporter(Arc::new(ConsoleReporter));
+    }
+    let stream = cli.output == OutputFormat::Text;
+    let on_text = move |text: &str| {
+        if stream {
+            print!("{}", text);
+            let _ = io::stdout().flush();
+        }
+    };
+    let result = tokio::select! {
+        result = explainer.explain(question, &on_text) => result,
+        _ = cancel.cancelled() => Err(AgentError::Cancelled),
+    };
+    end_run();
+    flush_traces().await;
+    let answer = match result {
+        Ok(answer) => answer,
+        Err(AgentError::Cancelled) => {
+            eprintln!("\n{}", "⏹️  Explanation cancelled.".bold().yellow());
+            return Ok(ExitCode::FAILURE);
+        }
+        Err(e) => {
+            error!("Explaining failed: {:?}", e);
+            eprintln!("{} {}", "❌ Explanation Failed:".bold().red(), e);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    match cli.output {
+        OutputFormat::Text => println!("\n"),
+        OutputFormat::Json => println!("{}", serde_json::json!({ "question": question, "answer": answer, "cost": cost_tracker.get_total_cost() })),
+    }
+    say!(cli, "{} {}{:.4}", "💰 Explanation Cost:".bold().green(), "$".bold().green(), cost_tracker.get_total_cost());
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Reviews the diff of `source` with the reviewer and prints its findings: as text, as JSON with
+/// `--output json`, or as a GitHub review body with `github`. Exits with 1 on high-severity findings.
+async fn review_diff(source: &DiffSource, github: bool, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels) -> Result<ExitCode> {
+    let diff = match source.diff().await {
+        Ok(diff) if diff.is_empty() => {
+            eprintln!("{}", "❌ There are no changes to review.".bold().red());
+            return Ok(ExitCode::FAILURE);
+        }
+        Ok(diff) => diff,
+        Err(e) => {
+            eprintln!("{} {}", "❌".bold().red(), e.to_string().red());
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    let context = source.surrounding_context(&diff).await;
+    let print_text = cli.output == OutputFormat::Text && !github;
+    if print_text {
+        println!("{} {} file(s)", "🧐 Reviewing".yellow(), review::hunks(&diff).iter().map(|hunk| &hunk.path).collect::<std::collections::BTreeSet<_>>().len());
+    }
+    let cost_tracker = Arc::new(CostTracker::new());
+    let client = traced(create_llm_client_for(&models.reasoner, config.clone())?, "reviewer");
+    let reviewer = ReviewerAgent::new(client, cost_tracker.clone()).with_params(config.reasoner_params).with_system_prompt(config.reviewer_system_prompt.clone());
+    let result = reviewer.review_diff(&diff, &context).await;
+    flush_traces().await;
+    let review = match result {
+        Ok(review) => review,
+        Err(e) => {
+            error!("Review failed: {:?}", e);
+            eprintln!("{} {}", "❌ Review Failed:".bold().red(), e);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    if github {
+        println!("{}", serde_json::to_string_pretty(&review::github_review(&review, &review::hunks(&diff)))?);
+    } else if cli.output == OutputFormat::Json {
+        println!("{}", serde_json::json!({ "summary": review.summary, "findings": review.findings, "cost": cost_tracker.get_total_cost() }));
+    } else {
+        println!("\n{}\n", review::render(&review));
+        println!("{} {}{:.4}", "💰 Review Cost:".bold().green(), "$".bold().green(), cost_tracker.get_total_cost());
+    }
+    Ok(exit_code(!review.has_blocking()))
+}
+
+/// Has the documenter write doc comments for the undocumented public items of the Rust files under
+/// `paths` and applies them with `EditFile`, one file at a time, once the file's diff is approved.
+async fn document(paths: &[PathBuf], yes: bool, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels) -> Result<ExitCode> {
+    let cost_tracker = Arc::new(CostTracker::new());
+    let client = traced(create_llm_client_for(&models.coder, config.clone())?, "documenter");
+    let documenter = DocumenterAgent::new(client, cost_tracker.clone()).with_params(config.coder_params);
+    let context = ToolContext::new(config.clone());
+    if let Some(missing) = paths.iter().find(|path| !path.exists()) {
+        return Err(AgentError::ConfigError(format!("{} does not exist", missing.display())).into());
+    }
+    // Like a run, the files documented are journaled so `undo` restores them.
+    let mut journal = Journal::new(&format!("doc-gen {}", paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(" ")));
+    let (mut documented, mut failed) = (0, 0);
+    for file in paths.iter().flat_map(|path| doc_gen::rust_files(path)) {
+        let path = file.display().to_string();
+        let source = std::fs::read_to_string(&file)?;
+        let items = match doc_gen::undocumented_items(&source) {
+            Ok(items) if items.is_empty() => continue,
+            Ok(items) => items,
+            Err(e) => {
+                warn!("Skipping {}: {}", path, e);
+                continue;
+            }
+        };
+        say!(cli, "{} {} ({} undocumented)", "📝 Documenting".yellow(), path, items.len());
+        let docs = match documenter.document(&path, &source, &items).await {
+            Ok(docs) => docs,
+            Err(e) => {
+                eprintln!("{} {} {}", "❌".bold().red(), path, e.to_string().red());
+                failed += 1;
+                continue;
+            }
+        };
+        let Some(edit) = doc_gen::doc_edit(&path, &source, &items, &docs) else { continue };
+        let Some(preview) = tools::preview_change(&edit, &context).await else { continue };
+        say!(cli, "{}", colorize_diff(&preview.diff));
+        if config.dry_run {
+            continue;
+        }
+        if !(yes || cli.non_interactive || ConsoleApprover.approve(&path, &preview.diff).await) {
+            say!(cli, "   {} {}", "Skipped".yellow(), path);
             continue;
         }
+        journal.record(&file)?;
+        journal.save(Path::new(JOURNAL_FILE))?;
+        match tools::run_tool_in(edit, &context).await {
+            Ok(_) => documented += 1,
+            Err(e) => {
+                eprintln!("{} {} {}", "❌".bold().red(), path, e.to_string().red());
+                failed += 1;
+            }
+        }
+    }
+    flush_traces().await;
+    say!(cli, "{} {} file(s)", "✅ Documented".bold().green(), documented);
+    say!(cli, "{} {}{:.4}", "💰 Documentation Cost:".bold().green(), "$".bold().green(), cost_tracker.get_total_cost());
+    Ok(exit_code(failed == 0))
+}
 
-        // Deus Ex Inspired: "Objective" and gold/blue color scheme
-        println!(
-            "{} {}",
-            "🗝️ OBJECTIVE:".bold().truecolor(212, 175, 55), // gold
-            goal.truecolor(51, 153, 255) // blue
-        );
-        
-        let llm_client = create_llm_client(cli.provider, config.clone())?;
-        info!("LLM client created for provider: {}", cli.provider);
-        
-        let reasoning_client = create_llm_client(LLMProvider::OpenAI, config.clone())?;
-        info!("Reasoning client (OpenAI GPT-4o) created for planning and tool decisions.");
+/// Creates and prints the plan for `goal` without executing it; `None` if planning failed.
+async fn plan_goal(goal: String, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels, transcript: Option<&Arc<dyn EventSink>>) -> Result<Option<Vec<String>>> {
+    let cost_tracker = Arc::new(CostTracker::new());
+    let mut builder = agent_builder(AppState::new(goal), cli, config, models, &cost_tracker, &std::env::current_dir()?)?;
+    builder = match cli.output {
+        OutputFormat::Json => builder.event_sink(Arc::new(JsonlTranscript::stdout())),
+        OutputFormat::Text => builder.reporter(Arc::new(ConsoleReporter)),
+    };
+    if let Some(sink) = transcript {
+        builder = builder.event_sink(sink.clone());
+    }
+    if let Some(sink) = trace_sink() {
+        builder = builder.event_sink(sink);
+    }
+    let mut agent = builder.cancellation(start_run()).build()?;
 
-        // Display cost information (Phase 1.2)
-        println!("{} {}{}", "💰 Current Session Cost:".bold().green(), "$".bold().green(), 0.00); // Placeholder for now
+    let planned = agent.plan().await;
+    end_run();
+    flush_traces().await;
+    let steps = match planned {
+        Ok(steps) => Some(steps),
+        Err(AgentError::Cancelled) => {
+            eprintln!("{}", "⏹️  Planning cancelled.".bold().yellow());
+            None
+        }
+        Err(e) => {
+            error!("Planning failed: {:?}", e);
+            eprintln!("{} {}", "❌ Planning Failed:".bold().red(), e);
+            None
+        }
+    };
+    say!(cli, "{} {}{:.4}", "💰 Planning Cost:".bold().green(), "$".bold().green(), cost_tracker.get_total_cost());
+    Ok(steps)
+}
 
-        let cost_tracker = Arc::new(CostTracker::new());
-        let mut orchestrator = Orchestrator::new(goal.to_string(), llm_client, reasoning_client, cost_tracker.clone());
-        info!("Orchestrator initialized.");
+/// How a goal run by `run_goal` ended.
+struct GoalOutcome {
+    succeeded: bool,
+    /// Stopped by Ctrl-C rather than finished or failed.
+    cancelled: bool,
+    state: AppState,
+    /// The run's costs, which the pull request's calls are added to.
+    cost_tracker: Arc<CostTracker>,
+}
 
-        // Display cost information (Phase 1.2)
-        println!("{} {}{:.4}", "💰 Current Session Cost:".bold().green(), "$".bold().green(), cost_tracker.get_total_cost());
+/// Runs one goal (fresh or resumed) to completion in the current directory and reports how it ended.
+async fn run_goal(state: AppState, cli: &Cli, config: &Arc<AppConfig>, models: &RoleModels, transcript: Option<&Arc<dyn EventSink>>) -> Result<GoalOutcome> {
+    run_goal_in(state, cli, config, models, transcript, &std::env::current_dir()?).await
+}
 
-        match orchestrator.run().await {
-            Ok(_) => println!("{}", "✅ Task Completed Successfully!".bold().green()),
-            Err(e) => {
-                error!("Orchestrator failed: {:?}", e);
-                println!("{} {}", "❌ Task Failed:".bold().red(), e);
+/// Runs one goal (fresh or resumed) to completion in `workdir`, which holds its session files,
+/// and reports how it ended.
+async fn run_goal_in(
+    mut state: AppState,
+    cli: &Cli,
+    config: &Arc<AppConfig>,
+    models: &RoleModels,
+    transcript: Option<&Arc<dyn EventSink>>,
+    workdir: &Path,
+) -> Result<GoalOutcome> {
+    let cost_tracker = Arc::new(CostTracker::new());
+    let goal = state.goal.clone();
+    state.workdir = Some(workdir.to_path_buf());
+    state.transcript = cli.transcript.clone().or(state.transcript);
+    let stored = match session_store(config).filter(|_| config.sessions_enabled) {
+        Some(store) => {
+            match store.prune(session_store::MAX_STORED_SESSIONS) {
+                Ok(removed) if !removed.is_empty() => info!("Removed {} old session(s) from {}", removed.len(), store.dir().display()),
+                Ok(_) => {}
+                Err(e) => warn!("Could not prune old sessions in {}: {}", store.dir().display(), e),
             }
+            Some(store.checkpoint_path(&state)?)
+        }
+        None => None,
+    };
+    let mut builder = agent_builder(state, cli, config, models, &cost_tracker, workdir)?;
+
+    // The UI owns the terminal while it runs, so plain output and logging are switched off.
+    let log_level = log::max_level();
+    let cancel = start_run();
+    let tui = cli.tui.then(|| Tui::start(goal, cost_tracker.clone(), cancel.clone()));
+    if tui.is_some() {
+        log::set_max_level(log::LevelFilter::Off);
+    }
+    builder = match (&tui, cli.output) {
+        (Some(tui), _) => builder.event_sink(tui.sink()),
+        // JSON mode: the event stream on stdout is the only output.
+        (None, OutputFormat::Json) => builder.event_sink(Arc::new(JsonlTranscript::stdout())),
+        (None, OutputFormat::Text) => builder.reporter(Arc::new(ConsoleReporter)),
+    };
+    // Confirm mode needs the diff on the terminal and an answer on stdin.
+    if config.confirm_writes && !config.dry_run && !cli.non_interactive && tui.is_none() && cli.output == OutputFormat::Text {
+        builder = builder.approver(Arc::new(ConsoleApprover));
+    }
+    if config.step_feedback && !config.dry_run && !cli.non_interactive && tui.is_none() && cli.output == OutputFormat::Text {
+        builder = builder.steering(Arc::new(ConsoleSteering));
+    }
+    // Model calls cost money in dry runs too, so expensive plans are confirmed there as well.
+    if !cli.non_interactive && tui.is_none() && cli.output == OutputFormat::Text {
+        builder = builder.cost_approver(Arc::new(ConsoleCostApprover));
+    }
+    if let Some(sink) = transcript {
+        builder = builder.event_sink(sink.clone());
+    }
+    let traced = trace_sink();
+    // Hashing the workdir around every step is only worth it when something shows the step
+    // changes; otherwise the run report takes its line counts from the undo journal.
+    builder = builder.step_changes(tui.is_some() || cli.output == OutputFormat::Json || transcript.is_some() || traced.is_some());
+    if let Some(sink) = traced {
+        builder = builder.event_sink(sink);
+    }
+    // The run report is made from the run's events.
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let collected = events.clone();
+    builder = builder.on_event(move |record: &EventRecord| collected.lock().unwrap().push(record.event.clone()));
+    // A dry run leaves no trace on disk, not even a session checkpoint.
+    if !config.dry_run {
+        builder = builder.checkpoint(SESSION_FILE).journal(JOURNAL_FILE);
+        if let Some(path) = stored {
+            builder = builder.checkpoint(path);
         }
-        println!("{}", "===================================".cyan());
     }
+    if let Some(embedder) = create_embedder(config)? {
+        let memory = Memory::new(embedder, config.memory_top_k);
+        builder = builder.memory(if config.dry_run { memory } else { memory.persist_to(workdir.join(MEMORY_FILE))? });
+    }
+    let mut agent = builder.cancellation(cancel).build()?;
+    info!("Agent initialized.");
 
-    Ok(())
+    let result = agent.run().await;
+    end_run();
+    flush_traces().await;
+    if let Some(tui) = tui {
+        tui.finish(result.is_ok())?;
+        log::set_max_level(log_level);
+    }
+
+    let cancelled = matches!(&result, Err(e) if is_cancelled(e));
+    let outcome = match &result {
+        Ok(_) => RunOutcome::Completed,
+        Err(_) if cancelled => RunOutcome::Cancelled,
+        Err(e) => RunOutcome::Failed(e.to_string()),
+    };
+    let journal = if config.dry_run { None } else { Journal::load(&workdir.join(JOURNAL_FILE)).ok() };
+    let report = RunReport::new(agent.state(), outcome, &events.lock().unwrap(), journal.as_ref(), cost_tracker.get_total_cost(), agent.metrics().elapsed());
+    say!(cli, "\n{}", report.to_markdown());
+    if !config.dry_run {
+        match report.save(&workdir.join(run_report::REPORTS_DIR)) {
+            Ok(path) => say!(cli, "{} {}", "📝 Run report saved to".bold().cyan(), path.display()),
+            Err(e) => warn!("Could not save the run report: {}", e),
+        }
+    }
+    let succeeded = match result {
+        Ok(_) => true,
+        Err(e) if is_cancelled(&e) => {
+            if config.dry_run {
+                say!(cli, "{}", "⏹️  Task cancelled.".bold().yellow());
+            } else {
+                say!(cli, "{} {} {}", "⏹️  Task cancelled. Session".bold().yellow(), agent.state().id.bold(), "saved; continue it with `cli_coding_agent run --resume`.".bold().yellow());
+            }
+            false
+        }
+        Err(e) => {
+            error!("Orchestrator failed: {:?}", e);
+            say!(cli, "{} {}", "❌ Task Failed:".bold().red(), e);
+            false
+        }
+    };
+    if !agent.metrics().steps().is_empty() {
+        say!(cli, "{}", "⏱️  Step Timings:".bold().cyan());
+        say!(cli, "{}", agent.metrics().summary());
+    }
+    record_stats(config, agent.state(), succeeded, &cost_tracker);
+    let routes = cost_tracker.usage_by_route();
+    if !routes.is_empty() {
+        say!(cli, "{}", "🔀 Model Routing:".bold().cyan());
+        for (route, usage) in &routes {
+            say!(cli, "  {:<40} {:>4} call{}  ${:.4}", route, usage.calls, if usage.calls == 1 { " " } else { "s" }, usage.cost);
+        }
+    }
+    let reasoning_tokens = cost_tracker.total_usage().reasoning_tokens;
+    if reasoning_tokens > 0 {
+        say!(cli, "{} {} (billed as output)", "🧠 Reasoning Tokens:".bold().cyan(), reasoning_tokens);
+    }
+    say!(cli, "{} {}{:.4}", "💰 Session Cost:".bold().green(), "$".bold().green(), cost_tracker.get_total_cost());
+    say!(cli, "{}", "===================================".cyan());
+    Ok(GoalOutcome { succeeded, cancelled, state: agent.state().clone(), cost_tracker })
 }
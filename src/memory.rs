@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::error::AgentError;
+use crate::llm::embeddings::{create_embeddings_client, EmbeddingsClient};
+
+/// Where the long-term memory of a project is stored between runs.
+pub const MEMORY_FILE: &str = ".agent/memory.json";
+/// Chunks recalled into the context of each decision by default.
+pub const DEFAULT_MEMORY_TOP_K: usize = 5;
+/// Characters per indexed chunk; chunks end on line boundaries where possible.
+const CHUNK_CHARS: usize = 1500;
+
+/// Creates the embeddings client selected by `AGENT_MEMORY` and `AGENT_EMBEDDING_MODEL`; `None`
+/// when long-term memory is off.
+pub fn create_embedder(config: &AppConfig) -> Result<Option<Arc<dyn EmbeddingsClient>>, AgentError> {
+    match config.memory_provider {
+        None => Ok(None),
+        Some(provider) => Ok(Some(create_embeddings_client(provider, config.embedding_model.clone(), config)?)),
+    }
+}
+
+/// One indexed piece of text and its embedding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryChunk {
+    /// Where the text came from, e.g. a file path or `history: Tool Output`.
+    pub source: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+/// A flat local vector store, searched by brute-force cosine similarity.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VectorStore {
+    pub model: String,
+    pub chunks: Vec<MemoryChunk>,
+}
+
+impl VectorStore {
+    pub fn new(model: &str) -> Self {
+        Self { model: model.to_string(), chunks: Vec::new() }
+    }
+
+    /// Loads the store at `path`, starting an empty one if there is none or it was built with
+    /// a different embedding model.
+    pub fn load(path: &Path, model: &str) -> Result<Self, AgentError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new(model)),
+            Err(e) => return Err(e.into()),
+        };
+        let store: Self = serde_json::from_str(&content)?;
+        Ok(if store.model == model { store } else { Self::new(model) })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AgentError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn remove_source(&mut self, source: &str) {
+        self.chunks.retain(|chunk| chunk.source != source);
+    }
+
+    /// The `k` chunks most similar to `query`, best first.
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<&MemoryChunk> {
+        let mut scored: Vec<(f32, &MemoryChunk)> = self.chunks.iter().map(|chunk| (cosine_similarity(query, &chunk.embedding), chunk)).collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(k).map(|(_, chunk)| chunk).collect()
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 { 0.0 } else { dot / denominator }
+}
+
+/// Splits `text` into chunks of about `CHUNK_CHARS` characters, cutting at line ends where
+/// possible. Blank chunks are dropped.
+fn chunk_text(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for line in text.split_inclusive('\n') {
+        if !current.is_empty() && current.chars().count() + line.chars().count() > CHUNK_CHARS {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push_str(line);
+        // A single line longer than a chunk is split on char boundaries.
+        while current.chars().count() > CHUNK_CHARS {
+            let split = current.char_indices().nth(CHUNK_CHARS).map(|(i, _)| i).unwrap_or(current.len());
+            let rest = current.split_off(split);
+            chunks.push(std::mem::replace(&mut current, rest));
+        }
+    }
+    chunks.push(current);
+    chunks.retain(|chunk| !chunk.trim().is_empty());
+    chunks
+}
+
+/// Long-term memory: history entries and file contents are embedded into a vector store, and
+/// the chunks most relevant to a plan step are recalled into the context for its decision.
+pub struct Memory {
+    embedder: Arc<dyn EmbeddingsClient>,
+    store: VectorStore,
+    path: Option<PathBuf>,
+    top_k: usize,
+}
+
+impl Memory {
+    /// An in-memory store that is lost when the run ends.
+    pub fn new(embedder: Arc<dyn EmbeddingsClient>, top_k: usize) -> Self {
+        let store = VectorStore::new(embedder.model());
+        Self { embedder, store, path: None, top_k }
+    }
+
+    /// Loads the store at `path` and saves it there after every change, so later runs in the
+    /// same project remember what earlier ones saw.
+    pub fn persist_to(mut self, path: impl Into<PathBuf>) -> Result<Self, AgentError> {
+        let path = path.into();
+        self.store = VectorStore::load(&path, self.embedder.model())?;
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    pub fn store(&self) -> &VectorStore {
+        &self.store
+    }
+
+    /// Adds `text` under `source` alongside anything already indexed for it.
+    pub async fn remember(&mut self, source: &str, text: &str) -> Result<(), AgentError> {
+        self.index(source, text, false).await
+    }
+
+    /// Indexes `text` as the current content of `source`, replacing its earlier chunks; used for
+    /// files, whose old content would otherwise be recalled.
+    pub async fn replace(&mut self, source: &str, text: &str) -> Result<(), AgentError> {
+        self.index(source, text, true).await
+    }
+
+    async fn index(&mut self, source: &str, text: &str, replace: bool) -> Result<(), AgentError> {
+        let chunks = chunk_text(text);
+        if chunks.is_empty() {
+            return Ok(());
+        }
+        let embeddings = self.embedder.embed(&chunks).await?;
+        if embeddings.len() != chunks.len() {
+            return Err(AgentError::LLMError(format!("expected {} embeddings, got {}", chunks.len(), embeddings.len())));
+        }
+        if replace {
+            self.store.remove_source(source);
+        }
+        self.store.chunks.extend(
+            chunks.into_iter().zip(embeddings).map(|(text, embedding)| MemoryChunk { source: source.to_string(), text, embedding }),
+        );
+        if let Some(path) = &self.path {
+            self.store.save(path)?;
+        }
+        Ok(())
+    }
+
+    /// The `top_k` indexed chunks most relevant to `query`, best first.
+    pub async fn recall(&self, query: &str) -> Result<Vec<MemoryChunk>, AgentError> {
+        if self.store.chunks.is_empty() || self.top_k == 0 {
+            return Ok(Vec::new());
+        }
+        let embedding = self.embedder.embed(&[query.to_string()]).await?.into_iter().next().unwrap_or_default();
+        Ok(self.store.search(&embedding, self.top_k).into_iter().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_ranks_by_cosine_similarity() {
+        let mut store = VectorStore::new("test");
+        for (source, embedding) in [("a", vec![1.0, 0.0]), ("b", vec![0.0, 1.0]), ("c", vec![0.7, 0.7])] {
+            store.chunks.push(MemoryChunk { source: source.to_string(), text: String::new(), embedding });
+        }
+        let sources: Vec<&str> = store.search(&[0.9, 0.1], 2).iter().map(|chunk| chunk.source.as_str()).collect();
+        assert_eq!(sources, ["a", "c"]);
+
+        store.remove_source("a");
+        assert_eq!(store.search(&[0.9, 0.1], 1)[0].source, "c");
+    }
+
+    #[test]
+    fn test_chunk_text_splits_at_line_ends() {
+        let line = format!("{}\n", "x".repeat(99));
+        let chunks = chunk_text(&line.repeat(40));
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= CHUNK_CHARS && chunk.ends_with('\n')));
+        assert_eq!(chunk_text(&"y".repeat(CHUNK_CHARS * 2 + 1)).len(), 3);
+        assert!(chunk_text("  \n").is_empty());
+    }
+
+    #[test]
+    fn test_store_is_reset_for_another_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory.json");
+        let mut store = VectorStore::new("model-a");
+        store.chunks.push(MemoryChunk { source: "a".to_string(), text: "t".to_string(), embedding: vec![1.0] });
+        store.save(&path).unwrap();
+
+        assert_eq!(VectorStore::load(&path, "model-a").unwrap().chunks.len(), 1);
+        assert!(VectorStore::load(&path, "model-b").unwrap().chunks.is_empty());
+    }
+}
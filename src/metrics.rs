@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+use crate::cost_tracker::{AgentUsage, CostTracker};
+
+/// Wall-clock timing of one executed plan step, including the LLM calls made during it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepTiming {
+    pub step_index: usize,
+    pub step: String,
+    pub duration: Duration,
+    pub llm_calls: u64,
+    pub llm_time: Duration,
+}
+
+/// Times a run and each of its steps for progress lines and the final summary. LLM calls are
+/// attributed to a step from the cost tracker's totals before and after it.
+#[derive(Debug, Clone)]
+pub struct RunMetrics {
+    started: Instant,
+    steps: Vec<StepTiming>,
+    current: Option<(usize, String, Instant, AgentUsage)>,
+}
+
+impl Default for RunMetrics {
+    fn default() -> Self {
+        Self { started: Instant::now(), steps: Vec::new(), current: None }
+    }
+}
+
+impl RunMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Steps finished so far, in the order they ran.
+    pub fn steps(&self) -> &[StepTiming] {
+        &self.steps
+    }
+
+    pub fn start_step(&mut self, step_index: usize, step: &str, cost_tracker: &CostTracker) {
+        self.current = Some((step_index, step.to_string(), Instant::now(), cost_tracker.total_usage()));
+    }
+
+    /// Records the step started last and returns its timing; `None` if no step is running.
+    pub fn finish_step(&mut self, cost_tracker: &CostTracker) -> Option<StepTiming> {
+        let (step_index, step, started, before) = self.current.take()?;
+        let after = cost_tracker.total_usage();
+        let timing = StepTiming {
+            step_index,
+            step,
+            duration: started.elapsed(),
+            llm_calls: after.calls - before.calls,
+            llm_time: Duration::from_millis(after.total_latency_ms - before.total_latency_ms),
+        };
+        self.steps.push(timing.clone());
+        Some(timing)
+    }
+
+    /// E.g. "Step 3/7, ~2m elapsed, ~3m left". The estimate is the average step so far times the
+    /// steps remaining, so it only appears once a step has finished.
+    pub fn progress(&self, step_index: usize, total: usize) -> String {
+        let mut progress = format!("Step {}/{}, ~{} elapsed", step_index + 1, total, format_duration(self.elapsed()));
+        if !self.steps.is_empty() {
+            let average = self.steps.iter().map(|s| s.duration).sum::<Duration>() / self.steps.len() as u32;
+            let remaining = total.saturating_sub(step_index) as u32;
+            progress.push_str(&format!(", ~{} left", format_duration(average * remaining)));
+        }
+        progress
+    }
+
+    /// One line per finished step with its time and LLM share, then the total.
+    pub fn summary(&self) -> String {
+        let mut lines: Vec<String> = self
+            .steps
+            .iter()
+            .map(|s| {
+                format!(
+                    "  {:>3}. {:<48} {:>8}  ({} LLM call{}, {})",
+                    s.step_index + 1,
+                    truncate(&s.step, 48),
+                    format_duration(s.duration),
+                    s.llm_calls,
+                    if s.llm_calls == 1 { "" } else { "s" },
+                    format_duration(s.llm_time)
+                )
+            })
+            .collect();
+        lines.push(format!("  Total: {}", format_duration(self.elapsed())));
+        lines.join("\n")
+    }
+}
+
+/// "850ms", "42s", "2m 5s" or "1h 3m".
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0 => format!("{}ms", duration.as_millis()),
+        1..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max_chars - 3).collect();
+    format!("{}...", kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::AIResponse;
+
+    fn response() -> AIResponse {
+        AIResponse { content: String::new(), input_tokens: 10, output_tokens: 5, reasoning_tokens: 0, cost: 0.0, model: "mock".to_string(), provider: "mock".to_string() }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(850)), "850ms");
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m 5s");
+        assert_eq!(format_duration(Duration::from_secs(3780)), "1h 3m");
+    }
+
+    #[test]
+    fn test_steps_record_their_llm_calls_and_feed_the_estimate() {
+        let tracker = CostTracker::new();
+        let mut metrics = RunMetrics::new();
+        assert!(metrics.progress(0, 3).starts_with("Step 1/3, ~"));
+        assert!(!metrics.progress(0, 3).contains("left"));
+        assert!(metrics.finish_step(&tracker).is_none());
+
+        tracker.record_call("planner", &response(), Duration::from_millis(100));
+        metrics.start_step(0, "Write main.rs", &tracker);
+        tracker.record_call("decision", &response(), Duration::from_millis(200));
+        tracker.record_call("coder", &response(), Duration::from_millis(300));
+        let timing = metrics.finish_step(&tracker).unwrap();
+        assert_eq!((timing.step_index, timing.llm_calls, timing.llm_time), (0, 2, Duration::from_millis(500)));
+
+        assert!(metrics.progress(1, 3).starts_with("Step 2/3, ~"));
+        assert!(metrics.progress(1, 3).contains(" left"));
+        let summary = metrics.summary();
+        assert!(summary.contains("1. Write main.rs") && summary.contains("(2 LLM calls, 500ms)"), "{}", summary);
+        assert!(summary.lines().last().unwrap().starts_with("  Total: "));
+    }
+}
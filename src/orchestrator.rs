@@ -1,103 +1,1169 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 use anyhow::Result;
 use colored::*;
+use futures::future::{join_all, BoxFuture, FutureExt};
 use log::{info, warn};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    agents::{coder::CoderAgent, planner::PlannerAgent},
+    agents::{coder::{self, CoderAgent}, judge::{self, JudgeAgent}, planner::PlannerAgent, retriever::RetrieverAgent, reviewer::ReviewerAgent, summarizer::SummarizerAgent, verifier::VerifierAgent, SystemPrompts},
+    approval::{Approver, CostApprover, Steering},
+    context::{truncate_to_tokens, ContextBuilder},
     error::AgentError,
-    llm::LLMClient,
+    events::{AgentEvent, EventBus, EventSink},
+    journal::Journal,
+    json_repair,
+    llm::{ChatMessage, ImageAttachment, LLMClient, LLMProvider, RoleParams, ToolCall},
+    memory::Memory,
+    metrics::{format_duration, RunMetrics},
+    reporter::{ConsoleReporter, PrefixedReporter, Reporter},
+    snapshot::WorkspaceSnapshot,
     state::AppState,
-    tools::{self, Tool, ToolResult, Decision},
-    cost_tracker::CostTracker,
+    tools::{self, CommandOptions, CommandOutput, Tool, ToolContext, ToolRegistry, ToolResult, Decision},
+    cost_tracker::{CostEstimate, CostTracker, ESTIMATED_CODE_TOKENS, ESTIMATED_DECISION_TOKENS},
 };
 
+/// How many times a failing command is handed back to the reasoning client for a fix by default.
+pub const DEFAULT_MAX_FIX_ATTEMPTS: u32 = 3;
+
+/// How many times a decision that is not valid JSON, even after repair, is sent back to the
+/// reasoning client for correction by default.
+pub const DEFAULT_MAX_REPAIR_ATTEMPTS: u32 = 2;
+
+/// How many reviewer passes generated code gets before it is saved by default. Zero disables review.
+pub const DEFAULT_MAX_REVIEW_ITERATIONS: u32 = 2;
+
+/// How many times an incomplete verification sends the run back into planning by default.
+pub const DEFAULT_MAX_VERIFICATION_ROUNDS: u32 = 1;
+
+/// How many plan steps a single run may execute by default, across re-plans and verification rounds.
+pub const DEFAULT_MAX_STEPS: usize = 50;
+
+/// How many history entries a run keeps before older ones are summarized by default, however
+/// small they are.
+pub const DEFAULT_MAX_HISTORY_ENTRIES: usize = 30;
+
+/// Bytes of tool output above which it is summarized for the history by default; the full text is
+/// saved under `tools::TOOL_OUTPUT_DIR`.
+pub const DEFAULT_SUMMARIZE_OUTPUT_BYTES: usize = 16 * 1024;
+
+/// How many times in a row the same decision may produce the same result before the run is aborted.
+pub const DEFAULT_LOOP_THRESHOLD: u32 = 3;
+
+/// How many existing files are shown to the coder before it writes code, when configured from `AppConfig`.
+pub const DEFAULT_RELEVANT_FILES: usize = 5;
+
+/// How many images are shown with each decision; attaching another drops the oldest.
+const MAX_ATTACHED_IMAGES: usize = 4;
+
+/// Sends a line of progress output to the orchestrator's `Reporter`.
+macro_rules! say {
+    ($self:expr) => {
+        $self.reporter.report("")
+    };
+    ($self:expr, $($arg:tt)*) => {
+        $self.reporter.report(&format!($($arg)*))
+    };
+}
+
 pub struct Orchestrator {
     state: AppState,
     llm_client: Arc<dyn LLMClient>,
     reasoning_client: Arc<dyn LLMClient>,
+    /// Clients that each answer every decision prompt when there are two or more; the majority
+    /// or, on a split vote, a judge picks the action.
+    decision_ensemble: Vec<Arc<dyn LLMClient>>,
+    planner_client: Arc<dyn LLMClient>,
+    summarizer_client: Arc<dyn LLMClient>,
     cost_tracker: Arc<CostTracker>,
+    params: RoleParams,
+    system_prompts: SystemPrompts,
+    max_fix_attempts: u32,
+    max_repair_attempts: u32,
+    max_review_iterations: u32,
+    context: ContextBuilder,
+    max_history_entries: usize,
+    summarize_output_bytes: usize,
+    native_tool_calls: bool,
+    replan: bool,
+    verify: bool,
+    max_verification_rounds: u32,
+    max_steps: usize,
+    /// Dollars the run may spend, as recorded by `cost_tracker`; checked before every step.
+    max_cost: Option<f64>,
+    steps_executed: usize,
+    /// How many subtasks a step may hand to parallel workers; fewer than two disables `Delegate`.
+    max_workers: usize,
+    /// How many existing files are picked and shown to the coder before it writes code; zero disables.
+    max_relevant_files: usize,
+    metrics: RunMetrics,
+    loop_detector: LoopDetector,
+    events: EventBus,
+    reporter: Arc<dyn Reporter>,
+    /// Asked before each file change; changes are made without asking when unset.
+    approver: Option<Arc<dyn Approver>>,
+    /// Held while a change is shown and approved, so parallel workers ask one at a time.
+    approval_turn: Arc<tokio::sync::Mutex<()>>,
+    /// For a worker, which worker of its delegation changes which file.
+    file_claims: Option<FileClaims>,
+    /// Asked for feedback after each step that changed files; never asked when unset.
+    steering: Option<Arc<dyn Steering>>,
+    /// Dollars above which a new plan's estimated cost needs `cost_approver`'s consent; no estimate
+    /// is made when unset.
+    cost_threshold: Option<f64>,
+    cost_approver: Option<Arc<dyn CostApprover>>,
+    checkpoint_paths: Vec<PathBuf>,
+    journal_path: Option<PathBuf>,
+    journal: Option<Journal>,
+    memory: Option<Memory>,
+    tools: ToolRegistry,
+    /// Settings and working directory for tools; loaded from the environment per call when unset.
+    tool_context: Option<ToolContext>,
+    cancel: CancellationToken,
+    /// Where the history of the step being executed starts; `None` between steps.
+    in_flight_step: Option<usize>,
+    /// Files changed since the memory last indexed them.
+    unindexed_files: Vec<String>,
+    /// Files changed by the step being executed, for `steering`.
+    step_files: Vec<String>,
+    /// Whether steps' file changes are emitted as `StepChanges`, when events are emitted at all.
+    step_changes: bool,
+    /// The working directory as the step being executed found it, when `StepChanges` are emitted.
+    step_snapshot: Option<WorkspaceSnapshot>,
+    /// The working directory as the last step left it, so the next snapshot only reads what changed.
+    last_snapshot: Option<WorkspaceSnapshot>,
+    /// The text of the files earlier steps changed, as they left them, to diff their next change.
+    changed_texts: BTreeMap<String, String>,
+    /// The attached images as last read, by resolved path with the modification time they were
+    /// read at, so every decision does not read and encode them again.
+    image_cache: std::sync::Mutex<HashMap<PathBuf, (Option<SystemTime>, ImageAttachment)>>,
 }
 
 impl Orchestrator {
     pub fn new(goal: String, llm_client: Arc<dyn LLMClient>, reasoning_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
+        Self::from_state(AppState::new(goal), llm_client, reasoning_client, cost_tracker)
+    }
+
+    /// Builds an orchestrator around an existing (e.g. checkpointed) state. If the state already
+    /// has a plan, `run` skips planning and continues from `current_step`.
+    pub fn from_state(state: AppState, llm_client: Arc<dyn LLMClient>, reasoning_client: Arc<dyn LLMClient>, cost_tracker: Arc<CostTracker>) -> Self {
         Self {
-            state: AppState::new(goal),
+            state,
             llm_client,
+            planner_client: reasoning_client.clone(),
+            summarizer_client: reasoning_client.clone(),
             reasoning_client,
+            decision_ensemble: Vec::new(),
             cost_tracker,
+            params: RoleParams::default(),
+            system_prompts: SystemPrompts::default(),
+            max_fix_attempts: DEFAULT_MAX_FIX_ATTEMPTS,
+            max_repair_attempts: DEFAULT_MAX_REPAIR_ATTEMPTS,
+            max_review_iterations: DEFAULT_MAX_REVIEW_ITERATIONS,
+            context: ContextBuilder::default(),
+            max_history_entries: DEFAULT_MAX_HISTORY_ENTRIES,
+            summarize_output_bytes: DEFAULT_SUMMARIZE_OUTPUT_BYTES,
+            native_tool_calls: true,
+            replan: true,
+            verify: true,
+            max_verification_rounds: DEFAULT_MAX_VERIFICATION_ROUNDS,
+            max_steps: DEFAULT_MAX_STEPS,
+            max_cost: None,
+            steps_executed: 0,
+            max_workers: 0,
+            max_relevant_files: 0,
+            metrics: RunMetrics::new(),
+            loop_detector: LoopDetector::new(DEFAULT_LOOP_THRESHOLD),
+            events: EventBus::new(),
+            reporter: Arc::new(ConsoleReporter),
+            approver: None,
+            approval_turn: Arc::new(tokio::sync::Mutex::new(())),
+            file_claims: None,
+            steering: None,
+            cost_threshold: None,
+            cost_approver: None,
+            checkpoint_paths: Vec::new(),
+            journal_path: None,
+            journal: None,
+            memory: None,
+            tools: ToolRegistry::default(),
+            tool_context: None,
+            cancel: CancellationToken::new(),
+            in_flight_step: None,
+            unindexed_files: Vec::new(),
+            step_files: Vec::new(),
+            step_changes: true,
+            step_snapshot: None,
+            last_snapshot: None,
+            changed_texts: BTreeMap::new(),
+            image_cache: Default::default(),
         }
     }
 
+    /// Uses a separate client for planning instead of the reasoning client.
+    pub fn with_planner_client(mut self, planner_client: Arc<dyn LLMClient>) -> Self {
+        self.planner_client = planner_client;
+        self
+    }
+
+    /// Uses a separate (typically cheaper) client to summarize history and large tool output
+    /// instead of the reasoning client.
+    pub fn with_summarizer_client(mut self, summarizer_client: Arc<dyn LLMClient>) -> Self {
+        self.summarizer_client = summarizer_client;
+        self
+    }
+
+    /// Sends every decision prompt to each of `clients` (two or three, typically of different
+    /// providers) instead of the reasoning client alone. The tool most of them choose is used; on a
+    /// split vote the reasoning client critiques the candidates and picks one. Costs a decision
+    /// call per client, plus the judge's call on split votes.
+    pub fn with_decision_ensemble(mut self, clients: Vec<Arc<dyn LLMClient>>) -> Self {
+        self.decision_ensemble = clients;
+        self
+    }
+
+    /// Saves the session state to `path` after planning and after every step. Call it again to
+    /// keep the same checkpoint in several places.
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint_paths.push(path.into());
+        self
+    }
+
+    /// Journals the original content of every file the run writes or edits to `path`, so the
+    /// run can be undone with `journal::Journal::rollback`.
+    pub fn with_journal(mut self, path: impl Into<PathBuf>) -> Self {
+        self.journal_path = Some(path.into());
+        self
+    }
+
+    /// Indexes history and changed files into `memory` after every step and recalls the most
+    /// relevant chunks into the context of each tool decision, in place of older history.
+    pub fn with_memory(mut self, memory: Memory) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// The tools offered to the reasoning model; the built-in ones by default.
+    pub fn with_tool_registry(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools.with_delegation(self.max_workers > 1);
+        self
+    }
+
+    /// Runs tools with `context` instead of settings read from the environment, so concurrent
+    /// sessions can each have their own configuration and working directory.
+    pub fn with_tool_context(mut self, context: ToolContext) -> Self {
+        self.tool_context = Some(context);
+        self
+    }
+
+    /// Stops the run with `AgentError::Cancelled` once `token` is cancelled. In-flight LLM
+    /// requests are dropped and running commands killed; the interrupted step is rolled back out
+    /// of the state and the checkpoint saved, so resuming runs that step again.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Temperature, token limit and top-p for each role's requests.
+    pub fn with_generation_params(mut self, params: RoleParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Personas sent as system messages by the planner, coder and reviewer.
+    pub fn with_system_prompts(mut self, system_prompts: SystemPrompts) -> Self {
+        self.system_prompts = system_prompts;
+        self
+    }
+
+    pub fn with_max_fix_attempts(mut self, max_fix_attempts: u32) -> Self {
+        self.max_fix_attempts = max_fix_attempts;
+        self
+    }
+
+    pub fn with_max_repair_attempts(mut self, max_repair_attempts: u32) -> Self {
+        self.max_repair_attempts = max_repair_attempts;
+        self
+    }
+
+    pub fn with_max_review_iterations(mut self, max_review_iterations: u32) -> Self {
+        self.max_review_iterations = max_review_iterations;
+        self
+    }
+
+    /// Token budget for the context sent to agents; older history is summarized once it is exceeded.
+    pub fn with_context_budget(mut self, tokens: usize) -> Self {
+        self.context = self.context.with_budget(tokens);
+        self
+    }
+
+    /// Counts context tokens with `provider`'s tokenizer, so the budget matches what its models
+    /// are billed for; a provider-neutral approximation is used otherwise.
+    pub fn with_tokenizer(mut self, provider: LLMProvider) -> Self {
+        self.context = self.context.with_provider(provider);
+        self
+    }
+
+    /// Summarizes older history once it has more than `entries` entries, even if they still fit the
+    /// context budget, so long runs keep a compact context. Zero leaves it to the budget alone.
+    pub fn with_max_history_entries(mut self, entries: usize) -> Self {
+        self.max_history_entries = entries;
+        self
+    }
+
+    /// Tool output longer than `bytes` is summarized before it enters the history, with the full
+    /// text saved under `tools::TOOL_OUTPUT_DIR` for `ReadToolOutput`. Zero keeps output as is.
+    pub fn with_output_summary_threshold(mut self, bytes: usize) -> Self {
+        self.summarize_output_bytes = bytes;
+        self
+    }
+
+    /// Whether tool decisions use the reasoning client's native tool calling (the default) or
+    /// prompt-enforced JSON text.
+    pub fn with_native_tool_calls(mut self, enabled: bool) -> Self {
+        self.native_tool_calls = enabled;
+        self
+    }
+
+    /// Whether to check the remaining plan after every step and re-plan when it is no longer valid.
+    pub fn with_replanning(mut self, enabled: bool) -> Self {
+        self.replan = enabled;
+        self
+    }
+
+    /// Whether to hash the working directory before and after each step to emit what it changed
+    /// as `AgentEvent::StepChanges`. On by default, but only done when something receives events.
+    pub fn with_step_changes(mut self, enabled: bool) -> Self {
+        self.step_changes = enabled;
+        self
+    }
+
+    /// Whether to have the reasoning client verify the goal was achieved once the plan is done.
+    pub fn with_verification(mut self, enabled: bool) -> Self {
+        self.verify = enabled;
+        self
+    }
+
+    /// How many times an incomplete verification may send the run back into planning, with the
+    /// missing items as the new objective. Zero only reports the verdict.
+    pub fn with_max_verification_rounds(mut self, rounds: u32) -> Self {
+        self.max_verification_rounds = rounds;
+        self
+    }
+
+    /// Upper bound on plan steps executed in one run, including steps added by re-planning.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Stops the run with `AgentError::BudgetExceeded` before the next step once `cost_tracker`
+    /// has recorded `dollars` or more. Unlimited when unset.
+    pub fn with_max_cost(mut self, dollars: f64) -> Self {
+        self.max_cost = Some(dollars);
+        self
+    }
+
+    /// Lets the reasoning model split a step into up to `workers` independent subtasks with
+    /// `Delegate`. Each is planned and run by a worker orchestrator in parallel with the others and
+    /// their histories are merged back into this run. Fewer than two, the default, disables it.
+    pub fn with_max_workers(mut self, workers: usize) -> Self {
+        self.max_workers = workers;
+        self.tools = std::mem::take(&mut self.tools).with_delegation(workers > 1);
+        self
+    }
+
+    /// Before each `CodeGeneration`, has the reasoning model pick up to `files` existing project
+    /// files the task modifies or builds on, and shows their content to the coder. Zero, the
+    /// default, disables it.
+    pub fn with_max_relevant_files(mut self, files: usize) -> Self {
+        self.max_relevant_files = files;
+        self
+    }
+
+    /// Aborts the run once the same decision (tool and parameters) has produced the same result
+    /// `threshold` times in a row. Zero disables loop detection.
+    pub fn with_loop_threshold(mut self, threshold: u32) -> Self {
+        self.loop_detector = LoopDetector::new(threshold);
+        self
+    }
+
+    /// Subscribes `sink` to the structured events emitted during the run (plan, steps, decisions,
+    /// tool results, generated code, errors).
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.events.subscribe(sink);
+        self
+    }
+
+    /// Where progress output goes; stdout by default. Use `NullReporter` when something else renders
+    /// the run, e.g. an event sink driving a terminal UI.
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Has `approver` accept or reject every file change after its diff is shown (confirm mode).
+    pub fn with_approver(mut self, approver: Arc<dyn Approver>) -> Self {
+        self.approver = Some(approver);
+        self
+    }
+
+    /// Has `steering` review the files each step changed and add feedback for the next decision.
+    /// Workers of a delegated step are not asked; the delegating step is, once they finish.
+    pub fn with_steering(mut self, steering: Arc<dyn Steering>) -> Self {
+        self.steering = Some(steering);
+        self
+    }
+
+    /// Estimates the cost of each new plan before it runs and, when the estimate is over
+    /// `threshold` dollars, asks the cost approver (if any) whether to go ahead.
+    pub fn with_cost_threshold(mut self, threshold: f64) -> Self {
+        self.cost_threshold = Some(threshold);
+        self
+    }
+
+    /// Has `approver` confirm plans estimated to cost more than the cost threshold.
+    pub fn with_cost_approver(mut self, approver: Arc<dyn CostApprover>) -> Self {
+        self.cost_approver = Some(approver);
+        self
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Timings of the steps run so far.
+    pub fn metrics(&self) -> &RunMetrics {
+        &self.metrics
+    }
+
     pub async fn run(&mut self) -> Result<()> {
-        self.gather_initial_context().await?;
-        self.create_plan().await?;
+        self.metrics = RunMetrics::new();
+        self.open_journal();
+        let cancel = self.cancel.clone();
+        let result = tokio::select! {
+            result = self.run_plan() => result,
+            _ = cancel.cancelled() => Err(AgentError::Cancelled),
+        };
+        if matches!(result, Err(AgentError::Cancelled)) {
+            self.abandon_step();
+        }
+        if let Err(e) = &result {
+            let step_index = (!self.state.plan.is_empty()).then_some(self.state.current_step);
+            self.events.emit(AgentEvent::Error { step_index, message: e.to_string() });
+        }
+        self.events.emit(AgentEvent::RunFinished { success: result.is_ok(), total_cost: self.cost_tracker.get_total_cost() });
+        Ok(result?)
+    }
+
+    /// Gathers context and creates the plan without executing any of it.
+    pub async fn plan(&mut self) -> Result<Vec<String>, AgentError> {
+        let cancel = self.cancel.clone();
+        tokio::select! {
+            result = async {
+                self.gather_initial_context().await?;
+                self.create_plan().await
+            } => result?,
+            _ = cancel.cancelled() => return Err(AgentError::Cancelled),
+        }
+        Ok(self.state.plan.clone())
+    }
+
+    async fn run_plan(&mut self) -> Result<(), AgentError> {
+        if self.state.plan.is_empty() {
+            self.gather_initial_context().await?;
+            self.create_plan().await?;
+            self.confirm_cost(self.state.current_step).await?;
+            self.checkpoint();
+        } else if self.state.history.is_empty() {
+            // A plan supplied up front (e.g. edited by the user) rather than a resumed session.
+            self.gather_initial_context().await?;
+            self.announce_plan();
+            self.confirm_cost(self.state.current_step).await?;
+            self.checkpoint();
+        } else {
+            say!(self, "{} {}/{}", "⏯️  Resuming at step".bold().yellow(), self.state.current_step + 1, self.state.plan.len());
+        }
         self.execute_plan().await?;
+        if self.verify {
+            self.verify_goal().await?;
+        }
         Ok(())
     }
 
+    /// Asks the verifier whether the goal was achieved. While it is not and rounds remain, plans the
+    /// missing work, appends it to the plan and executes it. A failed verifier call is logged and
+    /// ends verification: the plan itself has already run.
+    async fn verify_goal(&mut self) -> Result<(), AgentError> {
+        let verifier = VerifierAgent::new(self.reasoning_client.clone(), self.cost_tracker.clone()).with_params(self.params.reasoner);
+        let mut round = 0;
+        loop {
+            say!(self, "{}", "🧪 Verifying the goal was achieved...".yellow());
+            let changes = self.workspace_changes().await;
+            let verification = match verifier.verify(&self.state.goal, &self.context(), &changes).await {
+                Ok(verification) => verification,
+                Err(e) => {
+                    warn!("Goal verification failed, skipping it: {}", e);
+                    return Ok(());
+                }
+            };
+            self.events.emit(AgentEvent::GoalVerified { complete: verification.is_complete(), missing: verification.missing.clone() });
+            if verification.is_complete() {
+                say!(self, "   {} Goal verified as complete", "✅ Verified:".green());
+                self.state.add_history("Verification", "Complete");
+                return Ok(());
+            }
+
+            let missing = verification.missing.iter().map(|m| format!("- {}", m)).collect::<Vec<_>>().join("\n");
+            say!(self, "   {}\n{}", "📝 Still Missing:".yellow(), missing);
+            self.state.add_history("Verification", &format!("Incomplete. Missing:\n{}", missing));
+            if round >= self.max_verification_rounds {
+                warn!("Goal still incomplete after {} verification round(s).", round);
+                say!(self, "   {} Verification rounds exhausted, stopping.", "⚠️ Incomplete:".yellow());
+                return Ok(());
+            }
+            round += 1;
+
+            say!(self, "{} {}/{}", "🔁 Planning the missing work, round".bold().yellow(), round, self.max_verification_rounds);
+            let planner = PlannerAgent::new(self.planner_client.clone(), self.cost_tracker.clone()).with_params(self.params.planner).with_system_prompt(self.system_prompts.planner.clone());
+            let objective = format!("Finish the goal \"{}\". This work is still missing:\n{}", self.state.goal, missing);
+            let steps = planner.create_plan(&objective, &self.context()).await?;
+            let step_index = self.state.plan.len();
+            self.state.plan.extend(steps.iter().cloned());
+            self.events.emit(AgentEvent::PlanRevised { step_index, reason: "Verification found missing work".to_string(), steps });
+            say!(self, "{}", "📝 Additional Steps:".bold().green());
+            for (n, step) in self.state.plan.iter().enumerate().skip(step_index) {
+                say!(self, "   {}. {}", n + 1, step);
+            }
+            self.confirm_cost(step_index).await?;
+            self.checkpoint();
+            self.execute_plan().await?;
+        }
+    }
+
+    /// Summarizes what changed in the session's working tree for the verifier, via `git status` and
+    /// `git diff --stat`. Outside a git repository the verifier relies on the run history alone.
+    /// Git runs directly in the workdir, not through the shell or execution backend the tools use.
+    async fn workspace_changes(&self) -> String {
+        let workdir = self.tool_context.as_ref().and_then(|context| context.workdir.clone());
+        let mut summary = String::new();
+        for args in [["status", "--short"], ["diff", "--stat"]] {
+            let mut git = tokio::process::Command::new("git");
+            git.args(args).stdin(std::process::Stdio::null());
+            if let Some(dir) = &workdir {
+                git.current_dir(dir);
+            }
+            match git.output().await {
+                Ok(output) if output.status.success() => summary.push_str(&String::from_utf8_lossy(&output.stdout)),
+                _ => return "Not a git repository; rely on the run history.".to_string(),
+            }
+        }
+        if summary.trim().is_empty() {
+            "No uncommitted changes.".to_string()
+        } else {
+            truncate_to_tokens(&summary, self.context.budget() / 4)
+        }
+    }
+
+    /// Forgets the history of the interrupted step, which is re-run on resume, and saves the
+    /// checkpoint and journal as they stand.
+    fn abandon_step(&mut self) {
+        if let Some(history_start) = self.in_flight_step.take() {
+            self.state.history.truncate(history_start);
+        }
+        say!(self, "{}", "⏹️  Run cancelled; the interrupted step will be re-run on resume.".yellow());
+        self.checkpoint();
+        self.save_journal();
+    }
+
+    fn checkpoint(&self) {
+        for path in &self.checkpoint_paths {
+            if let Err(e) = self.state.save(path) {
+                warn!("Failed to save session checkpoint to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Starts a fresh journal for a new run; a resumed run keeps adding to the journal it began.
+    fn open_journal(&mut self) {
+        let Some(path) = &self.journal_path else { return };
+        let resumed = !self.state.history.is_empty();
+        self.journal = Some(match Journal::load(path) {
+            Ok(journal) if resumed && journal.goal == self.state.goal => journal,
+            _ => Journal::new(&self.state.goal),
+        });
+        self.save_journal();
+    }
+
+    fn save_journal(&self) {
+        if let (Some(path), Some(journal)) = (&self.journal_path, &self.journal) {
+            if let Err(e) = journal.save(path) {
+                warn!("Failed to save the undo journal to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    fn tool_context(&self) -> Result<ToolContext, AgentError> {
+        let context = match &self.tool_context {
+            Some(context) => context.clone(),
+            None => ToolContext::from_env()?,
+        };
+        Ok(context.with_cancellation(self.cancel.clone()))
+    }
+
+    /// Reports the diff of the file change `tool` is about to make, terminal and history alike, and
+    /// asks the approver, if any. A rejected change fails the tool.
+    async fn preview_change(&mut self, tool: &Tool, context: &ToolContext) -> Result<(), AgentError> {
+        let Some(preview) = tools::preview_change(tool, context).await else {
+            return Ok(());
+        };
+        // Workers share the approver, so one shows its diff and waits for the answer at a time.
+        let _turn = match &self.approver {
+            Some(_) => Some(self.approval_turn.clone().lock_owned().await),
+            None => None,
+        };
+        say!(self, "   {} {}", "📄 Changes to".magenta(), preview.path);
+        say!(self, "{}", colorize_diff(&preview.diff));
+        // New files are recorded by size only: their content is already in the decision or the
+        // generated code.
+        if preview.created {
+            let lines = preview.diff.lines().filter(|l| l.starts_with('+') && !l.starts_with("+++")).count();
+            self.state.add_history("File Diff", &format!("Creating {} ({} lines)", preview.path, lines));
+        } else {
+            self.state.add_history("File Diff", &preview.diff);
+        }
+        if let Some(approver) = &self.approver {
+            if !approver.approve(&preview.path, &preview.diff).await {
+                say!(self, "   {} Change to {} rejected", "🚫 Rejected:".yellow(), preview.path);
+                return Err(AgentError::ToolError(format!("The user rejected the change to {}", preview.path)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `tool`, first showing the change it makes and journaling the file it is about to change.
+    /// A worker may not change a file another worker of its delegation has changed.
+    async fn run_tool(&mut self, tool: Tool) -> Result<ToolResult, AgentError> {
+        let context = self.tool_context()?;
+        if let (Some(claims), Some(file)) = (&self.file_claims, tool.modified_file()) {
+            claims.claim(Path::new(&context.resolve(file)))?;
+        }
+        if !context.config.dry_run {
+            self.preview_change(&tool, &context).await?;
+        }
+        if let (Some(journal), Some(file)) = (self.journal.as_mut(), tool.modified_file()) {
+            match journal.record(Path::new(&context.resolve(file))) {
+                Ok(()) => self.save_journal(),
+                Err(e) => warn!("{} will not be restored by undo: {}", file, e),
+            }
+        }
+        if let (Some(snapshot), Some(file)) = (self.step_snapshot.as_mut(), tool.modified_file()) {
+            // Hidden and ignored files are only tracked once the step writes them.
+            snapshot.include(Path::new(&context.resolve(file)));
+        }
+        let modified = tool.modified_file().map(str::to_string);
+        let result = self.tools.run(tool, &context).await;
+        if let (Some(file), Ok(ToolResult::Success(output))) = (modified, &result) {
+            if !output.starts_with(tools::DRY_RUN_PREFIX) {
+                if self.memory.is_some() && !self.unindexed_files.contains(&file) {
+                    self.unindexed_files.push(file.clone());
+                }
+                if !self.step_files.contains(&file) {
+                    self.step_files.push(file.clone());
+                }
+                if !self.state.files_modified.contains(&file) {
+                    self.state.files_modified.push(file);
+                }
+                self.refresh_pinned_files();
+            }
+        }
+        result
+    }
+
+    fn context(&self) -> String {
+        self.context.build(&self.state)
+    }
+
+    /// The context for the decision on `step`: with memory, the chunks most relevant to the step
+    /// stand in for older history. Recall failures fall back to the plain context.
+    async fn decision_context(&self, step: &str) -> String {
+        let Some(memory) = &self.memory else {
+            return self.context();
+        };
+        match memory.recall(&format!("{}\n{}", self.state.goal, step)).await {
+            Ok(chunks) => {
+                let recalled: Vec<String> = chunks.into_iter().map(|chunk| format!("[{}]\n{}", chunk.source, chunk.text.trim_end())).collect();
+                self.context.build_with_recall(&self.state, &recalled)
+            }
+            Err(e) => {
+                warn!("Recalling memory failed, using the plain context: {}", e);
+                self.context()
+            }
+        }
+    }
+
+    /// Indexes the history entries added by step `step_index` (from `history_start` on) and the
+    /// current content of the files it changed. Failures are logged; memory is best effort.
+    async fn update_memory(&mut self, step_index: usize, history_start: usize) {
+        let context = self.tool_context.clone();
+        let Some(memory) = self.memory.as_mut() else { return };
+        for (entry_type, content) in self.state.history.iter().skip(history_start) {
+            let source = format!("step {}: {}", step_index + 1, entry_type);
+            if let Err(e) = memory.remember(&source, content).await {
+                warn!("Could not index {} into memory: {}", source, e);
+            }
+        }
+        for file in std::mem::take(&mut self.unindexed_files) {
+            let path = context.as_ref().map_or_else(|| file.clone(), |context| context.resolve(&file));
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!("Could not read {} for memory: {}", file, e);
+                    continue;
+                }
+            };
+            if let Err(e) = memory.replace(&file, &content).await {
+                warn!("Could not index {} into memory: {}", file, e);
+            }
+        }
+    }
+
+    /// Summarizes older history through the summarizer client once the full history exceeds the
+    /// context budget or `max_history_entries`. Failures are logged and ignored: the builder still
+    /// truncates to fit.
+    async fn compact_history(&mut self) {
+        let too_many = self.max_history_entries > 0 && self.state.history.len() > self.max_history_entries;
+        if !(too_many || self.context.needs_compaction(&self.state)) || self.state.compactable_history().is_empty() {
+            return;
+        }
+        say!(self, "{} {} {}", "🗜️  Summarizing".yellow(), self.state.compactable_history().len(), "earlier history entries to keep the context small...".yellow());
+        let summarizer = SummarizerAgent::new(self.summarizer_client.clone(), self.cost_tracker.clone()).with_params(self.params.summarizer);
+        let summary = summarizer
+            .summarize(&self.state.goal, self.state.summary.as_deref(), self.state.compactable_history())
+            .await;
+        match summary {
+            Ok(summary) => self.state.compact(summary),
+            Err(e) => warn!("Failed to summarize history, falling back to truncation: {}", e),
+        }
+    }
+
+    /// The history text for `output` of `tool`: the output itself, or, once it is longer than the
+    /// summary threshold, a summary followed by the id the full output is saved under. Output is
+    /// kept as is in dry runs, or if saving or summarizing fails. Tools that already page or limit
+    /// their own output are never summarized: a summarized `ReadFile` would leave the next
+    /// `EditFile` without the exact text to search for.
+    async fn condense_output(&mut self, tool: &Tool, output: String) -> String {
+        let pages_itself = matches!(tool, Tool::ReadToolOutput { .. } | Tool::ReadFile { .. } | Tool::SearchInFiles { .. } | Tool::ListFiles { .. });
+        if self.summarize_output_bytes == 0 || output.len() <= self.summarize_output_bytes || pages_itself {
+            return output;
+        }
+        let context = match self.tool_context() {
+            Ok(context) if !context.config.dry_run => context,
+            _ => return output,
+        };
+        let id = match tools::save_output(Path::new(&context.resolve(tools::TOOL_OUTPUT_DIR)), &output) {
+            Ok(id) => id,
+            Err(e) => {
+                warn!("Could not save tool output, keeping it in the history: {}", e);
+                return output;
+            }
+        };
+        say!(self, "   {} {} bytes of output (saved as {})...", "🗜️  Summarizing".yellow(), output.len(), id);
+        let summarizer = SummarizerAgent::new(self.summarizer_client.clone(), self.cost_tracker.clone()).with_params(self.params.summarizer);
+        match summarizer.summarize_output(&summarize(&format!("{:?}", tool)), &output).await {
+            Ok(summary) => format!(
+                "{}\n[Full output ({} lines, {} bytes) saved as {}; page through it with ReadToolOutput {{\"id\": \"{}\", \"range\": \"1-200\"}}]",
+                summary,
+                output.lines().count(),
+                output.len(),
+                id,
+                id
+            ),
+            Err(e) => {
+                warn!("Failed to summarize tool output, keeping it in the history: {}", e);
+                output
+            }
+        }
+    }
+
     async fn gather_initial_context(&mut self) -> Result<(), AgentError> {
-        println!("{}", "🔍 Gathering initial context...".yellow());
-        let result = tools::run_tool(Tool::ListFiles { path: ".".to_string() }).await?;
-        let ToolResult::Success(output) = result;
+        say!(self, "{}", "🔍 Gathering initial context...".yellow());
+        self.refresh_pinned_files();
+        let listing = Tool::ListFiles { path: ".".to_string(), max_depth: None, glob: None };
+        let output = tools::run_tool_in(listing, &self.tool_context()?).await?.text();
              self.state.add_history("Initial Directory Listing", &output);
-             println!("   {}", "Found existing file structure.".green());
+             say!(self, "   {}", "Found existing file structure.".green());
+        let context = self.tool_context()?;
+        for path in tools::referenced_images(&self.state.goal, |path| context.resolve(path)) {
+            // Read like `ReadImage`, so the sandbox and the size limit apply to it too.
+            match tools::run_tool_in(Tool::ReadImage { path: path.clone() }, &context).await {
+                Ok(_) => {
+                    say!(self, "   {} {}", "🖼️ Attaching image from the goal:".green(), path);
+                    self.attach_image(path);
+                }
+                Err(e) => warn!("Not attaching image {} from the goal: {}", path, e),
+            }
+        }
         Ok(())
     }
 
+    /// Shows the image at `path` with the following decisions.
+    fn attach_image(&mut self, path: String) {
+        self.state.images.retain(|attached| *attached != path);
+        self.state.images.push(path);
+        if self.state.images.len() > MAX_ATTACHED_IMAGES {
+            self.state.images.remove(0);
+        }
+    }
+
+    /// The attached images that can still be read, with their paths. Unreadable ones are skipped.
+    /// Images unchanged since they were last read come from `image_cache`.
+    fn attached_images(&self) -> (Vec<String>, Vec<ImageAttachment>) {
+        let Ok(context) = self.tool_context() else {
+            return (Vec::new(), Vec::new());
+        };
+        let mut cache = self.image_cache.lock().unwrap();
+        let mut read = HashMap::new();
+        let mut paths = Vec::new();
+        let mut images = Vec::new();
+        for path in &self.state.images {
+            let resolved = PathBuf::from(context.resolve(path));
+            let modified = std::fs::metadata(&resolved).and_then(|metadata| metadata.modified()).ok();
+            let image = match cache.remove(&resolved) {
+                Some((read_at, image)) if read_at.is_some() && read_at == modified => Ok(image),
+                _ => tools::read_image(&resolved),
+            };
+            match image {
+                Ok(image) => {
+                    paths.push(path.clone());
+                    images.push(image.clone());
+                    read.insert(resolved, (modified, image));
+                }
+                Err(e) => warn!("Not showing image {}: {}", path, e),
+            }
+        }
+        // Images no longer attached are dropped from the cache.
+        *cache = read;
+        (paths, images)
+    }
+
+    /// Re-reads the pinned files, so the context shows their current content.
+    fn refresh_pinned_files(&mut self) {
+        if self.state.pinned_files.is_empty() {
+            return;
+        }
+        let context = self.tool_context().ok();
+        for file in &mut self.state.pinned_files {
+            let path = context.as_ref().map_or_else(|| file.path.clone(), |context| context.resolve(&file.path));
+            file.content = match std::fs::read_to_string(&path) {
+                Ok(content) => Some(content),
+                Err(e) => {
+                    warn!("Pinned file {} could not be read: {}", file.path, e);
+                    None
+                }
+            };
+        }
+    }
+
     async fn create_plan(&mut self) -> Result<(), AgentError> {
-        println!("{}", "🤔 Thinking... Creating a plan...".yellow());
-        let planner = PlannerAgent::new(self.reasoning_client.clone(), self.cost_tracker.clone());
-        let plan = planner.create_plan(&self.state.goal, &self.state.get_context()).await?;
+        say!(self, "{}", "🤔 Thinking... Creating a plan...".yellow());
+        let planner = PlannerAgent::new(self.planner_client.clone(), self.cost_tracker.clone()).with_params(self.params.planner).with_system_prompt(self.system_prompts.planner.clone());
+        let plan = planner.create_plan(&self.state.goal, &self.context()).await?;
         self.state.plan = plan;
-        println!("{}", "📝 Plan Created:".bold().green());
+        self.announce_plan();
+        Ok(())
+    }
+
+    fn announce_plan(&self) {
+        self.events.emit(AgentEvent::PlanCreated { steps: self.state.plan.clone() });
+        say!(self, "{}", "📝 Plan Created:".bold().green());
         for (i, step) in self.state.plan.iter().enumerate() {
-            println!("   {}. {}", i + 1, step);
+            say!(self, "   {}. {}", i + 1, step);
         }
-        println!();
+        say!(self);
         info!("Plan created with {} steps.", self.state.plan.len());
+    }
+
+    /// What the plan is expected to cost from step `from` on, with that step's decision prompt as
+    /// the size of every prompt. Each step is priced at its most: a decision, a plan check unless it
+    /// is the last, the relevant-file pick, a code generation with one syntax fix, and every review
+    /// pass with a revision after all but the last.
+    fn estimate_cost(&self, from: usize) -> CostEstimate {
+        let steps = self.state.plan.len().saturating_sub(from);
+        let prompt = match self.state.plan.get(from) {
+            Some(step) => self.tools.decision_prompt(step, &self.context()),
+            None => String::new(),
+        };
+        let prompt_tokens = u32::try_from(self.context.count(&prompt)).unwrap_or(u32::MAX);
+        let reasoning = self.reasoning_client.calculate_cost(prompt_tokens, ESTIMATED_DECISION_TOKENS);
+        let code = self.llm_client.calculate_cost(prompt_tokens, ESTIMATED_CODE_TOKENS);
+        let decision = if self.decision_ensemble.is_empty() {
+            reasoning
+        } else {
+            self.decision_ensemble.iter().map(|client| client.calculate_cost(prompt_tokens, ESTIMATED_DECISION_TOKENS)).sum()
+        };
+        let retrieval = if self.max_relevant_files > 0 { reasoning } else { 0.0 };
+        let reviews = self.max_review_iterations as f64 * reasoning + self.max_review_iterations.saturating_sub(1) as f64 * code;
+        let step = decision + retrieval + 2.0 * code + reviews;
+        let plan_checks = if self.replan { steps.saturating_sub(1) as f64 * reasoning } else { 0.0 };
+        let verification = if self.verify { reasoning } else { 0.0 };
+        CostEstimate { steps, prompt_tokens, cost: step * steps as f64 + plan_checks + verification }
+    }
+
+    /// Shows the estimated cost of the plan from step `from` on and, when it is over the cost
+    /// threshold, stops the run before that step unless the cost approver agrees.
+    async fn confirm_cost(&mut self, from: usize) -> Result<(), AgentError> {
+        let Some(threshold) = self.cost_threshold else {
+            return Ok(());
+        };
+        let estimate = self.estimate_cost(from);
+        info!("Estimated cost of the plan: {}", estimate);
+        say!(self, "{} {}", "💰 Estimated Cost:".bold().yellow(), estimate);
+        if estimate.cost <= threshold {
+            return Ok(());
+        }
+        if let Some(approver) = &self.cost_approver {
+            if !approver.approve_cost(&estimate, threshold).await {
+                return Err(AgentError::CostNotApproved(estimate.cost));
+            }
+        }
         Ok(())
     }
 
     async fn execute_plan(&mut self) -> Result<(), AgentError> {
-        let coder = CoderAgent::new(self.llm_client.clone(), self.cost_tracker.clone());
-        for i in 0..self.state.plan.len() {
+        let coder = CoderAgent::new(self.llm_client.clone(), self.cost_tracker.clone())
+            .with_params(self.params.coder)
+            .with_language(self.state.language)
+            .with_system_prompt(self.system_prompts.coder.clone());
+        // The plan can be revised mid-run, so its length is re-read on every iteration.
+        let mut i = self.state.current_step;
+        while i < self.state.plan.len() {
             self.state.current_step = i;
+            if self.steps_executed >= self.max_steps {
+                return Err(AgentError::MaxStepsExceeded(self.max_steps));
+            }
+            if let Some(limit) = self.max_cost {
+                let spent = self.cost_tracker.get_total_cost();
+                if spent >= limit {
+                    return Err(AgentError::BudgetExceeded { spent, limit });
+                }
+            }
+            self.steps_executed += 1;
             let step = &self.state.plan[i].clone();
-            println!("{}", format!("\n▶️  Executing Step {}: {}", i + 1, step).bold().cyan());
-            
-            let decision = self.decide_action(step, &self.state.get_context()).await?;
-            
-            match decision.tool {
-                Tool::CodeGeneration { task } => {
-                    println!("   {} {}...", "✍️ Writing Code for:".magenta(), task);
-                    let code = coder.generate_code(&task, &self.state.get_context()).await?;
-                    println!("{}", "Generated Code:".bold().green());
-                    println!("{}", code.trim().green());
-                    self.state.add_history("Generated Code", &code);
-
-                    if let Some(path) = decision.file_path {
-                         println!("   {} '{}'...", "💾 Saving code to file".magenta(), path);
-                         match tools::run_tool(Tool::WriteFile { path: path.clone(), content: code }).await {
-                             Ok(_) => println!("   {} Code saved to {}", "✅ Success:".green(), path),
-                             Err(e) => println!("   {} Failed to save code: {}", "❌ Error:".red(), e),
-                         }
+            say!(self, "{}", format!("\n▶️  Executing Step {}: {}", i + 1, step).bold().cyan());
+            say!(self, "   {}", self.metrics.progress(i, self.state.plan.len()).dimmed());
+            self.metrics.start_step(i, step, &self.cost_tracker);
+            self.events.emit(AgentEvent::StepStarted { step_index: i, step: step.clone() });
+            self.compact_history().await;
+            let history_start = self.state.history.len();
+            self.in_flight_step = Some(history_start);
+            self.step_files.clear();
+            self.step_snapshot = self.snapshot_workdir().await;
+            self.refresh_pinned_files();
+
+            let executed = self.execute_step(step, i, &coder).await;
+            // What a failed or cancelled step changed is reported too.
+            self.emit_step_changes(i).await;
+            let action = executed?;
+            // A command killed by cancellation looks like a failed step; do not count it as done.
+            if self.cancel.is_cancelled() {
+                return Err(AgentError::Cancelled);
+            }
+            self.update_memory(i, history_start).await;
+            let outcome = self.state.history.last().map(|(_, content)| content.as_str()).unwrap_or_default();
+            if self.loop_detector.record(&action, outcome) {
+                return Err(AgentError::LoopDetected(format!(
+                    "step {} repeated the decision {} with the same result {} times in a row",
+                    i + 1,
+                    action,
+                    self.loop_detector.repeats
+                )));
+            }
+            if let Some(timing) = self.metrics.finish_step(&self.cost_tracker) {
+                say!(self, "   {}", format!("⏱️  Step {} took {} ({} in LLM calls)", i + 1, format_duration(timing.duration), format_duration(timing.llm_time)).dimmed());
+                self.events.emit(AgentEvent::StepFinished {
+                    step_index: i,
+                    duration_ms: timing.duration.as_millis() as u64,
+                    llm_calls: timing.llm_calls,
+                    llm_ms: timing.llm_time.as_millis() as u64,
+                });
+            }
+            self.state.current_step = i + 1;
+            self.in_flight_step = None;
+            self.ask_for_feedback(step).await;
+            // The revised plan is saved even when its cost is not approved, so a resume picks it up.
+            let revised = if self.replan && i + 1 < self.state.plan.len() { self.revise_plan(i).await } else { Ok(()) };
+            self.checkpoint();
+            revised?;
+            i += 1;
+        }
+        Ok(())
+    }
+
+    /// Decides on and carries out the action for `step`, returning the decision's tool as JSON.
+    async fn execute_step(&mut self, step: &str, step_index: usize, coder: &CoderAgent) -> Result<String, AgentError> {
+        let context = self.decision_context(step).await;
+        let decision = self.decide_action(step, &context).await?;
+        self.events.emit(AgentEvent::DecisionMade { step_index, decision: decision.clone() });
+        let action = serde_json::to_string(&decision.tool)?;
+        match decision.tool {
+            Tool::RunCommand { command, timeout_secs, cwd, env, stdin } => {
+                self.run_command_with_fixes(step, command, timeout_secs, CommandOptions { cwd, env, stdin }, coder, step_index).await?
+            }
+            _ => self.execute_decision(decision, coder, step_index).await?,
+        }
+        Ok(action)
+    }
+
+    /// A snapshot of the working directory, taken only when `StepChanges` are enabled and events
+    /// are emitted, since the changes it finds are only reported as `AgentEvent::StepChanges`.
+    /// Files the last step's snapshot saw unchanged are not read again.
+    async fn snapshot_workdir(&mut self) -> Option<WorkspaceSnapshot> {
+        if !self.step_changes || self.events.is_empty() {
+            return None;
+        }
+        let root = match self.tool_context.as_ref().and_then(|context| context.workdir.clone()) {
+            Some(root) => root,
+            None => std::env::current_dir().ok()?,
+        };
+        let previous = self.last_snapshot.take().filter(|previous| previous.root() == root.as_path());
+        tokio::task::spawn_blocking(move || WorkspaceSnapshot::capture(&root, previous.as_ref())).await.ok()
+    }
+
+    /// Emits what step `step_index` changed in the working directory since it started, including
+    /// the hidden or ignored files it wrote itself. The journal supplies the pre-run text of files
+    /// for their diffs.
+    async fn emit_step_changes(&mut self, step_index: usize) {
+        let Some(before) = self.step_snapshot.take() else { return };
+        let mut texts = std::mem::take(&mut self.changed_texts);
+        let originals: BTreeMap<PathBuf, String> = self.journal.iter().flat_map(|journal| &journal.changes).filter_map(|change| Some((change.path.clone(), change.original.clone()?))).collect();
+        let touched: Vec<PathBuf> = match self.tool_context() {
+            Ok(context) => self.step_files.iter().map(|file| PathBuf::from(context.resolve(file))).collect(),
+            Err(_) => Vec::new(),
+        };
+        let found = tokio::task::spawn_blocking(move || {
+            let mut after = WorkspaceSnapshot::capture(before.root(), Some(&before));
+            for path in &touched {
+                after.include(path);
+            }
+            let original = |path: &str| std::path::absolute(before.root().join(path)).ok().and_then(|path| originals.get(&path).cloned());
+            let changes = after.changes_since(&before, &mut texts, &original);
+            (changes, texts, after)
+        })
+        .await;
+        let Ok((changes, texts, after)) = found else { return };
+        self.changed_texts = texts;
+        self.last_snapshot = Some(after);
+        if !changes.is_empty() {
+            self.events.emit(AgentEvent::StepChanges { step_index, changes });
+        }
+    }
+
+    /// Shows the steering the files `step` changed and records its feedback, if any, so the next
+    /// decision (and plan revision) takes it into account.
+    async fn ask_for_feedback(&mut self, step: &str) {
+        let Some(steering) = self.steering.clone() else {
+            return;
+        };
+        if self.step_files.is_empty() {
+            return;
+        }
+        if let Some(feedback) = steering.feedback(step, &self.step_files).await {
+            say!(self, "   {} {}", "💬 Feedback:".cyan(), feedback);
+            self.state.add_history("User Feedback", &feedback);
+        }
+    }
+
+    /// Asks the reasoning client whether the steps after `step_index` still make sense and, if not,
+    /// has the planner replace them. Failures are logged and the current plan is kept; a revised
+    /// plan's cost is confirmed like the first one's.
+    async fn revise_plan(&mut self, step_index: usize) -> Result<(), AgentError> {
+        let checker = PlannerAgent::new(self.reasoning_client.clone(), self.cost_tracker.clone()).with_params(self.params.reasoner).with_system_prompt(self.system_prompts.planner.clone());
+        let (completed, remaining) = self.state.plan.split_at(step_index + 1);
+        let check = match checker.check_plan(&self.state.goal, completed, remaining, &self.context()).await {
+            Ok(check) => check,
+            Err(e) => {
+                warn!("Plan check after step {} failed, keeping the current plan: {}", step_index + 1, e);
+                return Ok(());
+            }
+        };
+        if check.valid {
+            return Ok(());
+        }
+
+        say!(self, "{} {}", "🔄 Re-planning:".bold().yellow(), check.reason);
+        let planner = PlannerAgent::new(self.planner_client.clone(), self.cost_tracker.clone()).with_params(self.params.planner).with_system_prompt(self.system_prompts.planner.clone());
+        let completed = self.state.plan[..=step_index].to_vec();
+        let steps = match planner.replan(&self.state.goal, &completed, &check.reason, &self.context()).await {
+            Ok(steps) => steps,
+            Err(e) => {
+                warn!("Re-planning after step {} failed, keeping the current plan: {}", step_index + 1, e);
+                return Ok(());
+            }
+        };
+        self.state.plan.truncate(step_index + 1);
+        self.state.plan.extend(steps.iter().cloned());
+        self.state.add_history("Plan Revised", &format!("{}\nNew remaining steps:\n{}", check.reason, steps.join("\n")));
+        self.events.emit(AgentEvent::PlanRevised { step_index, reason: check.reason, steps });
+        say!(self, "{}", "📝 Revised Plan:".bold().green());
+        for (n, step) in self.state.plan.iter().enumerate().skip(step_index + 1) {
+            say!(self, "   {}. {}", n + 1, step);
+        }
+        self.confirm_cost(step_index + 1).await
+    }
+
+    async fn execute_decision(&mut self, decision: Decision, coder: &CoderAgent, step_index: usize) -> Result<(), AgentError> {
+        match decision.tool {
+            Tool::CodeGeneration { task } => {
+                say!(self, "   {} {}...", "✍️ Writing Code for:".magenta(), task);
+                let context = self.coding_context(&task, decision.file_path.as_deref()).await;
+                let code = coder.generate_code(&task, &context).await?;
+                let code = self.fix_syntax(&task, code, decision.file_path.as_deref(), coder).await?;
+                let code = self.review_code(&task, code, coder).await?;
+                say!(self, "{}", "Generated Code:".bold().green());
+                say!(self, "{}", code.trim().green());
+                self.state.add_history("Generated Code", &code);
+                self.events.emit(AgentEvent::CodeGenerated { step_index, task: task.clone(), file_path: decision.file_path.clone(), code: code.clone() });
+
+                let files = coder::split_files(&code);
+                if !files.is_empty() {
+                    for file in files {
+                        self.save_generated_code(step_index, file.path, file.content).await;
                     }
-                },
-                other_tool => {
-                    println!("   {} {:?}...", "🛠️ Using Tool:".magenta(), other_tool);
-                    let result = tools::run_tool(other_tool).await;
-                    match result {
-                        Ok(ToolResult::Success(output)) => {
-                            let summarized = if output.len() > 300 { format!("{}...", &output[..300]) } else { output.clone() };
-                            println!("   {} {}", "✅ Tool Success:".green(), summarized);
-                            self.state.add_history("Tool Output", &output);
-                        },
-                        Err(e) => {
-                             println!("   {} {}", "❌ Tool Error:".red(), e);
-                             warn!("Tool execution failed for step {}: {}", i + 1, e);
-                             self.state.add_history("Tool Error", &e.to_string());
+                } else if let Some(path) = decision.file_path {
+                    self.save_generated_code(step_index, path, code).await;
+                }
+            },
+            Tool::Delegate { subtasks } => self.run_workers(subtasks, step_index).await,
+            other_tool => {
+                say!(self, "   {} {:?}...", "🛠️ Using Tool:".magenta(), other_tool);
+                let result = self.run_tool(other_tool.clone()).await;
+                self.emit_tool_executed(step_index, other_tool.clone(), &result);
+                match result {
+                    Ok(result) if result.is_success() => {
+                        let output = result.text();
+                        say!(self, "   {} {}", "✅ Tool Success:".green(), display_output(&output));
+                        if let Tool::ReadImage { path } = &other_tool {
+                            self.attach_image(path.clone());
                         }
+                        let output = self.condense_output(&other_tool, output).await;
+                        self.state.add_history(&result.history_label(), &output);
+                    },
+                    Ok(result) => {
+                        let output = result.text();
+                        say!(self, "   {} {}", "❌ Tool Failed:".red(), summarize(&output));
+                        let output = self.condense_output(&other_tool, output).await;
+                        self.state.add_history(&result.history_label(), &output);
+                    },
+                    Err(e) => {
+                         say!(self, "   {} {}", "❌ Tool Error:".red(), e);
+                         warn!("Tool execution failed for step {}: {}", step_index + 1, e);
+                         self.state.add_history("Tool Error", &e.to_string());
                     }
                 }
             }
@@ -105,15 +1171,535 @@ impl Orchestrator {
         Ok(())
     }
 
+    /// The coder's context for `task`: the run's context followed by the current content of the
+    /// project files relevant to it, as picked by the reasoning model. `file_path`, the file the
+    /// decision names, is always included when it exists. Pinned files are left out, as they are
+    /// in the context already. Without relevant files, or when picking them fails, the run's
+    /// context alone.
+    async fn coding_context(&self, task: &str, file_path: Option<&str>) -> String {
+        let context = self.context();
+        if self.max_relevant_files == 0 {
+            return context;
+        }
+        let Ok(tool_context) = self.tool_context() else {
+            return context;
+        };
+        let candidates: Vec<String> = tools::project_files(Path::new(&tool_context.resolve(".")))
+            .into_iter()
+            .filter(|path| !self.state.pinned_files.iter().any(|file| file.path == *path))
+            // Dotfiles such as .env may hold secrets; like the snapshot's walker, skip hidden files.
+            .filter(|path| !path.split('/').any(|part| part.starts_with('.')))
+            .collect();
+        if candidates.is_empty() {
+            return context;
+        }
+        let retriever = RetrieverAgent::new(self.reasoning_client.clone(), self.cost_tracker.clone()).with_params(self.params.reasoner);
+        let mut files = match retriever.select_files(&self.state.goal, task, &candidates, self.max_relevant_files).await {
+            Ok(files) => files,
+            Err(e) => {
+                warn!("Selecting the files relevant to the task failed, writing the code without them: {}", e);
+                Vec::new()
+            }
+        };
+        if let Some(path) = file_path.map(|path| path.trim_start_matches("./")).filter(|path| candidates.iter().any(|candidate| candidate == path)) {
+            if !files.iter().any(|file| file == path) {
+                files.insert(0, path.to_string());
+                files.truncate(self.max_relevant_files);
+            }
+        }
+        if files.is_empty() {
+            return context;
+        }
+        say!(self, "   {} {}", "📚 Reading relevant files:".magenta(), files.join(", "));
+        // The files share half the context budget, like pinned files.
+        let file_tokens = (self.context.budget() / 2 / files.len()).max(1);
+        let mut relevant = String::from("\n--- Relevant Files ---\n");
+        for path in files {
+            let read = Tool::ReadFile { path: path.clone(), start_line: None, end_line: None };
+            match tools::run_tool_in(read, &tool_context).await {
+                Ok(result) => relevant.push_str(&format!("[{}]\n{}\n---\n", path, truncate_to_tokens(&result.text(), file_tokens))),
+                Err(e) => warn!("Not showing {} to the coder: {}", path, e),
+            }
+        }
+        relevant.push_str("These are the current contents of existing files the task modifies or builds on.\n--- End Relevant Files ---\n");
+        format!("{}{}", context, relevant)
+    }
+
+    /// Fans `subtasks` out to workers that plan and run them concurrently, then merges what they
+    /// did (history, changed files, journaled originals) back into this run. Workers see only the
+    /// goal and their subtask, and share the remaining step budget equally. A failed worker is
+    /// recorded and stops neither the others nor the run.
+    ///
+    /// Boxed because the workers run the same futures as this orchestrator, recursively.
+    fn run_workers(&mut self, subtasks: Vec<String>, step_index: usize) -> BoxFuture<'_, ()> {
+        async move {
+            let tool = Tool::Delegate { subtasks: subtasks.clone() };
+            let steps_left = self.max_steps.saturating_sub(self.steps_executed);
+            let problem = if subtasks.len() < 2 || subtasks.len() > self.max_workers {
+                Some(format!("Delegate takes 2 to {} subtasks, got {}", self.max_workers, subtasks.len()))
+            } else if steps_left < subtasks.len() {
+                Some(format!("Only {} step(s) are left, too few for {} workers", steps_left, subtasks.len()))
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                let error = AgentError::ToolError(problem);
+                self.emit_tool_failed(step_index, tool, &error);
+                say!(self, "   {} {}", "❌ Tool Error:".red(), error);
+                self.state.add_history("Tool Error", &error.to_string());
+                return;
+            }
+
+            say!(self, "   {} {} subtasks to parallel workers...", "👷 Delegating".magenta(), subtasks.len());
+            let budget = steps_left / subtasks.len();
+            let owners = Arc::new(std::sync::Mutex::new(HashMap::new()));
+            let runs = subtasks.iter().enumerate().map(|(n, subtask)| {
+                let mut worker = self.worker(n + 1, subtask, budget);
+                worker.file_claims = Some(FileClaims { worker: n + 1, owners: owners.clone() });
+                async move {
+                    let result = worker.run_plan().await;
+                    (worker, result)
+                }
+            });
+            let finished = join_all(runs).await;
+
+            let mut outcomes = Vec::new();
+            for (n, (subtask, (worker, result))) in subtasks.iter().zip(finished).enumerate() {
+                let label = format!("Worker {}", n + 1);
+                self.steps_executed += worker.steps_executed;
+                if let Some(summary) = worker.state.summary {
+                    self.state.add_history(&format!("{}: Summary", label), &summary);
+                }
+                for (entry_type, content) in worker.state.history.iter().filter(|(entry_type, _)| entry_type != "Initial Directory Listing") {
+                    self.state.add_history(&format!("{}: {}", label, entry_type), content);
+                }
+                for file in worker.state.files_modified {
+                    if self.memory.is_some() && !self.unindexed_files.contains(&file) {
+                        self.unindexed_files.push(file.clone());
+                    }
+                    if !self.step_files.contains(&file) {
+                        self.step_files.push(file.clone());
+                    }
+                    if !self.state.files_modified.contains(&file) {
+                        self.state.files_modified.push(file);
+                    }
+                }
+                if let (Some(journal), Some(worker_journal)) = (self.journal.as_mut(), worker.journal) {
+                    journal.merge(worker_journal);
+                }
+                let (status, outcome) = match &result {
+                    Ok(()) => ("✅ Worker Done:".green(), format!("{} finished \"{}\" in {} step(s)", label, subtask, worker.steps_executed)),
+                    Err(e) => ("❌ Worker Failed:".red(), format!("{} failed \"{}\": {}", label, subtask, e)),
+                };
+                say!(self, "   {} {}", status, outcome);
+                self.state.add_history(&format!("{} Result", label), &outcome);
+                outcomes.push((result.is_ok(), outcome));
+            }
+            self.save_journal();
+            let success = outcomes.iter().all(|(ok, _)| *ok);
+            let output = outcomes.into_iter().map(|(_, outcome)| outcome).collect::<Vec<_>>().join("\n");
+            self.events.emit(AgentEvent::ToolExecuted { step_index, tool, success, output });
+        }
+        .boxed()
+    }
+
+    /// A worker orchestrator for `subtask`, the `number`th of a delegation, allowed `max_steps`
+    /// steps. It shares this run's clients, cost tracker, settings, tools, approver (one worker
+    /// asking at a time) and cancellation, but cannot delegate further, checkpoints nothing, skips memory and goal
+    /// verification, emits no events and reports through this run's reporter with its number.
+    fn worker(&self, number: usize, subtask: &str, max_steps: usize) -> Orchestrator {
+        let goal = format!("{} (one part of the goal \"{}\"; other workers handle its other parts in parallel)", subtask, self.state.goal);
+        let mut state = AppState::new(goal);
+        state.instructions = self.state.instructions.clone();
+        state.language = self.state.language;
+        state.pinned_files = self.state.pinned_files.clone();
+        let base = Orchestrator::from_state(state, self.llm_client.clone(), self.reasoning_client.clone(), self.cost_tracker.clone());
+        Orchestrator {
+            decision_ensemble: self.decision_ensemble.clone(),
+            planner_client: self.planner_client.clone(),
+            summarizer_client: self.summarizer_client.clone(),
+            params: self.params,
+            system_prompts: self.system_prompts.clone(),
+            max_fix_attempts: self.max_fix_attempts,
+            max_repair_attempts: self.max_repair_attempts,
+            max_review_iterations: self.max_review_iterations,
+            context: self.context,
+            max_history_entries: self.max_history_entries,
+            summarize_output_bytes: self.summarize_output_bytes,
+            native_tool_calls: self.native_tool_calls,
+            replan: self.replan,
+            verify: false,
+            max_relevant_files: self.max_relevant_files,
+            max_steps,
+            // Workers share the tracker, so the budget covers the whole run.
+            max_cost: self.max_cost,
+            loop_detector: LoopDetector::new(self.loop_detector.threshold),
+            reporter: Arc::new(PrefixedReporter::new(format!("[worker {}] ", number), self.reporter.clone())),
+            approver: self.approver.clone(),
+            approval_turn: self.approval_turn.clone(),
+            journal: self.journal.as_ref().map(|journal| Journal::new(&journal.goal)),
+            tools: self.tools.clone().with_delegation(false),
+            tool_context: self.tool_context.clone(),
+            cancel: self.cancel.child_token(),
+            ..base
+        }
+    }
+
+    /// Writes one generated file. Failures are reported and recorded but do not stop the run.
+    async fn save_generated_code(&mut self, step_index: usize, path: String, code: String) {
+        say!(self, "   {} '{}'...", "💾 Saving code to file".magenta(), path);
+        let write = Tool::WriteFile { path: path.clone(), content: code };
+        let result = self.run_tool(write.clone()).await;
+        self.emit_tool_executed(step_index, write, &result);
+        match result {
+            Ok(ToolResult::Success(output)) if output.starts_with(tools::DRY_RUN_PREFIX) => say!(self, "   {}", output.dimmed()),
+            Ok(_) => say!(self, "   {} Code saved to {}", "✅ Success:".green(), path),
+            Err(e) => {
+                say!(self, "   {} Failed to save code: {}", "❌ Error:".red(), e);
+                self.state.add_history("Tool Error", &format!("Failed to save {}: {}", path, e));
+            }
+        }
+    }
+
+    /// Sends generated code that does not parse back to the coder once, with the parse errors as
+    /// feedback. Code that still does not parse is kept and the errors are recorded in the history.
+    async fn fix_syntax(&mut self, task: &str, code: String, file_path: Option<&str>, coder: &CoderAgent) -> Result<String, AgentError> {
+        let errors = coder.syntax_errors(&code, file_path);
+        if errors.is_empty() {
+            return Ok(code);
+        }
+        say!(self, "   {} {}", "⚠️ Syntax Check:".yellow(), errors.join("; "));
+        say!(self, "   {} {}...", "✍️ Revising Code for:".magenta(), task);
+        let code = coder.revise_code(task, &code, &errors, &self.context()).await?;
+        let errors = coder.syntax_errors(&code, file_path);
+        if !errors.is_empty() {
+            warn!("Code for task '{}' still does not parse: {}", task, errors.join("; "));
+            self.state.add_history("Syntax Check", &format!("Generated code still does not parse:\n{}", errors.join("\n")));
+        }
+        Ok(code)
+    }
+
+    /// Has the reviewer critique generated code, sending it back to the coder until it is approved
+    /// or `max_review_iterations` passes are used up. Returns the latest version either way.
+    async fn review_code(&mut self, task: &str, code: String, coder: &CoderAgent) -> Result<String, AgentError> {
+        if self.max_review_iterations == 0 {
+            return Ok(code);
+        }
+        let reviewer = ReviewerAgent::new(self.reasoning_client.clone(), self.cost_tracker.clone()).with_params(self.params.reasoner).with_system_prompt(self.system_prompts.reviewer.clone());
+        let mut code = code;
+        for iteration in 1..=self.max_review_iterations {
+            say!(self, "   {} pass {}/{}...", "🔎 Reviewing Code:".magenta(), iteration, self.max_review_iterations);
+            let review = reviewer.review(task, &code, &self.context()).await?;
+            if review.is_approved() {
+                say!(self, "   {} Code approved by reviewer", "✅ Review:".green());
+                self.state.add_history("Code Review", "Approved");
+                return Ok(code);
+            }
+
+            let feedback = review.reasons.iter().map(|r| format!("- {}", r)).collect::<Vec<_>>().join("\n");
+            say!(self, "   {}\n{}", "📝 Changes Requested:".yellow(), feedback);
+            self.state.add_history("Code Review", &format!("Changes requested:\n{}", feedback));
+            if iteration == self.max_review_iterations {
+                warn!("Code for task '{}' was not approved after {} review passes.", task, iteration);
+                say!(self, "   {} Review passes exhausted, keeping the latest revision.", "⚠️ Review:".yellow());
+                break;
+            }
+            say!(self, "   {} {}...", "✍️ Revising Code for:".magenta(), task);
+            code = coder.revise_code(task, &code, &review.reasons, &self.context()).await?;
+        }
+        Ok(code)
+    }
+
+    /// Runs a command and, while it keeps failing (or timing out), asks the reasoning client for a
+    /// fix and retries.
+    async fn run_command_with_fixes(&mut self, step: &str, command: String, timeout_secs: Option<u64>, options: CommandOptions, coder: &CoderAgent, step_index: usize) -> Result<(), AgentError> {
+        let mut command = command;
+        let mut timeout_secs = timeout_secs;
+        let mut options = options;
+        let mut attempts = 0;
+        loop {
+            say!(self, "   {} {}...", "🛠️ Running Command:".magenta(), command);
+            let result = match self.tool_context() {
+                Ok(context) => tools::run_command_with(&command, timeout_secs, &options, &context).await,
+                Err(e) => Err(e),
+            };
+            let tool = Tool::RunCommand {
+                command: command.clone(),
+                timeout_secs,
+                cwd: options.cwd.clone(),
+                env: options.env.clone(),
+                stdin: options.stdin.clone(),
+            };
+            let output = match result {
+                Ok(output) => {
+                    self.events.emit(AgentEvent::ToolExecuted { step_index, tool: tool.clone(), success: output.success, output: output.to_tool_output() });
+                    output
+                }
+                // A hung command is a failure the fix loop can address, e.g. with a non-interactive flag.
+                Err(e @ AgentError::ToolTimeout { .. }) => {
+                    self.emit_tool_failed(step_index, tool.clone(), &e);
+                    CommandOutput { success: false, exit_code: None, stdout: String::new(), stderr: e.to_string() }
+                }
+                Err(e) => {
+                    self.emit_tool_failed(step_index, tool, &e);
+                    say!(self, "   {} {}", "❌ Tool Error:".red(), e);
+                    warn!("Tool execution failed for step {}: {}", step_index + 1, e);
+                    self.state.add_history("Tool Error", &e.to_string());
+                    return Ok(());
+                }
+            };
+            let text = output.to_tool_output();
+            if output.success {
+                say!(self, "   {} {}", "✅ Tool Success:".green(), display_output(&text));
+                let text = self.condense_output(&tool, text).await;
+                self.state.add_history("Tool Output", &text);
+                return Ok(());
+            }
+
+            say!(self, "   {} {}", "❌ Command Failed:".red(), summarize(&text));
+            let text = self.condense_output(&tool, text).await;
+            let label = match output.exit_code {
+                Some(code) => format!("Command Failed (exit code {})", code),
+                None => "Command Failed".to_string(),
+            };
+            self.state.add_history(&label, &text);
+            if attempts >= self.max_fix_attempts {
+                warn!("Command for step {} still failing after {} fix attempts.", step_index + 1, attempts);
+                say!(self, "   {} {} fix attempt(s) exhausted, moving on.", "⚠️ Giving Up:".yellow(), attempts);
+                return Ok(());
+            }
+            attempts += 1;
+            say!(self, "   {} attempt {}/{}", "🔁 Self-Correcting:".yellow(), attempts, self.max_fix_attempts);
+
+            let prompt = self.tools.fix_prompt(step, &command, &text, &self.context());
+            let fix = self.request_decision(&prompt).await?;
+            self.events.emit(AgentEvent::DecisionMade { step_index, decision: fix.clone() });
+            say!(self, "   {} {}", "💡 Fix:".yellow(), fix.thought);
+            match fix.tool {
+                Tool::RunCommand { command: fixed, timeout_secs: fixed_timeout, cwd, env, stdin } => {
+                    command = fixed;
+                    timeout_secs = fixed_timeout.or(timeout_secs);
+                    options = CommandOptions { cwd, env, stdin }.or(options);
+                }
+                _ => self.execute_decision(fix, coder, step_index).await?,
+            }
+        }
+    }
+
+    fn emit_tool_executed(&self, step_index: usize, tool: Tool, result: &Result<ToolResult, AgentError>) {
+        match result {
+            Ok(result) => {
+                self.events.emit(AgentEvent::ToolExecuted { step_index, tool, success: result.is_success(), output: result.text() });
+            }
+            Err(e) => self.emit_tool_failed(step_index, tool, e),
+        }
+    }
+
+    fn emit_tool_failed(&self, step_index: usize, tool: Tool, error: &AgentError) {
+        self.events.emit(AgentEvent::ToolExecuted { step_index, tool, success: false, output: error.to_string() });
+        self.events.emit(AgentEvent::Error { step_index: Some(step_index), message: error.to_string() });
+    }
+
     async fn decide_action(&self, step: &str, context: &str) -> Result<Decision, AgentError> {
-        let prompt = tools::get_decision_prompt(step, context);
+        let prompt = self.tools.decision_prompt(step, context);
+        self.request_decision(&prompt).await
+    }
+
+    /// The decision for `prompt`: the reasoning client's, or the ensemble's verdict.
+    async fn request_decision(&self, prompt: &str) -> Result<Decision, AgentError> {
+        if self.decision_ensemble.len() < 2 {
+            return self.request_decision_from(&self.reasoning_client, prompt).await;
+        }
+        let ballots = join_all(self.decision_ensemble.iter().map(|client| self.request_decision_from(client, prompt))).await;
+        let mut candidates = Vec::new();
+        let mut first_error = None;
+        for ballot in ballots {
+            match ballot {
+                Ok(decision) => candidates.push(decision),
+                Err(e) => {
+                    warn!("An ensemble member's decision failed, leaving it out of the vote: {}", e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(error) = first_error.filter(|_| candidates.is_empty()) {
+            return Err(error);
+        }
+        let tools: Vec<String> = candidates.iter().map(|decision| decision.tool.name()).collect();
+        if let Some(index) = judge::majority(&candidates) {
+            say!(self, "   {} {} ({})", "🗳️  Ensemble Vote:".yellow(), tools[index], tools.join(", "));
+            return Ok(candidates.swap_remove(index));
+        }
+        say!(self, "   {} {}; asking for a tie-break...", "🗳️  Split Vote:".yellow(), tools.join(", "));
+        let judge = JudgeAgent::new(self.reasoning_client.clone(), self.cost_tracker.clone()).with_params(self.params.reasoner);
+        let index = match judge.pick(prompt, &candidates).await {
+            Ok(index) => index,
+            Err(e) => {
+                warn!("The tie-break failed, using the first decision: {}", e);
+                0
+            }
+        };
+        say!(self, "   {} {}", "⚖️  Tie-Break:".yellow(), tools[index]);
+        Ok(candidates.swap_remove(index))
+    }
+
+    /// The decision of `client` for `prompt`, with invalid answers sent back for repair.
+    async fn request_decision_from(&self, client: &Arc<dyn LLMClient>, prompt: &str) -> Result<Decision, AgentError> {
         info!("Decision prompt:\n{}", prompt);
         
-        let response = self.reasoning_client.generate_json(&prompt).await?;
-        self.cost_tracker.add_cost(response.cost);
+        let started = Instant::now();
+        let (image_paths, images) = self.attached_images();
+        let (response, tool_call) = if !images.is_empty() {
+            // Images travel in a chat message; tool-call requests are text only.
+            let prompt = format!("{}\n\nAttached images, in order: {}", prompt, image_paths.join(", "));
+            (client.generate_json_chat(&[ChatMessage::user(prompt).with_images(images)], &self.params.reasoner).await?, None)
+        } else if self.native_tool_calls {
+            client.generate_with_tools(prompt, &self.tools.definitions(), &self.params.reasoner).await?
+        } else {
+            (client.generate_json(prompt, &self.params.reasoner).await?, None)
+        };
+        self.cost_tracker.record_call("decision", &response, started.elapsed());
         info!("Decision response:\n{}", response.content);
-        
-        serde_json::from_str(&response.content)
-            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse tool decision: {}. Response: {}", e, response.content)))
+
+        // Native tool calls are preferred; providers without them answer with JSON text. Either is
+        // checked against the chosen tool's schema. Malformed JSON is repaired locally if possible,
+        // otherwise the model is asked to correct the decision.
+        let (mut content, mut decision) = match tool_call {
+            Some(call) => {
+                info!("Decision tool call: {} {}", call.name, call.arguments);
+                let decision = self.parse_tool_call(&call, &response.content);
+                // Shown as a text decision if the model has to correct it.
+                let mut text = serde_json::json!({ "thought": response.content.trim(), "tool_name": call.name, "parameters": call.arguments });
+                for field in ["thought", "file_path"] {
+                    if let Some(value) = text["parameters"].as_object_mut().and_then(|parameters| parameters.remove(field)) {
+                        text[field] = value;
+                    }
+                }
+                (text.to_string(), decision)
+            }
+            None => {
+                let decision = self.parse_decision(&response.content);
+                (response.content, decision)
+            }
+        };
+        let mut attempts = 0;
+        while let Err(error) = &decision {
+            if attempts >= self.max_repair_attempts {
+                break;
+            }
+            attempts += 1;
+            warn!("Invalid decision ({}); asking for a corrected one.", error);
+            say!(self, "   {} attempt {}/{}", "🩹 Repairing Decision:".yellow(), attempts, self.max_repair_attempts);
+            let started = Instant::now();
+            let response = client.generate_json(&tools::repair_prompt(prompt, &content, &error.to_string()), &self.params.reasoner).await?;
+            self.cost_tracker.record_call("decision", &response, started.elapsed());
+            info!("Corrected decision response:\n{}", response.content);
+            content = response.content;
+            decision = self.parse_decision(&content);
+        }
+        decision.map_err(|e| AgentError::ResponseParseError(format!("Failed to parse tool decision: {}. Response: {}", e, content)))
+    }
+
+    /// A decision from its JSON text, leniently parsed and checked against the registered tools
+    /// and their schemas.
+    fn parse_decision(&self, content: &str) -> Result<Decision, String> {
+        let value: serde_json::Value = json_repair::parse_lenient(content).map_err(|e| e.to_string())?;
+        self.tools.validate_decision(&value).map_err(|e| e.to_string())?;
+        let decision = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        self.tools.check(&decision).map_err(|e| e.to_string())?;
+        Ok(decision)
+    }
+
+    /// A decision from a native tool call, checked like `parse_decision`. `text` is the model's
+    /// reply around the call, used as the thought when the call has none.
+    fn parse_tool_call(&self, call: &ToolCall, text: &str) -> Result<Decision, String> {
+        self.tools.validate_call(call).map_err(|e| e.to_string())?;
+        let decision = Decision::from_tool_call(call, text).map_err(|e| e.to_string())?;
+        self.tools.check(&decision).map_err(|e| e.to_string())?;
+        Ok(decision)
+    }
+}
+
+/// Notices when consecutive decisions repeat the same action with the same outcome, i.e. the agent
+/// is going round in circles without changing anything.
+struct LoopDetector {
+    threshold: u32,
+    last: Option<u64>,
+    repeats: u32,
+}
+
+impl LoopDetector {
+    fn new(threshold: u32) -> Self {
+        Self { threshold, last: None, repeats: 0 }
+    }
+
+    /// Records one executed decision and returns whether the loop threshold has been reached.
+    fn record(&mut self, action: &str, outcome: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        (action, outcome).hash(&mut hasher);
+        let fingerprint = hasher.finish();
+        self.repeats = if self.last == Some(fingerprint) { self.repeats + 1 } else { 1 };
+        self.last = Some(fingerprint);
+        self.threshold > 0 && self.repeats >= self.threshold
+    }
+}
+
+/// Which worker of a delegation changes which file, so parallel workers never write the same one.
+#[derive(Clone)]
+struct FileClaims {
+    worker: usize,
+    owners: Arc<std::sync::Mutex<HashMap<PathBuf, usize>>>,
+}
+
+impl FileClaims {
+    /// Claims `path` for this worker, unless another worker of the delegation already has.
+    fn claim(&self, path: &Path) -> Result<(), AgentError> {
+        let path = std::path::absolute(path)?;
+        let mut owners = self.owners.lock().unwrap();
+        match *owners.entry(path.clone()).or_insert(self.worker) {
+            owner if owner == self.worker => Ok(()),
+            owner => Err(AgentError::ToolError(format!(
+                "{} is being changed by worker {} in parallel; leave it to that worker and change only your own files",
+                path.display(),
+                owner
+            ))),
+        }
+    }
+}
+
+/// Colors a unified diff for the terminal: additions green, removals red, hunk headers cyan.
+pub fn colorize_diff(diff: &str) -> String {
+    diff.lines()
+        .map(|line| {
+            if line.starts_with("+++") || line.starts_with("---") {
+                line.bold().to_string()
+            } else if line.starts_with('+') {
+                line.green().to_string()
+            } else if line.starts_with('-') {
+                line.red().to_string()
+            } else if line.starts_with("@@") {
+                line.cyan().to_string()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Dry-run previews (diffs, would-run commands) are shown in full; real tool output is summarized.
+fn display_output(output: &str) -> String {
+    if output.starts_with(tools::DRY_RUN_PREFIX) {
+        output.to_string()
+    } else {
+        summarize(output)
+    }
+}
+
+fn summarize(output: &str) -> String {
+    if output.chars().count() > 300 {
+        format!("{}...", output.chars().take(300).collect::<String>())
+    } else {
+        output.to_string()
     }
 }
@@ -0,0 +1,159 @@
+use regex::Regex;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::error::AgentError;
+
+/// Renders `steps` as an editable plan file: a commented header, then one numbered step per line.
+pub fn render(goal: &str, steps: &[String]) -> String {
+    let mut content = format!(
+        "# Plan for: {}\n# One step per line, executed in order. Lines starting with '#' are ignored.\n# Delete every step to cancel the run.\n\n",
+        goal.lines().next().unwrap_or_default()
+    );
+    for (i, step) in steps.iter().enumerate() {
+        content.push_str(&format!("{}. {}\n", i + 1, step));
+    }
+    content
+}
+
+/// Reads the steps from plan file content. Blank lines and `#` comments are skipped, and leading
+/// numbering or bullets (`1.`, `2)`, `-`, `*`) are removed.
+pub fn parse(content: &str) -> Vec<String> {
+    static MARKER: OnceLock<Regex> = OnceLock::new();
+    let marker = MARKER.get_or_init(|| Regex::new(r"^(?:\d+[.)]|[-*])(?:\s+|$)").expect("valid step marker regex"));
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| marker.replace(line, "").trim().to_string())
+        .filter(|step| !step.is_empty())
+        .collect()
+}
+
+pub fn write(path: &Path, goal: &str, steps: &[String]) -> Result<(), AgentError> {
+    std::fs::write(path, render(goal, steps))?;
+    Ok(())
+}
+
+/// Reads a plan file, failing if it contains no steps.
+pub fn read(path: &Path) -> Result<Vec<String>, AgentError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| AgentError::ConfigError(format!("Failed to read plan file {}: {}", path.display(), e)))?;
+    let steps = parse(&content);
+    if steps.is_empty() {
+        return Err(AgentError::ConfigError(format!("Plan file {} contains no steps", path.display())));
+    }
+    Ok(steps)
+}
+
+/// Which steps of a plan file to run, by their numbers in the file (1-based).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StepSelection {
+    /// Runs the plan from this step on, e.g. to rerun the steps after a failure.
+    pub from: Option<usize>,
+    /// Runs this step alone.
+    pub only: Option<usize>,
+    /// Leaves these steps out.
+    pub skip: Vec<usize>,
+}
+
+impl StepSelection {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The selected steps, in plan order. Fails for step numbers outside the plan and for a
+    /// selection that leaves nothing to run.
+    pub fn apply(&self, steps: &[String]) -> Result<Vec<String>, AgentError> {
+        for number in self.from.iter().chain(&self.only).chain(&self.skip) {
+            if *number == 0 || *number > steps.len() {
+                return Err(AgentError::ConfigError(format!("The plan has steps 1 to {}; there is no step {}", steps.len(), number)));
+            }
+        }
+        let selected: Vec<String> = steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| (i + 1, step))
+            .filter(|(number, _)| self.only.is_none_or(|only| *number == only))
+            .filter(|(number, _)| self.from.is_none_or(|from| *number >= from))
+            .filter(|(number, _)| !self.skip.contains(number))
+            .map(|(_, step)| step.clone())
+            .collect();
+        if selected.is_empty() {
+            return Err(AgentError::ConfigError("The step selection leaves no steps to run".to_string()));
+        }
+        Ok(selected)
+    }
+}
+
+/// Opens the plan in the user's editor (`$VISUAL`, then `$EDITOR`, then a platform default) and
+/// returns the steps as saved. An empty plan means the user cancelled.
+pub fn edit(goal: &str, steps: &[String]) -> Result<Vec<String>, AgentError> {
+    let path = std::env::temp_dir().join(format!("agent-plan-{}.md", std::process::id()));
+    write(&path, goal, steps)?;
+    let result = open_in_editor(&path).and_then(|_| read(&path));
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+fn open_in_editor(path: &Path) -> Result<(), AgentError> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad".to_string() } else { "vi".to_string() });
+    // Editors are often configured with arguments, e.g. `code --wait`.
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or_else(|| AgentError::ConfigError("EDITOR is empty".to_string()))?;
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .map_err(|e| AgentError::ConfigError(format!("Failed to start editor '{}': {}", editor, e)))?;
+    if !status.success() {
+        return Err(AgentError::ConfigError(format!("Editor '{}' exited with {}", editor, status)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_and_parse_round_trip() {
+        let steps = vec!["Read src/main.rs".to_string(), "Add the --verbose flag".to_string()];
+        let content = render("Add a --verbose flag", &steps);
+        assert!(content.starts_with("# Plan for: Add a --verbose flag\n"));
+        assert_eq!(parse(&content), steps);
+    }
+
+    #[test]
+    fn test_parse_accepts_hand_edited_lists() {
+        let content = "# comment\n1) First\n\n- Second\n* Third\nFourth. With a period\n3. \n";
+        assert_eq!(parse(content), vec!["First", "Second", "Third", "Fourth. With a period"]);
+    }
+
+    #[test]
+    fn test_read_rejects_empty_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.md");
+        std::fs::write(&path, render("Goal", &[])).unwrap();
+        assert!(matches!(read(&path), Err(AgentError::ConfigError(msg)) if msg.contains("no steps")));
+    }
+
+    #[test]
+    fn test_step_selection() {
+        let steps: Vec<String> = (1..=5).map(|n| format!("Step {}", n)).collect();
+        let from = StepSelection { from: Some(3), skip: vec![4], ..StepSelection::default() };
+        assert_eq!(from.apply(&steps).unwrap(), vec!["Step 3", "Step 5"]);
+        let only = StepSelection { only: Some(2), ..StepSelection::default() };
+        assert_eq!(only.apply(&steps).unwrap(), vec!["Step 2"]);
+        assert!(StepSelection::default().is_empty());
+        assert_eq!(StepSelection::default().apply(&steps).unwrap(), steps);
+
+        let out_of_range = StepSelection { from: Some(6), ..StepSelection::default() };
+        assert!(matches!(out_of_range.apply(&steps), Err(AgentError::ConfigError(msg)) if msg.contains("no step 6")));
+        let nothing_left = StepSelection { from: Some(5), skip: vec![5], ..StepSelection::default() };
+        assert!(nothing_left.apply(&steps).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+use crate::error::AgentError;
+
+/// Files checked for per-project instructions, in order of preference.
+pub const INSTRUCTION_FILES: [&str; 3] = ["AGENTS.md", ".agentrc", "CLAUDE.md"];
+
+/// Per-repository conventions ("use pnpm", "never touch migrations/") that every agent prompt
+/// should respect, read from the first instructions file found in the working directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectInstructions {
+    /// File name the instructions were read from, e.g. `AGENTS.md`.
+    pub source: String,
+    pub content: String,
+}
+
+impl ProjectInstructions {
+    /// Reads the first non-empty file of `INSTRUCTION_FILES` in `dir`, if any.
+    pub fn discover(dir: &Path) -> Result<Option<Self>, AgentError> {
+        for name in INSTRUCTION_FILES {
+            let path = dir.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            let content = std::fs::read_to_string(&path)?;
+            if !content.trim().is_empty() {
+                return Ok(Some(Self { source: name.to_string(), content: content.trim().to_string() }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The `AGENT_INSTRUCTIONS` setting followed by the `discovered` instructions file, if any.
+    pub fn with_configured(configured: &str, discovered: Option<Self>) -> Self {
+        match discovered {
+            Some(file) => Self { source: format!("AGENT_INSTRUCTIONS and {}", file.source), content: format!("{}\n\n{}", configured.trim(), file.content) },
+            None => Self { source: "AGENT_INSTRUCTIONS".to_string(), content: configured.trim().to_string() },
+        }
+    }
+}
+
+/// Main language of the project in the working directory, used to steer the coder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProjectLanguage {
+    Rust,
+    TypeScript,
+    JavaScript,
+    Python,
+    Go,
+}
+
+/// Manifest files that identify a project's language, in order of preference.
+const MANIFESTS: [(&str, ProjectLanguage); 8] = [
+    ("Cargo.toml", ProjectLanguage::Rust),
+    ("tsconfig.json", ProjectLanguage::TypeScript),
+    ("package.json", ProjectLanguage::JavaScript),
+    ("pyproject.toml", ProjectLanguage::Python),
+    ("setup.py", ProjectLanguage::Python),
+    ("requirements.txt", ProjectLanguage::Python),
+    ("Pipfile", ProjectLanguage::Python),
+    ("go.mod", ProjectLanguage::Go),
+];
+
+impl ProjectLanguage {
+    /// Detects the language from the first manifest file found in `dir`.
+    pub fn detect(dir: &Path) -> Option<Self> {
+        MANIFESTS.iter().find(|(name, _)| dir.join(name).is_file()).map(|(_, language)| *language)
+    }
+}
+
+impl fmt::Display for ProjectLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ProjectLanguage::Rust => "Rust",
+            ProjectLanguage::TypeScript => "TypeScript",
+            ProjectLanguage::JavaScript => "JavaScript",
+            ProjectLanguage::Python => "Python",
+            ProjectLanguage::Go => "Go",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_prefers_agents_md() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("CLAUDE.md"), "Use npm").unwrap();
+        std::fs::write(dir.path().join("AGENTS.md"), "Use pnpm\n").unwrap();
+
+        let instructions = ProjectInstructions::discover(dir.path()).unwrap().unwrap();
+        assert_eq!(instructions.source, "AGENTS.md");
+        assert_eq!(instructions.content, "Use pnpm");
+    }
+
+    #[test]
+    fn test_discover_skips_empty_files() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(ProjectInstructions::discover(dir.path()).unwrap(), None);
+
+        std::fs::write(dir.path().join("AGENTS.md"), "  \n").unwrap();
+        std::fs::write(dir.path().join(".agentrc"), "Never touch migrations/").unwrap();
+        let instructions = ProjectInstructions::discover(dir.path()).unwrap().unwrap();
+        assert_eq!(instructions.source, ".agentrc");
+    }
+
+    #[test]
+    fn test_configured_instructions_come_before_the_file() {
+        let file = ProjectInstructions { source: "AGENTS.md".to_string(), content: "Use pnpm".to_string() };
+        let merged = ProjectInstructions::with_configured("Answer tersely.\n", Some(file));
+        assert_eq!(merged.source, "AGENT_INSTRUCTIONS and AGENTS.md");
+        assert_eq!(merged.content, "Answer tersely.\n\nUse pnpm");
+        assert_eq!(ProjectInstructions::with_configured("Answer tersely.", None).source, "AGENT_INSTRUCTIONS");
+    }
+
+    #[test]
+    fn test_detect_language_from_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(ProjectLanguage::detect(dir.path()), None);
+
+        std::fs::write(dir.path().join("package.json"), "{}").unwrap();
+        assert_eq!(ProjectLanguage::detect(dir.path()), Some(ProjectLanguage::JavaScript));
+        std::fs::write(dir.path().join("tsconfig.json"), "{}").unwrap();
+        assert_eq!(ProjectLanguage::detect(dir.path()), Some(ProjectLanguage::TypeScript));
+        std::fs::write(dir.path().join("Cargo.toml"), "[package]").unwrap();
+        assert_eq!(ProjectLanguage::detect(dir.path()), Some(ProjectLanguage::Rust));
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::process::Command;
+
+use crate::error::AgentError;
+use crate::state::AppState;
+
+/// Branch pull requests target when neither `AGENT_PR_BASE` nor the remote's default branch is known.
+pub const DEFAULT_PR_BASE: &str = "main";
+/// Characters of `git diff --stat` output included in the description prompt.
+const MAX_DIFF_STAT_CHARS: usize = 4000;
+/// How long a request to the GitHub or GitLab API may take.
+pub const API_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The hosting service a remote belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Forge {
+    GitHub,
+    GitLab,
+}
+
+impl Forge {
+    /// The variable its API token is read from.
+    pub fn token_variable(&self) -> &'static str {
+        match self {
+            Forge::GitHub => "GITHUB_TOKEN",
+            Forge::GitLab => "GITLAB_TOKEN",
+        }
+    }
+}
+
+impl fmt::Display for Forge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Forge::GitHub => write!(f, "GitHub"),
+            Forge::GitLab => write!(f, "GitLab"),
+        }
+    }
+}
+
+/// A repository on GitHub or GitLab, as named by a git remote URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remote {
+    pub forge: Forge,
+    pub host: String,
+    /// `owner/repo` on GitHub; `group/subgroup/project` on GitLab.
+    pub path: String,
+}
+
+impl Remote {
+    /// Parses `https://github.com/owner/repo.git`, `git@github.com:owner/repo.git` or
+    /// `ssh://git@gitlab.example.com/group/project`. Hosts with "gitlab" in their name are taken for
+    /// GitLab, every other host for GitHub (or GitHub Enterprise).
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = url.trim();
+        let (host, path) = match url.split_once("://") {
+            Some((_, rest)) => rest.split_once('/')?,
+            None => url.split_once(':')?,
+        };
+        // Drop the user (`git@`) and port of the host.
+        let host = host.rsplit('@').next()?.split(':').next()?.to_lowercase();
+        let path = path.trim_matches('/').trim_end_matches(".git").to_string();
+        if host.is_empty() || path.split('/').filter(|part| !part.is_empty()).count() < 2 {
+            return None;
+        }
+        let forge = if host.contains("gitlab") { Forge::GitLab } else { Forge::GitHub };
+        Some(Self { forge, host, path })
+    }
+
+    /// The REST API of the remote's host.
+    pub fn api_url(&self) -> String {
+        match (self.forge, self.host.as_str()) {
+            (Forge::GitHub, "github.com") => "https://api.github.com".to_string(),
+            (Forge::GitHub, host) => format!("https://{}/api/v3", host),
+            (Forge::GitLab, host) => format!("https://{}/api/v4", host),
+        }
+    }
+}
+
+/// The title and description of a pull request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PullRequest {
+    pub title: String,
+    pub body: String,
+}
+
+impl PullRequest {
+    /// Reads the model's answer to `description_prompt`: the first non-empty line is the title
+    /// (without Markdown heading marks or a `Title:` label), the rest the description.
+    pub fn parse(text: &str) -> Result<Self, AgentError> {
+        let text = text.trim().trim_start_matches("```markdown").trim_start_matches("```").trim_end_matches("```").trim();
+        let (title, body) = text.split_once('\n').unwrap_or((text, ""));
+        let title = title.trim().trim_start_matches('#').trim();
+        let title = title.strip_prefix("Title:").unwrap_or(title).trim().trim_matches('"');
+        if title.is_empty() {
+            return Err(AgentError::ResponseParseError("The pull request description has no title".to_string()));
+        }
+        let body = body.trim();
+        let body = body.strip_prefix("Description:").unwrap_or(body).trim();
+        Ok(Self { title: title.to_string(), body: body.to_string() })
+    }
+}
+
+/// The prompt asking for a pull request title and description for a run that achieved `state.goal`,
+/// from its plan, the files it changed and `diff_stat`.
+pub fn description_prompt(state: &AppState, diff_stat: &str) -> String {
+    let steps: Vec<String> = state.plan.iter().enumerate().map(|(i, step)| format!("{}. {}", i + 1, step)).collect();
+    let files = if state.files_modified.is_empty() {
+        "(none recorded)".to_string()
+    } else {
+        state.files_modified.join("\n")
+    };
+    let diff_stat = match diff_stat.char_indices().nth(MAX_DIFF_STAT_CHARS) {
+        Some((cut, _)) => format!("{}\n[...]", &diff_stat[..cut]),
+        None => diff_stat.to_string(),
+    };
+    format!(
+        "Write the title and description of a pull request for the changes below.\n\n\
+         Goal: {}\n\nPlan that was carried out:\n{}\n\nFiles changed:\n{}\n\nDiff summary:\n{}\n\n\
+         Answer with the title on the first line, in the imperative mood and at most 72 characters, \
+         then a blank line and a short Markdown description: what changed and why, then how it was \
+         verified. Do not wrap the answer in a code block.",
+        state.goal,
+        steps.join("\n"),
+        files,
+        diff_stat.trim()
+    )
+}
+
+/// Opens pull requests on a GitHub or GitLab repository.
+pub struct PullRequestClient {
+    client: reqwest::Client,
+    remote: Remote,
+    token: String,
+    api_url: String,
+}
+
+impl PullRequestClient {
+    pub fn new(remote: Remote, token: &str) -> Self {
+        let api_url = remote.api_url();
+        Self { client: reqwest::Client::new(), remote, token: token.to_string(), api_url }
+    }
+
+    /// Sends API requests to `api_url` instead of the remote host's API, e.g. a test server.
+    pub fn with_api_url(mut self, api_url: &str) -> Self {
+        self.api_url = api_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sends API requests through `client`, e.g. the shared client with the configured proxy.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Opens a pull request from `head` into `base` and returns its web URL.
+    pub async fn open(&self, head: &str, base: &str, pull_request: &PullRequest) -> Result<String, AgentError> {
+        let request = match self.remote.forge {
+            Forge::GitHub => self
+                .client
+                .post(format!("{}/repos/{}/pulls", self.api_url, self.remote.path))
+                .bearer_auth(&self.token)
+                .header("Accept", "application/vnd.github+json")
+                .header("User-Agent", concat!("rust-cli-agent/", env!("CARGO_PKG_VERSION")))
+                .json(&json!({ "title": pull_request.title, "body": pull_request.body, "head": head, "base": base })),
+            Forge::GitLab => self
+                .client
+                .post(format!("{}/projects/{}/merge_requests", self.api_url, self.remote.path.replace('/', "%2F")))
+                .header("PRIVATE-TOKEN", &self.token)
+                .json(&json!({ "title": pull_request.title, "description": pull_request.body, "source_branch": head, "target_branch": base })),
+        };
+        let response = request.timeout(API_TIMEOUT).send().await?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or_default();
+        if !status.is_success() {
+            let message = body.get("message").map(Value::to_string).unwrap_or_else(|| body.to_string());
+            return Err(AgentError::ToolError(format!("{} refused to open the pull request ({}): {}", self.remote.forge, status, message)));
+        }
+        let url = match self.remote.forge {
+            Forge::GitHub => &body["html_url"],
+            Forge::GitLab => &body["web_url"],
+        };
+        url.as_str().map(str::to_string).ok_or_else(|| AgentError::ToolError(format!("{} did not return the pull request's URL", self.remote.forge)))
+    }
+}
+
+/// Runs `git` with `args` in the current directory and returns its trimmed standard output.
+pub async fn git(args: &[&str]) -> Result<String, AgentError> {
+    let output = Command::new("git").args(args).output().await?;
+    if !output.status.success() {
+        return Err(AgentError::ToolError(format!("`git {}` failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Runs `git` with `args` limited to the pathspecs `paths` (the whole tree when empty).
+pub async fn git_on(args: &[&str], paths: &[String]) -> Result<String, AgentError> {
+    let mut args = args.to_vec();
+    args.push("--");
+    args.extend(paths.iter().map(String::as_str));
+    git(&args).await
+}
+
+/// The files among `paths` (every file when empty) with staged changes, relative to the current directory.
+pub async fn staged_files(paths: &[String]) -> Result<Vec<String>, AgentError> {
+    Ok(git_on(&["diff", "--cached", "--name-only", "--relative"], paths).await?.lines().map(str::to_string).collect())
+}
+
+/// The files among `paths` that differ from the index, deleted and new files included but ignored
+/// ones left out, relative to the current directory.
+pub async fn changed_files(paths: &[String]) -> Result<Vec<String>, AgentError> {
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut files: Vec<String> = git_on(&["ls-files", "--modified", "--deleted", "--others", "--exclude-standard"], paths).await?.lines().map(str::to_string).collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// The branch pull requests target: `configured` (`AGENT_PR_BASE`), else the default branch of
+/// `origin`, else `DEFAULT_PR_BASE`.
+pub async fn base_branch(configured: Option<&str>) -> String {
+    if let Some(base) = configured {
+        return base.to_string();
+    }
+    match git(&["symbolic-ref", "--short", "refs/remotes/origin/HEAD"]).await {
+        Ok(head) => head.trim_start_matches("origin/").to_string(),
+        Err(_) => DEFAULT_PR_BASE.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_remote_parses_https_and_ssh_urls() {
+        let remote = Remote::parse("git@github.com:ohboyftw/rust-cli-agent.git").unwrap();
+        assert_eq!((remote.forge, remote.host.as_str(), remote.path.as_str()), (Forge::GitHub, "github.com", "ohboyftw/rust-cli-agent"));
+        assert_eq!(remote.api_url(), "https://api.github.com");
+        assert_eq!(Remote::parse("https://github.com/ohboyftw/rust-cli-agent").unwrap(), remote);
+
+        let remote = Remote::parse("ssh://git@gitlab.example.com:2222/platform/tools/agent.git").unwrap();
+        assert_eq!((remote.forge, remote.path.as_str()), (Forge::GitLab, "platform/tools/agent"));
+        assert_eq!(remote.api_url(), "https://gitlab.example.com/api/v4");
+        assert_eq!(Remote::parse("https://git.corp.example/team/app.git").unwrap().api_url(), "https://git.corp.example/api/v3");
+        assert!(Remote::parse("/srv/git/app.git").is_none());
+    }
+
+    #[test]
+    fn test_pull_request_parse_splits_title_and_body() {
+        let pull_request = PullRequest::parse("# Title: Add a users repository\n\nAdds `UserRepository`.\n\nTested with `cargo test`.").unwrap();
+        assert_eq!(pull_request.title, "Add a users repository");
+        assert_eq!(pull_request.body, "Adds `UserRepository`.\n\nTested with `cargo test`.");
+        assert_eq!(PullRequest::parse("Fix the build").unwrap().body, "");
+        assert!(PullRequest::parse("  \n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_creates_github_pull_requests_and_gitlab_merge_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/owner/app/pulls"))
+            .and(header("authorization", "Bearer gh-token"))
+            .and(body_partial_json(json!({ "title": "Add users", "head": "agent/users", "base": "main" })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "html_url": "https://github.com/owner/app/pull/7" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/projects/group%2Fapp/merge_requests"))
+            .and(header("private-token", "gl-token"))
+            .and(body_partial_json(json!({ "source_branch": "agent/users", "target_branch": "develop" })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "web_url": "https://gitlab.com/group/app/-/merge_requests/3" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let pull_request = PullRequest { title: "Add users".to_string(), body: "Adds users.".to_string() };
+
+        let github = PullRequestClient::new(Remote::parse("git@github.com:owner/app.git").unwrap(), "gh-token").with_api_url(&server.uri());
+        assert_eq!(github.open("agent/users", "main", &pull_request).await.unwrap(), "https://github.com/owner/app/pull/7");
+        let gitlab = PullRequestClient::new(Remote::parse("https://gitlab.com/group/app.git").unwrap(), "gl-token").with_api_url(&server.uri());
+        assert_eq!(gitlab.open("agent/users", "develop", &pull_request).await.unwrap(), "https://gitlab.com/group/app/-/merge_requests/3");
+
+        let unknown = PullRequestClient::new(Remote::parse("git@github.com:owner/missing.git").unwrap(), "gh-token").with_api_url(&server.uri());
+        assert!(unknown.open("agent/users", "main", &pull_request).await.is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::error::AgentError;
+use crate::state::goal_headline;
+use crate::tools::write_atomic;
+
+/// Where `enqueue` adds tasks and `worker` takes them from.
+pub const QUEUE_FILE: &str = ".agent/queue.json";
+/// Where `worker` writes each task's transcript, as `task-<id>.jsonl`.
+pub const TRANSCRIPTS_DIR: &str = ".agent/transcripts";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// A goal waiting in the queue, or one the worker has run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedTask {
+    pub id: u64,
+    pub goal: String,
+    /// Dollars the task may spend; the worker's `--budget` (or `AGENT_MAX_COST`) when unset.
+    #[serde(default)]
+    pub budget: Option<f64>,
+    pub status: TaskStatus,
+    pub enqueued_at: DateTime<Utc>,
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// What the run cost, once finished.
+    #[serde(default)]
+    pub cost: Option<f64>,
+    /// Id of the run's session, for `sessions show`.
+    #[serde(default)]
+    pub session: Option<String>,
+    #[serde(default)]
+    pub transcript: Option<PathBuf>,
+    /// Process id of the worker running the task, so another worker leaves it alone while that
+    /// process lives.
+    #[serde(default)]
+    pub owner: Option<u32>,
+}
+
+/// The tasks of `QUEUE_FILE`, in the order they were enqueued.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaskQueue {
+    pub tasks: Vec<QueuedTask>,
+}
+
+impl TaskQueue {
+    /// Reads the queue; a missing file is an empty queue.
+    pub fn load(path: &Path) -> Result<Self, AgentError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the queue atomically, so a crash mid-write leaves the previous queue intact.
+    pub fn save(&self, path: &Path) -> Result<(), AgentError> {
+        write_atomic(path, &serde_json::to_string_pretty(self)?)
+    }
+
+    /// Loads the queue at `path`, lets `change` modify it and saves it, holding a lock on
+    /// `<path>.lock` throughout so that `enqueue` and concurrent workers never lose each other's
+    /// changes. The lock is the operating system's, so a crashed process never leaves it held.
+    pub fn update<R>(path: &Path, change: impl FnOnce(&mut TaskQueue) -> R) -> Result<R, AgentError> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let lock = std::fs::File::options().create(true).truncate(false).write(true).open(PathBuf::from(lock_path))?;
+        lock.lock()?;
+        let mut queue = Self::load(path)?;
+        let result = change(&mut queue);
+        queue.save(path)?;
+        Ok(result)
+    }
+
+    /// Appends a pending task for `goal` and returns it.
+    pub fn enqueue(&mut self, goal: String, budget: Option<f64>) -> &QueuedTask {
+        let id = self.tasks.iter().map(|task| task.id).max().unwrap_or(0) + 1;
+        self.tasks.push(QueuedTask { id, goal, budget, status: TaskStatus::Pending, enqueued_at: Utc::now(), finished_at: None, cost: None, session: None, transcript: None, owner: None });
+        self.tasks.last().unwrap()
+    }
+
+    /// Marks the oldest pending task running by the worker process `owner` and returns it.
+    pub fn start_next(&mut self, owner: u32) -> Option<QueuedTask> {
+        let task = self.tasks.iter_mut().find(|task| task.status == TaskStatus::Pending)?;
+        task.status = TaskStatus::Running;
+        task.owner = Some(owner);
+        Some(task.clone())
+    }
+
+    /// Records how the task with `id` ended.
+    pub fn finish(&mut self, id: u64, succeeded: bool, cost: f64, session: &str, transcript: &Path) {
+        if let Some(task) = self.tasks.iter_mut().find(|task| task.id == id) {
+            task.status = if succeeded { TaskStatus::Succeeded } else { TaskStatus::Failed };
+            task.finished_at = Some(Utc::now());
+            task.cost = Some(cost);
+            task.session = Some(session.to_string());
+            task.transcript = Some(transcript.to_path_buf());
+        }
+    }
+
+    /// Puts the running task with `id` back in line, e.g. when its worker was stopped.
+    pub fn requeue(&mut self, id: u64) {
+        if let Some(task) = self.tasks.iter_mut().find(|task| task.id == id && task.status == TaskStatus::Running) {
+            task.status = TaskStatus::Pending;
+            task.owner = None;
+        }
+    }
+
+    /// Puts tasks left running by a worker that crashed, i.e. whose owner `alive` says is gone, back
+    /// in line to run from the start. Tasks of workers still running are left to them. Returns how
+    /// many were put back.
+    pub fn requeue_abandoned(&mut self, alive: impl Fn(u32) -> bool) -> usize {
+        let mut count = 0;
+        for task in self.tasks.iter_mut().filter(|task| task.status == TaskStatus::Running && !task.owner.is_some_and(&alive)) {
+            task.status = TaskStatus::Pending;
+            task.owner = None;
+            count += 1;
+        }
+        count
+    }
+
+    pub fn pending(&self) -> usize {
+        self.tasks.iter().filter(|task| task.status == TaskStatus::Pending).count()
+    }
+}
+
+/// Whether the process `pid` is still running. Only Unix can tell; elsewhere it is taken to be gone.
+pub fn process_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        // Signal 0 only checks that the process exists; EPERM means it exists but is not ours.
+        // SAFETY: kill with signal 0 sends nothing.
+        let exists = unsafe { libc::kill(pid as libc::pid_t, 0) == 0 };
+        exists || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        false
+    }
+}
+
+/// Renders `tasks` as the table shown by the `queue` subcommand.
+pub fn table(tasks: &[QueuedTask]) -> String {
+    if tasks.is_empty() {
+        return "The queue is empty.".to_string();
+    }
+    let mut out = String::new();
+    let _ = writeln!(out, "{:>4}  {:<10} {:>8}  {:<17} Goal", "Id", "Status", "Cost", "Finished");
+    for task in tasks {
+        let status = match task.status {
+            TaskStatus::Pending => "pending",
+            TaskStatus::Running => "running",
+            TaskStatus::Succeeded => "succeeded",
+            TaskStatus::Failed => "failed",
+        };
+        let cost = task.cost.map(|cost| format!("${:.4}", cost)).unwrap_or_else(|| "-".to_string());
+        let finished = task.finished_at.map(|at| at.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()).unwrap_or_else(|| "-".to_string());
+        let _ = writeln!(out, "{:>4}  {:<10} {:>8}  {:<17} {}", task.id, status, cost, finished, goal_headline(&task.goal));
+    }
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tasks_run_in_order_and_survive_a_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(QUEUE_FILE);
+        let mut queue = TaskQueue::load(&path).unwrap();
+        assert_eq!(queue.enqueue("Fix the flaky test".to_string(), None).id, 1);
+        assert_eq!(queue.enqueue("Update the changelog".to_string(), Some(0.25)).id, 2);
+        queue.save(&path).unwrap();
+
+        let mut queue = TaskQueue::load(&path).unwrap();
+        let first = queue.start_next(100).unwrap();
+        assert_eq!(first.goal, "Fix the flaky test");
+        queue.finish(first.id, false, 0.12, "20240501-142233", Path::new(".agent/transcripts/task-1.jsonl"));
+        let second = queue.start_next(100).unwrap();
+        assert_eq!((second.id, second.budget, second.owner), (2, Some(0.25), Some(100)));
+        assert!(queue.start_next(100).is_none());
+        assert_eq!(queue.pending(), 0);
+
+        // Another worker leaves the task alone while its owner runs, and starts it over once it crashed.
+        assert_eq!(queue.requeue_abandoned(|pid| pid == 100), 0);
+        assert_eq!(queue.requeue_abandoned(|_| false), 1);
+        assert_eq!(queue.pending(), 1);
+        assert_eq!(queue.tasks[0].status, TaskStatus::Failed);
+        assert_eq!(queue.tasks[0].session.as_deref(), Some("20240501-142233"));
+    }
+
+    #[test]
+    fn test_update_saves_changes_under_a_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(QUEUE_FILE);
+        let adds: Vec<_> = (0..8)
+            .map(|n| {
+                let path = path.clone();
+                std::thread::spawn(move || TaskQueue::update(&path, |queue| queue.enqueue(format!("Task {}", n), None).id).unwrap())
+            })
+            .collect();
+        let mut ids: Vec<u64> = adds.into_iter().map(|add| add.join().unwrap()).collect();
+        ids.sort();
+        // No enqueue overwrote another's.
+        assert_eq!(ids, (1..=8).collect::<Vec<u64>>());
+        assert_eq!(TaskQueue::load(&path).unwrap().pending(), 8);
+        assert!(process_alive(std::process::id()));
+    }
+
+    #[test]
+    fn test_table_lists_status_and_cost() {
+        assert_eq!(table(&[]), "The queue is empty.");
+        let mut queue = TaskQueue::default();
+        queue.enqueue("Fix the flaky test".to_string(), None);
+        queue.enqueue("Update the changelog".to_string(), None);
+        let task = queue.start_next(100).unwrap();
+        queue.finish(task.id, true, 0.0421, "20240501-142233", Path::new("task-1.jsonl"));
+
+        let table = table(&queue.tasks);
+        assert!(table.contains("succeeded"));
+        assert!(table.contains("$0.0421"));
+        assert!(table.lines().nth(2).unwrap().contains("pending"));
+    }
+}
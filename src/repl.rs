@@ -0,0 +1,422 @@
+use std::fmt;
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+
+use log::warn;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Config, Editor, Helper};
+
+use crate::error::AgentError;
+use crate::llm::ModelSpec;
+use crate::state::{goal_headline, AppState};
+use crate::stats;
+
+/// Goals kept in the history file.
+pub const MAX_HISTORY_ENTRIES: usize = 1000;
+
+/// Where the chat prompt keeps its history between sessions: `history.txt` in `stats::data_dir()`.
+pub fn default_history_path() -> Option<PathBuf> {
+    Some(stats::data_dir()?.join("history.txt"))
+}
+
+/// Reads goals at the chat prompt with line editing, arrow-key history, Ctrl-R history search and
+/// multiline input. A line ending in `\` or an unclosed ``` fence continues the goal on the next
+/// line. Without a terminal (e.g. piped input) each line is one goal, and none are kept in the
+/// history.
+pub struct GoalReader {
+    editor: Editor<GoalHelper, FileHistory>,
+    history_path: Option<PathBuf>,
+}
+
+impl GoalReader {
+    /// A reader whose history is loaded from and appended to `history_path`, if given and the
+    /// goals are typed at a terminal.
+    pub fn new(history_path: Option<PathBuf>) -> Result<Self, AgentError> {
+        let history_path = history_path.filter(|_| std::io::stdin().is_terminal());
+        let config = Config::builder()
+            .max_history_size(MAX_HISTORY_ENTRIES)
+            .and_then(|builder| builder.history_ignore_dups(true))
+            .map_err(readline_error)?
+            .auto_add_history(false)
+            .build();
+        let mut editor = Editor::with_config(config).map_err(readline_error)?;
+        editor.set_helper(Some(GoalHelper));
+        if let Some(path) = &history_path {
+            // A missing file just means no goals have been entered yet.
+            if path.exists() {
+                if let Err(e) = editor.load_history(path) {
+                    warn!("Could not load the prompt history from {}: {}", path.display(), e);
+                }
+            }
+        }
+        Ok(Self { editor, history_path })
+    }
+
+    /// The next goal, trimmed, or `None` at the end of input or on Ctrl-D at the prompt. Ctrl-C
+    /// discards the line being typed and prompts again. Every non-empty goal is added to the
+    /// history and saved.
+    pub fn read_goal(&mut self, prompt: &str) -> Result<Option<String>, AgentError> {
+        let line = loop {
+            match self.editor.readline(prompt) {
+                Ok(line) => break line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(e) => return Err(readline_error(e)),
+            }
+        };
+        let goal = join_continuations(&line).trim().to_string();
+        if !goal.is_empty() {
+            self.remember(&goal);
+        }
+        Ok(Some(goal))
+    }
+
+    fn remember(&mut self, goal: &str) {
+        if let Err(e) = self.editor.add_history_entry(goal) {
+            warn!("Could not add the goal to the prompt history: {}", e);
+        }
+        let Some(path) = &self.history_path else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Err(e) = self.editor.append_history(path) {
+            warn!("Could not save the prompt history to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Decides when Enter submits the goal and when it continues it on a new line.
+struct GoalHelper;
+
+impl Validator for GoalHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(if is_incomplete(ctx.input()) { ValidationResult::Incomplete } else { ValidationResult::Valid(None) })
+    }
+}
+
+impl Completer for GoalHelper {
+    type Candidate = String;
+}
+
+impl Hinter for GoalHelper {
+    type Hint = String;
+}
+
+impl Highlighter for GoalHelper {}
+
+impl Helper for GoalHelper {}
+
+/// Whether `input` continues on the next line: its last line ends with `\`, or it opens a code
+/// fence it has not closed.
+fn is_incomplete(input: &str) -> bool {
+    let fences = input.lines().filter(|line| line.trim_start().starts_with("```")).count();
+    input.trim_end_matches([' ', '\t']).ends_with('\\') || fences % 2 == 1
+}
+
+/// Drops the `\` that continued each line onto the next one.
+fn join_continuations(input: &str) -> String {
+    input.split('\n').map(|line| line.trim_end().strip_suffix('\\').unwrap_or(line)).collect::<Vec<_>>().join("\n")
+}
+
+/// Reads a whole goal from `reader`, e.g. piped stdin or a goal file, keeping its line breaks and
+/// code blocks as written. `source` names the input in the error for an empty goal.
+pub fn read_goal_text(mut reader: impl Read, source: &str) -> Result<String, AgentError> {
+    let mut goal = String::new();
+    reader.read_to_string(&mut goal)?;
+    let goal = goal.trim();
+    if goal.is_empty() {
+        return Err(AgentError::ConfigError(format!("The goal read from {} is empty", source)));
+    }
+    Ok(goal.to_string())
+}
+
+/// The agent roles whose model `/model` can switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelRole {
+    Planner,
+    Coder,
+    Reasoner,
+    Summarizer,
+}
+
+impl ModelRole {
+    pub const ALL: [ModelRole; 4] = [ModelRole::Planner, ModelRole::Coder, ModelRole::Reasoner, ModelRole::Summarizer];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let role = match name.to_lowercase().as_str() {
+            "planner" => ModelRole::Planner,
+            "coder" => ModelRole::Coder,
+            "reasoner" => ModelRole::Reasoner,
+            "summarizer" => ModelRole::Summarizer,
+            _ => return None,
+        };
+        Some(role)
+    }
+}
+
+impl fmt::Display for ModelRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ModelRole::Planner => "planner",
+            ModelRole::Coder => "coder",
+            ModelRole::Reasoner => "reasoner",
+            ModelRole::Summarizer => "summarizer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Commands entered at the chat prompt instead of a goal, to inspect and steer the session.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlashCommand {
+    Help,
+    Cost,
+    Plan,
+    History,
+    /// `/model` shows the role models; `/model [ROLE] PROVIDER[:MODEL]` switches one (the coder's
+    /// by default) for the goals that follow.
+    Model(Option<(ModelRole, ModelSpec)>),
+    /// `/provider PROVIDER [MODEL]` switches every role to one provider for the goals that follow,
+    /// e.g. to run fully local with `/provider ollama llama3.1:70b`.
+    Provider(ModelSpec),
+    Undo,
+    Clear,
+    Save(PathBuf),
+    /// `/pin` lists the pinned files; `/pin PATH` pins one for the goals that follow.
+    Pin(Option<String>),
+    Unpin(String),
+}
+
+/// Usage and description of every slash command, as `/help` lists them.
+pub const SLASH_COMMANDS: &[(&str, &str)] = &[
+    ("/cost", "Show what the goals of this session have cost"),
+    ("/plan", "Show the plan of the last goal and how far it got"),
+    ("/history", "List the goals of this session with their outcome and cost"),
+    ("/model [ROLE] PROVIDER[:MODEL]", "Show the models, or switch the coder's (or ROLE's) model for the next goals"),
+    ("/provider PROVIDER [MODEL]", "Switch every role to PROVIDER (and MODEL) for the next goals"),
+    ("/undo", "Restore the files changed by the last goal"),
+    ("/clear", "Forget the earlier goals, so the next goal starts without them as context"),
+    ("/save FILE", "Write the goals of this session, with their plans and changed files, to FILE"),
+    ("/pin [PATH]", "List the pinned files, or pin PATH so its current content is in every goal's context"),
+    ("/unpin PATH", "Stop including PATH in the context"),
+    ("/help", "List these commands"),
+];
+
+impl SlashCommand {
+    /// Parses `input` if it is a slash command, or returns `None` for a goal. Unknown commands and
+    /// invalid arguments are errors, so a typo is not run as a goal.
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let rest = input.trim().strip_prefix('/')?;
+        let mut words = rest.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        let command = match (name.to_lowercase().as_str(), args.as_slice()) {
+            ("help" | "?", []) => Ok(SlashCommand::Help),
+            ("cost", []) => Ok(SlashCommand::Cost),
+            ("plan", []) => Ok(SlashCommand::Plan),
+            ("history", []) => Ok(SlashCommand::History),
+            ("undo", []) => Ok(SlashCommand::Undo),
+            ("clear", []) => Ok(SlashCommand::Clear),
+            ("model", []) => Ok(SlashCommand::Model(None)),
+            ("model", [spec]) => spec.parse().map(|spec| SlashCommand::Model(Some((ModelRole::Coder, spec)))),
+            ("model", [role, spec]) => match ModelRole::from_name(role) {
+                Some(role) => spec.parse().map(|spec| SlashCommand::Model(Some((role, spec)))),
+                None => Err(format!("Unknown role '{}': use planner, coder, reasoner or summarizer", role)),
+            },
+            ("provider", [spec]) => spec.parse().map(SlashCommand::Provider),
+            ("provider", [provider, model]) => {
+                provider.parse::<ModelSpec>().map(|spec| SlashCommand::Provider(ModelSpec::new(spec.provider, Some(model.to_string()))))
+            }
+            ("provider", []) => Err("/provider needs a provider, e.g. /provider ollama llama3.1:70b".to_string()),
+            ("save", []) => Err("/save needs a file to write to, e.g. /save session.md".to_string()),
+            ("save", _) => Ok(SlashCommand::Save(PathBuf::from(args.join(" ")))),
+            ("pin", []) => Ok(SlashCommand::Pin(None)),
+            ("pin", _) => Ok(SlashCommand::Pin(Some(args.join(" ")))),
+            ("unpin", []) => Err("/unpin needs a pinned file, e.g. /unpin src/lib.rs".to_string()),
+            ("unpin", _) => Ok(SlashCommand::Unpin(args.join(" "))),
+            ("cost" | "plan" | "history" | "undo" | "clear" | "help" | "model" | "provider", _) => Err(format!("Too many arguments for /{}", name)),
+            _ => Err(format!("Unknown command '/{}'; /help lists the commands", name)),
+        };
+        Some(command)
+    }
+}
+
+/// One goal run at the chat prompt, as the slash commands report it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinishedGoal {
+    pub goal: String,
+    pub succeeded: bool,
+    pub plan: Vec<String>,
+    /// How many steps of `plan` were completed.
+    pub steps_done: usize,
+    pub files_modified: Vec<String>,
+    pub cost: f64,
+}
+
+/// Every goal of a chat session. Unlike `ConversationMemory`, which keeps the last few goals as
+/// context for the model, it is kept whole for `/history`, `/cost` and `/save`.
+#[derive(Debug, Clone, Default)]
+pub struct SessionLog {
+    goals: Vec<FinishedGoal>,
+    /// Cost of the goals dropped by `clear`, still counted in `total_cost`.
+    cleared_cost: f64,
+}
+
+impl SessionLog {
+    pub fn record(&mut self, state: &AppState, succeeded: bool, cost: f64) {
+        self.goals.push(FinishedGoal {
+            goal: state.goal.clone(),
+            succeeded,
+            plan: state.plan.clone(),
+            steps_done: state.current_step.min(state.plan.len()),
+            files_modified: state.files_modified.clone(),
+            cost,
+        });
+    }
+
+    pub fn goals(&self) -> &[FinishedGoal] {
+        &self.goals
+    }
+
+    pub fn last(&self) -> Option<&FinishedGoal> {
+        self.goals.last()
+    }
+
+    /// Forgets the goals so far; their cost still counts towards the session's.
+    pub fn clear(&mut self) {
+        self.cleared_cost += self.goals.iter().map(|goal| goal.cost).sum::<f64>();
+        self.goals.clear();
+    }
+
+    /// What every goal of the session has cost, including cleared ones.
+    pub fn total_cost(&self) -> f64 {
+        self.cleared_cost + self.goals.iter().map(|goal| goal.cost).sum::<f64>()
+    }
+
+    /// One line per goal: its number, outcome, cost and text.
+    pub fn render_history(&self) -> String {
+        self.goals
+            .iter()
+            .enumerate()
+            .map(|(n, goal)| format!("{}. [{}] ${:.4} {}", n + 1, outcome(goal), goal.cost, goal_headline(&goal.goal)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The goals as a Markdown document, for `/save`.
+    pub fn to_markdown(&self) -> String {
+        let mut text = format!("# Chat session\n\nTotal cost: ${:.4}\n", self.total_cost());
+        for (n, goal) in self.goals.iter().enumerate() {
+            text.push_str(&format!("\n## {}. {}\n\nOutcome: {}, cost ${:.4}\n", n + 1, goal_headline(&goal.goal), outcome(goal), goal.cost));
+            if goal.goal.trim().lines().nth(1).is_some() {
+                text.push_str(&format!("\n### Goal\n\n{}\n", goal.goal.trim()));
+            }
+            if !goal.plan.is_empty() {
+                text.push_str(&format!("\n### Plan\n\n{}\n", render_plan(goal)));
+            }
+            if !goal.files_modified.is_empty() {
+                let files: Vec<String> = goal.files_modified.iter().map(|file| format!("- {}", file)).collect();
+                text.push_str(&format!("\n### Files changed\n\n{}\n", files.join("\n")));
+            }
+        }
+        text
+    }
+}
+
+/// The steps of `goal`, each marked `[x]` once completed.
+pub fn render_plan(goal: &FinishedGoal) -> String {
+    goal.plan
+        .iter()
+        .enumerate()
+        .map(|(n, step)| format!("- [{}] {}. {}", if n < goal.steps_done { "x" } else { " " }, n + 1, step))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn outcome(goal: &FinishedGoal) -> &'static str {
+    if goal.succeeded { "completed" } else { "failed" }
+}
+
+fn readline_error(error: ReadlineError) -> AgentError {
+    match error {
+        ReadlineError::Io(e) => AgentError::IoError(e),
+        other => AgentError::IoError(std::io::Error::other(other.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_continuations_and_fences_keep_the_goal_open() {
+        assert!(!is_incomplete("Add a CLI flag"));
+        assert!(is_incomplete("Fix this function: \\"));
+        assert!(is_incomplete("Make this compile:\n```rust\nfn main() {"));
+        assert!(!is_incomplete("Make this compile:\n```rust\nfn main() {}\n```"));
+    }
+
+    #[test]
+    fn test_join_continuations() {
+        assert_eq!(join_continuations("Add tests \\\nfor the parser"), "Add tests \nfor the parser");
+        assert_eq!(join_continuations("```\nlet a = 1;\n```"), "```\nlet a = 1;\n```");
+    }
+
+    #[test]
+    fn test_parse_slash_commands() {
+        assert_eq!(SlashCommand::parse("Add a /health route"), None);
+        assert_eq!(SlashCommand::parse("/cost"), Some(Ok(SlashCommand::Cost)));
+        assert_eq!(SlashCommand::parse(" /History "), Some(Ok(SlashCommand::History)));
+        assert_eq!(SlashCommand::parse("/model claude"), Some(Ok(SlashCommand::Model(Some((ModelRole::Coder, "claude".parse().unwrap()))))));
+        assert_eq!(
+            SlashCommand::parse("/model planner openai:gpt-4o"),
+            Some(Ok(SlashCommand::Model(Some((ModelRole::Planner, "openai:gpt-4o".parse().unwrap())))))
+        );
+        assert_eq!(
+            SlashCommand::parse("/provider ollama llama3.1:70b"),
+            Some(Ok(SlashCommand::Provider("ollama:llama3.1:70b".parse().unwrap())))
+        );
+        assert_eq!(SlashCommand::parse("/provider claude"), Some(Ok(SlashCommand::Provider("claude".parse().unwrap()))));
+        assert_eq!(SlashCommand::parse("/save notes/session.md"), Some(Ok(SlashCommand::Save(PathBuf::from("notes/session.md")))));
+        assert_eq!(SlashCommand::parse("/pin"), Some(Ok(SlashCommand::Pin(None))));
+        assert_eq!(SlashCommand::parse("/pin src/lib.rs"), Some(Ok(SlashCommand::Pin(Some("src/lib.rs".to_string())))));
+        assert_eq!(SlashCommand::parse("/unpin src/lib.rs"), Some(Ok(SlashCommand::Unpin("src/lib.rs".to_string()))));
+        for bad in ["/save", "/unpin", "/provider", "/provider acme", "/model nonsense", "/model tester claude", "/cost now", "/deploy"] {
+            assert!(matches!(SlashCommand::parse(bad), Some(Err(_))), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_read_goal_text_keeps_code_blocks() {
+        let piped = "Make this compile:\n```rust\nfn main() { let x: u8 = 300; }\n```\n\n";
+        assert_eq!(read_goal_text(piped.as_bytes(), "stdin").unwrap(), piped.trim());
+        assert!(read_goal_text(" \n".as_bytes(), "stdin").unwrap_err().to_string().contains("goal read from stdin is empty"));
+    }
+
+    #[test]
+    fn test_session_log_keeps_cost_across_clear() {
+        let mut state = AppState::new("Add a slugify helper".to_string());
+        state.plan = vec!["Write the code".to_string(), "Run the tests".to_string()];
+        state.current_step = 1;
+        state.files_modified = vec!["src/slug.rs".to_string()];
+        let mut log = SessionLog::default();
+        log.record(&state, false, 0.25);
+
+        assert_eq!(log.render_history(), "1. [failed] $0.2500 Add a slugify helper");
+        assert_eq!(render_plan(log.last().unwrap()), "- [x] 1. Write the code\n- [ ] 2. Run the tests");
+        let markdown = log.to_markdown();
+        assert!(markdown.contains("## 1. Add a slugify helper") && markdown.contains("- src/slug.rs"), "{}", markdown);
+
+        log.clear();
+        log.record(&AppState::new("Next".to_string()), true, 0.5);
+        assert_eq!(log.goals().len(), 1);
+        assert_eq!(log.total_cost(), 0.75);
+    }
+}
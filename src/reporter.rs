@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+/// Receives the human-readable progress lines the orchestrator produces ("Executing Step 2: ...",
+/// tool results, review feedback). Implement it to render progress in your own UI.
+pub trait Reporter: Send + Sync {
+    fn report(&self, line: &str);
+}
+
+/// Prints every line to stdout; what the CLI uses by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleReporter;
+
+impl Reporter for ConsoleReporter {
+    fn report(&self, line: &str) {
+        println!("{}", line);
+    }
+}
+
+/// Starts every line with a prefix before passing it on, so the output of workers running in
+/// parallel can be told apart.
+pub struct PrefixedReporter {
+    prefix: String,
+    inner: Arc<dyn Reporter>,
+}
+
+impl PrefixedReporter {
+    pub fn new(prefix: impl Into<String>, inner: Arc<dyn Reporter>) -> Self {
+        Self { prefix: prefix.into(), inner }
+    }
+}
+
+impl Reporter for PrefixedReporter {
+    fn report(&self, line: &str) {
+        let prefixed: Vec<String> = line.split('\n').map(|part| format!("{}{}", self.prefix, part)).collect();
+        self.inner.report(&prefixed.join("\n"));
+    }
+}
+
+/// Discards all progress output, e.g. when events are rendered instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullReporter;
+
+impl Reporter for NullReporter {
+    fn report(&self, _line: &str) {}
+}
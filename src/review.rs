@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::error::AgentError;
+use crate::pull_request::git;
+
+/// Lines of the changed files shown above and below each hunk, so the reviewer sees what the
+/// changed code calls and is called from.
+const CONTEXT_LINES: usize = 20;
+/// Characters of the diff sent to the reviewer; longer diffs are cut.
+const MAX_DIFF_CHARS: usize = 60_000;
+/// Characters of surrounding file context sent to the reviewer.
+const MAX_CONTEXT_CHARS: usize = 40_000;
+
+/// The changes `review` looks at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffSource {
+    /// Everything not yet committed, staged or not.
+    Uncommitted,
+    /// What `git commit` would commit.
+    Staged,
+    /// A range of commits, e.g. `main..HEAD`.
+    Range(String),
+}
+
+impl DiffSource {
+    /// The `git diff` arguments for these changes.
+    fn diff_args(&self) -> Vec<&str> {
+        match self {
+            DiffSource::Uncommitted => vec!["diff", "HEAD"],
+            DiffSource::Staged => vec!["diff", "--cached"],
+            DiffSource::Range(range) => vec!["diff", range],
+        }
+    }
+
+    /// The `git show` revision the changed version of `path` is read from; the working tree's
+    /// file for uncommitted changes and for a single revision (`git diff <rev>` compares it with
+    /// the working tree).
+    fn revision_of(&self, path: &str) -> Option<String> {
+        match self {
+            DiffSource::Uncommitted => None,
+            DiffSource::Staged => Some(format!(":{}", path)),
+            DiffSource::Range(range) if !range.contains("..") => None,
+            DiffSource::Range(range) => {
+                let end = range.rsplit("..").next().unwrap_or_default().trim_start_matches('.');
+                Some(format!("{}:{}", if end.is_empty() { "HEAD" } else { end }, path))
+            }
+        }
+    }
+
+    /// The diff of these changes, cut to `MAX_DIFF_CHARS`.
+    pub async fn diff(&self) -> Result<String, AgentError> {
+        let diff = git(&self.diff_args()).await?;
+        Ok(cut(diff, MAX_DIFF_CHARS))
+    }
+
+    /// The lines around every hunk of `diff` in the changed files, as `path:start-end` sections
+    /// with numbered lines. Files that cannot be read (e.g. deleted ones) are left out.
+    pub async fn surrounding_context(&self, diff: &str) -> String {
+        // Diff paths are relative to the repository root, not to the directory `review` runs in.
+        let root = git(&["rev-parse", "--show-toplevel"]).await.unwrap_or_else(|_| ".".to_string());
+        let mut out = String::new();
+        for (path, start, end) in context_windows(&hunks(diff), CONTEXT_LINES) {
+            let content = match self.revision_of(&path) {
+                Some(revision) => git(&["show", &revision]).await.ok(),
+                None => std::fs::read_to_string(Path::new(&root).join(&path)).ok(),
+            };
+            let Some(content) = content else { continue };
+            let lines: Vec<&str> = content.lines().collect();
+            let end = end.min(lines.len());
+            if start > end {
+                continue;
+            }
+            let _ = writeln!(out, "--- {}:{}-{} ---", path, start, end);
+            for (number, line) in lines[start - 1..end].iter().enumerate() {
+                let _ = writeln!(out, "{:>5} {}", start + number, line);
+            }
+        }
+        cut(out, MAX_CONTEXT_CHARS)
+    }
+}
+
+/// A changed region of the diff: `len` lines from `start` in the new version of `path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub path: String,
+    pub start: usize,
+    pub len: usize,
+}
+
+/// The hunks of a unified diff. Deleted files have none.
+pub fn hunks(diff: &str) -> Vec<Hunk> {
+    let mut hunks = Vec::new();
+    let mut path: Option<String> = None;
+    for line in diff.lines() {
+        if let Some(target) = line.strip_prefix("+++ ") {
+            path = target.strip_prefix("b/").map(str::to_string);
+        } else if let (Some(header), Some(path)) = (line.strip_prefix("@@ "), &path) {
+            // @@ -old_start,old_len +new_start,new_len @@
+            let Some(new) = header.split_whitespace().find_map(|range| range.strip_prefix('+')) else { continue };
+            let (start, len) = match new.split_once(',') {
+                Some((start, len)) => (start.parse().unwrap_or(1), len.parse().unwrap_or(0)),
+                None => (new.parse().unwrap_or(1), 1),
+            };
+            hunks.push(Hunk { path: path.clone(), start, len });
+        }
+    }
+    hunks
+}
+
+/// The 1-based line ranges to show around `hunks`, widened by `margin` lines and merged where
+/// they overlap, per file in the order the files appear.
+pub fn context_windows(hunks: &[Hunk], margin: usize) -> Vec<(String, usize, usize)> {
+    let mut windows: Vec<(String, usize, usize)> = Vec::new();
+    for hunk in hunks {
+        let start = hunk.start.saturating_sub(margin).max(1);
+        let end = hunk.start + hunk.len + margin;
+        match windows.last_mut() {
+            Some((path, _, last_end)) if *path == hunk.path && start <= *last_end + 1 => *last_end = (*last_end).max(end),
+            _ => windows.push((hunk.path.clone(), start, end)),
+        }
+    }
+    windows
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        }
+    }
+}
+
+/// One problem the reviewer found in a diff.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub file: String,
+    /// The line in the changed version of `file`, when the problem has one.
+    #[serde(default)]
+    pub line: Option<usize>,
+    pub message: String,
+    /// How to fix it, when the reviewer has a concrete fix.
+    #[serde(default)]
+    pub suggestion: Option<String>,
+}
+
+/// The reviewer's verdict on a diff: an overall summary and its findings, most severe first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiffReview {
+    pub summary: String,
+    #[serde(default)]
+    pub findings: Vec<Finding>,
+}
+
+impl DiffReview {
+    /// Whether any finding is `High`, which makes `review` exit with 1.
+    pub fn has_blocking(&self) -> bool {
+        self.findings.iter().any(|finding| finding.severity == Severity::High)
+    }
+}
+
+/// The review as text, one finding per paragraph.
+pub fn render(review: &DiffReview) -> String {
+    let mut out = format!("{}\n", review.summary.trim());
+    if review.findings.is_empty() {
+        out.push_str("\nNo findings.");
+    }
+    for finding in &review.findings {
+        let location = match finding.line {
+            Some(line) => format!("{}:{}", finding.file, line),
+            None => finding.file.clone(),
+        };
+        let _ = write!(out, "\n[{}] {}\n  {}", finding.severity.as_str(), location, finding.message);
+        if let Some(suggestion) = &finding.suggestion {
+            let _ = write!(out, "\n  Suggestion: {}", suggestion);
+        }
+        out.push('\n');
+    }
+    out.trim_end().to_string()
+}
+
+/// The review as the body of a GitHub "create a review for a pull request" request: findings on a
+/// line inside one of `hunks` become comments on it, the rest are listed in the body, as GitHub
+/// rejects comments on lines the diff does not show. High findings request changes.
+pub fn github_review(review: &DiffReview, hunks: &[Hunk]) -> Value {
+    let comment = |finding: &Finding| {
+        let mut body = format!("**{}**: {}", finding.severity.as_str(), finding.message);
+        if let Some(suggestion) = &finding.suggestion {
+            body.push_str(&format!("\n\nSuggestion: {}", suggestion));
+        }
+        body
+    };
+    let in_diff = |finding: &Finding| {
+        finding.line.is_some_and(|line| hunks.iter().any(|hunk| hunk.path == finding.file && (hunk.start..hunk.start + hunk.len).contains(&line)))
+    };
+    let mut body = review.summary.trim().to_string();
+    for finding in review.findings.iter().filter(|finding| !in_diff(finding)) {
+        match finding.line {
+            Some(line) => body.push_str(&format!("\n\n- `{}:{}`: {}", finding.file, line, comment(finding))),
+            None => body.push_str(&format!("\n\n- `{}`: {}", finding.file, comment(finding))),
+        }
+    }
+    let comments: Vec<Value> = review
+        .findings
+        .iter()
+        .filter(|finding| in_diff(finding))
+        .map(|finding| json!({ "path": finding.file, "line": finding.line, "side": "RIGHT", "body": comment(finding) }))
+        .collect();
+    json!({
+        "event": if review.has_blocking() { "REQUEST_CHANGES" } else { "COMMENT" },
+        "body": body,
+        "comments": comments,
+    })
+}
+
+fn cut(text: String, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}\n... (cut)", &text[..end]),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "diff --git a/src/auth.rs b/src/auth.rs\n--- a/src/auth.rs\n+++ b/src/auth.rs\n@@ -10,3 +10,4 @@ fn check()\n ctx\n+new\n@@ -80,2 +81,2 @@\n-a\n+b\ndiff --git a/old.rs b/old.rs\n--- a/old.rs\n+++ /dev/null\n@@ -1,2 +0,0 @@\n-gone\n";
+
+    #[test]
+    fn test_hunks_and_context_windows() {
+        let hunks = hunks(DIFF);
+        assert_eq!(hunks, vec![Hunk { path: "src/auth.rs".to_string(), start: 10, len: 4 }, Hunk { path: "src/auth.rs".to_string(), start: 81, len: 2 }]);
+        assert_eq!(context_windows(&hunks, 20), vec![("src/auth.rs".to_string(), 1, 34), ("src/auth.rs".to_string(), 61, 103)]);
+        // Windows that meet are merged.
+        assert_eq!(context_windows(&hunks, 40), vec![("src/auth.rs".to_string(), 1, 123)]);
+        assert_eq!(DiffSource::Range("main..feature".to_string()).revision_of("a.rs").as_deref(), Some("feature:a.rs"));
+        assert_eq!(DiffSource::Range("main...".to_string()).revision_of("a.rs").as_deref(), Some("HEAD:a.rs"));
+        assert_eq!(DiffSource::Staged.revision_of("a.rs").as_deref(), Some(":a.rs"));
+        // A single revision is compared with the working tree.
+        assert_eq!(DiffSource::Range("HEAD~1".to_string()).revision_of("a.rs"), None);
+    }
+
+    #[test]
+    fn test_github_review_comments_on_lines() {
+        let review = DiffReview {
+            summary: "Mostly fine.".to_string(),
+            findings: vec![
+                Finding { severity: Severity::High, file: "src/auth.rs".to_string(), line: Some(11), message: "Tokens are never expired.".to_string(), suggestion: Some("Check `exp`.".to_string()) },
+                Finding { severity: Severity::Low, file: "README.md".to_string(), line: None, message: "Document the flag.".to_string(), suggestion: None },
+                Finding { severity: Severity::Low, file: "src/auth.rs".to_string(), line: Some(50), message: "Unused import.".to_string(), suggestion: None },
+            ],
+        };
+        let json = github_review(&review, &hunks(DIFF));
+        assert_eq!(json["event"], "REQUEST_CHANGES");
+        assert_eq!(json["comments"][0], json!({ "path": "src/auth.rs", "line": 11, "side": "RIGHT", "body": "**high**: Tokens are never expired.\n\nSuggestion: Check `exp`." }));
+        assert_eq!(json["comments"].as_array().unwrap().len(), 1);
+        // Line 50 is outside the diff's hunks, so it is listed in the body.
+        assert_eq!(json["body"], "Mostly fine.\n\n- `README.md`: **low**: Document the flag.\n\n- `src/auth.rs:50`: **low**: Unused import.");
+
+        let text = render(&review);
+        assert!(text.contains("[high] src/auth.rs:11\n  Tokens are never expired.\n  Suggestion: Check `exp`."));
+        assert_eq!(render(&DiffReview { summary: "Clean.".to_string(), findings: Vec::new() }), "Clean.\n\nNo findings.");
+    }
+}
@@ -0,0 +1,367 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::AgentError;
+use crate::events::AgentEvent;
+use crate::journal::Journal;
+use crate::metrics::format_duration;
+use crate::snapshot::ChangeKind;
+use crate::state::AppState;
+use crate::tools::Tool;
+
+/// Where the report of every run is written, one `<timestamp>.md` per run.
+pub const REPORTS_DIR: &str = ".agent/reports";
+
+/// Commands treated as running tests when the model uses `RunCommand` rather than `RunTests`,
+/// matched against the program and subcommand a command starts with.
+const TEST_COMMANDS: [&str; 12] = ["cargo test", "cargo nextest", "pytest", "unittest", "npm test", "yarn test", "pnpm test", "jest", "vitest", "mocha", "node --test", "go test"];
+
+/// How a run ended.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "status", content = "error")]
+pub enum RunOutcome {
+    Completed,
+    Cancelled,
+    Failed(String),
+}
+
+/// A file the run changed, over all of its steps.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangedFile {
+    pub path: String,
+    pub kind: ChangeKind,
+    /// Lines added and removed; `None` for files changed without a diff (binary or large).
+    pub lines: Option<(usize, usize)>,
+    /// The 0-based steps that changed it.
+    pub steps: Vec<usize>,
+}
+
+/// A command the run ran, with the step it ran in.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommandRun {
+    pub step_index: usize,
+    pub command: String,
+    pub success: bool,
+}
+
+/// Whether the tests passed the last time the run ran them, and how it ran them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestStatus {
+    pub command: String,
+    pub passed: bool,
+}
+
+/// What a run did, shown when it ends and written to `REPORTS_DIR`: its goal and outcome, the
+/// plan and how far it got, the files it changed by step, the commands it ran, whether the tests
+/// pass, and what it cost.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunReport {
+    pub goal: String,
+    pub outcome: RunOutcome,
+    pub plan: Vec<String>,
+    /// Steps of `plan` that finished.
+    pub completed_steps: usize,
+    pub files: Vec<ChangedFile>,
+    pub commands: Vec<CommandRun>,
+    /// `None` when the run did not run the tests.
+    pub tests: Option<TestStatus>,
+    pub cost: f64,
+    #[serde(serialize_with = "as_secs")]
+    pub duration: Duration,
+}
+
+fn as_secs<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+impl RunReport {
+    /// The report of the run that left `state`, from the `events` it emitted. The files come from
+    /// the `StepChanges` events; those of `state.files_modified` they leave out (hidden or ignored
+    /// files, or all of them when the events were not emitted or it was a dry run) are added with
+    /// their line counts worked out from the pre-run text in `journal` and the text on disk now.
+    pub fn new(state: &AppState, outcome: RunOutcome, events: &[AgentEvent], journal: Option<&Journal>, cost: f64, duration: Duration) -> Self {
+        // Each file with whether it existed before the run and the kind of its latest change.
+        let mut files: BTreeMap<String, (ChangedFile, bool, ChangeKind)> = BTreeMap::new();
+        let mut commands = Vec::new();
+        let mut tests = None;
+        for event in events {
+            match event {
+                AgentEvent::StepChanges { step_index, changes } => {
+                    for change in changes {
+                        let lines = change.diff.as_deref().map(line_counts);
+                        let (file, _, latest) = files.entry(change.path.clone()).or_insert_with(|| {
+                            let file = ChangedFile { path: change.path.clone(), kind: change.kind, lines: Some((0, 0)), steps: Vec::new() };
+                            (file, change.kind != ChangeKind::Created, change.kind)
+                        });
+                        *latest = change.kind;
+                        file.lines = match (file.lines, lines) {
+                            (Some((added, removed)), Some((more_added, more_removed))) => Some((added + more_added, removed + more_removed)),
+                            _ => None,
+                        };
+                        if !file.steps.contains(step_index) {
+                            file.steps.push(*step_index);
+                        }
+                    }
+                }
+                AgentEvent::ToolExecuted { step_index, tool: Tool::RunCommand { command, .. }, success, .. } => {
+                    if is_test_command(command) {
+                        tests = Some(TestStatus { command: command.clone(), passed: *success });
+                    }
+                    commands.push(CommandRun { step_index: *step_index, command: command.clone(), success: *success });
+                }
+                AgentEvent::ToolExecuted { tool: Tool::RunTests { framework_hint }, success, .. } => {
+                    let command = match framework_hint {
+                        Some(framework) => format!("RunTests ({})", framework),
+                        None => "RunTests".to_string(),
+                    };
+                    tests = Some(TestStatus { command, passed: *success });
+                }
+                _ => {}
+            }
+        }
+        let captured: Vec<String> = files.keys().cloned().collect();
+        let mut files: Vec<ChangedFile> = files
+            .into_values()
+            .filter_map(|(mut file, existed, latest)| {
+                file.kind = match (existed, latest) {
+                    (true, ChangeKind::Deleted) => ChangeKind::Deleted,
+                    (true, _) => ChangeKind::Modified,
+                    // A file the run created and deleted again is not a change.
+                    (false, ChangeKind::Deleted) => return None,
+                    (false, _) => ChangeKind::Created,
+                };
+                Some(file)
+            })
+            .collect();
+        let missed = state.files_modified.iter().filter(|path| !captured.iter().any(|file| file == path.trim_start_matches("./")));
+        files.extend(missed.filter_map(|path| journaled_change(path, journal)));
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Self {
+            goal: state.goal.clone(),
+            outcome,
+            plan: state.plan.clone(),
+            completed_steps: state.current_step.min(state.plan.len()),
+            files,
+            commands,
+            tests,
+            cost,
+            duration,
+        }
+    }
+
+    /// The report as Markdown, for the terminal and the report file alike.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# Run Report\n\n");
+        let outcome = match &self.outcome {
+            RunOutcome::Completed => "completed".to_string(),
+            RunOutcome::Cancelled => "cancelled".to_string(),
+            RunOutcome::Failed(error) => format!("failed: {}", error),
+        };
+        let tests = match &self.tests {
+            Some(tests) => format!("{} (`{}`)", if tests.passed { "passing" } else { "failing" }, tests.command),
+            None => "not run".to_string(),
+        };
+        let _ = writeln!(out, "- **Goal:** {}", self.goal.trim().replace('\n', "\n  "));
+        let _ = writeln!(out, "- **Outcome:** {}", outcome);
+        let _ = writeln!(out, "- **Tests:** {}", tests);
+        let _ = writeln!(out, "- **Duration:** {}", format_duration(self.duration));
+        let _ = writeln!(out, "- **Cost:** ${:.4}", self.cost);
+
+        if !self.plan.is_empty() {
+            let _ = writeln!(out, "\n## Plan ({}/{} steps done)\n", self.completed_steps, self.plan.len());
+            for (i, step) in self.plan.iter().enumerate() {
+                let _ = writeln!(out, "{}. [{}] {}", i + 1, if i < self.completed_steps { "x" } else { " " }, step);
+            }
+        }
+
+        let _ = writeln!(out, "\n## Files\n");
+        if self.files.is_empty() {
+            out.push_str("No files changed.\n");
+        } else {
+            out.push_str("| File | Change | Lines | Steps |\n| --- | --- | --- | --- |\n");
+            for file in &self.files {
+                let kind = match file.kind {
+                    ChangeKind::Created => "created",
+                    ChangeKind::Modified => "modified",
+                    ChangeKind::Deleted => "deleted",
+                };
+                let lines = file.lines.map_or_else(|| "-".to_string(), |(added, removed)| format!("+{} -{}", added, removed));
+                let steps: Vec<String> = file.steps.iter().map(|step| (step + 1).to_string()).collect();
+                let _ = writeln!(out, "| `{}` | {} | {} | {} |", file.path, kind, lines, steps.join(", "));
+            }
+        }
+
+        let _ = writeln!(out, "\n## Commands\n");
+        if self.commands.is_empty() {
+            out.push_str("No commands run.\n");
+        }
+        for command in &self.commands {
+            let _ = writeln!(out, "- {} `{}` (step {})", if command.success { "✅" } else { "❌" }, command.command, command.step_index + 1);
+        }
+        out
+    }
+
+    /// Writes the report to `dir` as `<timestamp>.md`, or `<timestamp>-2.md` and so on when
+    /// another run's report already has that name, and returns its path.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, AgentError> {
+        std::fs::create_dir_all(dir)?;
+        let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+        for n in 1.. {
+            let path = dir.join(if n == 1 { format!("{}.md", timestamp) } else { format!("{}-{}.md", timestamp, n) });
+            match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    std::io::Write::write_all(&mut file, self.to_markdown().as_bytes())?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        unreachable!("a free report name is always found")
+    }
+}
+
+/// Whether `command`, or one of the commands it chains, starts with one of `TEST_COMMANDS`, after
+/// any `NAME=value` assignments and a launcher such as `npx` or `python -m`.
+fn is_test_command(command: &str) -> bool {
+    command.split(['&', '|', ';', '\n']).any(|part| {
+        let mut words: Vec<&str> = part.split_whitespace().skip_while(|word| word.contains('=')).collect();
+        if let Some(program) = words.first_mut() {
+            *program = program.rsplit('/').next().unwrap_or(program);
+        }
+        let words = match words.as_slice() {
+            ["npx" | "bunx" | "pnpx", rest @ ..] | ["python" | "python3", "-m", rest @ ..] | ["poetry" | "uv" | "pipenv", "run", rest @ ..] => rest,
+            words => words,
+        };
+        TEST_COMMANDS.iter().any(|test| {
+            let test: Vec<&str> = test.split_whitespace().collect();
+            words.starts_with(&test)
+        })
+    })
+}
+
+/// How the run changed `path`, from its pre-run text in `journal` and its text now; `None` when
+/// the run created and removed it again. Without a journal entry, the file is listed as modified
+/// without line counts.
+fn journaled_change(path: &str, journal: Option<&Journal>) -> Option<ChangedFile> {
+    let absolute = std::path::absolute(path).ok();
+    let original = journal.and_then(|journal| journal.changes.iter().find(|change| Some(&change.path) == absolute.as_ref()));
+    let Some(original) = original else {
+        return Some(ChangedFile { path: path.to_string(), kind: ChangeKind::Modified, lines: None, steps: Vec::new() });
+    };
+    let current = std::fs::read_to_string(&original.path).ok();
+    let kind = match (&original.original, &current) {
+        (None, None) => return None,
+        (None, Some(_)) => ChangeKind::Created,
+        (Some(_), None) => ChangeKind::Deleted,
+        (Some(_), Some(_)) => ChangeKind::Modified,
+    };
+    let diff = crate::tools::unified_diff(path, original.original.as_deref().unwrap_or_default(), current.as_deref().unwrap_or_default());
+    Some(ChangedFile { path: path.to_string(), kind, lines: Some(line_counts(&diff)), steps: Vec::new() })
+}
+
+/// Lines added and removed by a unified diff.
+fn line_counts(diff: &str) -> (usize, usize) {
+    let added = diff.lines().filter(|line| line.starts_with('+') && !line.starts_with("+++")).count();
+    let removed = diff.lines().filter(|line| line.starts_with('-') && !line.starts_with("---")).count();
+    (added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::FileChange;
+
+    fn change(path: &str, kind: ChangeKind, diff: &str) -> FileChange {
+        FileChange { path: path.to_string(), kind, before: None, after: None, diff: Some(diff.to_string()) }
+    }
+
+    fn command(step_index: usize, command: &str, success: bool) -> AgentEvent {
+        let tool = Tool::RunCommand { command: command.to_string(), timeout_secs: None, cwd: None, env: Default::default(), stdin: None };
+        AgentEvent::ToolExecuted { step_index, tool, success, output: String::new() }
+    }
+
+    #[test]
+    fn test_report_attributes_changes_to_steps() {
+        let mut state = AppState::new("Add a parser".to_string());
+        state.plan = vec!["Write the parser".to_string(), "Test it".to_string(), "Document it".to_string()];
+        state.current_step = 2;
+        let events = [
+            AgentEvent::StepChanges { step_index: 0, changes: vec![change("src/parse.rs", ChangeKind::Created, "+++ b/src/parse.rs\n+fn a() {}\n+fn b() {}\n"), change("tmp.txt", ChangeKind::Created, "")] },
+            command(1, "cargo test", false),
+            AgentEvent::StepChanges { step_index: 1, changes: vec![change("src/parse.rs", ChangeKind::Modified, "-fn b() {}\n+fn b() -> u8 { 1 }\n"), change("tmp.txt", ChangeKind::Deleted, "")] },
+            command(1, "cargo test parse", true),
+        ];
+
+        let report = RunReport::new(&state, RunOutcome::Failed("Stopped".to_string()), &events, None, 0.25, Duration::from_secs(125));
+
+        assert_eq!(report.files, vec![ChangedFile { path: "src/parse.rs".to_string(), kind: ChangeKind::Created, lines: Some((3, 1)), steps: vec![0, 1] }]);
+        assert_eq!(report.tests, Some(TestStatus { command: "cargo test parse".to_string(), passed: true }));
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("- **Outcome:** failed: Stopped\n- **Tests:** passing (`cargo test parse`)\n- **Duration:** 2m 5s\n- **Cost:** $0.2500"));
+        assert!(markdown.contains("## Plan (2/3 steps done)\n\n1. [x] Write the parser\n2. [x] Test it\n3. [ ] Document it"));
+        assert!(markdown.contains("| `src/parse.rs` | created | +3 -1 | 1, 2 |"));
+        assert!(markdown.contains("- ❌ `cargo test` (step 2)"));
+    }
+
+    #[test]
+    fn test_report_adds_the_files_step_changes_missed() {
+        let mut state = AppState::new("Configure the app".to_string());
+        state.files_modified = vec!["src/app.rs".to_string(), ".env".to_string()];
+        let events = [AgentEvent::StepChanges { step_index: 0, changes: vec![change("src/app.rs", ChangeKind::Modified, "+let port = 80;\n")] }];
+
+        let report = RunReport::new(&state, RunOutcome::Completed, &events, None, 0.0, Duration::from_secs(1));
+        assert_eq!(report.files[0], ChangedFile { path: ".env".to_string(), kind: ChangeKind::Modified, lines: None, steps: Vec::new() });
+        assert_eq!(report.files[1], ChangedFile { path: "src/app.rs".to_string(), kind: ChangeKind::Modified, lines: Some((1, 0)), steps: vec![0] });
+        assert_eq!(report.files.len(), 2);
+    }
+
+    #[test]
+    fn test_test_commands_match_the_program_and_subcommand() {
+        for command in ["cargo test", "RUST_LOG=debug cargo test -p core", "cd backend && npm test", "python -m pytest -x", "npx jest", "./node_modules/.bin/vitest run", "go test ./..."] {
+            assert!(is_test_command(command), "{} runs tests", command);
+        }
+        for command in ["pip install pytest", "npm install --save-dev jest", "cargo build && echo 'cargo test next'", "cat go.test.log", "git commit -m 'add pytest'"] {
+            assert!(!is_test_command(command), "{} does not run tests", command);
+        }
+    }
+
+    #[test]
+    fn test_report_without_step_changes_lists_modified_files() {
+        let mut state = AppState::new("Say hello".to_string());
+        state.files_modified = vec!["hello.txt".to_string()];
+        let report = RunReport::new(&state, RunOutcome::Completed, &[], None, 0.0, Duration::from_millis(500));
+        assert_eq!(report.files[0].lines, None);
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| `hello.txt` | modified | - |  |") && markdown.contains("No commands run.") && markdown.contains("**Tests:** not run"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = report.save(&dir.path().join("reports")).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), markdown);
+        // A second report in the same second gets its own file.
+        let second = report.save(&dir.path().join("reports")).unwrap();
+        assert_ne!(second, path);
+        assert_eq!(std::fs::read_dir(dir.path().join("reports")).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_report_counts_lines_from_the_journal_without_step_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (notes, todo) = (dir.path().join("notes.txt"), dir.path().join("todo.txt"));
+        std::fs::write(&notes, "one\ntwo\n").unwrap();
+        let mut journal = Journal::new("Tidy up");
+        journal.record(&notes).unwrap();
+        journal.record(&todo).unwrap();
+        std::fs::write(&notes, "one\n2\nthree\n").unwrap();
+        std::fs::write(&todo, "buy milk\n").unwrap();
+
+        let mut state = AppState::new("Tidy up".to_string());
+        state.files_modified = vec![notes.display().to_string(), todo.display().to_string()];
+        let report = RunReport::new(&state, RunOutcome::Completed, &[], Some(&journal), 0.0, Duration::from_secs(1));
+        let files: Vec<(ChangeKind, Option<(usize, usize)>)> = report.files.iter().map(|file| (file.kind, file.lines)).collect();
+        assert_eq!(files, [(ChangeKind::Modified, Some((2, 1))), (ChangeKind::Created, Some((1, 0)))]);
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{agent::AgentBuilder, error::AgentError, state::AppState};
+
+/// Identifies a session within its `SessionManager`.
+pub type SessionId = u64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionStatus {
+    Running,
+    Completed,
+    Failed(String),
+    Cancelled,
+}
+
+/// A snapshot of one session for listings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub id: SessionId,
+    pub goal: String,
+    pub status: SessionStatus,
+}
+
+struct Session {
+    goal: String,
+    status: Arc<Mutex<SessionStatus>>,
+    cancel: CancellationToken,
+    task: JoinHandle<AppState>,
+}
+
+/// Runs independent agent sessions concurrently on the tokio runtime. Each session is built from
+/// its own `AgentBuilder`, so configuration (`config`, `workdir`), cost tracking and event sinks
+/// are never shared between sessions unless the caller shares them on purpose.
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: Mutex<HashMap<SessionId, Session>>,
+    next_id: AtomicU64,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the agent with a cancellation token of its own and starts running it.
+    pub fn spawn(&self, builder: AgentBuilder) -> Result<SessionId, AgentError> {
+        let cancel = CancellationToken::new();
+        let mut agent = builder.cancellation(cancel.clone()).build()?;
+        let goal = agent.state().goal.clone();
+        let status = Arc::new(Mutex::new(SessionStatus::Running));
+
+        let finished = status.clone();
+        let task = tokio::spawn(async move {
+            let outcome = match agent.run().await {
+                Ok(()) => SessionStatus::Completed,
+                Err(e) if matches!(e.downcast_ref::<AgentError>(), Some(AgentError::Cancelled)) => SessionStatus::Cancelled,
+                Err(e) => SessionStatus::Failed(e.to_string()),
+            };
+            *finished.lock().unwrap() = outcome;
+            agent.state().clone()
+        });
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.sessions.lock().unwrap().insert(id, Session { goal, status, cancel, task });
+        Ok(id)
+    }
+
+    pub fn status(&self, id: SessionId) -> Option<SessionStatus> {
+        self.sessions.lock().unwrap().get(&id).map(|session| session.status.lock().unwrap().clone())
+    }
+
+    /// Every session not yet collected with `wait`, oldest first.
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let sessions = self.sessions.lock().unwrap();
+        let mut infos: Vec<SessionInfo> = sessions
+            .iter()
+            .map(|(id, session)| SessionInfo { id: *id, goal: session.goal.clone(), status: session.status.lock().unwrap().clone() })
+            .collect();
+        infos.sort_by_key(|info| info.id);
+        infos
+    }
+
+    /// Asks the session to stop; it finishes with `SessionStatus::Cancelled`. Returns whether the
+    /// session exists.
+    pub fn cancel(&self, id: SessionId) -> bool {
+        match self.sessions.lock().unwrap().get(&id) {
+            Some(session) => {
+                session.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn cancel_all(&self) {
+        for session in self.sessions.lock().unwrap().values() {
+            session.cancel.cancel();
+        }
+    }
+
+    /// Waits for the session to finish and removes it from the manager, returning how it ended
+    /// and its final state.
+    pub async fn wait(&self, id: SessionId) -> Result<(SessionStatus, AppState), AgentError> {
+        let session = self
+            .sessions
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .ok_or_else(|| AgentError::SessionError(format!("no session with id {}", id)))?;
+        let state = session
+            .task
+            .await
+            .map_err(|e| AgentError::SessionError(format!("session {} stopped unexpectedly: {}", id, e)))?;
+        let status = session.status.lock().unwrap().clone();
+        Ok((status, state))
+    }
+}
@@ -0,0 +1,304 @@
+use chrono::{DateTime, Local, Utc};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::error::AgentError;
+use crate::state::{goal_headline, is_valid_session_id, AppState};
+use crate::stats::data_dir;
+
+/// History entries `describe` shows, most recent last.
+const RECENT_HISTORY_ENTRIES: usize = 5;
+/// Characters of a history entry `describe` shows.
+const HISTORY_PREVIEW_CHARS: usize = 200;
+/// Sessions the store keeps; `prune` removes the least recently saved beyond this.
+pub const MAX_STORED_SESSIONS: usize = 200;
+
+/// Where session checkpoints are kept: `sessions/` in `stats::data_dir()`.
+pub fn default_sessions_dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("sessions"))
+}
+
+/// A session's checkpoint in the store, with when it was last saved.
+#[derive(Debug, Clone)]
+pub struct StoredSession {
+    pub state: AppState,
+    pub updated_at: DateTime<Utc>,
+    pub path: PathBuf,
+}
+
+impl StoredSession {
+    /// How far the session got: `done`, `step 3/7` (the next step to run) or `not planned`.
+    pub fn progress(&self) -> String {
+        let state = &self.state;
+        if state.is_complete() {
+            "done".to_string()
+        } else if state.plan.is_empty() {
+            "not planned".to_string()
+        } else {
+            format!("step {}/{}", state.current_step + 1, state.plan.len())
+        }
+    }
+}
+
+/// One checkpoint per session (`<id>.json`) in a single per-user directory, so sessions started in
+/// different repositories can be listed, resumed and deleted from anywhere.
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where the checkpoint of the session with `id` is kept. Fails for an id that is not a valid
+    /// session id, e.g. one with path separators from a tampered checkpoint.
+    pub fn path(&self, id: &str) -> Result<PathBuf, AgentError> {
+        if !is_valid_session_id(id) {
+            return Err(AgentError::SessionError(format!("'{}' is not a valid session id: use letters, digits, '-' and '_' only", id)));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+
+    /// Where `state` is checkpointed: `path` for its id, unless a session of another directory is
+    /// already kept under that id, which is never overwritten.
+    pub fn checkpoint_path(&self, state: &AppState) -> Result<PathBuf, AgentError> {
+        let path = self.path(&state.id)?;
+        match AppState::load(&path) {
+            Ok(stored) if stored.workdir.is_some() && stored.workdir != state.workdir => Err(AgentError::SessionError(format!(
+                "Session {} is already kept for {}; not overwriting it with a session of {}",
+                state.id,
+                stored.workdir.as_deref().unwrap_or(Path::new("-")).display(),
+                state.workdir.as_deref().unwrap_or(Path::new("-")).display()
+            ))),
+            _ => Ok(path),
+        }
+    }
+
+    /// Removes all but the `keep` most recently saved sessions and returns the ones it removed.
+    pub fn prune(&self, keep: usize) -> Result<Vec<StoredSession>, AgentError> {
+        let removed: Vec<StoredSession> = self.list()?.into_iter().skip(keep).collect();
+        for session in &removed {
+            std::fs::remove_file(&session.path)?;
+        }
+        Ok(removed)
+    }
+
+    /// Every stored session, most recently saved first. A missing directory means no sessions yet;
+    /// files that are not session checkpoints are skipped.
+    pub fn list(&self) -> Result<Vec<StoredSession>, AgentError> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut sessions = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let Ok(state) = AppState::load(&path) else { continue };
+            let updated_at = std::fs::metadata(&path)?.modified()?.into();
+            sessions.push(StoredSession { state, updated_at, path });
+        }
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+        Ok(sessions)
+    }
+
+    /// The session `query` refers to: by its id, its name, or the start of its id. Fails when no
+    /// session, or more than one, matches.
+    pub fn find(&self, query: &str) -> Result<StoredSession, AgentError> {
+        let sessions = self.list()?;
+        if let Some(session) = sessions.iter().find(|session| session.state.id == query) {
+            return Ok(session.clone());
+        }
+        let mut matches: Vec<StoredSession> = sessions.iter().filter(|session| session.state.name.as_deref() == Some(query)).cloned().collect();
+        if matches.is_empty() {
+            matches = sessions.into_iter().filter(|session| session.state.id.starts_with(query)).collect();
+        }
+        match matches.len() {
+            0 => Err(AgentError::SessionError(format!("No session matches '{}'; `sessions list` shows them all", query))),
+            1 => Ok(matches.remove(0)),
+            _ => {
+                let ids: Vec<&str> = matches.iter().map(|session| session.state.id.as_str()).collect();
+                Err(AgentError::SessionError(format!("'{}' matches several sessions ({}); give the full id", query, ids.join(", "))))
+            }
+        }
+    }
+
+    /// Removes the checkpoint of the session `query` refers to (see `find`) and returns the session.
+    pub fn delete(&self, query: &str) -> Result<StoredSession, AgentError> {
+        let session = self.find(query)?;
+        std::fs::remove_file(&session.path)?;
+        Ok(session)
+    }
+}
+
+/// Renders `sessions` as the table shown by `sessions list`.
+pub fn table(sessions: &[StoredSession]) -> String {
+    if sessions.is_empty() {
+        return "No sessions saved yet.".to_string();
+    }
+    let mut out = String::new();
+    let _ = writeln!(out, "{:<22} {:<16} {:<12} {:<17} {:<30} Goal", "Id", "Name", "Progress", "Updated", "Directory");
+    for session in sessions {
+        let state = &session.state;
+        let workdir = state.workdir.as_ref().map(|dir| dir.display().to_string()).unwrap_or_else(|| "-".to_string());
+        let _ = writeln!(
+            out,
+            "{:<22} {:<16} {:<12} {:<17} {:<30} {}",
+            state.id,
+            state.name.as_deref().unwrap_or("-"),
+            session.progress(),
+            session.updated_at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            workdir,
+            goal_headline(&state.goal)
+        );
+    }
+    out.trim_end().to_string()
+}
+
+/// Renders the details shown by `sessions show`: where the session runs, its plan with the steps
+/// done so far, the files it changed and its latest history.
+pub fn describe(session: &StoredSession) -> String {
+    let state = &session.state;
+    let mut out = String::new();
+    let _ = writeln!(out, "Session:    {}", state.id);
+    if let Some(name) = &state.name {
+        let _ = writeln!(out, "Name:       {}", name);
+    }
+    let _ = writeln!(out, "Goal:       {}", goal_headline(&state.goal));
+    let _ = writeln!(out, "Progress:   {}", session.progress());
+    let _ = writeln!(out, "Updated:    {}", session.updated_at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"));
+    if let Some(workdir) = &state.workdir {
+        let _ = writeln!(out, "Directory:  {}", workdir.display());
+    }
+    if let Some(transcript) = &state.transcript {
+        let _ = writeln!(out, "Transcript: {}", transcript.display());
+    }
+    let _ = writeln!(out, "Checkpoint: {}", session.path.display());
+    if !state.plan.is_empty() {
+        let _ = writeln!(out, "\nPlan:");
+        for (i, step) in state.plan.iter().enumerate() {
+            let mark = if i < state.current_step { "✓" } else { " " };
+            let _ = writeln!(out, "  [{}] {}. {}", mark, i + 1, step);
+        }
+    }
+    if !state.files_modified.is_empty() {
+        let _ = writeln!(out, "\nFiles changed:");
+        for file in &state.files_modified {
+            let _ = writeln!(out, "  {}", file);
+        }
+    }
+    let recent = &state.history[state.history.len().saturating_sub(RECENT_HISTORY_ENTRIES)..];
+    if !recent.is_empty() {
+        let _ = writeln!(out, "\nLatest history:");
+        for (kind, content) in recent {
+            let content = content.trim().replace('\n', " ");
+            let content = match content.char_indices().nth(HISTORY_PREVIEW_CHARS) {
+                Some((cut, _)) => format!("{}…", &content[..cut]),
+                None => content,
+            };
+            let _ = writeln!(out, "  [{}] {}", kind, content);
+        }
+    }
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save(store: &SessionStore, id: &str, name: Option<&str>, steps_done: usize) -> AppState {
+        let mut state = AppState::new(format!("Goal of {}", id));
+        state.id = id.to_string();
+        state.name = name.map(str::to_string);
+        state.workdir = Some(PathBuf::from("/srv/repos/api"));
+        state.plan = vec!["Write the code".to_string(), "Run the tests".to_string()];
+        state.current_step = steps_done;
+        state.save(&store.path(id).unwrap()).unwrap();
+        state
+    }
+
+    #[test]
+    fn test_find_matches_ids_names_and_id_prefixes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().join("sessions"));
+        assert!(store.list().unwrap().is_empty());
+        save(&store, "20240501-142233", Some("health-route"), 0);
+        save(&store, "20240501-150000", None, 2);
+        save(&store, "20240602-090000", None, 1);
+        std::fs::write(store.path("broken").unwrap(), "not a session").unwrap();
+
+        assert_eq!(store.list().unwrap().len(), 3);
+        assert_eq!(store.find("20240501-150000").unwrap().progress(), "done");
+        assert_eq!(store.find("health-route").unwrap().state.id, "20240501-142233");
+        assert_eq!(store.find("202406").unwrap().progress(), "step 2/2");
+        assert!(store.find("20240501").unwrap_err().to_string().contains("several sessions"));
+        assert!(matches!(store.find("missing"), Err(AgentError::SessionError(_))));
+
+        store.delete("health-route").unwrap();
+        assert!(!store.path("20240501-142233").unwrap().exists());
+        assert_eq!(store.find("20240501").unwrap().state.id, "20240501-150000");
+    }
+
+    #[test]
+    fn test_table_and_describe_show_where_sessions_stand() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path());
+        assert_eq!(table(&[]), "No sessions saved yet.");
+        let mut state = save(&store, "20240501-142233", Some("health-route"), 1);
+        state.add_history("Tool Success", "cargo build\nFinished");
+        state.files_modified.push("src/routes.rs".to_string());
+        state.save(&store.path(&state.id).unwrap()).unwrap();
+
+        let sessions = store.list().unwrap();
+        let table = table(&sessions);
+        assert!(table.contains("health-route"));
+        assert!(table.contains("step 2/2"));
+        assert!(table.contains("/srv/repos/api"));
+        let details = describe(&sessions[0]);
+        assert!(details.contains("  [✓] 1. Write the code\n  [ ] 2. Run the tests"));
+        assert!(details.contains("Files changed:\n  src/routes.rs"));
+        assert!(details.contains("[Tool Success] cargo build Finished"));
+    }
+
+    #[test]
+    fn test_store_refuses_bad_ids_and_other_directories_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path());
+        for id in ["../../.bashrc", "a/b", "", "id with spaces"] {
+            assert!(matches!(store.path(id), Err(AgentError::SessionError(_))), "{}", id);
+        }
+
+        // Two sessions started back to back get different ids.
+        let first = AppState::new("First".to_string());
+        let mut second = AppState::new("Second".to_string());
+        assert_ne!(first.id, second.id);
+        assert!(is_valid_session_id(&first.id));
+
+        let stored = save(&store, &second.id.clone(), None, 0);
+        second.workdir = Some(PathBuf::from("/srv/repos/web"));
+        assert!(store.checkpoint_path(&second).unwrap_err().to_string().contains("already kept for /srv/repos/api"));
+        assert_eq!(store.checkpoint_path(&stored).unwrap(), store.path(&stored.id).unwrap());
+        assert_eq!(store.checkpoint_path(&first).unwrap(), store.path(&first.id).unwrap());
+    }
+
+    #[test]
+    fn test_prune_keeps_the_most_recent_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path());
+        for (id, age) in [("old", 300), ("older", 600), ("new", 0)] {
+            save(&store, id, None, 0);
+            let file = std::fs::File::options().write(true).open(store.path(id).unwrap()).unwrap();
+            file.set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(age)).unwrap();
+        }
+        let removed: Vec<String> = store.prune(2).unwrap().into_iter().map(|session| session.state.id).collect();
+        assert_eq!(removed, ["older"]);
+        let kept: Vec<String> = store.list().unwrap().into_iter().map(|session| session.state.id).collect();
+        assert_eq!(kept, ["new", "old"]);
+    }
+}
@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::tools::unified_diff;
+
+/// Files larger than this are hashed but not diffed, so their changes are listed without a diff.
+const MAX_DIFFED_BYTES: usize = 256 * 1024;
+/// Files larger than this are not tracked, so a huge build artifact cannot stall every step.
+const MAX_HASHED_BYTES: u64 = 32 * 1024 * 1024;
+/// Files beyond this many are not tracked, so a huge working directory cannot stall every step.
+const MAX_FILES: usize = 20_000;
+
+/// The SHA-256 of every file in a working directory at one moment, taken before and after each
+/// step so the transcript records which step changed which file. Hidden files (`.agent/`,
+/// `.git/`, `.env`) and files git ignores are left out unless added with `include`. Only the
+/// hashes are kept, not the text.
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceSnapshot {
+    root: PathBuf,
+    files: BTreeMap<String, FileState>,
+}
+
+/// A tracked file's hash, with the size and modification time it was hashed at.
+#[derive(Debug, Clone, PartialEq)]
+struct FileState {
+    hash: String,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl WorkspaceSnapshot {
+    /// Hashes the files under `root`, with paths relative to it. Files whose size and modification
+    /// time are the ones `previous` recorded keep its hash instead of being read again.
+    pub fn capture(root: &Path, previous: Option<&WorkspaceSnapshot>) -> Self {
+        let mut files = BTreeMap::new();
+        let entries = ignore::WalkBuilder::new(root).build().filter_map(Result::ok).filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()));
+        for entry in entries.take(MAX_FILES) {
+            let Ok(relative) = entry.path().strip_prefix(root) else { continue };
+            let Ok(metadata) = entry.metadata() else { continue };
+            let path = relative.to_string_lossy().replace('\\', "/");
+            let known = previous.and_then(|previous| previous.files.get(&path)).filter(|state| state.len == metadata.len() && state.modified.is_some() && state.modified == metadata.modified().ok());
+            let state = match known {
+                Some(state) => Some(state.clone()),
+                None => hash_file(entry.path(), &metadata),
+            };
+            if let Some(state) = state {
+                files.insert(path, state);
+            }
+        }
+        Self { root: root.to_path_buf(), files }
+    }
+
+    /// Tracks `path` (absolute, or relative to the root) too, even if it is hidden or ignored, as
+    /// it is now; a file that does not exist yet is left out. Files outside the root and files
+    /// already tracked are left as they are.
+    pub fn include(&mut self, path: &Path) {
+        let absolute = self.root.join(path);
+        let Ok(relative) = absolute.strip_prefix(&self.root) else { return };
+        let parts: Vec<String> = relative.components().filter(|part| !matches!(part, Component::CurDir)).map(|part| part.as_os_str().to_string_lossy().into_owned()).collect();
+        let key = parts.join("/");
+        if key.is_empty() || parts.iter().any(|part| part == "..") || self.files.contains_key(&key) {
+            return;
+        }
+        if let Some(state) = std::fs::metadata(&absolute).ok().filter(|metadata| metadata.is_file()).and_then(|metadata| hash_file(&absolute, &metadata)) {
+            self.files.insert(key, state);
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// What changed between `before` and this snapshot, by path, with a diff where both texts are
+    /// known. The new text is read from disk; the earlier one comes from `texts` (the files earlier
+    /// changes left behind), from `original` (e.g. the journal) or from git's `HEAD`, and is only
+    /// used when its hash is the one `before` recorded. `texts` is then updated with the text of
+    /// the files changed now, so a later change to them can be diffed too.
+    pub fn changes_since(&self, before: &WorkspaceSnapshot, texts: &mut BTreeMap<String, String>, original: &dyn Fn(&str) -> Option<String>) -> Vec<FileChange> {
+        let paths: BTreeSet<&String> = before.files.keys().chain(self.files.keys()).collect();
+        let mut changes = Vec::new();
+        for path in paths {
+            let (old, new) = (before.files.get(path).map(|state| &state.hash), self.files.get(path).map(|state| &state.hash));
+            let kind = match (old, new) {
+                (None, Some(_)) => ChangeKind::Created,
+                (Some(_), None) => ChangeKind::Deleted,
+                (Some(old), Some(new)) if old != new => ChangeKind::Modified,
+                _ => continue,
+            };
+            let old_text = match old {
+                Some(hash) => [texts.remove(path), original(path), committed_text(&self.root, path)].into_iter().flatten().find(|text| sha256(text.as_bytes()) == *hash),
+                None => Some(String::new()),
+            };
+            let new_text = match new {
+                Some(hash) => std::fs::read(self.root.join(path)).ok().filter(|bytes| sha256(bytes) == *hash).and_then(|bytes| String::from_utf8(bytes).ok()),
+                None => Some(String::new()),
+            };
+            let diff = match (&old_text, &new_text) {
+                (Some(old_text), Some(new_text)) if old_text.len().max(new_text.len()) <= MAX_DIFFED_BYTES => Some(unified_diff(path, old_text, new_text)),
+                _ => None,
+            };
+            if let (Some(_), Some(text)) = (new, new_text.filter(|text| text.len() <= MAX_DIFFED_BYTES)) {
+                texts.insert(path.clone(), text);
+            }
+            changes.push(FileChange { path: path.clone(), kind, before: old.cloned(), after: new.cloned(), diff });
+        }
+        changes
+    }
+}
+
+/// The text `path` (relative to `root`) has in git's `HEAD`, if `root` is in a repository that
+/// has it.
+fn committed_text(root: &Path, path: &str) -> Option<String> {
+    let output = std::process::Command::new("git").args(["show", &format!("HEAD:./{}", path)]).current_dir(root).stderr(std::process::Stdio::null()).output().ok()?;
+    output.status.success().then(|| String::from_utf8(output.stdout).ok()).flatten()
+}
+
+/// The state of the file at `path` with `metadata`, unless it is too large or cannot be read.
+fn hash_file(path: &Path, metadata: &std::fs::Metadata) -> Option<FileState> {
+    if metadata.len() > MAX_HASHED_BYTES {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    Some(FileState { hash: sha256(&bytes), len: bytes.len() as u64, modified: metadata.modified().ok() })
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// One file a step changed, with the SHA-256 of its content before and after (`None` where it
+/// did not exist) and the unified diff, unless the file is binary, too large, or its earlier
+/// text is not known.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    #[serde(default)]
+    pub diff: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_since_lists_created_modified_and_deleted_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), "one\ntwo\n").unwrap();
+        std::fs::write(dir.path().join("old.txt"), "gone\n").unwrap();
+        std::fs::write(dir.path().join("same.txt"), "same\n").unwrap();
+        let before = WorkspaceSnapshot::capture(dir.path(), None);
+
+        std::fs::write(dir.path().join("src/lib.rs"), "one\n2\n").unwrap();
+        std::fs::remove_file(dir.path().join("old.txt")).unwrap();
+        std::fs::write(dir.path().join("new.bin"), [0xff, 0xfe, 0x00]).unwrap();
+        std::fs::create_dir(dir.path().join(".agent")).unwrap();
+        std::fs::write(dir.path().join(".agent/session.json"), "{}").unwrap();
+        let after = WorkspaceSnapshot::capture(dir.path(), None);
+        let mut texts = BTreeMap::new();
+        // Only the original of src/lib.rs is known, and a stale one is ignored.
+        let original = |path: &str| match path {
+            "src/lib.rs" => Some("one\ntwo\n".to_string()),
+            "old.txt" => Some("stale\n".to_string()),
+            _ => None,
+        };
+        let changes = after.changes_since(&before, &mut texts, &original);
+
+        let summary: Vec<(&str, ChangeKind)> = changes.iter().map(|change| (change.path.as_str(), change.kind)).collect();
+        assert_eq!(summary, [("new.bin", ChangeKind::Created), ("old.txt", ChangeKind::Deleted), ("src/lib.rs", ChangeKind::Modified)]);
+        assert_eq!(changes[0].before, None);
+        assert_eq!(changes[0].diff, None);
+        assert_eq!(changes[1].diff, None);
+        assert!(changes[2].diff.as_deref().unwrap().contains("+2"));
+        assert_ne!(changes[2].before, changes[2].after);
+        assert_eq!(changes[2].after.as_deref(), Some(sha256(b"one\n2\n").as_str()));
+
+        // The text the last change left is enough to diff the next one.
+        std::fs::write(dir.path().join("src/lib.rs"), "one\n3\n").unwrap();
+        let changes = WorkspaceSnapshot::capture(dir.path(), None).changes_since(&after, &mut texts, &|_| None);
+        assert!(changes[0].diff.as_deref().unwrap().contains("-2\n+3"), "{:?}", changes);
+    }
+
+    #[test]
+    fn test_capture_includes_touched_hidden_files_and_skips_unchanged_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".env"), "KEY=1\n").unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "one\n").unwrap();
+        let mut before = WorkspaceSnapshot::capture(dir.path(), None);
+        before.include(Path::new(".env"));
+        before.include(Path::new("missing.rs"));
+        before.include(Path::new("../outside.rs"));
+        assert_eq!(before.files.keys().collect::<Vec<_>>(), [".env", "lib.rs"]);
+
+        std::fs::write(dir.path().join(".env"), "KEY=2\n").unwrap();
+        let mut after = WorkspaceSnapshot::capture(dir.path(), Some(&before));
+        after.include(&dir.path().join("./.env"));
+        let changes = after.changes_since(&before, &mut BTreeMap::new(), &|_| None);
+        assert_eq!(changes.iter().map(|change| change.path.as_str()).collect::<Vec<_>>(), [".env"]);
+
+        // A file with the size and modification time recorded before is not read again.
+        let modified = std::fs::metadata(dir.path().join("lib.rs")).unwrap().modified().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "two\n").unwrap();
+        std::fs::File::options().write(true).open(dir.path().join("lib.rs")).unwrap().set_modified(modified).unwrap();
+        assert_eq!(WorkspaceSnapshot::capture(dir.path(), Some(&before)).files["lib.rs"], before.files["lib.rs"]);
+        assert_ne!(WorkspaceSnapshot::capture(dir.path(), None).files["lib.rs"], before.files["lib.rs"]);
+    }
+
+    #[test]
+    fn test_file_change_serializes_for_the_transcript() {
+        let change = FileChange { path: "a.rs".to_string(), kind: ChangeKind::Created, before: None, after: Some(sha256(b"")), diff: None };
+        let json = serde_json::to_value(&change).unwrap();
+        assert_eq!(json["kind"], "created");
+        assert_eq!(json["after"], "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(serde_json::from_value::<FileChange>(json).unwrap(), change);
+    }
+}
@@ -1,32 +1,149 @@
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::context::{ContextBuilder, KEEP_RECENT_ENTRIES};
+use crate::error::AgentError;
+use crate::project::{ProjectInstructions, ProjectLanguage};
+
+/// Where the orchestrator checkpoints the session after every step.
+pub const SESSION_FILE: &str = ".agent/session.json";
+
+/// Characters of a goal's first line shown where the goal is listed or echoed.
+pub const GOAL_HEADLINE_CHARS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppState {
+    /// Identifies the session in messages such as the resume hint after a cancelled run.
+    #[serde(default = "new_session_id")]
+    pub id: String,
+    /// Name given with `run --name`, accepted wherever the id is, e.g. by `sessions resume`.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Directory the session works in, so `sessions resume` continues it there from anywhere.
+    #[serde(default)]
+    pub workdir: Option<PathBuf>,
+    /// The `--transcript` file the session's events were written to.
+    #[serde(default)]
+    pub transcript: Option<PathBuf>,
     pub goal: String,
     pub plan: Vec<String>,
     pub history: Vec<(String, String)>,
     pub current_step: usize,
+    /// Summary of history entries that were compacted away to stay within the context budget.
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Project instructions (e.g. `AGENTS.md`) included in every agent's context.
+    #[serde(default)]
+    pub instructions: Option<ProjectInstructions>,
+    /// Language detected from the project's manifest files; the coder writes this by default.
+    #[serde(default)]
+    pub language: Option<ProjectLanguage>,
+    /// Files written or edited by this goal, in the order they were first changed.
+    #[serde(default)]
+    pub files_modified: Vec<String>,
+    /// What earlier goals of the same interactive session did, so follow-up goals have context.
+    #[serde(default)]
+    pub conversation: Option<String>,
+    /// Images shown with every decision prompt, as their paths were written in the goal or by
+    /// `ReadImage`; oldest first.
+    #[serde(default)]
+    pub images: Vec<String>,
+    /// Files whose current content is part of every planner, decision and coder context.
+    #[serde(default)]
+    pub pinned_files: Vec<PinnedFile>,
+}
+
+/// A file the user pinned, with its content as last read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinnedFile {
+    pub path: String,
+    /// `None` until read, or when the file cannot be read. Not checkpointed: the orchestrator
+    /// re-reads pinned files before planning, before every step and after every file change.
+    #[serde(skip)]
+    pub content: Option<String>,
+}
+
+/// The first non-empty line of `goal`, shortened to `GOAL_HEADLINE_CHARS`, with a count of the lines
+/// left out, so a long pasted goal (with code blocks, logs) takes one line where goals are listed.
+pub fn goal_headline(goal: &str) -> String {
+    let mut lines = goal.trim().lines();
+    let first = lines.next().unwrap_or_default().trim();
+    let mut headline = match first.char_indices().nth(GOAL_HEADLINE_CHARS) {
+        Some((cut, _)) => format!("{}…", &first[..cut]),
+        None => first.to_string(),
+    };
+    let more = lines.count();
+    if more > 0 {
+        headline.push_str(&format!(" (+{} line{})", more, if more == 1 { "" } else { "s" }));
+    }
+    headline
+}
+
+/// A session id from the time the session started and a random suffix, e.g.
+/// `20240501-142233-3fa9c1`, so sessions started in the same second get different ids.
+fn new_session_id() -> String {
+    use std::hash::BuildHasher;
+    let now = chrono::Utc::now();
+    // `RandomState` is seeded randomly per process; the time and pid tell apart ids within one.
+    let random = std::collections::hash_map::RandomState::new().hash_one((now.timestamp_nanos_opt(), std::process::id()));
+    format!("{}-{:06x}", now.format("%Y%m%d-%H%M%S"), random & 0xff_ffff)
+}
+
+/// Whether `id` can name a session: letters, digits, `-` and `_` only, so a checkpoint's id can
+/// be used as a file name safely.
+pub fn is_valid_session_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
 }
 
 impl AppState {
     pub fn new(goal: String) -> Self {
-        Self { goal, plan: Vec::new(), history: Vec::new(), current_step: 0 }
+        Self { id: new_session_id(), name: None, workdir: None, transcript: None, goal, plan: Vec::new(), history: Vec::new(), current_step: 0, summary: None, instructions: None, language: None, files_modified: Vec::new(), conversation: None, images: Vec::new(), pinned_files: Vec::new() }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AgentError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, AgentError> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// True once every step of a non-empty plan has run.
+    pub fn is_complete(&self) -> bool {
+        !self.plan.is_empty() && self.current_step >= self.plan.len()
     }
 
     pub fn add_history(&mut self, entry_type: &str, content: &str) {
         self.history.push((entry_type.to_string(), content.to_string()));
     }
 
-    pub fn get_context(&self) -> String {
-        let mut context = format!("The overall goal is: {}\n", self.goal);
-        context.push_str("\n--- History & Context ---\n");
-        if self.history.is_empty() {
-            context.push_str("No actions have been taken yet.\n");
-        } else {
-            for (entry_type, content) in &self.history {
-                let summarized = if content.len() > 500 { format!("{}...", &content[..500]) } else { content.clone() };
-                context.push_str(&format!("[{}]\n{}\n---\n", entry_type, summarized));
-            }
+    /// History entries that `compact` would fold into the summary, oldest first.
+    pub fn compactable_history(&self) -> &[(String, String)] {
+        &self.history[..self.history.len().saturating_sub(KEEP_RECENT_ENTRIES)]
+    }
+
+    /// Replaces the compactable history with `summary`, keeping the most recent entries verbatim.
+    pub fn compact(&mut self, summary: String) {
+        let count = self.compactable_history().len();
+        self.history.drain(..count);
+        self.summary = Some(summary);
+    }
+
+    /// Pins `path`, unless it is pinned already; its content is read on the next refresh.
+    pub fn pin(&mut self, path: &str) {
+        if !self.pinned_files.iter().any(|file| file.path == path) {
+            self.pinned_files.push(PinnedFile { path: path.to_string(), content: None });
         }
-        context
+    }
+
+    /// The context for agents, built within the default token budget.
+    pub fn get_context(&self) -> String {
+        ContextBuilder::default().build(self)
     }
 }
 
@@ -34,6 +151,14 @@ impl AppState {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_goal_headline_keeps_long_goals_to_one_line() {
+        assert_eq!(goal_headline("  Add a /health route\n"), "Add a /health route");
+        assert_eq!(goal_headline("Make this compile:\n```rust\nfn main() {}\n```"), "Make this compile: (+3 lines)");
+        let long = "x".repeat(GOAL_HEADLINE_CHARS + 10);
+        assert_eq!(goal_headline(&long), format!("{}…", "x".repeat(GOAL_HEADLINE_CHARS)));
+    }
+
     #[test]
     fn test_new_state() {
         let goal = "Test goal".to_string();
@@ -87,17 +212,32 @@ mod tests {
     #[test]
     fn test_get_context_with_long_content() {
         let mut state = AppState::new("Test goal".to_string());
-        let long_content = "a".repeat(600); // Content longer than 500 chars
+        let long_content = "a".repeat(40_000); // Far more than a quarter of the token budget
         state.add_history("LongContent", &long_content);
 
         let context = state.get_context();
 
         assert!(context.contains("[LongContent]"));
-        assert!(context.contains(&"a".repeat(500))); // Should be truncated to 500 chars
+        assert!(context.contains(&"a".repeat(500)));
         assert!(context.contains("...")); // Should have ellipsis
         assert!(!context.contains(&long_content)); // Should not contain full content
     }
 
+    #[test]
+    fn test_compact_keeps_recent_entries() {
+        let mut state = AppState::new("Test goal".to_string());
+        for i in 0..5 {
+            state.add_history("Tool", &format!("output {}", i));
+        }
+        assert_eq!(state.compactable_history().len(), 5 - KEEP_RECENT_ENTRIES);
+
+        state.compact("Ran five tools".to_string());
+        assert_eq!(state.history.len(), KEEP_RECENT_ENTRIES);
+        assert_eq!(state.history[0].1, format!("output {}", 5 - KEEP_RECENT_ENTRIES));
+        assert_eq!(state.summary.as_deref(), Some("Ran five tools"));
+        assert!(state.get_context().contains("Ran five tools"));
+    }
+
     #[test]
     fn test_state_debug() {
         let state = AppState::new("Test goal".to_string());
@@ -107,6 +247,7 @@ mod tests {
         assert!(debug_str.contains("plan"));
         assert!(debug_str.contains("history"));
         assert!(debug_str.contains("current_step"));
+        assert!(debug_str.contains("summary"));
     }
 
     #[test]
@@ -122,6 +263,42 @@ mod tests {
         assert_eq!(state.current_step, 1);
     }
 
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".agent").join("session.json");
+
+        let mut state = AppState::new("Persist me".to_string());
+        state.plan = vec!["Step 1".to_string(), "Step 2".to_string()];
+        state.add_history("Tool Output", "done");
+        state.current_step = 1;
+        state.save(&path).unwrap();
+
+        let loaded = AppState::load(&path).unwrap();
+        assert_eq!(loaded.goal, "Persist me");
+        assert_eq!(loaded.plan, state.plan);
+        assert_eq!(loaded.history, state.history);
+        assert_eq!(loaded.current_step, 1);
+        assert!(!loaded.is_complete());
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = AppState::load(&dir.path().join("missing.json"));
+        assert!(matches!(result, Err(AgentError::IoError(_))));
+    }
+
+    #[test]
+    fn test_is_complete() {
+        let mut state = AppState::new("Goal".to_string());
+        assert!(!state.is_complete());
+        state.plan = vec!["Only step".to_string()];
+        assert!(!state.is_complete());
+        state.current_step = 1;
+        assert!(state.is_complete());
+    }
+
     #[test]
     fn test_multiple_history_entries() {
         let mut state = AppState::new("Complex goal".to_string());
@@ -0,0 +1,143 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::cost_tracker::AgentUsage;
+use crate::error::AgentError;
+
+/// Usage of one goal run, appended to the stats file when the run ends.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionStats {
+    pub finished_at: DateTime<Utc>,
+    pub goal: String,
+    pub success: bool,
+    pub steps: usize,
+    pub total_cost: f64,
+    pub agents: BTreeMap<String, AgentUsage>,
+}
+
+impl SessionStats {
+    pub fn total_tokens(&self) -> u64 {
+        self.agents.values().map(|usage| usage.input_tokens + usage.output_tokens).sum()
+    }
+}
+
+/// Where session stats are kept: `stats.jsonl` in `data_dir()`.
+pub fn default_stats_path() -> Option<PathBuf> {
+    Some(data_dir()?.join("stats.jsonl"))
+}
+
+/// The agent's per-user data directory: `$XDG_DATA_HOME/rust-cli-agent`, falling back to
+/// `~/.local/share` (or `%LOCALAPPDATA%` on Windows).
+pub fn data_dir() -> Option<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(data_dir.join("rust-cli-agent"))
+}
+
+/// Appends `stats` as one JSON line, creating the file and its directory if needed.
+pub fn append(path: &Path, stats: &SessionStats) -> Result<(), AgentError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(stats)?)?;
+    Ok(())
+}
+
+/// Reads every recorded session; a missing file means no sessions yet. Unreadable lines (e.g.
+/// from an interrupted write) are skipped.
+pub fn load(path: &Path) -> Result<Vec<SessionStats>, AgentError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(path)?;
+    Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+/// Renders the totals across `sessions` as the table shown by the `stats` subcommand.
+pub fn summarize(sessions: &[SessionStats]) -> String {
+    if sessions.is_empty() {
+        return "No sessions recorded yet.".to_string();
+    }
+    let succeeded = sessions.iter().filter(|s| s.success).count();
+    let steps: usize = sessions.iter().map(|s| s.steps).sum();
+    let tokens: u64 = sessions.iter().map(SessionStats::total_tokens).sum();
+    let cost: f64 = sessions.iter().map(|s| s.total_cost).sum();
+    let mut agents: BTreeMap<&str, AgentUsage> = BTreeMap::new();
+    for session in sessions {
+        for (name, usage) in &session.agents {
+            agents.entry(name).or_default().merge(usage);
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "Sessions: {} ({} succeeded)", sessions.len(), succeeded);
+    if let (Some(first), Some(last)) = (sessions.first(), sessions.last()) {
+        let _ = writeln!(out, "Period:   {} to {}", first.finished_at.format("%Y-%m-%d"), last.finished_at.format("%Y-%m-%d"));
+    }
+    let _ = writeln!(out, "Cost:     ${:.4}", cost);
+    let _ = writeln!(out, "Tokens:   {} ({} per step over {} steps)", tokens, tokens.checked_div(steps as u64).unwrap_or(0), steps);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "{:<12} {:>7} {:>12} {:>12} {:>10} {:>14}", "Agent", "Calls", "Prompt", "Completion", "Cost", "Avg latency");
+    for (name, usage) in &agents {
+        let _ = writeln!(
+            out,
+            "{:<12} {:>7} {:>12} {:>12} {:>10} {:>11} ms",
+            name,
+            usage.calls,
+            usage.input_tokens,
+            usage.output_tokens,
+            format!("${:.4}", usage.cost),
+            usage.average_latency_ms()
+        );
+    }
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(success: bool, steps: usize, coder_calls: u64) -> SessionStats {
+        let mut agents = BTreeMap::new();
+        agents.insert(
+            "coder".to_string(),
+            AgentUsage { calls: coder_calls, input_tokens: 1000, output_tokens: 500, reasoning_tokens: 0, cost: 0.02, total_latency_ms: 900 * coder_calls },
+        );
+        SessionStats { finished_at: Utc::now(), goal: "Goal".to_string(), success, steps, total_cost: 0.02, agents }
+    }
+
+    #[test]
+    fn test_append_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("stats.jsonl");
+        assert!(load(&path).unwrap().is_empty());
+
+        append(&path, &session(true, 3, 2)).unwrap();
+        append(&path, &session(false, 1, 1)).unwrap();
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"truncated\n").unwrap();
+
+        let sessions = load(&path).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].agents["coder"].calls, 2);
+        assert!(!sessions[1].success);
+    }
+
+    #[test]
+    fn test_summarize_aggregates_sessions() {
+        assert_eq!(summarize(&[]), "No sessions recorded yet.");
+
+        let summary = summarize(&[session(true, 3, 2), session(false, 1, 1)]);
+        assert!(summary.contains("Sessions: 2 (1 succeeded)"));
+        assert!(summary.contains("Tokens:   3000 (750 per step over 4 steps)"));
+        assert!(summary.contains("$0.0400"));
+        let coder = summary.lines().find(|line| line.starts_with("coder")).unwrap();
+        assert!(coder.contains("  3 ") && coder.ends_with("900 ms"));
+    }
+}
@@ -0,0 +1,455 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+
+use crate::config::AppConfig;
+use crate::error::AgentError;
+use crate::events::{AgentEvent, EventRecord, EventSink};
+use crate::llm::{AIResponse, ChatMessage, GenerationParams, LLMClient, ModelInfo, OnText, ToolCall, ToolDefinition};
+
+/// Finished spans are sent once this many are waiting, besides the `flush` after every run.
+const EXPORT_BATCH_SIZE: usize = 256;
+
+/// A value of a span attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    Text(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+/// A finished span, ready to be exported.
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub parent_span_id: Option<u64>,
+    pub name: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub attributes: Vec<(String, AttributeValue)>,
+    /// Marks the span as failed, with this message.
+    pub error: Option<String>,
+}
+
+/// A span that has started but not yet ended.
+#[derive(Debug, Clone)]
+struct OpenSpan {
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    name: String,
+    start: DateTime<Utc>,
+    attributes: Vec<(String, AttributeValue)>,
+    error: Option<String>,
+}
+
+impl OpenSpan {
+    fn end(self, trace_id: u128, end: DateTime<Utc>) -> Span {
+        Span {
+            trace_id,
+            span_id: self.span_id,
+            parent_span_id: self.parent_span_id,
+            name: self.name,
+            start: self.start,
+            end,
+            attributes: self.attributes,
+            error: self.error,
+        }
+    }
+}
+
+#[derive(Default)]
+struct TraceState {
+    trace_id: u128,
+    run: Option<OpenSpan>,
+    step: Option<OpenSpan>,
+    tool: Option<OpenSpan>,
+    finished: Vec<Span>,
+}
+
+impl TraceState {
+    /// The span new spans are children of: the current step, else the current run.
+    fn parent(&self) -> Option<u64> {
+        self.step.as_ref().or(self.run.as_ref()).map(|span| span.span_id)
+    }
+
+    /// Opens the run span if none is open, starting a new trace.
+    fn ensure_run(&mut self, at: DateTime<Utc>) {
+        if self.run.is_none() {
+            self.trace_id = (u128::from(random_id()) << 64) | u128::from(random_id());
+            self.run = Some(open("agent.run", None, at));
+        }
+    }
+
+    fn finish(&mut self, span: OpenSpan, at: DateTime<Utc>) {
+        self.finished.push(span.end(self.trace_id, at));
+    }
+}
+
+/// Exports OpenTelemetry spans over OTLP/HTTP (JSON encoding) to a collector such as the
+/// OpenTelemetry Collector, Jaeger or Grafana Tempo. Each run is one trace: an `agent.run` span
+/// with an `agent.step` span per step, and under those a span per tool execution and per LLM
+/// call made through a client wrapped with `traced`.
+///
+/// The tracer is an `EventSink`, so it follows a run by being subscribed to its events, e.g. with
+/// `AgentBuilder::event_sink`. Spans are batched; call `flush` when a run ends.
+pub struct Tracer {
+    endpoint: String,
+    service_name: String,
+    client: reqwest::Client,
+    state: Mutex<TraceState>,
+}
+
+impl Tracer {
+    /// A tracer sending to the collector at `endpoint`, e.g. `http://localhost:4318`; spans are
+    /// posted to its `/v1/traces` path.
+    pub fn new(endpoint: &str, service_name: &str) -> Self {
+        Self {
+            endpoint: format!("{}/v1/traces", endpoint.trim_end_matches('/').trim_end_matches("/v1/traces")),
+            service_name: service_name.to_string(),
+            client: reqwest::Client::new(),
+            state: Mutex::new(TraceState::default()),
+        }
+    }
+
+    /// A tracer for the configured `OTEL_EXPORTER_OTLP_ENDPOINT`, or `None` when it is not set.
+    pub fn from_config(config: &AppConfig) -> Option<Arc<Self>> {
+        let endpoint = config.otel_endpoint.as_deref().filter(|endpoint| !endpoint.trim().is_empty())?;
+        Some(Arc::new(Self::new(endpoint, &config.otel_service_name)))
+    }
+
+    /// Records one LLM call made by `role` that started at `start`.
+    pub fn record_llm_call(&self, role: &str, model: &str, start: DateTime<Utc>, result: Result<&AIResponse, &AgentError>) {
+        let end = Utc::now();
+        let mut state = self.state.lock().unwrap();
+        // Planning calls come before the first event of the run, so they open it too.
+        state.ensure_run(start);
+        let mut span = open(&format!("llm {}", role), state.parent(), start);
+        span.attributes.push(("agent.role".to_string(), AttributeValue::Text(role.to_string())));
+        span.attributes.push(("gen_ai.request.model".to_string(), AttributeValue::Text(model.to_string())));
+        span.attributes.push(("llm.latency_ms".to_string(), AttributeValue::Int((end - start).num_milliseconds())));
+        match result {
+            Ok(response) => {
+                span.attributes.extend([
+                    ("gen_ai.system".to_string(), AttributeValue::Text(response.provider.clone())),
+                    ("gen_ai.response.model".to_string(), AttributeValue::Text(response.model.clone())),
+                    ("gen_ai.usage.input_tokens".to_string(), AttributeValue::Int(response.input_tokens.into())),
+                    ("gen_ai.usage.output_tokens".to_string(), AttributeValue::Int(response.output_tokens.into())),
+                    ("llm.cost_usd".to_string(), AttributeValue::Float(response.cost)),
+                ]);
+            }
+            Err(e) => span.error = Some(e.to_string()),
+        }
+        state.finish(span, end);
+        self.export_if_full(&mut state);
+    }
+
+    /// Ends the spans still open, such as the run of a `plan` that is never executed, and sends
+    /// every finished span to the collector.
+    pub async fn flush(&self) -> Result<(), AgentError> {
+        let spans = {
+            let mut state = self.state.lock().unwrap();
+            let now = Utc::now();
+            for span in [state.tool.take(), state.step.take(), state.run.take()].into_iter().flatten() {
+                state.finish(span, now);
+            }
+            std::mem::take(&mut state.finished)
+        };
+        if spans.is_empty() {
+            return Ok(());
+        }
+        send(&self.client, &self.endpoint, otlp_json(&self.service_name, &spans)).await
+    }
+
+    /// Sends the finished spans in the background once a batch has built up.
+    fn export_if_full(&self, state: &mut TraceState) {
+        if state.finished.len() < EXPORT_BATCH_SIZE {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let body = otlp_json(&self.service_name, &std::mem::take(&mut state.finished));
+        let (client, endpoint) = (self.client.clone(), self.endpoint.clone());
+        runtime.spawn(async move {
+            if let Err(e) = send(&client, &endpoint, body).await {
+                warn!("Failed to export spans: {}", e);
+            }
+        });
+    }
+}
+
+impl EventSink for Tracer {
+    fn handle(&self, record: &EventRecord) {
+        let at = record.timestamp;
+        let mut state = self.state.lock().unwrap();
+        state.ensure_run(at);
+        match &record.event {
+            AgentEvent::StepStarted { step_index, step } => {
+                if let Some(previous) = state.step.take() {
+                    state.finish(previous, at);
+                }
+                let mut span = open("agent.step", state.parent(), at);
+                span.attributes.push(("step.index".to_string(), AttributeValue::Int(*step_index as i64)));
+                span.attributes.push(("step.description".to_string(), AttributeValue::Text(step.clone())));
+                state.step = Some(span);
+            }
+            AgentEvent::StepFinished { llm_calls, llm_ms, .. } => {
+                if let Some(mut span) = state.step.take() {
+                    span.attributes.push(("step.llm_calls".to_string(), AttributeValue::Int(*llm_calls as i64)));
+                    span.attributes.push(("step.llm_ms".to_string(), AttributeValue::Int(*llm_ms as i64)));
+                    state.finish(span, at);
+                }
+            }
+            // The tool runs right after the decision, so its span starts there.
+            AgentEvent::DecisionMade { decision, .. } => {
+                let mut span = open(&format!("tool {}", decision.tool.name()), state.parent(), at);
+                span.attributes.push(("tool.name".to_string(), AttributeValue::Text(decision.tool.name())));
+                state.tool = Some(span);
+            }
+            AgentEvent::ToolExecuted { tool, success, .. } => {
+                let mut span = state.tool.take().unwrap_or_else(|| open(&format!("tool {}", tool.name()), state.parent(), at));
+                span.attributes.push(("tool.success".to_string(), AttributeValue::Bool(*success)));
+                if !success {
+                    span.error = Some(format!("{} failed", tool.name()));
+                }
+                state.finish(span, at);
+            }
+            AgentEvent::Error { message, .. } => {
+                let state = &mut *state;
+                if let Some(span) = state.step.as_mut().or(state.run.as_mut()) {
+                    span.error = Some(message.clone());
+                }
+            }
+            AgentEvent::RunFinished { success, total_cost } => {
+                for span in [state.tool.take(), state.step.take()].into_iter().flatten() {
+                    state.finish(span, at);
+                }
+                if let Some(mut run) = state.run.take() {
+                    run.attributes.push(("run.success".to_string(), AttributeValue::Bool(*success)));
+                    run.attributes.push(("run.cost_usd".to_string(), AttributeValue::Float(*total_cost)));
+                    if !success && run.error.is_none() {
+                        run.error = Some("run failed".to_string());
+                    }
+                    state.finish(run, at);
+                }
+            }
+            _ => {}
+        }
+        self.export_if_full(&mut state);
+    }
+}
+
+/// Wraps `client` so every call `role` makes through it is recorded as a span by `tracer`.
+pub fn traced(client: Arc<dyn LLMClient>, role: &str, tracer: Arc<Tracer>) -> Arc<dyn LLMClient> {
+    Arc::new(TracedClient { inner: client, role: role.to_string(), tracer })
+}
+
+struct TracedClient {
+    inner: Arc<dyn LLMClient>,
+    role: String,
+    tracer: Arc<Tracer>,
+}
+
+impl TracedClient {
+    async fn record<T>(&self, start: DateTime<Utc>, result: &Result<T, AgentError>, response: impl Fn(&T) -> &AIResponse) {
+        let model = self.inner.get_model_info().await.name;
+        self.tracer.record_llm_call(&self.role, &model, start, result.as_ref().map(response));
+    }
+}
+
+#[async_trait]
+impl LLMClient for TracedClient {
+    async fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let start = Utc::now();
+        let result = self.inner.generate(prompt, params).await;
+        self.record(start, &result, |response| response).await;
+        result
+    }
+
+    async fn generate_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let start = Utc::now();
+        let result = self.inner.generate_chat(messages, params).await;
+        self.record(start, &result, |response| response).await;
+        result
+    }
+
+    async fn generate_json(&self, prompt: &str, params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let start = Utc::now();
+        let result = self.inner.generate_json(prompt, params).await;
+        self.record(start, &result, |response| response).await;
+        result
+    }
+
+    async fn generate_json_chat(&self, messages: &[ChatMessage], params: &GenerationParams) -> Result<AIResponse, AgentError> {
+        let start = Utc::now();
+        let result = self.inner.generate_json_chat(messages, params).await;
+        self.record(start, &result, |response| response).await;
+        result
+    }
+
+    async fn generate_stream(&self, messages: &[ChatMessage], params: &GenerationParams, on_text: &OnText<'_>) -> Result<AIResponse, AgentError> {
+        let start = Utc::now();
+        let result = self.inner.generate_stream(messages, params, on_text).await;
+        self.record(start, &result, |response| response).await;
+        result
+    }
+
+    async fn generate_with_tools(&self, prompt: &str, tools: &[ToolDefinition], params: &GenerationParams) -> Result<(AIResponse, Option<ToolCall>), AgentError> {
+        let start = Utc::now();
+        let result = self.inner.generate_with_tools(prompt, tools, params).await;
+        self.record(start, &result, |(response, _)| response).await;
+        result
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AgentError> {
+        self.inner.list_models().await
+    }
+
+    async fn get_model_info(&self) -> ModelInfo {
+        self.inner.get_model_info().await
+    }
+
+    fn calculate_cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        self.inner.calculate_cost(input_tokens, output_tokens)
+    }
+}
+
+fn open(name: &str, parent_span_id: Option<u64>, start: DateTime<Utc>) -> OpenSpan {
+    OpenSpan { span_id: random_id(), parent_span_id, name: name.to_string(), start, attributes: Vec::new(), error: None }
+}
+
+/// A random, non-zero id. `RandomState` is seeded randomly, so no random number crate is needed.
+fn random_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(Utc::now().timestamp_nanos_opt().unwrap_or_default() as u128);
+    hasher.finish().max(1)
+}
+
+/// `spans` as an OTLP `ExportTraceServiceRequest` in its JSON encoding.
+pub fn otlp_json(service_name: &str, spans: &[Span]) -> Value {
+    let spans: Vec<Value> = spans
+        .iter()
+        .map(|span| {
+            let mut value = json!({
+                "traceId": format!("{:032x}", span.trace_id),
+                "spanId": format!("{:016x}", span.span_id),
+                "name": span.name,
+                "kind": 1,
+                "startTimeUnixNano": nanos(span.start),
+                "endTimeUnixNano": nanos(span.end),
+                "attributes": span.attributes.iter().map(|(key, value)| attribute(key, value)).collect::<Vec<_>>(),
+                "status": match &span.error {
+                    Some(message) => json!({ "code": 2, "message": message }),
+                    None => json!({ "code": 1 }),
+                },
+            });
+            if let Some(parent) = span.parent_span_id {
+                value["parentSpanId"] = json!(format!("{:016x}", parent));
+            }
+            value
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [attribute("service.name", &AttributeValue::Text(service_name.to_string()))] },
+            "scopeSpans": [{ "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") }, "spans": spans }],
+        }]
+    })
+}
+
+fn attribute(key: &str, value: &AttributeValue) -> Value {
+    // OTLP JSON encodes 64-bit integers as strings.
+    let value = match value {
+        AttributeValue::Text(text) => json!({ "stringValue": text }),
+        AttributeValue::Int(int) => json!({ "intValue": int.to_string() }),
+        AttributeValue::Float(float) => json!({ "doubleValue": float }),
+        AttributeValue::Bool(boolean) => json!({ "boolValue": boolean }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn nanos(time: DateTime<Utc>) -> String {
+    time.timestamp_nanos_opt().unwrap_or_default().to_string()
+}
+
+async fn send(client: &reqwest::Client, endpoint: &str, body: Value) -> Result<(), AgentError> {
+    let response = client.post(endpoint).json(&body).send().await?;
+    if !response.status().is_success() {
+        return Err(AgentError::LLMError(format!("The OTLP collector at {} answered {}", endpoint, response.status())));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{Decision, Tool};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    struct MockLLMClient;
+
+    #[async_trait]
+    impl LLMClient for MockLLMClient {
+        async fn generate(&self, _prompt: &str, _params: &GenerationParams) -> Result<AIResponse, AgentError> {
+            Ok(AIResponse {
+                content: "done".to_string(),
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 0,
+                cost: 0.01,
+                model: "mock-model".to_string(),
+                provider: "mock-provider".to_string(),
+            })
+        }
+        async fn get_model_info(&self) -> ModelInfo {
+            ModelInfo { name: "mock-model".to_string(), input_cost_per_token: 0.0, output_cost_per_token: 0.0 }
+        }
+        fn calculate_cost(&self, _input_tokens: u32, _output_tokens: u32) -> f64 {
+            0.0
+        }
+    }
+
+    fn emit(tracer: &Tracer, event: AgentEvent) {
+        tracer.handle(&EventRecord { timestamp: Utc::now(), event });
+    }
+
+    #[tokio::test]
+    async fn test_run_steps_tools_and_llm_calls_become_one_trace() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/v1/traces")).respond_with(ResponseTemplate::new(200)).expect(1).mount(&server).await;
+        let tracer = Arc::new(Tracer::new(&server.uri(), "test-agent"));
+        let client = traced(Arc::new(MockLLMClient), "coder", tracer.clone());
+
+        emit(&tracer, AgentEvent::StepStarted { step_index: 0, step: "List the files".to_string() });
+        client.generate("prompt", &GenerationParams::default()).await.unwrap();
+        let tool = Tool::ListFiles { path: ".".to_string(), max_depth: None, glob: None };
+        emit(&tracer, AgentEvent::DecisionMade { step_index: 0, decision: Decision { thought: "t".to_string(), tool: tool.clone(), file_path: None } });
+        emit(&tracer, AgentEvent::ToolExecuted { step_index: 0, tool, success: false, output: String::new() });
+        emit(&tracer, AgentEvent::StepFinished { step_index: 0, duration_ms: 5, llm_calls: 1, llm_ms: 2 });
+        emit(&tracer, AgentEvent::RunFinished { success: true, total_cost: 0.5 });
+
+        let spans = tracer.state.lock().unwrap().finished.clone();
+        let names: Vec<&str> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["llm coder", "tool ListFiles", "agent.step", "agent.run"]);
+        assert!(spans.iter().all(|span| span.trace_id == spans[0].trace_id));
+        assert_eq!(spans[0].parent_span_id, Some(spans[2].span_id));
+        assert_eq!(spans[2].parent_span_id, Some(spans[3].span_id));
+        assert_eq!(spans[1].error.as_deref(), Some("ListFiles failed"));
+
+        let json = otlp_json("test-agent", &spans);
+        let exported = &json["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(exported[0]["attributes"][0], json!({ "key": "agent.role", "value": { "stringValue": "coder" } }));
+        assert_eq!(exported[1]["status"]["code"], 2);
+        assert_eq!(exported[3].get("parentSpanId"), None);
+
+        tracer.flush().await.unwrap();
+        assert!(tracer.state.lock().unwrap().finished.is_empty());
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::AgentError;
+
+/// Characters of the target file given to the run as context; longer files are cut.
+const MAX_SOURCE_CHARS: usize = 30_000;
+
+/// What `test-gen` writes tests for: a file, or one function in it, written `src/foo.rs::parse_config`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestTarget {
+    pub path: PathBuf,
+    /// The function (or method, e.g. `Config::parse`) to test; the whole file when unset.
+    pub item: Option<String>,
+}
+
+impl FromStr for TestTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, item) = match s.trim().split_once("::") {
+            Some((path, item)) => (path, Some(item.trim())),
+            None => (s.trim(), None),
+        };
+        if path.is_empty() || item == Some("") {
+            return Err(format!("'{}' is not a test target; expected a file, e.g. src/foo.rs, or a function in it, e.g. src/foo.rs::parse_config", s));
+        }
+        Ok(Self { path: PathBuf::from(path), item: item.map(str::to_string) })
+    }
+}
+
+impl fmt::Display for TestTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.item {
+            Some(item) => write!(f, "{}::{}", self.path.display(), item),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+/// The test framework the generated tests are written for and run with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestFramework {
+    /// `#[test]` functions run by `cargo test`.
+    Cargo,
+    Pytest,
+    Unittest,
+    Jest,
+    Vitest,
+    Mocha,
+    /// Node's built-in `node:test` runner.
+    NodeTest,
+    GoTest,
+}
+
+impl TestFramework {
+    /// The framework for `target`, from its extension and what the project in `dir` already uses:
+    /// its dev dependencies for JavaScript and TypeScript, its configuration for Python.
+    pub fn infer(dir: &Path, target: &Path) -> Option<Self> {
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap_or_default();
+        match target.extension()?.to_str()? {
+            "rs" => Some(TestFramework::Cargo),
+            "go" => Some(TestFramework::GoTest),
+            "py" => {
+                let configured = ["pyproject.toml", "setup.cfg", "tox.ini", "requirements.txt", "requirements-dev.txt"].iter().any(|name| read(name).contains("pytest"));
+                let uses_pytest = configured || dir.join("pytest.ini").is_file() || dir.join("conftest.py").is_file();
+                Some(if uses_pytest { TestFramework::Pytest } else { TestFramework::Unittest })
+            }
+            "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "mts" | "cts" => {
+                let manifest = read("package.json");
+                let framework = [("\"vitest\"", TestFramework::Vitest), ("\"jest\"", TestFramework::Jest), ("\"mocha\"", TestFramework::Mocha)]
+                    .into_iter()
+                    .find(|(dependency, _)| manifest.contains(dependency))
+                    .map(|(_, framework)| framework);
+                Some(framework.unwrap_or(TestFramework::NodeTest))
+            }
+            _ => None,
+        }
+    }
+
+    /// Where the tests for `target` conventionally go: the file itself for Rust unit tests,
+    /// `tests/test_<name>.py` for Python, `test/<name>.test.<ext>` for Mocha, `<name>_test.go`
+    /// beside Go files and `<name>.test.<ext>` beside the others.
+    pub fn test_file(&self, target: &Path) -> PathBuf {
+        let stem = target.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+        let extension = target.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        let beside = |name: String| target.with_file_name(name);
+        match self {
+            TestFramework::Cargo => target.to_path_buf(),
+            TestFramework::Pytest | TestFramework::Unittest => PathBuf::from("tests").join(format!("test_{}.py", stem)),
+            TestFramework::Mocha => PathBuf::from("test").join(format!("{}.test.{}", stem, extension)),
+            TestFramework::GoTest => beside(format!("{}_test.go", stem)),
+            TestFramework::Jest | TestFramework::Vitest | TestFramework::NodeTest => beside(format!("{}.test.{}", stem, extension)),
+        }
+    }
+
+    /// The command that runs the tests in `test_file`; for Rust, only the tests named after `item`
+    /// (its last segment) when there is one.
+    pub fn command(&self, test_file: &Path, item: Option<&str>) -> String {
+        let file = test_file.display();
+        match self {
+            TestFramework::Cargo => match item.and_then(|item| item.rsplit("::").next()) {
+                Some(name) => format!("cargo test {}", name),
+                None => "cargo test".to_string(),
+            },
+            TestFramework::Pytest => format!("python -m pytest {}", file),
+            TestFramework::Unittest => format!("python -m unittest {}", file),
+            TestFramework::Jest => format!("npx jest {}", file),
+            TestFramework::Vitest => format!("npx vitest run {}", file),
+            TestFramework::Mocha => format!("npx mocha {}", file),
+            TestFramework::NodeTest => format!("node --test {}", file),
+            TestFramework::GoTest => {
+                let package = test_file.parent().filter(|dir| !dir.as_os_str().is_empty()).map(|dir| format!("./{}", dir.display())).unwrap_or_else(|| ".".to_string());
+                format!("go test {}", package)
+            }
+        }
+    }
+
+    /// How the tests are laid out, for the coder.
+    fn conventions(&self) -> &'static str {
+        match self {
+            TestFramework::Cargo => "Add the tests to the `#[cfg(test)] mod tests` module at the end of the file (create it if there is none), as `#[test]` functions that `use super::*;`.",
+            TestFramework::Pytest => "Write plain `test_*` functions using `assert`, and pytest fixtures or `pytest.mark.parametrize` where they help.",
+            TestFramework::Unittest => "Write a `unittest.TestCase` subclass with `test_*` methods.",
+            TestFramework::Jest | TestFramework::Vitest => "Use `describe`/`it` blocks with `expect` assertions.",
+            TestFramework::Mocha => "Use `describe`/`it` blocks with the assertion library the project already uses, or `node:assert`.",
+            TestFramework::NodeTest => "Use `test` from `node:test` with `node:assert/strict` assertions.",
+            TestFramework::GoTest => "Write `TestXxx(t *testing.T)` functions in the same package, table-driven where there are several cases.",
+        }
+    }
+}
+
+impl fmt::Display for TestFramework {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TestFramework::Cargo => "Rust",
+            TestFramework::Pytest => "pytest",
+            TestFramework::Unittest => "unittest",
+            TestFramework::Jest => "Jest",
+            TestFramework::Vitest => "Vitest",
+            TestFramework::Mocha => "Mocha",
+            TestFramework::NodeTest => "node:test",
+            TestFramework::GoTest => "Go",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A `test-gen` run worked out ahead of time: the goal, its plan and the target's code, so the run
+/// starts writing tests without planning or looking for the code first.
+#[derive(Debug, Clone)]
+pub struct TestGeneration {
+    pub target: TestTarget,
+    pub framework: TestFramework,
+    pub test_file: PathBuf,
+    pub command: String,
+    source: String,
+}
+
+impl TestGeneration {
+    /// Reads `target` from the project in `dir` and infers how its tests are written and run.
+    /// Fails if the file cannot be read, does not define the function, or is in a language
+    /// without a known test framework.
+    pub fn prepare(dir: &Path, target: TestTarget) -> Result<Self, AgentError> {
+        let source = std::fs::read_to_string(dir.join(&target.path)).map_err(|e| AgentError::ConfigError(format!("Cannot read {}: {}", target.path.display(), e)))?;
+        if let Some(item) = &target.item {
+            // The last segment, so `Config::parse` is found as `fn parse`.
+            let name = item.rsplit("::").next().unwrap_or(item);
+            if !defines(&source, name) {
+                return Err(AgentError::ConfigError(format!("{} does not define `{}`", target.path.display(), item)));
+            }
+        }
+        let framework = TestFramework::infer(dir, &target.path).ok_or_else(|| AgentError::ConfigError(format!("No known test framework for {}", target.path.display())))?;
+        let test_file = framework.test_file(&target.path);
+        let command = framework.command(&test_file, target.item.as_deref());
+        Ok(Self { target, framework, test_file, command, source })
+    }
+
+    /// The run's goal, which the verifier checks once the plan is done.
+    pub fn goal(&self) -> String {
+        let subject = match &self.target.item {
+            Some(item) => format!("`{}` in {}", item, self.target.path.display()),
+            None => self.target.path.display().to_string(),
+        };
+        format!(
+            "Write {} tests for {} in {}, covering its normal behaviour, edge cases and error handling, and make sure `{}` passes.",
+            self.framework,
+            subject,
+            self.test_file.display(),
+            self.command
+        )
+    }
+
+    /// The plan the run executes instead of asking the planner for one.
+    pub fn steps(&self) -> Vec<String> {
+        let subject = self.target.item.as_deref().map(|item| format!("`{}`", item)).unwrap_or_else(|| "the public functions in the file".to_string());
+        // Rust unit tests live in the file under test, so only its test module may change.
+        let limits = match (self.framework, self.target.item.as_deref().and_then(|item| item.rsplit("::").next())) {
+            (TestFramework::Cargo, Some(name)) => format!("Only add to the `#[cfg(test)] mod tests` module; do not change the code outside it. Put `{}` in every test's name so `{}` runs them.", name, self.command),
+            (TestFramework::Cargo, None) => "Only add to the `#[cfg(test)] mod tests` module; do not change the code outside it.".to_string(),
+            _ => format!("Do not change {}.", self.target.path.display()),
+        };
+        vec![
+            format!(
+                "Write {} tests for {} in {}, keeping any tests already there. {} Test behaviour through the public interface. {}",
+                self.framework,
+                subject,
+                self.test_file.display(),
+                self.framework.conventions(),
+                limits
+            ),
+            format!("Run `{}` and read the failures, if any.", self.command),
+            format!(
+                "Fix the failing tests and run `{}` again until they pass. If a test fails because of a bug in {} rather than in the test, leave the test as the correct expectation, mark it as skipped with the reason, and say so.",
+                self.command,
+                self.target.path.display()
+            ),
+        ]
+    }
+
+    /// The target's code, for the run's initial context.
+    pub fn context(&self) -> String {
+        let source = match self.source.char_indices().nth(MAX_SOURCE_CHARS) {
+            Some((end, _)) => format!("{}\n... (cut; read the file for the rest)", &self.source[..end]),
+            None => self.source.clone(),
+        };
+        format!("Contents of {}:\n{}", self.target.path.display(), source)
+    }
+}
+
+/// Whether a line of `source` looks like it defines a function (or method) called `name` in one of
+/// the supported languages: it names it next to `fn`, `def`, `function` or `func`, assigns it to a
+/// `const`, or starts with it as a class method does in JavaScript.
+fn defines(source: &str, name: &str) -> bool {
+    source.lines().any(|line| {
+        let words: Vec<&str> = line.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|word| !word.is_empty()).collect();
+        let declares = words.iter().any(|word| matches!(*word, "fn" | "def" | "function" | "func"))
+            || line.contains(&format!("const {} =", name))
+            || line.trim_start().starts_with(&format!("{}(", name));
+        declares && words.contains(&name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let target: TestTarget = "src/foo.rs::Config::parse".parse().unwrap();
+        assert_eq!(target, TestTarget { path: PathBuf::from("src/foo.rs"), item: Some("Config::parse".to_string()) });
+        assert_eq!(target.to_string(), "src/foo.rs::Config::parse");
+        assert_eq!("app.py".parse::<TestTarget>().unwrap().item, None);
+        assert!("src/foo.rs::".parse::<TestTarget>().is_err());
+    }
+
+    #[test]
+    fn test_infer_framework_and_test_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(TestFramework::infer(dir.path(), Path::new("src/config.rs")), Some(TestFramework::Cargo));
+        assert_eq!(TestFramework::infer(dir.path(), Path::new("app/util.py")), Some(TestFramework::Unittest));
+        assert_eq!(TestFramework::infer(dir.path(), Path::new("src/util.ts")), Some(TestFramework::NodeTest));
+        assert_eq!(TestFramework::infer(dir.path(), Path::new("README.md")), None);
+
+        std::fs::write(dir.path().join("pyproject.toml"), "[tool.pytest.ini_options]").unwrap();
+        std::fs::write(dir.path().join("package.json"), r#"{"devDependencies": {"vitest": "^1.0.0"}}"#).unwrap();
+        assert_eq!(TestFramework::infer(dir.path(), Path::new("app/util.py")), Some(TestFramework::Pytest));
+        assert_eq!(TestFramework::infer(dir.path(), Path::new("src/util.ts")), Some(TestFramework::Vitest));
+
+        assert_eq!(TestFramework::Pytest.test_file(Path::new("app/util.py")), PathBuf::from("tests/test_util.py"));
+        assert_eq!(TestFramework::Vitest.test_file(Path::new("src/util.ts")), PathBuf::from("src/util.test.ts"));
+        assert_eq!(TestFramework::GoTest.command(&TestFramework::GoTest.test_file(Path::new("pkg/parse.go")), None), "go test ./pkg");
+        assert_eq!(TestFramework::Cargo.test_file(Path::new("src/config.rs")), PathBuf::from("src/config.rs"));
+    }
+
+    #[test]
+    fn test_prepare_checks_the_function_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/foo.rs"), "pub fn parse_config(text: &str) -> Config {\n    todo!()\n}\n").unwrap();
+
+        let generation = TestGeneration::prepare(dir.path(), "src/foo.rs::parse_config".parse().unwrap()).unwrap();
+        assert_eq!(generation.command, "cargo test parse_config");
+        assert!(generation.goal().starts_with("Write Rust tests for `parse_config` in src/foo.rs"));
+        let steps = generation.steps();
+        assert_eq!(steps.len(), 3);
+        // The tests go in the file under test, so the step must not forbid changing it.
+        assert!(!steps[0].contains("not change src/foo.rs") && steps[0].contains("Only add to the `#[cfg(test)] mod tests` module"), "{}", steps[0]);
+        assert!(generation.context().contains("pub fn parse_config"));
+
+        let missing = TestGeneration::prepare(dir.path(), "src/foo.rs::load".parse().unwrap()).unwrap_err();
+        assert_eq!(missing.to_string(), "Configuration error: src/foo.rs does not define `load`");
+        assert!(TestGeneration::prepare(dir.path(), "src/bar.rs".parse().unwrap()).is_err());
+    }
+}
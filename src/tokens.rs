@@ -0,0 +1,108 @@
+use crate::llm::LLMProvider;
+
+/// Estimates how many tokens `text` costs with `provider`'s models. OpenAI models are counted
+/// exactly with their tiktoken encoding (`o200k_base`, used since GPT-4o); other providers do not
+/// publish their tokenizers, so their count is `approximate_tokens`.
+pub fn estimate_tokens(provider: LLMProvider, text: &str) -> usize {
+    match provider {
+        LLMProvider::OpenAI => tiktoken_rs::o200k_base_singleton().lock().encode_ordinary(text).len(),
+        _ => approximate_tokens(text),
+    }
+}
+
+/// Estimates how many tokens `text` costs, roughly the way BPE tokenizers like tiktoken split it:
+/// runs of letters/digits cost about one token per four characters, every other non-whitespace
+/// character costs one, and whitespace is folded into the following token.
+pub fn approximate_tokens(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut run: usize = 0;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            run += 1;
+            continue;
+        }
+        tokens += run.div_ceil(4);
+        run = 0;
+        if !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens + run.div_ceil(4)
+}
+
+/// The context window, in tokens, of `model` on `provider`, matched by model name prefix.
+/// `None` for models it does not know, such as most local Ollama models.
+pub fn context_window(provider: LLMProvider, model: &str) -> Option<usize> {
+    let model = model.to_lowercase();
+    let windows: &[(&str, usize)] = match provider {
+        LLMProvider::OpenAI => &[
+            ("gpt-4.1", 1_047_576),
+            ("gpt-4o", 128_000),
+            ("gpt-4-turbo", 128_000),
+            ("gpt-4-32k", 32_768),
+            ("gpt-4", 8_192),
+            ("gpt-3.5-turbo", 16_385),
+            ("o1-mini", 128_000),
+            ("o1", 200_000),
+            ("o3", 200_000),
+            ("o4", 200_000),
+        ],
+        LLMProvider::Claude => &[("claude", 200_000)],
+        LLMProvider::Gemini => &[("gemini-1.5-pro", 2_097_152), ("gemini-1.0", 32_760), ("gemini", 1_048_576)],
+        LLMProvider::DeepSeek => &[("deepseek", 64_000)],
+        LLMProvider::Mistral => &[("mistral-large", 128_000), ("codestral", 256_000), ("open-mistral-nemo", 128_000), ("mistral", 32_000)],
+        LLMProvider::Groq => &[("llama-3", 128_000), ("llama3", 8_192), ("mixtral", 32_768), ("gemma", 8_192)],
+        LLMProvider::Ollama | LLMProvider::Mock => &[],
+    };
+    windows.iter().find(|(prefix, _)| model.starts_with(prefix)).map(|(_, window)| *window)
+}
+
+/// Describes why `prompt` will not fit `model`'s context window, or `None` if it fits or the
+/// window is unknown. Checked before sending, so an oversized prompt is explained instead of
+/// surfacing as an opaque API error.
+pub fn prompt_overflow(provider: LLMProvider, model: &str, prompt: &str) -> Option<String> {
+    let window = context_window(provider, model)?;
+    let tokens = estimate_tokens(provider, prompt);
+    (tokens > window).then(|| {
+        format!(
+            "The prompt is about {} tokens, more than the {} token context window of {} {}; lower CONTEXT_TOKEN_BUDGET or use a model with a larger window",
+            tokens, window, provider, model
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_approximate_tokens() {
+        assert_eq!(approximate_tokens(""), 0);
+        assert_eq!(approximate_tokens("hello"), 2);
+        assert_eq!(approximate_tokens("fn main() {}"), 6);
+        assert_eq!(approximate_tokens("a b c"), 3);
+    }
+
+    #[test]
+    fn test_openai_counts_with_tiktoken() {
+        assert_eq!(estimate_tokens(LLMProvider::OpenAI, "hello world"), 2);
+        assert_eq!(estimate_tokens(LLMProvider::Claude, "hello world"), approximate_tokens("hello world"));
+    }
+
+    #[test]
+    fn test_context_window_matches_by_prefix() {
+        assert_eq!(context_window(LLMProvider::OpenAI, "gpt-4o-mini"), Some(128_000));
+        assert_eq!(context_window(LLMProvider::OpenAI, "gpt-4"), Some(8_192));
+        assert_eq!(context_window(LLMProvider::Claude, "claude-3-opus-20240229"), Some(200_000));
+        assert_eq!(context_window(LLMProvider::Ollama, "llama3"), None);
+    }
+
+    #[test]
+    fn test_prompt_overflow() {
+        assert_eq!(prompt_overflow(LLMProvider::OpenAI, "gpt-4o", "hello"), None);
+        let long = "word ".repeat(9_000);
+        let message = prompt_overflow(LLMProvider::OpenAI, "gpt-4", &long).unwrap();
+        assert!(message.contains("8192 token context window of OpenAI gpt-4"), "{}", message);
+        assert_eq!(prompt_overflow(LLMProvider::Ollama, "llama3", &long), None);
+    }
+}
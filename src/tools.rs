@@ -1,19 +1,194 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use log::info;
-use walkdir::WalkDir;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 use crate::config::AppConfig;
 use crate::error::AgentError;
+use crate::llm::{ToolCall, ToolDefinition};
+
+pub mod db;
+pub mod diff;
+pub mod fetch;
+pub mod http;
+pub mod image;
+pub mod kubernetes;
+pub mod list;
+pub mod outline;
+pub mod output_store;
+pub mod plugin;
+pub mod process;
+pub mod read;
+pub mod registry;
+pub mod sandbox;
+pub mod scaffold;
+pub mod schema;
+pub mod script;
+pub mod search;
+pub mod test_runner;
+pub mod web_search;
+pub mod write;
+
+pub use diff::unified_diff;
+pub use fetch::{fetch_url, html_to_text};
+pub use http::http_request;
+pub use image::{read_image, referenced_images};
+pub use kubernetes::{ExecBackend, KubernetesJob};
+pub use list::{list_files, project_files};
+pub use outline::code_outline;
+pub use output_store::{save_output, TOOL_OUTPUT_DIR};
+pub use plugin::{load_plugins, WasmPlugin, DEFAULT_PLUGIN_DIR};
+pub use process::CommandLimits;
+pub use read::read_file;
+pub use registry::{ToolHandler, ToolRegistry};
+pub use sandbox::Sandbox;
+pub use scaffold::ProjectTemplate;
+pub use script::{ScriptRuntime, SCRIPT_DIR};
+pub use search::{search_in_files, SearchMatch};
+pub use test_runner::{TestFailure, TestFramework, TestReport};
+pub use web_search::{create_search_provider, SearchBackend, SearchProvider, WebResult};
+pub use write::{backup_file, write_atomic};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "tool_name", content = "parameters")]
 pub enum Tool {
-    ReadFile { path: String },
+    ReadFile {
+        path: String,
+        /// First line to read (1-based); reads from the start when absent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start_line: Option<usize>,
+        /// Last line to read (inclusive); reads to the end when absent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        end_line: Option<usize>,
+    },
+    /// Attaches an image (e.g. a screenshot or design mock) to the following decision prompts.
+    ReadImage { path: String },
+    /// Reads a tool output that was too long for the history and was summarized instead.
+    ReadToolOutput {
+        id: String,
+        /// Lines to read, e.g. "120-200"; the whole output (shortened if large) when absent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        range: Option<String>,
+    },
     WriteFile { path: String, content: String },
-    RunCommand { command: String },
+    EditFile { path: String, edits: Vec<FileEdit> },
+    /// Creates a directory and any missing parents.
+    CreateDirectory { path: String },
+    RunCommand {
+        command: String,
+        /// Overrides the configured command timeout for this command.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_secs: Option<u64>,
+        /// Directory to run the command in, relative to the working directory.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cwd: Option<String>,
+        /// Environment variables set for this command only.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        /// Text written to the command's standard input.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stdin: Option<String>,
+    },
+    RunTests {
+        /// Names the test runner (e.g. "cargo", "pytest", "jest") when it cannot be detected.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        framework_hint: Option<String>,
+    },
+    /// Creates project `name` in the working directory with a generator such as `cargo new`.
+    ScaffoldProject {
+        /// A `scaffold::ProjectTemplate` name, e.g. "rust-bin", "rust-lib", "node", "python" or "go".
+        template: String,
+        name: String,
+    },
+    /// Runs `code` with the WASI interpreter configured for `language` in a wasmtime sandbox: it can
+    /// read the working directory, write only to `output_dir`, and has no network or shell.
+    RunScript {
+        language: String,
+        code: String,
+        /// Directory, relative to the working directory, the script may create and change files in.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        output_dir: Option<String>,
+    },
     Search { query: String },
-    ListFiles { path: String },
+    FetchUrl { url: String },
+    /// Calls an HTTP API on an allowed host, e.g. to check the shape of an endpoint's response.
+    HttpRequest {
+        method: String,
+        url: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        body: Option<String>,
+    },
+    /// Runs a read-only `SELECT` or `EXPLAIN` against a database configured in `AGENT_DB_CONNECTIONS`,
+    /// e.g. to look up the columns of a table before writing code against it.
+    DbQuery { connection_name: String, sql: String },
+    SearchInFiles {
+        pattern: String,
+        path: String,
+        #[serde(default)]
+        regex: bool,
+    },
+    ListFiles {
+        path: String,
+        /// How many levels below `path` to list; unlimited when absent.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_depth: Option<usize>,
+        /// Only list files matching this glob (gitignore syntax, e.g. `*.rs`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        glob: Option<String>,
+    },
+    /// Lists the items of a Rust file, or of every Rust file in a directory, with their line
+    /// numbers and signatures but not their bodies.
+    CodeOutline { path: String },
     CodeGeneration { task: String },
+    /// Hands independent subtasks of a large step to worker orchestrators that run in parallel.
+    /// Only offered when the registry has delegation enabled.
+    Delegate { subtasks: Vec<String> },
+    /// A tool registered in a `ToolRegistry` at runtime, e.g. by a library user or a WASM plugin.
+    /// Any decision naming a tool that is not built in parses into this variant.
+    #[serde(untagged)]
+    Custom {
+        #[serde(rename = "tool_name")]
+        name: String,
+        #[serde(default)]
+        parameters: serde_json::Value,
+    },
+}
+
+impl Tool {
+    /// The name the model chose this tool by, e.g. "ReadFile".
+    pub fn name(&self) -> String {
+        serde_json::to_value(self).ok().and_then(|v| v["tool_name"].as_str().map(str::to_string)).unwrap_or_default()
+    }
+
+    /// Whether the tool reaches out over the network, so offline mode (`AppConfig::offline`) refuses it.
+    pub fn needs_network(&self) -> bool {
+        NETWORK_TOOLS.contains(&self.name().as_str())
+    }
+
+    /// Whether the tool only looks at the workspace (or the web), so read-only mode
+    /// (`ToolRegistry::with_read_only`) lets it run.
+    pub fn is_read_only(&self) -> bool {
+        READ_ONLY_TOOLS.contains(&self.name().as_str())
+    }
+
+    /// The file this tool changes, for tools that write to disk.
+    pub fn modified_file(&self) -> Option<&str> {
+        match self {
+            Tool::WriteFile { path, .. } | Tool::EditFile { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// A search/replace block for `Tool::EditFile`. `search` must match exactly once in the file.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileEdit {
+    pub search: String,
+    pub replace: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -25,77 +200,869 @@ pub struct Decision {
     pub file_path: Option<String>,
 }
 
-#[derive(Debug)]
+impl Decision {
+    /// Builds a decision from a native tool call. The `thought` and `file_path` arguments are
+    /// lifted out of the call; `fallback_thought` (usually the model's text) fills a missing thought.
+    pub fn from_tool_call(call: &ToolCall, fallback_thought: &str) -> Result<Self, AgentError> {
+        let mut parameters = match &call.arguments {
+            serde_json::Value::Object(map) => map.clone(),
+            other => return Err(AgentError::ResponseParseError(format!("Tool call arguments for {} are not an object: {}", call.name, other))),
+        };
+        let thought = parameters
+            .remove("thought")
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_else(|| fallback_thought.trim().to_string());
+        let file_path = parameters.remove("file_path").and_then(|v| v.as_str().map(str::to_string));
+        let tool = serde_json::from_value(serde_json::json!({ "tool_name": call.name, "parameters": parameters }))
+            .map_err(|e| AgentError::ResponseParseError(format!("Failed to parse tool call {}: {}. Arguments: {}", call.name, e, call.arguments)))?;
+        Ok(Self { thought, tool, file_path })
+    }
+}
+
+/// JSON-schema definitions of every `Tool` variant for providers with native tool calling.
+/// Each tool also takes a required `thought`; `CodeGeneration` takes an optional `file_path`.
+pub fn tool_definitions() -> Vec<ToolDefinition> {
+    use serde_json::json;
+
+    let string = |description: &str| json!({ "type": "string", "description": description });
+    let define = |name: &str, description: &str, mut properties: serde_json::Map<String, serde_json::Value>, required: &[&str]| {
+        properties.insert("thought".to_string(), string("Your reasoning for choosing this tool for the current step."));
+        let mut required: Vec<&str> = required.to_vec();
+        required.insert(0, "thought");
+        ToolDefinition {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters: json!({ "type": "object", "properties": properties, "required": required }),
+        }
+    };
+    let props = |pairs: Vec<(&str, serde_json::Value)>| pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect::<serde_json::Map<_, _>>();
+
+    vec![
+        define(
+            "ReadFile",
+            "Examine the contents of an existing text file. Large files are shortened; read specific parts with start_line and end_line.",
+            props(vec![
+                ("path", string("Path of the file to read.")),
+                ("start_line", json!({ "type": "integer", "description": "First line to read (1-based)." })),
+                ("end_line", json!({ "type": "integer", "description": "Last line to read (inclusive)." })),
+            ]),
+            &["path"],
+        ),
+        define(
+            "ReadImage",
+            "Look at an image such as a screenshot of a failing UI or a design mock. The image is shown to you with every following decision.",
+            props(vec![("path", string("Path of the PNG, JPEG, GIF or WebP image."))]),
+            &["path"],
+        ),
+        define(
+            "ReadToolOutput",
+            "Read the full output of an earlier tool call that was summarized because it was too long. The summary names the output's id.",
+            props(vec![
+                ("id", string("Id of the saved output, e.g. \"out-1a2b3c4d\".")),
+                ("range", string("Lines to read, e.g. \"120-200\". Omit to read the whole output.")),
+            ]),
+            &["id"],
+        ),
+        define(
+            "WriteFile",
+            "Save content to a file. For code, use CodeGeneration instead.",
+            props(vec![("path", string("Path of the file to write.")), ("content", string("The content to write."))]),
+            &["path", "content"],
+        ),
+        define(
+            "EditFile",
+            "Make targeted changes to an existing file. Each search must match exactly once; if any edit conflicts, nothing is written.",
+            props(vec![
+                ("path", string("Path of the file to edit.")),
+                ("edits", json!({
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": { "search": string("Exact existing text."), "replace": string("Replacement text.") },
+                        "required": ["search", "replace"],
+                    },
+                })),
+            ]),
+            &["path", "edits"],
+        ),
+        define(
+            "CreateDirectory",
+            "Create a directory, including any missing parent directories.",
+            props(vec![("path", string("Path of the directory to create."))]),
+            &["path"],
+        ),
+        define(
+            "RunCommand",
+            "Execute a shell command, like running tests, building code, or installing dependencies.",
+            props(vec![
+                ("command", string("The shell command to run.")),
+                ("timeout_secs", json!({ "type": "integer", "description": "Seconds before the command is killed. Only set this for commands expected to run unusually long." })),
+                ("cwd", string("Directory to run the command in, e.g. \"frontend\". Use this instead of `cd dir && ...`.")),
+                ("env", json!({ "type": "object", "additionalProperties": { "type": "string" }, "description": "Environment variables for this command only, e.g. {\"DATABASE_URL\": \"...\"}. Use this instead of `FOO=bar cmd`." })),
+                ("stdin", string("Text to write to the command's standard input, e.g. answers to prompts.")),
+            ]),
+            &["command"],
+        ),
+        define(
+            "RunTests",
+            "Run the project's test suite (cargo test, pytest, npm test or go test, detected from the project) and get pass/fail counts with the failing tests and their errors.",
+            props(vec![("framework_hint", string("Test runner to use when detection would be wrong, e.g. \"cargo\", \"pytest\", \"jest\" or \"go\"."))]),
+            &[],
+        ),
+        define(
+            "ScaffoldProject",
+            "Create a new project with its standard generator: cargo new for Rust, npm init for Node, poetry new for Python, go mod init for Go.",
+            props(vec![
+                ("template", string("One of \"rust-bin\", \"rust-lib\", \"node\", \"python\" or \"go\".")),
+                ("name", string("Name of the project and of the directory it is created in.")),
+            ]),
+            &["template", "name"],
+        ),
+        define(
+            "RunScript",
+            "Run a short script in a sandboxed interpreter for data processing, such as parsing, converting or summarizing files. It can read the working directory, write only to output_dir, and has no network or shell.",
+            props(vec![
+                ("language", string("Language of the script, one of the configured script runtimes, e.g. \"python\".")),
+                ("code", string("The complete script. It runs with the working directory as its current directory.")),
+                ("output_dir", string("Directory the script may write files to, e.g. \"out\". Omit it for scripts that only print.")),
+            ]),
+            &["language", "code"],
+        ),
+        define(
+            "Search",
+            "Search the web for up-to-date information or to research a library/API.",
+            props(vec![("query", string("The search query."))]),
+            &["query"],
+        ),
+        define(
+            "FetchUrl",
+            "Download a web page (e.g. documentation found via Search) and read it as plain text.",
+            props(vec![("url", string("The http or https URL to read."))]),
+            &["url"],
+        ),
+        define(
+            "HttpRequest",
+            "Call a REST API, e.g. a service you are writing code against, to see the real status, headers and body of its responses. Only allowed hosts can be called; redirects are not followed.",
+            props(vec![
+                ("method", string("GET, HEAD, OPTIONS, POST, PUT, PATCH or DELETE.")),
+                ("url", string("The http or https URL to call.")),
+                ("headers", json!({ "type": "object", "additionalProperties": { "type": "string" }, "description": "Request headers, e.g. {\"Content-Type\": \"application/json\"}." })),
+                ("body", string("The request body, e.g. a JSON document.")),
+            ]),
+            &["method", "url"],
+        ),
+        define(
+            "DbQuery",
+            "Run a read-only SQL query (SELECT or EXPLAIN) against a configured database, e.g. to inspect the real schema of a table before writing code against it. Returns the rows as a table.",
+            props(vec![
+                ("connection_name", string("Name of one of the configured database connections.")),
+                ("sql", string("A single SELECT (or WITH ... SELECT) or EXPLAIN statement.")),
+            ]),
+            &["connection_name", "sql"],
+        ),
+        define(
+            "SearchInFiles",
+            "Find where a symbol or string appears in the codebase. Returns file:line:snippet matches and skips files ignored by .gitignore.",
+            props(vec![
+                ("pattern", string("Text (or regular expression when regex is true) to find.")),
+                ("path", string("Directory to search, e.g. \".\".")),
+                ("regex", json!({ "type": "boolean", "description": "Treat pattern as a regular expression." })),
+            ]),
+            &["pattern", "path"],
+        ),
+        define(
+            "ListFiles",
+            "See the layout of a directory as a tree. Skips files ignored by .gitignore.",
+            props(vec![
+                ("path", string("Directory to list, e.g. \".\".")),
+                ("max_depth", json!({ "type": "integer", "description": "How many levels below path to list. Use 1 or 2 for a quick overview of large directories." })),
+                ("glob", string("Only list files matching this glob, e.g. \"*.rs\" or \"src/**/*.toml\".")),
+            ]),
+            &["path"],
+        ),
+        define(
+            "CodeOutline",
+            "See the structure of Rust code without reading it all: the items of a .rs file, or of every .rs file in a directory, with their line numbers and signatures. Read the lines you need afterwards with ReadFile.",
+            props(vec![("path", string("A .rs file or a directory, e.g. \"src\"."))]),
+            &["path"],
+        ),
+        define(
+            "CodeGeneration",
+            "Write code when the step explicitly requires it. The task is a detailed prompt for another AI that will only write the code.",
+            props(vec![
+                ("task", string("A clear, specific instruction for the coder agent.")),
+                ("file_path", string("Where the generated code should be saved. Omit it when the task creates several files; the coder names each one.")),
+            ]),
+            &["task"],
+        ),
+        define(
+            "Delegate",
+            "Split a large step into independent subtasks, such as one per module, that separate workers plan and carry out in parallel. Only for subtasks that change different files and do not depend on each other.",
+            props(vec![(
+                "subtasks",
+                json!({ "type": "array", "items": { "type": "string" }, "description": "Two or more self-contained instructions, one per worker, each naming the files it owns." }),
+            )]),
+            &["subtasks"],
+        ),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ToolResult {
     Success(String),
+    /// The tool ran but reported failure, e.g. failing tests or a script that exited non-zero. The
+    /// model is shown the output so it can react; `exit_code` is the process's, when there was one.
+    Failure { output: String, exit_code: Option<i32> },
+    /// Machine-readable output, e.g. a custom tool answering with a JSON object.
+    Structured(serde_json::Value),
+}
+
+impl ToolResult {
+    pub fn is_success(&self) -> bool {
+        !matches!(self, ToolResult::Failure { .. })
+    }
+
+    /// The output as the model and the history see it; structured output as pretty-printed JSON.
+    pub fn text(&self) -> String {
+        match self {
+            ToolResult::Success(output) | ToolResult::Failure { output, .. } => output.clone(),
+            ToolResult::Structured(value) => serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string()),
+        }
+    }
+
+    /// The history entry type the result is recorded under: failures are marked, with their exit code.
+    pub fn history_label(&self) -> String {
+        match self {
+            ToolResult::Failure { exit_code: Some(code), .. } => format!("Tool Failure (exit code {})", code),
+            ToolResult::Failure { exit_code: None, .. } => "Tool Failure".to_string(),
+            _ => "Tool Output".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    pub success: bool,
+    /// `None` when there is none to report: dry runs, Kubernetes jobs and processes killed by a signal.
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// Formats the output the way `RunCommand` reports it: stdout alone on success, the exit code and
+    /// both streams on failure.
+    pub fn to_tool_output(&self) -> String {
+        match (self.success, self.exit_code) {
+            (true, _) => self.stdout.clone(),
+            (false, Some(code)) => format!("Exit code: {}\nSTDOUT:\n{}\nSTDERR:\n{}", code, self.stdout, self.stderr),
+            (false, None) => format!("STDOUT:\n{}\nSTDERR:\n{}", self.stdout, self.stderr),
+        }
+    }
+
+    /// The output as a tool result: a `Failure` carrying the exit code when the command failed.
+    pub fn into_tool_result(self) -> ToolResult {
+        if self.success {
+            ToolResult::Success(self.to_tool_output())
+        } else {
+            ToolResult::Failure { output: self.to_tool_output(), exit_code: self.exit_code }
+        }
+    }
+}
+
+/// The shell `RunCommand` hands command lines to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shell {
+    Sh,
+    Cmd,
+    PowerShell,
+    /// Any other program that accepts `-c <command>` (bash, zsh, pwsh, ...).
+    Custom(String),
+}
+
+impl Shell {
+    /// `cmd` on Windows, `sh` everywhere else.
+    pub fn detect() -> Self {
+        if cfg!(windows) { Shell::Cmd } else { Shell::Sh }
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "sh" => Shell::Sh,
+            "cmd" | "cmd.exe" => Shell::Cmd,
+            "powershell" | "powershell.exe" => Shell::PowerShell,
+            _ => Shell::Custom(name.trim().to_string()),
+        }
+    }
+
+    /// Runs `command` through this shell, killing it once `limits.timeout` passes.
+    pub async fn run(&self, command: &str, limits: CommandLimits) -> Result<CommandOutput, AgentError> {
+        self.run_in(command, None, limits, None).await
+    }
+
+    /// Like `run`, but in `dir` instead of the process working directory when it is given, and
+    /// killed early when `cancel` is cancelled.
+    pub async fn run_in(
+        &self,
+        command: &str,
+        dir: Option<&Path>,
+        limits: CommandLimits,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<CommandOutput, AgentError> {
+        self.run_with(command, dir, &BTreeMap::new(), None, limits, cancel).await
+    }
+
+    /// Like `run_in`, with `env` added to the inherited environment and `input` fed to stdin.
+    pub async fn run_with(
+        &self,
+        command: &str,
+        dir: Option<&Path>,
+        env: &BTreeMap<String, String>,
+        input: Option<&str>,
+        limits: CommandLimits,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<CommandOutput, AgentError> {
+        let mut process = match self {
+            Shell::Sh => tokio::process::Command::new("sh"),
+            Shell::Cmd => tokio::process::Command::new("cmd"),
+            Shell::PowerShell => tokio::process::Command::new("powershell"),
+            Shell::Custom(program) => tokio::process::Command::new(program),
+        };
+        match self {
+            Shell::Sh | Shell::Custom(_) => process.arg("-c"),
+            Shell::Cmd => process.arg("/C"),
+            Shell::PowerShell => process.args(["-NoProfile", "-Command"]),
+        };
+        process.arg(command);
+        if let Some(dir) = dir {
+            process.current_dir(dir);
+        }
+        process.envs(env);
+        process::run_until_cancelled(process, command, input, limits, cancel).await
+    }
+}
+
+/// Prefix of every tool output produced in dry-run mode.
+pub const DRY_RUN_PREFIX: &str = "[dry run]";
+/// The built-in tools that need the network; offline mode neither offers nor runs them.
+pub const NETWORK_TOOLS: [&str; 3] = ["Search", "FetchUrl", "HttpRequest"];
+/// The built-in tools that neither change files nor run anything; the only ones read-only mode offers.
+pub const READ_ONLY_TOOLS: [&str; 5] = ["ReadFile", "Search", "SearchInFiles", "ListFiles", "CodeOutline"];
+
+/// The settings and working directory tools run with. Each session of a multi-session process
+/// has its own, so sessions never share configuration through the environment or the process
+/// working directory.
+#[derive(Debug, Clone)]
+pub struct ToolContext {
+    pub config: Arc<AppConfig>,
+    /// Directory relative paths and commands resolve against; the process working directory when `None`.
+    pub workdir: Option<PathBuf>,
+    /// Kills running commands when cancelled.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl ToolContext {
+    pub fn new(config: Arc<AppConfig>) -> Self {
+        Self { config, workdir: None, cancel: None }
+    }
+
+    /// Settings loaded from the environment, in the process working directory.
+    pub fn from_env() -> Result<Self, AgentError> {
+        Ok(Self::new(Arc::new(AppConfig::load()?)))
+    }
+
+    pub fn with_workdir(mut self, workdir: impl Into<PathBuf>) -> Self {
+        self.workdir = Some(workdir.into());
+        self
+    }
+
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// `path` as tools should open it: relative paths are taken relative to the workdir.
+    pub fn resolve(&self, path: &str) -> String {
+        match &self.workdir {
+            Some(dir) if Path::new(path).is_relative() => dir.join(path).to_string_lossy().into_owned(),
+            _ => path.to_string(),
+        }
+    }
+
+    /// `tool` with its file paths resolved against the workdir.
+    fn resolve_paths(&self, tool: Tool) -> Tool {
+        if self.workdir.is_none() {
+            return tool;
+        }
+        match tool {
+            Tool::ReadFile { path, start_line, end_line } => Tool::ReadFile { path: self.resolve(&path), start_line, end_line },
+            Tool::ReadImage { path } => Tool::ReadImage { path: self.resolve(&path) },
+            Tool::WriteFile { path, content } => Tool::WriteFile { path: self.resolve(&path), content },
+            Tool::EditFile { path, edits } => Tool::EditFile { path: self.resolve(&path), edits },
+            Tool::CreateDirectory { path } => Tool::CreateDirectory { path: self.resolve(&path) },
+            Tool::SearchInFiles { pattern, path, regex } => Tool::SearchInFiles { pattern, path: self.resolve(&path), regex },
+            Tool::ListFiles { path, max_depth, glob } => Tool::ListFiles { path: self.resolve(&path), max_depth, glob },
+            Tool::CodeOutline { path } => Tool::CodeOutline { path: self.resolve(&path) },
+            other => other,
+        }
+    }
+
+    fn sandbox(&self) -> Result<Sandbox, AgentError> {
+        match &self.workdir {
+            Some(dir) => Sandbox::for_root(&self.config, dir),
+            None => Sandbox::from_config(&self.config),
+        }
+    }
+}
+
+/// Runs a shell command under the sandbox and the configured limits. `timeout_secs` overrides the
+/// configured timeout.
+pub async fn run_command(command: &str, timeout_secs: Option<u64>) -> Result<CommandOutput, AgentError> {
+    run_command_in(command, timeout_secs, &ToolContext::from_env()?).await
+}
+
+/// `run_command` with the settings and working directory of `context`.
+pub async fn run_command_in(command: &str, timeout_secs: Option<u64>, context: &ToolContext) -> Result<CommandOutput, AgentError> {
+    run_command_with(command, timeout_secs, &CommandOptions::default(), context).await
+}
+
+/// Where and with what `RunCommand` runs a command, besides its timeout.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandOptions {
+    /// Directory to run in, relative to the workdir; the workdir itself when `None`.
+    pub cwd: Option<String>,
+    /// Variables added to the inherited environment.
+    pub env: BTreeMap<String, String>,
+    /// Text written to stdin; stdin is empty when `None`.
+    pub stdin: Option<String>,
+}
+
+impl CommandOptions {
+    /// Options from a fix for a failed command: fields the fix leaves unset keep their earlier value.
+    pub fn or(self, earlier: CommandOptions) -> CommandOptions {
+        CommandOptions {
+            cwd: self.cwd.or(earlier.cwd),
+            env: if self.env.is_empty() { earlier.env } else { self.env },
+            stdin: self.stdin.or(earlier.stdin),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut description = String::new();
+        if let Some(cwd) = &self.cwd {
+            description.push_str(&format!(" (in {})", cwd));
+        }
+        if !self.env.is_empty() {
+            description.push_str(&format!(" (with {} set)", self.env.keys().cloned().collect::<Vec<_>>().join(", ")));
+        }
+        if self.stdin.is_some() {
+            description.push_str(" (with input)");
+        }
+        description
+    }
+}
+
+/// `run_command_in` with a working directory, extra environment variables and stdin input. The
+/// directory must exist and, with the sandbox enabled, lie within it. With `AGENT_EXEC_BACKEND`
+/// set to `kubernetes`, the command runs as a Kubernetes Job instead of a local process.
+pub async fn run_command_with(command: &str, timeout_secs: Option<u64>, options: &CommandOptions, context: &ToolContext) -> Result<CommandOutput, AgentError> {
+    let config = &context.config;
+    let sandbox = context.sandbox()?;
+    sandbox.check_command(command)?;
+    sandbox.check_env(&options.env)?;
+    let dir = match &options.cwd {
+        Some(cwd) => {
+            let dir = context.resolve(cwd);
+            if !Path::new(&dir).is_dir() {
+                return Err(AgentError::ToolError(format!("Cannot run `{}` in {}: no such directory", command, cwd)));
+            }
+            sandbox.check_read(&dir)?;
+            Some(PathBuf::from(dir))
+        }
+        None => context.workdir.clone(),
+    };
+    if config.dry_run {
+        // Report success so callers carry on as if the command had worked.
+        return Ok(CommandOutput { success: true, exit_code: None, stdout: format!("{} Would run: {}{}", DRY_RUN_PREFIX, command, options.describe()), stderr: String::new() });
+    }
+    let limits = CommandLimits {
+        timeout: std::time::Duration::from_secs(timeout_secs.unwrap_or(config.command_timeout_secs)),
+        max_output_bytes: config.max_command_output_bytes,
+    };
+    if config.exec_backend == ExecBackend::Kubernetes {
+        return KubernetesJob::from_config(config)?.run(command, options, limits, context.cancel.as_ref()).await;
+    }
+    let shell = config.shell.as_deref().map(Shell::from_name).unwrap_or_else(Shell::detect);
+    shell.run_with(command, dir.as_deref(), &options.env, options.stdin.as_deref(), limits, context.cancel.as_ref()).await
+}
+
+/// A change `WriteFile` or `EditFile` is about to make, shown before the file is touched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangePreview {
+    /// The path as the decision named it.
+    pub path: String,
+    /// Unified diff of the current content against the new content.
+    pub diff: String,
+    /// Whether the file does not exist yet.
+    pub created: bool,
+}
+
+/// The change `tool` would make, for `WriteFile` and `EditFile`. `None` for other tools and for
+/// edits that cannot be applied, which fail when run.
+pub async fn preview_change(tool: &Tool, context: &ToolContext) -> Option<ChangePreview> {
+    let path = tool.modified_file()?.to_string();
+    let (existing, updated) = match context.resolve_paths(tool.clone()) {
+        Tool::WriteFile { path: resolved, content } => (tokio::fs::read_to_string(&resolved).await.ok(), content),
+        Tool::EditFile { path: resolved, edits } => {
+            let original = tokio::fs::read_to_string(&resolved).await.ok()?;
+            let updated = apply_edits(&original, &edits).ok()?;
+            (Some(original), updated)
+        }
+        _ => return None,
+    };
+    let created = existing.is_none();
+    let diff = unified_diff(&path, existing.as_deref().unwrap_or_default(), &updated);
+    Some(ChangePreview { path, diff, created })
+}
+
+/// Runs the project's test suite with the framework named by `hint` or detected in the workdir,
+/// and reads the results from its output.
+pub async fn run_tests(hint: Option<&str>, context: &ToolContext) -> Result<TestReport, AgentError> {
+    let dir = match &context.workdir {
+        Some(dir) => dir.clone(),
+        None => std::env::current_dir()?,
+    };
+    let framework = test_runner::resolve_framework(hint, &dir)?;
+    let output = run_command_in(framework.command(), None, context).await?;
+    Ok(framework.parse(&format!("{}\n{}", output.stdout, output.stderr), output.success))
+}
+
+/// Creates project `name` in the workdir with the generator for `template`, refusing to touch an
+/// existing directory, and returns the generator's output followed by the new project's layout.
+pub async fn scaffold_project(template: &str, name: &str, context: &ToolContext) -> Result<String, AgentError> {
+    let template = scaffold::resolve_template(template)?;
+    scaffold::check_project_name(name)?;
+    let dir = PathBuf::from(context.resolve(name));
+    context.sandbox()?.check_write(&dir.to_string_lossy())?;
+    if dir.exists() {
+        return Err(AgentError::ToolError(format!("{} already exists; choose another project name or edit the existing project", name)));
+    }
+    let command = template.command(name);
+    if context.config.dry_run {
+        return Ok(format!("{} Would create {} project {} with: {}", DRY_RUN_PREFIX, template, name, command));
+    }
+    let output = if template.runs_inside() {
+        tokio::fs::create_dir_all(&dir).await?;
+        run_command_in(&command, None, &context.clone().with_workdir(&dir)).await?
+    } else {
+        run_command_in(&command, None, context).await?
+    };
+    if !output.success {
+        return Err(AgentError::ToolError(format!("`{}` failed:\n{}", command, output.to_tool_output())));
+    }
+    let root = dir.clone();
+    let layout = tokio::task::spawn_blocking(move || list_files(&root, Some(2), None))
+        .await
+        .map_err(|e| AgentError::ToolError(format!("ScaffoldProject task failed: {}", e)))??;
+    let generator_output = format!("{}\n{}", output.stdout, output.stderr);
+    Ok(format!("Created {} project {} with `{}`.\n{}\n{}", template, name, command, generator_output.trim(), layout))
+}
+
+/// Saves `code` under `script::SCRIPT_DIR` in the workdir and runs it with the runtime configured
+/// for `language`, under the command timeout and output limits. The script may read the workdir
+/// and write only to `output_dir`, which is created when missing and must lie within the sandbox.
+pub async fn run_script(language: &str, code: &str, output_dir: Option<&str>, context: &ToolContext) -> Result<CommandOutput, AgentError> {
+    let config = &context.config;
+    let runtime = script::find_runtime(&config.script_runtimes, language)?;
+    let root = context.workdir.clone().map_or_else(std::env::current_dir, Ok)?;
+    let writable = match output_dir {
+        Some(dir) => {
+            let resolved = context.sandbox()?.check_write(&context.resolve(dir))?;
+            Some((resolved, dir.to_string()))
+        }
+        None => None,
+    };
+    if config.dry_run {
+        let target = output_dir.map(|dir| format!(" writing to {}", dir)).unwrap_or_default();
+        return Ok(CommandOutput { success: true, exit_code: None, stdout: format!("{} Would run a {}-line {} script{}", DRY_RUN_PREFIX, code.lines().count(), runtime.language, target), stderr: String::new() });
+    }
+    if let Some((dir, _)) = &writable {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let name = script::save_script(&root.join(SCRIPT_DIR), code)?;
+    let limits = CommandLimits {
+        timeout: std::time::Duration::from_secs(config.command_timeout_secs),
+        max_output_bytes: config.max_command_output_bytes,
+    };
+    let access = script::ScriptAccess { root, writable };
+    info!("Running {} script {}/{}", runtime.language, SCRIPT_DIR, name);
+    script::run_script(runtime, &format!("{}/{}", SCRIPT_DIR, name), access, limits, context.cancel.as_ref()).await
+}
+
+/// Runs the read-only statement `sql` on the connection named `connection_name` and returns its
+/// rows as a table. SQLite database files are resolved against the workdir and must be readable
+/// within the sandbox. Queries only read, so they also run in dry-run mode.
+pub async fn db_query(connection_name: &str, sql: &str, context: &ToolContext) -> Result<String, AgentError> {
+    let config = &context.config;
+    let connection = db::find_connection(&config.db_connections, connection_name)?;
+    let sql = db::check_read_only(sql, connection.kind())?;
+    let sqlite_file = match connection.sqlite_path() {
+        Some(path) => Some(context.sandbox()?.check_read(&context.resolve(path))?),
+        None => None,
+    };
+    info!("Querying {}: {}", connection.name, sql);
+    let timeout = std::time::Duration::from_secs(config.command_timeout_secs);
+    let result = db::run_query(connection, sqlite_file.as_deref(), &sql, config.max_db_rows, timeout).await?;
+    Ok(result.to_table())
+}
+
+/// Rewrites Windows `\` separators to `/` so paths look the same on every platform.
+pub fn normalize_path_separators(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// Atomically writes `content` to `path`, first backing up the existing file into `backup_root`
+/// when one is given. Returns where the backup went.
+async fn save_file(path: &str, content: String, backup_root: Option<String>) -> Result<Option<std::path::PathBuf>, AgentError> {
+    let path = std::path::PathBuf::from(path);
+    tokio::task::spawn_blocking(move || {
+        let saved = match backup_root {
+            Some(root) => backup_file(&path, Path::new(&root))?,
+            None => None,
+        };
+        write_atomic(&path, &content)?;
+        Ok(saved)
+    })
+    .await
+    .map_err(|e| AgentError::ToolError(format!("Write task failed: {}", e)))?
 }
 
 pub async fn run_tool(tool: Tool) -> Result<ToolResult, AgentError> {
+    run_tool_in(tool, &ToolContext::from_env()?).await
+}
+
+/// `run_tool` with the settings and working directory of `context`.
+pub async fn run_tool_in(tool: Tool, context: &ToolContext) -> Result<ToolResult, AgentError> {
+    let config = &context.config;
+    if config.offline && tool.needs_network() {
+        return Err(offline_error(&tool.name()));
+    }
+    let sandbox = context.sandbox()?;
+    let tool = context.resolve_paths(tool);
     match tool {
-        Tool::ReadFile { path } => {
-            let content = tokio::fs::read_to_string(path).await?;
+        Tool::ReadFile { path, start_line, end_line } => {
+            sandbox.check_read(&path)?;
+            let max_bytes = config.max_read_bytes;
+            let content = tokio::task::spawn_blocking(move || read_file(Path::new(&path), start_line, end_line, max_bytes))
+                .await
+                .map_err(|e| AgentError::ToolError(format!("ReadFile task failed: {}", e)))??;
+            Ok(ToolResult::Success(content))
+        }
+        Tool::ReadImage { path } => {
+            sandbox.check_read(&path)?;
+            let image = {
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || read_image(Path::new(&path)))
+                    .await
+                    .map_err(|e| AgentError::ToolError(format!("ReadImage task failed: {}", e)))??
+            };
+            Ok(ToolResult::Success(format!("Attached {} ({}); it is shown with the following decisions.", path, image.media_type)))
+        }
+        Tool::ReadToolOutput { id, range } => {
+            let path = output_store::output_path(Path::new(&context.resolve(TOOL_OUTPUT_DIR)), &id)?;
+            let (start_line, end_line) = match range.as_deref() {
+                Some(range) => {
+                    let (start, end) = output_store::parse_range(range)?;
+                    (Some(start), end)
+                }
+                None => (None, None),
+            };
+            let max_bytes = config.max_read_bytes;
+            let content = tokio::task::spawn_blocking(move || read_file(&path, start_line, end_line, max_bytes))
+                .await
+                .map_err(|e| AgentError::ToolError(format!("ReadToolOutput task failed: {}", e)))??;
             Ok(ToolResult::Success(content))
         }
         Tool::WriteFile { path, content } => {
-            tokio::fs::write(path, content).await?;
-            Ok(ToolResult::Success("File written successfully.".to_string()))
+            sandbox.check_write(&path)?;
+            if config.dry_run {
+                let existing = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+                return Ok(ToolResult::Success(format!("{} Would write {} bytes to {}:\n{}", DRY_RUN_PREFIX, content.len(), path, unified_diff(&path, &existing, &content))));
+            }
+            let backup = save_file(&path, content, config.backup_writes.then(|| context.resolve(write::BACKUP_DIR))).await?;
+            Ok(ToolResult::Success(match backup {
+                Some(backup) => format!("File written successfully (previous version backed up to {}).", backup.display()),
+                None => "File written successfully.".to_string(),
+            }))
         }
-        Tool::RunCommand { command } => {
-            let output = tokio::process::Command::new("sh").arg("-c").arg(command).output().await?;
-            let result = if output.status.success() {
-                String::from_utf8_lossy(&output.stdout).to_string()
+        Tool::EditFile { path, edits } => {
+            sandbox.check_write(&path)?;
+            let original = tokio::fs::read_to_string(&path).await?;
+            let updated = apply_edits(&original, &edits)?;
+            if config.dry_run {
+                return Ok(ToolResult::Success(format!("{} Would apply {} edit(s) to {}:\n{}", DRY_RUN_PREFIX, edits.len(), path, unified_diff(&path, &original, &updated))));
+            }
+            save_file(&path, updated, config.backup_writes.then(|| context.resolve(write::BACKUP_DIR))).await?;
+            Ok(ToolResult::Success(format!("Applied {} edit(s) to {}.", edits.len(), path)))
+        }
+        Tool::CreateDirectory { path } => {
+            sandbox.check_write(&path)?;
+            if Path::new(&path).is_dir() {
+                return Ok(ToolResult::Success(format!("Directory {} already exists.", path)));
+            }
+            if config.dry_run {
+                return Ok(ToolResult::Success(format!("{} Would create directory {}", DRY_RUN_PREFIX, path)));
+            }
+            tokio::fs::create_dir_all(&path).await?;
+            Ok(ToolResult::Success(format!("Created directory {}.", path)))
+        }
+        Tool::RunCommand { command, timeout_secs, cwd, env, stdin } => {
+            let output = run_command_with(&command, timeout_secs, &CommandOptions { cwd, env, stdin }, context).await?;
+            Ok(output.into_tool_result())
+        }
+        Tool::RunTests { framework_hint } => {
+            if config.dry_run {
+                let dir = context.workdir.clone().map_or_else(std::env::current_dir, Ok)?;
+                let framework = test_runner::resolve_framework(framework_hint.as_deref(), &dir)?;
+                return Ok(ToolResult::Success(format!("{} Would run tests: {}", DRY_RUN_PREFIX, framework.command())));
+            }
+            let report = run_tests(framework_hint.as_deref(), context).await?;
+            if report.success {
+                Ok(ToolResult::Success(report.summary()))
             } else {
-                format!("STDOUT:\n{}\nSTDERR:\n{}", 
-                    String::from_utf8_lossy(&output.stdout),
-                    String::from_utf8_lossy(&output.stderr)
-                )
-            };
-            Ok(ToolResult::Success(result))
+                Ok(ToolResult::Failure { output: report.summary(), exit_code: None })
+            }
+        }
+        Tool::ScaffoldProject { template, name } => Ok(ToolResult::Success(scaffold_project(&template, &name, context).await?)),
+        Tool::RunScript { language, code, output_dir } => {
+            Ok(run_script(&language, &code, output_dir.as_deref(), context).await?.into_tool_result())
         }
         Tool::Search { query } => {
-            info!("Performing web search for: {}", query);
-            let config = AppConfig::load()?;
-            let api_key = config.brave_search_api_key.ok_or_else(|| AgentError::ApiKeyMissing("Brave Search".to_string()))?;
-            let client = reqwest::Client::new();
-            let url = format!("https://api.search.brave.com/res/v1/web/search?q={}", query);
-            let response = client.get(url).header("X-Subscription-Token", api_key).send().await?;
-            
-            if !response.status().is_success() {
-                return Err(AgentError::ToolError(format!("Brave Search API Error: {}", response.text().await?)));
-            }
-            
-            #[derive(Deserialize)]
-            struct BraveResponse { web: Web }
-            #[derive(Deserialize)]
-            struct Web { results: Vec<BraveResult> }
-            #[derive(Deserialize)]
-            struct BraveResult { title: String, url: String, description: String }
-
-            let body: BraveResponse = response.json().await?;
-            let mut result_string = String::new();
-            for (i, res) in body.web.results.into_iter().take(3).enumerate() {
-                result_string.push_str(&format!("[Result {}]\nTitle: {}\nURL: {}\nSnippet: {}\n\n", i+1, res.title, res.url, res.description));
+            let provider = create_search_provider(config)?;
+            info!("Performing web search with {} for: {}", provider.name(), query);
+            let results = provider.search(&query).await?;
+            Ok(ToolResult::Success(web_search::format_results(&results)))
+        }
+        Tool::FetchUrl { url } => {
+            info!("Fetching {}", url);
+            Ok(ToolResult::Success(fetch_url(&url, config.max_fetch_bytes).await?))
+        }
+        Tool::HttpRequest { method, url, headers, body } => {
+            if config.dry_run && !http::is_safe_method(&method) {
+                return Ok(ToolResult::Success(format!("{} Would send {} {}", DRY_RUN_PREFIX, method.to_uppercase(), url)));
             }
-            Ok(ToolResult::Success(result_string))
-        }
-        Tool::ListFiles { path } => {
-            let mut files = String::new();
-            for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-                let path = entry.path().display().to_string();
-                if !path.contains("target/") && !path.contains(".git/") {
-                     files.push_str(&path);
-                     files.push('\n');
+            info!("Sending {} {}", method, url);
+            Ok(ToolResult::Success(http_request(&method, &url, &headers, body.as_deref(), &config.http_allowed_hosts, config.max_http_bytes).await?))
+        }
+        Tool::DbQuery { connection_name, sql } => Ok(ToolResult::Success(db_query(&connection_name, &sql, context).await?)),
+        Tool::SearchInFiles { pattern, path, regex } => {
+            sandbox.check_read(&path)?;
+            info!("Searching files under {} for: {}", path, pattern);
+            let root = path.clone();
+            let query = pattern.clone();
+            let mut matches = tokio::task::spawn_blocking(move || search_in_files(&query, Path::new(&root), regex))
+                .await
+                .map_err(|e| AgentError::ToolError(format!("SearchInFiles task failed: {}", e)))??;
+            if let Some(dir) = &context.workdir {
+                // Report matches relative to the workdir, as if the search had run there.
+                for found in &mut matches {
+                    if let Ok(relative) = Path::new(&found.path).strip_prefix(dir) {
+                        found.path = relative.display().to_string();
+                    }
                 }
             }
-            Ok(ToolResult::Success(files))
-        },
+            Ok(ToolResult::Success(search::format_matches(&pattern, &matches)))
+        }
+        Tool::ListFiles { path, max_depth, glob } => {
+            sandbox.check_read(&path)?;
+            let listing = tokio::task::spawn_blocking(move || list_files(Path::new(&path), max_depth, glob.as_deref()))
+                .await
+                .map_err(|e| AgentError::ToolError(format!("ListFiles task failed: {}", e)))??;
+            let listing = if cfg!(windows) { normalize_path_separators(&listing) } else { listing };
+            Ok(ToolResult::Success(listing))
+        }
+        Tool::CodeOutline { path } => {
+            sandbox.check_read(&path)?;
+            let outline = tokio::task::spawn_blocking(move || code_outline(Path::new(&path)))
+                .await
+                .map_err(|e| AgentError::ToolError(format!("CodeOutline task failed: {}", e)))??;
+            Ok(ToolResult::Success(outline))
+        }
         Tool::CodeGeneration {..} => {
             Err(AgentError::ToolError("CodeGeneration is not a runnable tool.".to_string()))
         }
+        Tool::Delegate {..} => {
+            Err(AgentError::ToolError("Delegate is not a runnable tool; the orchestrator runs its subtasks.".to_string()))
+        }
+        Tool::Custom { name, .. } => {
+            Err(AgentError::ToolError(format!("{} is not a built-in tool; run it through the ToolRegistry it was registered with.", name)))
+        }
     }
 }
 
+/// Applies all edits in order, failing without changes if any block is missing or ambiguous.
+pub fn apply_edits(content: &str, edits: &[FileEdit]) -> Result<String, AgentError> {
+    if edits.is_empty() {
+        return Err(AgentError::ToolError("EditFile requires at least one edit.".to_string()));
+    }
+    let mut updated = content.to_string();
+    let mut conflicts = Vec::new();
+    for (i, edit) in edits.iter().enumerate() {
+        if edit.search.is_empty() {
+            conflicts.push(format!("edit {}: search text is empty", i + 1));
+            continue;
+        }
+        match updated.matches(edit.search.as_str()).count() {
+            1 => updated = updated.replacen(edit.search.as_str(), &edit.replace, 1),
+            0 => conflicts.push(format!("edit {}: search text not found", i + 1)),
+            n => conflicts.push(format!("edit {}: search text matches {} locations", i + 1, n)),
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(AgentError::ToolError(format!("EditFile conflicts, no changes written: {}", conflicts.join("; "))));
+    }
+    Ok(updated)
+}
+
+/// How each built-in tool is offered in the text decision prompt, in prompt order.
+pub(crate) const BUILTIN_TOOL_PROMPTS: [(&str, &str); 16] = [
+    ("ReadFile", r#"`ReadFile { "path": "path/to/file.ext", "start_line": 1, "end_line": 200 }`: Use when you need to examine the contents of an existing text file. `start_line` and `end_line` are optional; use them to read parts of large files, which are otherwise shortened."#),
+    ("ReadImage", r#"`ReadImage { "path": "path/to/screenshot.png" }`: Use to look at an image, such as a screenshot of a failing UI or a design mock. The image is shown to you with every following decision."#),
+    ("ReadToolOutput", r#"`ReadToolOutput { "id": "out-1a2b3c4d", "range": "120-200" }`: Use to read the full output of an earlier command or tool that was summarized because it was too long; the summary names the id. `range` is optional; use it to page through long outputs."#),
+    ("WriteFile", r#"`WriteFile { "path": "path/to/save.ext", "content": "The content to write" }`: Use when saving content. For code, use CodeGeneration instead."#),
+    ("EditFile", r#"`EditFile { "path": "path/to/file.ext", "edits": [{ "search": "exact existing text", "replace": "new text" }] }`: Use for targeted changes to an existing file instead of rewriting it. Each `search` must match exactly once; if any edit conflicts, nothing is written."#),
+    ("CreateDirectory", r#"`CreateDirectory { "path": "path/to/dir" }`: Use to create a directory, including any missing parent directories."#),
+    ("RunCommand", r#"`RunCommand { "command": "e.g., cargo test", "cwd": "backend", "env": { "RUST_LOG": "debug" }, "stdin": "y\n" }`: Use for executing shell commands, like running tests, building code, or installing dependencies. `cwd` (the directory to run in), `env` (variables for this command only) and `stdin` (input to feed it) are optional; use them instead of `cd dir && FOO=bar cmd` strings. Commands are killed after a timeout; add `"timeout_secs"` only for commands expected to run unusually long."#),
+    ("RunTests", r#"`RunTests { "framework_hint": "cargo" }`: Use to run the project's test suite. The test command is detected from the project (cargo test, pytest, npm test, go test) and the result is summarized as pass/fail counts with the failing tests and their errors. `framework_hint` is optional; set it only when detection would pick the wrong runner."#),
+    ("ScaffoldProject", r#"`ScaffoldProject { "template": "rust-bin", "name": "my_project" }`: Use to start a new project with its standard generator instead of RunCommand. `template` is one of "rust-bin", "rust-lib" (cargo new), "node" (npm init), "python" (poetry new) or "go" (go mod init); `name` is the new project's directory."#),
+    ("Search", r#"`Search { "query": "Your search query" }`: Use when you need up-to-date information or to research a library/API."#),
+    ("FetchUrl", r#"`FetchUrl { "url": "https://..." }`: Use to read a web page, such as documentation found with Search. Returns the page as plain text."#),
+    ("HttpRequest", r#"`HttpRequest { "method": "POST", "url": "http://localhost:8080/users", "headers": { "Content-Type": "application/json" }, "body": "{\"name\": \"Ada\"}" }`: Use to call a REST API, such as a service you are writing code against, and see the real status, headers and body of its response instead of guessing its shape. `headers` and `body` are optional. Only allowed hosts can be called, and redirects are not followed."#),
+    ("SearchInFiles", r#"`SearchInFiles { "pattern": "text to find", "path": ".", "regex": false }`: Use to find where a symbol or string appears in the codebase without reading every file. Returns `file:line:snippet` matches and skips files ignored by .gitignore. Set `regex` to true to treat `pattern` as a regular expression."#),
+    ("ListFiles", r#"`ListFiles { "path": ".", "max_depth": 2, "glob": "*.rs" }`: Use to see the layout of a directory as a tree. `max_depth` and `glob` are optional; set them to keep listings of large directories short."#),
+    ("CodeOutline", r#"`CodeOutline { "path": "src/config.rs" }`: Use to see the structure of Rust code, a `.rs` file or a directory of them, as the items (functions, types, traits, impls and their methods) with their line numbers and signatures, without reading every body. Then read the parts you need with ReadFile and `start_line`/`end_line`."#),
+    ("CodeGeneration", r#"`CodeGeneration { "task": "A clear, specific instruction for the coder agent" }`: Use this when the step explicitly requires writing code. The `task` should be a detailed prompt for another AI that will *only* write the code. For tasks that create several files, such as scaffolding a project, list every file path in the `task` and omit `file_path`; all of the files are saved."#),
+];
+
+/// How `Tool::RunScript` is offered in the text decision prompt when script runtimes are
+/// configured; the registry appends the available languages.
+pub(crate) const RUN_SCRIPT_TOOL_PROMPT: (&str, &str) = ("RunScript", r#"`RunScript { "language": "python", "code": "import csv\n...", "output_dir": "out" }`: Use for data-processing subtasks, such as parsing, converting or summarizing files, instead of RunCommand. The script runs in a sandboxed interpreter with the working directory as its current directory: it can read files there, but can only write inside `output_dir` (optional; omit it for scripts that only print), and has no network or shell. Its output is returned to you."#);
+
+/// How `Tool::DbQuery` is offered in the text decision prompt when database connections are
+/// configured; the registry appends the connection names.
+pub(crate) const DB_QUERY_TOOL_PROMPT: (&str, &str) = ("DbQuery", r#"`DbQuery { "connection_name": "app", "sql": "SELECT column_name, data_type FROM information_schema.columns WHERE table_name = 'users'" }`: Use to inspect a database the project uses, such as the columns of a table before writing code against it, instead of guessing its schema. Only single SELECT and EXPLAIN statements are allowed, and long results are cut off, so select the columns and rows you need."#);
+
+/// How `Tool::Delegate` is offered in the text decision prompt when delegation is enabled.
+pub(crate) const DELEGATE_TOOL_PROMPT: (&str, &str) = ("Delegate", r#"`Delegate { "subtasks": ["Implement module A in src/a.rs", "Implement module B in src/b.rs"] }`: Use for a large step made of independent parts, such as one module each. Every subtask goes to a separate worker that plans and carries it out in parallel with the others, and their results are reported back to you. Only use it when the subtasks change different files and do not depend on each other's results."#);
+
 pub fn get_decision_prompt(step: &str, context: &str) -> String {
+    let tools: Vec<String> = BUILTIN_TOOL_PROMPTS.iter().map(|(_, usage)| usage.to_string()).collect();
+    decision_prompt(step, context, &tools)
+}
+
+/// The decision prompt offering `tools`, one usage line per tool, numbered in order.
+pub(crate) fn decision_prompt(step: &str, context: &str, tools: &[String]) -> String {
+    let tools = tools.iter().enumerate().map(|(i, usage)| format!("{}. {}", i + 1, usage)).collect::<Vec<_>>().join("\n");
     format!(r#"
 You are the reasoning engine for a CLI agent. Your job is to decide which tool to use to accomplish the current step of a plan.
 You must respond in a specific JSON format.
@@ -108,14 +1075,13 @@ You must respond in a specific JSON format.
 {step}
 --- END CURRENT STEP ---
 
+History entries marked "Tool Failure" or "Command Failed" are tools and commands that ran but failed, with
+their exit code when they returned one; their output says why. If the latest of them has not been resolved yet,
+address it (fix its cause, or re-run it corrected) before continuing with the rest of the step.
+
 Based on the context and the current step, which tool should be used?
 Here are the available tools:
-1. `ReadFile {{ "path": "path/to/file.ext" }}`: Use when you need to examine the contents of an existing file.
-2. `WriteFile {{ "path": "path/to/save.ext", "content": "The content to write" }}`: Use when saving content. For code, use CodeGeneration instead.
-3. `RunCommand {{ "command": "e.g., cargo test" }}`: Use for executing shell commands, like running tests, building code, or installing dependencies.
-4. `Search {{ "query": "Your search query" }}`: Use when you need up-to-date information or to research a library/API.
-5. `ListFiles {{ "path": "." }}`: Use to see the layout of the current directory.
-6. `CodeGeneration {{ "task": "A clear, specific instruction for the coder agent" }}`: Use this when the step explicitly requires writing code. The `task` should be a detailed prompt for another AI that will *only* write the code.
+{tools}
 
 --- RESPONSE FORMAT ---
 You MUST respond with a single JSON object matching this structure:
@@ -131,3 +1097,31 @@ You MUST respond with a single JSON object matching this structure:
 Now, make your decision for the current step.
 "#)
 }
+
+pub fn get_fix_prompt(step: &str, command: &str, output: &str, context: &str) -> String {
+    get_decision_prompt(&fix_step(step, command, output), context)
+}
+
+/// Asks for `response`, an answer to the decision `prompt` that could not be used, to be corrected.
+pub(crate) fn repair_prompt(prompt: &str, response: &str, error: &str) -> String {
+    format!(
+        "{prompt}\n\n--- YOUR PREVIOUS RESPONSE ---\n{response}\n--- END PREVIOUS RESPONSE ---\n\nThat response could not be used: {error}\nRespond again with only the corrected JSON object: no markdown fences, comments or text around it."
+    )
+}
+
+/// The error for `tool`, a network tool, chosen in offline mode.
+pub(crate) fn offline_error(tool: &str) -> AgentError {
+    AgentError::ToolError(format!("{} needs the network, which offline mode (--offline) disables; work from local files and commands instead.", tool))
+}
+
+/// The error for `tool`, one that changes files or runs something, chosen in read-only mode.
+pub(crate) fn read_only_error(tool: &str) -> AgentError {
+    AgentError::ToolError(format!("{} is not available in read-only mode; use {} to look at the code.", tool, READ_ONLY_TOOLS.join(", ")))
+}
+
+/// The "step" a fix decision is asked for after `command` failed while working on `step`.
+pub(crate) fn fix_step(step: &str, command: &str, output: &str) -> String {
+    format!(
+        "While working on the step \"{step}\", the command `{command}` failed with this output:\n{output}\n\nChoose the tool that fixes the cause of this failure. To retry with a corrected command, choose RunCommand with the new command; otherwise the original command will be re-run after your fix is applied."
+    )
+}
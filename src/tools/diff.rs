@@ -0,0 +1,34 @@
+use similar::TextDiff;
+
+/// Renders a unified diff of `old` → `new` for `path`, or a note when nothing would change.
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    if old == new {
+        return format!("(no changes to {})", path);
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let diff = unified_diff("src/lib.rs", "one\ntwo\n", "one\n2\n");
+        assert!(diff.starts_with("--- a/src/lib.rs\n+++ b/src/lib.rs\n"));
+        assert!(diff.contains("-two\n"));
+        assert!(diff.contains("+2\n"));
+        assert!(diff.contains(" one\n"));
+    }
+
+    #[test]
+    fn test_unified_diff_new_file_and_no_change() {
+        let diff = unified_diff("new.txt", "", "hello\n");
+        assert!(diff.contains("+hello"));
+        assert_eq!(unified_diff("same.txt", "a\n", "a\n"), "(no changes to same.txt)");
+    }
+}
@@ -0,0 +1,181 @@
+use regex::{Regex, RegexBuilder};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::error::AgentError;
+
+/// Bytes of a page `FetchUrl` downloads by default before it stops reading.
+pub const DEFAULT_MAX_FETCH_BYTES: usize = 1024 * 1024;
+/// Characters of readable text returned to the agent; longer pages are cut off with a marker.
+pub const MAX_FETCH_TEXT_CHARS: usize = 24_000;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Downloads `url` (http or https only) and returns its readable text. HTML is converted to plain
+/// text with markdown-style headings, list items and links; other text types are returned as is.
+/// Binary content types are refused and at most `max_bytes` of the body are read.
+pub async fn fetch_url(url: &str, max_bytes: usize) -> Result<String, AgentError> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| AgentError::ToolError(format!("Invalid URL '{}': {}", url, e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(AgentError::ToolError(format!("FetchUrl only supports http and https URLs, not '{}'", parsed.scheme())));
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent(concat!("rust-cli-agent/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let mut response = client.get(parsed).send().await?;
+    if !response.status().is_success() {
+        return Err(AgentError::ToolError(format!("Fetching {} failed with HTTP {}", url, response.status())));
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("text/html")
+        .to_lowercase();
+    let is_html = content_type.contains("html");
+    let is_text = is_html || content_type.starts_with("text/") || content_type.contains("json") || content_type.contains("xml");
+    if !is_text {
+        return Err(AgentError::ToolError(format!("FetchUrl cannot read {} content from {}", content_type, url)));
+    }
+
+    let mut body = Vec::new();
+    let mut truncated = false;
+    while let Some(chunk) = response.chunk().await? {
+        let remaining = max_bytes - body.len();
+        if chunk.len() > remaining {
+            body.extend_from_slice(&chunk[..remaining]);
+            truncated = true;
+            break;
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let raw = String::from_utf8_lossy(&body);
+    let mut text = if is_html { html_to_text(&raw) } else { raw.trim().to_string() };
+    if let Some((cut, _)) = text.char_indices().nth(MAX_FETCH_TEXT_CHARS) {
+        text.truncate(cut);
+        truncated = true;
+    }
+    if truncated {
+        text.push_str("\n[... page truncated ...]");
+    }
+    Ok(format!("Content of {}:\n\n{}", url, text))
+}
+
+fn regex(cache: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cache.get_or_init(|| {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .dot_matches_new_line(true)
+            .build()
+            .expect("valid HTML regex")
+    })
+}
+
+/// Reduces an HTML page to readable text: scripts, styles and page chrome are dropped, headings,
+/// list items and links become markdown, and entities are decoded.
+pub fn html_to_text(html: &str) -> String {
+    static IGNORED: OnceLock<Vec<Regex>> = OnceLock::new();
+    static COMMENT: OnceLock<Regex> = OnceLock::new();
+    static HEADING: OnceLock<Regex> = OnceLock::new();
+    static LINK: OnceLock<Regex> = OnceLock::new();
+    static LIST_ITEM: OnceLock<Regex> = OnceLock::new();
+    static BLOCK: OnceLock<Regex> = OnceLock::new();
+    static TAG: OnceLock<Regex> = OnceLock::new();
+
+    let mut text = regex(&COMMENT, r"<!--.*?-->").replace_all(html, "").into_owned();
+    // The regex crate has no backreferences, so each ignored element gets its own pattern.
+    let ignored = IGNORED.get_or_init(|| {
+        ["script", "style", "noscript", "svg", "head", "nav", "footer", "iframe"]
+            .iter()
+            .map(|tag| {
+                RegexBuilder::new(&format!(r"<{tag}\b[^>]*>.*?</{tag}\s*>"))
+                    .case_insensitive(true)
+                    .dot_matches_new_line(true)
+                    .build()
+                    .expect("valid HTML regex")
+            })
+            .collect()
+    });
+    for pattern in ignored {
+        text = pattern.replace_all(&text, "").into_owned();
+    }
+    let text = regex(&HEADING, r"<h([1-6])\b[^>]*>(.*?)</h[1-6]>").replace_all(&text, |caps: &regex::Captures| {
+        let level: usize = caps[1].parse().unwrap_or(1);
+        format!("\n\n{} {}\n\n", "#".repeat(level), caps[2].trim())
+    });
+    let text = regex(&LINK, r#"<a\b[^>]*?href\s*=\s*["']([^"']+)["'][^>]*>(.*?)</a>"#).replace_all(&text, |caps: &regex::Captures| {
+        let label = caps[2].trim();
+        if label.is_empty() || caps[1].starts_with('#') { label.to_string() } else { format!("[{}]({})", label, &caps[1]) }
+    });
+    let text = regex(&LIST_ITEM, r"<li\b[^>]*>").replace_all(&text, "\n- ");
+    let text = regex(&BLOCK, r"</?(p|div|br|tr|table|ul|ol|pre|section|article|blockquote|hr)\b[^>]*>").replace_all(&text, "\n");
+    let text = regex(&TAG, r"<[^>]+>").replace_all(&text, "");
+    let text = decode_entities(&text);
+
+    // Collapse runs of spaces within lines and of blank lines between them.
+    let mut out = String::new();
+    let mut blank_lines = 0;
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        out.push_str(&line);
+        blank_lines = 0;
+    }
+    out
+}
+
+fn decode_entities(text: &str) -> String {
+    static ENTITY: OnceLock<Regex> = OnceLock::new();
+    let entity = ENTITY.get_or_init(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").expect("valid entity regex"));
+    entity
+        .replace_all(text, |caps: &regex::Captures| {
+            let name = &caps[1];
+            let decoded = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ if name.starts_with("#x") || name.starts_with("#X") => u32::from_str_radix(&name[2..], 16).ok().and_then(char::from_u32),
+                _ if name.starts_with('#') => name[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            decoded.map(String::from).unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text_keeps_readable_content() {
+        let html = r#"<html><head><title>Docs</title><style>body { color: red; }</style></head>
+            <body><nav><a href="/">Home</a></nav>
+            <h1>Getting   started</h1>
+            <p>Install with <code>cargo add tokio</code> &amp; read the <a href="https://docs.rs/tokio">API docs</a>.</p>
+            <ul><li>Fast</li><li>Reliable</li></ul>
+            <script>alert("x")</script><!-- hidden --></body></html>"#;
+        let text = html_to_text(html);
+        assert_eq!(
+            text,
+            "# Getting started\n\nInstall with cargo add tokio & read the [API docs](https://docs.rs/tokio).\n\n- Fast\n- Reliable"
+        );
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(decode_entities("&lt;T&gt; &#39;a&#x27; &copy;"), "<T> 'a' &copy;");
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::error::AgentError;
+
+/// Hosts `HttpRequest` may call unless `AGENT_HTTP_ALLOWED_HOSTS` is set: the local machine, where
+/// the services the agent is working on usually run.
+pub const DEFAULT_HTTP_ALLOWED_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+/// Bytes of a request body `HttpRequest` sends, and of a response body it reads, by default.
+pub const DEFAULT_MAX_HTTP_BYTES: usize = 256 * 1024;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+const METHODS: &[&str] = &["GET", "HEAD", "OPTIONS", "POST", "PUT", "PATCH", "DELETE"];
+
+/// Whether `method` only reads, so it may be sent in dry-run mode.
+pub fn is_safe_method(method: &str) -> bool {
+    matches!(method.to_uppercase().as_str(), "GET" | "HEAD" | "OPTIONS")
+}
+
+/// Whether `url`'s host matches an entry of `allowed`: a host name (`api.example.com`), a host and
+/// port (`localhost:8080`), a wildcard for subdomains (`*.example.com`), or `*` for any host.
+pub fn host_allowed(url: &reqwest::Url, allowed: &[String]) -> bool {
+    let Some(host) = url.host_str().map(str::to_lowercase) else {
+        return false;
+    };
+    let with_port = format!("{}:{}", host, url.port_or_known_default().unwrap_or_default());
+    allowed.iter().map(|entry| entry.trim().to_lowercase()).any(|entry| match entry.strip_prefix("*.") {
+        _ if entry == "*" => true,
+        Some(domain) => host.ends_with(&format!(".{}", domain)),
+        None => entry == host || entry == with_port,
+    })
+}
+
+/// Sends an HTTP request to a host in `allowed_hosts` and returns the status line, the response
+/// headers and at most `max_bytes` of the body. Responses with error statuses are returned like
+/// any other, since their shape is often what the agent wants to see; redirects are not followed.
+pub async fn http_request(
+    method: &str,
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    body: Option<&str>,
+    allowed_hosts: &[String],
+    max_bytes: usize,
+) -> Result<String, AgentError> {
+    let method = method.trim().to_uppercase();
+    if !METHODS.contains(&method.as_str()) {
+        return Err(AgentError::ToolError(format!("Unsupported HTTP method '{}' (expected one of {})", method, METHODS.join(", "))));
+    }
+    let parsed = reqwest::Url::parse(url).map_err(|e| AgentError::ToolError(format!("Invalid URL '{}': {}", url, e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(AgentError::ToolError(format!("HttpRequest only supports http and https URLs, not '{}'", parsed.scheme())));
+    }
+    if !host_allowed(&parsed, allowed_hosts) {
+        return Err(AgentError::SandboxViolation(format!(
+            "{} is not an allowed host for HttpRequest (AGENT_HTTP_ALLOWED_HOSTS: {})",
+            parsed.host_str().unwrap_or_default(),
+            allowed_hosts.join(", ")
+        )));
+    }
+    if body.is_some_and(|body| body.len() > max_bytes) {
+        return Err(AgentError::ToolError(format!("The request body is larger than the limit of {} bytes", max_bytes)));
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(concat!("rust-cli-agent/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let method = reqwest::Method::from_bytes(method.as_bytes()).map_err(|e| AgentError::ToolError(e.to_string()))?;
+    let mut request = client.request(method, parsed);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    if let Some(body) = body {
+        request = request.body(body.to_string());
+    }
+    let mut response = request.send().await?;
+
+    let mut output = format!("HTTP {}\n", response.status());
+    for (name, value) in response.headers() {
+        output.push_str(&format!("{}: {}\n", name, String::from_utf8_lossy(value.as_bytes())));
+    }
+    let mut received = Vec::new();
+    let mut truncated = false;
+    while let Some(chunk) = response.chunk().await? {
+        let remaining = max_bytes - received.len();
+        if chunk.len() > remaining {
+            received.extend_from_slice(&chunk[..remaining]);
+            truncated = true;
+            break;
+        }
+        received.extend_from_slice(&chunk);
+    }
+    output.push('\n');
+    output.push_str(&String::from_utf8_lossy(&received));
+    if truncated {
+        output.push_str(&format!("\n[... body truncated at {} bytes ...]", max_bytes));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_allowed_matches_hosts_ports_and_wildcards() {
+        let allowed: Vec<String> = ["localhost", "api.example.com:8443", "*.internal.dev"].iter().map(|s| s.to_string()).collect();
+        let check = |url: &str| host_allowed(&reqwest::Url::parse(url).unwrap(), &allowed);
+        assert!(check("http://localhost:3000/users"));
+        assert!(check("https://api.example.com:8443/v1"));
+        assert!(!check("https://api.example.com/v1"));
+        assert!(check("http://users.internal.dev/health"));
+        assert!(!check("http://internal.dev.evil.com/"));
+        assert!(!check("http://127.0.0.1/"));
+        assert!(host_allowed(&reqwest::Url::parse("https://anything.com").unwrap(), &["*".to_string()]));
+    }
+}
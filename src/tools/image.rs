@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use crate::error::AgentError;
+use crate::llm::ImageAttachment;
+
+/// Largest image `ReadImage` attaches; providers reject bigger ones (Claude's limit is 5 MB).
+pub const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// The MIME type of an image file, from its extension; `None` for formats models do not accept.
+pub fn image_media_type(path: &Path) -> Option<&'static str> {
+    match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Reads a PNG, JPEG, GIF or WebP image to attach to prompts.
+pub fn read_image(path: &Path) -> Result<ImageAttachment, AgentError> {
+    let media_type = image_media_type(path).ok_or_else(|| {
+        AgentError::ToolError(format!("{} is not a PNG, JPEG, GIF or WebP image", path.display()))
+    })?;
+    let size = std::fs::metadata(path)?.len();
+    if size > MAX_IMAGE_BYTES {
+        return Err(AgentError::ToolError(format!(
+            "{} is {} bytes; images over {} bytes cannot be attached",
+            path.display(),
+            size,
+            MAX_IMAGE_BYTES
+        )));
+    }
+    Ok(ImageAttachment::new(media_type, &std::fs::read(path)?))
+}
+
+/// Paths of image files named in `text` (e.g. "match the mock in design/login.png"), in the
+/// order they appear. `resolve` maps a path as written to where it is opened; only files that
+/// exist there are returned.
+pub fn referenced_images(text: &str, resolve: impl Fn(&str) -> String) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        let word = word.trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '(' | ')' | '[' | ']' | ',' | ';' | ':'));
+        let word = word.trim_end_matches(['.', '!', '?']);
+        if image_media_type(Path::new(word)).is_some() && Path::new(&resolve(word)).is_file() && !paths.iter().any(|p| p == word) {
+            paths.push(word.to_string());
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_referenced_images_finds_existing_images_in_text() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("design")).unwrap();
+        std::fs::write(dir.path().join("design/login.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let resolve = |path: &str| dir.path().join(path).display().to_string();
+
+        let goal = "Make the page match `design/login.png`. See notes.txt and missing.jpg; design/login.png again.";
+        assert_eq!(referenced_images(goal, resolve), vec!["design/login.png".to_string()]);
+
+        let image = read_image(&dir.path().join("design/login.png")).unwrap();
+        assert_eq!(image.media_type, "image/png");
+        assert_eq!(image.data_url(), "data:image/png;base64,iVBORw==");
+        assert!(read_image(&dir.path().join("notes.txt")).unwrap_err().to_string().contains("is not a PNG"));
+    }
+}
@@ -0,0 +1,306 @@
+use log::warn;
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use super::{process, CommandLimits, CommandOptions, CommandOutput};
+use crate::config::AppConfig;
+use crate::error::AgentError;
+
+/// Where the workspace volume is mounted in job pods.
+pub const WORKSPACE_MOUNT_PATH: &str = "/workspace";
+
+/// Seconds a finished job is kept before Kubernetes deletes it, in case the agent could not.
+const JOB_TTL_SECS: u64 = 300;
+
+/// How long `kubectl` calls other than following the logs may take.
+const KUBECTL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often the job is checked for a result once its logs have ended.
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where `RunCommand` runs commands, set with `AGENT_EXEC_BACKEND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecBackend {
+    /// A child process of the agent, in its working directory.
+    Local,
+    /// A Kubernetes Job per command (see `KubernetesJob`).
+    Kubernetes,
+}
+
+impl fmt::Display for ExecBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecBackend::Local => write!(f, "local"),
+            ExecBackend::Kubernetes => write!(f, "kubernetes"),
+        }
+    }
+}
+
+impl FromStr for ExecBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "local" => Ok(ExecBackend::Local),
+            "kubernetes" | "k8s" => Ok(ExecBackend::Kubernetes),
+            other => Err(format!("unknown execution backend '{}' (expected local or kubernetes)", other)),
+        }
+    }
+}
+
+/// Runs each command as a Kubernetes Job through `kubectl`, which must be on the `PATH` and
+/// configured for the cluster (`KUBECONFIG`). The pod's logs become the command's output, and the
+/// job and its pod are deleted once the command finishes, times out or is cancelled.
+///
+/// Pods run `sh -c <command>` in `image` without a service account token. The agent's files are
+/// only visible to them through `workspace_claim`, a PersistentVolumeClaim holding the working
+/// directory, which is mounted at `WORKSPACE_MOUNT_PATH` and used as the working directory.
+#[derive(Debug, Clone)]
+pub struct KubernetesJob {
+    namespace: String,
+    image: String,
+    workspace_claim: Option<String>,
+    kubectl: String,
+}
+
+impl KubernetesJob {
+    pub fn new(namespace: impl Into<String>, image: impl Into<String>) -> Self {
+        Self { namespace: namespace.into(), image: image.into(), workspace_claim: None, kubectl: "kubectl".to_string() }
+    }
+
+    /// The backend configured with `AGENT_K8S_*`; fails when no image is set.
+    pub fn from_config(config: &AppConfig) -> Result<Self, AgentError> {
+        let image = config
+            .k8s_image
+            .clone()
+            .ok_or_else(|| AgentError::ConfigError("AGENT_EXEC_BACKEND=kubernetes needs the image to run commands in (AGENT_K8S_IMAGE)".to_string()))?;
+        let job = Self::new(config.k8s_namespace.clone(), image);
+        Ok(match &config.k8s_workspace_claim {
+            Some(claim) => job.with_workspace_claim(claim.clone()),
+            None => job,
+        })
+    }
+
+    /// Mounts the PersistentVolumeClaim `claim` at `WORKSPACE_MOUNT_PATH` and runs commands there.
+    pub fn with_workspace_claim(mut self, claim: impl Into<String>) -> Self {
+        self.workspace_claim = Some(claim.into());
+        self
+    }
+
+    /// Runs `program` instead of `kubectl`.
+    pub fn with_kubectl(mut self, program: impl Into<String>) -> Self {
+        self.kubectl = program.into();
+        self
+    }
+
+    /// Runs `command` as a job and waits for it within `limits`. A command that fails or whose pod
+    /// cannot start is reported as an unsuccessful run with the pod's logs.
+    pub async fn run(&self, command: &str, options: &CommandOptions, limits: CommandLimits, cancel: Option<&CancellationToken>) -> Result<CommandOutput, AgentError> {
+        if options.cwd.is_some() && self.workspace_claim.is_none() {
+            return Err(AgentError::ToolError(format!("Cannot run `{}` in a subdirectory: no workspace volume is mounted in job pods (AGENT_K8S_WORKSPACE_CLAIM)", command)));
+        }
+        let name = job_name(command);
+        let manifest = self.manifest(&name, command, options, limits.timeout);
+        let created = self.kubectl(&["apply", "-f", "-"], Some(&manifest.to_string()), None).await?;
+        if !created.success {
+            return Err(AgentError::ToolError(format!("Could not create job {} for `{}`:\n{}", name, command, created.stderr.trim())));
+        }
+        let result = self.wait(&name, command, limits, cancel).await;
+        match self.kubectl(&["delete", "job", &name, "--ignore-not-found", "--wait=false", "--cascade=background"], None, None).await {
+            Ok(deleted) if deleted.success => {}
+            Ok(deleted) => warn!("Could not delete job {}: {}", name, deleted.stderr.trim()),
+            Err(e) => warn!("Could not delete job {}: {}", name, e),
+        }
+        result
+    }
+
+    /// The Job running `command` in a single pod that is never restarted.
+    pub fn manifest(&self, name: &str, command: &str, options: &CommandOptions, timeout: Duration) -> Value {
+        let mut env: Vec<Value> = options.env.iter().map(|(name, value)| json!({ "name": name, "value": value })).collect();
+        let args = match &options.stdin {
+            // `$0` is the command, so it needs no quoting.
+            Some(stdin) => {
+                env.push(json!({ "name": "AGENT_STDIN", "value": stdin }));
+                vec!["sh", "-c", r#"printf '%s' "$AGENT_STDIN" | sh -c "$0""#, command]
+            }
+            None => vec!["sh", "-c", command],
+        };
+        let mut container = json!({
+            "name": "command",
+            "image": self.image,
+            "command": args,
+            "env": env,
+            "securityContext": { "allowPrivilegeEscalation": false },
+        });
+        let mut pod = json!({
+            "restartPolicy": "Never",
+            "automountServiceAccountToken": false,
+            "containers": [],
+        });
+        if let Some(claim) = &self.workspace_claim {
+            let workdir = match options.cwd.as_deref() {
+                Some(cwd) => format!("{}/{}", WORKSPACE_MOUNT_PATH, cwd.trim_start_matches("./")),
+                None => WORKSPACE_MOUNT_PATH.to_string(),
+            };
+            container["workingDir"] = json!(workdir);
+            container["volumeMounts"] = json!([{ "name": "workspace", "mountPath": WORKSPACE_MOUNT_PATH }]);
+            pod["volumes"] = json!([{ "name": "workspace", "persistentVolumeClaim": { "claimName": claim } }]);
+        }
+        pod["containers"] = json!([container]);
+        json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": {
+                "name": name,
+                "namespace": self.namespace,
+                "labels": { "app.kubernetes.io/managed-by": "rust-cli-agent" },
+            },
+            "spec": {
+                "backoffLimit": 0,
+                "activeDeadlineSeconds": timeout.as_secs().max(1),
+                "ttlSecondsAfterFinished": JOB_TTL_SECS,
+                "template": { "spec": pod },
+            },
+        })
+    }
+
+    /// Follows the logs of job `name` until its pod ends, then waits for the job's verdict.
+    async fn wait(&self, name: &str, command: &str, limits: CommandLimits, cancel: Option<&CancellationToken>) -> Result<CommandOutput, AgentError> {
+        let started = Instant::now();
+        let job = format!("job/{}", name);
+        let pod_timeout = format!("--pod-running-timeout={}s", limits.timeout.as_secs().max(1));
+        let logs = self.follow(&["logs", "-f", &job, &pod_timeout], command, limits, cancel).await?;
+        loop {
+            let status = self.kubectl(&["get", "job", name, "-o", "jsonpath={.status.succeeded}/{.status.failed}"], None, cancel).await?;
+            let (succeeded, failed) = status.stdout.trim().split_once('/').unwrap_or_default();
+            let counted = |count: &str| count.parse::<u32>().is_ok_and(|count| count > 0);
+            if counted(succeeded) || counted(failed) {
+                let success = counted(succeeded);
+                let stderr = if success { logs.stderr } else { format!("{}Job {} failed.", logs.stderr, name) };
+                return Ok(CommandOutput { success, exit_code: None, stdout: logs.stdout, stderr });
+            }
+            if started.elapsed() >= limits.timeout {
+                return Err(AgentError::ToolTimeout { command: command.to_string(), seconds: limits.timeout.as_secs() });
+            }
+            tokio::time::sleep(STATUS_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Runs `kubectl` within `limits`, reporting a timeout as one of `command`.
+    async fn follow(&self, args: &[&str], command: &str, limits: CommandLimits, cancel: Option<&CancellationToken>) -> Result<CommandOutput, AgentError> {
+        let mut process = Command::new(&self.kubectl);
+        process.args(args).args(["--namespace", &self.namespace]);
+        process::run_until_cancelled(process, command, None, limits, cancel).await
+    }
+
+    /// Runs a short `kubectl` call with `input` on its stdin.
+    async fn kubectl(&self, args: &[&str], input: Option<&str>, cancel: Option<&CancellationToken>) -> Result<CommandOutput, AgentError> {
+        let mut process = Command::new(&self.kubectl);
+        process.args(args).args(["--namespace", &self.namespace]);
+        let limits = CommandLimits { timeout: KUBECTL_TIMEOUT, ..CommandLimits::default() };
+        let label = format!("{} {}", self.kubectl, args.join(" "));
+        process::run_until_cancelled(process, &label, input, limits, cancel).await
+    }
+}
+
+/// A job name unique to this run of `command`, e.g. `agent-run-1a2b3c4d`.
+fn job_name(command: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    command.hash(&mut hasher);
+    std::time::SystemTime::now().hash(&mut hasher);
+    format!("agent-run-{:08x}", hasher.finish() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_manifest_runs_the_command_in_the_workspace() {
+        let job = KubernetesJob::new("ci", "rust:1.80").with_workspace_claim("agent-workspace");
+        let options = CommandOptions {
+            cwd: Some("backend".to_string()),
+            env: BTreeMap::from([("RUST_LOG".to_string(), "debug".to_string())]),
+            stdin: Some("y\n".to_string()),
+        };
+        let manifest = job.manifest("agent-run-1", "cargo test", &options, Duration::from_secs(120));
+        assert_eq!(manifest["metadata"]["namespace"], "ci");
+        assert_eq!(manifest["spec"]["backoffLimit"], 0);
+        assert_eq!(manifest["spec"]["activeDeadlineSeconds"], 120);
+        let pod = &manifest["spec"]["template"]["spec"];
+        assert_eq!(pod["restartPolicy"], "Never");
+        assert_eq!(pod["automountServiceAccountToken"], false);
+        assert_eq!(pod["volumes"][0]["persistentVolumeClaim"]["claimName"], "agent-workspace");
+        let container = &pod["containers"][0];
+        assert_eq!(container["image"], "rust:1.80");
+        assert_eq!(container["workingDir"], "/workspace/backend");
+        assert_eq!(container["command"][3], "cargo test");
+        assert_eq!(container["env"], json!([{ "name": "RUST_LOG", "value": "debug" }, { "name": "AGENT_STDIN", "value": "y\n" }]));
+
+        let plain = KubernetesJob::new("ci", "alpine").manifest("agent-run-2", "ls", &CommandOptions::default(), Duration::from_secs(5));
+        let container = &plain["spec"]["template"]["spec"]["containers"][0];
+        assert_eq!(container["command"], json!(["sh", "-c", "ls"]));
+        assert!(container.get("workingDir").is_none());
+    }
+
+    #[test]
+    fn test_exec_backend_parses() {
+        assert_eq!("Kubernetes".parse::<ExecBackend>().unwrap(), ExecBackend::Kubernetes);
+        assert_eq!("k8s".parse::<ExecBackend>().unwrap(), ExecBackend::Kubernetes);
+        assert_eq!(ExecBackend::Local.to_string(), "local");
+        assert!("docker".parse::<ExecBackend>().is_err());
+    }
+
+    /// A stand-in for `kubectl` that records its calls in `calls` and reports the job as
+    /// succeeded or failed.
+    #[cfg(unix)]
+    fn fake_kubectl(dir: &std::path::Path, status: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+        let script = dir.join("kubectl");
+        let calls = dir.join("calls");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> {calls}\ncase \"$1\" in\n  apply) cat > {manifest} ;;\n  logs) echo 'hello from the pod' ;;\n  get) printf '{status}' ;;\nesac\n",
+                calls = calls.display(),
+                manifest = dir.join("manifest.json").display(),
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        script.to_string_lossy().into_owned()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_creates_follows_and_deletes_the_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let job = KubernetesJob::new("ci", "alpine").with_kubectl(fake_kubectl(dir.path(), "1/"));
+        let output = job.run("echo hi", &CommandOptions::default(), CommandLimits::default(), None).await.unwrap();
+        assert!(output.success);
+        assert_eq!(output.stdout, "hello from the pod\n");
+
+        let manifest: Value = serde_json::from_str(&std::fs::read_to_string(dir.path().join("manifest.json")).unwrap()).unwrap();
+        let name = manifest["metadata"]["name"].as_str().unwrap().to_string();
+        let calls = std::fs::read_to_string(dir.path().join("calls")).unwrap();
+        let calls: Vec<&str> = calls.lines().map(|call| call.split_whitespace().next().unwrap()).collect();
+        assert_eq!(calls, ["apply", "logs", "get", "delete"]);
+        assert!(name.starts_with("agent-run-"), "{}", name);
+
+        let dir = tempfile::tempdir().unwrap();
+        let job = KubernetesJob::new("ci", "alpine").with_kubectl(fake_kubectl(dir.path(), "/1"));
+        let output = job.run("false", &CommandOptions::default(), CommandLimits::default(), None).await.unwrap();
+        assert!(!output.success);
+        assert!(output.to_tool_output().contains("hello from the pod") && output.stderr.contains("failed"), "{:?}", output);
+
+        let cwd = CommandOptions { cwd: Some("backend".to_string()), ..CommandOptions::default() };
+        assert!(matches!(job.run("ls", &cwd, CommandLimits::default(), None).await, Err(AgentError::ToolError(_))));
+    }
+}
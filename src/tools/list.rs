@@ -0,0 +1,137 @@
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::error::AgentError;
+
+/// Stop listing after this many entries so a large repository cannot flood the context.
+pub const MAX_LIST_ENTRIES: usize = 500;
+
+struct Entry {
+    path: PathBuf,
+    depth: usize,
+    is_dir: bool,
+}
+
+/// Lists `root` as an indented tree, skipping anything ignored by .gitignore, `.git/` and
+/// `target/`. `max_depth` limits how far below `root` the walk goes; `glob` (gitignore syntax,
+/// e.g. `*.rs` or `src/**/*.toml`) keeps only matching files and the directories containing them.
+pub fn list_files(root: &Path, max_depth: Option<usize>, glob: Option<&str>) -> Result<String, AgentError> {
+    let mut walker = walker(root);
+    walker.max_depth(max_depth);
+    if let Some(glob) = glob {
+        let overrides = OverrideBuilder::new(root)
+            .add(glob)
+            .and_then(|builder| builder.build())
+            .map_err(|e| AgentError::ToolError(format!("Invalid glob '{}': {}", glob, e)))?;
+        walker.overrides(overrides);
+    }
+
+    let entries: Vec<Entry> = walker
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.depth() > 0)
+        .map(|entry| Entry {
+            is_dir: entry.file_type().is_some_and(|t| t.is_dir()),
+            depth: entry.depth(),
+            path: entry.into_path(),
+        })
+        .collect();
+
+    // With a glob, directories are only shown when they lead to a matching file.
+    let wanted_dirs: Option<HashSet<&Path>> = glob.map(|_| {
+        entries.iter().filter(|e| !e.is_dir).flat_map(|e| e.path.ancestors().skip(1)).collect()
+    });
+    let visible: Vec<&Entry> = entries
+        .iter()
+        .filter(|e| !e.is_dir || wanted_dirs.as_ref().is_none_or(|dirs| dirs.contains(e.path.as_path())))
+        .collect();
+
+    let mut out = format!("{}/\n", root.display().to_string().trim_end_matches(['/', '\\']));
+    for entry in visible.iter().take(MAX_LIST_ENTRIES) {
+        let name = entry.path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        out.push_str(&"  ".repeat(entry.depth));
+        out.push_str(&name);
+        if entry.is_dir {
+            out.push('/');
+        }
+        out.push('\n');
+    }
+    if visible.len() > MAX_LIST_ENTRIES {
+        out.push_str(&format!(
+            "[... {} more entries; list a subdirectory or use max_depth or glob to narrow the listing ...]\n",
+            visible.len() - MAX_LIST_ENTRIES
+        ));
+    }
+    Ok(out)
+}
+
+/// The files below `root` as paths relative to it, `/`-separated and in listing order, skipping the
+/// same files as `list_files`; at most `MAX_LIST_ENTRIES`.
+pub fn project_files(root: &Path) -> Vec<String> {
+    walker(root)
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|entry| entry.path().strip_prefix(root).ok().map(|path| path.to_string_lossy().replace('\\', "/")))
+        .take(MAX_LIST_ENTRIES)
+        .collect()
+}
+
+fn walker(root: &Path) -> WalkBuilder {
+    let mut walker = WalkBuilder::new(root);
+    walker
+        .require_git(false)
+        .hidden(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(|entry| entry.file_name() != ".git" && entry.file_name() != "target");
+    walker
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn project() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/tools")).unwrap();
+        fs::create_dir_all(dir.path().join("docs")).unwrap();
+        fs::create_dir_all(dir.path().join("build")).unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        fs::write(dir.path().join("src/main.rs"), "").unwrap();
+        fs::write(dir.path().join("src/tools/list.rs"), "").unwrap();
+        fs::write(dir.path().join("docs/guide.md"), "").unwrap();
+        fs::write(dir.path().join("build/out.o"), "").unwrap();
+        fs::write(dir.path().join(".gitignore"), "build/\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_list_files_renders_tree_respecting_gitignore() {
+        let dir = project();
+        let listing = list_files(dir.path(), None, None).unwrap();
+        let body: Vec<&str> = listing.lines().skip(1).collect();
+        assert_eq!(body, [".gitignore", "Cargo.toml", "docs/", "  guide.md", "src/", "  main.rs", "  tools/", "    list.rs"].map(|l| format!("  {}", l)));
+    }
+
+    #[test]
+    fn test_list_files_with_depth_and_glob() {
+        let dir = project();
+        let shallow = list_files(dir.path(), Some(1), None).unwrap();
+        assert!(shallow.contains("  src/\n") && !shallow.contains("main.rs"));
+
+        let rust_only = list_files(dir.path(), None, Some("*.rs")).unwrap();
+        let body: Vec<&str> = rust_only.lines().skip(1).collect();
+        assert_eq!(body, ["  src/", "    main.rs", "    tools/", "      list.rs"]);
+
+        assert!(matches!(list_files(dir.path(), None, Some("[")), Err(AgentError::ToolError(_))));
+    }
+
+    #[test]
+    fn test_project_files_lists_relative_file_paths() {
+        let dir = project();
+        assert_eq!(project_files(dir.path()), [".gitignore", "Cargo.toml", "docs/guide.md", "src/main.rs", "src/tools/list.rs"]);
+    }
+}
@@ -0,0 +1,172 @@
+use proc_macro2::Span;
+use std::path::Path;
+use syn::spanned::Spanned;
+use syn::{ImplItem, Item, TraitItem, Visibility};
+
+use crate::doc_gen::rust_files;
+use crate::error::AgentError;
+
+/// Stop outlining after this many lines so a large crate cannot flood the context.
+pub const MAX_OUTLINE_LINES: usize = 500;
+/// Lines a signature may span before it is cut off, e.g. a function with many parameters.
+const MAX_SIGNATURE_LINES: usize = 8;
+
+/// Outlines the Rust code at `path`, a `.rs` file or a directory of them: one line per item with
+/// its line number and signature, bodies left out. Impl blocks, traits and inline modules list
+/// their items indented below them. Files in a directory that do not parse are noted and skipped.
+pub fn code_outline(path: &Path) -> Result<String, AgentError> {
+    let mut lines = Vec::new();
+    if path.is_dir() {
+        for file in rust_files(path) {
+            let name = file.strip_prefix(path).unwrap_or(&file).to_string_lossy().replace('\\', "/");
+            match outline_file(&file) {
+                Ok(items) => {
+                    lines.push(name);
+                    lines.extend(items.into_iter().map(|item| format!("  {}", item)));
+                }
+                Err(e) => lines.push(format!("{} (skipped: {})", name, e)),
+            }
+        }
+        if lines.is_empty() {
+            return Err(AgentError::ToolError(format!("No Rust files found in {}", path.display())));
+        }
+    } else {
+        if path.extension().is_none_or(|extension| extension != "rs") {
+            return Err(AgentError::ToolError(format!("CodeOutline only outlines Rust (.rs) files; use ReadFile for {}", path.display())));
+        }
+        lines = outline_file(path)?;
+    }
+    let total = lines.len();
+    lines.truncate(MAX_OUTLINE_LINES);
+    let mut out = lines.join("\n");
+    out.push('\n');
+    if total > MAX_OUTLINE_LINES {
+        out.push_str(&format!("[... {} more lines; outline a single file or a subdirectory ...]\n", total - MAX_OUTLINE_LINES));
+    }
+    Ok(out)
+}
+
+fn outline_file(path: &Path) -> Result<Vec<String>, AgentError> {
+    let source = std::fs::read_to_string(path)?;
+    let file = syn::parse_file(&source).map_err(|e| AgentError::ToolError(format!("{} does not parse as Rust: {}", path.display(), e)))?;
+    let source: Vec<&str> = source.lines().collect();
+    let mut out = Vec::new();
+    outline_items(&file.items, &source, 0, &mut out);
+    Ok(out)
+}
+
+fn outline_items(items: &[Item], source: &[&str], depth: usize, out: &mut Vec<String>) {
+    let entry = |out: &mut Vec<String>, depth: usize, line: usize| out.push(format!("{}{}: {}", "  ".repeat(depth), line, signature(source, line)));
+    for item in items {
+        match item {
+            Item::Fn(f) => entry(out, depth, start(&f.vis, f.sig.span())),
+            Item::Struct(s) => entry(out, depth, start(&s.vis, s.struct_token.span)),
+            Item::Enum(e) => entry(out, depth, start(&e.vis, e.enum_token.span)),
+            Item::Union(u) => entry(out, depth, start(&u.vis, u.union_token.span)),
+            Item::Type(t) => entry(out, depth, start(&t.vis, t.type_token.span)),
+            Item::Const(c) => entry(out, depth, start(&c.vis, c.const_token.span)),
+            Item::Static(s) => entry(out, depth, start(&s.vis, s.static_token.span)),
+            Item::Macro(m) if m.ident.is_some() => entry(out, depth, m.mac.path.span().start().line),
+            Item::Trait(t) => {
+                entry(out, depth, start(&t.vis, t.trait_token.span));
+                for trait_item in &t.items {
+                    match trait_item {
+                        TraitItem::Fn(f) => entry(out, depth + 1, f.sig.span().start().line),
+                        TraitItem::Const(c) => entry(out, depth + 1, c.const_token.span.start().line),
+                        TraitItem::Type(ty) => entry(out, depth + 1, ty.type_token.span.start().line),
+                        _ => {}
+                    }
+                }
+            }
+            Item::Impl(i) => {
+                entry(out, depth, i.impl_token.span.start().line);
+                for impl_item in &i.items {
+                    match impl_item {
+                        ImplItem::Fn(f) => entry(out, depth + 1, start(&f.vis, f.sig.span())),
+                        ImplItem::Const(c) => entry(out, depth + 1, start(&c.vis, c.const_token.span)),
+                        ImplItem::Type(ty) => entry(out, depth + 1, start(&ty.vis, ty.type_token.span)),
+                        _ => {}
+                    }
+                }
+            }
+            Item::Mod(m) => {
+                entry(out, depth, start(&m.vis, m.mod_token.span));
+                if let Some((_, content)) = &m.content {
+                    outline_items(content, source, depth + 1, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The line an item's signature starts on: its visibility, or else its first keyword, so
+/// attributes and doc comments above it are skipped.
+fn start(vis: &Visibility, keyword: Span) -> usize {
+    match vis {
+        Visibility::Inherited => keyword.start().line,
+        vis => vis.span().start().line,
+    }
+}
+
+/// The signature starting on 1-based `line`, on one line and without its body or trailing `;`.
+fn signature(source: &[&str], line: usize) -> String {
+    let lines = source.iter().skip(line.saturating_sub(1)).take(MAX_SIGNATURE_LINES).map(|line| line.trim());
+    let mut signature = String::new();
+    for text in lines {
+        let end = text.find(['{', ';']);
+        if !signature.is_empty() && !signature.ends_with('(') && !text.starts_with(')') {
+            signature.push(' ');
+        }
+        signature.push_str(&text[..end.unwrap_or(text.len())]);
+        if end.is_some() {
+            break;
+        }
+    }
+    signature.replace(",)", ")").trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_outline_lists_items_with_lines_and_signatures() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let source = r#"/// Settings.
+#[derive(Debug)]
+pub struct Config {
+    pub name: String,
+}
+
+impl Config {
+    pub fn parse(
+        text: &str,
+        strict: bool,
+    ) -> Result<Self, String> {
+        todo!()
+    }
+}
+
+pub trait Loader {
+    fn load(&self) -> Config;
+}
+
+mod nested {
+    pub const LIMIT: usize = 3;
+}
+"#;
+        std::fs::write(dir.path().join("src/config.rs"), source).unwrap();
+        std::fs::write(dir.path().join("src/broken.rs"), "pub fn broken(").unwrap();
+
+        let outline = code_outline(&dir.path().join("src/config.rs")).unwrap();
+        let expected = "3: pub struct Config\n7: impl Config\n  8: pub fn parse(text: &str, strict: bool) -> Result<Self, String>\n16: pub trait Loader\n  17: fn load(&self) -> Config\n20: mod nested\n  21: pub const LIMIT: usize = 3\n";
+        assert_eq!(outline, expected);
+
+        let outline = code_outline(dir.path()).unwrap();
+        assert!(outline.starts_with("src/broken.rs (skipped: "), "{}", outline);
+        assert!(outline.contains("src/config.rs\n  3: pub struct Config\n"), "{}", outline);
+        assert!(code_outline(&dir.path().join("README.md")).is_err());
+    }
+}
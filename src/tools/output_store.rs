@@ -0,0 +1,68 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::error::AgentError;
+
+/// Where the full text of summarized tool outputs is kept, relative to the working directory.
+pub const TOOL_OUTPUT_DIR: &str = ".agent/outputs";
+
+/// Writes `output` into `dir` and returns the id `ReadToolOutput` reads it back by. The id is
+/// derived from the content, so saving the same output twice keeps one file.
+pub fn save_output(dir: &Path, output: &str) -> Result<String, AgentError> {
+    let mut hasher = DefaultHasher::new();
+    output.hash(&mut hasher);
+    let id = format!("out-{:08x}", hasher.finish() as u32);
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join(format!("{}.txt", id)), output)?;
+    Ok(id)
+}
+
+/// The file holding output `id` in `dir`. Only ids made by `save_output` are accepted, so an id
+/// cannot name a file outside `dir`.
+pub fn output_path(dir: &Path, id: &str) -> Result<PathBuf, AgentError> {
+    let valid = id.strip_prefix("out-").is_some_and(|hash| !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()));
+    let path = dir.join(format!("{}.txt", id));
+    if !valid || !path.is_file() {
+        return Err(AgentError::ToolError(format!("No saved tool output with id '{}'", id)));
+    }
+    Ok(path)
+}
+
+/// Parses a 1-based, inclusive line range: `"120-200"`, `"120-"` (to the end) or `"120"`.
+pub fn parse_range(range: &str) -> Result<(usize, Option<usize>), AgentError> {
+    let invalid = || AgentError::ToolError(format!("Invalid line range '{}': use START-END, e.g. 120-200", range));
+    let number = |text: &str| text.trim().parse::<usize>().map_err(|_| invalid());
+    match range.split_once('-') {
+        Some((start, end)) if end.trim().is_empty() => Ok((number(start)?, None)),
+        Some((start, end)) => Ok((number(start)?, Some(number(end)?))),
+        None => {
+            let line = number(range)?;
+            Ok((line, Some(line)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saved_outputs_are_found_by_id_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = save_output(dir.path(), "line 1\nline 2\n").unwrap();
+        assert_eq!(save_output(dir.path(), "line 1\nline 2\n").unwrap(), id);
+        assert_eq!(std::fs::read_to_string(output_path(dir.path(), &id).unwrap()).unwrap(), "line 1\nline 2\n");
+
+        assert!(output_path(dir.path(), "out-00000000").is_err());
+        assert!(output_path(dir.path(), "../secrets").is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("120-200").unwrap(), (120, Some(200)));
+        assert_eq!(parse_range(" 5 - ").unwrap(), (5, None));
+        assert_eq!(parse_range("7").unwrap(), (7, Some(7)));
+        assert!(parse_range("lots").is_err());
+    }
+}
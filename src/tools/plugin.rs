@@ -0,0 +1,193 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+use wasmi::{Config, Engine, Linker, Module, Store};
+
+use crate::error::AgentError;
+use crate::tools::registry::{ToolHandler, ToolRegistry};
+
+/// Where the CLI looks for WASM tool plugins when `AGENT_PLUGIN_DIR` is unset.
+pub const DEFAULT_PLUGIN_DIR: &str = ".agent/plugins";
+
+/// Instructions a plugin may execute per call before it is stopped, so a looping plugin cannot
+/// hang the run.
+pub const PLUGIN_FUEL: u64 = 1_000_000_000;
+
+/// The most output a plugin call may return, so a bogus length cannot make the host allocate
+/// gigabytes.
+pub const MAX_PLUGIN_OUTPUT_BYTES: usize = 16 * 1024 * 1024;
+
+/// The `<name>.json` manifest next to each `<name>.wasm` plugin.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginManifest {
+    /// Tool name offered to the model; the file name when absent.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub description: String,
+    /// JSON schema of the tool's parameters.
+    #[serde(default)]
+    pub parameters: Value,
+}
+
+/// A tool implemented by a WebAssembly module. Plugins get no imports, so they can only compute
+/// on their input; every call runs in a fresh instance with a `PLUGIN_FUEL` budget.
+///
+/// The module must export `memory`, `alloc(len: i32) -> i32`, which returns space for the input,
+/// and `run(ptr: i32, len: i32) -> i64`, which receives the parameters as UTF-8 JSON and returns
+/// the location of its UTF-8 output packed as `(ptr << 32) | len`.
+#[derive(Clone)]
+pub struct WasmPlugin {
+    engine: Engine,
+    module: Arc<Module>,
+}
+
+impl WasmPlugin {
+    pub fn new(wasm: &[u8]) -> Result<Self, AgentError> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|e| AgentError::ConfigError(format!("Invalid WASM module: {}", e)))?;
+        Ok(Self { engine, module: Arc::new(module) })
+    }
+
+    /// Runs the plugin on `input` and returns its output.
+    pub fn invoke(&self, input: &str) -> Result<String, AgentError> {
+        let failed = |e: &dyn std::fmt::Display| AgentError::ToolError(format!("WASM plugin failed: {}", e));
+        let mut store = Store::new(&self.engine, ());
+        store.set_fuel(PLUGIN_FUEL).map_err(|e| failed(&e))?;
+        let instance = Linker::<()>::new(&self.engine)
+            .instantiate(&mut store, &self.module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|e| failed(&e))?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| failed(&"the module does not export `memory`"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc").map_err(|e| failed(&e))?;
+        let run = instance.get_typed_func::<(i32, i32), i64>(&store, "run").map_err(|e| failed(&e))?;
+
+        let len = i32::try_from(input.len()).map_err(|e| failed(&e))?;
+        let ptr = alloc.call(&mut store, len).map_err(|e| failed(&e))?;
+        memory.write(&mut store, ptr as u32 as usize, input.as_bytes()).map_err(|e| failed(&e))?;
+        let packed = run.call(&mut store, (ptr, len)).map_err(|e| failed(&e))? as u64;
+
+        let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        if len > MAX_PLUGIN_OUTPUT_BYTES {
+            return Err(failed(&format!("its output of {} bytes is over the limit of {} bytes", len, MAX_PLUGIN_OUTPUT_BYTES)));
+        }
+        // Checked against the module's memory before anything is copied out of it.
+        let output = memory.data(&store).get(ptr..ptr + len).ok_or_else(|| failed(&"its output lies outside its memory"))?;
+        String::from_utf8(output.to_vec()).map_err(|e| failed(&e))
+    }
+}
+
+#[async_trait]
+impl ToolHandler for WasmPlugin {
+    async fn call(&self, parameters: Value) -> Result<String, AgentError> {
+        let plugin = self.clone();
+        tokio::task::spawn_blocking(move || plugin.invoke(&parameters.to_string()))
+            .await
+            .map_err(|e| AgentError::ToolError(format!("WASM plugin task failed: {}", e)))?
+    }
+}
+
+/// Registers every `<name>.wasm` in `dir` that has a `<name>.json` manifest, in file name order,
+/// and returns the registered tool names. A missing directory registers nothing.
+pub fn load_plugins(dir: &Path, registry: &mut ToolRegistry) -> Result<Vec<String>, AgentError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut modules: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    modules.sort();
+
+    let mut names = Vec::new();
+    for path in modules {
+        let manifest_path = path.with_extension("json");
+        let manifest: PluginManifest = serde_json::from_str(&std::fs::read_to_string(&manifest_path).map_err(|e| {
+            AgentError::ConfigError(format!("Plugin {} has no readable manifest {}: {}", path.display(), manifest_path.display(), e))
+        })?)
+        .map_err(|e| AgentError::ConfigError(format!("Invalid plugin manifest {}: {}", manifest_path.display(), e)))?;
+        let plugin = WasmPlugin::new(&std::fs::read(&path)?)
+            .map_err(|e| AgentError::ConfigError(format!("Cannot load plugin {}: {}", path.display(), e)))?;
+        let name = manifest
+            .name
+            .unwrap_or_else(|| path.file_stem().unwrap_or_default().to_string_lossy().into_owned());
+        registry.register(name.clone(), manifest.description, manifest.parameters, plugin)?;
+        names.push(name);
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Echoes its input back: the output is the input buffer itself.
+    const ECHO: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 16))
+          (func (export "run") (param $ptr i32) (param $len i32) (result i64)
+            (i64.or
+              (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+              (i64.extend_i32_u (local.get $len)))))
+    "#;
+
+    #[test]
+    fn test_invoke_round_trips_input() {
+        let plugin = WasmPlugin::new(&wat::parse_str(ECHO).unwrap()).unwrap();
+        assert_eq!(plugin.invoke(r#"{"text":"hi"}"#).unwrap(), r#"{"text":"hi"}"#);
+    }
+
+    #[test]
+    fn test_invoke_stops_plugins_that_run_out_of_fuel() {
+        let spin = r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const 0))
+              (func (export "run") (param i32 i32) (result i64) (loop (br 0)) (i64.const 0)))
+        "#;
+        let plugin = WasmPlugin::new(&wat::parse_str(spin).unwrap()).unwrap();
+        assert!(matches!(plugin.invoke("{}"), Err(AgentError::ToolError(_))));
+    }
+
+    #[test]
+    fn test_invoke_rejects_output_outside_memory_or_over_the_limit() {
+        let returning = |packed: &str| {
+            let module = format!(
+                r#"(module
+                     (memory (export "memory") 1)
+                     (func (export "alloc") (param i32) (result i32) (i32.const 0))
+                     (func (export "run") (param i32 i32) (result i64) (i64.const {})))"#,
+                packed
+            );
+            WasmPlugin::new(&wat::parse_str(module).unwrap()).unwrap().invoke("{}")
+        };
+        assert!(returning("0xffffffff").unwrap_err().to_string().contains("over the limit"));
+        // The memory is one 64 KiB page.
+        assert!(returning("0x10001").unwrap_err().to_string().contains("outside its memory"));
+        assert!(returning("0xfffa00000010").unwrap_err().to_string().contains("outside its memory"));
+        assert_eq!(returning("0x10000").unwrap().len(), 0x10000);
+    }
+
+    #[test]
+    fn test_load_plugins_registers_modules_with_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("echo.wasm"), wat::parse_str(ECHO).unwrap()).unwrap();
+        std::fs::write(dir.path().join("echo.json"), r#"{ "description": "Echo the parameters." }"#).unwrap();
+
+        let mut registry = ToolRegistry::default();
+        assert_eq!(load_plugins(dir.path(), &mut registry).unwrap(), vec!["echo".to_string()]);
+        assert!(registry.decision_prompt("step", "ctx").contains("`echo {}`: Echo the parameters."));
+        assert!(load_plugins(&dir.path().join("missing"), &mut ToolRegistry::default()).unwrap().is_empty());
+
+        std::fs::remove_file(dir.path().join("echo.json")).unwrap();
+        assert!(matches!(load_plugins(dir.path(), &mut ToolRegistry::default()), Err(AgentError::ConfigError(_))));
+    }
+}
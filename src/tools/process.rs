@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
+use tokio_util::sync::CancellationToken;
+
+use super::CommandOutput;
+use crate::error::AgentError;
+
+/// How long a `RunCommand` may run by default before it is killed.
+pub const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 300;
+/// How much of each of stdout and stderr is kept by default; the middle of longer output is dropped.
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Bounds on a single command run.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandLimits {
+    pub timeout: Duration,
+    pub max_output_bytes: usize,
+}
+
+impl Default for CommandLimits {
+    fn default() -> Self {
+        Self { timeout: Duration::from_secs(DEFAULT_COMMAND_TIMEOUT_SECS), max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES }
+    }
+}
+
+/// Runs `process` to completion within `limits`. The child gets its own process group so that on
+/// timeout everything it spawned (compilers, test binaries, ...) is killed along with it.
+pub async fn run_with_limits(process: Command, command: &str, limits: CommandLimits) -> Result<CommandOutput, AgentError> {
+    run_until_cancelled(process, command, None, limits, None).await
+}
+
+/// `run_with_limits` that also kills the process tree and fails with `AgentError::Cancelled` as
+/// soon as `cancel` is cancelled. `input` is written to the command's stdin, which is otherwise
+/// empty.
+pub async fn run_until_cancelled(
+    mut process: Command,
+    command: &str,
+    input: Option<&str>,
+    limits: CommandLimits,
+    cancel: Option<&CancellationToken>,
+) -> Result<CommandOutput, AgentError> {
+    let stdin = if input.is_some() { Stdio::piped() } else { Stdio::null() };
+    process.stdin(stdin).stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+    #[cfg(unix)]
+    process.process_group(0);
+    let mut child = process.spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stdin = child.stdin.take();
+
+    // Both pipes are drained while waiting (and stdin fed) so a chatty command never blocks on a
+    // full pipe.
+    let run = async {
+        let write_input = async {
+            if let (Some(mut stdin), Some(input)) = (stdin, input) {
+                // A command that exits without reading its input closes the pipe; that is not an error.
+                let _ = stdin.write_all(input.as_bytes()).await;
+            }
+        };
+        let (status, stdout, stderr, ()) = tokio::join!(
+            child.wait(),
+            read_capped(stdout, limits.max_output_bytes),
+            read_capped(stderr, limits.max_output_bytes),
+            write_input,
+        );
+        Ok::<_, std::io::Error>((status?, stdout?, stderr?))
+    };
+    let cancelled = async {
+        match cancel {
+            Some(token) => token.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+    let finished = tokio::select! {
+        finished = tokio::time::timeout(limits.timeout, run) => Some(finished),
+        _ = cancelled => None,
+    };
+    match finished {
+        Some(Ok(result)) => {
+            let (status, stdout, stderr) = result?;
+            Ok(CommandOutput { success: status.success(), exit_code: status.code(), stdout, stderr })
+        }
+        Some(Err(_)) => {
+            kill_process_tree(&mut child).await;
+            Err(AgentError::ToolTimeout { command: command.to_string(), seconds: limits.timeout.as_secs() })
+        }
+        None => {
+            kill_process_tree(&mut child).await;
+            Err(AgentError::Cancelled)
+        }
+    }
+}
+
+/// Reads `reader` to the end, keeping the first and last `max_bytes / 2` bytes and replacing the
+/// rest with a marker saying how much was dropped.
+async fn read_capped(mut reader: impl AsyncRead + Unpin, max_bytes: usize) -> std::io::Result<String> {
+    let head_limit = max_bytes / 2;
+    let tail_limit = max_bytes - head_limit;
+    let mut head = Vec::new();
+    let mut tail = VecDeque::new();
+    let mut dropped = 0usize;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        let mut chunk = &buf[..n];
+        if head.len() < head_limit {
+            let take = chunk.len().min(head_limit - head.len());
+            head.extend_from_slice(&chunk[..take]);
+            chunk = &chunk[take..];
+        }
+        tail.extend(chunk);
+        if tail.len() > tail_limit {
+            let excess = tail.len() - tail_limit;
+            tail.drain(..excess);
+            dropped += excess;
+        }
+    }
+
+    let mut output = String::from_utf8_lossy(&head).to_string();
+    if dropped > 0 {
+        output.push_str(&format!("\n[... {} bytes truncated ...]\n", dropped));
+    }
+    output.push_str(&String::from_utf8_lossy(tail.make_contiguous()));
+    Ok(output)
+}
+
+async fn kill_process_tree(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: killpg only sends a signal; the group id is the child's pid because it was
+        // spawned with process_group(0).
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+    #[cfg(windows)]
+    if let Some(pid) = child.id() {
+        let _ = Command::new("taskkill").args(["/F", "/T", "/PID", &pid.to_string()]).output().await;
+    }
+    let _ = child.kill().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_capped_keeps_head_and_tail() {
+        let input: Vec<u8> = (0..100u8).map(|i| b'a' + i % 26).collect();
+        let output = read_capped(&input[..], 20).await.unwrap();
+
+        assert!(output.starts_with("abcdefghij\n[... 80 bytes truncated ...]\n"));
+        assert!(output.ends_with(&String::from_utf8_lossy(&input[90..]).to_string()));
+        assert_eq!(read_capped(&b"short"[..], 20).await.unwrap(), "short");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_until_cancelled_kills_the_command() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            canceller.cancel();
+        });
+        let mut process = Command::new("sh");
+        process.args(["-c", "sleep 30"]);
+
+        let started = std::time::Instant::now();
+        let result = run_until_cancelled(process, "sleep 30", None, CommandLimits::default(), Some(&token)).await;
+        assert!(matches!(result, Err(AgentError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_until_cancelled_feeds_input_to_stdin() {
+        let mut process = Command::new("sh");
+        process.args(["-c", "tr a-z A-Z"]);
+        let output = run_until_cancelled(process, "tr a-z A-Z", Some("hello\n"), CommandLimits::default(), None).await.unwrap();
+        assert_eq!(output.stdout, "HELLO\n");
+    }
+}
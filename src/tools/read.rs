@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::error::AgentError;
+
+/// Bytes of a file `ReadFile` returns by default; the middle of larger files is left out.
+pub const DEFAULT_MAX_READ_BYTES: usize = 256 * 1024;
+/// Bytes inspected at the start of a file to decide whether it is binary.
+const SNIFF_BYTES: usize = 8 * 1024;
+
+/// Reads a text file for the agent. Binary files are refused. With `start_line`/`end_line`
+/// (1-based, inclusive) only that range is returned, prefixed with its position in the file;
+/// otherwise files over `max_bytes` keep their first and last lines with a marker in between.
+pub fn read_file(path: &Path, start_line: Option<usize>, end_line: Option<usize>, max_bytes: usize) -> Result<String, AgentError> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    if is_binary(&mut file)? {
+        return Err(AgentError::ToolError(format!(
+            "{} looks like a binary file ({} bytes); ReadFile only reads text",
+            path.display(),
+            size
+        )));
+    }
+
+    if start_line.is_some() || end_line.is_some() {
+        return read_range(file, start_line.unwrap_or(1), end_line, max_bytes);
+    }
+    if size <= max_bytes as u64 {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        return Ok(content);
+    }
+    read_head_and_tail(file, size, max_bytes)
+}
+
+/// Treats a file as binary when its first bytes contain a NUL or are not valid UTF-8.
+fn is_binary(file: &mut File) -> Result<bool, AgentError> {
+    let mut sniff = Vec::with_capacity(SNIFF_BYTES);
+    file.by_ref().take(SNIFF_BYTES as u64).read_to_end(&mut sniff)?;
+    file.rewind()?;
+    if sniff.contains(&0) {
+        return Ok(true);
+    }
+    Ok(match std::str::from_utf8(&sniff) {
+        Ok(_) => false,
+        // A multi-byte character cut off by the sniff window is not a sign of binary content.
+        Err(e) => e.error_len().is_some(),
+    })
+}
+
+fn read_range(file: File, start: usize, end: Option<usize>, max_bytes: usize) -> Result<String, AgentError> {
+    let start = start.max(1);
+    if end.is_some_and(|end| end < start) {
+        return Err(AgentError::ToolError(format!("end_line {} is before start_line {}", end.unwrap_or_default(), start)));
+    }
+
+    let mut selected = String::new();
+    let mut last = start - 1;
+    let mut total = 0;
+    let mut truncated = false;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        total = number;
+        if number < start || end.is_some_and(|end| number > end) || truncated {
+            continue;
+        }
+        if selected.len() + line.len() + 1 > max_bytes {
+            truncated = true;
+            continue;
+        }
+        selected.push_str(&line);
+        selected.push('\n');
+        last = number;
+    }
+
+    if start > total {
+        return Err(AgentError::ToolError(format!("start_line {} is past the end of the file ({} lines)", start, total)));
+    }
+    let mut output = format!("[Lines {}-{} of {}]\n{}", start, last, total, selected);
+    if truncated {
+        output.push_str(&format!("[... range cut off after line {} to stay under {} bytes ...]\n", last, max_bytes));
+    }
+    Ok(output)
+}
+
+fn read_head_and_tail(mut file: File, size: u64, max_bytes: usize) -> Result<String, AgentError> {
+    let head_limit = max_bytes / 2;
+    let tail_limit = max_bytes - head_limit;
+
+    let mut head = Vec::with_capacity(head_limit);
+    file.by_ref().take(head_limit as u64).read_to_end(&mut head)?;
+    // Cut at line boundaries so neither half starts or ends mid-line.
+    if let Some(newline) = head.iter().rposition(|&b| b == b'\n') {
+        head.truncate(newline + 1);
+    }
+
+    let mut tail = Vec::with_capacity(tail_limit);
+    file.seek(SeekFrom::Start(size - tail_limit as u64))?;
+    file.read_to_end(&mut tail)?;
+    if let Some(newline) = tail.iter().position(|&b| b == b'\n') {
+        tail.drain(..=newline);
+    }
+
+    let omitted = size - head.len() as u64 - tail.len() as u64;
+    let head_lines = head.iter().filter(|&&b| b == b'\n').count();
+    Ok(format!(
+        "{}[... {} bytes omitted after line {}; use start_line/end_line to read a specific range ...]\n{}",
+        String::from_utf8_lossy(&head),
+        omitted,
+        head_lines,
+        String::from_utf8_lossy(&tail)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count).map(|i| format!("line {}\n", i)).collect()
+    }
+
+    #[test]
+    fn test_read_file_refuses_binary_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.png");
+        std::fs::write(&path, [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00]).unwrap();
+        assert!(matches!(read_file(&path, None, None, 1024), Err(AgentError::ToolError(msg)) if msg.contains("binary")));
+    }
+
+    #[test]
+    fn test_read_file_keeps_head_and_tail_of_large_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.log");
+        std::fs::write(&path, numbered_lines(1000)).unwrap();
+
+        let output = read_file(&path, None, None, 100).unwrap();
+        assert!(output.starts_with("line 1\nline 2\n"));
+        assert!(output.contains("bytes omitted after line"));
+        assert!(output.ends_with("line 999\nline 1000\n"));
+        assert!(output.len() < 250);
+    }
+
+    #[test]
+    fn test_read_file_line_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lib.rs");
+        std::fs::write(&path, numbered_lines(50)).unwrap();
+
+        assert_eq!(read_file(&path, Some(10), Some(12), 1024).unwrap(), "[Lines 10-12 of 50]\nline 10\nline 11\nline 12\n");
+        assert_eq!(read_file(&path, Some(49), None, 1024).unwrap(), "[Lines 49-50 of 50]\nline 49\nline 50\n");
+        assert!(read_file(&path, Some(5), Some(200), 20).unwrap().contains("range cut off after line 6"));
+        assert!(read_file(&path, Some(60), None, 1024).is_err());
+        assert!(read_file(&path, Some(5), Some(4), 1024).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::error::AgentError;
+use crate::llm::{ToolCall, ToolDefinition};
+use crate::tools::{self, schema, Decision, Tool, ToolContext, ToolResult, BUILTIN_TOOL_PROMPTS, DB_QUERY_TOOL_PROMPT, DELEGATE_TOOL_PROMPT, NETWORK_TOOLS, READ_ONLY_TOOLS, RUN_SCRIPT_TOOL_PROMPT};
+
+/// Runs a custom tool with the parameters the reasoning model chose and returns its output.
+#[async_trait]
+pub trait ToolHandler: Send + Sync {
+    async fn call(&self, parameters: Value) -> Result<String, AgentError>;
+}
+
+#[async_trait]
+impl<F, Fut> ToolHandler for F
+where
+    F: Fn(Value) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, AgentError>> + Send,
+{
+    async fn call(&self, parameters: Value) -> Result<String, AgentError> {
+        self(parameters).await
+    }
+}
+
+#[derive(Clone)]
+struct CustomTool {
+    definition: ToolDefinition,
+    handler: Arc<dyn ToolHandler>,
+}
+
+/// The tools offered to the reasoning model: the built-in ones plus any registered at runtime.
+/// Decision prompts, native tool definitions and dispatch are all generated from it.
+///
+/// ```no_run
+/// # fn example() -> Result<(), cli_coding_agent::AgentError> {
+/// use cli_coding_agent::tools::ToolRegistry;
+/// use serde_json::{json, Value};
+///
+/// let mut registry = ToolRegistry::default();
+/// registry.register(
+///     "Weather",
+///     "Look up the current weather for a city.",
+///     json!({ "type": "object", "properties": { "city": { "type": "string" } }, "required": ["city"] }),
+///     |parameters: Value| async move { Ok(format!("Sunny in {}", parameters["city"])) },
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    custom: Vec<CustomTool>,
+    /// Whether `Delegate` is offered; the orchestrator enables it when it may spawn workers.
+    delegation: bool,
+    /// Languages `RunScript` has runtimes for; it is only offered when there are any.
+    script_languages: Vec<String>,
+    /// Names of the databases `DbQuery` may read; it is only offered when there are any.
+    db_connections: Vec<String>,
+    /// Whether the network tools (`NETWORK_TOOLS`) are withheld, in offline mode.
+    offline: bool,
+    /// Whether only the built-in tools that change nothing (`READ_ONLY_TOOLS`) are offered.
+    read_only: bool,
+}
+
+impl ToolRegistry {
+    /// Offers `Delegate`, after the other built-in tools, when `enabled`.
+    pub fn with_delegation(mut self, enabled: bool) -> Self {
+        self.delegation = enabled;
+        self
+    }
+
+    /// Offers `RunScript`, after the other built-in tools, for scripts in `languages` (the
+    /// languages of `AppConfig::script_runtimes`). None, the default, leaves it out.
+    pub fn with_script_languages(mut self, languages: Vec<String>) -> Self {
+        self.script_languages = languages;
+        self
+    }
+
+    /// Offers `DbQuery`, after the other built-in tools, for the databases named `connections`
+    /// (the names of `AppConfig::db_connections`). None, the default, leaves it out.
+    pub fn with_db_connections(mut self, connections: Vec<String>) -> Self {
+        self.db_connections = connections;
+        self
+    }
+
+    /// Withholds `Search`, `FetchUrl` and `HttpRequest` when `offline`, and rejects decisions
+    /// choosing them.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Offers only `ReadFile`, `Search`, `SearchInFiles`, `ListFiles` and `CodeOutline` of the built-in tools when
+    /// `read_only`, and rejects decisions choosing any other. Custom tools are still offered.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Adds a tool the reasoning model may choose. `parameters` is the JSON schema of its
+    /// parameters object. Names must be identifiers and may not shadow another tool.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: Value,
+        handler: impl ToolHandler + 'static,
+    ) -> Result<(), AgentError> {
+        let name = name.into();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(AgentError::ConfigError(format!("Invalid tool name '{}': use letters, digits and underscores", name)));
+        }
+        if self.is_builtin(&name) || self.find(&name).is_some() {
+            return Err(AgentError::ConfigError(format!("A tool named {} is already registered", name)));
+        }
+        let parameters = match parameters {
+            Value::Null => json!({ "type": "object", "properties": {} }),
+            Value::Object(_) => parameters,
+            other => return Err(AgentError::ConfigError(format!("The parameters of {} must be a JSON schema object, got {}", name, other))),
+        };
+        let definition = ToolDefinition { name, description: description.into(), parameters };
+        self.custom.push(CustomTool { definition, handler: Arc::new(handler) });
+        Ok(())
+    }
+
+    /// Names of every tool, built-in ones first.
+    pub fn names(&self) -> Vec<String> {
+        self.builtin_prompts()
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .chain(self.custom.iter().map(|tool| tool.definition.name.clone()))
+            .collect()
+    }
+
+    /// Native tool-calling definitions of every tool. Custom tools also take a required `thought`.
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        let mut definitions: Vec<ToolDefinition> = tools::tool_definitions().into_iter().filter(|definition| self.offers(&definition.name)).collect();
+        definitions.extend(self.custom.iter().map(|tool| with_thought(&tool.definition)));
+        definitions
+    }
+
+    /// The text decision prompt for `step`, offering every tool.
+    pub fn decision_prompt(&self, step: &str, context: &str) -> String {
+        let mut usages: Vec<String> = self.builtin_prompts().into_iter().map(|(_, usage)| usage).collect();
+        usages.extend(self.custom.iter().map(|tool| custom_usage(&tool.definition)));
+        tools::decision_prompt(step, context, &usages)
+    }
+
+    /// The decision prompt asking for a fix after `command` failed while working on `step`.
+    pub fn fix_prompt(&self, step: &str, command: &str, output: &str, context: &str) -> String {
+        self.decision_prompt(&tools::fix_step(step, command, output), context)
+    }
+
+    /// Rejects decisions naming a tool that is not registered. Since unknown names parse into
+    /// `Tool::Custom`, so do built-in names with invalid parameters; those are reported as such.
+    pub fn check(&self, decision: &Decision) -> Result<(), AgentError> {
+        let (name, parameters) = match &decision.tool {
+            Tool::Custom { name, parameters } => (name, parameters),
+            tool if self.offline && tool.needs_network() => return Err(tools::offline_error(&tool.name())),
+            tool if self.read_only && !tool.is_read_only() => return Err(tools::read_only_error(&tool.name())),
+            tool @ (Tool::Delegate { .. } | Tool::RunScript { .. } | Tool::DbQuery { .. }) if !self.offers(&tool.name()) => {
+                return Err(AgentError::ResponseParseError(format!("Unknown tool '{}'. Available tools: {}", tool.name(), self.names().join(", "))));
+            }
+            _ => return Ok(()),
+        };
+        if self.is_builtin(name) {
+            return Err(AgentError::ResponseParseError(format!("Invalid parameters for {}: {}", name, parameters)));
+        }
+        if self.find(name).is_none() {
+            return Err(AgentError::ResponseParseError(format!("Unknown tool '{}'. Available tools: {}", name, self.names().join(", "))));
+        }
+        Ok(())
+    }
+
+    /// The JSON Schema of a text decision, with one branch per tool.
+    pub fn decision_schema(&self) -> Value {
+        schema::decision_schema(self.definitions().iter().map(|definition| schema::decision_branch(definition, self.parameter_schema(definition))).collect())
+    }
+
+    /// Checks a text decision against the schema of the tool it names before it is parsed, so
+    /// unknown fields and missing or mistyped parameters are reported instead of misparsed.
+    /// Decisions naming an unknown tool are left to `check`.
+    pub fn validate_decision(&self, decision: &Value) -> Result<(), AgentError> {
+        let Some(name) = decision["tool_name"].as_str() else {
+            return Err(AgentError::ResponseParseError("Invalid decision: tool_name is required and must be a string".to_string()));
+        };
+        match self.definitions().into_iter().find(|definition| definition.name == name) {
+            Some(definition) => invalid_if(name, schema::validate(&schema::decision_branch(&definition, self.parameter_schema(&definition)), decision, "")),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the arguments of a native tool call against the schema of the tool it names.
+    /// `thought` and `file_path` may be left out; calls to unknown tools are left to `check`.
+    pub fn validate_call(&self, call: &ToolCall) -> Result<(), AgentError> {
+        let Some(definition) = self.definitions().into_iter().find(|definition| definition.name == call.name) else {
+            return Ok(());
+        };
+        let mut arguments = call.arguments.clone();
+        if let Some(arguments) = arguments.as_object_mut() {
+            arguments.retain(|field, value| !(field == "thought" && value.is_string()) && !(field == "file_path" && (value.is_string() || value.is_null())));
+        }
+        invalid_if(&call.name, schema::validate(&self.parameter_schema(&definition), &arguments, "arguments"))
+    }
+
+    /// Runs `tool`: custom tools through their handler, built-in ones through `tools::run_tool_in`
+    /// with `context`. Custom output that is a JSON object or array comes back `Structured`.
+    pub async fn run(&self, tool: Tool, context: &ToolContext) -> Result<ToolResult, AgentError> {
+        match tool {
+            Tool::Custom { name, parameters } => {
+                let custom = self.find(&name).ok_or_else(|| AgentError::ToolError(format!("Unknown tool '{}'", name)))?;
+                let output = custom.handler.call(parameters).await?;
+                // Handlers answering with a JSON object or array are passed on as structured output.
+                match serde_json::from_str::<Value>(output.trim()) {
+                    Ok(value) if value.is_object() || value.is_array() => Ok(ToolResult::Structured(value)),
+                    _ => Ok(ToolResult::Success(output)),
+                }
+            }
+            builtin => tools::run_tool_in(builtin, context).await,
+        }
+    }
+
+    /// The schema of `definition`'s parameters. Built-in tools reject unknown fields; custom
+    /// tools only when their schema says so.
+    fn parameter_schema(&self, definition: &ToolDefinition) -> Value {
+        schema::parameter_schema(definition, self.is_builtin(&definition.name))
+    }
+
+    /// Names and usage lines of the built-in tools on offer, in prompt order.
+    fn builtin_prompts(&self) -> Vec<(&'static str, String)> {
+        let mut prompts: Vec<(&'static str, String)> = BUILTIN_TOOL_PROMPTS.iter().filter(|(name, _)| self.offers(name)).map(|(name, usage)| (*name, usage.to_string())).collect();
+        if self.delegation {
+            prompts.push((DELEGATE_TOOL_PROMPT.0, DELEGATE_TOOL_PROMPT.1.to_string()));
+        }
+        if !self.script_languages.is_empty() {
+            let usage = format!("{} Available languages: {}.", RUN_SCRIPT_TOOL_PROMPT.1, self.script_languages.join(", "));
+            prompts.push((RUN_SCRIPT_TOOL_PROMPT.0, usage));
+        }
+        if !self.db_connections.is_empty() {
+            let usage = format!("{} Available connections: {}.", DB_QUERY_TOOL_PROMPT.1, self.db_connections.join(", "));
+            prompts.push((DB_QUERY_TOOL_PROMPT.0, usage));
+        }
+        prompts
+    }
+
+    /// Whether the built-in tool `name` is on offer; `Delegate`, `RunScript` and `DbQuery` only are
+    /// when enabled, the network tools only when not offline, and only the read-only ones in
+    /// read-only mode.
+    fn offers(&self, name: &str) -> bool {
+        match name {
+            _ if NETWORK_TOOLS.contains(&name) && self.offline => false,
+            _ if self.read_only => READ_ONLY_TOOLS.contains(&name),
+            _ if NETWORK_TOOLS.contains(&name) => true,
+            _ if name == DELEGATE_TOOL_PROMPT.0 => self.delegation,
+            _ if name == RUN_SCRIPT_TOOL_PROMPT.0 => !self.script_languages.is_empty(),
+            _ if name == DB_QUERY_TOOL_PROMPT.0 => !self.db_connections.is_empty(),
+            _ => true,
+        }
+    }
+
+    /// Whether `name` belongs to a built-in tool, including the optional ones when they are not
+    /// offered.
+    fn is_builtin(&self, name: &str) -> bool {
+        [DELEGATE_TOOL_PROMPT.0, RUN_SCRIPT_TOOL_PROMPT.0, DB_QUERY_TOOL_PROMPT.0].contains(&name) || BUILTIN_TOOL_PROMPTS.iter().any(|(builtin, _)| *builtin == name)
+    }
+
+    fn find(&self, name: &str) -> Option<&CustomTool> {
+        self.custom.iter().find(|tool| tool.definition.name == name)
+    }
+}
+
+fn invalid_if(name: &str, problems: Vec<String>) -> Result<(), AgentError> {
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(AgentError::ResponseParseError(format!("Invalid {} decision: {}", name, problems.join("; "))))
+    }
+}
+
+/// `definition` with the `thought` parameter every decision carries added to its schema.
+fn with_thought(definition: &ToolDefinition) -> ToolDefinition {
+    let mut parameters = definition.parameters.clone();
+    if !parameters["properties"].is_object() {
+        parameters["properties"] = json!({});
+    }
+    parameters["properties"]["thought"] = json!({ "type": "string", "description": "Your reasoning for choosing this tool for the current step." });
+    let mut required = vec![json!("thought")];
+    if let Some(existing) = parameters["required"].as_array() {
+        required.extend(existing.iter().cloned());
+    }
+    parameters["required"] = Value::Array(required);
+    ToolDefinition { parameters, ..definition.clone() }
+}
+
+/// The decision prompt line for a custom tool, e.g. `` `Weather { "city": "string" }`: ... ``.
+fn custom_usage(definition: &ToolDefinition) -> String {
+    let properties = definition.parameters["properties"].as_object();
+    let example: Vec<String> = properties
+        .into_iter()
+        .flatten()
+        .map(|(key, schema)| format!("\"{}\": \"{}\"", key, schema["type"].as_str().unwrap_or("value")))
+        .collect();
+    let mut usage = if example.is_empty() {
+        format!("`{} {{}}`: {}", definition.name, definition.description)
+    } else {
+        format!("`{} {{ {} }}`: {}", definition.name, example.join(", "), definition.description)
+    };
+    if let Some(described) = properties.filter(|p| p.values().any(|schema| schema["description"].is_string())) {
+        let notes: Vec<String> = described
+            .iter()
+            .filter_map(|(key, schema)| schema["description"].as_str().map(|d| format!("`{}`: {}", key, d)))
+            .collect();
+        usage.push_str(&format!(" Parameters: {}", notes.join("; ")));
+    }
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_registry() -> ToolRegistry {
+        let mut registry = ToolRegistry::default();
+        registry
+            .register(
+                "Echo",
+                "Repeat the given text.",
+                json!({ "type": "object", "properties": { "text": { "type": "string", "description": "What to repeat." } }, "required": ["text"] }),
+                |parameters: Value| async move { Ok(parameters["text"].as_str().unwrap_or_default().to_string()) },
+            )
+            .unwrap();
+        registry
+    }
+
+    fn decision(json: Value) -> Decision {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_register_rejects_invalid_and_taken_names() {
+        let mut registry = echo_registry();
+        let noop = |_: Value| async { Ok(String::new()) };
+        assert!(matches!(registry.register("ReadFile", "", Value::Null, noop), Err(AgentError::ConfigError(_))));
+        assert!(matches!(registry.register("Echo", "", Value::Null, noop), Err(AgentError::ConfigError(_))));
+        assert!(matches!(registry.register("bad name", "", Value::Null, noop), Err(AgentError::ConfigError(_))));
+        assert!(matches!(registry.register("Schema", "", json!("string"), noop), Err(AgentError::ConfigError(_))));
+        assert_eq!(registry.names().last().map(String::as_str), Some("Echo"));
+    }
+
+    #[test]
+    fn test_prompt_and_definitions_include_custom_tools() {
+        let registry = echo_registry();
+        let prompt = registry.decision_prompt("Say hi", "ctx");
+        assert!(prompt.contains("17. `Echo { \"text\": \"string\" }`: Repeat the given text. Parameters: `text`: What to repeat."));
+
+        let echo = registry.definitions().pop().unwrap();
+        assert_eq!(echo.name, "Echo");
+        assert_eq!(echo.parameters["required"], json!(["thought", "text"]));
+        assert_eq!(echo.parameters["properties"]["thought"]["type"], "string");
+        assert_eq!(ToolRegistry::default().decision_prompt("Say hi", "ctx"), tools::get_decision_prompt("Say hi", "ctx"));
+    }
+
+    #[test]
+    fn test_check_rejects_unknown_tools_and_invalid_builtin_parameters() {
+        let registry = echo_registry();
+        let unknown = decision(json!({ "thought": "t", "tool_name": "Teleport", "parameters": {} }));
+        let err = registry.check(&unknown).unwrap_err().to_string();
+        assert!(err.contains("Unknown tool 'Teleport'") && err.contains("Echo"), "{}", err);
+
+        let invalid = decision(json!({ "thought": "t", "tool_name": "ReadFile", "parameters": { "file": "x" } }));
+        assert!(registry.check(&invalid).unwrap_err().to_string().contains("Invalid parameters for ReadFile"));
+
+        let echo = decision(json!({ "thought": "t", "tool_name": "Echo", "parameters": { "text": "hi" } }));
+        assert!(registry.check(&echo).is_ok());
+    }
+
+    #[test]
+    fn test_delegate_is_only_offered_with_delegation() {
+        let delegate = decision(json!({ "thought": "t", "tool_name": "Delegate", "parameters": { "subtasks": ["a", "b"] } }));
+        let registry = echo_registry();
+        assert!(!registry.names().contains(&"Delegate".to_string()));
+        assert!(registry.definitions().iter().all(|definition| definition.name != "Delegate"));
+        assert!(registry.check(&delegate).unwrap_err().to_string().contains("Unknown tool 'Delegate'"));
+
+        let registry = registry.with_delegation(true);
+        assert_eq!(registry.names()[16..], ["Delegate", "Echo"]);
+        let prompt = registry.decision_prompt("Build it", "ctx");
+        assert!(prompt.contains("17. `Delegate {") && prompt.contains("18. `Echo {"), "{}", prompt);
+        assert!(registry.definitions().iter().any(|definition| definition.name == "Delegate"));
+        assert!(registry.check(&delegate).is_ok());
+    }
+
+    #[test]
+    fn test_run_script_is_only_offered_with_script_languages() {
+        let run_script = decision(json!({ "thought": "t", "tool_name": "RunScript", "parameters": { "language": "python", "code": "print(1)" } }));
+        let registry = echo_registry();
+        assert!(!registry.names().contains(&"RunScript".to_string()));
+        assert!(registry.check(&run_script).unwrap_err().to_string().contains("Unknown tool 'RunScript'"));
+
+        let registry = registry.with_delegation(true).with_script_languages(vec!["python".to_string(), "js".to_string()]);
+        assert_eq!(registry.names()[16..], ["Delegate", "RunScript", "Echo"]);
+        let prompt = registry.decision_prompt("Count the rows", "ctx");
+        assert!(prompt.contains("18. `RunScript {") && prompt.contains("Available languages: python, js.\n19. `Echo {"), "{}", prompt);
+        assert!(registry.definitions().iter().any(|definition| definition.name == "RunScript"));
+        assert!(registry.check(&run_script).is_ok());
+    }
+
+    #[test]
+    fn test_db_query_is_only_offered_with_db_connections() {
+        let db_query = decision(json!({ "thought": "t", "tool_name": "DbQuery", "parameters": { "connection_name": "app", "sql": "SELECT 1" } }));
+        let registry = echo_registry();
+        assert!(!registry.names().contains(&"DbQuery".to_string()));
+        assert!(registry.check(&db_query).unwrap_err().to_string().contains("Unknown tool 'DbQuery'"));
+
+        let registry = registry.with_db_connections(vec!["app".to_string(), "local".to_string()]);
+        assert_eq!(registry.names()[16..], ["DbQuery", "Echo"]);
+        let prompt = registry.decision_prompt("Write the users repository", "ctx");
+        assert!(prompt.contains("17. `DbQuery {") && prompt.contains("Available connections: app, local.\n18. `Echo {"), "{}", prompt);
+        assert!(registry.definitions().iter().any(|definition| definition.name == "DbQuery"));
+        assert!(registry.check(&db_query).is_ok());
+    }
+
+    #[test]
+    fn test_offline_withholds_network_tools() {
+        let search = decision(json!({ "thought": "t", "tool_name": "Search", "parameters": { "query": "tokio select" } }));
+        let registry = echo_registry().with_offline(true);
+        for name in NETWORK_TOOLS {
+            assert!(!registry.names().contains(&name.to_string()));
+            assert!(!registry.definitions().iter().any(|definition| definition.name == name));
+        }
+        assert!(!registry.decision_prompt("Look it up", "ctx").contains("`Search {"));
+        assert!(registry.check(&search).unwrap_err().to_string().contains("Search needs the network"));
+        assert!(echo_registry().check(&search).is_ok());
+    }
+
+    #[test]
+    fn test_read_only_offers_only_tools_that_change_nothing() {
+        let registry = echo_registry().with_read_only(true);
+        let mut builtin = registry.names();
+        builtin.retain(|name| name != "Echo");
+        assert_eq!(builtin, READ_ONLY_TOOLS);
+        assert!(registry.definitions().iter().any(|definition| definition.name == "Echo"));
+
+        let write = decision(json!({ "thought": "t", "tool_name": "WriteFile", "parameters": { "path": "a.txt", "content": "x" } }));
+        let read = decision(json!({ "thought": "t", "tool_name": "ReadFile", "parameters": { "path": "a.txt" } }));
+        assert!(registry.check(&write).unwrap_err().to_string().contains("not available in read-only mode"));
+        assert!(registry.check(&read).is_ok());
+        assert_eq!(registry.clone().with_offline(true).names(), ["ReadFile", "SearchInFiles", "ListFiles", "CodeOutline", "Echo"]);
+    }
+
+    #[test]
+    fn test_validate_checks_decisions_and_calls_against_tool_schemas() {
+        let registry = echo_registry();
+        let valid = json!({ "thought": "t", "tool_name": "CodeGeneration", "parameters": { "task": "x" }, "file_path": null });
+        assert!(registry.validate_decision(&valid).is_ok());
+        // Custom tools accept fields their schema does not close off.
+        assert!(registry.validate_decision(&json!({ "thought": "t", "tool_name": "Echo", "parameters": { "text": "hi", "loud": true } })).is_ok());
+        assert!(registry.validate_decision(&json!({ "thought": "t", "tool_name": "Teleport", "parameters": {} })).is_ok());
+
+        let err = registry.validate_decision(&json!({ "tool_name": "WriteFile", "parameters": { "path": "a", "text": "b" }, "reason": "r" })).unwrap_err().to_string();
+        assert!(err.contains("Invalid WriteFile decision: thought is required; parameters.content is required; parameters.text is not a known field"), "{}", err);
+        assert!(err.contains("reason is not a known field"), "{}", err);
+        assert!(registry.validate_decision(&json!({ "thought": "t" })).unwrap_err().to_string().contains("tool_name is required"));
+
+        let call = ToolCall { name: "ReadFile".to_string(), arguments: json!({ "thought": "t", "path": "a", "end_line": "9" }) };
+        assert!(registry.validate_call(&call).unwrap_err().to_string().contains("arguments.end_line must be integer, not a string"));
+        let call = ToolCall { name: "CodeGeneration".to_string(), arguments: json!({ "task": "x", "file_path": "a.rs" }) };
+        assert!(registry.validate_call(&call).is_ok());
+
+        let schema = registry.decision_schema();
+        let echo = schema["oneOf"].as_array().unwrap().last().unwrap().clone();
+        assert_eq!(echo["properties"]["tool_name"]["const"], "Echo");
+        assert_eq!(echo["properties"]["parameters"]["required"], json!(["text"]));
+    }
+
+    #[tokio::test]
+    async fn test_run_dispatches_custom_tools_to_their_handler() {
+        let registry = echo_registry();
+        let context = ToolContext::new(Arc::new(crate::config::AppConfig::test_config()));
+        let echo = Tool::Custom { name: "Echo".to_string(), parameters: json!({ "text": "hi" }) };
+        assert_eq!(registry.run(echo, &context).await.unwrap(), ToolResult::Success("hi".to_string()));
+        let echo = Tool::Custom { name: "Echo".to_string(), parameters: json!({ "text": r#"{"rows": 2}"# }) };
+        assert_eq!(registry.run(echo, &context).await.unwrap(), ToolResult::Structured(json!({ "rows": 2 })));
+        let missing = registry.run(Tool::Custom { name: "Teleport".to_string(), parameters: Value::Null }, &context).await;
+        assert!(matches!(missing, Err(AgentError::ToolError(_))));
+    }
+}
@@ -0,0 +1,166 @@
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::AppConfig;
+use crate::error::AgentError;
+
+/// Commands that are refused even when the model asks for them.
+const DEFAULT_DENIED_COMMANDS: &[&str] = &[
+    // rm -rf /, rm -rf ~, rm -rf /* ...
+    r"\brm\s+(-[a-zA-Z-]+\s+)*(/|/\*|~|~/|\$HOME)(\s|$)",
+    // curl ... | sh, wget -O- ... | sudo bash ...
+    r"\b(curl|wget)\b[^|]*\|\s*(sudo\s+)?(sh|bash|zsh)\b",
+    r"\bmkfs(\.\w+)?\b",
+    r"\bdd\b.*\bof=/dev/",
+    r">\s*/dev/sd[a-z]",
+    r":\(\)\s*\{\s*:\s*\|\s*:\s*&\s*\}\s*;\s*:",
+    r"\b(shutdown|reboot|halt|poweroff)\b",
+    r"\bchmod\s+(-R\s+)?777\s+/(\s|$)",
+];
+
+/// Environment variables a command may not set, since they change which programs and libraries
+/// it runs, or run code of their own, whatever the command says.
+const DENIED_ENV_VARS: &[&str] = &[
+    "PATH", "LD_PRELOAD", "LD_LIBRARY_PATH", "LD_AUDIT", "DYLD_INSERT_LIBRARIES", "DYLD_LIBRARY_PATH",
+    "BASH_ENV", "ENV", "SHELLOPTS", "BASHOPTS", "PROMPT_COMMAND", "PS4", "IFS",
+];
+
+/// Restricts file tools to allowed directories and refuses dangerous commands.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    enabled: bool,
+    allowed_roots: Vec<PathBuf>,
+    denied_commands: Vec<Regex>,
+}
+
+impl Sandbox {
+    /// An enabled sandbox rooted at `root`. The system temp directory is also allowed so
+    /// scratch files keep working.
+    pub fn new(root: &Path) -> Result<Self, AgentError> {
+        let mut sandbox = Self { enabled: true, allowed_roots: Vec::new(), denied_commands: Vec::new() };
+        sandbox = sandbox.allow_path(root)?.allow_path(&std::env::temp_dir())?;
+        for pattern in DEFAULT_DENIED_COMMANDS {
+            sandbox = sandbox.deny_command(pattern)?;
+        }
+        Ok(sandbox)
+    }
+
+    pub fn disabled() -> Self {
+        Self { enabled: false, allowed_roots: Vec::new(), denied_commands: Vec::new() }
+    }
+
+    /// Builds the sandbox for the current working directory from `AGENT_SANDBOX*` settings.
+    pub fn from_config(config: &AppConfig) -> Result<Self, AgentError> {
+        Self::for_root(config, &std::env::current_dir()?)
+    }
+
+    /// Builds the sandbox for `root` from `AGENT_SANDBOX*` settings.
+    pub fn for_root(config: &AppConfig, root: &Path) -> Result<Self, AgentError> {
+        if !config.sandbox_enabled {
+            return Ok(Self::disabled());
+        }
+        let mut sandbox = Self::new(root)?;
+        for path in &config.sandbox_allowed_paths {
+            sandbox = sandbox.allow_path(Path::new(path))?;
+        }
+        for pattern in &config.denied_commands {
+            sandbox = sandbox.deny_command(pattern)?;
+        }
+        Ok(sandbox)
+    }
+
+    pub fn allow_path(mut self, path: &Path) -> Result<Self, AgentError> {
+        let canonical = path.canonicalize().map_err(|e| {
+            AgentError::ConfigError(format!("Sandbox path {} is not accessible: {}", path.display(), e))
+        })?;
+        self.allowed_roots.push(canonical);
+        Ok(self)
+    }
+
+    pub fn deny_command(mut self, pattern: &str) -> Result<Self, AgentError> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| AgentError::ConfigError(format!("Invalid denied command pattern '{}': {}", pattern, e)))?;
+        self.denied_commands.push(regex);
+        Ok(self)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Resolves an existing path for reading, rejecting anything outside the allowed roots.
+    pub fn check_read(&self, path: &str) -> Result<PathBuf, AgentError> {
+        if !self.enabled {
+            return Ok(PathBuf::from(path));
+        }
+        let resolved = Path::new(path).canonicalize()?;
+        self.ensure_allowed(path, resolved)
+    }
+
+    /// Resolves a path for writing. The file and its parent directories may not exist yet; the
+    /// missing part is resolved against the nearest existing ancestor.
+    pub fn check_write(&self, path: &str) -> Result<PathBuf, AgentError> {
+        if !self.enabled {
+            return Ok(PathBuf::from(path));
+        }
+        let target = Path::new(path);
+        if target.file_name().is_none() {
+            return Err(AgentError::SandboxViolation(format!("'{}' is not a file path", path)));
+        }
+        let existing = target
+            .ancestors()
+            .find(|ancestor| !ancestor.as_os_str().is_empty() && ancestor.exists())
+            .unwrap_or(Path::new(""));
+        let mut resolved = if existing.as_os_str().is_empty() { Path::new(".").canonicalize()? } else { existing.canonicalize()? };
+        // Resolve `..` in the missing part lexically so it cannot climb out of an allowed root.
+        for component in target.strip_prefix(existing).unwrap_or(target).components() {
+            match component {
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::Normal(part) => resolved.push(part),
+                _ => {}
+            }
+        }
+        self.ensure_allowed(path, resolved)
+    }
+
+    pub fn check_command(&self, command: &str) -> Result<(), AgentError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        match self.denied_commands.iter().find(|pattern| pattern.is_match(command)) {
+            Some(pattern) => Err(AgentError::SandboxViolation(format!(
+                "command '{}' matches denied pattern '{}'",
+                command,
+                pattern.as_str()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Refuses `env` when it sets a variable in `DENIED_ENV_VARS`, or one whose value a denied
+    /// command pattern matches.
+    pub fn check_env(&self, env: &BTreeMap<String, String>) -> Result<(), AgentError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if let Some(name) = env.keys().find(|name| DENIED_ENV_VARS.iter().any(|denied| name.eq_ignore_ascii_case(denied))) {
+            return Err(AgentError::SandboxViolation(format!("commands may not set the environment variable {}", name)));
+        }
+        env.values().try_for_each(|value| self.check_command(value))
+    }
+
+    fn ensure_allowed(&self, original: &str, resolved: PathBuf) -> Result<PathBuf, AgentError> {
+        if self.allowed_roots.iter().any(|root| resolved.starts_with(root)) {
+            Ok(resolved)
+        } else {
+            Err(AgentError::SandboxViolation(format!(
+                "'{}' resolves to {}, outside the allowed directories",
+                original,
+                resolved.display()
+            )))
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+use crate::error::AgentError;
+
+/// Project generators `ScaffoldProject` knows how to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectTemplate {
+    RustBin,
+    RustLib,
+    Node,
+    Python,
+    Go,
+}
+
+impl ProjectTemplate {
+    /// Reads a template name such as "rust-cli", "rust-lib", "node", "python" or "go".
+    pub fn from_name(name: &str) -> Option<Self> {
+        let template = match name.trim().to_lowercase().replace(['_', ' '], "-").as_str() {
+            "rust" | "rust-bin" | "rust-cli" | "rust-app" | "cargo" | "cargo-bin" => ProjectTemplate::RustBin,
+            "rust-lib" | "rust-library" | "cargo-lib" => ProjectTemplate::RustLib,
+            "node" | "npm" | "javascript" | "js" | "typescript" | "ts" => ProjectTemplate::Node,
+            "python" | "py" | "poetry" => ProjectTemplate::Python,
+            "go" | "golang" => ProjectTemplate::Go,
+            _ => return None,
+        };
+        Some(template)
+    }
+
+    /// Whether the generator runs inside a directory it expects to exist, rather than creating
+    /// the project directory itself.
+    pub fn runs_inside(&self) -> bool {
+        matches!(self, ProjectTemplate::Node | ProjectTemplate::Go)
+    }
+
+    /// The generator command for project `name`. Generators that run inside the project directory
+    /// run there; the others run in its parent.
+    pub fn command(&self, name: &str) -> String {
+        match self {
+            ProjectTemplate::RustBin => format!("cargo new --bin {}", name),
+            ProjectTemplate::RustLib => format!("cargo new --lib {}", name),
+            ProjectTemplate::Node => "npm init -y".to_string(),
+            ProjectTemplate::Python => format!("poetry new {}", name),
+            ProjectTemplate::Go => format!("go mod init {}", name),
+        }
+    }
+}
+
+impl fmt::Display for ProjectTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ProjectTemplate::RustBin => "rust-bin",
+            ProjectTemplate::RustLib => "rust-lib",
+            ProjectTemplate::Node => "node",
+            ProjectTemplate::Python => "python",
+            ProjectTemplate::Go => "go",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The template named `template`, or an error listing the known ones.
+pub fn resolve_template(template: &str) -> Result<ProjectTemplate, AgentError> {
+    ProjectTemplate::from_name(template).ok_or_else(|| {
+        AgentError::ToolError(format!("Unknown project template '{}'. Use one of: rust-bin, rust-lib, node, python, go", template))
+    })
+}
+
+/// Rejects project names that are not a single plain directory name, since the name ends up in
+/// a shell command.
+pub fn check_project_name(name: &str) -> Result<(), AgentError> {
+    let valid = !name.is_empty()
+        && !name.starts_with(['-', '.'])
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(AgentError::ToolError(format!(
+            "Invalid project name '{}': use letters, digits, '-', '_' and '.', without a leading '-' or '.'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_templates_and_commands() {
+        assert_eq!(ProjectTemplate::from_name("Rust CLI"), Some(ProjectTemplate::RustBin));
+        assert_eq!(ProjectTemplate::from_name("rust_lib"), Some(ProjectTemplate::RustLib));
+        assert_eq!(ProjectTemplate::from_name("poetry"), Some(ProjectTemplate::Python));
+        assert!(resolve_template("cobol").is_err());
+        assert_eq!(ProjectTemplate::RustLib.command("parser"), "cargo new --lib parser");
+        assert_eq!(ProjectTemplate::Go.command("app"), "go mod init app");
+        assert!(ProjectTemplate::Node.runs_inside() && !ProjectTemplate::Python.runs_inside());
+    }
+
+    #[test]
+    fn test_check_project_name() {
+        assert!(check_project_name("my-cli_2").is_ok());
+        for bad in ["", "../escape", "a b", "x; rm -rf /", "--help", ".hidden"] {
+            assert!(check_project_name(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use serde_json::{json, Map, Value};
+
+use crate::llm::ToolDefinition;
+
+/// Decision fields that native tool definitions carry among the parameters but text decisions
+/// carry next to them.
+const DECISION_FIELDS: [&str; 2] = ["thought", "file_path"];
+
+/// The schema of a decision's `parameters` for `definition`: its native schema without the
+/// `thought` and `file_path` fields. A `closed` schema rejects unknown fields unless it says otherwise.
+pub fn parameter_schema(definition: &ToolDefinition, closed: bool) -> Value {
+    let mut schema = definition.parameters.clone();
+    if let Some(properties) = schema["properties"].as_object_mut() {
+        for field in DECISION_FIELDS {
+            properties.remove(field);
+        }
+    }
+    if let Some(required) = schema["required"].as_array_mut() {
+        required.retain(|name| !DECISION_FIELDS.iter().any(|field| name == field));
+    }
+    if let Some(object) = schema.as_object_mut().filter(|_| closed) {
+        object.entry("additionalProperties").or_insert(Value::Bool(false));
+    }
+    schema
+}
+
+/// The schema of a text decision choosing `definition`, whose parameters follow `parameters`.
+pub fn decision_branch(definition: &ToolDefinition, parameters: Value) -> Value {
+    json!({
+        "type": "object",
+        "description": definition.description,
+        "properties": {
+            "thought": { "type": "string" },
+            "tool_name": { "const": definition.name },
+            "parameters": parameters,
+            "file_path": { "type": ["string", "null"] },
+        },
+        "required": ["thought", "tool_name", "parameters"],
+        "additionalProperties": false,
+    })
+}
+
+/// The JSON Schema of a text decision: one `decision_branch` per tool, selected by `tool_name`.
+pub fn decision_schema(branches: Vec<Value>) -> Value {
+    json!({ "$schema": "https://json-schema.org/draft/2020-12/schema", "title": "Decision", "oneOf": branches })
+}
+
+/// Checks `value` against `schema` and describes every violation, located by its path from
+/// `path` (empty for the root). Supports the keywords tool schemas use: `type`, `enum`, `const`, `properties`,
+/// `required`, `additionalProperties` and `items`. A `null` optional property counts as absent.
+pub fn validate(schema: &Value, value: &Value, path: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    check(schema, value, path, &mut problems);
+    problems
+}
+
+fn check(schema: &Value, value: &Value, path: &str, problems: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+            problems.push(format!("{} must be {}, not {}", path, types.join(" or "), type_name(value)));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            problems.push(format!("{} must be one of {}, not {}", path, allowed.join(", "), value));
+        }
+    }
+    if let Some(constant) = schema.get("const") {
+        if constant != value {
+            problems.push(format!("{} must be {}, not {}", path, constant, value));
+        }
+    }
+    if let Value::Object(object) = value {
+        check_object(schema, object, path, problems);
+    }
+    if let (Some(items), Value::Array(array)) = (schema.get("items"), value) {
+        for (i, item) in array.iter().enumerate() {
+            check(items, item, &format!("{}[{}]", path, i), problems);
+        }
+    }
+}
+
+fn check_object(schema: &Value, object: &Map<String, Value>, path: &str, problems: &mut Vec<String>) {
+    let empty = Map::new();
+    let properties = schema.get("properties").and_then(Value::as_object).unwrap_or(&empty);
+    let required: Vec<&str> = schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str).collect();
+    for name in &required {
+        if object.get(*name).is_none_or(Value::is_null) {
+            problems.push(format!("{} is required", join(path, name)));
+        }
+    }
+    for (name, field) in object {
+        let field_path = join(path, name);
+        match properties.get(name) {
+            _ if field.is_null() && !required.contains(&name.as_str()) => {}
+            Some(field_schema) => check(field_schema, field, &field_path, problems),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    let known: Vec<&str> = properties.keys().map(String::as_str).collect();
+                    problems.push(format!("{} is not a known field (expected {})", field_path, if known.is_empty() { "none".to_string() } else { known.join(", ") }));
+                }
+                Some(extra @ Value::Object(_)) => check(extra, field, &field_path, problems),
+                _ => {}
+            },
+        }
+    }
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(number) if number.is_f64() => "a number",
+        Value::Number(_) => "an integer",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::tool_definitions;
+
+    fn definition(name: &str) -> ToolDefinition {
+        tool_definitions().into_iter().find(|definition| definition.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_parameter_schema_drops_decision_fields_and_closes_the_object() {
+        let schema = parameter_schema(&definition("CodeGeneration"), true);
+        assert_eq!(schema["required"], json!(["task"]));
+        assert!(schema["properties"].get("thought").is_none() && schema["properties"].get("file_path").is_none());
+        assert_eq!(schema["additionalProperties"], json!(false));
+        assert!(parameter_schema(&definition("CodeGeneration"), false).get("additionalProperties").is_none());
+
+        let decision = decision_schema(vec![decision_branch(&definition("CodeGeneration"), schema)]);
+        assert_eq!(decision["oneOf"][0]["properties"]["tool_name"], json!({ "const": "CodeGeneration" }));
+        assert_eq!(decision["oneOf"][0]["properties"]["parameters"]["required"], json!(["task"]));
+    }
+
+    #[test]
+    fn test_validate_reports_each_violation_with_its_path() {
+        let read = parameter_schema(&definition("ReadFile"), true);
+        assert!(validate(&read, &json!({ "path": "a.rs", "start_line": 3, "end_line": null }), "parameters").is_empty());
+        assert_eq!(
+            validate(&read, &json!({ "file": "a.rs", "start_line": "3" }), "parameters"),
+            vec![
+                "parameters.path is required".to_string(),
+                "parameters.file is not a known field (expected end_line, path, start_line)".to_string(),
+                "parameters.start_line must be integer, not a string".to_string(),
+            ]
+        );
+
+        let edit = parameter_schema(&definition("EditFile"), true);
+        let problems = validate(&edit, &json!({ "path": "a.rs", "edits": [{ "search": "x" }] }), "parameters");
+        assert_eq!(problems, vec!["parameters.edits[0].replace is required".to_string()]);
+        let run = parameter_schema(&definition("RunCommand"), true);
+        let problems = validate(&run, &json!({ "command": "ls", "env": { "A": 1 } }), "parameters");
+        assert_eq!(problems, vec!["parameters.env.A must be string, not an integer".to_string()]);
+        assert_eq!(validate(&json!({ "enum": ["a", "b"] }), &json!("c"), "x"), vec!["x must be one of \"a\", \"b\", not \"c\"".to_string()]);
+    }
+}
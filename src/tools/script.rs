@@ -0,0 +1,346 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio_util::sync::CancellationToken;
+
+use super::{CommandLimits, CommandOutput};
+use crate::error::AgentError;
+
+/// Where `RunScript` saves each script before running it, relative to the working directory.
+pub const SCRIPT_DIR: &str = ".agent/scripts";
+
+/// Linear memory a script's interpreter may grow to before its allocations fail.
+pub const SCRIPT_MEMORY_BYTES: usize = 512 * 1024 * 1024;
+
+/// A WASI interpreter `RunScript` runs scripts of `language` with, e.g.
+/// `python=.agent/runtimes/python.wasm`. The module is called as `<language> <script path>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptRuntime {
+    pub language: String,
+    pub module: PathBuf,
+}
+
+impl FromStr for ScriptRuntime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (language, module) = s.split_once('=').ok_or_else(|| format!("expected language=path/to/interpreter.wasm, got '{}'", s.trim()))?;
+        let language = language.trim().to_lowercase();
+        if language.is_empty() || !language.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(format!("invalid script language '{}' in '{}'", language, s.trim()));
+        }
+        match module.trim() {
+            "" => Err(format!("missing interpreter module after '=' in '{}'", s.trim())),
+            module => Ok(Self { language, module: PathBuf::from(module) }),
+        }
+    }
+}
+
+impl fmt::Display for ScriptRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.language, self.module.display())
+    }
+}
+
+/// The runtime for `language` among `runtimes`.
+pub fn find_runtime<'a>(runtimes: &'a [ScriptRuntime], language: &str) -> Result<&'a ScriptRuntime, AgentError> {
+    let wanted = language.trim().to_lowercase();
+    runtimes.iter().find(|runtime| runtime.language == wanted).ok_or_else(|| {
+        let languages: Vec<&str> = runtimes.iter().map(|runtime| runtime.language.as_str()).collect();
+        if languages.is_empty() {
+            AgentError::ToolError("RunScript is not available: no script runtimes are configured (AGENT_SCRIPT_RUNTIMES).".to_string())
+        } else {
+            AgentError::ToolError(format!("No script runtime for '{}'. Available languages: {}", language, languages.join(", ")))
+        }
+    })
+}
+
+/// Writes `code` into `dir` and returns the file's name. The name is derived from the content, so
+/// running the same script twice keeps one file.
+pub fn save_script(dir: &Path, code: &str) -> Result<String, AgentError> {
+    let mut hasher = DefaultHasher::new();
+    code.hash(&mut hasher);
+    let name = format!("script-{:08x}", hasher.finish() as u32);
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join(&name), code)?;
+    Ok(name)
+}
+
+/// The directories a script can see. Everything under `root` is readable at `.`; only
+/// `writable`, a directory below it, can be changed.
+#[derive(Debug, Clone)]
+pub struct ScriptAccess {
+    pub root: PathBuf,
+    /// The host directory and the relative path the script opens it by.
+    pub writable: Option<(PathBuf, String)>,
+}
+
+/// Runs `script` (a path relative to `access.root`) with `runtime`'s interpreter inside a wasmtime
+/// sandbox. The script gets no network, no environment and no files outside `access`; it is
+/// stopped once `limits.timeout` passes or `cancel` is cancelled, and each output stream is cut
+/// off at `limits.max_output_bytes`.
+#[cfg(feature = "scripts")]
+pub async fn run_script(
+    runtime: &ScriptRuntime,
+    script: &str,
+    access: ScriptAccess,
+    limits: CommandLimits,
+    cancel: Option<&CancellationToken>,
+) -> Result<CommandOutput, AgentError> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let module = sandbox::module(&runtime.module)?;
+    let args = vec![runtime.language.clone(), script.to_string()];
+    let stop = Arc::new(AtomicBool::new(false));
+    let deadline = std::time::Instant::now() + limits.timeout;
+    let mut task = {
+        let stop = stop.clone();
+        tokio::task::spawn_blocking(move || sandbox::execute(&module, &args, &access, limits.max_output_bytes, deadline, stop))
+    };
+    let cancelled = async {
+        match cancel {
+            Some(token) => token.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+    let (finished, was_cancelled) = tokio::select! {
+        finished = &mut task => (finished, false),
+        _ = cancelled => {
+            stop.store(true, Ordering::Relaxed);
+            (task.await, true)
+        }
+    };
+    let finished = finished.map_err(|e| AgentError::ToolError(format!("RunScript task failed: {}", e)))?;
+    match finished {
+        Ok(output) => Ok(output),
+        Err(_) if was_cancelled => Err(AgentError::Cancelled),
+        Err(_) if std::time::Instant::now() >= deadline => {
+            Err(AgentError::ToolTimeout { command: format!("{} script {}", runtime.language, script), seconds: limits.timeout.as_secs() })
+        }
+        Err(e) => Err(AgentError::ToolError(format!("The {} script failed: {:#}", runtime.language, e))),
+    }
+}
+
+/// Without the `scripts` feature there is no WebAssembly runtime to run scripts in.
+#[cfg(not(feature = "scripts"))]
+pub async fn run_script(
+    _runtime: &ScriptRuntime,
+    _script: &str,
+    _access: ScriptAccess,
+    _limits: CommandLimits,
+    _cancel: Option<&CancellationToken>,
+) -> Result<CommandOutput, AgentError> {
+    Err(AgentError::ToolError("RunScript is not available in this build; rebuild with `--features scripts`.".to_string()))
+}
+
+#[cfg(feature = "scripts")]
+mod sandbox {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::time::{Duration, Instant, SystemTime};
+    use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, UpdateDeadline};
+    use wasmtime_wasi::pipe::MemoryOutputPipe;
+    use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+    use wasmtime_wasi::{DirPerms, FilePerms, I32Exit, WasiCtxBuilder};
+
+    use super::{ScriptAccess, SCRIPT_MEMORY_BYTES};
+    use crate::error::AgentError;
+    use crate::tools::CommandOutput;
+
+    /// How often running scripts are checked against their deadline and for cancellation.
+    const TICK: Duration = Duration::from_millis(50);
+
+    struct ScriptState {
+        wasi: WasiP1Ctx,
+        limits: StoreLimits,
+    }
+
+    /// The engine every script runs on. A background thread advances its epoch every `TICK`, so a
+    /// script that loops without calling into WASI can still be interrupted.
+    fn engine() -> Result<&'static Engine, AgentError> {
+        static ENGINE: OnceLock<Engine> = OnceLock::new();
+        if let Some(engine) = ENGINE.get() {
+            return Ok(engine);
+        }
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).map_err(|e| AgentError::ToolError(format!("Cannot start the WebAssembly runtime: {}", e)))?;
+        let mut started = false;
+        let engine = ENGINE.get_or_init(|| {
+            started = true;
+            engine
+        });
+        if started {
+            std::thread::spawn(move || loop {
+                std::thread::sleep(TICK);
+                engine.increment_epoch();
+            });
+        }
+        Ok(engine)
+    }
+
+    /// The compiled interpreter at `path`. Compiling a full interpreter takes seconds, so modules
+    /// are kept until the file changes.
+    pub(super) fn module(path: &Path) -> Result<Module, AgentError> {
+        static MODULES: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, Module)>>> = OnceLock::new();
+        let unreadable = |e: &dyn std::fmt::Display| AgentError::ToolError(format!("Cannot load the script interpreter {}: {}", path.display(), e));
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).map_err(|e| unreadable(&e))?;
+        let modules = MODULES.get_or_init(Default::default);
+        if let Some((compiled_at, module)) = modules.lock().unwrap().get(path) {
+            if *compiled_at == modified {
+                return Ok(module.clone());
+            }
+        }
+        let module = Module::from_file(engine()?, path).map_err(|e| unreadable(&e))?;
+        modules.lock().unwrap().insert(path.to_path_buf(), (modified, module.clone()));
+        Ok(module)
+    }
+
+    /// Instantiates `module` with only `access` preopened and runs its `_start` to completion. A
+    /// non-zero exit is reported as a failed run; traps, running out of memory and interruption
+    /// are errors.
+    pub(super) fn execute(
+        module: &Module,
+        args: &[String],
+        access: &ScriptAccess,
+        max_output_bytes: usize,
+        deadline: Instant,
+        stop: Arc<AtomicBool>,
+    ) -> anyhow::Result<CommandOutput> {
+        let engine = module.engine();
+        let stdout = MemoryOutputPipe::new(max_output_bytes);
+        let stderr = MemoryOutputPipe::new(max_output_bytes);
+        let mut wasi = WasiCtxBuilder::new();
+        wasi.args(args).stdout(stdout.clone()).stderr(stderr.clone());
+        wasi.preopened_dir(&access.root, ".", DirPerms::READ, FilePerms::READ)?;
+        if let Some((dir, guest_path)) = &access.writable {
+            wasi.preopened_dir(dir, guest_path, DirPerms::all(), FilePerms::all())?;
+        }
+        let state = ScriptState { wasi: wasi.build_p1(), limits: StoreLimitsBuilder::new().memory_size(SCRIPT_MEMORY_BYTES).build() };
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(move |_| {
+            if stop.load(Ordering::Relaxed) || Instant::now() >= deadline {
+                anyhow::bail!("the script was interrupted")
+            }
+            Ok(UpdateDeadline::Continue(1))
+        });
+
+        let mut linker = Linker::new(engine);
+        preview1::add_to_linker_sync(&mut linker, |state: &mut ScriptState| &mut state.wasi)?;
+        let instance = linker.instantiate(&mut store, module)?;
+        let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+        let exit_code = match start.call(&mut store, ()) {
+            Ok(()) => 0,
+            Err(e) => match e.downcast_ref::<I32Exit>() {
+                Some(exit) => exit.0,
+                None => return Err(e),
+            },
+        };
+        let text = |pipe: &MemoryOutputPipe| {
+            let mut text = String::from_utf8_lossy(&pipe.contents()).into_owned();
+            if text.len() >= max_output_bytes {
+                text.push_str("\n[... output limit reached ...]\n");
+            }
+            text
+        };
+        Ok(CommandOutput { success: exit_code == 0, exit_code: Some(exit_code), stdout: text(&stdout), stderr: text(&stderr) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtimes_parse_and_are_found_by_language() {
+        let runtime: ScriptRuntime = " Python = .agent/runtimes/python.wasm ".parse().unwrap();
+        assert_eq!(runtime, ScriptRuntime { language: "python".to_string(), module: PathBuf::from(".agent/runtimes/python.wasm") });
+        assert_eq!(runtime.to_string(), "python=.agent/runtimes/python.wasm");
+        assert!("python".parse::<ScriptRuntime>().is_err());
+        assert!("python=".parse::<ScriptRuntime>().is_err());
+        assert!("my lang=x.wasm".parse::<ScriptRuntime>().is_err());
+
+        let runtimes = vec![runtime];
+        assert_eq!(find_runtime(&runtimes, "PYTHON").unwrap().language, "python");
+        assert!(find_runtime(&runtimes, "ruby").unwrap_err().to_string().contains("Available languages: python"));
+        assert!(find_runtime(&[], "python").unwrap_err().to_string().contains("AGENT_SCRIPT_RUNTIMES"));
+    }
+
+    /// A stand-in for an interpreter: instead of running the script it is given, it runs `body`,
+    /// which may print the "hi\n" at 16 or create the file named at 32 in a preopened directory.
+    #[cfg(feature = "scripts")]
+    fn interpreter(body: &str) -> String {
+        format!(
+            r#"
+            (module
+              (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+              (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+              (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+              (memory (export "memory") 1)
+              (data (i32.const 16) "hi\n")
+              (data (i32.const 32) "made.txt")
+              (func (export "_start") {body}))
+            "#
+        )
+    }
+
+    /// `body` exiting with the error number of creating `made.txt` in the preopened directory `fd`.
+    #[cfg(feature = "scripts")]
+    fn create_in(fd: u32) -> String {
+        format!("(call $proc_exit (call $path_open (i32.const {fd}) (i32.const 0) (i32.const 32) (i32.const 8) (i32.const 1) (i64.const 64) (i64.const 0) (i32.const 0) (i32.const 48)))")
+    }
+
+    #[cfg(feature = "scripts")]
+    async fn run(body: &str, root: &Path, limits: CommandLimits) -> Result<CommandOutput, AgentError> {
+        let module = root.join("interpreter.wasm");
+        std::fs::write(&module, wat::parse_str(interpreter(body)).unwrap()).unwrap();
+        let runtime = ScriptRuntime { language: "test".to_string(), module };
+        std::fs::create_dir_all(root.join("out")).unwrap();
+        let access = ScriptAccess { root: root.to_path_buf(), writable: Some((root.join("out"), "out".to_string())) };
+        run_script(&runtime, "script", access, limits, None).await
+    }
+
+    #[cfg(feature = "scripts")]
+    #[tokio::test]
+    async fn test_run_script_captures_output_and_exit_status() {
+        let dir = tempfile::tempdir().unwrap();
+        // iovec { buf: 16, len: 3 } at 0, bytes written at 8.
+        let print = "(i32.store (i32.const 0) (i32.const 16)) (i32.store (i32.const 4) (i32.const 3)) \
+                     (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))";
+        let output = run(print, dir.path(), CommandLimits::default()).await.unwrap();
+        assert!(output.success);
+        assert_eq!(output.stdout, "hi\n");
+
+        let output = run(&format!("{} (call $proc_exit (i32.const 3))", print), dir.path(), CommandLimits::default()).await.unwrap();
+        assert!(!output.success);
+        assert_eq!(output.exit_code, Some(3));
+        assert_eq!(output.to_tool_output(), "Exit code: 3\nSTDOUT:\nhi\n\nSTDERR:\n");
+    }
+
+    #[cfg(feature = "scripts")]
+    #[tokio::test]
+    async fn test_run_script_can_only_write_to_the_writable_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        // The root is preopened first (fd 3), the writable directory second (fd 4).
+        assert!(!run(&create_in(3), dir.path(), CommandLimits::default()).await.unwrap().success);
+        assert!(!dir.path().join("made.txt").exists());
+        assert!(run(&create_in(4), dir.path(), CommandLimits::default()).await.unwrap().success);
+        assert!(dir.path().join("out/made.txt").exists());
+    }
+
+    #[cfg(feature = "scripts")]
+    #[tokio::test]
+    async fn test_run_script_stops_scripts_at_the_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = CommandLimits { timeout: std::time::Duration::from_millis(200), ..CommandLimits::default() };
+        let result = run("(loop (br 0))", dir.path(), limits).await;
+        assert!(matches!(result, Err(AgentError::ToolTimeout { .. })), "{:?}", result);
+    }
+}
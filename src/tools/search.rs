@@ -0,0 +1,88 @@
+use ignore::WalkBuilder;
+use regex::{Regex, RegexBuilder};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::error::AgentError;
+
+/// Stop after this many matches so a broad pattern cannot flood the context.
+pub const MAX_SEARCH_MATCHES: usize = 200;
+/// Snippets longer than this many characters are truncated.
+const MAX_SNIPPET_CHARS: usize = 200;
+
+/// A single `file:line:snippet` hit from `search_in_files`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchMatch {
+    pub path: String,
+    pub line: usize,
+    pub snippet: String,
+}
+
+impl std::fmt::Display for SearchMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line, self.snippet)
+    }
+}
+
+/// Searches every text file under `root`, skipping anything ignored by .gitignore, hidden files and
+/// `target/`. `pattern` is a literal string unless `is_regex` is set. Files are read line by line and
+/// the walk stops once `MAX_SEARCH_MATCHES` hits are found.
+pub fn search_in_files(pattern: &str, root: &Path, is_regex: bool) -> Result<Vec<SearchMatch>, AgentError> {
+    let matcher = build_matcher(pattern, is_regex)?;
+    let mut matches = Vec::new();
+
+    let walker = WalkBuilder::new(root)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != "target")
+        .build();
+    for entry in walker.filter_map(|e| e.ok()) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let Ok(file) = File::open(entry.path()) else { continue };
+        let display_path = entry.path().display().to_string();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            // Binary or non-UTF-8 files fail to decode; skip the rest of them.
+            let Ok(line) = line else { break };
+            if matcher.is_match(&line) {
+                matches.push(SearchMatch { path: display_path.clone(), line: index + 1, snippet: snippet(&line) });
+                if matches.len() >= MAX_SEARCH_MATCHES {
+                    return Ok(matches);
+                }
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// Formats matches one per line as `file:line:snippet`, noting when the result was truncated.
+pub fn format_matches(pattern: &str, matches: &[SearchMatch]) -> String {
+    if matches.is_empty() {
+        return format!("No matches found for '{}'.", pattern);
+    }
+    let mut output = matches.iter().map(|m| m.to_string()).collect::<Vec<_>>().join("\n");
+    if matches.len() >= MAX_SEARCH_MATCHES {
+        output.push_str(&format!("\n... stopped after {} matches; narrow the pattern or path.", MAX_SEARCH_MATCHES));
+    }
+    output
+}
+
+fn build_matcher(pattern: &str, is_regex: bool) -> Result<Regex, AgentError> {
+    if pattern.is_empty() {
+        return Err(AgentError::ToolError("SearchInFiles requires a non-empty pattern.".to_string()));
+    }
+    let source = if is_regex { pattern.to_string() } else { regex::escape(pattern) };
+    RegexBuilder::new(&source)
+        .build()
+        .map_err(|e| AgentError::ToolError(format!("Invalid search pattern '{}': {}", pattern, e)))
+}
+
+fn snippet(line: &str) -> String {
+    let trimmed = line.trim();
+    if trimmed.chars().count() > MAX_SNIPPET_CHARS {
+        format!("{}...", trimmed.chars().take(MAX_SNIPPET_CHARS).collect::<String>())
+    } else {
+        trimmed.to_string()
+    }
+}
@@ -0,0 +1,336 @@
+use regex::Regex;
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::error::AgentError;
+use crate::project::ProjectLanguage;
+
+/// At most this many failing tests are listed in a summary.
+pub const MAX_LISTED_FAILURES: usize = 20;
+/// Lines of failure output kept per failing test.
+const MAX_DETAIL_LINES: usize = 6;
+/// Output kept when the test output could not be parsed at all.
+const MAX_UNPARSED_CHARS: usize = 2000;
+
+/// Test runners `RunTests` knows how to run and read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestFramework {
+    Cargo,
+    Pytest,
+    Npm,
+    Go,
+}
+
+impl TestFramework {
+    /// The framework for the project in `dir`, from its manifest files.
+    pub fn detect(dir: &Path) -> Option<Self> {
+        match ProjectLanguage::detect(dir)? {
+            ProjectLanguage::Rust => Some(TestFramework::Cargo),
+            ProjectLanguage::Python => Some(TestFramework::Pytest),
+            ProjectLanguage::TypeScript | ProjectLanguage::JavaScript => Some(TestFramework::Npm),
+            ProjectLanguage::Go => Some(TestFramework::Go),
+        }
+    }
+
+    /// Reads a hint such as "cargo", "pytest", "jest" or "python".
+    pub fn from_hint(hint: &str) -> Option<Self> {
+        let hint = hint.trim().to_lowercase();
+        let framework = match hint.split_whitespace().next().unwrap_or_default() {
+            "cargo" | "rust" => TestFramework::Cargo,
+            "pytest" | "python" | "py" => TestFramework::Pytest,
+            "npm" | "jest" | "mocha" | "vitest" | "node" | "javascript" | "typescript" | "js" | "ts" => TestFramework::Npm,
+            "go" | "golang" => TestFramework::Go,
+            _ => return None,
+        };
+        Some(framework)
+    }
+
+    /// The command that runs the whole test suite.
+    pub fn command(&self) -> &'static str {
+        match self {
+            TestFramework::Cargo => "cargo test",
+            TestFramework::Pytest => "pytest",
+            TestFramework::Npm => "npm test",
+            TestFramework::Go => "go test -v ./...",
+        }
+    }
+
+    /// Reads the pass/fail counts and failing tests from the runner's combined output.
+    pub fn parse(&self, output: &str, success: bool) -> TestReport {
+        let mut report = TestReport { framework: *self, success, passed: 0, failed: 0, ignored: 0, failures: Vec::new(), raw: None };
+        match self {
+            TestFramework::Cargo => parse_cargo(output, &mut report),
+            TestFramework::Pytest => parse_pytest(output, &mut report),
+            TestFramework::Npm => parse_npm(output, &mut report),
+            TestFramework::Go => parse_go(output, &mut report),
+        }
+        if report.passed + report.failed + report.ignored == 0 && report.failures.is_empty() {
+            // Probably a build error rather than test results: keep the end of the output.
+            let chars: Vec<char> = output.trim().chars().collect();
+            report.raw = Some(chars[chars.len().saturating_sub(MAX_UNPARSED_CHARS)..].iter().collect());
+        }
+        report
+    }
+}
+
+impl fmt::Display for TestFramework {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.command())
+    }
+}
+
+/// One failing test and the first lines of what it reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestFailure {
+    pub name: String,
+    pub details: Vec<String>,
+}
+
+/// The outcome of a test run, condensed from the runner's output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestReport {
+    pub framework: TestFramework,
+    /// Whether the test command exited successfully.
+    pub success: bool,
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    pub failures: Vec<TestFailure>,
+    /// The end of the output when no results could be read from it, e.g. after a compile error.
+    pub raw: Option<String>,
+}
+
+impl TestReport {
+    /// A short summary for the agent's history: the counts and the failing tests with the first
+    /// lines of their output, instead of the full test log.
+    pub fn summary(&self) -> String {
+        let verdict = if self.success { "PASSED" } else { "FAILED" };
+        let mut summary = format!(
+            "Tests {} ({}): {} passed, {} failed, {} ignored",
+            verdict, self.framework, self.passed, self.failed, self.ignored
+        );
+        if !self.failures.is_empty() {
+            summary.push_str("\nFailing tests:");
+            for failure in self.failures.iter().take(MAX_LISTED_FAILURES) {
+                summary.push_str(&format!("\n- {}", failure.name));
+                for line in &failure.details {
+                    summary.push_str(&format!("\n    {}", line));
+                }
+            }
+            if self.failures.len() > MAX_LISTED_FAILURES {
+                summary.push_str(&format!("\n... and {} more", self.failures.len() - MAX_LISTED_FAILURES));
+            }
+        }
+        if let Some(raw) = &self.raw {
+            summary.push_str(&format!("\nNo test results found in the output; it ends with:\n{}", raw));
+        }
+        summary
+    }
+
+    fn add_failure(&mut self, name: &str) {
+        if !self.failures.iter().any(|failure| failure.name == name) {
+            self.failures.push(TestFailure { name: name.to_string(), details: Vec::new() });
+        }
+    }
+
+    fn add_details(&mut self, name: &str, lines: &[&str]) {
+        if let Some(failure) = self.failures.iter_mut().find(|failure| failure.name == name) {
+            let lines = lines.iter().map(|line| line.trim()).filter(|line| !line.is_empty());
+            failure.details = lines.take(MAX_DETAIL_LINES).map(str::to_string).collect();
+        }
+    }
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).expect("valid test output pattern"))
+}
+
+/// Number before `word` in a summary such as "3 passed, 1 failed".
+fn count(text: &str, word: &str) -> usize {
+    let pattern = Regex::new(&format!(r"(\d+) {}", word)).expect("valid count pattern");
+    pattern.captures_iter(text).filter_map(|c| c[1].parse::<usize>().ok()).sum()
+}
+
+fn parse_cargo(output: &str, report: &mut TestReport) {
+    static RESULT: OnceLock<Regex> = OnceLock::new();
+    static FAILED: OnceLock<Regex> = OnceLock::new();
+    static SECTION: OnceLock<Regex> = OnceLock::new();
+    for result in regex(&RESULT, r"test result: \w+\. (\d+) passed; (\d+) failed; (\d+) ignored").captures_iter(output) {
+        report.passed += result[1].parse::<usize>().unwrap_or(0);
+        report.failed += result[2].parse::<usize>().unwrap_or(0);
+        report.ignored += result[3].parse::<usize>().unwrap_or(0);
+    }
+    for failed in regex(&FAILED, r"(?m)^test (\S+) \.\.\. FAILED").captures_iter(output) {
+        report.add_failure(&failed[1]);
+    }
+    // Each failure's output is printed in a "---- name stdout ----" section.
+    let lines: Vec<&str> = output.lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(section) = regex(&SECTION, r"^---- (\S+) stdout ----$").captures(line) {
+            let end = lines[i + 1..].iter().position(|l| l.starts_with("---- ") || l.starts_with("failures:")).map_or(lines.len(), |p| i + 1 + p);
+            report.add_details(&section[1], &lines[i + 1..end]);
+        }
+    }
+}
+
+fn parse_pytest(output: &str, report: &mut TestReport) {
+    static SUMMARY: OnceLock<Regex> = OnceLock::new();
+    static FAILED: OnceLock<Regex> = OnceLock::new();
+    // The last line looks like "==== 1 failed, 3 passed, 1 skipped in 0.12s ====".
+    if let Some(summary) = regex(&SUMMARY, r"(?m)^=+ (.*\d+ (passed|failed|skipped|error).*) in [\d.]+s.*=+$").captures_iter(output).last() {
+        report.passed = count(&summary[1], "passed");
+        report.failed = count(&summary[1], "failed") + count(&summary[1], "errors?");
+        report.ignored = count(&summary[1], "skipped") + count(&summary[1], "xfailed");
+    }
+    for failed in regex(&FAILED, r"(?m)^(?:FAILED|ERROR) (\S+)(?: - (.*))?$").captures_iter(output) {
+        report.add_failure(&failed[1]);
+        if let Some(reason) = failed.get(2) {
+            report.add_details(&failed[1], &[reason.as_str()]);
+        }
+    }
+}
+
+fn parse_npm(output: &str, report: &mut TestReport) {
+    static JEST: OnceLock<Regex> = OnceLock::new();
+    static JEST_FAILURE: OnceLock<Regex> = OnceLock::new();
+    static MOCHA_FAILURE: OnceLock<Regex> = OnceLock::new();
+    if let Some(jest) = regex(&JEST, r"(?m)^Tests:\s+(.*)$").captures(output) {
+        // Jest: "Tests:       1 failed, 1 skipped, 5 passed, 7 total"
+        report.passed = count(&jest[1], "passed");
+        report.failed = count(&jest[1], "failed");
+        report.ignored = count(&jest[1], "skipped") + count(&jest[1], "todo");
+        let lines: Vec<&str> = output.lines().collect();
+        for (i, line) in lines.iter().enumerate() {
+            if let Some(failure) = regex(&JEST_FAILURE, r"^\s*● (.+ › .+)$").captures(line) {
+                report.add_failure(failure[1].trim());
+                let end = lines[i + 1..].iter().position(|l| l.trim_start().starts_with("● ")).map_or(lines.len(), |p| i + 1 + p);
+                report.add_details(failure[1].trim(), &lines[i + 1..end]);
+            }
+        }
+    } else {
+        // Mocha: "5 passing", "1 pending", "2 failing", then "  1) Suite\n       test name:" blocks.
+        report.passed = count(output, "passing");
+        report.failed = count(output, "failing");
+        report.ignored = count(output, "pending");
+        let lines: Vec<&str> = output.lines().collect();
+        for (i, line) in lines.iter().enumerate() {
+            if regex(&MOCHA_FAILURE, r"^\s+\d+\) \S").is_match(line) && i + 1 < lines.len() && report.failures.len() < report.failed {
+                let name = format!("{} {}", line.trim().split_once(") ").map_or("", |(_, suite)| suite), lines[i + 1].trim().trim_end_matches(':'));
+                report.add_failure(&name);
+                report.add_details(&name, &lines[(i + 2).min(lines.len())..(i + 2 + MAX_DETAIL_LINES).min(lines.len())]);
+            }
+        }
+    }
+}
+
+fn parse_go(output: &str, report: &mut TestReport) {
+    static RUN: OnceLock<Regex> = OnceLock::new();
+    static RESULT: OnceLock<Regex> = OnceLock::new();
+    let lines: Vec<&str> = output.lines().collect();
+    // With -v, a test's log lines are printed between its "=== RUN" line and its result.
+    let mut started = std::collections::HashMap::new();
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(run) = regex(&RUN, r"^=== RUN\s+(\S+)").captures(line) {
+            started.insert(run[1].to_string(), i);
+            continue;
+        }
+        let Some(result) = regex(&RESULT, r"^\s*--- (PASS|FAIL|SKIP): (\S+)").captures(line) else { continue };
+        match &result[1] {
+            "PASS" => report.passed += 1,
+            "SKIP" => report.ignored += 1,
+            _ => {
+                report.failed += 1;
+                report.add_failure(&result[2]);
+                let start = started.get(&result[2]).map_or(i, |start| start + 1);
+                let logs: Vec<&str> = lines[start..i].iter().copied().filter(|l| l.starts_with("    ")).collect();
+                report.add_details(&result[2], &logs);
+            }
+        }
+    }
+}
+
+/// The framework `RunTests` uses: the hint when it names one, otherwise detected from `dir`.
+pub fn resolve_framework(hint: Option<&str>, dir: &Path) -> Result<TestFramework, AgentError> {
+    if let Some(framework) = hint.and_then(TestFramework::from_hint) {
+        return Ok(framework);
+    }
+    TestFramework::detect(dir).ok_or_else(|| {
+        AgentError::ToolError(format!(
+            "Could not detect a test framework in {}; pass framework_hint (cargo, pytest, npm or go) or use RunCommand.",
+            dir.display()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cargo_counts_and_failures() {
+        let output = "running 3 tests
+test parser::tests::parses_empty ... ok
+test parser::tests::parses_nested ... FAILED
+test parser::tests::slow ... ignored
+
+failures:
+
+---- parser::tests::parses_nested stdout ----
+thread 'parser::tests::parses_nested' panicked at src/parser.rs:42:9:
+assertion `left == right` failed
+  left: 1
+ right: 2
+
+failures:
+    parser::tests::parses_nested
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.01s
+
+running 2 tests
+test result: ok. 2 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+";
+        let report = TestFramework::Cargo.parse(output, false);
+        assert_eq!((report.passed, report.failed, report.ignored), (3, 1, 1));
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].name, "parser::tests::parses_nested");
+        assert_eq!(report.failures[0].details[1], "assertion `left == right` failed");
+        assert!(report.summary().starts_with("Tests FAILED (cargo test): 3 passed, 1 failed, 1 ignored\nFailing tests:\n- parser::tests::parses_nested\n    thread"));
+    }
+
+    #[test]
+    fn test_parse_pytest_jest_and_go() {
+        let pytest = "FAILED tests/test_api.py::test_login - AssertionError: 401 != 200\n==== 1 failed, 4 passed, 2 skipped in 0.31s ====\n";
+        let report = TestFramework::Pytest.parse(pytest, false);
+        assert_eq!((report.passed, report.failed, report.ignored), (4, 1, 2));
+        assert_eq!(report.failures[0], TestFailure { name: "tests/test_api.py::test_login".to_string(), details: vec!["AssertionError: 401 != 200".to_string()] });
+
+        let jest = "  ● Cart › adds items\n\n    expect(received).toBe(expected)\n\nTests:       1 failed, 5 passed, 6 total\n";
+        let report = TestFramework::Npm.parse(jest, false);
+        assert_eq!((report.passed, report.failed), (5, 1));
+        assert_eq!(report.failures[0].name, "Cart › adds items");
+        assert_eq!(report.failures[0].details[0], "expect(received).toBe(expected)");
+
+        let go = "=== RUN   TestAdd\n--- PASS: TestAdd (0.00s)\n=== RUN   TestSub\n    math_test.go:12: got 1, want 2\n--- FAIL: TestSub (0.00s)\n    math_test.go:14: extra\nFAIL\n";
+        let report = TestFramework::Go.parse(go, false);
+        assert_eq!((report.passed, report.failed), (1, 1));
+        assert_eq!(report.failures[0], TestFailure { name: "TestSub".to_string(), details: vec!["math_test.go:12: got 1, want 2".to_string()] });
+    }
+
+    #[test]
+    fn test_unparsed_output_keeps_the_tail() {
+        let report = TestFramework::Cargo.parse("error[E0425]: cannot find value `x` in this scope", false);
+        assert_eq!(report.raw.as_deref(), Some("error[E0425]: cannot find value `x` in this scope"));
+        assert!(report.summary().contains("No test results found"));
+    }
+
+    #[test]
+    fn test_resolve_framework_prefers_the_hint() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(resolve_framework(None, dir.path()).is_err());
+        std::fs::write(dir.path().join("Cargo.toml"), "[package]\n").unwrap();
+        assert_eq!(resolve_framework(None, dir.path()).unwrap(), TestFramework::Cargo);
+        assert_eq!(resolve_framework(Some("jest"), dir.path()).unwrap(), TestFramework::Npm);
+        assert_eq!(resolve_framework(Some("unknown"), dir.path()).unwrap(), TestFramework::Cargo);
+    }
+}
@@ -0,0 +1,401 @@
+use async_trait::async_trait;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::config::AppConfig;
+use crate::error::AgentError;
+use crate::tools::fetch::html_to_text;
+
+/// Results returned to the agent per search.
+pub const MAX_SEARCH_RESULTS: usize = 3;
+
+pub const BRAVE_SEARCH_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+pub const DUCKDUCKGO_SEARCH_URL: &str = "https://html.duckduckgo.com/html/";
+pub const TAVILY_SEARCH_URL: &str = "https://api.tavily.com/search";
+pub const SERPAPI_SEARCH_URL: &str = "https://serpapi.com/search.json";
+
+/// One web search hit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+/// A web search service used by `Tool::Search`.
+#[async_trait]
+pub trait SearchProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn search(&self, query: &str) -> Result<Vec<WebResult>, AgentError>;
+}
+
+/// Which `SearchProvider` `Tool::Search` uses, set with `AGENT_SEARCH_PROVIDER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchBackend {
+    Brave,
+    DuckDuckGo,
+    Tavily,
+    SerpApi,
+    Searx,
+}
+
+impl fmt::Display for SearchBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchBackend::Brave => write!(f, "brave"),
+            SearchBackend::DuckDuckGo => write!(f, "duckduckgo"),
+            SearchBackend::Tavily => write!(f, "tavily"),
+            SearchBackend::SerpApi => write!(f, "serpapi"),
+            SearchBackend::Searx => write!(f, "searx"),
+        }
+    }
+}
+
+impl FromStr for SearchBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "brave" => Ok(SearchBackend::Brave),
+            "duckduckgo" | "ddg" => Ok(SearchBackend::DuckDuckGo),
+            "tavily" => Ok(SearchBackend::Tavily),
+            "serpapi" => Ok(SearchBackend::SerpApi),
+            "searx" | "searxng" => Ok(SearchBackend::Searx),
+            other => Err(format!("unknown search provider '{}' (expected brave, duckduckgo, tavily, serpapi or searx)", other)),
+        }
+    }
+}
+
+/// Creates the search provider selected in `config`, failing if it is missing its key or URL.
+/// `search_base_url`, when set, replaces the provider's endpoint.
+pub fn create_search_provider(config: &AppConfig) -> Result<Box<dyn SearchProvider>, AgentError> {
+    let endpoint = |default: &str| config.search_base_url.clone().unwrap_or_else(|| default.to_string());
+    let provider: Box<dyn SearchProvider> = match config.search_provider {
+        SearchBackend::Brave => {
+            let api_key = config.brave_search_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("Brave Search".to_string()))?;
+            Box::new(BraveSearch::with_base_url(api_key, endpoint(BRAVE_SEARCH_URL)))
+        }
+        SearchBackend::DuckDuckGo => Box::new(DuckDuckGoSearch::with_base_url(endpoint(DUCKDUCKGO_SEARCH_URL))),
+        SearchBackend::Tavily => {
+            let api_key = config.tavily_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("Tavily".to_string()))?;
+            Box::new(TavilySearch::with_base_url(api_key, endpoint(TAVILY_SEARCH_URL)))
+        }
+        SearchBackend::SerpApi => {
+            let api_key = config.serpapi_api_key.clone().ok_or_else(|| AgentError::ApiKeyMissing("SerpAPI".to_string()))?;
+            Box::new(SerpApiSearch::with_base_url(api_key, endpoint(SERPAPI_SEARCH_URL)))
+        }
+        SearchBackend::Searx => {
+            let base_url = config
+                .search_base_url
+                .clone()
+                .or_else(|| config.searx_url.clone())
+                .ok_or_else(|| AgentError::ConfigError("SEARX_URL must be set to use the searx search provider".to_string()))?;
+            Box::new(SearxSearch::new(base_url))
+        }
+    };
+    Ok(provider)
+}
+
+/// Renders search results the way `Tool::Search` reports them.
+pub fn format_results(results: &[WebResult]) -> String {
+    if results.is_empty() {
+        return "No results found.".to_string();
+    }
+    results
+        .iter()
+        .take(MAX_SEARCH_RESULTS)
+        .enumerate()
+        .map(|(i, res)| format!("[Result {}]\nTitle: {}\nURL: {}\nSnippet: {}\n\n", i + 1, res.title, res.url, res.snippet))
+        .collect()
+}
+
+async fn check_status(name: &str, response: reqwest::Response) -> Result<reqwest::Response, AgentError> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(AgentError::ToolError(format!("{} API Error: {}", name, response.text().await?)))
+    }
+}
+
+pub struct BraveSearch {
+    api_key: String,
+    base_url: String,
+}
+
+impl BraveSearch {
+    pub fn new(api_key: String) -> Self {
+        Self::with_base_url(api_key, BRAVE_SEARCH_URL.to_string())
+    }
+
+    pub fn with_base_url(api_key: String, base_url: String) -> Self {
+        Self { api_key, base_url }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for BraveSearch {
+    fn name(&self) -> &'static str {
+        "Brave Search"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<WebResult>, AgentError> {
+        #[derive(Deserialize)]
+        struct BraveResponse {
+            #[serde(default)]
+            web: Option<Web>,
+        }
+        #[derive(Deserialize)]
+        struct Web {
+            results: Vec<BraveResult>,
+        }
+        #[derive(Deserialize)]
+        struct BraveResult {
+            title: String,
+            url: String,
+            #[serde(default)]
+            description: String,
+        }
+
+        let response = reqwest::Client::new()
+            .get(&self.base_url)
+            .query(&[("q", query)])
+            .header("X-Subscription-Token", &self.api_key)
+            .send()
+            .await?;
+        let body: BraveResponse = check_status(self.name(), response).await?.json().await?;
+        Ok(body
+            .web
+            .map(|web| web.results)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|res| WebResult { title: res.title, url: res.url, snippet: res.description })
+            .collect())
+    }
+}
+
+/// Scrapes DuckDuckGo's HTML results page; needs no API key.
+pub struct DuckDuckGoSearch {
+    base_url: String,
+}
+
+impl DuckDuckGoSearch {
+    pub fn new() -> Self {
+        Self::with_base_url(DUCKDUCKGO_SEARCH_URL.to_string())
+    }
+
+    pub fn with_base_url(base_url: String) -> Self {
+        Self { base_url }
+    }
+}
+
+impl Default for DuckDuckGoSearch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl SearchProvider for DuckDuckGoSearch {
+    fn name(&self) -> &'static str {
+        "DuckDuckGo"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<WebResult>, AgentError> {
+        let response = reqwest::Client::new()
+            .get(&self.base_url)
+            .query(&[("q", query)])
+            .header(reqwest::header::USER_AGENT, concat!("rust-cli-agent/", env!("CARGO_PKG_VERSION")))
+            .send()
+            .await?;
+        let html = check_status(self.name(), response).await?.text().await?;
+        Ok(parse_duckduckgo_html(&html))
+    }
+}
+
+/// Pulls the title, target URL and snippet of each result out of a DuckDuckGo HTML results page.
+pub fn parse_duckduckgo_html(html: &str) -> Vec<WebResult> {
+    static RESULT: OnceLock<Regex> = OnceLock::new();
+    static SNIPPET: OnceLock<Regex> = OnceLock::new();
+    let build = |pattern: &str| RegexBuilder::new(pattern).dot_matches_new_line(true).build().expect("valid DuckDuckGo regex");
+    let result = RESULT.get_or_init(|| build(r#"<a[^>]*class="result__a"[^>]*href="([^"]+)"[^>]*>(.*?)</a>"#));
+    let snippet = SNIPPET.get_or_init(|| build(r#"class="result__snippet"[^>]*>(.*?)</a>"#));
+
+    let snippets: Vec<String> = snippet.captures_iter(html).map(|caps| html_to_text(&caps[1])).collect();
+    result
+        .captures_iter(html)
+        .enumerate()
+        .map(|(i, caps)| WebResult {
+            title: html_to_text(&caps[2]),
+            url: duckduckgo_target(&html_to_text(&caps[1])),
+            snippet: snippets.get(i).cloned().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// DuckDuckGo links go through a redirect (`//duckduckgo.com/l/?uddg=<target>`); returns the target.
+fn duckduckgo_target(href: &str) -> String {
+    let absolute = if href.starts_with("//") { format!("https:{}", href) } else { href.to_string() };
+    reqwest::Url::parse(&absolute)
+        .ok()
+        .and_then(|url| url.query_pairs().find(|(key, _)| key == "uddg").map(|(_, target)| target.into_owned()))
+        .unwrap_or(absolute)
+}
+
+pub struct TavilySearch {
+    api_key: String,
+    base_url: String,
+}
+
+impl TavilySearch {
+    pub fn new(api_key: String) -> Self {
+        Self::with_base_url(api_key, TAVILY_SEARCH_URL.to_string())
+    }
+
+    pub fn with_base_url(api_key: String, base_url: String) -> Self {
+        Self { api_key, base_url }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for TavilySearch {
+    fn name(&self) -> &'static str {
+        "Tavily"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<WebResult>, AgentError> {
+        #[derive(Deserialize)]
+        struct TavilyResponse {
+            #[serde(default)]
+            results: Vec<TavilyResult>,
+        }
+        #[derive(Deserialize)]
+        struct TavilyResult {
+            title: String,
+            url: String,
+            #[serde(default)]
+            content: String,
+        }
+
+        let response = reqwest::Client::new()
+            .post(&self.base_url)
+            .bearer_auth(&self.api_key)
+            .json(&serde_json::json!({ "query": query, "max_results": MAX_SEARCH_RESULTS }))
+            .send()
+            .await?;
+        let body: TavilyResponse = check_status(self.name(), response).await?.json().await?;
+        Ok(body.results.into_iter().map(|res| WebResult { title: res.title, url: res.url, snippet: res.content }).collect())
+    }
+}
+
+pub struct SerpApiSearch {
+    api_key: String,
+    base_url: String,
+}
+
+impl SerpApiSearch {
+    pub fn new(api_key: String) -> Self {
+        Self::with_base_url(api_key, SERPAPI_SEARCH_URL.to_string())
+    }
+
+    pub fn with_base_url(api_key: String, base_url: String) -> Self {
+        Self { api_key, base_url }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for SerpApiSearch {
+    fn name(&self) -> &'static str {
+        "SerpAPI"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<WebResult>, AgentError> {
+        #[derive(Deserialize)]
+        struct SerpApiResponse {
+            #[serde(default)]
+            organic_results: Vec<SerpApiResult>,
+        }
+        #[derive(Deserialize)]
+        struct SerpApiResult {
+            title: String,
+            link: String,
+            #[serde(default)]
+            snippet: String,
+        }
+
+        let response = reqwest::Client::new()
+            .get(&self.base_url)
+            .query(&[("engine", "google"), ("q", query), ("api_key", self.api_key.as_str())])
+            .send()
+            .await?;
+        let body: SerpApiResponse = check_status(self.name(), response).await?.json().await?;
+        Ok(body.organic_results.into_iter().map(|res| WebResult { title: res.title, url: res.link, snippet: res.snippet }).collect())
+    }
+}
+
+/// A self-hosted SearxNG instance; its JSON output format must be enabled.
+pub struct SearxSearch {
+    base_url: String,
+}
+
+impl SearxSearch {
+    pub fn new(base_url: String) -> Self {
+        Self { base_url }
+    }
+}
+
+#[async_trait]
+impl SearchProvider for SearxSearch {
+    fn name(&self) -> &'static str {
+        "SearxNG"
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<WebResult>, AgentError> {
+        #[derive(Deserialize)]
+        struct SearxResponse {
+            #[serde(default)]
+            results: Vec<SearxResult>,
+        }
+        #[derive(Deserialize)]
+        struct SearxResult {
+            title: String,
+            url: String,
+            #[serde(default)]
+            content: String,
+        }
+
+        let url = format!("{}/search", self.base_url.trim_end_matches('/'));
+        let response = reqwest::Client::new().get(url).query(&[("q", query), ("format", "json")]).send().await?;
+        let body: SearxResponse = check_status(self.name(), response).await?.json().await?;
+        Ok(body.results.into_iter().map(|res| WebResult { title: res.title, url: res.url, snippet: res.content }).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_backend_from_str() {
+        assert_eq!("DuckDuckGo".parse::<SearchBackend>(), Ok(SearchBackend::DuckDuckGo));
+        assert_eq!("searxng".parse::<SearchBackend>(), Ok(SearchBackend::Searx));
+        assert!("bing".parse::<SearchBackend>().is_err());
+    }
+
+    #[test]
+    fn test_parse_duckduckgo_html() {
+        let html = r#"
+            <div class="result"><h2><a rel="nofollow" class="result__a" href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fdocs.rs%2Ftokio&amp;rut=abc">Tokio &amp; docs</a></h2>
+            <a class="result__snippet" href="x">An <b>async</b> runtime.</a></div>
+            <div class="result"><h2><a class="result__a" href="https://tokio.rs/">Tokio</a></h2></div>"#;
+        assert_eq!(
+            parse_duckduckgo_html(html),
+            vec![
+                WebResult { title: "Tokio & docs".to_string(), url: "https://docs.rs/tokio".to_string(), snippet: "An async runtime.".to_string() },
+                WebResult { title: "Tokio".to_string(), url: "https://tokio.rs/".to_string(), snippet: String::new() },
+            ]
+        );
+    }
+}
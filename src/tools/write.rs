@@ -0,0 +1,80 @@
+use chrono::Utc;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::AgentError;
+
+/// Where previous versions of overwritten files are kept when backups are enabled.
+pub const BACKUP_DIR: &str = ".agent/backups";
+
+/// Writes `content` to `path`, creating missing parent directories. The content goes to a sibling
+/// temp file that is then renamed over `path`, so a failed write never leaves a half-written file.
+pub fn write_atomic(path: &Path, content: &str) -> Result<(), AgentError> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".agent-tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    std::fs::write(&tmp_path, content)?;
+    if let Err(e) = std::fs::rename(&tmp_path, path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Copies the current content of `path` to `<backup_root>/<timestamp>/<path>` and returns where it
+/// went, or `None` when there is no existing file to back up.
+pub fn backup_file(path: &Path, backup_root: &Path) -> Result<Option<PathBuf>, AgentError> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let timestamp = Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string();
+    let backup = backup_root.join(timestamp).join(relative_backup_path(path));
+    if let Some(parent) = backup.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(path, &backup)?;
+    Ok(Some(backup))
+}
+
+/// Mirrors `path` under the backup directory: relative paths are kept, absolute ones lose their
+/// root (and drive prefix), and `..` components are dropped so backups cannot escape the directory.
+fn relative_backup_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_creates_parent_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("src").join("nested").join("lib.rs");
+        write_atomic(&path, "pub fn a() {}\n").unwrap();
+        write_atomic(&path, "pub fn b() {}\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "pub fn b() {}\n");
+        assert!(!dir.path().join("src/nested/lib.rs.agent-tmp").exists());
+    }
+
+    #[test]
+    fn test_backup_file_mirrors_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let backups = dir.path().join("backups");
+        assert_eq!(backup_file(&path, &backups).unwrap(), None);
+
+        std::fs::write(&path, "old = true\n").unwrap();
+        let backup = backup_file(&path, &backups).unwrap().unwrap();
+        assert!(backup.starts_with(&backups));
+        assert!(backup.ends_with(relative_backup_path(&path)));
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), "old = true\n");
+        assert_eq!(relative_backup_path(Path::new("../src/./main.rs")), PathBuf::from("src/main.rs"));
+    }
+}
@@ -0,0 +1,353 @@
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+use tokio_util::sync::CancellationToken;
+
+use crate::cost_tracker::CostTracker;
+use crate::events::{AgentEvent, EventRecord, EventSink};
+use crate::metrics::format_duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+/// Everything the terminal UI shows, folded together from the run's events.
+#[derive(Debug, Default)]
+pub struct TuiState {
+    pub steps: Vec<(String, StepStatus)>,
+    pub history: Vec<EventRecord>,
+    /// History entry being browsed; `None` follows the live output.
+    pub selected: Option<usize>,
+    /// Latest tool output or generated code.
+    pub output: String,
+    /// Set once the run is over, to whether it succeeded.
+    pub finished: Option<bool>,
+    /// Set once Ctrl-C asked the run to stop, until it has.
+    pub stopping: bool,
+}
+
+impl TuiState {
+    pub fn apply(&mut self, record: EventRecord) {
+        match &record.event {
+            AgentEvent::PlanCreated { steps } => {
+                self.steps = steps.iter().map(|s| (s.clone(), StepStatus::Pending)).collect();
+            }
+            AgentEvent::PlanRevised { step_index, steps, .. } => {
+                self.steps.truncate(step_index + 1);
+                self.steps.extend(steps.iter().map(|s| (s.clone(), StepStatus::Pending)));
+            }
+            AgentEvent::StepStarted { step_index, .. } => {
+                self.settle_running(StepStatus::Done);
+                self.set_status(*step_index, StepStatus::Running);
+            }
+            AgentEvent::ToolExecuted { step_index, success, output, .. } => {
+                self.output = output.clone();
+                // A later success (e.g. a fixed command) brings a failed step back to running.
+                self.set_status(*step_index, if *success { StepStatus::Running } else { StepStatus::Failed });
+            }
+            AgentEvent::CodeGenerated { code, .. } => self.output = code.clone(),
+            AgentEvent::StepChanges { changes, .. } => {
+                self.output = changes.iter().map(|change| change.diff.clone().unwrap_or_else(|| format!("{:?} {} (no diff)", change.kind, change.path))).collect::<Vec<_>>().join("\n");
+            }
+            AgentEvent::Error { step_index: Some(step_index), message } => {
+                self.output = message.clone();
+                self.set_status(*step_index, StepStatus::Failed);
+            }
+            AgentEvent::RunFinished { success, .. } => self.finish(*success),
+            AgentEvent::DecisionMade { .. } | AgentEvent::StepFinished { .. } | AgentEvent::GoalVerified { .. } | AgentEvent::Error { step_index: None, .. } => {}
+        }
+        self.history.push(record);
+    }
+
+    pub fn finish(&mut self, succeeded: bool) {
+        self.settle_running(if succeeded { StepStatus::Done } else { StepStatus::Failed });
+        self.finished = Some(succeeded);
+    }
+
+    pub fn select_previous(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() - 1;
+        self.selected = Some(self.selected.unwrap_or(last + 1).saturating_sub(1).min(last));
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = match self.selected {
+            Some(index) if index + 1 < self.history.len() => Some(index + 1),
+            _ => None,
+        };
+    }
+
+    /// The text for the output panel: the browsed event in full, or the live output.
+    pub fn detail(&self) -> String {
+        match self.selected.and_then(|index| self.history.get(index)) {
+            Some(record) => serde_json::to_string_pretty(&record.event).unwrap_or_default(),
+            None => self.output.clone(),
+        }
+    }
+
+    fn set_status(&mut self, step_index: usize, status: StepStatus) {
+        if let Some(step) = self.steps.get_mut(step_index) {
+            step.1 = status;
+        }
+    }
+
+    fn settle_running(&mut self, status: StepStatus) {
+        for step in self.steps.iter_mut().filter(|s| s.1 == StepStatus::Running) {
+            step.1 = status;
+        }
+    }
+}
+
+/// One line describing an event in the history list.
+fn describe(event: &AgentEvent) -> String {
+    match event {
+        AgentEvent::PlanCreated { steps } => format!("Plan created ({} steps)", steps.len()),
+        AgentEvent::PlanRevised { reason, .. } => format!("Plan revised: {}", reason),
+        AgentEvent::StepStarted { step_index, step } => format!("Step {}: {}", step_index + 1, step),
+        AgentEvent::StepChanges { step_index, changes } => format!("Step {} changed {} file(s)", step_index + 1, changes.len()),
+        AgentEvent::StepFinished { step_index, duration_ms, .. } => {
+            format!("Step {} finished in {}", step_index + 1, format_duration(Duration::from_millis(*duration_ms)))
+        }
+        AgentEvent::DecisionMade { decision, .. } => format!("Decision: {}", decision.thought),
+        AgentEvent::ToolExecuted { tool, success, .. } => format!("{} {}", tool.name(), if *success { "succeeded" } else { "failed" }),
+        AgentEvent::CodeGenerated { task, .. } => format!("Code generated: {}", task),
+        AgentEvent::GoalVerified { complete: true, .. } => "Goal verified".to_string(),
+        AgentEvent::GoalVerified { missing, .. } => format!("Goal incomplete: {} item(s) missing", missing.len()),
+        AgentEvent::Error { message, .. } => format!("Error: {}", message),
+        AgentEvent::RunFinished { success, total_cost } => {
+            format!("Run {} (${:.4})", if *success { "completed" } else { "failed" }, total_cost)
+        }
+    }
+}
+
+pub fn draw(frame: &mut Frame, state: &TuiState, goal: &str, cost: f64) {
+    let [header, body, history, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(8),
+        Constraint::Length(10),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [plan, output] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(body);
+
+    let status = match state.finished {
+        None if state.stopping => Span::styled("stopping", Style::new().fg(Color::Yellow)),
+        None => Span::styled("running", Style::new().fg(Color::Yellow)),
+        Some(true) => Span::styled("completed", Style::new().fg(Color::Green)),
+        Some(false) => Span::styled("failed", Style::new().fg(Color::Red)),
+    };
+    let title = Line::from(vec![
+        Span::styled(goal.to_string(), Style::new().add_modifier(Modifier::BOLD)),
+        Span::raw("  |  "),
+        status,
+        Span::raw(format!("  |  cost ${:.4}", cost)),
+    ]);
+    frame.render_widget(Paragraph::new(title).block(Block::bordered().title(" Objective ")), header);
+
+    let steps: Vec<ListItem> = state
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(i, (step, status))| {
+            let (marker, color) = match status {
+                StepStatus::Pending => ("  ", Color::DarkGray),
+                StepStatus::Running => ("▶ ", Color::Cyan),
+                StepStatus::Done => ("✓ ", Color::Green),
+                StepStatus::Failed => ("✗ ", Color::Red),
+            };
+            ListItem::new(format!("{}{}. {}", marker, i + 1, step)).style(Style::new().fg(color))
+        })
+        .collect();
+    frame.render_widget(List::new(steps).block(Block::bordered().title(" Plan ")), plan);
+
+    let output_title = if state.selected.is_some() { " Event " } else { " Output " };
+    frame.render_widget(
+        Paragraph::new(state.detail()).wrap(Wrap { trim: false }).block(Block::bordered().title(output_title)),
+        output,
+    );
+
+    draw_history(frame, state, history);
+
+    let hint = if state.finished.is_some() { "q: close  ↑/↓: browse history  Esc: live output" } else { "↑/↓: browse history  Esc: live output  Ctrl-C: abort" };
+    frame.render_widget(Paragraph::new(hint).dark_gray(), footer);
+}
+
+fn draw_history(frame: &mut Frame, state: &TuiState, area: Rect) {
+    let items: Vec<ListItem> = state.history.iter().map(|r| ListItem::new(format!("{}  {}", r.timestamp.format("%H:%M:%S"), describe(&r.event)))).collect();
+    // Without a selection the list follows the newest entry.
+    let mut list_state = ListState::default().with_selected(state.selected.or(state.history.len().checked_sub(1)));
+    let highlight = if state.selected.is_some() { Style::new().reversed() } else { Style::new() };
+    frame.render_stateful_widget(List::new(items).highlight_style(highlight).block(Block::bordered().title(" History ")), area, &mut list_state);
+}
+
+enum TuiMessage {
+    Event(EventRecord),
+    Finished(bool),
+}
+
+struct TuiSink(Sender<TuiMessage>);
+
+impl EventSink for TuiSink {
+    fn handle(&self, record: &EventRecord) {
+        // The UI thread may already be gone if the user aborted.
+        let _ = self.0.send(TuiMessage::Event(record.clone()));
+    }
+}
+
+/// A full-screen terminal UI for one run, drawn on its own thread from the orchestrator's events.
+pub struct Tui {
+    sender: Sender<TuiMessage>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl Tui {
+    /// Starts the UI for a run that Ctrl-C stops by cancelling `cancel`.
+    pub fn start(goal: String, cost_tracker: Arc<CostTracker>, cancel: CancellationToken) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            let mut terminal = ratatui::init();
+            let result = ui_loop(&mut terminal, receiver, &goal, &cost_tracker, &cancel);
+            ratatui::restore();
+            result
+        });
+        Self { sender, handle }
+    }
+
+    /// The sink to subscribe to the orchestrator.
+    pub fn sink(&self) -> Arc<dyn EventSink> {
+        Arc::new(TuiSink(self.sender.clone()))
+    }
+
+    /// Marks the run finished and waits for the user to close the UI.
+    pub fn finish(self, succeeded: bool) -> io::Result<()> {
+        let _ = self.sender.send(TuiMessage::Finished(succeeded));
+        self.handle.join().unwrap_or_else(|_| Err(io::Error::other("TUI thread panicked")))
+    }
+}
+
+fn ui_loop(terminal: &mut ratatui::DefaultTerminal, receiver: Receiver<TuiMessage>, goal: &str, cost_tracker: &CostTracker, cancel: &CancellationToken) -> io::Result<()> {
+    let mut state = TuiState::default();
+    loop {
+        while let Ok(message) = receiver.try_recv() {
+            match message {
+                TuiMessage::Event(record) => state.apply(record),
+                TuiMessage::Finished(succeeded) => state.finish(succeeded),
+            }
+        }
+        terminal.draw(|frame| draw(frame, &state, goal, cost_tracker.get_total_cost()))?;
+
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Up => state.select_previous(),
+            KeyCode::Down => state.select_next(),
+            KeyCode::Esc => state.selected = None,
+            KeyCode::Char('q') if state.finished.is_some() => return Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) && state.finished.is_some() => return Ok(()),
+            // Raw mode swallows SIGINT, so stop the run the way the Ctrl-C handler would; it then
+            // saves its session and finishes.
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                cancel.cancel();
+                state.stopping = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::Tool;
+    use chrono::Utc;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    fn record(event: AgentEvent) -> EventRecord {
+        EventRecord { timestamp: Utc::now(), event }
+    }
+
+    fn tool_executed(step_index: usize, success: bool) -> AgentEvent {
+        AgentEvent::ToolExecuted { step_index, tool: Tool::ListFiles { path: ".".to_string(), max_depth: None, glob: None }, success, output: format!("output {}", step_index) }
+    }
+
+    #[test]
+    fn test_state_tracks_step_status() {
+        let mut state = TuiState::default();
+        state.apply(record(AgentEvent::PlanCreated { steps: vec!["Build".to_string(), "Test".to_string()] }));
+        state.apply(record(AgentEvent::StepStarted { step_index: 0, step: "Build".to_string() }));
+        state.apply(record(tool_executed(0, false)));
+        assert_eq!(state.steps[0].1, StepStatus::Failed);
+        state.apply(record(tool_executed(0, true)));
+        assert_eq!(state.steps[0].1, StepStatus::Running);
+
+        state.apply(record(AgentEvent::StepStarted { step_index: 1, step: "Test".to_string() }));
+        assert_eq!(state.steps[0].1, StepStatus::Done);
+        assert_eq!(state.steps[1].1, StepStatus::Running);
+        assert_eq!(state.output, "output 0");
+
+        state.finish(true);
+        assert_eq!(state.steps[1].1, StepStatus::Done);
+        assert_eq!(state.history.len(), 5);
+    }
+
+    #[test]
+    fn test_history_selection() {
+        let mut state = TuiState::default();
+        state.select_previous();
+        assert_eq!(state.selected, None);
+
+        state.apply(record(AgentEvent::PlanCreated { steps: vec!["Build".to_string()] }));
+        state.apply(record(tool_executed(0, true)));
+        state.select_previous();
+        assert_eq!(state.selected, Some(1));
+        state.select_previous();
+        state.select_previous();
+        assert_eq!(state.selected, Some(0));
+        assert!(state.detail().contains("PlanCreated"));
+        state.select_next();
+        state.select_next();
+        assert_eq!(state.selected, None);
+        assert_eq!(state.detail(), "output 0");
+    }
+
+    #[test]
+    fn test_draw_renders_panels() {
+        let mut state = TuiState::default();
+        state.apply(record(AgentEvent::PlanCreated { steps: vec!["Build the crate".to_string()] }));
+        state.apply(record(AgentEvent::StepStarted { step_index: 0, step: "Build the crate".to_string() }));
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| draw(frame, &state, "Ship it", 0.0123)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+
+        assert!(screen.contains("Ship it"));
+        assert!(screen.contains("cost $0.0123"));
+        assert!(screen.contains("1. Build the crate"));
+        assert!(screen.contains("Step 1: Build the crate"));
+
+        state.stopping = true;
+        terminal.draw(|frame| draw(frame, &state, "Ship it", 0.0123)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("stopping"));
+    }
+}
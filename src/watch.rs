@@ -0,0 +1,135 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ignore::gitignore::Gitignore;
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::error::AgentError;
+
+/// How long the workspace must stay quiet after a change before `watch` runs the goal again.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(1500);
+/// Directories whose changes never trigger a run: the agent's own state, git's and build output.
+const IGNORED_DIRS: [&str; 3] = [".agent", ".git", "target"];
+
+/// Reports the files created, changed or removed under a directory, skipping `IGNORED_DIRS` and
+/// anything its `.gitignore` ignores.
+pub struct WorkspaceWatcher {
+    root: PathBuf,
+    gitignore: Gitignore,
+    events: UnboundedReceiver<notify::Result<Event>>,
+    // Watching stops when the watcher is dropped.
+    _watcher: RecommendedWatcher,
+}
+
+impl WorkspaceWatcher {
+    /// Starts watching `root` and everything below it.
+    pub fn new(root: &Path) -> Result<Self, AgentError> {
+        let root = root.canonicalize()?;
+        let watch_error = |e: notify::Error| AgentError::ToolError(format!("Cannot watch {} for changes: {}", root.display(), e));
+        let (sender, events) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })
+        .map_err(watch_error)?;
+        watcher.watch(&root, RecursiveMode::Recursive).map_err(watch_error)?;
+        // A missing or unreadable .gitignore ignores nothing.
+        let (gitignore, _) = Gitignore::new(root.join(".gitignore"));
+        Ok(Self { root, gitignore, events, _watcher: watcher })
+    }
+
+    /// Waits for the next change, then for `debounce` to pass without another one, so a burst of
+    /// saves (an editor, a `git checkout`) counts once. Returns the changed paths relative to the
+    /// root, sorted.
+    pub async fn changes(&mut self, debounce: Duration) -> Result<Vec<String>, AgentError> {
+        let mut changed = BTreeSet::new();
+        while changed.is_empty() {
+            let event = self.events.recv().await.ok_or_else(|| AgentError::ToolError("The file watcher stopped".to_string()))?;
+            changed.extend(self.relevant(event));
+        }
+        while let Ok(Some(event)) = tokio::time::timeout(debounce, self.events.recv()).await {
+            changed.extend(self.relevant(event));
+        }
+        Ok(changed.into_iter().collect())
+    }
+
+    /// Drops the changes to `files` seen so far, e.g. those the agent made itself while running the
+    /// goal, and any that arrive until `debounce` passes without another: the events of the last
+    /// writes may still be on their way. Returns the other changes, sorted, which were made by
+    /// someone else meanwhile.
+    pub async fn discard(&mut self, debounce: Duration, files: &[String]) -> Vec<String> {
+        let own: BTreeSet<String> = files.iter().map(|file| self.relative(Path::new(file))).collect();
+        let mut changed = BTreeSet::new();
+        while let Ok(Some(event)) = tokio::time::timeout(debounce, self.events.recv()).await {
+            changed.extend(self.relevant(event).into_iter().filter(|path| !own.contains(path)));
+        }
+        changed.into_iter().collect()
+    }
+
+    /// `path` relative to the root with `/` separators, as changes are reported; `path` may be
+    /// relative to the root already or absolute.
+    fn relative(&self, path: &Path) -> String {
+        let path = path.strip_prefix(&self.root).unwrap_or(path);
+        let parts: Vec<String> = path
+            .components()
+            .filter(|component| !matches!(component, std::path::Component::CurDir))
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        parts.join("/")
+    }
+
+    /// The paths of `event` that count as changes to the workspace.
+    fn relevant(&self, event: notify::Result<Event>) -> Vec<String> {
+        let Ok(event) = event else { return Vec::new() };
+        // Reads and permission or timestamp updates do not change what the goal is about.
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(_)) || matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_))) {
+            return Vec::new();
+        }
+        event
+            .paths
+            .iter()
+            .filter_map(|path| {
+                let relative = path.strip_prefix(&self.root).ok()?;
+                let first = relative.components().next()?.as_os_str().to_str()?;
+                if IGNORED_DIRS.contains(&first) || self.gitignore.matched_path_or_any_parents(relative, path.is_dir()).is_ignore() {
+                    return None;
+                }
+                Some(self.relative(relative))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(200);
+
+    async fn next_changes(watcher: &mut WorkspaceWatcher) -> Vec<String> {
+        tokio::time::timeout(Duration::from_secs(10), watcher.changes(DEBOUNCE)).await.expect("no change was reported").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_debounced_and_skip_ignored_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+        std::fs::create_dir_all(dir.path().join(".agent")).unwrap();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        let mut watcher = WorkspaceWatcher::new(dir.path()).unwrap();
+
+        std::fs::write(dir.path().join(".agent/session.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("build.log"), "noise").unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), "fn a() {}").unwrap();
+        std::fs::write(dir.path().join("README.md"), "# Demo").unwrap();
+        assert_eq!(next_changes(&mut watcher).await, vec!["README.md".to_string(), "src/lib.rs".to_string()]);
+
+        std::fs::write(dir.path().join("src/lib.rs"), "fn b() {}").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "by hand").unwrap();
+        assert_eq!(watcher.discard(DEBOUNCE, &["./src/lib.rs".to_string()]).await, vec!["notes.txt".to_string()]);
+        std::fs::remove_file(dir.path().join("README.md")).unwrap();
+        assert_eq!(next_changes(&mut watcher).await, vec!["README.md".to_string()]);
+    }
+}
@@ -0,0 +1,10 @@
+//! Helpers shared by the tool test binaries.
+use cli_coding_agent::tools::ToolResult;
+
+/// The output of a tool that is expected to have succeeded.
+pub fn success(result: ToolResult) -> String {
+    match result {
+        ToolResult::Success(output) => output,
+        other => panic!("Expected success, got {:?}", other),
+    }
+}
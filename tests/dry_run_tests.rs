@@ -0,0 +1,70 @@
+//! Dry-run behaviour. Every test here runs with AGENT_DRY_RUN set, so they live in their own
+//! test binary to keep the variable away from the other tool tests.
+mod common;
+
+use cli_coding_agent::tools::{run_command, run_tool, FileEdit, Tool, DRY_RUN_PREFIX};
+use common::success;
+use std::fs;
+use tempfile::tempdir;
+
+fn enable_dry_run() {
+    std::env::set_var("AGENT_DRY_RUN", "true");
+}
+
+#[tokio::test]
+async fn test_dry_run_write_file_shows_diff_without_writing() {
+    enable_dry_run();
+    let temp_dir = tempdir().unwrap();
+    let existing = temp_dir.path().join("existing.txt");
+    let new_file = temp_dir.path().join("new.txt");
+    fs::write(&existing, "one\ntwo\n").unwrap();
+
+    let tool = Tool::WriteFile { path: existing.to_string_lossy().to_string(), content: "one\n2\n".to_string() };
+    let output = success(run_tool(tool).await.unwrap());
+    assert!(output.starts_with(DRY_RUN_PREFIX));
+    assert!(output.contains("-two"));
+    assert!(output.contains("+2"));
+    assert_eq!(fs::read_to_string(&existing).unwrap(), "one\ntwo\n");
+
+    let tool = Tool::WriteFile { path: new_file.to_string_lossy().to_string(), content: "hello\n".to_string() };
+    let output = success(run_tool(tool).await.unwrap());
+    assert!(output.contains("+hello"));
+    assert!(!new_file.exists());
+}
+
+#[tokio::test]
+async fn test_dry_run_edit_file_leaves_file_unchanged() {
+    enable_dry_run();
+    let temp_dir = tempdir().unwrap();
+    let file = temp_dir.path().join("lib.rs");
+    fs::write(&file, "fn old() {}\n").unwrap();
+
+    let tool = Tool::EditFile {
+        path: file.to_string_lossy().to_string(),
+        edits: vec![FileEdit { search: "old".to_string(), replace: "new".to_string() }],
+    };
+    let output = success(run_tool(tool).await.unwrap());
+    assert!(output.contains("Would apply 1 edit(s)"));
+    assert!(output.contains("-fn old() {}"));
+    assert!(output.contains("+fn new() {}"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "fn old() {}\n");
+}
+
+#[tokio::test]
+async fn test_dry_run_command_is_not_executed() {
+    enable_dry_run();
+    let temp_dir = tempdir().unwrap();
+    let marker = temp_dir.path().join("marker");
+    let command = format!("echo hi > {}", marker.display());
+
+    let output = run_command(&command, None).await.unwrap();
+    assert!(output.success);
+    assert_eq!(output.stdout, format!("{} Would run: {}", DRY_RUN_PREFIX, command));
+    assert!(!marker.exists());
+}
+
+#[tokio::test]
+async fn test_dry_run_still_enforces_sandbox() {
+    enable_dry_run();
+    assert!(run_command("rm -rf /", None).await.is_err());
+}
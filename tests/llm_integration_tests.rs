@@ -1,11 +1,13 @@
 use cli_coding_agent::{
     config::AppConfig,
     error::AgentError,
-    llm::{create_llm_client, LLMProvider},
+    llm::{create_llm_client, create_llm_client_for, model_available, model_available_within, ChatMessage, GenerationParams, LLMProvider, ModelSpec},
+    llm::embeddings::{create_embeddings_client, EmbeddingProvider, EmbeddingsClient, GeminiEmbeddingsClient, OpenAIEmbeddingsClient},
+    memory::create_embedder,
 };
 use std::sync::Arc;
 use wiremock::{
-    matchers::{method, path},
+    matchers::{body_partial_json, header, method, path, query_param},
     Mock, MockServer, ResponseTemplate,
 };
 
@@ -25,22 +27,18 @@ async fn test_ollama_client_success() {
 
     // Create config with mock server URL
     let config = AppConfig {
-        openai_api_key: None,
-        anthropic_api_key: None,
-        google_api_key: None,
-        deepseek_api_key: None,
-        brave_search_api_key: None,
         ollama_base_url: mock_server.uri(),
         ollama_model: "test_model".to_string(),
+        ..AppConfig::default()
     };
 
     // Create Ollama client
     let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
 
     // Test generation
-    let result = client.generate("Test prompt").await;
+    let result = client.generate("Test prompt", &GenerationParams::default()).await;
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), "Hello, this is a test response!");
+    assert_eq!(result.unwrap().content, "Hello, this is a test response!");
 }
 
 #[tokio::test]
@@ -57,20 +55,16 @@ async fn test_ollama_client_error_response() {
 
     // Create config with mock server URL
     let config = AppConfig {
-        openai_api_key: None,
-        anthropic_api_key: None,
-        google_api_key: None,
-        deepseek_api_key: None,
-        brave_search_api_key: None,
         ollama_base_url: mock_server.uri(),
         ollama_model: "test_model".to_string(),
+        ..AppConfig::default()
     };
 
     // Create Ollama client
     let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
 
     // Test generation - should return error
-    let result = client.generate("Test prompt").await;
+    let result = client.generate("Test prompt", &GenerationParams::default()).await;
     assert!(result.is_err());
     
     match result.unwrap_err() {
@@ -95,20 +89,16 @@ async fn test_ollama_client_invalid_json_response() {
 
     // Create config with mock server URL
     let config = AppConfig {
-        openai_api_key: None,
-        anthropic_api_key: None,
-        google_api_key: None,
-        deepseek_api_key: None,
-        brave_search_api_key: None,
         ollama_base_url: mock_server.uri(),
         ollama_model: "test_model".to_string(),
+        ..AppConfig::default()
     };
 
     // Create Ollama client
     let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
 
     // Test generation - should return request/parse error due to invalid JSON
-    let result = client.generate("Test prompt").await;
+    let result = client.generate("Test prompt", &GenerationParams::default()).await;
     assert!(result.is_err());
     
     // When reqwest receives invalid JSON, it returns a RequestError with Decode kind
@@ -126,15 +116,7 @@ async fn test_ollama_client_invalid_json_response() {
 
 #[test]
 fn test_create_llm_client_missing_api_key() {
-    let config = AppConfig {
-        openai_api_key: None,
-        anthropic_api_key: None,
-        google_api_key: None,
-        deepseek_api_key: None,
-        brave_search_api_key: None,
-        ollama_base_url: "http://localhost:11434".to_string(),
-        ollama_model: "llama3".to_string(),
-    };
+    let config = AppConfig::default();
 
     // Test OpenAI without API key
     let result = create_llm_client(LLMProvider::OpenAI, Arc::new(config.clone()));
@@ -172,6 +154,12 @@ fn test_create_llm_client_missing_api_key() {
         panic!("Expected ApiKeyMissing error for DeepSeek");
     }
 
+    // Test Mistral and Groq without API keys
+    for (provider, name) in [(LLMProvider::Mistral, "Mistral"), (LLMProvider::Groq, "Groq")] {
+        let result = create_llm_client(provider, Arc::new(config.clone()));
+        assert!(matches!(result, Err(AgentError::ApiKeyMissing(missing)) if missing == name));
+    }
+
     // Test Ollama - should work without API key
     let result = create_llm_client(LLMProvider::Ollama, Arc::new(config));
     assert!(result.is_ok());
@@ -184,9 +172,10 @@ fn test_create_llm_client_with_api_keys() {
         anthropic_api_key: Some("test_anthropic_key".to_string()),
         google_api_key: Some("test_google_key".to_string()),
         deepseek_api_key: Some("test_deepseek_key".to_string()),
+        mistral_api_key: Some("test_mistral_key".to_string()),
+        groq_api_key: Some("test_groq_key".to_string()),
         brave_search_api_key: Some("test_brave_key".to_string()),
-        ollama_base_url: "http://localhost:11434".to_string(),
-        ollama_model: "llama3".to_string(),
+        ..AppConfig::default()
     };
 
     // Test all providers with API keys
@@ -195,6 +184,8 @@ fn test_create_llm_client_with_api_keys() {
         LLMProvider::Claude,
         LLMProvider::Gemini,
         LLMProvider::DeepSeek,
+        LLMProvider::Mistral,
+        LLMProvider::Groq,
         LLMProvider::Ollama,
     ];
 
@@ -210,6 +201,8 @@ fn test_llm_provider_display() {
     assert_eq!(LLMProvider::Claude.to_string(), "Claude");
     assert_eq!(LLMProvider::Gemini.to_string(), "Gemini");
     assert_eq!(LLMProvider::DeepSeek.to_string(), "DeepSeek");
+    assert_eq!(LLMProvider::Mistral.to_string(), "Mistral");
+    assert_eq!(LLMProvider::Groq.to_string(), "Groq");
     assert_eq!(LLMProvider::Ollama.to_string(), "Ollama");
 }
 
@@ -220,6 +213,8 @@ fn test_llm_provider_debug() {
         LLMProvider::Claude,
         LLMProvider::Gemini,
         LLMProvider::DeepSeek,
+        LLMProvider::Mistral,
+        LLMProvider::Groq,
         LLMProvider::Ollama,
     ];
 
@@ -230,6 +225,7 @@ fn test_llm_provider_debug() {
 }
 
 #[test]
+#[allow(clippy::clone_on_copy)]
 fn test_llm_provider_clone_and_copy() {
     let provider = LLMProvider::OpenAI;
     let cloned = provider.clone();
@@ -264,42 +260,62 @@ async fn test_ollama_request_structure() {
 
     // Create config with mock server URL
     let config = AppConfig {
-        openai_api_key: None,
-        anthropic_api_key: None,
-        google_api_key: None,
-        deepseek_api_key: None,
-        brave_search_api_key: None,
         ollama_base_url: mock_server.uri(),
         ollama_model: "test_model".to_string(),
+        ..AppConfig::default()
     };
 
     // Create Ollama client
     let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
 
     // Test generation
-    let result = client.generate("Test prompt").await;
+    let result = client.generate("Test prompt", &GenerationParams::default()).await;
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), "Test response");
+    assert_eq!(result.unwrap().content, "Test response");
+}
+
+#[tokio::test]
+async fn test_ollama_streams_the_answer_line_by_line() {
+    let mock_server = MockServer::start().await;
+    let body = [
+        r#"{"message":{"role":"assistant","content":"Auth is "},"done":false}"#,
+        r#"{"message":{"role":"assistant","content":"handled in src/auth.rs."},"done":false}"#,
+        r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":42,"eval_count":7}"#,
+    ]
+    .join("\n");
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_partial_json(serde_json::json!({ "stream": true })))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .mount(&mock_server)
+        .await;
+    let config = AppConfig { ollama_base_url: mock_server.uri(), ollama_model: "test_model".to_string(), ..AppConfig::default() };
+    let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
+
+    let pieces = std::sync::Mutex::new(Vec::new());
+    let response = client
+        .generate_stream(&[ChatMessage::user("How does auth work?")], &GenerationParams::default(), &|text: &str| pieces.lock().unwrap().push(text.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(*pieces.lock().unwrap(), ["Auth is ", "handled in src/auth.rs."]);
+    assert_eq!(response.content, "Auth is handled in src/auth.rs.");
+    assert_eq!((response.input_tokens, response.output_tokens), (42, 7));
 }
 
 #[tokio::test]
 async fn test_ollama_network_error() {
     // Create config with invalid URL
     let config = AppConfig {
-        openai_api_key: None,
-        anthropic_api_key: None,
-        google_api_key: None,
-        deepseek_api_key: None,
-        brave_search_api_key: None,
         ollama_base_url: "http://invalid-url:99999".to_string(),
         ollama_model: "test_model".to_string(),
+        ..AppConfig::default()
     };
 
     // Create Ollama client
     let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
 
     // Test generation - should return network error
-    let result = client.generate("Test prompt").await;
+    let result = client.generate("Test prompt", &GenerationParams::default()).await;
     assert!(result.is_err());
     
     match result.unwrap_err() {
@@ -308,4 +324,257 @@ async fn test_ollama_network_error() {
         }
         _ => panic!("Expected RequestError"),
     }
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_ollama_generate_chat() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/chat"))
+        .and(body_partial_json(serde_json::json!({
+            "messages": [
+                { "role": "system", "content": "You are terse." },
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hello." },
+                { "role": "user", "content": "Again" }
+            ]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "message": { "role": "assistant", "content": "Hello again." },
+            "prompt_eval_count": 12,
+            "eval_count": 3
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let config = AppConfig {
+        ollama_base_url: mock_server.uri(),
+        ollama_model: "test_model".to_string(),
+        ..AppConfig::default()
+    };
+    let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
+
+    let messages = vec![
+        ChatMessage::system("You are terse."),
+        ChatMessage::user("Hi"),
+        ChatMessage::assistant("Hello."),
+        ChatMessage::user("Again"),
+    ];
+    let response = client.generate_chat(&messages, &GenerationParams::default()).await.unwrap();
+    assert_eq!(response.content, "Hello again.");
+    assert_eq!(response.input_tokens, 12);
+    assert_eq!(response.output_tokens, 3);
+}
+
+#[tokio::test]
+async fn test_pricing_override_file_sets_response_cost() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "response": "priced",
+            "prompt_eval_count": 1000,
+            "eval_count": 500
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let pricing_file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(pricing_file.path(), "[ollama]\n\"test_model\" = { input = 2.0, output = 4.0 }\n").unwrap();
+
+    let config = AppConfig {
+        ollama_base_url: mock_server.uri(),
+        ollama_model: "test_model".to_string(),
+        pricing_file: Some(pricing_file.path().to_string_lossy().to_string()),
+        ..AppConfig::default()
+    };
+    let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
+
+    let response = client.generate("Test prompt", &GenerationParams::default()).await.unwrap();
+    // 1000 * $2/1M + 500 * $4/1M
+    assert!((response.cost - 0.004).abs() < 1e-12);
+
+    let info = client.get_model_info().await;
+    assert_eq!(info.name, "test_model");
+    assert!((info.input_cost_per_token - 0.000002).abs() < 1e-15);
+}
+
+#[test]
+fn test_create_llm_client_missing_pricing_file() {
+    let config = AppConfig {
+        pricing_file: Some("/nonexistent/pricing.toml".to_string()),
+        ..AppConfig::default()
+    };
+    match create_llm_client(LLMProvider::Ollama, Arc::new(config)) {
+        Err(AgentError::ConfigError(msg)) => assert!(msg.contains("pricing file")),
+        _ => panic!("Expected ConfigError"),
+    }
+}
+
+#[tokio::test]
+async fn test_create_llm_client_for_overrides_model() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_partial_json(serde_json::json!({ "model": "codellama:7b" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "response": "fn main() {}" })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = AppConfig {
+        ollama_base_url: mock_server.uri(),
+        ollama_model: "llama3".to_string(),
+        ..AppConfig::default()
+    };
+    let spec: ModelSpec = "ollama:codellama:7b".parse().unwrap();
+    let client = create_llm_client_for(&spec, Arc::new(config)).unwrap();
+
+    let response = client.generate("Write main", &GenerationParams::default()).await.unwrap();
+    assert_eq!(response.content, "fn main() {}");
+    assert_eq!(client.get_model_info().await.name, "codellama:7b");
+}
+
+#[tokio::test]
+async fn test_ollama_client_sends_generation_params_as_options() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_partial_json(serde_json::json!({ "options": { "temperature": 0.5, "num_predict": 256 } })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "response": "tuned" })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = AppConfig { ollama_base_url: mock_server.uri(), ..AppConfig::default() };
+    let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
+    let params: GenerationParams = "temperature=0.5,max_tokens=256".parse().unwrap();
+
+    let response = client.generate("Test prompt", &params).await.unwrap();
+    assert_eq!(response.content, "tuned");
+}
+
+#[tokio::test]
+async fn test_ollama_lists_pulled_models_and_checks_the_configured_one() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/tags"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "models": [{ "name": "llama3:latest" }, { "name": "codellama:7b" }]
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let client_for = |model: &str| {
+        let config = AppConfig { ollama_base_url: mock_server.uri(), ollama_model: model.to_string(), ..AppConfig::default() };
+        create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap()
+    };
+    assert_eq!(client_for("llama3").list_models().await.unwrap(), vec!["codellama:7b", "llama3:latest"]);
+    assert!(model_available(client_for("llama3").as_ref()).await.unwrap());
+    assert!(model_available(client_for("codellama:7b").as_ref()).await.unwrap());
+    assert!(!model_available(client_for("codellama:13b").as_ref()).await.unwrap());
+
+    let unreachable = AppConfig { ollama_base_url: "http://127.0.0.1:9".to_string(), ..AppConfig::default() };
+    let client = create_llm_client(LLMProvider::Ollama, Arc::new(unreachable)).unwrap();
+    assert!(model_available(client.as_ref()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_model_available_gives_up_on_a_provider_that_does_not_answer() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/tags"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "models": [] })).set_delay(std::time::Duration::from_secs(30)))
+        .mount(&mock_server)
+        .await;
+
+    let config = AppConfig { ollama_base_url: mock_server.uri(), ..AppConfig::default() };
+    let client = create_llm_client(LLMProvider::Ollama, Arc::new(config)).unwrap();
+    let error = model_available_within(client.as_ref(), std::time::Duration::from_millis(200)).await.unwrap_err();
+    assert!(error.to_string().contains("not listed within 0.2s"), "{}", error);
+}
+
+#[test]
+fn test_create_llm_client_for_missing_api_key() {
+    let spec: ModelSpec = "openai:gpt-4o-mini".parse().unwrap();
+    match create_llm_client_for(&spec, Arc::new(AppConfig::default())) {
+        Err(AgentError::ApiKeyMissing(provider)) => assert_eq!(provider, "OpenAI"),
+        _ => panic!("Expected ApiKeyMissing"),
+    }
+}
+
+#[tokio::test]
+async fn test_ollama_embedder_uses_embed_endpoint() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/embed"))
+        .and(body_partial_json(serde_json::json!({ "model": "nomic-embed-text", "input": ["fn main() {}"] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "embeddings": [[0.1, 0.2]] })))
+        .mount(&mock_server)
+        .await;
+
+    let config = AppConfig { memory_provider: Some(EmbeddingProvider::Ollama), ollama_base_url: mock_server.uri(), ..AppConfig::default() };
+    let embedder = create_embedder(&config).unwrap().unwrap();
+    assert_eq!(embedder.embed(&["fn main() {}".to_string()]).await.unwrap(), vec![vec![0.1, 0.2]]);
+}
+
+#[tokio::test]
+async fn test_openai_embedder_orders_embeddings_by_index() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/embeddings"))
+        .and(header("authorization", "Bearer test-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "data": [{ "index": 1, "embedding": [0.0, 1.0] }, { "index": 0, "embedding": [1.0, 0.0] }]
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let embedder = OpenAIEmbeddingsClient::with_base_url("test-key".to_string(), "text-embedding-3-small".to_string(), format!("{}/v1/embeddings", mock_server.uri()));
+    let embeddings = embedder.embed(&["a".to_string(), "b".to_string()]).await.unwrap();
+    assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+}
+
+#[test]
+fn test_create_embedder_is_off_by_default() {
+    assert!(create_embedder(&AppConfig::default()).unwrap().is_none());
+    let config = AppConfig { memory_provider: Some(EmbeddingProvider::OpenAI), openai_api_key: None, ..AppConfig::default() };
+    assert!(matches!(create_embedder(&config), Err(AgentError::ApiKeyMissing(_))));
+}
+
+#[tokio::test]
+async fn test_gemini_embeddings_client_batches_texts() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/models/text-embedding-004:batchEmbedContents"))
+        .and(query_param("key", "test-key"))
+        .and(body_partial_json(serde_json::json!({
+            "requests": [{ "model": "models/text-embedding-004", "content": { "parts": [{ "text": "a" }] } }, { "model": "models/text-embedding-004", "content": { "parts": [{ "text": "b" }] } }]
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "embeddings": [{ "values": [1.0, 0.0] }, { "values": [0.0, 1.0] }] })))
+        .mount(&mock_server)
+        .await;
+
+    let client = GeminiEmbeddingsClient::with_base_url("test-key".to_string(), "text-embedding-004".to_string(), mock_server.uri());
+    let embeddings = client.embed(&["a".to_string(), "b".to_string()]).await.unwrap();
+    assert_eq!(embeddings, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+
+    // A reply missing vectors is an error rather than a shorter list.
+    Mock::given(method("POST"))
+        .and(path("/models/truncating:batchEmbedContents"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "embeddings": [{ "values": [1.0] }] })))
+        .mount(&mock_server)
+        .await;
+    let truncating = GeminiEmbeddingsClient::with_base_url("test-key".to_string(), "truncating".to_string(), mock_server.uri());
+    assert!(matches!(truncating.embed(&["a".to_string(), "b".to_string()]).await, Err(AgentError::LLMError(_))));
+}
+
+#[test]
+fn test_create_embeddings_client_defaults_the_model() {
+    let config = AppConfig { google_api_key: Some("key".to_string()), ..AppConfig::default() };
+    assert_eq!(create_embeddings_client(EmbeddingProvider::Gemini, None, &config).unwrap().model(), "text-embedding-004");
+    assert_eq!(create_embeddings_client(EmbeddingProvider::Ollama, Some("mxbai-embed-large".to_string()), &config).unwrap().model(), "mxbai-embed-large");
+    assert!(matches!(create_embeddings_client(EmbeddingProvider::OpenAI, None, &config), Err(AgentError::ApiKeyMissing(_))));
+    assert_eq!("Gemini".parse::<EmbeddingProvider>(), Ok(EmbeddingProvider::Gemini));
+}